@@ -0,0 +1,26 @@
+//! Decode arbitrary bytes as a generated type: `cargo fuzz run decode` from `qjs-extensions`.
+//!
+//! The input first picks the type, the rest of it is decoded. Decoding may fail but must not
+//! panic, and what decodes must encode back to the same value.
+#![no_main]
+
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+use qjs_extensions::scale2::roundtrip::{check_decode, Shape};
+
+thread_local! {
+    // The context comes first to be dropped before its runtime.
+    static CONTEXT: (js::Context, js::Runtime) = {
+        let runtime = js::Runtime::new(&Default::default());
+        (runtime.new_context(), runtime)
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let Ok(shape) = Shape::arbitrary(&mut u, 4) else {
+        return;
+    };
+    let bytes = u.take_rest();
+    CONTEXT.with(|(ctx, _)| check_decode(ctx, &shape, bytes));
+});
@@ -0,0 +1,32 @@
+//! Decode arbitrary bytes as a transferable value: `cargo fuzz run transfer` from
+//! `qjs-extensions`.
+//!
+//! Decoding may fail but must not panic. What decodes may encode to other bytes, as duplicate
+//! keys are dropped, but those must decode and encode to the same bytes again.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+thread_local! {
+    // The context comes first to be dropped before its runtime.
+    static CONTEXT: (js::Context, js::Runtime) = {
+        let runtime = js::Runtime::new(&Default::default());
+        (runtime.new_context(), runtime)
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    CONTEXT.with(|(ctx, _)| {
+        let Ok(value) = ctx.deserialize_transferable(data) else {
+            return;
+        };
+        // Dropped duplicates may leave shared objects deeper than the limit.
+        let Ok(bytes) = value.serialize_transferable() else {
+            return;
+        };
+        let again = ctx
+            .deserialize_transferable(&bytes)
+            .expect("encoded values decode");
+        assert_eq!(again.serialize_transferable().unwrap(), bytes);
+    });
+});
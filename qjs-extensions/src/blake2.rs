@@ -2,7 +2,9 @@ use blake2::{
     digest::typenum::{U16, U32, U64},
     Blake2b, Blake2s, Digest,
 };
-use js::{AsBytes, BytesOrString};
+use js::{BytesOrString, JsUint8Array, Result};
+
+use crate::output::Output;
 
 fn blake2b128_encode(data: &[u8]) -> [u8; 16] {
     let mut hasher = Blake2b::<U16>::new();
@@ -26,21 +28,55 @@ fn blake2s256_encode(data: &[u8]) -> [u8; 32] {
 }
 
 #[js::host_call]
-pub fn blake2b_128(data: BytesOrString) -> AsBytes<[u8; 16]> {
-    AsBytes(blake2b128_encode(data.as_bytes()))
+pub fn blake2b_128(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 16]>> {
+    Output::new(blake2b128_encode(data.as_bytes()), out)
 }
 
 #[js::host_call]
-pub fn blake2b_256(data: BytesOrString) -> AsBytes<[u8; 32]> {
-    AsBytes(blake2b256_encode(data.as_bytes()))
+pub fn blake2b_256(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 32]>> {
+    Output::new(blake2b256_encode(data.as_bytes()), out)
 }
 
 #[js::host_call]
-pub fn blake2b_512(data: BytesOrString) -> AsBytes<[u8; 64]> {
-    AsBytes(blake2b512_encode(data.as_bytes()))
+pub fn blake2b_512(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 64]>> {
+    Output::new(blake2b512_encode(data.as_bytes()), out)
 }
 
 #[js::host_call]
-pub fn blake2s_256(data: BytesOrString) -> AsBytes<[u8; 32]> {
-    AsBytes(blake2s256_encode(data.as_bytes()))
+pub fn blake2s_256(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 32]>> {
+    Output::new(blake2s256_encode(data.as_bytes()), out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Extensions;
+
+    #[test]
+    fn blake2_into_output_buffer() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        Extensions::new().with_hash().install(&ctx).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let same = eval(
+            r#"
+            [
+                ["blake2b_128", 16],
+                ["blake2b_256", 32],
+                ["blake2b_512", 64],
+                ["blake2s_256", 32],
+            ].every(([name, len]) => {
+                const out = new Uint8Array(len);
+                return Hash[name]("abc", out) === out && Hash[name]("abc").join() === out.join();
+            })
+            "#,
+        )
+        .unwrap();
+        assert!(same.decode_bool().unwrap());
+
+        let err = eval(r#"Hash.blake2b_128("abc", new Uint8Array(32))"#).unwrap_err();
+        assert!(
+            err.contains("output buffer must be 16 bytes long, got 32"),
+            "{err}"
+        );
+    }
 }
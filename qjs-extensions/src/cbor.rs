@@ -0,0 +1,1006 @@
+//! CBOR (RFC 8949) encoding of JS values, as used by COSE and WebAuthn.
+//!
+//! Values are encoded deterministically, so equal values always produce the same bytes:
+//!
+//! | JS                       | CBOR                                              |
+//! |--------------------------|---------------------------------------------------|
+//! | safe integer             | unsigned or negative integer                      |
+//! | other number             | shortest float that preserves the value           |
+//! | BigInt                   | integer, or tagged bignum (tags 2 and 3) if wider |
+//! | string                   | text string                                       |
+//! | Uint8Array, ArrayBuffer  | byte string                                       |
+//! | Array                    | array                                             |
+//! | Map, other objects       | map, sorted by encoded key                        |
+//! | boolean, null, undefined | simple values                                     |
+//!
+//! Decoding maps the other way. Integers outside the safe integer range and bignums become
+//! BigInts, maps with only text keys become objects and other maps become `Map`s. Tags other than
+//! bignums are dropped, leaving their content.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use anyhow::bail;
+use js::{AsBytes, Result};
+
+/// The largest integer a JS number represents exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// The deepest nesting of arrays, maps and tags encoded, and decoded unless a lower limit is given.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+const BREAK: u8 = 0xff;
+const TAG_POSITIVE_BIGNUM: u64 = 2;
+const TAG_NEGATIVE_BIGNUM: u64 = 3;
+
+/// A decoded CBOR data item.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Unsigned(u64),
+    /// The negative integer `-1 - n`.
+    Negative(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Item>),
+    Map(Vec<(Item, Item)>),
+    Tag(u64, Box<Item>),
+    Bool(bool),
+    Null,
+    Undefined,
+    Simple(u8),
+    Float(f64),
+}
+
+/// Options for [`decode`].
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeOptions {
+    /// Accept indefinite-length strings, arrays and maps.
+    pub allow_indefinite: bool,
+    /// The deepest nesting of arrays, maps and tags accepted.
+    pub max_depth: usize,
+    /// The longest byte or text string accepted, in bytes.
+    pub max_string_bytes: usize,
+    /// The most elements of an array, or entries of a map, accepted.
+    pub max_items: usize,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        let limits = js::ConversionLimits::default();
+        Self {
+            allow_indefinite: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_string_bytes: limits.max_string_bytes,
+            max_items: limits.max_array_len,
+        }
+    }
+}
+
+/// Encode `item` in the deterministic encoding of RFC 8949 section 4.2.1: shortest arguments
+/// and floats, definite lengths, and map keys sorted by their encoded bytes.
+pub fn encode(item: &Item) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(item, &mut out);
+    out
+}
+
+fn write_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&arg.to_be_bytes());
+        }
+    }
+}
+
+fn encode_into(item: &Item, out: &mut Vec<u8>) {
+    match item {
+        Item::Unsigned(n) => write_head(0, *n, out),
+        Item::Negative(n) => write_head(1, *n, out),
+        Item::Bytes(bytes) => {
+            write_head(2, bytes.len() as u64, out);
+            out.extend_from_slice(bytes);
+        }
+        Item::Text(text) => {
+            write_head(3, text.len() as u64, out);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Item::Array(items) => {
+            write_head(4, items.len() as u64, out);
+            for item in items {
+                encode_into(item, out);
+            }
+        }
+        Item::Map(entries) => {
+            let mut encoded: Vec<(Vec<u8>, &Item)> = entries
+                .iter()
+                .map(|(key, value)| (encode(key), value))
+                .collect();
+            encoded.sort_by(|a, b| a.0.cmp(&b.0));
+            write_head(5, encoded.len() as u64, out);
+            for (key, value) in encoded {
+                out.extend_from_slice(&key);
+                encode_into(value, out);
+            }
+        }
+        Item::Tag(tag, item) => {
+            write_head(6, *tag, out);
+            encode_into(item, out);
+        }
+        Item::Bool(false) => out.push(0xf4),
+        Item::Bool(true) => out.push(0xf5),
+        Item::Null => out.push(0xf6),
+        Item::Undefined => out.push(0xf7),
+        Item::Simple(n) => write_head(7, *n as u64, out),
+        Item::Float(f) => encode_float(*f, out),
+    }
+}
+
+fn encode_float(f: f64, out: &mut Vec<u8>) {
+    if f.is_nan() {
+        out.extend_from_slice(&[0xf9, 0x7e, 0x00]);
+        return;
+    }
+    if let Some(half) = f64_to_f16(f) {
+        out.push(0xf9);
+        out.extend_from_slice(&half.to_be_bytes());
+    } else if (f as f32) as f64 == f {
+        out.push(0xfa);
+        out.extend_from_slice(&(f as f32).to_bits().to_be_bytes());
+    } else {
+        out.push(0xfb);
+        out.extend_from_slice(&f.to_bits().to_be_bytes());
+    }
+}
+
+/// The half-precision bits of `f` if it can be represented exactly.
+fn f64_to_f16(f: f64) -> Option<u16> {
+    let sign = if f.is_sign_negative() { 0x8000 } else { 0 };
+    let abs = f.abs();
+    let bits = if abs == 0.0 {
+        0
+    } else if abs.is_infinite() {
+        0x7c00
+    } else if abs < 6.103515625e-5 {
+        // Subnormal: a multiple of 2^-24.
+        let m = abs * 16777216.0;
+        if m != (m as u16) as f64 {
+            return None;
+        }
+        m as u16
+    } else {
+        let single = abs as f32;
+        if single as f64 != abs {
+            return None;
+        }
+        let bits = single.to_bits();
+        let exp = ((bits >> 23) & 0xff) as i32 - 127;
+        let mantissa = bits & 0x7f_ffff;
+        if exp > 15 || mantissa & 0x1fff != 0 {
+            return None;
+        }
+        (((exp + 15) as u16) << 10) | (mantissa >> 13) as u16
+    };
+    Some(sign | bits)
+}
+
+fn f16_to_f64(half: u16) -> f64 {
+    let exp = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as f64;
+    let value = match exp {
+        0 => mantissa * (1.0 / 16777216.0),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * pow2(exp as i32 - 15),
+    };
+    if half & 0x8000 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+fn pow2(exp: i32) -> f64 {
+    f64::from_bits(((exp + 1023) as u64) << 52)
+}
+
+/// Why decoding failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a data item.
+    Truncated,
+    /// The input is not well-formed CBOR or uses a feature that is not allowed.
+    Invalid(String),
+    /// The data is nested deeper than allowed.
+    TooDeep(usize),
+    /// A string, array or map is longer than allowed.
+    TooLong(js::LimitExceeded),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "truncated CBOR input"),
+            Self::Invalid(reason) => write!(f, "invalid CBOR: {reason}"),
+            Self::TooDeep(max) => write!(f, "CBOR nesting exceeds {max} levels"),
+            Self::TooLong(exceeded) => write!(f, "CBOR {exceeded}"),
+        }
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> DecodeError {
+    DecodeError::Invalid(reason.into())
+}
+
+/// Decode a single data item occupying all of `data`.
+pub fn decode(data: &[u8], options: &DecodeOptions) -> Result<Item, DecodeError> {
+    let mut decoder = Decoder {
+        data,
+        pos: 0,
+        options,
+    };
+    let item = decoder.item(0)?;
+    if decoder.pos != data.len() {
+        return Err(invalid(format!(
+            "{} trailing bytes after the data item",
+            data.len() - decoder.pos
+        )));
+    }
+    Ok(item)
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    options: &'a DecodeOptions,
+}
+
+/// The argument of a data item head; `None` for indefinite lengths.
+type Arg = Option<u64>;
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let end = self.pos.checked_add(len).ok_or(DecodeError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(DecodeError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn head(&mut self) -> Result<(u8, u8, Arg), DecodeError> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let arg = match info {
+            0..=23 => Some(info as u64),
+            24 => Some(self.take(1)?[0] as u64),
+            25 => Some(u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64),
+            26 => Some(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64),
+            27 => Some(u64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            31 if major >= 2 => None,
+            _ => return Err(invalid(format!("reserved additional information {info}"))),
+        };
+        Ok((major, info, arg))
+    }
+
+    /// Check that `count` items of at least `min_size` bytes each can fit in the rest of the
+    /// input, so that a forged length cannot make us allocate more than the input justifies.
+    fn check_len(&self, count: u64, min_size: u64) -> Result<usize, DecodeError> {
+        let remaining = (self.data.len() - self.pos) as u64;
+        match count.checked_mul(min_size) {
+            Some(size) if size <= remaining => Ok(count as usize),
+            _ => Err(DecodeError::Truncated),
+        }
+    }
+
+    fn check_limit(
+        &self,
+        limit: &'static str,
+        max: usize,
+        actual: usize,
+    ) -> Result<(), DecodeError> {
+        if actual > max {
+            return Err(DecodeError::TooLong(js::LimitExceeded {
+                limit,
+                max,
+                actual,
+            }));
+        }
+        Ok(())
+    }
+
+    fn check_string(&self, len: usize) -> Result<(), DecodeError> {
+        self.check_limit("max_string_bytes", self.options.max_string_bytes, len)
+    }
+
+    fn check_items(&self, len: usize) -> Result<(), DecodeError> {
+        self.check_limit("max_items", self.options.max_items, len)
+    }
+
+    fn indefinite(&self, what: &str) -> Result<(), DecodeError> {
+        if self.options.allow_indefinite {
+            Ok(())
+        } else {
+            Err(invalid(format!("indefinite-length {what} not allowed")))
+        }
+    }
+
+    fn at_break(&mut self) -> Result<bool, DecodeError> {
+        match self.data.get(self.pos) {
+            Some(&BREAK) => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Err(DecodeError::Truncated),
+        }
+    }
+
+    fn item(&mut self, depth: usize) -> Result<Item, DecodeError> {
+        let (major, info, arg) = self.head()?;
+        match (major, arg) {
+            (0, Some(n)) => Ok(Item::Unsigned(n)),
+            (1, Some(n)) => Ok(Item::Negative(n)),
+            (2, Some(len)) => {
+                let len = self.check_len(len, 1)?;
+                self.check_string(len)?;
+                Ok(Item::Bytes(self.take(len)?.to_vec()))
+            }
+            (3, Some(len)) => {
+                let len = self.check_len(len, 1)?;
+                self.check_string(len)?;
+                Ok(Item::Text(self.text(len)?))
+            }
+            (2 | 3, None) => {
+                self.indefinite("string")?;
+                let mut bytes = Vec::new();
+                while !self.at_break()? {
+                    match self.head()? {
+                        (chunk_major, _, Some(len)) if chunk_major == major => {
+                            let len = self.check_len(len, 1)?;
+                            self.check_string(bytes.len().saturating_add(len))?;
+                            if major == 2 {
+                                bytes.extend_from_slice(self.take(len)?);
+                            } else {
+                                bytes.extend_from_slice(self.text(len)?.as_bytes());
+                            }
+                        }
+                        _ => return Err(invalid("invalid chunk in indefinite-length string")),
+                    }
+                }
+                if major == 2 {
+                    Ok(Item::Bytes(bytes))
+                } else {
+                    String::from_utf8(bytes)
+                        .map(Item::Text)
+                        .map_err(|_| invalid("text string is not valid UTF-8"))
+                }
+            }
+            (4 | 5 | 6, _) if depth >= self.options.max_depth => {
+                Err(DecodeError::TooDeep(self.options.max_depth))
+            }
+            (4, Some(len)) => {
+                let len = self.check_len(len, 1)?;
+                self.check_items(len)?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(self.item(depth + 1)?);
+                }
+                Ok(Item::Array(items))
+            }
+            (4, None) => {
+                self.indefinite("array")?;
+                let mut items = Vec::new();
+                while !self.at_break()? {
+                    self.check_items(items.len() + 1)?;
+                    items.push(self.item(depth + 1)?);
+                }
+                Ok(Item::Array(items))
+            }
+            (5, Some(len)) => {
+                let len = self.check_len(len, 2)?;
+                self.check_items(len)?;
+                let mut entries = Vec::with_capacity(len);
+                let mut keys = BTreeSet::new();
+                for _ in 0..len {
+                    entries.push(self.entry(depth, &mut keys)?);
+                }
+                Ok(Item::Map(entries))
+            }
+            (5, None) => {
+                self.indefinite("map")?;
+                let mut entries = Vec::new();
+                let mut keys = BTreeSet::new();
+                while !self.at_break()? {
+                    self.check_items(entries.len() + 1)?;
+                    entries.push(self.entry(depth, &mut keys)?);
+                }
+                Ok(Item::Map(entries))
+            }
+            (6, Some(tag)) => Ok(Item::Tag(tag, Box::new(self.item(depth + 1)?))),
+            (7, Some(n)) => match info {
+                20 => Ok(Item::Bool(false)),
+                21 => Ok(Item::Bool(true)),
+                22 => Ok(Item::Null),
+                23 => Ok(Item::Undefined),
+                0..=19 => Ok(Item::Simple(n as u8)),
+                24 if n >= 32 => Ok(Item::Simple(n as u8)),
+                24 => Err(invalid(format!("badly formed simple value {n}"))),
+                25 => Ok(Item::Float(f16_to_f64(n as u16))),
+                26 => Ok(Item::Float(f32::from_bits(n as u32) as f64)),
+                _ => Ok(Item::Float(f64::from_bits(n))),
+            },
+            (7, None) => Err(invalid("unexpected break")),
+            _ => Err(invalid(format!("indefinite-length major type {major}"))),
+        }
+    }
+
+    /// A map entry, rejecting a key `keys`, the encoded keys of the map so far, already holds.
+    fn entry(
+        &mut self,
+        depth: usize,
+        keys: &mut BTreeSet<Vec<u8>>,
+    ) -> Result<(Item, Item), DecodeError> {
+        let key = self.item(depth + 1)?;
+        if !keys.insert(encode(&key)) {
+            return Err(invalid("duplicate map key"));
+        }
+        Ok((key, self.item(depth + 1)?))
+    }
+
+    fn text(&mut self, len: usize) -> Result<String, DecodeError> {
+        let bytes = self.take(len)?;
+        core::str::from_utf8(bytes)
+            .map(Into::into)
+            .map_err(|_| invalid("text string is not valid UTF-8"))
+    }
+}
+
+/// Parse the hex digits of `BigInt.prototype.toString(16)` into big-endian bytes.
+fn hex_to_bytes(digits: &str) -> Result<Vec<u8>> {
+    let digits = digits.as_bytes();
+    let mut bytes = Vec::with_capacity(digits.len() / 2 + 1);
+    let first = digits.len() % 2;
+    if first == 1 {
+        bytes.push(hex_digit(digits[0])?);
+    }
+    for pair in digits[first..].chunks(2) {
+        bytes.push(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?);
+    }
+    Ok(bytes)
+}
+
+fn hex_digit(digit: u8) -> Result<u8> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        _ => bail!("invalid hex digit in BigInt: {:?}", digit as char),
+    }
+}
+
+/// Add `delta`, 1 or -1, to a big-endian magnitude. The magnitude must not underflow.
+fn add_one(bytes: &mut Vec<u8>, delta: i8) {
+    let (from, to) = if delta > 0 { (0xff, 0) } else { (0, 0xff) };
+    for byte in bytes.iter_mut().rev() {
+        if *byte != from {
+            *byte = byte.wrapping_add(delta as u8);
+            return;
+        }
+        *byte = to;
+    }
+    if delta > 0 {
+        bytes.insert(0, 1);
+    }
+}
+
+fn strip_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// Format a big-endian magnitude in decimal.
+fn bytes_to_decimal(bytes: &[u8]) -> String {
+    let mut magnitude = strip_leading_zeros(bytes).to_vec();
+    let mut digits = Vec::new();
+    while !magnitude.is_empty() {
+        let mut remainder = 0u32;
+        for byte in magnitude.iter_mut() {
+            let acc = (remainder << 8) | *byte as u32;
+            *byte = (acc / 10) as u8;
+            remainder = acc % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+        magnitude = strip_leading_zeros(&magnitude).to_vec();
+    }
+    if digits.is_empty() {
+        digits.push(b'0');
+    }
+    digits.reverse();
+    String::from_utf8(digits).expect("decimal digits are ASCII")
+}
+
+fn number_item(n: f64) -> Item {
+    let negative_zero = n == 0.0 && n.is_sign_negative();
+    if negative_zero || n.abs() > MAX_SAFE_INTEGER as f64 || n != (n as i64) as f64 {
+        return Item::Float(n);
+    }
+    if n >= 0.0 {
+        Item::Unsigned(n as u64)
+    } else {
+        Item::Negative((-1.0 - n) as u64)
+    }
+}
+
+fn bigint_item(value: &js::Value) -> Result<Item> {
+    let ctx = value.context()?;
+    let hex = value
+        .call_method("toString", &[js::Value::from_i32(ctx, 16)])?
+        .decode_string()?;
+    let (negative, digits) = match hex.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, hex.as_str()),
+    };
+    let mut magnitude = hex_to_bytes(digits)?;
+    if negative {
+        // CBOR stores a negative integer `n` as `-1 - n`.
+        add_one(&mut magnitude, -1);
+    }
+    let magnitude = strip_leading_zeros(&magnitude);
+    if magnitude.len() <= 8 {
+        let mut buf = [0u8; 8];
+        buf[8 - magnitude.len()..].copy_from_slice(magnitude);
+        let n = u64::from_be_bytes(buf);
+        return Ok(if negative {
+            Item::Negative(n)
+        } else {
+            Item::Unsigned(n)
+        });
+    }
+    let tag = if negative {
+        TAG_NEGATIVE_BIGNUM
+    } else {
+        TAG_POSITIVE_BIGNUM
+    };
+    Ok(Item::Tag(tag, Box::new(Item::Bytes(magnitude.to_vec()))))
+}
+
+/// Convert a JS value to a CBOR data item, see the module documentation for the mapping.
+///
+/// Values nested deeper, and arrays longer, than the conversion limits of the context allow are
+/// rejected.
+pub fn to_item(value: &js::Value) -> Result<Item> {
+    let limits = value.context()?.conversion_limits();
+    to_item_at(value, 0, &limits)
+}
+
+fn to_item_at(value: &js::Value, depth: usize, limits: &js::ConversionLimits) -> Result<Item> {
+    if depth > limits.max_depth {
+        bail!("value nested deeper than {} levels", limits.max_depth);
+    }
+    if value.is_undefined() {
+        return Ok(Item::Undefined);
+    }
+    if value.is_null() {
+        return Ok(Item::Null);
+    }
+    if value.is_bool() {
+        return Ok(Item::Bool(value.decode_bool()?));
+    }
+    if value.is_number() {
+        return Ok(number_item(value.decode_f64()?));
+    }
+    if value.is_big_int() {
+        return bigint_item(value);
+    }
+    if value.is_string() {
+        return Ok(Item::Text(value.decode_string()?));
+    }
+    if value.is_uint8_array() || value.is_array_buffer() {
+        return Ok(Item::Bytes(value.decode_bytes()?));
+    }
+    if value.is_array() {
+        let len = value.length()?;
+        if len > limits.max_array_len {
+            bail!("array longer than {} elements", limits.max_array_len);
+        }
+        let items = (0..len)
+            .map(|i| to_item_at(&value.index(i)?, depth + 1, limits))
+            .collect::<Result<_>>()?;
+        return Ok(Item::Array(items));
+    }
+    if value.is_object() && !value.is_function() {
+        let map = value.is_map();
+        let entries = value
+            .entries()?
+            .map(|entry| {
+                let (key, value) = entry?;
+                let key = if map {
+                    to_item_at(&key, depth + 1, limits)?
+                } else {
+                    Item::Text(key.decode_string()?)
+                };
+                Ok((key, to_item_at(&value, depth + 1, limits)?))
+            })
+            .collect::<Result<_>>()?;
+        return Ok(Item::Map(entries));
+    }
+    bail!("functions and symbols can not be encoded as CBOR")
+}
+
+fn number_value(ctx: &js::Context, n: u64, negative: bool) -> js::Value {
+    match negative {
+        false if n <= MAX_SAFE_INTEGER => js::Value::from_f64(ctx, n as f64),
+        // `-1 - n` must be safe too.
+        true if n < MAX_SAFE_INTEGER => js::Value::from_f64(ctx, -1.0 - n as f64),
+        false => js::Value::biguint(ctx, n),
+        true => js::Value::from_i128(ctx, -1 - n as i128),
+    }
+}
+
+fn bignum_value(ctx: &js::Context, tag: u64, content: &Item) -> Result<js::Value> {
+    let Item::Bytes(magnitude) = content else {
+        bail!("invalid CBOR: bignum content must be a byte string");
+    };
+    if tag == TAG_POSITIVE_BIGNUM {
+        return js::Value::bigint_from_str(ctx, &bytes_to_decimal(magnitude));
+    }
+    let mut magnitude = magnitude.clone();
+    add_one(&mut magnitude, 1);
+    js::Value::bigint_from_str(ctx, &format!("-{}", bytes_to_decimal(&magnitude)))
+}
+
+/// Convert a CBOR data item to a JS value, see the module documentation for the mapping.
+pub fn to_value(ctx: &js::Context, item: Item) -> Result<js::Value> {
+    Ok(match item {
+        Item::Unsigned(n) => number_value(ctx, n, false),
+        Item::Negative(n) => number_value(ctx, n, true),
+        Item::Bytes(bytes) => js::Value::from_bytes_owned(ctx, bytes)?,
+        Item::Text(text) => js::Value::from_str(ctx, &text),
+        Item::Array(items) => {
+            let array = js::Value::new_array(ctx);
+            for item in items {
+                array.array_push(&to_value(ctx, item)?)?;
+            }
+            array
+        }
+        Item::Map(entries) if entries.iter().all(|(key, _)| matches!(key, Item::Text(_))) => {
+            let object = js::Value::new_object(ctx, "");
+            for (key, value) in entries {
+                let Item::Text(key) = key else { unreachable!() };
+                // Defined rather than set, so that a `__proto__` key is an own property.
+                object.define_property_value(&key, to_value(ctx, value)?)?;
+            }
+            object
+        }
+        Item::Map(entries) => {
+            let map = js::get_global(ctx).get_property("Map")?.construct(&[])?;
+            for (key, value) in entries {
+                map.call_method("set", &[to_value(ctx, key)?, to_value(ctx, value)?])?;
+            }
+            map
+        }
+        Item::Tag(tag @ (TAG_POSITIVE_BIGNUM | TAG_NEGATIVE_BIGNUM), content) => {
+            bignum_value(ctx, tag, &content)?
+        }
+        Item::Tag(_, content) => to_value(ctx, *content)?,
+        Item::Bool(b) => js::Value::from_bool(ctx, b),
+        Item::Null => js::Value::null(),
+        Item::Undefined => js::Value::undefined(),
+        Item::Simple(n) => bail!("unsupported CBOR simple value {n}"),
+        Item::Float(f) => js::Value::from_f64(ctx, f),
+    })
+}
+
+#[derive(js::FromJsValue, Default)]
+#[qjs(rename_all = "camelCase")]
+struct JsDecodeOptions {
+    allow_indefinite: Option<bool>,
+    max_depth: Option<usize>,
+}
+
+#[js::host_call]
+fn encode_value(value: js::Value) -> Result<AsBytes<Vec<u8>>> {
+    Ok(AsBytes(encode(&to_item(&value)?)))
+}
+
+#[js::host_call(with_context)]
+fn decode_value(
+    ctx: js::Context,
+    _this: js::Value,
+    data: js::Bytes,
+    options: Option<JsDecodeOptions>,
+) -> Result<js::Value> {
+    let options = options.unwrap_or_default();
+    let limits = ctx.conversion_limits();
+    let options = DecodeOptions {
+        allow_indefinite: options.allow_indefinite.unwrap_or(false),
+        max_depth: options
+            .max_depth
+            .map_or(limits.max_depth, |depth| depth.min(limits.max_depth)),
+        max_string_bytes: limits.max_string_bytes,
+        max_items: limits.max_array_len,
+    };
+    let item = decode(data.as_bytes(), &options).map_err(anyhow::Error::msg)?;
+    to_value(&ctx, item)
+}
+
+/// Define `encode(value)` and `decode(bytes, {allowIndefinite, maxDepth}?)` on `ns`.
+pub fn setup(ns: &js::Value) -> Result<()> {
+    ns.define_property_fn("encode", encode_value)?;
+    ns.define_property_fn("decode", decode_value)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn text(s: &str) -> Item {
+        Item::Text(s.into())
+    }
+
+    #[test]
+    fn rfc8949_vectors() {
+        use Item::*;
+        let vectors = [
+            ("00", Unsigned(0)),
+            ("17", Unsigned(23)),
+            ("1818", Unsigned(24)),
+            ("1903e8", Unsigned(1000)),
+            ("1b000000e8d4a51000", Unsigned(1000000000000)),
+            ("1bffffffffffffffff", Unsigned(u64::MAX)),
+            ("3bffffffffffffffff", Negative(u64::MAX)),
+            ("3863", Negative(99)),
+            (
+                "c249010000000000000000",
+                Tag(2, Box::new(Bytes(hex("010000000000000000")))),
+            ),
+            ("f90000", Float(0.0)),
+            ("f98000", Float(-0.0)),
+            ("f93e00", Float(1.5)),
+            ("f97bff", Float(65504.0)),
+            ("fa47c35000", Float(100000.0)),
+            ("fb3ff199999999999a", Float(1.1)),
+            ("fb7e37e43c8800759c", Float(1.0e300)),
+            ("f90001", Float(5.960464477539063e-8)),
+            ("f9c400", Float(-4.0)),
+            ("f97c00", Float(f64::INFINITY)),
+            ("f9fc00", Float(f64::NEG_INFINITY)),
+            ("f4", Bool(false)),
+            ("f6", Null),
+            ("f7", Undefined),
+            ("f8ff", Simple(255)),
+            (
+                "c074323031332d30332d32315432303a30343a30305a",
+                Tag(0, Box::new(text("2013-03-21T20:04:00Z"))),
+            ),
+            ("4401020304", Bytes(vec![1, 2, 3, 4])),
+            ("62c3bc", text("\u{fc}")),
+            ("64f0908591", text("\u{10151}")),
+            (
+                "8301820203820405",
+                Array(vec![
+                    Unsigned(1),
+                    Array(vec![Unsigned(2), Unsigned(3)]),
+                    Array(vec![Unsigned(4), Unsigned(5)]),
+                ]),
+            ),
+            (
+                "98190102030405060708090a0b0c0d0e0f101112131415161718181819",
+                Array((1..=25).map(Unsigned).collect()),
+            ),
+            (
+                "a26161016162820203",
+                Map(vec![
+                    (text("a"), Unsigned(1)),
+                    (text("b"), Array(vec![Unsigned(2), Unsigned(3)])),
+                ]),
+            ),
+        ];
+        for (encoded, item) in vectors {
+            assert_eq!(encode(&item), hex(encoded), "{item:?}");
+            assert_eq!(
+                decode(&hex(encoded), &DecodeOptions::default()).unwrap(),
+                item
+            );
+        }
+        assert_eq!(encode(&Float(f64::NAN)), hex("f97e00"));
+    }
+
+    #[test]
+    fn sorts_map_keys() {
+        let map = Item::Map(vec![
+            (text("b"), Item::Unsigned(1)),
+            (Item::Unsigned(10), Item::Unsigned(2)),
+            (text("a"), Item::Unsigned(3)),
+        ]);
+        assert_eq!(encode(&map), hex("a30a02616103616201"));
+    }
+
+    #[test]
+    fn indefinite_lengths_are_opt_in() {
+        let options = DecodeOptions {
+            allow_indefinite: true,
+            ..Default::default()
+        };
+        let vectors = [
+            ("5f42010243030405ff", "450102030405"),
+            ("7f657374726561646d696e67ff", "6973747265616d696e67"),
+            ("9f018202039f0405ffff", "8301820203820405"),
+            ("bf61610161629f0203ffff", "a26161016162820203"),
+        ];
+        for (indefinite, definite) in vectors {
+            let definite = hex(definite);
+            let item = decode(&hex(indefinite), &options).unwrap();
+            assert_eq!(encode(&item), definite);
+            assert!(matches!(
+                decode(&hex(indefinite), &DecodeOptions::default()),
+                Err(DecodeError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let options = DecodeOptions {
+            allow_indefinite: true,
+            max_depth: 16,
+            ..Default::default()
+        };
+        // Lengths beyond the input.
+        for input in [
+            "8301",
+            "9f01",
+            "5a00000010",
+            "9b00000000ffffffff",
+            "bb7fffffffffffffff",
+        ] {
+            assert_eq!(
+                decode(&hex(input), &options),
+                Err(DecodeError::Truncated),
+                "{input}"
+            );
+        }
+        for input in ["0000", "1c", "f818", "ff", "62c328"] {
+            assert!(
+                matches!(decode(&hex(input), &options), Err(DecodeError::Invalid(_))),
+                "{input}"
+            );
+        }
+        let mut deep = vec![0x81; 17];
+        deep.push(0);
+        assert_eq!(decode(&deep, &options), Err(DecodeError::TooDeep(16)));
+
+        // {"a": 1, "a": 2}
+        assert!(matches!(
+            decode(&hex("a2616101616102"), &options),
+            Err(DecodeError::Invalid(reason)) if reason == "duplicate map key"
+        ));
+        let options = DecodeOptions {
+            max_string_bytes: 2,
+            max_items: 2,
+            ..options
+        };
+        for input in [
+            "43010203",
+            "7f6261626163ff",
+            "83010203",
+            "9f010203ff",
+            "a3010102020303",
+        ] {
+            assert!(
+                matches!(decode(&hex(input), &options), Err(DecodeError::TooLong(_))),
+                "{input}"
+            );
+        }
+        assert!(decode(&hex("820102"), &options).is_ok());
+    }
+
+    #[test]
+    fn big_numbers() {
+        assert_eq!(
+            bytes_to_decimal(&hex("010000000000000000")),
+            "18446744073709551616"
+        );
+        let mut magnitude = hex("ffff");
+        add_one(&mut magnitude, 1);
+        assert_eq!(magnitude, hex("010000"));
+        add_one(&mut magnitude, -1);
+        assert_eq!(magnitude, hex("00ffff"));
+    }
+
+    #[test]
+    fn round_trips_js_values() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let ns = ctx.new_object("Cbor");
+        setup(&ns).unwrap();
+        ctx.get_global_object().set_property("Cbor", &ns).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src)).unwrap();
+
+        let encoded = eval(
+            "var value = { b: [1, -1, 1.5, 2n ** 64n, -(2n ** 64n) - 1n], a: new Uint8Array([1, 2]) };
+            var encoded = Cbor.encode(value);
+            encoded",
+        );
+        assert_eq!(
+            encoded.decode_bytes().unwrap(),
+            hex(concat!(
+                "a2",
+                "6161420102",
+                "616285",
+                "0120f93e00",
+                "c249010000000000000000",
+                "c349010000000000000000",
+            ))
+        );
+        let ok = eval(
+            "const decoded = Cbor.decode(encoded);
+            const map = Cbor.decode(Cbor.encode(new Map([[1, 'one'], ['x', null]])));
+            decoded.a instanceof Uint8Array && decoded.a.join() === '1,2'
+                && decoded.b[1] === -1 && decoded.b[2] === 1.5
+                && decoded.b[3] === 2n ** 64n && decoded.b[4] === -(2n ** 64n) - 1n
+                && Cbor.decode(Cbor.encode(2 ** 53 - 1)) === 2 ** 53 - 1
+                && Cbor.decode(new Uint8Array([0x1b, 0, 0x20, 0, 0, 0, 0, 0, 0])) === 2n ** 53n
+                && map instanceof Map && map.get(1) === 'one' && map.get('x') === null",
+        );
+        assert!(ok.decode_bool().unwrap());
+
+        let err = ctx
+            .eval(&js::Code::Source(
+                "Cbor.decode(new Uint8Array([0x9f, 0xff]))",
+            ))
+            .unwrap_err();
+        assert!(err.contains("indefinite-length array not allowed"), "{err}");
+        let decoded = eval("Cbor.decode(new Uint8Array([0x9f, 0xff]), { allowIndefinite: true })");
+        assert!(decoded.is_array());
+
+        // {"__proto__": {"polluted": true}}
+        let ok = eval(
+            "const ascii = (s) => Array.from(s, (c) => c.charCodeAt(0));
+            const proto = Cbor.decode(new Uint8Array([0xa1, 0x69, ...ascii('__proto__'),
+                0xa1, 0x68, ...ascii('polluted'), 0xf5]));
+            Object.getPrototypeOf(proto) === Object.prototype
+                && proto.polluted === undefined
+                && Object.keys(proto).join() === '__proto__'",
+        );
+        assert!(ok.decode_bool().unwrap());
+
+        ctx.set_conversion_limits(js::ConversionLimits {
+            max_depth: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let err = ctx
+            .eval(&js::Code::Source("Cbor.decode(Cbor.encode([[[[1]]]]))"))
+            .unwrap_err();
+        assert!(err.contains("nested deeper than 2 levels"), "{err}");
+        let err = ctx
+            .eval(&js::Code::Source(
+                "Cbor.decode(new Uint8Array([0x81, 0x81, 0x81, 0x01]))",
+            ))
+            .unwrap_err();
+        assert!(err.contains("CBOR nesting exceeds 2 levels"), "{err}");
+    }
+}
@@ -0,0 +1,413 @@
+//! Gzip and zlib deflate compression.
+//!
+//! Decompression is always bounded: the output may not exceed the limit set with
+//! [`set_max_decompressed_size`], 16 MB by default, so that small crafted inputs cannot exhaust
+//! the memory of the host.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use anyhow::bail;
+use js::{AsBytes, BytesOrString, Result};
+use miniz_oxide::{deflate, inflate, inflate::TINFLStatus};
+
+/// The default limit on the size of decompressed data.
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+const DEFAULT_LEVEL: u8 = 6;
+const MAX_LEVEL: u8 = 9;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const GZIP_DEFLATE: u8 = 8;
+const GZIP_HEADER_LEN: usize = 10;
+const GZIP_TRAILER_LEN: usize = 8;
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+/// Operating system "unknown" in the gzip header.
+const OS_UNKNOWN: u8 = 0xff;
+
+/// Why decompression failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The input ended before the end of the compressed stream.
+    Truncated,
+    /// The input is not valid compressed data.
+    Corrupt(&'static str),
+    /// The decompressed data would exceed the limit, in bytes.
+    TooLarge(usize),
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "truncated input: compressed stream ends unexpectedly"),
+            Self::Corrupt(reason) => write!(f, "corrupt input: {reason}"),
+            Self::TooLarge(limit) => write!(f, "decompressed data exceeds {limit} bytes"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecompressError {}
+
+fn check_level(level: Option<u8>) -> Result<u8> {
+    let level = level.unwrap_or(DEFAULT_LEVEL);
+    if level > MAX_LEVEL {
+        bail!("compression level must be between 0 and {MAX_LEVEL}, got {level}");
+    }
+    Ok(level)
+}
+
+fn inflate_error(status: TINFLStatus, max_size: usize) -> DecompressError {
+    match status {
+        TINFLStatus::FailedCannotMakeProgress | TINFLStatus::NeedsMoreInput => {
+            DecompressError::Truncated
+        }
+        TINFLStatus::HasMoreOutput => DecompressError::TooLarge(max_size),
+        TINFLStatus::Adler32Mismatch => DecompressError::Corrupt("adler32 checksum mismatch"),
+        _ => DecompressError::Corrupt("invalid deflate stream"),
+    }
+}
+
+/// Compress `data` into the zlib format, as produced by `CompressionStream("deflate")`.
+pub fn deflate_compress(data: &[u8], level: u8) -> Vec<u8> {
+    deflate::compress_to_vec_zlib(data, level)
+}
+
+/// Decompress zlib data, producing at most `max_size` bytes.
+pub fn deflate_decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, DecompressError> {
+    inflate::decompress_to_vec_zlib_with_limit(data, max_size)
+        .map_err(|err| inflate_error(err.status, max_size))
+}
+
+/// Compress `data` into a single gzip member.
+pub fn gzip_compress(data: &[u8], level: u8) -> Vec<u8> {
+    let body = deflate::compress_to_vec(data, level);
+    let mut out = Vec::with_capacity(GZIP_HEADER_LEN + body.len() + GZIP_TRAILER_LEN);
+    out.extend_from_slice(&GZIP_MAGIC);
+    out.push(GZIP_DEFLATE);
+    // No flags, no modification time.
+    out.extend_from_slice(&[0; 5]);
+    out.push(match level {
+        9 => 2,
+        0 | 1 => 4,
+        _ => 0,
+    });
+    out.push(OS_UNKNOWN);
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Decompress a single gzip member, producing at most `max_size` bytes.
+pub fn gzip_decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, DecompressError> {
+    let body_start = gzip_header_len(data)?;
+    if data.len() < body_start + GZIP_TRAILER_LEN {
+        return Err(DecompressError::Truncated);
+    }
+    let (body, trailer) = data[body_start..].split_at(data.len() - body_start - GZIP_TRAILER_LEN);
+    let out = inflate::decompress_to_vec_with_limit(body, max_size)
+        .map_err(|err| inflate_error(err.status, max_size))?;
+    let (crc, size) = trailer.split_at(4);
+    if crc32(&out).to_le_bytes() != crc {
+        return Err(DecompressError::Corrupt("crc32 checksum mismatch"));
+    }
+    if (out.len() as u32).to_le_bytes() != size {
+        return Err(DecompressError::Corrupt("size mismatch"));
+    }
+    Ok(out)
+}
+
+/// The length of the gzip header at the start of `data`, including the optional fields.
+fn gzip_header_len(data: &[u8]) -> Result<usize, DecompressError> {
+    if data.len() < GZIP_HEADER_LEN {
+        return if GZIP_MAGIC.starts_with(&data[..data.len().min(2)]) {
+            Err(DecompressError::Truncated)
+        } else {
+            Err(DecompressError::Corrupt("not gzip data"))
+        };
+    }
+    if data[..2] != GZIP_MAGIC {
+        return Err(DecompressError::Corrupt("not gzip data"));
+    }
+    if data[2] != GZIP_DEFLATE {
+        return Err(DecompressError::Corrupt(
+            "unsupported gzip compression method",
+        ));
+    }
+    let flags = data[3];
+    let mut pos = GZIP_HEADER_LEN;
+    if flags & FEXTRA != 0 {
+        let len = data.get(pos..pos + 2).ok_or(DecompressError::Truncated)?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let rest = data.get(pos..).ok_or(DecompressError::Truncated)?;
+            let end = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or(DecompressError::Truncated)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    if pos > data.len() {
+        return Err(DecompressError::Truncated);
+    }
+    Ok(pos)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// The decompression cap of a context, kept with its user data out of reach of scripts.
+struct MaxDecompressedSize(usize);
+
+/// Set the largest output the decompression functions of the context may produce. Scripts can
+/// request a lower limit with the `maxSize` option but never a higher one. Fails for contexts
+/// without teardown support, which can not keep the limit.
+pub fn set_max_decompressed_size(ctx: &js::Context, max_size: usize) -> Result<()> {
+    ctx.set_user_data(MaxDecompressedSize(max_size));
+    if ctx.user_data::<MaxDecompressedSize>().is_none() {
+        bail!("can not keep a decompression limit for a context without teardown support");
+    }
+    Ok(())
+}
+
+pub fn max_decompressed_size(ctx: &js::Context) -> Result<usize> {
+    Ok(ctx
+        .user_data::<MaxDecompressedSize>()
+        .map_or(DEFAULT_MAX_DECOMPRESSED_SIZE, |limit| limit.0))
+}
+
+#[derive(js::FromJsValue, Default)]
+#[qjs(rename_all = "camelCase")]
+struct DecompressOptions {
+    max_size: Option<usize>,
+}
+
+fn effective_limit(ctx: &js::Context, options: Option<DecompressOptions>) -> Result<usize> {
+    let limit = max_decompressed_size(ctx)?;
+    let requested = options.unwrap_or_default().max_size;
+    Ok(requested.map_or(limit, |size| size.min(limit)))
+}
+
+#[js::host_call(with_context)]
+fn gzip_compress_fn(
+    _ctx: js::Context,
+    _this: js::Value,
+    data: BytesOrString,
+    level: Option<u8>,
+) -> Result<AsBytes<Vec<u8>>> {
+    Ok(AsBytes(gzip_compress(data.as_bytes(), check_level(level)?)))
+}
+
+#[js::host_call(with_context)]
+fn gzip_decompress_fn(
+    ctx: js::Context,
+    _this: js::Value,
+    data: js::Bytes,
+    options: Option<DecompressOptions>,
+) -> Result<AsBytes<Vec<u8>>> {
+    let limit = effective_limit(&ctx, options)?;
+    gzip_decompress(data.as_bytes(), limit)
+        .map(AsBytes)
+        .map_err(anyhow::Error::msg)
+}
+
+#[js::host_call(with_context)]
+fn deflate_compress_fn(
+    _ctx: js::Context,
+    _this: js::Value,
+    data: BytesOrString,
+    level: Option<u8>,
+) -> Result<AsBytes<Vec<u8>>> {
+    Ok(AsBytes(deflate_compress(
+        data.as_bytes(),
+        check_level(level)?,
+    )))
+}
+
+#[js::host_call(with_context)]
+fn deflate_decompress_fn(
+    ctx: js::Context,
+    _this: js::Value,
+    data: js::Bytes,
+    options: Option<DecompressOptions>,
+) -> Result<AsBytes<Vec<u8>>> {
+    let limit = effective_limit(&ctx, options)?;
+    deflate_decompress(data.as_bytes(), limit)
+        .map(AsBytes)
+        .map_err(anyhow::Error::msg)
+}
+
+/// Define `gzip` and `deflate` on `ns`, each with `compress(data, level?)` and
+/// `decompress(bytes, {maxSize}?)`.
+pub fn setup(ns: &js::Value, ctx: &js::Context) -> Result<()> {
+    let gzip = ctx.new_object("Gzip");
+    gzip.define_property_fn("compress", gzip_compress_fn)?;
+    gzip.define_property_fn("decompress", gzip_decompress_fn)?;
+    ns.set_property("gzip", &gzip)?;
+
+    let deflate = ctx.new_object("Deflate");
+    deflate.define_property_fn("compress", deflate_compress_fn)?;
+    deflate.define_property_fn("decompress", deflate_decompress_fn)?;
+    ns.set_property("deflate", &deflate)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn sample() -> Vec<u8> {
+        (0..10_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let data = sample();
+        for level in [0, 1, 6, 9] {
+            let gz = gzip_compress(&data, level);
+            assert_eq!(gz[..2], GZIP_MAGIC);
+            assert_eq!(gzip_decompress(&gz, data.len()).unwrap(), data);
+            let zlib = deflate_compress(&data, level);
+            assert_eq!(deflate_decompress(&zlib, data.len()).unwrap(), data);
+        }
+        assert_eq!(gzip_decompress(&gzip_compress(b"", 6), 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn reads_headers_with_optional_fields() {
+        // As written by `gzip` without `-n`, which stores the original file name.
+        let mut gz = gzip_compress(b"hello", 6);
+        gz[3] = FNAME;
+        gz.splice(GZIP_HEADER_LEN..GZIP_HEADER_LEN, *b"hello.txt\0");
+        assert_eq!(gzip_decompress(&gz, 16).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn distinguishes_truncated_from_corrupt() {
+        let data = sample();
+        let gz = gzip_compress(&data, 6);
+        assert_eq!(
+            gzip_decompress(&gz[..gz.len() / 2], data.len()),
+            Err(DecompressError::Truncated)
+        );
+        assert_eq!(
+            gzip_decompress(&gz[..4], data.len()),
+            Err(DecompressError::Truncated)
+        );
+
+        let mut bad_crc = gz.clone();
+        let at = bad_crc.len() - GZIP_TRAILER_LEN;
+        bad_crc[at] ^= 1;
+        assert!(matches!(
+            gzip_decompress(&bad_crc, data.len()),
+            Err(DecompressError::Corrupt(_))
+        ));
+        assert!(matches!(
+            gzip_decompress(b"definitely not gzip", data.len()),
+            Err(DecompressError::Corrupt(_))
+        ));
+
+        let zlib = deflate_compress(&data, 6);
+        assert_eq!(
+            deflate_decompress(&zlib[..zlib.len() / 2], data.len()),
+            Err(DecompressError::Truncated)
+        );
+        let mut bad_adler = zlib.clone();
+        *bad_adler.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            deflate_decompress(&bad_adler, data.len()),
+            Err(DecompressError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bombs_over_the_cap() {
+        let bomb = gzip_compress(&vec![0; DEFAULT_MAX_DECOMPRESSED_SIZE + 1], 9);
+        assert!(bomb.len() < 64 * 1024);
+        assert_eq!(
+            gzip_decompress(&bomb, DEFAULT_MAX_DECOMPRESSED_SIZE),
+            Err(DecompressError::TooLarge(DEFAULT_MAX_DECOMPRESSED_SIZE))
+        );
+    }
+
+    #[test]
+    fn scripts_are_bound_by_the_host_limit() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let ns = ctx.new_object("Compression");
+        setup(&ns, &ctx).unwrap();
+        ctx.get_global_object()
+            .set_property("Compression", &ns)
+            .unwrap();
+        set_max_decompressed_size(&ctx, 1024).unwrap();
+
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let n = eval(
+            "const { gzip, deflate } = Compression;
+            gzip.decompress(gzip.compress('hello world', 9)).length
+                + deflate.decompress(deflate.compress('hello')).length",
+        )
+        .unwrap();
+        assert_eq!(n.decode_u32().unwrap(), 16);
+
+        let err = eval("gzip.decompress(gzip.compress(new Uint8Array(2048)))").unwrap_err();
+        assert!(err.contains("exceeds 1024 bytes"), "{err}");
+        let err = eval("gzip.decompress(gzip.compress(new Uint8Array(100)), { maxSize: 10 })")
+            .unwrap_err();
+        assert!(err.contains("exceeds 10 bytes"), "{err}");
+        let n = eval("gzip.decompress(gzip.compress('x'), { maxSize: 1 << 20 }).length").unwrap();
+        assert_eq!(n.decode_u32().unwrap(), 1);
+        let err = eval("gzip.decompress(gzip.compress('abc').subarray(0, 12))").unwrap_err();
+        assert!(err.contains("truncated"), "{err}");
+
+        let err = eval(
+            "delete globalThis._QjsBind;
+            gzip.decompress(gzip.compress(new Uint8Array(2048)))",
+        )
+        .unwrap_err();
+        assert!(err.contains("exceeds 1024 bytes"), "{err}");
+    }
+}
@@ -1,14 +1,77 @@
 use anyhow::bail;
 
+use alloc::boxed::Box;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use rand::RngCore;
+use core::cell::RefCell;
 
+#[cfg(feature = "crypto-aes")]
 use cipher::generic_array::GenericArray;
+#[cfg(feature = "crypto-aes")]
 use cipher::{ArrayLength, KeyInit, StreamCipher};
 
 use js::{Native, NoStdContext, Result, ToJsValue};
 
+use crate::output::Output;
+
+/// Every algorithm name recognized by `crypto.subtle`, in its canonical spelling, with whether a
+/// function compiled in accepts it, as listed by `crypto.capabilities.algorithms`.
+const ALGORITHMS: &[(&str, bool)] = &[
+    // Recognized, but no RSA operation is implemented, so there is no `crypto-rsa` feature.
+    ("RSASSA-PKCS1-v1_5", false),
+    ("RSA-PSS", false),
+    ("RSA-OAEP", false),
+    ("ECDSA", cfg!(feature = "crypto-ec")),
+    ("ECDH", cfg!(feature = "crypto-ec")),
+    ("AES-CTR", cfg!(feature = "crypto-aes")),
+    ("AES-CBC", cfg!(feature = "crypto-aes")),
+    ("AES-GCM", cfg!(feature = "crypto-aes")),
+    ("AES-KW", false),
+    // Keys of these can be generated and imported, but nothing signs with HMAC or digests with
+    // SHA-1, so they are not listed.
+    ("HMAC", false),
+    ("SHA-1", false),
+    // What `digest` takes.
+    ("SHA-256", true),
+    ("SHA-384", true),
+    ("SHA-512", true),
+    ("HKDF", false),
+    ("PBKDF2", false),
+];
+
+/// Map an algorithm name to its canonical spelling, matching case-insensitively.
+///
+/// Unknown names are returned unchanged.
+fn normalize_algorithm_name(name: &str) -> String {
+    ALGORITHMS
+        .iter()
+        .map(|(known, _)| *known)
+        .find(|known| known.eq_ignore_ascii_case(name))
+        .unwrap_or(name)
+        .to_string()
+}
+
+fn not_supported(name: &str, supported: &[&str]) -> js::Error {
+    js::Error::msg(
+        js::JsError::new(
+            "NotSupportedError",
+            alloc::format!(
+                "unsupported algorithm: {name}, expected one of: {}",
+                supported.join(", ")
+            ),
+        )
+        .with_code("ERR_UNSUPPORTED_ALGORITHM"),
+    )
+}
+
+#[cfg(feature = "crypto-aes")]
+fn invalid_key_length() -> js::Error {
+    js::Error::msg(
+        js::JsError::new("DataError", "key must be 16, 24, or 32 bytes long")
+            .with_code("ERR_INVALID_KEY_LENGTH"),
+    )
+}
+
 fn from_js<T>(value: js::Value) -> Result<T>
 where
     T: js::FromJsValue,
@@ -18,21 +81,73 @@ where
 
 #[derive(Debug)]
 struct BaseAlgorithm {
-    name: js::JsString,
+    /// The normalized algorithm name.
+    name: String,
 }
 
 impl js::FromJsValue for BaseAlgorithm {
     fn from_js_value(value: js::Value) -> Result<Self> {
         let name = if value.is_string() {
             value
-        } else {
+        } else if value.is_plain_object() {
             value.get_property("name")?
+        } else {
+            return Err(js::Error::msg(js::JsError::type_error(alloc::format!(
+                "algorithm must be a name or a plain object, got {}",
+                describe_exotic(&value)
+            ))));
         };
         let name = js::JsString::from_js_value(name)?;
-        Ok(BaseAlgorithm { name })
+        Ok(BaseAlgorithm {
+            name: normalize_algorithm_name(name.as_str()),
+        })
     }
 }
 
+/// What a value that is neither a string nor a plain object is, for error messages.
+fn describe_exotic(value: &js::Value) -> String {
+    if let Some(kind) = value.is_typed_array() {
+        return kind.name().into();
+    }
+    if !value.is_object() {
+        return value.get_name();
+    }
+    let kind = if value.is_array() {
+        "an array"
+    } else if value.is_function() {
+        "a function"
+    } else if value.is_array_buffer() {
+        "an ArrayBuffer"
+    } else if value.is_date() {
+        "a Date"
+    } else if value.is_reg_exp() {
+        "a RegExp"
+    } else if value.is_error() {
+        "an Error"
+    } else {
+        "an object with a custom prototype"
+    };
+    kind.into()
+}
+
+/// A hash algorithm, given either as a name or as an object with a `name` property.
+#[derive(js::ToJsValue, js::GcMark, Debug, Clone)]
+struct HashAlgorithm {
+    name: String,
+}
+
+impl js::FromJsValue for HashAlgorithm {
+    fn from_js_value(value: js::Value) -> Result<Self> {
+        const SUPPORTED: &[&str] = &["SHA-1", "SHA-256", "SHA-384", "SHA-512"];
+        let base = BaseAlgorithm::from_js_value(value)?;
+        if !SUPPORTED.contains(&base.name.as_str()) {
+            return Err(not_supported(&base.name, SUPPORTED));
+        }
+        Ok(HashAlgorithm { name: base.name })
+    }
+}
+
+#[cfg(feature = "crypto-aes")]
 #[allow(dead_code)]
 #[derive(js::FromJsValue, Debug)]
 #[qjs(rename_all = "camelCase")]
@@ -40,6 +155,7 @@ struct RsaOaepParams {
     label: js::Bytes,
 }
 
+#[cfg(feature = "crypto-aes")]
 #[allow(dead_code)]
 #[derive(js::FromJsValue, Debug)]
 #[qjs(rename_all = "camelCase")]
@@ -48,6 +164,7 @@ struct AesCtrParams {
     length: usize,
 }
 
+#[cfg(feature = "crypto-aes")]
 #[allow(dead_code)]
 #[derive(js::FromJsValue, Debug)]
 #[qjs(rename_all = "camelCase")]
@@ -55,6 +172,7 @@ struct AesCbcParams {
     iv: js::Bytes,
 }
 
+#[cfg(feature = "crypto-aes")]
 #[derive(js::FromJsValue, Debug)]
 #[qjs(rename_all = "camelCase")]
 struct AesGcmParams {
@@ -63,6 +181,7 @@ struct AesGcmParams {
     tag_length: Option<usize>,
 }
 
+#[cfg(feature = "crypto-aes")]
 enum CryptAlgorithm {
     RsaOaep(RsaOaepParams),
     AesCtr(AesCtrParams),
@@ -70,6 +189,7 @@ enum CryptAlgorithm {
     AesGcm(AesGcmParams),
 }
 
+#[cfg(feature = "crypto-aes")]
 impl js::FromJsValue for CryptAlgorithm {
     fn from_js_value(value: js::Value) -> Result<Self> {
         use CryptAlgorithm::*;
@@ -79,41 +199,49 @@ impl js::FromJsValue for CryptAlgorithm {
             "AES-CBC" => Ok(AesCbc(from_js(value)?)),
             "AES-CTR" => Ok(AesCtr(from_js(value)?)),
             "RSA-OAEP" => Ok(RsaOaep(from_js(value)?)),
-            _ => bail!("unsupported algorithm: {}", base.name),
+            name => Err(not_supported(
+                name,
+                &["AES-GCM", "AES-CBC", "AES-CTR", "RSA-OAEP"],
+            )),
         }
     }
 }
 
+#[cfg(feature = "crypto-ec")]
 #[derive(js::FromJsValue)]
 #[qjs(rename_all = "camelCase")]
 struct EcdhKeyDeriveParams {
     public: Native<CryptoKey>,
 }
 
+#[cfg(feature = "crypto-ec")]
 #[allow(dead_code)]
 #[derive(js::FromJsValue, Debug)]
 #[qjs(rename_all = "camelCase")]
 struct HkdfParams {
-    hash: js::JsString,
+    hash: HashAlgorithm,
     salt: js::Bytes,
     info: js::Bytes,
 }
 
+#[cfg(feature = "crypto-ec")]
 #[allow(dead_code)]
 #[derive(js::FromJsValue, Debug)]
 #[qjs(rename_all = "camelCase")]
 struct Pbkdf2Params {
-    hash: js::JsString,
+    hash: HashAlgorithm,
     salt: js::Bytes,
     iterations: usize,
 }
 
+#[cfg(feature = "crypto-ec")]
 enum DeriveAlgorithm {
     Ecdh(EcdhKeyDeriveParams),
     Hkdf(HkdfParams),
     Pbkdf2(Pbkdf2Params),
 }
 
+#[cfg(feature = "crypto-ec")]
 impl js::FromJsValue for DeriveAlgorithm {
     fn from_js_value(value: js::Value) -> Result<Self> {
         use DeriveAlgorithm::*;
@@ -122,7 +250,7 @@ impl js::FromJsValue for DeriveAlgorithm {
             "ECDH" => Ok(Ecdh(from_js(value)?)),
             "HKDF" => Ok(Hkdf(from_js(value)?)),
             "PBKDF2" => Ok(Pbkdf2(from_js(value)?)),
-            _ => bail!("unsupported algorithm: {}", base.name),
+            name => Err(not_supported(name, &["ECDH", "HKDF", "PBKDF2"])),
         }
     }
 }
@@ -130,16 +258,17 @@ impl js::FromJsValue for DeriveAlgorithm {
 #[derive(js::FromJsValue, js::ToJsValue, js::GcMark, Debug, Clone)]
 #[qjs(rename_all = "camelCase")]
 struct HmacKeyGenParams {
-    hash: js::JsString,
+    hash: HashAlgorithm,
     length: Option<usize>,
 }
 
 #[derive(js::FromJsValue, js::ToJsValue, js::GcMark, Debug, Clone)]
 struct AesKeyGenParams {
-    name: js::JsString,
+    name: String,
     length: usize,
 }
 
+#[cfg(feature = "crypto-ec")]
 enum DeriveKeyGenAlgorithm {
     Hmac(HmacKeyGenParams),
     Aes(AesKeyGenParams),
@@ -147,16 +276,25 @@ enum DeriveKeyGenAlgorithm {
     Pbkdf2(Pbkdf2Params),
 }
 
+#[cfg(feature = "crypto-ec")]
 impl js::FromJsValue for DeriveKeyGenAlgorithm {
     fn from_js_value(value: js::Value) -> Result<Self> {
         use DeriveKeyGenAlgorithm::*;
         let base = BaseAlgorithm::from_js_value(value.clone())?;
         match base.name.as_str() {
             "HMAC" => Ok(Hmac(from_js(value)?)),
-            "AES-CBC" | "AES-CTR" | "AES-GCM" | "AES-KW" => Ok(Aes(from_js(value)?)),
+            "AES-CBC" | "AES-CTR" | "AES-GCM" | "AES-KW" => Ok(Aes(AesKeyGenParams {
+                name: base.name,
+                ..from_js(value)?
+            })),
             "HKDF" => Ok(Hkdf(from_js(value)?)),
             "PBKDF2" => Ok(Pbkdf2(from_js(value)?)),
-            _ => bail!("unsupported algorithm: {}", base.name),
+            name => Err(not_supported(
+                name,
+                &[
+                    "HMAC", "AES-CBC", "AES-CTR", "AES-GCM", "AES-KW", "HKDF", "PBKDF2",
+                ],
+            )),
         }
     }
 }
@@ -164,16 +302,16 @@ impl js::FromJsValue for DeriveKeyGenAlgorithm {
 #[derive(js::FromJsValue, js::ToJsValue, js::GcMark, Debug, Clone)]
 #[qjs(rename_all = "camelCase")]
 struct RsaHashedKeyGenParams {
-    name: js::JsString,
+    name: String,
     modulus_length: usize,
     public_exponent: js::Bytes,
-    hash: js::JsString,
+    hash: HashAlgorithm,
 }
 
 #[derive(js::FromJsValue, js::ToJsValue, js::GcMark, Debug, Clone)]
 #[qjs(rename_all = "camelCase")]
 struct EcKeyGenParams {
-    name: js::JsString,
+    name: String,
     named_curve: js::JsString,
 }
 
@@ -190,11 +328,34 @@ impl js::FromJsValue for KeyGenAlgorithm {
         use KeyGenAlgorithm::*;
         let base = BaseAlgorithm::from_js_value(value.clone())?;
         match base.name.as_str() {
-            "RSASSA-PKCS1-v1_5" | "RSA-OAEP" | "RSA-PSS" => Ok(Rsa(from_js(value)?)),
-            "ECDSA" | "ECDH" => Ok(Ec(from_js(value)?)),
+            "RSASSA-PKCS1-v1_5" | "RSA-OAEP" | "RSA-PSS" => Ok(Rsa(RsaHashedKeyGenParams {
+                name: base.name,
+                ..from_js(value)?
+            })),
+            "ECDSA" | "ECDH" => Ok(Ec(EcKeyGenParams {
+                name: base.name,
+                ..from_js(value)?
+            })),
             "HMAC" => Ok(Hmac(from_js(value)?)),
-            "AES-CBC" | "AES-CTR" | "AES-GCM" | "AES-KW" => Ok(Aes(from_js(value)?)),
-            _ => bail!("unsupported algorithm: {}", base.name),
+            "AES-CBC" | "AES-CTR" | "AES-GCM" | "AES-KW" => Ok(Aes(AesKeyGenParams {
+                name: base.name,
+                ..from_js(value)?
+            })),
+            name => Err(not_supported(
+                name,
+                &[
+                    "RSASSA-PKCS1-v1_5",
+                    "RSA-OAEP",
+                    "RSA-PSS",
+                    "ECDSA",
+                    "ECDH",
+                    "HMAC",
+                    "AES-CBC",
+                    "AES-CTR",
+                    "AES-GCM",
+                    "AES-KW",
+                ],
+            )),
         }
     }
 }
@@ -210,26 +371,131 @@ impl js::ToJsValue for KeyGenAlgorithm {
     }
 }
 
-use native_classes::CryptoKey;
+impl KeyGenAlgorithm {
+    /// The parameters that tell keys with the same material apart, in a fixed order.
+    fn descriptor(&self) -> String {
+        match self {
+            KeyGenAlgorithm::Rsa(params) => alloc::format!(
+                "{}:{}:{}",
+                params.name,
+                params.hash.name,
+                params.modulus_length
+            ),
+            KeyGenAlgorithm::Ec(params) => alloc::format!("{}:{}", params.name, params.named_curve),
+            KeyGenAlgorithm::Hmac(params) => alloc::format!("HMAC:{}", params.hash.name),
+            KeyGenAlgorithm::Aes(params) => alloc::format!("{}:{}", params.name, params.length),
+        }
+    }
+}
+
+pub use native_classes::CryptoKey;
 
 #[js::qjsbind]
 mod native_classes {
-    use super::{KeyGenAlgorithm, String, Vec};
+    use super::{ec_spki, KeyGenAlgorithm, Native, Result, String, Vec};
 
     #[qjs(class(rename_all = "camelCase"))]
     pub struct CryptoKey {
         #[qjs(getter)]
-        pub r#type: String,
+        pub(crate) r#type: String,
         #[qjs(getter)]
-        pub extractable: bool,
+        pub(crate) extractable: bool,
         #[qjs(getter)]
-        pub algorithm: KeyGenAlgorithm,
+        pub(crate) algorithm: KeyGenAlgorithm,
         #[qjs(getter)]
-        pub usages: Vec<js::JsString>,
-        pub raw: Vec<u8>,
+        pub(crate) usages: Vec<js::JsString>,
+        pub(crate) raw: Vec<u8>,
     }
+
+    impl CryptoKey {
+        /// The SHA-256 of the key type, its algorithm and its material.
+        ///
+        /// Public keys are hashed in their SPKI encoding and other keys in their raw encoding.
+        /// The usages and the extractable flag are left out, so the same key imported for other
+        /// purposes has the same fingerprint.
+        ///
+        /// Non-extractable keys can be fingerprinted as well: the hash identifies the key
+        /// without revealing its material.
+        pub fn fingerprint(&self) -> Result<[u8; 32]> {
+            use sha2::{Digest, Sha256};
+            let mut hasher = Sha256::new();
+            for part in [
+                self.r#type.as_bytes(),
+                self.algorithm.descriptor().as_bytes(),
+                &self.canonical_material()?,
+            ] {
+                hasher.update((part.len() as u32).to_le_bytes());
+                hasher.update(part);
+            }
+            Ok(hasher.finalize().into())
+        }
+
+        /// Whether both keys are of the same type and algorithm, and hold the same material.
+        ///
+        /// The material is compared in constant time.
+        pub fn equals(&self, other: &CryptoKey) -> bool {
+            use subtle::ConstantTimeEq;
+            self.r#type == other.r#type
+                && self.algorithm.descriptor() == other.algorithm.descriptor()
+                && bool::from(self.raw.ct_eq(&other.raw))
+        }
+
+        /// The fingerprint as a lowercase hex string, usable as a `Map` key.
+        #[qjs(method, js_name = "fingerprint")]
+        fn js_fingerprint(&self) -> Result<String> {
+            Ok(self
+                .fingerprint()?
+                .iter()
+                .map(|b| alloc::format!("{b:02x}"))
+                .collect())
+        }
+
+        #[qjs(method, js_name = "equals")]
+        fn js_equals(&self, other: Native<CryptoKey>) -> bool {
+            self.equals(&other.borrow())
+        }
+
+        pub(super) fn canonical_material(&self) -> Result<Vec<u8>> {
+            let KeyGenAlgorithm::Ec(params) = &self.algorithm else {
+                return Ok(self.raw.clone());
+            };
+            if self.r#type != "public" {
+                return Ok(self.raw.clone());
+            }
+            ec_spki(params.named_curve.as_str(), &self.raw)
+        }
+    }
+}
+
+/// The SPKI encoding of an EC public key given in SEC1.
+#[cfg(feature = "crypto-ec")]
+fn ec_spki(curve: &str, sec1: &[u8]) -> Result<Vec<u8>> {
+    macro_rules! spki {
+        ($module: ident) => {{
+            use $module::pkcs8::EncodePublicKey;
+            $module::PublicKey::from_sec1_bytes(sec1)
+                .context("invalid public key")?
+                .to_public_key_der()
+                .context("failed to encode the public key")?
+                .as_bytes()
+                .to_vec()
+        }};
+    }
+    Ok(match curve {
+        "P-256" => spki!(p256),
+        "P-384" => spki!(p384),
+        "P-521" => spki!(p521),
+        curve => bail!("unsupported named curve: {curve}"),
+    })
 }
 
+/// Without the curves, public EC keys can not be made in the first place.
+#[cfg(not(feature = "crypto-ec"))]
+fn ec_spki(curve: &str, _sec1: &[u8]) -> Result<Vec<u8>> {
+    bail!("unsupported named curve: {curve}, built without the crypto-ec feature")
+}
+
+#[cfg(feature = "crypto-ec")]
 #[derive(ToJsValue)]
 #[qjs(rename_all = "camelCase")]
 struct CryptoKeyPair {
@@ -237,12 +503,14 @@ struct CryptoKeyPair {
     private_key: Native<CryptoKey>,
 }
 
+#[cfg(feature = "crypto-ec")]
 enum CryptoKeyOrPair {
     #[allow(dead_code)]
     Key(Native<CryptoKey>),
     Pair(CryptoKeyPair),
 }
 
+#[cfg(feature = "crypto-ec")]
 impl ToJsValue for CryptoKeyOrPair {
     fn to_js_value(&self, ctx: &js::Context) -> Result<js::Value> {
         match self {
@@ -252,6 +520,7 @@ impl ToJsValue for CryptoKeyOrPair {
     }
 }
 
+#[cfg(feature = "crypto-ec")]
 impl CryptoKeyOrPair {
     fn from_pair_raw(
         ctx: js::Context,
@@ -282,6 +551,7 @@ impl CryptoKeyOrPair {
     }
 }
 
+#[cfg(feature = "crypto-aes")]
 fn generic_array_from_slice<L>(arr: &[u8]) -> Result<GenericArray<u8, L>>
 where
     L: ArrayLength<u8>,
@@ -289,12 +559,14 @@ where
     GenericArray::from_exact_iter(arr.iter().copied()).context("invalid length")
 }
 
+#[cfg(feature = "crypto-aes")]
 #[js::host_call]
 fn encrypt(
     algorithm: CryptAlgorithm,
     key: Native<CryptoKey>,
     data: js::BytesOrString,
-) -> Result<js::Bytes> {
+    out: Option<js::JsUint8Array>,
+) -> Result<Output<Vec<u8>>> {
     let key = key.borrow();
     match algorithm {
         CryptAlgorithm::AesGcm(params) => {
@@ -304,7 +576,7 @@ fn encrypt(
             macro_rules! encrypt_with {
                 ($key_size:ident) => {{
                     let aead = aes_gcm::AesGcm::<aes::$key_size, U12>::new(
-                        &generic_array_from_slice(&key.raw).context("invalid key length")?,
+                        &generic_array_from_slice(&key.raw).map_err(|_| invalid_key_length())?,
                     );
                     let nonce = generic_array_from_slice(&params.iv)?;
                     let ciphertext = aead
@@ -332,9 +604,9 @@ fn encrypt(
                 128 => encrypt_with!(Aes128),
                 192 => encrypt_with!(Aes192),
                 256 => encrypt_with!(Aes256),
-                _ => bail!("key must be 16, 24, or 32 bytes long"),
+                _ => return Err(invalid_key_length()),
             };
-            Ok(ciphertext.into())
+            Output::new(ciphertext, out)
         }
         CryptAlgorithm::AesCbc(params) => {
             use aes::cipher::{block_padding::Pkcs7, BlockCipher, BlockEncryptMut, KeyIvInit};
@@ -344,7 +616,7 @@ fn encrypt(
             where
                 C: BlockEncryptMut + BlockCipher + KeyInit,
             {
-                let key = generic_array_from_slice(key).context("invalid key length")?;
+                let key = generic_array_from_slice(key).map_err(|_| invalid_key_length())?;
                 let iv = generic_array_from_slice(iv).context("invalid iv length")?;
                 let cipher = Encryptor::<C>::new(&key, &iv);
                 Ok(cipher.encrypt_padded_vec_mut::<Pkcs7>(data))
@@ -356,9 +628,9 @@ fn encrypt(
                 128 => encrypt_with::<Aes128>(&key.raw, &params.iv, data.as_bytes())?,
                 192 => encrypt_with::<Aes192>(&key.raw, &params.iv, data.as_bytes())?,
                 256 => encrypt_with::<Aes256>(&key.raw, &params.iv, data.as_bytes())?,
-                _ => bail!("key must be 16, 24, or 32 bytes long"),
+                _ => return Err(invalid_key_length()),
             };
-            Ok(ciphertext.into())
+            Output::new(ciphertext, out)
         }
         CryptAlgorithm::AesCtr(params) => {
             use aes::cipher::KeyIvInit;
@@ -366,7 +638,7 @@ fn encrypt(
             macro_rules! encrypt_with {
                 ($key_size:ident) => {{
                     let mut cipher = Ctr64LE::<aes::$key_size>::new(
-                        &generic_array_from_slice(&key.raw).context("invalid key length")?,
+                        &generic_array_from_slice(&key.raw).map_err(|_| invalid_key_length())?,
                         &generic_array_from_slice(&params.counter)
                             .context("invalid counter length")?,
                     );
@@ -384,20 +656,22 @@ fn encrypt(
                 128 => encrypt_with!(Aes128),
                 192 => encrypt_with!(Aes192),
                 256 => encrypt_with!(Aes256),
-                _ => bail!("key must be 16, 24, or 32 bytes long"),
+                _ => return Err(invalid_key_length()),
             };
-            Ok(ciphertext.into())
+            Output::new(ciphertext, out)
         }
         _ => bail!("unsupported encryption algorithm"),
     }
 }
 
+#[cfg(feature = "crypto-aes")]
 #[js::host_call]
 fn decrypt(
     algorithm: CryptAlgorithm,
     key: Native<CryptoKey>,
     data: js::BytesOrString,
-) -> Result<js::Bytes> {
+    out: Option<js::JsUint8Array>,
+) -> Result<Output<Vec<u8>>> {
     let key = key.borrow();
     match algorithm {
         CryptAlgorithm::AesGcm(params) => {
@@ -432,11 +706,14 @@ fn decrypt(
                 128 => decrypt_with!(Aes128),
                 192 => decrypt_with!(Aes192),
                 256 => decrypt_with!(Aes256),
-                _ => bail!("key must be 16, 24, or 32 bytes long"),
+                _ => return Err(invalid_key_length()),
             };
-            Ok(plaintext.into())
+            Output::new(plaintext, out)
         }
         CryptAlgorithm::AesCbc(params) => {
+            if out.is_some() {
+                bail!("AES-CBC decryption does not take an output buffer, the length depends on the padding");
+            }
             use aes::cipher::{block_padding::Pkcs7, BlockCipher, BlockDecryptMut, KeyIvInit};
             use aes::{Aes128, Aes192, Aes256};
             use cbc::Decryptor;
@@ -444,7 +721,7 @@ fn decrypt(
             where
                 C: BlockDecryptMut + BlockCipher + KeyInit,
             {
-                let key = generic_array_from_slice(key).context("invalid key length")?;
+                let key = generic_array_from_slice(key).map_err(|_| invalid_key_length())?;
                 let iv = generic_array_from_slice(iv).context("invalid iv length")?;
                 let cipher = Decryptor::<C>::new(&key, &iv);
                 Ok(cipher
@@ -458,9 +735,9 @@ fn decrypt(
                 128 => decrypt_with::<Aes128>(&key.raw, &params.iv, data.as_bytes())?,
                 192 => decrypt_with::<Aes192>(&key.raw, &params.iv, data.as_bytes())?,
                 256 => decrypt_with::<Aes256>(&key.raw, &params.iv, data.as_bytes())?,
-                _ => bail!("key must be 16, 24, or 32 bytes long"),
+                _ => return Err(invalid_key_length()),
             };
-            Ok(plaintext.into())
+            Output::new(plaintext, out)
         }
         CryptAlgorithm::AesCtr(params) => {
             use aes::cipher::KeyIvInit;
@@ -468,7 +745,7 @@ fn decrypt(
             macro_rules! decrypt_with {
                 ($key_size:ident) => {{
                     let mut cipher = Ctr64LE::<aes::$key_size>::new(
-                        &generic_array_from_slice(&key.raw).context("invalid key length")?,
+                        &generic_array_from_slice(&key.raw).map_err(|_| invalid_key_length())?,
                         &generic_array_from_slice(&params.counter)
                             .context("invalid counter length")?,
                     );
@@ -486,14 +763,15 @@ fn decrypt(
                 128 => decrypt_with!(Aes128),
                 192 => decrypt_with!(Aes192),
                 256 => decrypt_with!(Aes256),
-                _ => bail!("key must be 16, 24, or 32 bytes long"),
+                _ => return Err(invalid_key_length()),
             };
-            Ok(plaintext.into())
+            Output::new(plaintext, out)
         }
         _ => bail!("unsupported decryption algorithm"),
     }
 }
 
+#[cfg(feature = "crypto-ec")]
 fn derive_aes_key(
     shared_secret: impl AsRef<[u8]>,
     derived_key_algorithm: DeriveKeyGenAlgorithm,
@@ -519,6 +797,7 @@ fn derive_aes_key(
     }
 }
 
+#[cfg(feature = "crypto-ec")]
 #[js::host_call(with_context)]
 fn derive_key(
     ctx: js::Context,
@@ -527,8 +806,9 @@ fn derive_key(
     base_key: Native<CryptoKey>,
     derived_key_algorithm: DeriveKeyGenAlgorithm,
     extractable: bool,
-    key_usages: Vec<js::JsString>,
+    key_usages: js::OneOrMany<js::JsString>,
 ) -> Result<Native<CryptoKey>> {
+    let key_usages = key_usages.into_vec();
     let base_key = base_key.borrow();
     let key = match algorithm {
         DeriveAlgorithm::Ecdh(params) => {
@@ -572,23 +852,26 @@ fn derive_key(
     Native::new(&ctx, key)
 }
 
+#[cfg(feature = "crypto-ec")]
 #[js::host_call(with_context)]
 fn generate_key(
     ctx: js::Context,
     _this: js::Value,
     algorithm: KeyGenAlgorithm,
     extractable: bool,
-    key_usages: Vec<js::JsString>,
+    key_usages: js::OneOrMany<js::JsString>,
 ) -> Result<CryptoKeyOrPair> {
     use p256::elliptic_curve::sec1::ToEncodedPoint;
     use p256::SecretKey as SecretKeyP256;
     use p384::SecretKey as SecretKeyP384;
     use p521::SecretKey as SecretKeyP521;
+    let key_usages = key_usages.into_vec();
 
     match &algorithm {
         KeyGenAlgorithm::Ec(params) => match params.named_curve.as_str() {
             "P-256" => {
-                let secret_key = SecretKeyP256::random(&mut rand::rngs::OsRng);
+                let secret_key =
+                    random_secret_key(&ctx, 32, 0xff, |b| SecretKeyP256::from_slice(b).ok())?;
                 let public_key = secret_key.public_key();
 
                 let private_key_bytes = secret_key.to_bytes().to_vec();
@@ -603,7 +886,8 @@ fn generate_key(
                 )
             }
             "P-384" => {
-                let secret_key = SecretKeyP384::random(&mut rand::rngs::OsRng);
+                let secret_key =
+                    random_secret_key(&ctx, 48, 0xff, |b| SecretKeyP384::from_slice(b).ok())?;
                 let public_key = secret_key.public_key();
 
                 let private_key_bytes = secret_key.to_bytes().to_vec();
@@ -618,7 +902,8 @@ fn generate_key(
                 )
             }
             "P-521" => {
-                let secret_key = SecretKeyP521::random(&mut rand::rngs::OsRng);
+                let secret_key =
+                    random_secret_key(&ctx, 66, 0x01, |b| SecretKeyP521::from_slice(b).ok())?;
                 let public_key = secret_key.public_key();
 
                 let private_key_bytes = secret_key.to_bytes().to_vec();
@@ -639,6 +924,25 @@ fn generate_key(
     }
 }
 
+/// A secret key in the JSON Web Key format, the `oct` key type.
+#[derive(js::FromJsValue)]
+struct OctetJwk {
+    kty: String,
+    k: String,
+}
+
+impl OctetJwk {
+    fn secret(&self) -> Result<Vec<u8>> {
+        use base64::{engine::general_purpose, Engine as _};
+        if self.kty != "oct" {
+            bail!("unsupported JSON web key type: {}", self.kty);
+        }
+        general_purpose::URL_SAFE_NO_PAD
+            .decode(&self.k)
+            .context("invalid base64url key")
+    }
+}
+
 #[js::host_call(with_context)]
 fn import_key(
     ctx: js::Context,
@@ -647,19 +951,28 @@ fn import_key(
     key_data: js::Value,
     algorithm: KeyGenAlgorithm,
     extractable: bool,
-    key_usages: Vec<js::JsString>,
+    key_usages: js::OneOrMany<js::JsString>,
 ) -> Result<Native<CryptoKey>> {
-    if fmt.as_str() != "raw" {
+    let key_usages = key_usages.into_vec();
+    if !matches!(fmt.as_str(), "raw" | "jwk") {
         bail!("unsupported import format: {fmt}");
     }
-    use js::FromJsValue;
-    let key_data = js::Bytes::from_js_value(key_data)?;
+    let raw = js::Overloaded::new(core::slice::from_ref(&key_data))
+        .arm(|(data,): (js::Bytes,)| match fmt.as_str() {
+            "raw" => Ok(data.as_bytes().to_vec()),
+            _ => bail!("{fmt} key data can not be bytes"),
+        })
+        .arm(|(jwk,): (OctetJwk,)| match fmt.as_str() {
+            "jwk" => jwk.secret(),
+            _ => bail!("{fmt} key data can not be a JSON web key"),
+        })
+        .finish()?;
     let key = CryptoKey {
         r#type: "secret".into(),
         extractable,
         algorithm,
         usages: key_usages,
-        raw: key_data.as_bytes().to_vec(),
+        raw,
     };
     Native::new(&ctx, key)
 }
@@ -676,50 +989,757 @@ fn export_key(fmt: js::JsString, key: Native<CryptoKey>) -> Result<js::Bytes> {
     }
 }
 
-#[js::host_call]
-fn get_random_values(output: js::JsUint8Array) -> Result<js::JsUint8Array> {
+pub type EntropyFn = Box<dyn Fn(&mut [u8]) -> Result<()>>;
+
+/// The entropy source of a context, kept with its user data out of reach of scripts.
+struct EntropySource(EntropyFn);
+
+/// Set the source of random bytes used by `crypto.getRandomValues`, `crypto.randomUUID` and
+/// `crypto.subtle.generateKey` in `ctx`.
+///
+/// The calls fail while no source is registered. Fails for contexts without teardown support,
+/// which can not keep the source.
+pub fn set_entropy_source(
+    ctx: &js::Context,
+    source: impl Fn(&mut [u8]) -> Result<()> + 'static,
+) -> Result<()> {
+    ctx.set_user_data(EntropySource(Box::new(source)));
+    if ctx.user_data::<EntropySource>().is_none() {
+        bail!("can not keep an entropy source for a context without teardown support");
+    }
+    Ok(())
+}
+
+fn fill_random(ctx: &js::Context, buf: &mut [u8]) -> Result<()> {
+    let source = ctx
+        .user_data::<EntropySource>()
+        .context("no entropy source registered")?;
+    (source.0)(buf)
+}
+
+fn entropy_denied() -> js::Error {
+    js::Error::msg(
+        js::JsError::new(
+            "NotAllowedError",
+            "entropy is denied by the crypto setup policy",
+        )
+        .with_code("ERR_ENTROPY_DENIED"),
+    )
+}
+
+#[cfg(feature = "crypto-ec")]
+/// A secret key read from `len` bytes of the entropy source, failing if the source does.
+///
+/// Bytes out of the range of the curve order are drawn again. `top_mask` clears the bits above
+/// the order of curves whose size is not a whole number of bytes.
+fn random_secret_key<K>(
+    ctx: &js::Context,
+    len: usize,
+    top_mask: u8,
+    from_slice: impl Fn(&[u8]) -> Option<K>,
+) -> Result<K> {
+    let mut buf = alloc::vec![0u8; len];
+    // Valid bytes are drawn at the first attempt but with a negligible chance, unless the source
+    // is broken.
+    for _ in 0..64 {
+        fill_random(ctx, &mut buf)?;
+        buf[0] &= top_mask;
+        let key = from_slice(&buf);
+        buf.fill(0);
+        if let Some(key) = key {
+            return Ok(key);
+        }
+    }
+    bail!("the entropy source produced no valid secret key")
+}
+
+#[js::host_call(with_context)]
+fn get_random_values(
+    ctx: js::Context,
+    _this: js::Value,
+    output: js::JsUint8Array,
+) -> Result<js::JsUint8Array> {
     let mut buf = alloc::vec![0u8; output.len()];
-    rand::thread_rng().fill_bytes(&mut buf);
+    fill_random(&ctx, &mut buf)?;
     output.fill_with_bytes(&buf);
     Ok(output)
 }
 
-#[js::host_call]
-fn random_uuid() -> String {
-    uuid::Uuid::new_v4().to_string()
+#[cfg(feature = "uuid")]
+#[js::host_call(with_context)]
+fn random_uuid(ctx: js::Context, _this: js::Value) -> Result<String> {
+    let mut bytes = [0u8; 16];
+    fill_random(&ctx, &mut bytes)?;
+    Ok(format_uuid_v4(bytes))
+}
+
+#[cfg(feature = "uuid")]
+/// Format random bytes as a version 4, RFC 4122 variant UUID in lowercase hyphenated form.
+fn format_uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let mut out = String::with_capacity(36);
+    for (i, byte) in bytes.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            out.push('-');
+        }
+        out.push_str(&alloc::format!("{byte:02x}"));
+    }
+    out
 }
 
 #[js::host_call]
-fn digest(algorithm: BaseAlgorithm, data: js::Bytes) -> Result<js::Bytes> {
+fn digest(
+    algorithm: BaseAlgorithm,
+    data: js::Bytes,
+    out: Option<js::JsUint8Array>,
+) -> Result<Output<Vec<u8>>> {
     use sha2::{Digest, Sha256, Sha384, Sha512};
     let data = data.as_bytes();
     let hash = match algorithm.name.as_str() {
         "SHA-256" => Sha256::digest(data).to_vec(),
         "SHA-384" => Sha384::digest(data).to_vec(),
         "SHA-512" => Sha512::digest(data).to_vec(),
-        _ => bail!("unsupported algorithm: {}", algorithm.name),
+        name => return Err(not_supported(name, &["SHA-256", "SHA-384", "SHA-512"])),
     };
-    Ok(hash.into())
+    Output::new(hash, out)
 }
 
-fn setup_subtle(ns: &js::Value) -> Result<()> {
-    ns.define_property_fn("encrypt", encrypt)?;
-    ns.define_property_fn("decrypt", decrypt)?;
-    ns.define_property_fn("deriveKey", derive_key)?;
-    ns.define_property_fn("generateKey", generate_key)?;
+fn setup_subtle(ns: &js::Value, with_entropy: bool) -> Result<()> {
+    #[cfg(feature = "crypto-aes")]
+    {
+        ns.define_property_fn("encrypt", encrypt)?;
+        ns.define_property_fn("decrypt", decrypt)?;
+    }
+    #[cfg(feature = "crypto-ec")]
+    {
+        ns.define_property_fn("deriveKey", derive_key)?;
+        if with_entropy {
+            ns.define_property_fn("generateKey", generate_key)?;
+        }
+    }
     ns.define_property_fn("importKey", import_key)?;
     ns.define_property_fn("exportKey", export_key)?;
     ns.define_property_fn("digest", digest)?;
     Ok(())
 }
 
+/// Where `crypto.getRandomValues`, `crypto.randomUUID` and `crypto.subtle.generateKey` get their
+/// random bytes from.
+pub enum EntropyPolicy {
+    /// No entropy at all, for consensus code. The functions needing it are not installed, so
+    /// scripts can test for them, and any other use fails with a capability error.
+    Deny,
+    /// A deterministic stream drawn from [`js::SeededRng`], the generator of `Math.random` for
+    /// `Context::seed_random`, the same on every run and every platform.
+    Seeded([u8; 32]),
+    /// The bytes the host provides, as with [`set_entropy_source`].
+    Host(EntropyFn),
+}
+
+/// The options of [`setup_with_options`].
+pub struct CryptoSetupOptions {
+    pub entropy: EntropyPolicy,
+}
+
+/// Install `crypto`. With the `std` feature the thread RNG becomes the entropy source unless one
+/// is already registered. Without it, register one with [`set_entropy_source`] before, or
+/// `crypto` is installed as with [`EntropyPolicy::Deny`], without the functions needing entropy.
 pub fn setup(g: &js::Value) -> Result<()> {
-    let crypto = g.context()?.new_object("Crypto");
-    let subtle = g.context()?.new_object("SubtleCrypto");
-    setup_subtle(&subtle)?;
+    let ctx = g.context()?;
+    #[cfg(feature = "std")]
+    if ctx.user_data::<EntropySource>().is_none() {
+        set_entropy_source(ctx, |buf| {
+            use rand::RngCore;
+            rand::thread_rng().fill_bytes(buf);
+            Ok(())
+        })?;
+    }
+    if ctx.user_data::<EntropySource>().is_none() {
+        let options = CryptoSetupOptions {
+            entropy: EntropyPolicy::Deny,
+        };
+        return setup_with_options(g, options);
+    }
+    install(g, "host", true)
+}
+
+/// Install `crypto` with the functions `options` permits.
+///
+/// `crypto.capabilities` tells scripts which policy is in effect, and what the cargo features
+/// compiled in:
+///
+/// ```js
+/// crypto.capabilities // { entropy: "deny", getRandomValues: false, randomUUID: false, generateKey: false,
+///                     //   encrypt: true, deriveKey: true, algorithms: ["ECDSA", "ECDH", ...] }
+/// ```
+pub fn setup_with_options(g: &js::Value, options: CryptoSetupOptions) -> Result<()> {
+    let ctx = g.context()?;
+    match options.entropy {
+        EntropyPolicy::Deny => {
+            set_entropy_source(ctx, |_| Err(entropy_denied()))?;
+            install(g, "deny", false)
+        }
+        EntropyPolicy::Seeded(seed) => {
+            // The generator of `Context::seed_random`, fed with the whole seed.
+            let rng = RefCell::new(js::SeededRng::from_seed(seed));
+            set_entropy_source(ctx, move |buf| {
+                rng.borrow_mut().fill_bytes(buf);
+                Ok(())
+            })?;
+            install(g, "seeded", true)
+        }
+        EntropyPolicy::Host(source) => {
+            set_entropy_source(ctx, source)?;
+            install(g, "host", true)
+        }
+    }
+}
+
+fn install(g: &js::Value, entropy: &str, with_entropy: bool) -> Result<()> {
+    let ctx = g.context()?;
+    let crypto = ctx.new_object("Crypto");
+    let subtle = ctx.new_object("SubtleCrypto");
+    setup_subtle(&subtle, with_entropy)?;
     crypto.set_property("subtle", &subtle)?;
-    crypto.define_property_fn("getRandomValues", get_random_values)?;
-    crypto.define_property_fn("randomUUID", random_uuid)?;
+    if with_entropy {
+        crypto.define_property_fn("getRandomValues", get_random_values)?;
+        #[cfg(feature = "uuid")]
+        crypto.define_property_fn("randomUUID", random_uuid)?;
+    }
+    let capabilities = Capabilities {
+        entropy: entropy.into(),
+        get_random_values: with_entropy,
+        random_uuid: with_entropy && cfg!(feature = "uuid"),
+        generate_key: with_entropy && cfg!(feature = "crypto-ec"),
+        encrypt: cfg!(feature = "crypto-aes"),
+        derive_key: cfg!(feature = "crypto-ec"),
+        algorithms: compiled_algorithms(),
+    };
+    crypto.set_property("capabilities", &capabilities.to_js_value(ctx)?)?;
     g.set_property("crypto", &crypto)?;
     Ok(())
 }
+
+#[derive(ToJsValue)]
+#[qjs(rename_all = "camelCase")]
+struct Capabilities {
+    entropy: String,
+    get_random_values: bool,
+    #[qjs(rename = "randomUUID")]
+    random_uuid: bool,
+    generate_key: bool,
+    /// Whether `crypto.subtle.encrypt` and `decrypt` are there.
+    encrypt: bool,
+    derive_key: bool,
+    algorithms: Vec<String>,
+}
+
+/// The algorithms accepted by the functions compiled in, in the order of `ALGORITHMS`.
+fn compiled_algorithms() -> Vec<String> {
+    ALGORITHMS
+        .iter()
+        .filter(|(_, compiled)| *compiled)
+        .map(|(name, _)| (*name).into())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_algorithm_names() {
+        assert_eq!(normalize_algorithm_name("aes-gcm"), "AES-GCM");
+        assert_eq!(normalize_algorithm_name("Sha-256"), "SHA-256");
+        assert_eq!(
+            normalize_algorithm_name("rsassa-pkcs1-V1_5"),
+            "RSASSA-PKCS1-v1_5"
+        );
+        assert_eq!(normalize_algorithm_name("Foo"), "Foo");
+
+        let err = not_supported("Foo", &["ECDH", "HKDF"]);
+        let err = err.downcast_ref::<js::JsError>().unwrap();
+        assert_eq!(err.name, "NotSupportedError");
+        assert_eq!(
+            err.message,
+            "unsupported algorithm: Foo, expected one of: ECDH, HKDF"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "crypto-aes", feature = "crypto-ec"))]
+    fn normalizes_algorithms_from_js() {
+        use js::FromJsValue;
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let results = ctx
+            .eval(&js::Code::Source(
+                r#"
+                // Whether the arguments were rejected as unsupported, or accepted and the call
+                // failed later on.
+                const outcome = (f) => {
+                    try { return String(f()); }
+                    catch (e) { return e.name === "NotSupportedError" ? e.name : "accepted"; }
+                };
+                const key = crypto.subtle.importKey(
+                    "raw", new Uint8Array(16), { name: "aes-gcm", length: 128 }, false, ["encrypt"]);
+                const hmac = (hash) => crypto.subtle.importKey(
+                    "raw", new Uint8Array(16), { name: "Hmac", hash }, false, ["sign"]);
+                const encrypt = (name) => crypto.subtle.encrypt(
+                    { name, iv: new Uint8Array(12) }, key, new Uint8Array(3)).length;
+                const hkdf = (hash) => crypto.subtle.deriveKey(
+                    { name: "hkdf", hash, salt: new Uint8Array(), info: new Uint8Array() },
+                    key, { name: "Aes-Gcm", length: 128 }, false, ["encrypt"]);
+                [
+                    key.algorithm.name,
+                    hmac("sha-256").algorithm.hash.name,
+                    hmac({ name: "Sha-384" }).algorithm.hash.name,
+                    outcome(() => hmac("MD5")),
+                    outcome(() => hmac({ name: "md5" })),
+                    outcome(() => encrypt("aes-Gcm")),
+                    outcome(() => encrypt("AES-XTS")),
+                    outcome(() => hkdf("sha-256")),
+                    outcome(() => hkdf({ name: "SHA-512" })),
+                    outcome(() => hkdf("MD5")),
+                ]
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            Vec::<String>::from_js_value(results).unwrap(),
+            [
+                "AES-GCM",
+                "SHA-256",
+                "SHA-384",
+                "NotSupportedError",
+                "NotSupportedError",
+                "19",
+                "NotSupportedError",
+                "accepted",
+                "accepted",
+                "NotSupportedError",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crypto-aes")]
+    fn errors_carry_codes() {
+        use js::FromJsValue;
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let caught = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const describe = (f) => {
+                    try { f(); } catch (e) { return `${e.name}:${e.code}`; }
+                };
+                const key = crypto.subtle.importKey(
+                    "raw", new Uint8Array(10), { name: "AES-CBC", length: 80 }, false, []);
+                [
+                    describe(() => crypto.subtle.digest("MD5", new Uint8Array())),
+                    describe(() => crypto.subtle.encrypt(
+                        { name: "AES-CBC", iv: new Uint8Array(16) }, key, new Uint8Array())),
+                ]
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            Vec::<String>::from_js_value(caught).unwrap(),
+            [
+                "NotSupportedError:ERR_UNSUPPORTED_ALGORITHM",
+                "DataError:ERR_INVALID_KEY_LENGTH"
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_raw_and_jwk_keys() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let same = eval(
+            r#"
+            const alg = { name: "HMAC", hash: "SHA-256" };
+            const raw = crypto.subtle.importKey(
+                "raw", new Uint8Array([1, 2, 3]), alg, true, ["sign"]);
+            const jwk = crypto.subtle.importKey(
+                "jwk", { kty: "oct", k: "AQID" }, alg, true, ["sign"]);
+            new Uint8Array(crypto.subtle.exportKey("raw", jwk)).join()
+                === new Uint8Array(crypto.subtle.exportKey("raw", raw)).join()
+            "#,
+        )
+        .unwrap();
+        assert!(same.decode_bool().unwrap());
+
+        let err = eval("crypto.subtle.importKey('raw', { kty: 'oct', k: 'AQID' }, alg, true, [])")
+            .unwrap_err();
+        assert!(
+            err.contains("raw key data can not be a JSON web key"),
+            "{err}"
+        );
+        let err = eval("crypto.subtle.importKey('jwk', 7, alg, true, [])").unwrap_err();
+        assert!(err.contains("no matching overload"), "{err}");
+        let err =
+            eval("crypto.subtle.importKey('spki', new Uint8Array(3), alg, true, [])").unwrap_err();
+        assert!(err.contains("unsupported import format: spki"), "{err}");
+    }
+
+    #[test]
+    fn accepts_a_single_usage() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let usages = ctx
+            .eval(&js::Code::Source(
+                r#"
+                crypto.subtle.importKey(
+                    "raw", new Uint8Array(16), { name: "AES-CBC", length: 128 }, false, "encrypt"
+                ).usages.join(",")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(usages.decode_string().unwrap(), "encrypt");
+    }
+
+    #[test]
+    #[cfg(feature = "crypto-aes")]
+    fn encrypts_into_output_buffer() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let same = eval(
+            r#"
+            const key = crypto.subtle.importKey(
+                "raw", new Uint8Array(16), { name: "AES-GCM", length: 128 }, false, ["encrypt"]);
+            const gcm = { name: "AES-GCM", iv: new Uint8Array(12) };
+            const data = new Uint8Array([1, 2, 3]);
+            const out = new Uint8Array(data.length + 16);
+            const ret = crypto.subtle.encrypt(gcm, key, data, out);
+            const plain = new Uint8Array(3);
+            ret === out
+                && crypto.subtle.encrypt(gcm, key, data).join() === out.join()
+                && crypto.subtle.decrypt(gcm, key, out, plain) === plain
+                && plain.join() === "1,2,3"
+            "#,
+        )
+        .unwrap();
+        assert!(same.decode_bool().unwrap());
+
+        let err = eval("crypto.subtle.encrypt(gcm, key, data, new Uint8Array(3))").unwrap_err();
+        assert!(
+            err.contains("output buffer must be 19 bytes long, got 3"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn digests_into_output_buffer() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let same = eval(
+            r#"
+            const data = new Uint8Array([1, 2, 3]);
+            const out = new Uint8Array(48);
+            const ret = crypto.subtle.digest("SHA-384", data, out);
+            ret === out && crypto.subtle.digest("SHA-384", data).join() === out.join()
+            "#,
+        )
+        .unwrap();
+        assert!(same.decode_bool().unwrap());
+
+        let err = eval(r#"crypto.subtle.digest("SHA-256", data, new Uint8Array(48))"#).unwrap_err();
+        assert!(
+            err.contains("output buffer must be 32 bytes long, got 48"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn rejects_exotic_algorithms() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let messages = ctx
+            .eval(&js::Code::Source(
+                r#"
+                [["SHA-256"], new Uint8Array(2), new Date(0)].map((algorithm) => {
+                    try { crypto.subtle.digest(algorithm, new Uint8Array()); }
+                    catch (e) { return `${e.name}: ${e.message}`; }
+                }).join("\n")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            messages.decode_string().unwrap(),
+            "TypeError: algorithm must be a name or a plain object, got an array\n\
+             TypeError: algorithm must be a name or a plain object, got Uint8Array\n\
+             TypeError: algorithm must be a name or a plain object, got a Date"
+        );
+    }
+
+    #[test]
+    fn capabilities_match_the_features() {
+        use js::FromJsValue;
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let probe = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const caps = crypto.capabilities;
+                [
+                    caps.randomUUID === "randomUUID" in crypto,
+                    caps.generateKey === "generateKey" in crypto.subtle,
+                    caps.encrypt === "encrypt" in crypto.subtle,
+                    caps.encrypt === "decrypt" in crypto.subtle,
+                    caps.deriveKey === "deriveKey" in crypto.subtle,
+                    caps.randomUUID,
+                    caps.generateKey,
+                    caps.encrypt,
+                    caps.algorithms.join(),
+                ].map(String)
+                "#,
+            ))
+            .unwrap();
+        let probe = Vec::<String>::from_js_value(probe).unwrap();
+        assert_eq!(probe[..5], ["true"; 5]);
+
+        let (uuid, generate_key, encrypt, algorithms) =
+            (&probe[5], &probe[6], &probe[7], &probe[8]);
+        #[cfg(feature = "uuid")]
+        assert_eq!(uuid, "true");
+        #[cfg(not(feature = "uuid"))]
+        assert_eq!(uuid, "false");
+        #[cfg(feature = "crypto-ec")]
+        {
+            assert_eq!(generate_key, "true");
+            assert!(algorithms.starts_with("ECDSA,ECDH,"), "{algorithms}");
+        }
+        #[cfg(not(feature = "crypto-ec"))]
+        {
+            assert_eq!(generate_key, "false");
+            assert!(!algorithms.contains("ECD"), "{algorithms}");
+        }
+        #[cfg(feature = "crypto-aes")]
+        {
+            assert_eq!(encrypt, "true");
+            assert!(
+                algorithms.contains("AES-CTR,AES-CBC,AES-GCM,"),
+                "{algorithms}"
+            );
+        }
+        #[cfg(not(feature = "crypto-aes"))]
+        {
+            assert_eq!(encrypt, "false");
+            assert!(!algorithms.contains("AES"), "{algorithms}");
+        }
+        assert!(
+            algorithms.ends_with("SHA-256,SHA-384,SHA-512"),
+            "{algorithms}"
+        );
+        assert!(!algorithms.contains("HMAC"), "{algorithms}");
+        assert!(!algorithms.contains("SHA-1"), "{algorithms}");
+        #[cfg(feature = "crypto")]
+        assert_eq!(
+            algorithms,
+            "ECDSA,ECDH,AES-CTR,AES-CBC,AES-GCM,SHA-256,SHA-384,SHA-512"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "uuid")]
+    fn formats_uuid_v4() {
+        let uuid = format_uuid_v4([0xff; 16]);
+        assert_eq!(uuid, "ffffffff-ffff-4fff-bfff-ffffffffffff");
+        let uuid = format_uuid_v4([0; 16]);
+        assert_eq!(uuid, "00000000-0000-4000-8000-000000000000");
+    }
+
+    #[test]
+    #[cfg(feature = "uuid")]
+    fn random_uuid_uses_entropy_source() {
+        use js::FromJsValue;
+        use rand::{RngCore, SeedableRng};
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let uuids = || {
+            let rng = RefCell::new(rand::rngs::StdRng::seed_from_u64(7));
+            set_entropy_source(&ctx, move |buf| {
+                rng.borrow_mut().fill_bytes(buf);
+                Ok(())
+            })
+            .unwrap();
+            ctx.eval(&js::Code::Source(
+                "[crypto.randomUUID(), crypto.randomUUID()]",
+            ))
+            .unwrap()
+        };
+        let first = Vec::<String>::from_js_value(uuids()).unwrap();
+        let second = Vec::<String>::from_js_value(uuids()).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first[0], first[1]);
+        assert_eq!(&first[0][14..15], "4");
+    }
+
+    #[test]
+    #[cfg(feature = "crypto-ec")]
+    fn fingerprints_identify_the_material() {
+        use js::FromJsValue;
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup(&ctx.get_global_object()).unwrap();
+        let checks = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const raw = new Uint8Array(32).fill(7);
+                const aes = (usages) => crypto.subtle.importKey(
+                    "raw", raw, { name: "AES-GCM", length: 256 }, false, usages);
+                const ec = (curve) => crypto.subtle.importKey(
+                    "raw", raw, { name: "ECDH", namedCurve: curve }, false, ["deriveKey"]);
+                const a = aes(["encrypt"]);
+                const b = aes(["encrypt"]);
+                const c = aes(["decrypt"]);
+                const other = crypto.subtle.importKey(
+                    "raw", new Uint8Array(32), { name: "AES-GCM", length: 256 }, false, ["encrypt"]);
+                const pair = crypto.subtle.generateKey(
+                    { name: "ECDSA", namedCurve: "P-256" }, false, ["sign"]);
+                const keys = new Map([[a.fingerprint(), a]]);
+                [
+                    a.fingerprint() === b.fingerprint(),
+                    a.fingerprint() === c.fingerprint(),
+                    keys.has(c.fingerprint()),
+                    a.fingerprint() === other.fingerprint(),
+                    ec("P-256").fingerprint() === ec("P-384").fingerprint(),
+                    a.equals(c),
+                    a.equals(other),
+                    a.fingerprint().length,
+                    pair.privateKey.fingerprint() === pair.publicKey.fingerprint(),
+                ].join(" ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            checks.decode_string().unwrap(),
+            "true true true false false true false 64 false"
+        );
+
+        let key = ctx
+            .eval(&js::Code::Source(
+                r#"crypto.subtle.generateKey(
+                    { name: "ECDH", namedCurve: "P-384" }, true, ["deriveKey"]).publicKey"#,
+            ))
+            .unwrap();
+        let key = Native::<CryptoKey>::from_js_value(key).unwrap();
+        let key = key.borrow();
+        let spki = key.canonical_material().unwrap();
+        // The DER SEQUENCE of the SubjectPublicKeyInfo.
+        assert_eq!(spki[0], 0x30);
+        assert_ne!(spki, key.raw);
+        assert!(key.equals(&key));
+    }
+
+    #[test]
+    fn fails_closed_without_entropy_source() {
+        use js::FromJsValue;
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        install(&ctx.get_global_object(), "host", true).unwrap();
+        let value = ctx
+            .eval(&js::Code::Source(
+                r#"
+                delete globalThis._QjsBind;
+                try { crypto.getRandomValues(new Uint8Array(4)); "filled" } catch (e) { e.message }
+                "#,
+            ))
+            .unwrap();
+        let message = String::from_js_value(value).unwrap();
+        assert!(
+            message.contains("no entropy source registered"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "uuid", feature = "crypto-ec"))]
+    fn entropy_policies() {
+        use js::FromJsValue;
+
+        let run = |entropy, src: &str| {
+            let runtime = js::Runtime::new(&Default::default());
+            let ctx = runtime.new_context();
+            setup_with_options(&ctx.get_global_object(), CryptoSetupOptions { entropy }).unwrap();
+            let value = ctx.eval(&js::Code::Source(src)).unwrap();
+            String::from_js_value(value).unwrap()
+        };
+        let probe = r#"
+            const caps = crypto.capabilities;
+            [
+                caps.entropy,
+                caps.getRandomValues,
+                caps.randomUUID,
+                caps.generateKey,
+                "getRandomValues" in crypto,
+                "randomUUID" in crypto,
+                "generateKey" in crypto.subtle,
+            ].join(" ")
+        "#;
+        assert_eq!(
+            run(EntropyPolicy::Deny, probe),
+            "deny false false false false false false"
+        );
+        assert_eq!(
+            run(EntropyPolicy::Seeded([7; 32]), probe),
+            "seeded true true true true true true"
+        );
+
+        let uuids = "[crypto.randomUUID(), crypto.randomUUID()].join(' ')";
+        let seeded = run(EntropyPolicy::Seeded([7; 32]), uuids);
+        assert_eq!(seeded, run(EntropyPolicy::Seeded([7; 32]), uuids));
+        assert_ne!(seeded, run(EntropyPolicy::Seeded([8; 32]), uuids));
+        let keys = r#"
+            const gen = () => crypto.subtle.generateKey(
+                { name: "ECDSA", namedCurve: "P-256" }, true, ["sign"]);
+            Array.from(crypto.subtle.exportKey("raw", gen().publicKey)).join(",")
+        "#;
+        assert_eq!(
+            run(EntropyPolicy::Seeded([1; 32]), keys),
+            run(EntropyPolicy::Seeded([1; 32]), keys)
+        );
+
+        let host = EntropyPolicy::Host(Box::new(|buf: &mut [u8]| {
+            buf.fill(0xab);
+            Ok(())
+        }));
+        assert_eq!(
+            run(host, "crypto.getRandomValues(new Uint8Array(3)).join(',')"),
+            "171,171,171"
+        );
+        let failing = EntropyPolicy::Host(Box::new(|_: &mut [u8]| bail!("rng offline")));
+        let caught = r#"
+            const describe = (f) => { try { f(); } catch (e) { return e.message; } };
+            describe(() => crypto.subtle.generateKey(
+                { name: "ECDH", namedCurve: "P-384" }, true, ["deriveKey"]))
+        "#;
+        assert!(run(failing, caught).contains("rng offline"));
+        let zeros = EntropyPolicy::Host(Box::new(|buf: &mut [u8]| {
+            buf.fill(0);
+            Ok(())
+        }));
+        assert!(run(zeros, caught).contains("no valid secret key"));
+    }
+}
@@ -0,0 +1,171 @@
+//! `globalThis.Env`, read-only access to configuration provided by the host.
+
+use alloc::collections::BTreeSet;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use js::{ErrorContext, Result};
+
+/// The source of the variables read by scripts through `Env`.
+pub trait EnvProvider {
+    /// The value of `key`, created in `ctx`, or `None` if it is not set.
+    ///
+    /// Called on each `Env.get`, so values can be produced on demand.
+    fn get(&self, ctx: &js::Context, key: &str) -> Option<js::Value>;
+
+    /// The keys that are set.
+    fn keys(&self) -> Vec<String>;
+
+    /// Whether `key` is set, without producing its value.
+    fn has(&self, key: &str) -> bool {
+        self.keys().iter().any(|k| k == key)
+    }
+}
+
+impl EnvProvider for alloc::collections::BTreeMap<String, String> {
+    fn get(&self, ctx: &js::Context, key: &str) -> Option<js::Value> {
+        self.get(key).map(|value| js::Value::from_str(ctx, value))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.keys().cloned().collect()
+    }
+
+    fn has(&self, key: &str) -> bool {
+        self.contains_key(key)
+    }
+}
+
+/// The variables of a provider as seen by scripts, possibly restricted to an allow-list.
+///
+/// Cloning is cheap, so that one provider can back the `Env` of many contexts, each with its own
+/// allow-list:
+///
+/// ```ignore
+/// let env = Env::new(config);
+/// env.clone().allow(["TENANT_ID"]).setup(&untrusted_ctx)?;
+/// env.setup(&trusted_ctx)?;
+/// ```
+#[derive(Clone)]
+pub struct Env {
+    provider: Rc<dyn EnvProvider>,
+    allowed: Option<Rc<BTreeSet<String>>>,
+}
+
+impl Env {
+    pub fn new(provider: impl EnvProvider + 'static) -> Self {
+        Self {
+            provider: Rc::new(provider),
+            allowed: None,
+        }
+    }
+
+    /// Hide every key but `keys`: scripts see them as not set.
+    pub fn allow(mut self, keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.allowed = Some(Rc::new(keys.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    fn allows(&self, key: &str) -> bool {
+        match &self.allowed {
+            Some(allowed) => allowed.contains(key),
+            None => true,
+        }
+    }
+
+    /// Define the frozen `globalThis.Env` in `ctx`, with `get(key)`, returning `undefined` for
+    /// keys not set, `has(key)` and `keys()`.
+    pub fn setup(&self, ctx: &js::Context) -> Result<()> {
+        let env = js::Value::new_opaque_object(ctx, Some("Env"), self.clone());
+        env.define_property_fn("get", get)?;
+        env.define_property_fn("has", has)?;
+        env.define_property_fn("keys", keys)?;
+        let global = ctx.get_global_object();
+        global
+            .get_property("Object")?
+            .call_method("freeze", &[env.clone()])?;
+        global.set_property("Env", &env)
+    }
+}
+
+/// Define `globalThis.Env` in `ctx`, showing all the variables of `provider`.
+pub fn setup(ctx: &js::Context, provider: impl EnvProvider + 'static) -> Result<()> {
+    Env::new(provider).setup(ctx)
+}
+
+fn with_env<T>(this: &js::Value, f: impl FnOnce(&Env) -> T) -> Result<T> {
+    let data = this.opaque_object_data::<Env>();
+    let env = data.get().context("Env methods must be called on Env")?;
+    Ok(f(env))
+}
+
+#[js::host_call(with_context)]
+fn get(ctx: js::Context, this: js::Value, key: js::JsString) -> Result<js::Value> {
+    let key = key.as_str();
+    let value = with_env(&this, |env| {
+        env.allows(key)
+            .then(|| env.provider.get(&ctx, key))
+            .flatten()
+    })?;
+    Ok(value.unwrap_or_default())
+}
+
+#[js::host_call(with_context)]
+fn has(_ctx: js::Context, this: js::Value, key: js::JsString) -> Result<bool> {
+    let key = key.as_str();
+    with_env(&this, |env| env.allows(key) && env.provider.has(key))
+}
+
+#[js::host_call(with_context)]
+fn keys(_ctx: js::Context, this: js::Value) -> Result<Vec<String>> {
+    with_env(&this, |env| {
+        let mut keys = env.provider.keys();
+        keys.retain(|key| env.allows(key));
+        keys
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn scripts_see_the_allowed_variables() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let vars: BTreeMap<String, String> =
+            [("TENANT", "acme"), ("REGION", "eu"), ("SECRET", "x")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+        Env::new(vars)
+            .allow(["TENANT", "REGION", "MISSING"])
+            .setup(&ctx)
+            .unwrap();
+        let seen = ctx
+            .eval(&js::Code::Source(
+                r#"
+                Env.secret = 1;
+                [
+                    Env.get("TENANT"),
+                    Env.has("REGION"),
+                    Env.get("SECRET"),
+                    Env.has("SECRET"),
+                    Env.has("MISSING"),
+                    Env.keys().join("+"),
+                    Object.isFrozen(Env),
+                    Env.secret,
+                ].join()
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            seen.to_string(),
+            "acme,true,,false,false,REGION+TENANT,true,"
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use alloc::{boxed::Box, collections::BTreeSet, format, string::String, vec::Vec};
+
+use js::ErrorContext;
+
+/// A module that can be installed into a JS context.
+pub trait Extension {
+    /// The name recorded in `_QjsBind.extensions` once installed.
+    fn name(&self) -> &'static str;
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()>;
+}
+
+/// Define `Extensions::$method` adding `$extension`, or nothing if the cargo feature `$feature`
+/// is disabled, so that hosts build with any set of features. `install` returns what was added.
+macro_rules! optional_extension {
+    ($method: ident, $feature: literal, $extension: expr) => {
+        #[doc = concat!("Add [`", stringify!($extension), "`], skipped without the `")]
+        #[doc = concat!($feature, "` feature.")]
+        #[cfg(feature = $feature)]
+        pub fn $method(self) -> Self {
+            self.with($extension)
+        }
+
+        #[doc = concat!("Skipped, built without the `", $feature, "` feature.")]
+        #[cfg(not(feature = $feature))]
+        pub fn $method(self) -> Self {
+            self
+        }
+    };
+}
+
+/// Installs a set of extensions into a context.
+///
+/// ```ignore
+/// Extensions::new()
+///     .with_crypto()
+///     .with_scale()
+///     .with_hash()
+///     .install(&ctx)?;
+/// ```
+///
+/// Installing is idempotent: extensions already recorded in the context are skipped.
+#[derive(Default)]
+pub struct Extensions {
+    extensions: Vec<Box<dyn Extension>>,
+    /// Whether the globals of each extension are locked down once it is installed.
+    locked: Vec<bool>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, extension: impl Extension + 'static) -> Self {
+        self.extensions.push(Box::new(extension));
+        self.locked.push(false);
+        self
+    }
+
+    /// Lock down the globals defined by the extension added last once it is installed, see
+    /// [`js::lockdown`], so that scripts can not replace its functions:
+    ///
+    /// ```ignore
+    /// Extensions::new().with_crypto().locked().with_hash().install(&ctx)?;
+    /// ```
+    pub fn locked(mut self) -> Self {
+        if let Some(locked) = self.locked.last_mut() {
+            *locked = true;
+        }
+        self
+    }
+
+    optional_extension!(with_crypto, "crypto-core", Crypto);
+    optional_extension!(with_scale, "scale2", Scale);
+
+    pub fn with_hash(self) -> Self {
+        self.with(Hash)
+    }
+
+    pub fn with_encoding(self) -> Self {
+        self.with(Encoding)
+    }
+
+    pub fn with_repr(self) -> Self {
+        self.with(Repr)
+    }
+
+    /// Add `globalThis.Env`, see [`crate::env::Env`].
+    pub fn with_env(self, env: crate::env::Env) -> Self {
+        self.with(env)
+    }
+
+    optional_extension!(with_stable_hash, "stable-hash", StableHash);
+    optional_extension!(with_multiformats, "multiformats", Multiformats);
+    optional_extension!(with_compression, "compression", Compression);
+    optional_extension!(with_cbor, "cbor", Cbor);
+
+    /// Add the bundled extension reported as `name` by [`Extension::name`], for hosts that pick
+    /// extensions from configuration. Fails for unknown names, extensions left out by cargo
+    /// features are skipped.
+    pub fn with_named(self, name: &str) -> js::Result<Self> {
+        Ok(match name {
+            "crypto" => self.with_crypto(),
+            "scale" => self.with_scale(),
+            "hash" => self.with_hash(),
+            "encoding" => self.with_encoding(),
+            "repr" => self.with_repr(),
+            "stable-hash" => self.with_stable_hash(),
+            "multiformats" => self.with_multiformats(),
+            "compression" => self.with_compression(),
+            "cbor" => self.with_cbor(),
+            _ => anyhow::bail!("unknown extension {name}"),
+        })
+    }
+
+    /// Install all extensions, returning the names of the ones installed by this call.
+    ///
+    /// If one fails, the error names it along with the extensions installed before it.
+    pub fn install(&self, ctx: &js::Context) -> js::Result<Vec<&'static str>> {
+        let global = ctx.get_global_object();
+        let registry = installed_registry(ctx)?;
+        let mut installed = Vec::new();
+        for (extension, &locked) in self.extensions.iter().zip(&self.locked) {
+            let name = extension.name();
+            if !registry.get_property(name)?.is_undefined() {
+                continue;
+            }
+            let before = if locked {
+                global_names(&global)?
+            } else {
+                BTreeSet::new()
+            };
+            extension.install(ctx, &global).with_context(|| {
+                format!("failed to install extension {name}, installed before: {installed:?}")
+            })?;
+            if locked {
+                let added = global_names(&global)?;
+                let added: Vec<&str> = added.difference(&before).map(String::as_str).collect();
+                js::lockdown(ctx, &added)
+                    .with_context(|| format!("failed to lock down extension {name}"))?;
+            }
+            registry.set_property(name, &js::Value::from_bool(ctx, true))?;
+            installed.push(name);
+        }
+        Ok(installed)
+    }
+}
+
+/// The own string keys of the global object.
+fn global_names(global: &js::Value) -> js::Result<BTreeSet<String>> {
+    let keys = global
+        .get_property("Reflect")?
+        .call_method("ownKeys", &[global.clone()])?;
+    let mut names = BTreeSet::new();
+    for i in 0..keys.length()? {
+        let key = keys.index(i)?;
+        if key.is_string() {
+            names.insert(key.decode_string()?);
+        }
+    }
+    Ok(names)
+}
+
+fn installed_registry(ctx: &js::Context) -> js::Result<js::Value> {
+    ctx.get_qjsbind_object("extensions", || Ok(ctx.new_object("Extensions")))
+}
+
+/// Names of the extensions installed into the context.
+pub fn installed_extensions(ctx: &js::Context) -> js::Result<Vec<String>> {
+    installed_registry(ctx)?
+        .entries()?
+        .map(|entry| entry?.0.decode_string())
+        .collect()
+}
+
+fn new_namespace(ctx: &js::Context, global: &js::Value, name: &str) -> js::Result<js::Value> {
+    let ns = ctx.new_object(name);
+    global.set_property(name, &ns)?;
+    Ok(ns)
+}
+
+/// `globalThis.crypto`, see [`crate::crypto::setup`].
+#[cfg(feature = "crypto-core")]
+pub struct Crypto;
+
+#[cfg(feature = "crypto-core")]
+impl Extension for Crypto {
+    fn name(&self) -> &'static str {
+        "crypto"
+    }
+
+    fn install(&self, _ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        crate::crypto::setup(global)
+    }
+}
+
+/// `globalThis.Scale` and `globalThis.ScaleCodec`, see [`crate::scale2::setup`].
+#[cfg(feature = "scale2")]
+pub struct Scale;
+
+#[cfg(feature = "scale2")]
+impl Extension for Scale {
+    fn name(&self) -> &'static str {
+        "scale"
+    }
+
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        let ns = new_namespace(ctx, global, "Scale")?;
+        crate::scale2::setup(&ns, ctx)
+    }
+}
+
+/// `globalThis.Hash` with the hash functions enabled by cargo features.
+pub struct Hash;
+
+impl Extension for Hash {
+    fn name(&self) -> &'static str {
+        "hash"
+    }
+
+    #[allow(unused_variables)]
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        let ns = new_namespace(ctx, global, "Hash")?;
+        #[cfg(feature = "hash-sha1")]
+        ns.define_property_fn("sha1", crate::sha1::sha1)?;
+        #[cfg(feature = "hash-sha2")]
+        ns.define_property_fn("sha256", crate::sha2::sha256)?;
+        #[cfg(feature = "hash-sha3")]
+        {
+            ns.define_property_fn("sha3_256", crate::sha3::sha3_256)?;
+            ns.define_property_fn("sha3_512", crate::sha3::sha3_512)?;
+        }
+        #[cfg(feature = "hash-blake2")]
+        {
+            ns.define_property_fn("blake2b_128", crate::blake2::blake2b_128)?;
+            ns.define_property_fn("blake2b_256", crate::blake2::blake2b_256)?;
+            ns.define_property_fn("blake2b_512", crate::blake2::blake2b_512)?;
+            ns.define_property_fn("blake2s_256", crate::blake2::blake2s_256)?;
+        }
+        Ok(())
+    }
+}
+
+/// `globalThis.Utf8`, plus `Base64` and `Hex` when enabled by cargo features.
+pub struct Encoding;
+
+impl Extension for Encoding {
+    fn name(&self) -> &'static str {
+        "encoding"
+    }
+
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        let utf8 = new_namespace(ctx, global, "Utf8")?;
+        utf8.define_property_fn("encode", crate::utf8::encode)?;
+        utf8.define_property_fn("encodeInto", crate::utf8::encode_into)?;
+        utf8.define_property_fn("decode", crate::utf8::decode)?;
+        #[cfg(feature = "base64")]
+        {
+            let base64 = new_namespace(ctx, global, "Base64")?;
+            base64.define_property_fn("encode", crate::base64::encode)?;
+            base64.define_property_fn("decode", crate::base64::decode)?;
+        }
+        #[cfg(feature = "hex")]
+        {
+            let hex = new_namespace(ctx, global, "Hex")?;
+            hex.define_property_fn("encode", crate::hex::encode)?;
+            hex.define_property_fn("decode", crate::hex::decode)?;
+        }
+        Ok(())
+    }
+}
+
+/// `globalThis.repr`, see [`crate::repr::setup`].
+pub struct Repr;
+
+impl Extension for Repr {
+    fn name(&self) -> &'static str {
+        "repr"
+    }
+
+    fn install(&self, _ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        crate::repr::setup(global)
+    }
+}
+
+impl Extension for crate::env::Env {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn install(&self, ctx: &js::Context, _global: &js::Value) -> js::Result<()> {
+        self.setup(ctx)
+    }
+}
+
+/// `globalThis.stableHash` and `globalThis.memoize`, see [`crate::stable_hash::setup`].
+#[cfg(feature = "stable-hash")]
+pub struct StableHash;
+
+#[cfg(feature = "stable-hash")]
+impl Extension for StableHash {
+    fn name(&self) -> &'static str {
+        "stable-hash"
+    }
+
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        crate::stable_hash::setup(global, ctx)
+    }
+}
+
+/// `globalThis.Multiformats`, see [`crate::multiformats::setup`].
+#[cfg(feature = "multiformats")]
+pub struct Multiformats;
+
+#[cfg(feature = "multiformats")]
+impl Extension for Multiformats {
+    fn name(&self) -> &'static str {
+        "multiformats"
+    }
+
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        let ns = new_namespace(ctx, global, "Multiformats")?;
+        crate::multiformats::setup(&ns, ctx)
+    }
+}
+
+/// `globalThis.Compression`, see [`crate::compression::setup`].
+#[cfg(feature = "compression")]
+pub struct Compression;
+
+#[cfg(feature = "compression")]
+impl Extension for Compression {
+    fn name(&self) -> &'static str {
+        "compression"
+    }
+
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        let ns = new_namespace(ctx, global, "Compression")?;
+        crate::compression::setup(&ns, ctx)
+    }
+}
+
+/// `globalThis.Cbor`, see [`crate::cbor::setup`].
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Extension for Cbor {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+        let ns = new_namespace(ctx, global, "Cbor")?;
+        crate::cbor::setup(&ns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(&'static str, core::cell::Cell<u32>);
+
+    impl Extension for &'static Counter {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn install(&self, ctx: &js::Context, global: &js::Value) -> js::Result<()> {
+            self.1.set(self.1.get() + 1);
+            global.set_property(self.0, &js::Value::from_bool(ctx, true))
+        }
+    }
+
+    struct Failing;
+
+    impl Extension for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn install(&self, _ctx: &js::Context, _global: &js::Value) -> js::Result<()> {
+            anyhow::bail!("out of luck")
+        }
+    }
+
+    fn counter(name: &'static str) -> &'static Counter {
+        Box::leak(Box::new(Counter(name, Default::default())))
+    }
+
+    #[test]
+    fn installing_twice_is_idempotent() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let (a, b) = (counter("a"), counter("b"));
+        let installed = Extensions::new().with(a).with_hash().install(&ctx).unwrap();
+        assert_eq!(installed, ["a", "hash"]);
+        let installed = Extensions::new()
+            .with(a)
+            .with(b)
+            .with_hash()
+            .install(&ctx)
+            .unwrap();
+        assert_eq!(installed, ["b"]);
+        assert_eq!((a.1.get(), b.1.get()), (1, 1));
+        assert_eq!(installed_extensions(&ctx).unwrap(), ["a", "hash", "b"]);
+
+        // Each context has its own record.
+        let other = runtime.new_context();
+        let installed = Extensions::new().with(a).install(&other).unwrap();
+        assert_eq!(installed, ["a"]);
+        assert_eq!(a.1.get(), 2);
+    }
+
+    #[test]
+    fn failures_name_the_extension() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let (a, b) = (counter("a"), counter("b"));
+        let err = Extensions::new()
+            .with(a)
+            .with(Failing)
+            .with(b)
+            .install(&ctx)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"failed to install extension failing, installed before: ["a"]"#
+        );
+        assert_eq!(format!("{:#}", err.root_cause()), "out of luck");
+        // The extensions after the failing one are not installed, the ones before are kept.
+        assert_eq!(b.1.get(), 0);
+        assert_eq!(installed_extensions(&ctx).unwrap(), ["a"]);
+
+        assert!(Extensions::new().with_named("nope").is_err());
+        let installed = Extensions::new()
+            .with_named("repr")
+            .unwrap()
+            .install(&ctx)
+            .unwrap();
+        assert_eq!(installed, ["repr"]);
+    }
+
+    // Run by the feature matrix with `crypto-core` and `multiformats`, which use SHA-2 too.
+    #[cfg(not(feature = "hash-sha2"))]
+    #[test]
+    fn hash_functions_follow_their_features() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        Extensions::new().with_hash().install(&ctx).unwrap();
+        let found = ctx.eval(&js::Code::Source("'sha256' in Hash")).unwrap();
+        assert!(!found.decode_bool().unwrap());
+    }
+
+    #[cfg(feature = "crypto-aes")]
+    #[test]
+    fn locked_extensions_can_not_be_patched() {
+        let patch = r#"
+            const real = crypto.subtle.encrypt;
+            crypto.subtle.encrypt = () => "stolen";
+            crypto.subtle.encrypt === real
+        "#;
+        let runtime = js::Runtime::new(&Default::default());
+
+        let ctx = runtime.new_context();
+        Extensions::new().with_crypto().install(&ctx).unwrap();
+        let kept = ctx.eval(&js::Code::Source(patch)).unwrap();
+        assert!(!kept.decode_bool().unwrap());
+
+        let ctx = runtime.new_context();
+        Extensions::new()
+            .with_crypto()
+            .locked()
+            .with_hash()
+            .install(&ctx)
+            .unwrap();
+        let kept = ctx.eval(&js::Code::Source(patch)).unwrap();
+        assert!(kept.decode_bool().unwrap());
+        let strict = ctx
+            .eval(&js::Code::Source(
+                r#""use strict"; crypto.subtle.encrypt = () => "stolen";"#,
+            ))
+            .unwrap_err();
+        assert!(strict.contains("TypeError"), "{strict}");
+
+        // Only the locked extension is frozen.
+        let patched = ctx
+            .eval(&js::Code::Source(r#"Hash.sha256 = () => 1; Hash.sha256()"#))
+            .unwrap();
+        assert_eq!(patched.decode_u32().unwrap(), 1);
+    }
+}
@@ -3,24 +3,49 @@ extern crate alloc;
 
 #[cfg(feature = "base64")]
 pub mod base64;
-#[cfg(feature = "blake2")]
+#[cfg(feature = "hash-blake2")]
 pub mod blake2;
 #[cfg(feature = "hex")]
 pub mod hex;
-#[cfg(feature = "sha1")]
+#[cfg(feature = "hash-sha1")]
 pub mod sha1;
-#[cfg(feature = "sha2")]
+#[cfg(feature = "hash-sha2")]
 pub mod sha2;
-#[cfg(feature = "sha3")]
+#[cfg(feature = "hash-sha3")]
 pub mod sha3;
 pub mod utf8;
 
+#[cfg(any(
+    feature = "hash-sha1",
+    feature = "hash-sha2",
+    feature = "hash-sha3",
+    feature = "hash-blake2",
+    feature = "crypto-core"
+))]
+mod output;
+
 #[cfg(feature = "scale")]
 pub mod scale;
 #[cfg(feature = "scale2")]
 pub mod scale2;
 
-#[cfg(feature = "crypto")]
+#[cfg(feature = "crypto-core")]
 pub mod crypto;
 
+pub mod env;
 pub mod repr;
+
+#[cfg(feature = "multiformats")]
+pub mod multiformats;
+
+#[cfg(feature = "compression")]
+pub mod compression;
+
+#[cfg(feature = "cbor")]
+pub mod cbor;
+
+#[cfg(feature = "stable-hash")]
+pub mod stable_hash;
+
+mod extensions;
+pub use extensions::{installed_extensions, Extension, Extensions};
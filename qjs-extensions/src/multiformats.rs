@@ -0,0 +1,369 @@
+//! Multibase, unsigned varint and multihash encodings used by content identifiers.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::bail;
+use base64::{engine::general_purpose, Engine as _};
+use js::{AsBytes, BytesOrString, ErrorContext, JsString, Result};
+use sha2::{Digest, Sha256};
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// The longest unsigned varint allowed by the multiformats spec, covering 63 bits.
+const MAX_VARINT_LEN: usize = 9;
+
+/// Multihash codes with a fixed digest length.
+const DIGEST_LENGTHS: &[(u64, usize)] = &[
+    (0x11, 20),   // sha1
+    (0x12, 32),   // sha2-256
+    (0x13, 64),   // sha2-512
+    (0x14, 64),   // sha3-512
+    (0x16, 32),   // sha3-256
+    (0x1b, 32),   // keccak-256
+    (0xb220, 32), // blake2b-256
+    (0xb240, 64), // blake2b-512
+];
+
+const SHA2_256: u64 = 0x12;
+
+/// Encode `bytes` in the multibase encoding identified by `prefix`, which is one of `f` (base16),
+/// `b` (base32), `z` (base58btc) and `u` (base64url).
+pub fn multibase_encode(prefix: char, bytes: &[u8]) -> Result<String> {
+    let mut out = String::new();
+    out.push(prefix);
+    match prefix {
+        'f' => out.push_str(&hex::encode(bytes)),
+        'b' => out.push_str(&base32_encode(bytes)),
+        'z' => out.push_str(&base58_encode(bytes)),
+        'u' => out.push_str(&general_purpose::URL_SAFE_NO_PAD.encode(bytes)),
+        _ => bail!("unsupported multibase prefix: {prefix}"),
+    }
+    Ok(out)
+}
+
+/// Decode a multibase string, choosing the encoding by its prefix.
+pub fn multibase_decode(text: &str) -> Result<Vec<u8>> {
+    let mut chars = text.chars();
+    let Some(prefix) = chars.next() else {
+        bail!("empty multibase string");
+    };
+    let data = chars.as_str();
+    match prefix {
+        'f' | 'F' => hex::decode(data).context("invalid base16 string"),
+        'b' | 'B' => base32_decode(data),
+        'z' => base58_decode(data),
+        'u' => general_purpose::URL_SAFE_NO_PAD
+            .decode(data)
+            .context("invalid base64url string"),
+        _ => bail!("unsupported multibase prefix: {prefix}"),
+    }
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+fn base32_decode(text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let c = c.to_ascii_lowercase();
+        let Some(value) = BASE32_ALPHABET.iter().position(|&a| a == c) else {
+            bail!("invalid base32 character: {}", c as char);
+        };
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn base58_encode(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    // Digits in base 58, least significant first.
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in &bytes[zeros..] {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let mut out = String::with_capacity(zeros + digits.len());
+    out.extend(core::iter::repeat('1').take(zeros));
+    out.extend(
+        digits
+            .iter()
+            .rev()
+            .map(|&d| BASE58_ALPHABET[d as usize] as char),
+    );
+    out
+}
+
+fn base58_decode(text: &str) -> Result<Vec<u8>> {
+    let zeros = text.bytes().take_while(|&c| c == b'1').count();
+    // Bytes, least significant first.
+    let mut bytes: Vec<u8> = Vec::with_capacity(text.len());
+    for c in text.bytes().skip(zeros) {
+        let Some(value) = BASE58_ALPHABET.iter().position(|&a| a == c) else {
+            bail!("invalid base58 character: {}", c as char);
+        };
+        let mut carry = value as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let mut out = alloc::vec![0u8; zeros];
+    out.extend(bytes.iter().rev());
+    Ok(out)
+}
+
+/// Encode `value` as an unsigned LEB128 varint. Values above 2^63 - 1, which need more than
+/// 9 bytes, are rejected like [`varint_decode`] does.
+pub fn varint_encode(mut value: u64) -> Result<Vec<u8>> {
+    if value > i64::MAX as u64 {
+        bail!("varint value {value} does not fit in {MAX_VARINT_LEN} bytes");
+    }
+    let mut out = Vec::with_capacity(MAX_VARINT_LEN);
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return Ok(out);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Decode an unsigned varint from the start of `bytes`, returning the value and the number of
+/// bytes read. Varints longer than 9 bytes or with redundant trailing zero bytes are rejected.
+pub fn varint_decode(bytes: &[u8]) -> Result<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_VARINT_LEN) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            if byte == 0 && i > 0 {
+                bail!("varint is not minimally encoded");
+            }
+            return Ok((value, i + 1));
+        }
+    }
+    if bytes.len() >= MAX_VARINT_LEN {
+        bail!("varint is longer than {MAX_VARINT_LEN} bytes");
+    }
+    bail!("unexpected end of varint")
+}
+
+/// Prefix `digest` with its multihash code and length.
+pub fn multihash_wrap(code: u64, digest: &[u8]) -> Result<Vec<u8>> {
+    check_digest_len(code, digest.len())?;
+    let mut out = varint_encode(code)?;
+    out.extend(varint_encode(digest.len() as u64)?);
+    out.extend_from_slice(digest);
+    Ok(out)
+}
+
+/// Split a multihash into its code and digest.
+pub fn multihash_unwrap(bytes: &[u8]) -> Result<(u64, &[u8])> {
+    let (code, n) = varint_decode(bytes).context("invalid multihash code")?;
+    let rest = &bytes[n..];
+    let (len, n) = varint_decode(rest).context("invalid multihash length")?;
+    let digest = &rest[n..];
+    if digest.len() as u64 != len {
+        bail!(
+            "multihash length mismatch: declared {len}, got {}",
+            digest.len()
+        );
+    }
+    check_digest_len(code, digest.len())?;
+    Ok((code, digest))
+}
+
+fn check_digest_len(code: u64, len: usize) -> Result<()> {
+    if let Some((_, expected)) = DIGEST_LENGTHS.iter().find(|(c, _)| *c == code) {
+        if *expected != len {
+            bail!("invalid digest length for multihash code {code:#x}: expected {expected}, got {len}");
+        }
+    }
+    Ok(())
+}
+
+#[derive(js::ToJsValue)]
+struct Multihash {
+    code: u64,
+    digest: AsBytes<Vec<u8>>,
+}
+
+#[js::host_call]
+fn encode(prefix: JsString, data: BytesOrString) -> Result<String> {
+    let mut chars = prefix.as_str().chars();
+    let (Some(prefix), None) = (chars.next(), chars.next()) else {
+        bail!(
+            "multibase prefix must be a single character, got {:?}",
+            prefix.as_str()
+        );
+    };
+    multibase_encode(prefix, data.as_bytes())
+}
+
+#[js::host_call]
+fn decode(text: JsString) -> Result<AsBytes<Vec<u8>>> {
+    multibase_decode(text.as_str()).map(AsBytes)
+}
+
+#[js::host_call]
+fn encode_varint(value: u64) -> Result<AsBytes<Vec<u8>>> {
+    varint_encode(value).map(AsBytes)
+}
+
+#[js::host_call]
+fn decode_varint(bytes: js::Bytes) -> Result<(u64, usize)> {
+    varint_decode(bytes.as_bytes())
+}
+
+#[js::host_call]
+fn wrap(code: u64, digest: js::Bytes) -> Result<AsBytes<Vec<u8>>> {
+    multihash_wrap(code, digest.as_bytes()).map(AsBytes)
+}
+
+#[js::host_call]
+fn unwrap(bytes: js::Bytes) -> Result<Multihash> {
+    let (code, digest) = multihash_unwrap(bytes.as_bytes())?;
+    Ok(Multihash {
+        code,
+        digest: AsBytes(digest.to_vec()),
+    })
+}
+
+#[js::host_call]
+fn sha256(data: BytesOrString) -> Result<AsBytes<Vec<u8>>> {
+    multihash_wrap(SHA2_256, &Sha256::digest(data.as_bytes())).map(AsBytes)
+}
+
+/// Define `multibase`, `varint` and `multihash` on `ns`.
+pub fn setup(ns: &js::Value, ctx: &js::Context) -> Result<()> {
+    let multibase = ctx.new_object("Multibase");
+    multibase.define_property_fn("encode", encode)?;
+    multibase.define_property_fn("decode", decode)?;
+    ns.set_property("multibase", &multibase)?;
+
+    let varint = ctx.new_object("Varint");
+    varint.define_property_fn("encode", encode_varint)?;
+    varint.define_property_fn("decode", decode_varint)?;
+    ns.set_property("varint", &varint)?;
+
+    let multihash = ctx.new_object("Multihash");
+    multihash.define_property_fn("wrap", wrap)?;
+    multihash.define_property_fn("unwrap", unwrap)?;
+    multihash.define_property_fn("sha256", sha256)?;
+    ns.set_property("multihash", &multihash)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From the multiformats/multibase test vectors, basic.csv.
+    #[test]
+    fn multibase_vectors() {
+        let input = b"yes mani !";
+        for encoded in [
+            "f796573206d616e692021",
+            "bpfsxgidnmfxgsibb",
+            "z7paNL19xttacUY",
+            "ueWVzIG1hbmkgIQ",
+        ] {
+            let prefix = encoded.chars().next().unwrap();
+            assert_eq!(multibase_encode(prefix, input).unwrap(), encoded);
+            assert_eq!(multibase_decode(encoded).unwrap(), input);
+        }
+        // leading_zero.csv
+        assert_eq!(
+            multibase_encode('z', b"\x00yes mani !").unwrap(),
+            "z17paNL19xttacUY"
+        );
+        assert_eq!(
+            multibase_decode("z17paNL19xttacUY").unwrap(),
+            b"\x00yes mani !"
+        );
+        assert!(multibase_decode("x00").is_err());
+    }
+
+    // From the multiformats/unsigned-varint README.
+    #[test]
+    fn varint_vectors() {
+        for (value, encoded) in [
+            (1u64, &[0x01][..]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (255, &[0xff, 0x01]),
+            (300, &[0xac, 0x02]),
+            (16384, &[0x80, 0x80, 0x01]),
+        ] {
+            assert_eq!(varint_encode(value).unwrap(), encoded);
+            assert_eq!(varint_decode(encoded).unwrap(), (value, encoded.len()));
+        }
+        assert!(varint_decode(&[0x80]).is_err());
+        assert!(varint_decode(&[0x81, 0x00]).is_err());
+        assert!(varint_decode(&[0xff; 10]).is_err());
+    }
+
+    #[test]
+    fn varint_boundary() {
+        let max = i64::MAX as u64;
+        let encoded = varint_encode(max).unwrap();
+        assert_eq!(encoded, [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+        assert_eq!(varint_decode(&encoded).unwrap(), (max, MAX_VARINT_LEN));
+        assert!(varint_encode(max + 1).is_err());
+        assert!(varint_encode(u64::MAX).is_err());
+        let mut too_long = [0xff; 10];
+        too_long[9] = 0x01;
+        assert!(varint_decode(&too_long).is_err());
+    }
+
+    #[test]
+    fn multihash_sha256() {
+        let hash = multihash_wrap(SHA2_256, &Sha256::digest(b"foo")).unwrap();
+        assert_eq!(
+            hex::encode(&hash),
+            "12202c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+        );
+        let (code, digest) = multihash_unwrap(&hash).unwrap();
+        assert_eq!(code, SHA2_256);
+        assert_eq!(digest, &hash[2..]);
+        assert!(multihash_wrap(SHA2_256, &[0; 31]).is_err());
+        assert!(multihash_unwrap(&hash[..33]).is_err());
+    }
+}
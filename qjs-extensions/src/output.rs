@@ -0,0 +1,40 @@
+//! The optional output buffer of the hash and crypto functions, `sha256(data, out?)`.
+//!
+//! Like `Utf8.encodeInto`, passing a Uint8Array of the output length writes the result into it
+//! instead of allocating a new one, which saves the GC work when hashing many small messages.
+
+use js::{AsBytes, JsUint8Array, Result, ToJsValue};
+
+/// A freshly allocated result, or the caller's buffer holding it.
+pub enum Output<T> {
+    New(AsBytes<T>),
+    Filled(JsUint8Array),
+}
+
+impl<T: AsRef<[u8]>> Output<T> {
+    /// Write `bytes` into `out` if given, which must have exactly their length.
+    pub fn new(bytes: T, out: Option<JsUint8Array>) -> Result<Self> {
+        let Some(out) = out else {
+            return Ok(Output::New(AsBytes(bytes)));
+        };
+        let bytes = bytes.as_ref();
+        if out.len() != bytes.len() {
+            anyhow::bail!(
+                "output buffer must be {} bytes long, got {}",
+                bytes.len(),
+                out.len()
+            );
+        }
+        out.fill_with_bytes(bytes);
+        Ok(Output::Filled(out))
+    }
+}
+
+impl<T: AsRef<[u8]> + 'static> ToJsValue for Output<T> {
+    fn to_js_value(&self, ctx: &js::Context) -> Result<js::Value> {
+        match self {
+            Output::New(bytes) => bytes.to_js_value(ctx),
+            Output::Filled(out) => out.to_js_value(ctx),
+        }
+    }
+}
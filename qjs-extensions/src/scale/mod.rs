@@ -67,7 +67,7 @@ impl js::FromJsValue for TypeRegistry {
         let me = value
             .opaque_object_data::<Self>()
             .get()
-            .expect_js_value(&value, "TypeRegistry")?
+            .expect_type(&value, "TypeRegistry")?
             .clone();
         Ok(me)
     }
@@ -355,6 +355,12 @@ fn decode_valude(
             let length = Compact::<u32>::decode(buf)
                 .context("unexpected end of buffer")?
                 .0;
+            if length as usize > buf.len() {
+                bail!(
+                    "declared length {length} exceeds remaining {} bytes",
+                    buf.len()
+                );
+            }
             let out = ctx.new_array();
             for _ in 0..length {
                 let sub_value = decode_valude(ctx, buf, *tid, type_registry)?;
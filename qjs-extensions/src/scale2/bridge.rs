@@ -0,0 +1,283 @@
+//! Rust types described to the registry, so that a value encoded with `parity-scale-codec` can be
+//! decoded by scripts, and the other way round.
+//!
+//! `#[derive(ScaleJsType)]` follows the `#[codec(..)]` attributes of the `Encode` and `Decode`
+//! derives, `compact`, `skip` and `index`, so the registry reads the same bytes as `Decode` does:
+//!
+//! ```ignore
+//! #[derive(Encode, Decode, scale2::ScaleJsType)]
+//! enum Call {
+//!     Remark(Vec<u8>),
+//!     #[codec(index = 5)]
+//!     Transfer { to: [u8; 32], #[codec(compact)] amount: u128 },
+//! }
+//!
+//! let value = scale2::decode_to_js::<Call>(&ctx, &call.encode())?;
+//! ```
+//!
+//! Recursive types are not supported. `Option<bool>`, a single byte in `parity-scale-codec`, is
+//! described as the enum `<_None|True|False>`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail};
+use parity_scale_codec::{Compact, Decode, DecodeAll, Encode};
+
+use js::AsBytes;
+
+use super::{decode_valude, encode_into, Id, Registry, TypeRef, TypeRegistry, TypeSpec};
+
+/// A Rust type with a SCALE encoding the registry can describe.
+pub trait ScaleJsType {
+    /// Define the named types `Self` refers to in `registry` and return its id.
+    fn scale_type(registry: &TypeRegistry) -> TypeRef;
+
+    /// The id of `Option<Self>`.
+    fn option_scale_type(registry: &TypeRegistry) -> TypeRef {
+        TypeRef::generic("Option", [Self::scale_type(registry)])
+    }
+}
+
+macro_rules! impl_primitive {
+    ($($t: ty => $name: literal),*) => {
+        $(
+            impl ScaleJsType for $t {
+                fn scale_type(_registry: &TypeRegistry) -> TypeRef {
+                    TypeRef::named($name)
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive! {
+    u8 => "u8", u16 => "u16", u32 => "u32", u64 => "u64", u128 => "u128",
+    i8 => "i8", i16 => "i16", i32 => "i32", i64 => "i64", i128 => "i128",
+    String => "str"
+}
+
+impl ScaleJsType for bool {
+    fn scale_type(_registry: &TypeRegistry) -> TypeRef {
+        TypeRef::named("bool")
+    }
+
+    fn option_scale_type(_registry: &TypeRegistry) -> TypeRef {
+        let variants = [("_None", None, 0), ("True", None, 1), ("False", None, 2)];
+        TypeSpec::indexed_enumeration(variants).into()
+    }
+}
+
+impl<T: ScaleJsType> ScaleJsType for Option<T> {
+    fn scale_type(registry: &TypeRegistry) -> TypeRef {
+        T::option_scale_type(registry)
+    }
+}
+
+impl<T: ScaleJsType> ScaleJsType for Vec<T> {
+    fn scale_type(registry: &TypeRegistry) -> TypeRef {
+        TypeSpec::seq(T::scale_type(registry)).into()
+    }
+}
+
+impl<T: ScaleJsType, const N: usize> ScaleJsType for [T; N] {
+    fn scale_type(registry: &TypeRegistry) -> TypeRef {
+        TypeSpec::array(T::scale_type(registry), N as u32).into()
+    }
+}
+
+impl<T: ScaleJsType> ScaleJsType for Compact<T> {
+    fn scale_type(registry: &TypeRegistry) -> TypeRef {
+        TypeSpec::compact(T::scale_type(registry)).into()
+    }
+}
+
+impl<T: ScaleJsType> ScaleJsType for alloc::boxed::Box<T> {
+    fn scale_type(registry: &TypeRegistry) -> TypeRef {
+        T::scale_type(registry)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($t: ident),*) => {
+        impl<$($t: ScaleJsType),*> ScaleJsType for ($($t,)*) {
+            fn scale_type(_registry: &TypeRegistry) -> TypeRef {
+                TypeSpec::tuple([$($t::scale_type(_registry)),*]).into()
+            }
+        }
+    };
+}
+
+impl_tuple!();
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+
+impl TypeRegistry {
+    /// Define the types of `T` and return its id.
+    pub fn register<T: ScaleJsType>(&self) -> TypeRef {
+        T::scale_type(self)
+    }
+}
+
+pub fn to_scale<T: Encode>(value: &T) -> AsBytes<Vec<u8>> {
+    AsBytes(value.encode())
+}
+
+fn registry_of<T: ScaleJsType>(ctx: &js::Context) -> js::Result<(TypeRegistry, Id)> {
+    let registry = TypeRegistry::from(Registry::std_in(ctx)?);
+    let id = registry.register::<T>().0;
+    Ok((registry, id))
+}
+
+/// Decode `bytes` as `T` to the value scripts get from the registry.
+///
+/// The bytes are checked with `T::decode_all` first, so nothing `T` rejects is decoded.
+pub fn decode_to_js<T: Decode + ScaleJsType>(
+    ctx: &js::Context,
+    bytes: &[u8],
+) -> js::Result<js::Value> {
+    T::decode_all(&mut &bytes[..])
+        .map_err(|err| anyhow!("invalid {}: {err}", core::any::type_name::<T>()))?;
+    let (registry, id) = registry_of::<T>(ctx)?;
+    let mut buf = bytes;
+    let value = decode_valude(ctx, &mut buf, &id, &registry.borrow())?;
+    if !buf.is_empty() {
+        bail!("{} trailing bytes after decoding", buf.len());
+    }
+    Ok(value)
+}
+
+/// Encode a value shaped as `decode_to_js` returns it, and decode it as `T`.
+pub fn decode_from_js<T: Decode + ScaleJsType>(value: &js::Value) -> js::Result<T> {
+    let (registry, id) = registry_of::<T>(value.context()?)?;
+    let mut bytes = Vec::new();
+    encode_into(value.clone(), &id, &registry.borrow(), &mut bytes)?;
+    T::decode_all(&mut bytes.as_slice())
+        .map_err(|err| anyhow!("invalid {}: {err}", core::any::type_name::<T>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[derive(Debug, PartialEq, Encode, Decode, js::ScaleJsType)]
+    enum Kind {
+        Plain,
+        #[codec(index = 5)]
+        Tagged(u32),
+        Nested {
+            depth: Compact<u32>,
+            id: Option<i64>,
+        },
+        Pair(u8, bool),
+    }
+
+    #[derive(Debug, PartialEq, Encode, Decode, js::ScaleJsType)]
+    struct Transfer {
+        to: [u8; 4],
+        #[codec(compact)]
+        amount: u128,
+        memo: Option<String>,
+        kinds: Vec<Kind>,
+        flag: Option<bool>,
+        #[codec(skip)]
+        cached: u32,
+        tags: Vec<(u8, u16)>,
+    }
+
+    #[test]
+    fn registry_agrees_with_parity() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let transfer = Transfer {
+            to: [1, 2, 3, 4],
+            amount: 1 << 100,
+            memo: Some("rent".into()),
+            kinds: alloc::vec![
+                Kind::Plain,
+                Kind::Tagged(7),
+                Kind::Nested {
+                    depth: Compact(300),
+                    id: None,
+                },
+                Kind::Pair(9, true),
+            ],
+            flag: Some(false),
+            cached: 0,
+            tags: alloc::vec![(1, 1000)],
+        };
+        let bytes = to_scale(&transfer).0;
+        let value = decode_to_js::<Transfer>(&ctx, &bytes).unwrap();
+        ctx.get_global_object().set_property("t", &value).unwrap();
+        let summary = ctx
+            .eval(&js::Code::Source(
+                r#"
+                [
+                    Array.from(t.to).join(","),
+                    String(t.amount),
+                    t.memo,
+                    t.kinds.map((k) => Object.keys(k)[0]).join(","),
+                    t.kinds[1].Tagged,
+                    t.kinds[2].Nested.depth,
+                    t.kinds[2].Nested.id,
+                    t.kinds[3].Pair.join(","),
+                    Object.keys(t.flag)[0],
+                    "cached" in t,
+                    t.tags[0].join(","),
+                ].join(" ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            summary.decode_string().unwrap(),
+            "1,2,3,4 1267650600228229401496703205376 rent Plain,Tagged,Nested,Pair 7 300 null \
+             9,true False false 1,1000"
+        );
+        assert_eq!(decode_from_js::<Transfer>(&value).unwrap(), transfer);
+
+        // The tag 5 is the only one `Tagged` can have.
+        let tagged = Kind::Tagged(1).encode();
+        assert_eq!(tagged[0], 5);
+        assert!(decode_to_js::<Kind>(&ctx, &tagged).is_ok());
+        let err = decode_to_js::<Kind>(&ctx, &[1, 1, 0, 0, 0]).unwrap_err();
+        assert!(err.to_string().starts_with("invalid"), "{err}");
+    }
+
+    mod first {
+        use super::*;
+
+        #[derive(Debug, PartialEq, Encode, Decode, js::ScaleJsType)]
+        pub struct Tag(pub u8);
+    }
+
+    mod second {
+        use super::*;
+
+        #[derive(Debug, PartialEq, Encode, Decode, js::ScaleJsType)]
+        pub struct Tag(pub u32);
+    }
+
+    #[derive(Debug, PartialEq, Encode, Decode, js::ScaleJsType)]
+    struct Tags {
+        first: first::Tag,
+        second: second::Tag,
+    }
+
+    #[test]
+    fn same_named_types_do_not_collide() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let tags = Tags {
+            first: first::Tag(1),
+            second: second::Tag(70000),
+        };
+        let value = decode_to_js::<Tags>(&ctx, &tags.encode()).unwrap();
+        assert_eq!(
+            value.get_property("second").unwrap().decode_u32().unwrap(),
+            70000
+        );
+        assert_eq!(decode_from_js::<Tags>(&value).unwrap(), tags);
+    }
+}
@@ -0,0 +1,241 @@
+//! Codecs for the parts of a Substrate extrinsic the type DSL can not describe.
+
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail};
+use parity_scale_codec::{Compact, Decode, Encode};
+
+use js::{self as js, AsBytes, BytesOrHex, FromJsValue, ToJsValue};
+
+/// The extrinsic format version written by `wrapExtrinsic`.
+const EXTRINSIC_VERSION: u8 = 4;
+const SIGNED_FLAG: u8 = 0b1000_0000;
+
+/// The lifetime of a transaction, as `sp_runtime::generic::Era`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Era {
+    Immortal,
+    /// Valid for `period` blocks from the block whose number modulo `period` is `phase`.
+    Mortal {
+        period: u64,
+        phase: u64,
+    },
+}
+
+impl Era {
+    /// Fails unless `period` is a power of two between 4 and 65536 and `phase` is below it and
+    /// a multiple of the precision the two-byte encoding keeps for long periods.
+    pub fn encode(&self) -> js::Result<Vec<u8>> {
+        let (period, phase) = match *self {
+            Era::Immortal => return Ok(alloc::vec![0]),
+            Era::Mortal { period, phase } => (period, phase),
+        };
+        if !period.is_power_of_two() || !(4..=1 << 16).contains(&period) {
+            bail!("era period must be a power of two between 4 and 65536, got {period}");
+        }
+        if phase >= period {
+            bail!("era phase {phase} is not below the period {period}");
+        }
+        let quantize_factor = (period >> 12).max(1);
+        if phase % quantize_factor != 0 {
+            bail!("era phase {phase} is not a multiple of {quantize_factor}");
+        }
+        let encoded = (period.trailing_zeros() - 1).clamp(1, 15) as u16
+            | ((phase / quantize_factor) << 4) as u16;
+        Ok(encoded.encode())
+    }
+
+    pub fn decode(buf: &mut &[u8]) -> js::Result<Self> {
+        let first = u8::decode(buf).map_err(|_| super::unexpected_eof())?;
+        if first == 0 {
+            return Ok(Era::Immortal);
+        }
+        let second = u8::decode(buf).map_err(|_| super::unexpected_eof())?;
+        let encoded = u64::from(first) | (u64::from(second) << 8);
+        let period = 2 << (encoded % (1 << 4));
+        let quantize_factor = (period >> 12).max(1);
+        let phase = (encoded >> 4) * quantize_factor;
+        if period < 4 || phase >= period {
+            bail!("invalid era: period {period}, phase {phase}");
+        }
+        Ok(Era::Mortal { period, phase })
+    }
+}
+
+impl FromJsValue for Era {
+    fn from_js_value(value: js::Value) -> js::Result<Self> {
+        if value.is_string() {
+            return match value.decode_string()?.as_str() {
+                "immortal" => Ok(Era::Immortal),
+                other => {
+                    bail!("expected \"immortal\" or {{mortal: {{period, phase}}}}, got {other:?}")
+                }
+            };
+        }
+        let mortal = value.get_property("mortal")?;
+        if mortal.is_undefined() {
+            bail!(
+                "expected \"immortal\" or {{mortal: {{period, phase}}}}, got {}",
+                value.describe_type()
+            );
+        }
+        Ok(Era::Mortal {
+            period: mortal.get_property("period")?.decode_u64()?,
+            phase: mortal.get_property("phase")?.decode_u64()?,
+        })
+    }
+}
+
+impl ToJsValue for Era {
+    fn to_js_value(&self, ctx: &js::Context) -> js::Result<js::Value> {
+        match *self {
+            Era::Immortal => "immortal".to_js_value(ctx),
+            Era::Mortal { period, phase } => {
+                let mortal = ctx.new_object("");
+                // Both fit in 16 bits.
+                mortal.set_property("period", &(period as u32).to_js_value(ctx)?)?;
+                mortal.set_property("phase", &(phase as u32).to_js_value(ctx)?)?;
+                let out = ctx.new_object("");
+                out.set_property("mortal", &mortal)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, js::FromJsValue)]
+pub(super) struct WrapOptions {
+    #[qjs(default)]
+    signed: bool,
+}
+
+/// An extrinsic split by `unwrapExtrinsic`.
+#[derive(Debug, js::ToJsValue)]
+pub(super) struct Unwrapped {
+    version: u8,
+    signed: bool,
+    /// The signature part, if signed, followed by the call data.
+    body: Vec<u8>,
+}
+
+/// Prefix `body` with the version byte and the compact length of the whole.
+pub fn wrap_extrinsic(body: &[u8], signed: bool) -> Vec<u8> {
+    let version = EXTRINSIC_VERSION | if signed { SIGNED_FLAG } else { 0 };
+    let mut out = Compact(body.len() as u32 + 1).encode();
+    out.reserve(body.len() + 1);
+    out.push(version);
+    out.extend_from_slice(body);
+    out
+}
+
+/// Split an extrinsic into the signed flag, the version and the body.
+pub fn unwrap_extrinsic(bytes: &[u8]) -> js::Result<(u8, bool, &[u8])> {
+    let mut buf = bytes;
+    let len = Compact::<u32>::decode(&mut buf)
+        .map_err(|_| super::unexpected_eof())?
+        .0 as usize;
+    if len != buf.len() {
+        bail!(
+            "extrinsic length prefix is {len} but {} bytes follow",
+            buf.len()
+        );
+    }
+    let (&version, body) = buf
+        .split_first()
+        .ok_or_else(|| anyhow!("empty extrinsic"))?;
+    let signed = version & SIGNED_FLAG != 0;
+    let version = version & !SIGNED_FLAG;
+    if version != EXTRINSIC_VERSION {
+        bail!("unsupported extrinsic version {version}");
+    }
+    Ok((version, signed, body))
+}
+
+#[js::host_call]
+pub(super) fn encode_era(era: Era) -> js::Result<AsBytes<Vec<u8>>> {
+    Ok(AsBytes(era.encode()?))
+}
+
+#[js::host_call]
+pub(super) fn decode_era(bytes: BytesOrHex<Vec<u8>>) -> js::Result<Era> {
+    let mut buf = bytes.0.as_slice();
+    let era = Era::decode(&mut buf)?;
+    if !buf.is_empty() {
+        bail!("{} trailing bytes after the era", buf.len());
+    }
+    Ok(era)
+}
+
+#[js::host_call]
+pub(super) fn wrap(bytes: BytesOrHex<Vec<u8>>, options: Option<WrapOptions>) -> AsBytes<Vec<u8>> {
+    let signed = options.unwrap_or_default().signed;
+    AsBytes(wrap_extrinsic(&bytes.0, signed))
+}
+
+#[js::host_call]
+pub(super) fn unwrap(bytes: BytesOrHex<Vec<u8>>) -> js::Result<Unwrapped> {
+    let (version, signed, body) = unwrap_extrinsic(&bytes.0)?;
+    Ok(Unwrapped {
+        version,
+        signed,
+        body: body.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_eras() {
+        let mortal = |period, phase| Era::Mortal { period, phase };
+        // From the `sp_runtime` era tests.
+        assert_eq!(mortal(32768, 20000).encode().unwrap(), [78, 156]);
+        assert_eq!(mortal(64, 42).encode().unwrap(), [0xa5, 0x02]);
+        assert_eq!(Era::Immortal.encode().unwrap(), [0]);
+        for era in [
+            Era::Immortal,
+            mortal(4, 3),
+            mortal(64, 42),
+            mortal(32768, 20000),
+        ] {
+            let encoded = era.encode().unwrap();
+            assert_eq!(Era::decode(&mut encoded.as_slice()).unwrap(), era);
+        }
+        assert!(mortal(100, 1).encode().is_err());
+        assert!(mortal(64, 64).encode().is_err());
+        assert!(mortal(32768, 20001).encode().is_err());
+    }
+
+    #[test]
+    fn wraps_extrinsics_from_js() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = super::super::setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let out = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const hex = (bytes) => Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+                // A `timestamp.set` inherent of Polkadot.
+                const inherent = "0x280403000b0068e5cf8b01";
+                const unwrapped = scl.unwrapExtrinsic(inherent);
+                const signed = scl.unwrapExtrinsic(scl.wrapExtrinsic(unwrapped.body, { signed: true }));
+                [
+                    unwrapped.version,
+                    unwrapped.signed,
+                    hex(unwrapped.body),
+                    hex(scl.wrapExtrinsic(unwrapped.body)),
+                    signed.signed,
+                    hex(scl.encodeEra({ mortal: { period: 64, phase: 42 } })),
+                    JSON.stringify(scl.decodeEra("0x4e9c")),
+                    scl.decodeEra("0x00"),
+                ].join(" ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            out.decode_string().unwrap(),
+            r#"4 false 03000b0068e5cf8b01 280403000b0068e5cf8b01 true a502 {"mortal":{"period":32768,"phase":20000}} immortal"#
+        );
+    }
+}
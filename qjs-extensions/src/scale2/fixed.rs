@@ -0,0 +1,217 @@
+use alloc::string::String;
+use anyhow::{anyhow, bail};
+use parity_scale_codec::{Compact, Decode, Encode, Output};
+
+use js::{self as js, ToJsValue};
+
+/// A fixed-point number encoded as an integer count of parts.
+///
+/// The per-things (`Percent`, `Permill`, `Perbill`) range from zero to one, `FixedU128` spans
+/// the whole `u128` with 18 decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixedPoint {
+    Percent,
+    Permill,
+    Perbill,
+    FixedU128,
+}
+
+impl FixedPoint {
+    pub const ALL: [FixedPoint; 4] = [
+        FixedPoint::Percent,
+        FixedPoint::Permill,
+        FixedPoint::Perbill,
+        FixedPoint::FixedU128,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FixedPoint::Percent => "Percent",
+            FixedPoint::Permill => "Permill",
+            FixedPoint::Perbill => "Perbill",
+            FixedPoint::FixedU128 => "FixedU128",
+        }
+    }
+
+    /// Number of decimal digits in one part.
+    pub fn decimals(&self) -> u32 {
+        match self {
+            FixedPoint::Percent => 2,
+            FixedPoint::Permill => 6,
+            FixedPoint::Perbill => 9,
+            FixedPoint::FixedU128 => 18,
+        }
+    }
+
+    /// Number of parts in one.
+    pub fn accuracy(&self) -> u128 {
+        10u128.pow(self.decimals())
+    }
+
+    fn max_raw(&self) -> u128 {
+        match self {
+            FixedPoint::FixedU128 => u128::MAX,
+            _ => self.accuracy(),
+        }
+    }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        match self {
+            FixedPoint::Percent => 1,
+            FixedPoint::Permill | FixedPoint::Perbill => 4,
+            FixedPoint::FixedU128 => 16,
+        }
+    }
+
+    fn check_raw(&self, raw: u128) -> js::Result<u128> {
+        if raw > self.max_raw() {
+            match self {
+                FixedPoint::FixedU128 => bail!("{raw} is out of range for FixedU128"),
+                _ => bail!("{raw} parts exceed 100% of {}", self.name()),
+            }
+        }
+        Ok(raw)
+    }
+
+    /// Parse a non-negative decimal, optionally with an exponent or a `%` suffix, to parts.
+    ///
+    /// The conversion is exact: `"12.5%"` is 125_000_000 parts of a `Perbill`, and a value
+    /// finer than one part is an error rather than rounded.
+    pub fn parse(&self, text: &str) -> js::Result<u128> {
+        let name = self.name();
+        let invalid = || anyhow!("invalid {name}: {text:?}");
+        let s = text.trim();
+        let (s, percent) = match s.strip_suffix('%') {
+            Some(s) => (s.trim_end(), true),
+            None => (s, false),
+        };
+        let (mantissa, exp) = match s.find(['e', 'E']) {
+            Some(pos) => {
+                let exp = s[pos + 1..].parse::<i32>().map_err(|_| invalid())?;
+                (&s[..pos], exp)
+            }
+            None => (s, 0),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if (int.is_empty() && frac.is_empty())
+            || !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        // The value is `digits * 10^shift` parts.
+        let mut shift = i64::from(exp) + i64::from(self.decimals()) - frac.len() as i64;
+        if percent {
+            shift -= 2;
+        }
+        let digits: String = int.chars().chain(frac.chars()).collect();
+        let significant = digits.trim_start_matches('0');
+        let trimmed = significant.trim_end_matches('0');
+        if trimmed.is_empty() {
+            return Ok(0);
+        }
+        shift += (significant.len() - trimmed.len()) as i64;
+        if shift < 0 {
+            bail!("{text:?} is finer than one part of {name}");
+        }
+        let out_of_range = || anyhow!("{text:?} is out of range for {name}");
+        let digits = trimmed.parse::<u128>().map_err(|_| out_of_range())?;
+        let scale = u32::try_from(shift)
+            .ok()
+            .and_then(|shift| 10u128.checked_pow(shift))
+            .ok_or_else(out_of_range)?;
+        let raw = digits.checked_mul(scale).ok_or_else(out_of_range)?;
+        self.check_raw(raw)
+    }
+
+    /// Get the parts from a JS value.
+    ///
+    /// A BigInt or `{raw}` is the parts as is, a number or a string is a decimal scaled to parts,
+    /// so `0.125`, `"0.125"` and `"12.5%"` are all 125_000_000 parts of a `Perbill`.
+    pub fn raw_from_js(&self, value: &js::Value) -> js::Result<u128> {
+        if value.is_big_int() {
+            return self.check_raw(value.decode_u128()?);
+        }
+        if value.is_number() {
+            let n = value.decode_f64()?;
+            if n == 0.0 {
+                return Ok(0);
+            }
+            if !n.is_finite() || n < 0.0 {
+                bail!("invalid {}: {n}", self.name());
+            }
+            // The shortest representation that round-trips, so 0.1 is parsed as "0.1".
+            return self.parse(&alloc::format!("{n}"));
+        }
+        if value.is_string() {
+            return self.parse(&value.decode_string()?);
+        }
+        if value.is_object() {
+            let raw = value.get_property("raw")?;
+            if !raw.is_undefined() {
+                return self.check_raw(raw.decode_u128()?);
+            }
+        }
+        bail!(
+            "expected a number, a decimal string, a BigInt or {{raw}} for {}, got {}",
+            self.name(),
+            value.describe_type()
+        )
+    }
+
+    pub(crate) fn encode(&self, value: &js::Value, out: &mut impl Output) -> js::Result<()> {
+        let raw = self.raw_from_js(value)?;
+        match self {
+            FixedPoint::Percent => (raw as u8).encode_to(out),
+            FixedPoint::Permill | FixedPoint::Perbill => (raw as u32).encode_to(out),
+            FixedPoint::FixedU128 => raw.encode_to(out),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn encode_compact(
+        &self,
+        value: &js::Value,
+        out: &mut impl Output,
+    ) -> js::Result<()> {
+        Compact(self.raw_from_js(value)?).encode_to(out);
+        Ok(())
+    }
+
+    pub(crate) fn decode(&self, buf: &mut &[u8]) -> js::Result<u128> {
+        let raw = match self {
+            FixedPoint::Percent => u8::decode(buf).map(u128::from),
+            FixedPoint::Permill | FixedPoint::Perbill => u32::decode(buf).map(u128::from),
+            FixedPoint::FixedU128 => u128::decode(buf),
+        }
+        .map_err(|_| super::unexpected_eof())?;
+        self.check_raw(raw)
+    }
+
+    pub(crate) fn decode_compact(&self, buf: &mut &[u8]) -> js::Result<u128> {
+        let raw = Compact::<u128>::decode(buf).map_err(|_| super::unexpected_eof())?;
+        self.check_raw(raw.0)
+    }
+
+    /// The decoded value, `{raw, asNumber}` or just the number when `as_number` is set.
+    ///
+    /// `raw` is a number for the per-things and a BigInt for `FixedU128`.
+    pub(crate) fn to_js(
+        &self,
+        ctx: &js::Context,
+        raw: u128,
+        as_number: bool,
+    ) -> js::Result<js::Value> {
+        let number = js::Value::from_f64(ctx, raw as f64 / self.accuracy() as f64);
+        if as_number {
+            return Ok(number);
+        }
+        let raw = match self {
+            FixedPoint::FixedU128 => raw.to_js_value(ctx)?,
+            _ => (raw as u32).to_js_value(ctx)?,
+        };
+        let out = ctx.new_object("");
+        out.set_property("raw", &raw)?;
+        out.set_property("asNumber", &number)?;
+        Ok(out)
+    }
+}
@@ -0,0 +1,217 @@
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::time::Duration;
+
+use js::{ErrorContext, FromJsValue};
+
+use super::{Id, IdInfo};
+
+/// Receives a record of every encode and decode call made through the `scl` functions installed
+/// by [`super::setup_with_metrics`].
+pub trait MetricsCollector {
+    fn record_encode(&self, type_name: &str, bytes: usize, duration: Duration);
+    fn record_decode(&self, type_name: &str, bytes: usize, duration: Duration);
+    /// `op` is either `"encode"` or `"decode"`. `kind` is the `code` of the error, or its name
+    /// when it has none, e.g. `"ERR_EOF"` or `"TypeError"`.
+    fn record_error(&self, type_name: &str, op: &'static str, kind: &str);
+    /// The metrics collected so far, returned to scripts by `scl.__metrics()`.
+    fn snapshot(&self) -> Option<MetricsSnapshot> {
+        None
+    }
+    /// Called when the context the collector is installed in is destroyed, or when another
+    /// collector replaces it, to report what the collector buffers before it is dropped.
+    fn flush(&self) {}
+}
+
+/// Aggregated codec metrics.
+#[derive(Debug, Clone, Default, PartialEq, Eq, js::ToJsValue)]
+#[qjs(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    pub encode_calls: u64,
+    pub decode_calls: u64,
+    pub bytes_encoded: u64,
+    pub bytes_decoded: u64,
+    /// Number of failed calls by kind of error, see [`MetricsCollector::record_error`].
+    pub errors: BTreeMap<String, u64>,
+    /// The slowest call of each type, in microseconds, slowest first.
+    pub slowest: Vec<(String, u64)>,
+}
+
+/// A collector keeping counters in memory.
+///
+/// Durations are measured with the monotonic clock when the `std` feature is enabled and are zero
+/// otherwise.
+#[derive(Default)]
+pub struct ScaleMetrics {
+    state: RefCell<MetricsSnapshot>,
+    slowest: RefCell<BTreeMap<String, Duration>>,
+}
+
+impl ScaleMetrics {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self::default())
+    }
+
+    fn record_duration(&self, type_name: &str, duration: Duration) {
+        let mut slowest = self.slowest.borrow_mut();
+        match slowest.get_mut(type_name) {
+            Some(max) => *max = (*max).max(duration),
+            None => {
+                slowest.insert(type_name.to_string(), duration);
+            }
+        }
+    }
+}
+
+impl MetricsCollector for ScaleMetrics {
+    fn record_encode(&self, type_name: &str, bytes: usize, duration: Duration) {
+        let mut state = self.state.borrow_mut();
+        state.encode_calls += 1;
+        state.bytes_encoded += bytes as u64;
+        self.record_duration(type_name, duration);
+    }
+
+    fn record_decode(&self, type_name: &str, bytes: usize, duration: Duration) {
+        let mut state = self.state.borrow_mut();
+        state.decode_calls += 1;
+        state.bytes_decoded += bytes as u64;
+        self.record_duration(type_name, duration);
+    }
+
+    fn record_error(&self, _type_name: &str, _op: &'static str, kind: &str) {
+        *self
+            .state
+            .borrow_mut()
+            .errors
+            .entry(kind.to_string())
+            .or_default() += 1;
+    }
+
+    fn snapshot(&self) -> Option<MetricsSnapshot> {
+        let mut snapshot = self.state.borrow().clone();
+        let mut slowest: Vec<_> = self
+            .slowest
+            .borrow()
+            .iter()
+            .map(|(name, duration)| (name.clone(), duration.as_micros() as u64))
+            .collect();
+        slowest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        snapshot.slowest = slowest;
+        Some(snapshot)
+    }
+}
+
+type Collector = Rc<RefCell<Option<Rc<dyn MetricsCollector>>>>;
+
+fn collector_slot(ctx: &js::Context) -> js::Result<js::Value> {
+    ctx.get_qjsbind_object("scaleMetrics", || {
+        let collector = Collector::default();
+        // Registered once with the slot, flushing whichever collector is installed last.
+        ctx.on_destroy({
+            let collector = collector.clone();
+            move |_| {
+                let current = collector.borrow_mut().take();
+                if let Some(current) = current {
+                    current.flush();
+                }
+            }
+        });
+        Ok(js::Value::new_opaque_object(
+            ctx,
+            Some("ScaleMetrics"),
+            collector,
+        ))
+    })
+}
+
+/// Install `collector`, flushing the one it replaces.
+pub(super) fn set_collector(
+    ctx: &js::Context,
+    collector: Rc<dyn MetricsCollector>,
+) -> js::Result<()> {
+    let slot = collector_slot(ctx)?;
+    let data = slot.opaque_object_data::<Collector>();
+    let previous = data
+        .get()
+        .context("invalid metrics collector")?
+        .borrow_mut()
+        .replace(collector);
+    if let Some(previous) = previous {
+        previous.flush();
+    }
+    Ok(())
+}
+
+pub(super) fn collector(ctx: &js::Context) -> js::Result<Rc<dyn MetricsCollector>> {
+    let slot = collector_slot(ctx)?;
+    let data = slot.opaque_object_data::<Collector>();
+    let cell = data.get().context("invalid metrics collector")?;
+    let collector = cell.borrow().clone();
+    collector.context("no metrics collector configured")
+}
+
+#[derive(Clone, Copy)]
+pub(super) enum Op {
+    Encode,
+    Decode,
+}
+
+/// Run `f`, which returns its result and the number of bytes processed, and record the call.
+pub(super) fn measure<T>(
+    ctx: &js::Context,
+    op: Op,
+    type_name: impl FnOnce() -> String,
+    f: impl FnOnce() -> js::Result<(T, usize)>,
+) -> js::Result<T> {
+    let collector = collector(ctx)?;
+    let start = now();
+    let result = f();
+    let duration = now().saturating_sub(start);
+    let type_name = type_name();
+    match (&result, op) {
+        (Ok((_, bytes)), Op::Encode) => collector.record_encode(&type_name, *bytes, duration),
+        (Ok((_, bytes)), Op::Decode) => collector.record_decode(&type_name, *bytes, duration),
+        (Err(err), Op::Encode) => {
+            collector.record_error(&type_name, "encode", &error_kind(ctx, err))
+        }
+        (Err(err), Op::Decode) => {
+            collector.record_error(&type_name, "decode", &error_kind(ctx, err))
+        }
+    }
+    result.map(|(value, _)| value)
+}
+
+/// The `code` of a [`js::JsError`], its name when it has no code, or `"Error"` for other errors.
+fn error_kind(ctx: &js::Context, err: &js::Error) -> String {
+    let Some(err) = err.downcast_ref::<js::JsError>() else {
+        return "Error".to_string();
+    };
+    err.properties
+        .iter()
+        .find(|(key, _)| key == "code")
+        .and_then(|(_, code)| String::from_js_value(code.to_js_value(ctx).ok()?).ok())
+        .unwrap_or_else(|| err.name.clone())
+}
+
+/// The name a type is reported under: its registry name, or `#n` for type ids given by index.
+pub(super) fn type_name(id: &Id) -> String {
+    match &id.info {
+        IdInfo::Name(name) => name.to_string(),
+        IdInfo::Num(n) => alloc::format!("#{n}"),
+        IdInfo::Type(_) => "<anonymous>".to_string(),
+    }
+}
+
+#[cfg(feature = "std")]
+fn now() -> Duration {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START.get_or_init(std::time::Instant::now).elapsed()
+}
+
+#[cfg(not(feature = "std"))]
+fn now() -> Duration {
+    Duration::ZERO
+}
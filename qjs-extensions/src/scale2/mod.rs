@@ -1,5 +1,5 @@
 use alloc::borrow::Cow;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::String;
 use alloc::{rc::Rc, vec::Vec};
 use anyhow::{anyhow, bail};
@@ -8,47 +8,252 @@ use parity_scale_codec::{Compact, Decode, Encode, Output};
 
 use js::{self as js, AsBytes, BytesOrHex, ErrorContext, FromJsValue, JsResultExt, ToJsValue};
 
-use self::parser::{Enum, Id, IdInfo, PrimitiveType, String as TinyString, Type, TypeDef};
+pub use self::bridge::{decode_from_js, decode_to_js, to_scale, ScaleJsType};
+pub use self::extrinsic::{unwrap_extrinsic, wrap_extrinsic, Era};
+pub use self::fixed::FixedPoint;
+use self::metrics::{measure, type_name, Op};
+pub use self::metrics::{MetricsCollector, MetricsSnapshot, ScaleMetrics};
+use self::parser::{
+    Enum, Id, IdInfo, PrimitiveType, String as TinyString, Type, TypeDef, TypeName,
+};
+pub use self::spec::{TypeRef, TypeSpec};
+pub use self::wide::Wide;
+/// `#[derive(ScaleJsType)]`, see [`ScaleJsType`].
+pub use js::ScaleJsType;
 
+mod bridge;
+mod extrinsic;
+mod fixed;
+mod metrics;
 mod parser;
+#[cfg(any(test, feature = "fuzzing"))]
+#[doc(hidden)]
+pub mod roundtrip;
+mod spec;
+mod stream;
+mod wide;
 
 pub fn setup(obj: &js::Value, ctx: &js::Context) -> js::Result<()> {
     obj.define_property_fn("parseTypes", parse_types)?;
     obj.define_property_fn("appendTypes", append_types)?;
+    obj.define_property_fn("childRegistry", child_registry)?;
+    obj.define_property_fn("registerAlias", register_alias)?;
     obj.define_property_fn("builtinTypes", builtin_types)?;
     obj.define_property_fn("encode", encode)?;
     obj.define_property_fn("encodeAll", encode_all)?;
     obj.define_property_fn("decode", decode)?;
     obj.define_property_fn("decodeAll", decode_all)?;
+    obj.define_property_fn("validate", validate)?;
+    obj.define_property_fn("decoder", stream::decoder)?;
     obj.define_property_fn("codec", codec)?;
-    ctx.eval(&js::Code::Bytecode(qjsc::compiled!(
-        r#"globalThis.ScaleCodec = {
-            encode(value) {
-                const encoder = this.isArray ? this.scl.encodeAll : this.scl.encode;
-                return encoder(value, this.ty, this.registry);
-            },
-            decode(value) {
-                const decoder = this.isArray ? this.scl.decodeAll : this.scl.decode;
-                return decoder(value, this.ty, this.registry);
-            },
-        };"#
-    )))
-    .map_err(js::Error::msg)?;
+    obj.define_property_fn("encodeEra", extrinsic::encode_era)?;
+    obj.define_property_fn("decodeEra", extrinsic::decode_era)?;
+    obj.define_property_fn("wrapExtrinsic", extrinsic::wrap)?;
+    obj.define_property_fn("unwrapExtrinsic", extrinsic::unwrap)?;
+    // Host functions rather than an evaluated script: QuickJS runs bytecode in the realm it was
+    // loaded in, so a compiled prototype could not be shared between contexts anyway.
+    let codec_proto = ctx.new_object("");
+    codec_proto.define_property_fn("encode", codec_encode)?;
+    codec_proto.define_property_fn("decode", codec_decode)?;
+    codec_proto.set_property("scl", obj)?;
     ctx.get_global_object()
-        .get_property("ScaleCodec")?
-        .set_property("scl", obj)?;
+        .set_property("ScaleCodec", &codec_proto)?;
+    Ok(())
+}
+
+/// The state shared by the contexts of a runtime, see [`setup_runtime`].
+struct RuntimeCache {
+    std: Registry,
+}
+
+/// Parse the builtin types once for `runtime`. Registries created in its contexts then start
+/// from a copy of them instead of parsing them again, which is most of the cost of setting up
+/// a context and of `parseTypes`.
+///
+/// Optional: without it every registry parses the builtin types itself.
+///
+/// ```ignore
+/// scale2::setup_runtime(&runtime)?;
+/// for _ in 0..n {
+///     let ctx = runtime.new_context();
+///     let scale = scale2::setup_context(&ctx)?;
+///     ctx.get_global_object().set_property("Scale", &scale)?;
+/// }
+/// ```
+pub fn setup_runtime(runtime: &js::Runtime) -> js::Result<()> {
+    if runtime.user_data::<RuntimeCache>().is_none() {
+        runtime.set_user_data(RuntimeCache {
+            std: Registry::std()?,
+        });
+    }
     Ok(())
 }
 
+/// Create a `Scale` namespace for `ctx` and install `ScaleCodec`, like [`setup`] on a new object.
+pub fn setup_context(ctx: &js::Context) -> js::Result<js::Value> {
+    let obj = ctx.new_object("Scale");
+    setup(&obj, ctx)?;
+    Ok(obj)
+}
+
+/// `ScaleCodec.encode`, dispatching to `scl.encode` or `scl.encodeAll` of the codec.
+#[js::host_call(with_context)]
+fn codec_encode(_ctx: js::Context, this: js::Value, value: js::Value) -> js::Result<js::Value> {
+    call_codec(&this, "encode", "encodeAll", value)
+}
+
+/// `ScaleCodec.decode`, dispatching to `scl.decode` or `scl.decodeAll` of the codec.
+#[js::host_call(with_context)]
+fn codec_decode(_ctx: js::Context, this: js::Value, value: js::Value) -> js::Result<js::Value> {
+    call_codec(&this, "decode", "decodeAll", value)
+}
+
+/// Look the function up on `scl` on each call, so that the measured functions installed by
+/// [`setup_with_metrics`] are used.
+fn call_codec(
+    this: &js::Value,
+    single: &str,
+    all: &str,
+    value: js::Value,
+) -> js::Result<js::Value> {
+    let is_array = this.get_property("isArray")?.decode_bool().unwrap_or(false);
+    let f = this
+        .get_property("scl")?
+        .get_property(if is_array { all } else { single })?;
+    let args = [
+        value,
+        this.get_property("ty")?,
+        this.get_property("registry")?,
+        this.get_property("hooks")?,
+    ];
+    f.call(&js::Value::undefined(), &args)
+}
+
+/// Like [`setup`], but every encode and decode call is reported to `collector`, and
+/// `scl.__metrics()` returns the collector's snapshot.
+///
+/// ```ignore
+/// let metrics = ScaleMetrics::new();
+/// scale2::setup_with_metrics(&obj, &ctx, metrics.clone())?;
+/// // run scripts
+/// let snapshot = metrics.snapshot();
+/// ```
+pub fn setup_with_metrics(
+    obj: &js::Value,
+    ctx: &js::Context,
+    collector: Rc<dyn MetricsCollector>,
+) -> js::Result<()> {
+    setup(obj, ctx)?;
+    metrics::set_collector(ctx, collector)?;
+    obj.define_property_fn("encode", measured_encode)?;
+    obj.define_property_fn("encodeAll", measured_encode_all)?;
+    obj.define_property_fn("decode", measured_decode)?;
+    obj.define_property_fn("decodeAll", measured_decode_all)?;
+    obj.define_property_fn("__metrics", dump_metrics)?;
+    Ok(())
+}
+
+fn type_names(tids: &[Id]) -> String {
+    tids.iter().map(type_name).collect::<Vec<_>>().join(",")
+}
+
+#[js::host_call(with_context)]
+fn measured_encode(
+    ctx: js::Context,
+    _this: js::Value,
+    value: js::Value,
+    tid: Id,
+    type_registry: TypeRegistry,
+    hooks: Hooks,
+) -> js::Result<js::Value> {
+    let out = measure(
+        &ctx,
+        Op::Encode,
+        || type_name(&tid),
+        || {
+            let out = encode_one(value, &tid, &type_registry, &hooks)?;
+            let len = out.len();
+            Ok((out, len))
+        },
+    )?;
+    js::Value::from_bytes_owned(&ctx, out)
+}
+
+#[js::host_call(with_context)]
+fn measured_encode_all(
+    ctx: js::Context,
+    _this: js::Value,
+    value: js::Value,
+    tids: Vec<Id>,
+    type_registry: TypeRegistry,
+    hooks: Hooks,
+) -> js::Result<js::Value> {
+    let out = measure(
+        &ctx,
+        Op::Encode,
+        || type_names(&tids),
+        || {
+            let out = encode_each(&value, &tids, &type_registry, &hooks)?;
+            let len = out.len();
+            Ok((out, len))
+        },
+    )?;
+    js::Value::from_bytes_owned(&ctx, out)
+}
+
+#[js::host_call(with_context)]
+fn measured_decode(
+    ctx: js::Context,
+    _this: js::Value,
+    value: js::JsUint8Array,
+    tid: Id,
+    type_registry: TypeRegistry,
+    hooks: Hooks,
+) -> js::Result<js::Value> {
+    measure(
+        &ctx,
+        Op::Decode,
+        || type_name(&tid),
+        || {
+            let mut buf = value.as_bytes();
+            let decoded = decode_with(&ctx, &mut buf, &tid, &type_registry.borrow(), &hooks)?;
+            Ok((decoded, value.len() - buf.len()))
+        },
+    )
+}
+
+#[js::host_call(with_context)]
+fn measured_decode_all(
+    ctx: js::Context,
+    _this: js::Value,
+    value: js::JsUint8Array,
+    tids: Vec<Id>,
+    type_registry: TypeRegistry,
+    hooks: Hooks,
+) -> js::Result<Vec<js::Value>> {
+    measure(
+        &ctx,
+        Op::Decode,
+        || type_names(&tids),
+        || {
+            let mut buf = value.as_bytes();
+            let out = decode_each(&ctx, &mut buf, &tids, &type_registry, &hooks)?;
+            Ok((out, value.len() - buf.len()))
+        },
+    )
+}
+
+#[js::host_call(with_context)]
+fn dump_metrics(ctx: js::Context, _this: js::Value) -> js::Result<Option<MetricsSnapshot>> {
+    Ok(metrics::collector(&ctx)?.snapshot())
+}
+
 impl js::FromJsValue for Id {
     fn from_js_value(js_value: js::Value) -> js::Result<Self> {
-        if js_value.is_string() {
-            let name = js::JsString::from_js_value(js_value)?;
-            Ok(Id::from(name.as_str()))
-        } else {
-            let ind = js_value.decode_u32()?;
-            Ok(Id::from(ind))
-        }
+        js::Overloaded::new(core::slice::from_ref(&js_value))
+            .arm(|(name,): (js::JsString,)| Ok(Id::from(name.as_str())))
+            .arm(|(ind,): (u32,)| Ok(Id::from(ind)))
+            .finish()
     }
 }
 
@@ -85,24 +290,303 @@ impl Enum {
     }
 }
 
+/// The callbacks of `scl.codec(ty, registry, {preEncode, postDecode})`.
+///
+/// Each is called with a value and the name of its type for every type defined with a name,
+/// `preEncode` before the value is encoded and `postDecode` after it is decoded. What it returns
+/// is encoded, or decoded, in place of the value. Types written inline are not hooked.
+#[derive(Default)]
+struct Hooks {
+    pre_encode: Option<js::Value>,
+    post_decode: Option<js::Value>,
+}
+
+impl js::FromJsValue for Hooks {
+    fn from_js_value(value: js::Value) -> js::Result<Self> {
+        if value.is_null_or_undefined() {
+            return Ok(Self::default());
+        }
+        let hook = |name: &str| -> js::Result<Option<js::Value>> {
+            let f = value.get_property(name)?;
+            if f.is_null_or_undefined() {
+                return Ok(None);
+            }
+            if !f.is_function() {
+                bail!("codec hook {name} must be a function");
+            }
+            Ok(Some(f))
+        };
+        Ok(Self {
+            pre_encode: hook("preEncode")?,
+            post_decode: hook("postDecode")?,
+        })
+    }
+}
+
+impl Hooks {
+    fn pre_encode(&self, value: js::Value, tid: &Id, registry: &Registry) -> js::Result<js::Value> {
+        Self::apply(self.pre_encode.as_ref(), "preEncode", value, tid, registry)
+    }
+
+    fn post_decode(
+        &self,
+        value: js::Value,
+        tid: &Id,
+        registry: &Registry,
+    ) -> js::Result<js::Value> {
+        Self::apply(
+            self.post_decode.as_ref(),
+            "postDecode",
+            value,
+            tid,
+            registry,
+        )
+    }
+
+    fn apply(
+        hook: Option<&js::Value>,
+        hook_name: &str,
+        value: js::Value,
+        tid: &Id,
+        registry: &Registry,
+    ) -> js::Result<js::Value> {
+        let Some(hook) = hook else {
+            return Ok(value);
+        };
+        let Some(name) = registry.defined_name(tid) else {
+            return Ok(value);
+        };
+        let name_value = name.to_js_value(hook.context()?)?;
+        hook.call(&js::Value::undefined(), &[value, name_value])
+            .context(alloc::format!("{hook_name} hook of {name} failed"))
+    }
+}
+
 #[derive(Debug, Clone, FromJsValue, Default)]
 #[qjs(default)]
 struct ParseOptions {
     #[qjs(default)]
     no_std: bool,
+    /// Decode the fixed-point types to plain numbers instead of `{raw, asNumber}`.
+    #[qjs(default)]
+    fixed_as_number: bool,
+    /// Fall back to a case-insensitive match for unknown type names.
+    #[qjs(default)]
+    case_insensitive: bool,
+    /// Queue the types appended to the registry while it is in use instead of failing.
+    #[qjs(default)]
+    defer_reentrant: bool,
 }
 
+/// A set of SCALE type definitions, shared with scripts via `ToJsValue`.
+///
+/// Types can be added from the DSL with `append` or built directly in Rust:
+///
+/// ```ignore
+/// let registry = TypeRegistry::new()?;
+/// registry
+///     .define("AccountId", TypeSpec::array("u8", 32))?
+///     .define_enum("Message", [("Ping", None), ("Data", Some("Vec<u8>".into()))])?
+///     .define_struct("Transfer", [("to", "AccountId".into()), ("amount", "u128".into())])?;
+/// ```
+///
+/// The methods changing the registry fail with [`js::Reentrancy`] while it is in use, e.g. when
+/// a `preEncode` hook appends types to the registry encoding its value, unless
+/// [`set_defer_reentrant`](Self::set_defer_reentrant) is enabled.
 #[derive(Debug, Clone)]
-struct TypeRegistry {
+pub struct TypeRegistry {
     inner: Rc<RefCell<Registry>>,
 }
 
 impl TypeRegistry {
-    fn borrow(&self) -> Ref<'_, Registry> {
-        (*self.inner).borrow()
+    /// Create a registry with the builtin types.
+    pub fn new() -> js::Result<Self> {
+        Ok(Registry::std()?.into())
+    }
+
+    /// Create a registry without the builtin types.
+    pub fn no_std() -> Self {
+        Registry::no_std().into()
+    }
+
+    /// Create an empty registry layered over `base`: names are looked up in the child first, then
+    /// in `base`, and appended or defined types go to the child only.
+    ///
+    /// The types of `base` are shared rather than copied, so many children over a large base,
+    /// like per-chain overrides over common metadata, cost little more than the base. They see
+    /// the types `base` had when they were created. `base` stays usable, what is appended to it
+    /// later is only visible to it and to the children created after.
+    pub fn with_parent(base: &TypeRegistry) -> Self {
+        let (fixed_as_number, case_insensitive, defer_reentrant) = {
+            let base = base.borrow();
+            (
+                base.fixed_as_number,
+                base.case_insensitive,
+                base.defer_reentrant,
+            )
+        };
+        let mut child = Registry::child(base.share());
+        child.fixed_as_number = fixed_as_number;
+        child.case_insensitive = case_insensitive;
+        child.defer_reentrant = defer_reentrant;
+        child.into()
+    }
+
+    /// The types of the registry as a layer to share with children. They are moved to the
+    /// layer, which the registry then extends, so they are not copied, unless the registry is in
+    /// use.
+    fn share(&self) -> Rc<Registry> {
+        let Ok(mut inner) = self.inner.try_borrow_mut() else {
+            let mut layer = self.borrow().clone();
+            layer.deferred = Default::default();
+            return Rc::new(layer);
+        };
+        if inner.types.is_empty() {
+            if let Some(parent) = &inner.parent {
+                return parent.clone();
+            }
+        }
+        let layer = Rc::new(core::mem::replace(&mut *inner, Registry::no_std()));
+        let fixed_as_number = layer.fixed_as_number;
+        *inner = Registry::child(layer.clone());
+        inner.fixed_as_number = fixed_as_number;
+        layer
+    }
+
+    /// Decode the fixed-point types, `Perbill` and the like, to plain numbers instead of
+    /// `{raw, asNumber}`. The number may lose precision for `FixedU128`.
+    pub fn set_fixed_as_number(&self, as_number: bool) -> js::Result<&Self> {
+        self.borrow_mut("set_fixed_as_number")?.fixed_as_number = as_number;
+        Ok(self)
+    }
+
+    /// Look up the type names missing from the registry case-insensitively, so that `accountid`
+    /// finds `AccountId`. Exact matches still win, and a name matching several types differing
+    /// only in case is an error listing them.
+    pub fn set_case_insensitive(&self, case_insensitive: bool) -> js::Result<&Self> {
+        self.borrow_mut("set_case_insensitive")?.case_insensitive = case_insensitive;
+        Ok(self)
+    }
+
+    /// Queue the types appended, defined or aliased while the registry is in use instead of
+    /// failing. They are added once the registry is no longer in use, so the operation in
+    /// progress does not see them.
+    pub fn set_defer_reentrant(&self, defer: bool) -> js::Result<&Self> {
+        self.borrow_mut("set_defer_reentrant")?.defer_reentrant = defer;
+        Ok(self)
+    }
+
+    /// Make `alias` another name of the type named `target`, which may itself be an alias.
+    ///
+    /// Fails if `target` is unknown or is an alias of `alias`.
+    pub fn define_alias(&self, alias: &str, target: &str) -> js::Result<&Self> {
+        let inner = self.borrow();
+        let mut tid = Id::from(target);
+        loop {
+            if matches!(&tid.info, IdInfo::Name(name) if name.as_str() == alias) {
+                bail!("alias {alias} of {target} would be cyclic");
+            }
+            let ty = inner.get_type_shallow(&tid)?.into_owned();
+            match ty {
+                Type::Alias(next) => tid = next,
+                _ => break,
+            }
+        }
+        drop(inner);
+        self.add(
+            "define_alias",
+            alloc::vec![named_type(alias, Type::Alias(Id::from(target)))],
+        )?;
+        Ok(self)
+    }
+
+    /// Append the type definitions written in the DSL.
+    pub fn append(&self, typelist: &str) -> js::Result<()> {
+        let ast = parser::parse_types(typelist)?;
+        self.add("append", ast)
+    }
+
+    /// Define a named type. A later definition with the same name shadows the earlier one.
+    pub fn define(&self, name: &str, ty: TypeSpec) -> js::Result<&Self> {
+        self.add("define", alloc::vec![named_type(name, ty.0)])?;
+        Ok(self)
+    }
+
+    /// Define an enum from its variants, indexed in order.
+    pub fn define_enum<'a>(
+        &self,
+        name: &str,
+        variants: impl IntoIterator<Item = (&'a str, Option<TypeRef>)>,
+    ) -> js::Result<&Self> {
+        self.define(name, TypeSpec::enumeration(variants))
+    }
+
+    /// Define a struct from its fields, encoded in order.
+    pub fn define_struct<'a>(
+        &self,
+        name: &str,
+        fields: impl IntoIterator<Item = (&'a str, TypeRef)>,
+    ) -> js::Result<&Self> {
+        self.define(name, TypeSpec::structure(fields))
     }
-    fn borrow_mut(&self) -> RefMut<'_, Registry> {
-        (*self.inner).borrow_mut()
+
+    /// Add `defs`, or queue them if the registry is in use and deferring is enabled.
+    fn add(&self, operation: &'static str, defs: Vec<TypeDef>) -> js::Result<()> {
+        if let Ok(mut inner) = self.inner.try_borrow_mut() {
+            inner.append(defs);
+            return Ok(());
+        }
+        match self.inner.try_borrow() {
+            Ok(inner) if inner.defer_reentrant => {
+                inner.deferred.borrow_mut().extend(defs);
+                Ok(())
+            }
+            _ => Err(reentrancy(operation)),
+        }
+    }
+
+    fn borrow(&self) -> Reading<'_> {
+        Reading {
+            registry: Some((*self.inner).borrow()),
+            inner: &self.inner,
+        }
+    }
+    fn borrow_mut(&self, operation: &'static str) -> js::Result<RefMut<'_, Registry>> {
+        self.inner
+            .try_borrow_mut()
+            .map_err(|_| reentrancy(operation))
+    }
+}
+
+fn reentrancy(operation: &'static str) -> js::Error {
+    js::Error::msg(js::Reentrancy {
+        type_name: "TypeRegistry",
+        operation,
+    })
+}
+
+/// A shared borrow of a registry. The types deferred while the registry was in use are added
+/// when the last one is released.
+struct Reading<'a> {
+    registry: Option<Ref<'a, Registry>>,
+    inner: &'a RefCell<Registry>,
+}
+
+impl core::ops::Deref for Reading<'_> {
+    type Target = Registry;
+
+    fn deref(&self) -> &Registry {
+        self.registry.as_ref().expect("BUG: registry released")
+    }
+}
+
+impl Drop for Reading<'_> {
+    fn drop(&mut self) {
+        self.registry = None;
+        if let Ok(mut registry) = self.inner.try_borrow_mut() {
+            let deferred = core::mem::take(registry.deferred.get_mut());
+            registry.append(deferred);
+        }
     }
 }
 
@@ -163,9 +647,19 @@ impl<'a> GenericLookup<'a> {
         }
     }
 
+    fn resolve_fields(&self, fields: &[(TinyString, Id)]) -> js::Result<Vec<(TinyString, Id)>> {
+        fields
+            .iter()
+            .map(|(name, tid)| {
+                let ty = self.resolve_tid(tid)?;
+                Ok((name.clone(), ty.into_owned()))
+            })
+            .collect()
+    }
+
     fn resolve_type<'b>(&self, ty: &'b Type) -> js::Result<Cow<'b, Type>> {
         match ty {
-            Type::Primitive(_) => Ok(Cow::Borrowed(ty)),
+            Type::Primitive(_) | Type::Fixed(_) | Type::Wide(_) => Ok(Cow::Borrowed(ty)),
             Type::Compact(_) => Ok(Cow::Borrowed(ty)),
             Type::Seq(tid) => {
                 let tid = self.resolve_tid(tid)?;
@@ -207,16 +701,13 @@ impl<'a> GenericLookup<'a> {
                     .collect::<js::Result<Vec<_>>>()?;
                 Ok(Cow::Owned(Type::Enum(Enum { variants })))
             }
-            Type::Struct(fields) => {
-                let fields = fields
-                    .iter()
-                    .map(|(name, tid)| {
-                        let ty = self.resolve_tid(tid)?;
-                        Ok((name.clone(), ty.into_owned()))
-                    })
-                    .collect::<js::Result<Vec<_>>>()?;
-                Ok(Cow::Owned(Type::Struct(fields)))
-            }
+            Type::Struct(fields, defaults) => Ok(Cow::Owned(Type::Struct(
+                self.resolve_fields(fields)?,
+                defaults.clone(),
+            ))),
+            Type::LabeledTuple(elements) => Ok(Cow::Owned(Type::LabeledTuple(
+                self.resolve_fields(elements)?,
+            ))),
             Type::Alias(id) => {
                 let id = self.resolve_tid(id)?;
                 if matches!(id, Cow::Borrowed(_)) {
@@ -228,17 +719,53 @@ impl<'a> GenericLookup<'a> {
     }
 }
 
+fn named_type(name: &str, ty: Type) -> TypeDef {
+    TypeDef {
+        name: TypeName::new(name.into(), Vec::new()),
+        ty,
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Registry {
     n_builtin: usize,
+    /// The registry this one extends, for the names and indices not defined here.
+    parent: Option<Rc<Registry>>,
+    /// The number of types of the parents, which `types` are indexed after.
+    offset: usize,
     types: Vec<TypeDef>,
+    /// The indices of the named types, counting the types of the parents.
     lookup: BTreeMap<TinyString, usize>,
+    fixed_as_number: bool,
+    case_insensitive: bool,
+    defer_reentrant: bool,
+    /// The types added while the registry was in use, see `TypeRegistry::set_defer_reentrant`.
+    deferred: RefCell<Vec<TypeDef>>,
 }
 
 impl Registry {
     const fn no_std() -> Self {
         Self {
             n_builtin: 0,
+            parent: None,
+            offset: 0,
+            types: Vec::new(),
+            lookup: BTreeMap::new(),
+            fixed_as_number: false,
+            case_insensitive: false,
+            defer_reentrant: false,
+            deferred: RefCell::new(Vec::new()),
+        }
+    }
+    fn child(parent: Rc<Registry>) -> Self {
+        Self {
+            n_builtin: parent.n_builtin,
+            offset: parent.len(),
+            fixed_as_number: parent.fixed_as_number,
+            case_insensitive: parent.case_insensitive,
+            defer_reentrant: parent.defer_reentrant,
+            deferred: RefCell::new(Vec::new()),
+            parent: Some(parent),
             types: Vec::new(),
             lookup: BTreeMap::new(),
         }
@@ -246,12 +773,27 @@ impl Registry {
     fn std() -> js::Result<Self> {
         Self::new(false)
     }
+    /// The builtin types, copied from the runtime of `ctx` if it went through [`setup_runtime`].
+    fn std_in(ctx: &js::Context) -> js::Result<Self> {
+        match ctx.runtime_user_data::<RuntimeCache>() {
+            Some(cache) => Ok(cache.std.clone()),
+            None => Self::std(),
+        }
+    }
     fn new(no_std: bool) -> js::Result<Self> {
         let mut me = Self::no_std();
         if !no_std {
+            #[cfg(test)]
+            tests::STD_PARSES.with(|n| n.set(n.get() + 1));
             let ast = parser::parse_types(BUILTIN_TYPES)?;
-            me.append(ast)?;
-            me.n_builtin = me.types.len();
+            me.append(ast);
+            for fixed in FixedPoint::ALL {
+                me.define(fixed.name(), Type::Fixed(fixed));
+            }
+            for wide in Wide::ALL {
+                me.define(wide.name(), Type::Wide(wide));
+            }
+            me.n_builtin = me.len();
         }
         Ok(me)
     }
@@ -260,14 +802,80 @@ impl Registry {
         self.n_builtin + id as usize
     }
 
-    fn append(&mut self, typelist: Vec<parser::TypeDef>) -> js::Result<()> {
+    /// The number of types, counting the ones of the parents.
+    fn len(&self) -> usize {
+        self.offset + self.types.len()
+    }
+
+    fn type_at(&self, ind: usize) -> Option<&TypeDef> {
+        match ind.checked_sub(self.offset) {
+            Some(ind) => self.types.get(ind),
+            None => self.parent.as_ref()?.type_at(ind),
+        }
+    }
+
+    /// The type defined as `name`, here or in the parents.
+    fn find(&self, name: &TinyString) -> Option<&TypeDef> {
+        match self.lookup.get(name) {
+            Some(ind) => self.type_at(*ind),
+            None => self.parent.as_ref()?.find(name),
+        }
+    }
+
+    /// The names of the types, here and in the parents.
+    fn names(&self) -> BTreeSet<&str> {
+        let mut names = match &self.parent {
+            Some(parent) => parent.names(),
+            None => BTreeSet::new(),
+        };
+        names.extend(self.lookup.keys().map(|name| name.as_str()));
+        names
+    }
+
+    /// Look up a name missing from the registry: case-insensitively if enabled, and otherwise
+    /// fail with the names close to it.
+    fn find_unknown(&self, name: &str) -> js::Result<&TypeDef> {
+        let names = self.names();
+        if self.case_insensitive {
+            let folded: Vec<&str> = names
+                .iter()
+                .copied()
+                .filter(|known| known.eq_ignore_ascii_case(name))
+                .collect();
+            match folded[..] {
+                [] => {}
+                [known] => return Ok(self.find(&known.into()).expect("BUG: listed name")),
+                _ => {
+                    return Err(unknown_type(
+                        name,
+                        alloc::format!("ambiguous type {name}, it matches {}", folded.join(", ")),
+                    ))
+                }
+            }
+        }
+        let name_lower = name.to_ascii_lowercase();
+        let near: Vec<&str> = names
+            .into_iter()
+            .filter(|known| edit_distance(&known.to_ascii_lowercase(), &name_lower) <= 2)
+            .collect();
+        let message = match near[..] {
+            [] => alloc::format!("unknown type {name}"),
+            _ => alloc::format!("unknown type {name}, did you mean {}?", near.join(" or ")),
+        };
+        Err(unknown_type(name, message))
+    }
+
+    fn append(&mut self, typelist: Vec<parser::TypeDef>) {
         for def in typelist.into_iter() {
             if let Some(name) = def.name.name.clone() {
-                self.lookup.insert(name, self.types.len());
+                self.lookup.insert(name, self.len());
             }
             self.types.push(def);
         }
-        Ok(())
+    }
+
+    fn define(&mut self, name: &str, ty: Type) {
+        self.append(alloc::vec![named_type(name, ty)]);
     }
 
     fn resolve_generic<'a>(&self, tid: &Id, def: &'a TypeDef) -> js::Result<Cow<'a, Type>> {
@@ -288,20 +896,16 @@ impl Registry {
 
     fn get_type_shallow<'a>(&'a self, tid: &'a Id) -> js::Result<Cow<'a, Type>> {
         let def = match &tid.info {
-            IdInfo::Name(name) => {
-                let Some(id) = self.lookup.get(name) else {
-                    return match Type::primitive(name.as_str()) {
-                        Some(prim) => Ok(Cow::Borrowed(prim)),
-                        None => bail!("unknown type {name}"),
-                    };
-                };
-                self.types
-                    .get(*id)
-                    .ok_or(anyhow!("unknown type id of {name}"))?
-            }
+            IdInfo::Name(name) => match self.find(name) {
+                Some(def) => def,
+                None => match Type::primitive(name.as_str()) {
+                    Some(prim) => return Ok(Cow::Borrowed(prim)),
+                    None => self.find_unknown(name.as_str())?,
+                },
+            },
             IdInfo::Num(id) => {
                 let ind = self.id2ind(*id);
-                self.types.get(ind).ok_or(anyhow!("unknown type id {id}"))?
+                self.type_at(ind).ok_or(anyhow!("unknown type id {id}"))?
             }
             IdInfo::Type(ty) => return Ok(Cow::Borrowed(ty)),
         };
@@ -320,13 +924,33 @@ impl Registry {
         Ok(Cow::Owned(t))
     }
 
-    fn resolve_type<'a>(&'a self, tid: &'a Id, fallback: bool) -> js::Result<Cow<'a, Type>> {
-        let result = self.get_type(tid);
-        if result.is_ok() || !fallback {
-            return result;
+    /// The name `tid` refers to a type defined with, `None` for primitives and types written
+    /// inline.
+    fn defined_name<'a>(&'a self, tid: &'a Id) -> Option<&'a str> {
+        match &tid.info {
+            IdInfo::Name(name) => self.find(name).is_some().then_some(name.as_str()),
+            IdInfo::Num(id) => self
+                .type_at(self.id2ind(*id))?
+                .name
+                .name
+                .as_ref()
+                .map(|name| name.as_str()),
+            IdInfo::Type(_) => None,
         }
-        let IdInfo::Name(lit) = &tid.info else {
-            return result;
+    }
+
+    fn resolve_type<'a>(&'a self, tid: &'a Id, fallback: bool) -> js::Result<Cow<'a, Type>> {
+        // Names that are not defined are parsed as types, like `Vec<u8>`, before being looked up
+        // as unknown names.
+        let lit = match &tid.info {
+            IdInfo::Name(lit)
+                if fallback
+                    && self.find(lit).is_none()
+                    && Type::primitive(lit.as_str()).is_none() =>
+            {
+                lit
+            }
+            _ => return self.get_type(tid),
         };
         let ty = parser::parse_type(lit)?;
         if let Type::Alias(id) = ty {
@@ -341,28 +965,25 @@ impl Registry {
 impl js::FromJsValue for TypeRegistry {
     fn from_js_value(value: js::Value) -> js::Result<Self> {
         if value.is_null_or_undefined() {
-            return Ok(Registry::std()?.into());
+            return TypeRegistry::new();
         }
         if value.is_string() {
+            let ctx = value.context()?.clone();
             let typelist = js::JsString::from_js_value(value)?;
-            return parse_types_str(typelist.as_str(), false);
+            return parse_types_str(Some(&ctx), typelist.as_str(), false);
         }
-        let me = value
-            .opaque_object_data::<Self>()
+        let inner = value
+            .opaque_object_data::<Rc<RefCell<Registry>>>()
             .get()
-            .expect_js_value(&value, "TypeRegistry")?
+            .expect_type(&value, "TypeRegistry")?
             .clone();
-        Ok(me)
+        Ok(Self { inner })
     }
 }
 
 impl js::ToJsValue for TypeRegistry {
     fn to_js_value(&self, ctx: &js::Context) -> js::Result<js::Value> {
-        Ok(js::Value::new_opaque_object(
-            ctx,
-            Some("TypeRegistry"),
-            self.clone(),
-        ))
+        js::Value::new_opaque_object_cached(ctx, Some("TypeRegistry"), self.inner.clone())
     }
 }
 
@@ -374,44 +995,288 @@ fn builtin_types() -> String {
     BUILTIN_TYPES.to_string()
 }
 
-#[js::host_call]
-fn parse_types(typelist: js::JsString, options: ParseOptions) -> js::Result<TypeRegistry> {
-    parse_types_str(typelist.as_str(), options.no_std)
+#[js::host_call(with_context)]
+fn parse_types(
+    ctx: js::Context,
+    _this: js::Value,
+    typelist: js::JsString,
+    options: ParseOptions,
+) -> js::Result<TypeRegistry> {
+    let registry = parse_types_str(Some(&ctx), typelist.as_str(), options.no_std)?;
+    registry
+        .set_fixed_as_number(options.fixed_as_number)?
+        .set_case_insensitive(options.case_insensitive)?
+        .set_defer_reentrant(options.defer_reentrant)?;
+    Ok(registry)
 }
 
-fn parse_types_str(typelist: &str, no_std: bool) -> js::Result<TypeRegistry> {
+fn parse_types_str(
+    ctx: Option<&js::Context>,
+    typelist: &str,
+    no_std: bool,
+) -> js::Result<TypeRegistry> {
     let ast = parser::parse_types(typelist)?;
-    let mut registry = Registry::new(no_std)?;
-    registry.append(ast)?;
+    let mut registry = match ctx {
+        Some(ctx) if !no_std => Registry::std_in(ctx)?,
+        _ => Registry::new(no_std)?,
+    };
+    registry.append(ast);
     Ok(registry.into())
 }
 
+/// `scl.registerAlias(registry, alias, target)`, see [`TypeRegistry::define_alias`].
 #[js::host_call]
-fn append_types(type_registry: TypeRegistry, typelist: js::JsString) -> js::Result<()> {
-    let ast = parser::parse_types(typelist.as_str())?;
-    type_registry.borrow_mut().append(ast)?;
+fn register_alias(
+    type_registry: TypeRegistry,
+    alias: js::JsString,
+    target: js::JsString,
+) -> js::Result<()> {
+    type_registry.define_alias(alias.as_str(), target.as_str())?;
     Ok(())
 }
 
 #[js::host_call]
+fn append_types(type_registry: TypeRegistry, typelist: js::JsString) -> js::Result<()> {
+    type_registry.append(typelist.as_str())
+}
+
+/// `scl.childRegistry(base, extraTypes?)`, see [`TypeRegistry::with_parent`].
+#[js::host_call]
+fn child_registry(base: TypeRegistry, typelist: Option<js::JsString>) -> js::Result<TypeRegistry> {
+    let child = TypeRegistry::with_parent(&base);
+    if let Some(typelist) = typelist {
+        child.append(typelist.as_str())?;
+    }
+    Ok(child)
+}
+
+#[js::host_call(with_context)]
 fn encode_all(
+    ctx: js::Context,
+    _this: js::Value,
     value: js::Value,
     tids: Vec<Id>,
     type_registry: TypeRegistry,
-) -> js::Result<AsBytes<Vec<u8>>> {
+    hooks: Hooks,
+) -> js::Result<js::Value> {
+    let out = encode_each(&value, &tids, &type_registry, &hooks)?;
+    js::Value::from_bytes_owned(&ctx, out)
+}
+
+#[js::host_call(with_context)]
+fn encode(
+    ctx: js::Context,
+    _this: js::Value,
+    value: js::Value,
+    tid: Id,
+    type_registry: TypeRegistry,
+    hooks: Hooks,
+) -> js::Result<js::Value> {
+    let out = encode_one(value, &tid, &type_registry, &hooks)?;
+    js::Value::from_bytes_owned(&ctx, out)
+}
+
+/// The body of `scl.encode`, shared with the measured function of [`setup_with_metrics`].
+fn encode_one(
+    value: js::Value,
+    tid: &Id,
+    type_registry: &TypeRegistry,
+    hooks: &Hooks,
+) -> js::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    encode_checked(value, tid, &type_registry.borrow(), &mut out, hooks, None)?;
+    Ok(out)
+}
+
+/// The body of `scl.encodeAll`, shared with the measured function of [`setup_with_metrics`].
+fn encode_each(
+    value: &js::Value,
+    tids: &[Id],
+    type_registry: &TypeRegistry,
+    hooks: &Hooks,
+) -> js::Result<Vec<u8>> {
     let mut out = Vec::new();
     for (ind, tid) in tids.iter().enumerate() {
         let sub_value = value.index(ind as _)?;
-        encode_value(sub_value, tid, &type_registry.borrow(), &mut out)?;
+        encode_checked(
+            sub_value,
+            tid,
+            &type_registry.borrow(),
+            &mut out,
+            hooks,
+            None,
+        )?;
     }
-    Ok(AsBytes(out))
+    Ok(out)
 }
 
-#[js::host_call]
-fn encode(value: js::Value, tid: Id, type_registry: TypeRegistry) -> js::Result<AsBytes<Vec<u8>>> {
+/// Encode `value` as the type `ty`, which is either a type name or a type written in the DSL.
+pub fn encode_value(value: &js::Value, ty: &str, registry: &TypeRegistry) -> js::Result<Vec<u8>> {
     let mut out = Vec::new();
-    encode_value(value, &tid, &type_registry.borrow(), &mut out)?;
-    Ok(AsBytes(out))
+    encode_into(value.clone(), &Id::from(ty), &registry.borrow(), &mut out)?;
+    Ok(out)
+}
+
+/// Check that `value` can be encoded as the type `ty` and return every problem found, with the
+/// path of the value it was found at.
+pub fn validate_value(value: &js::Value, ty: &str, registry: &TypeRegistry) -> Vec<Issue> {
+    validate_into(value.clone(), &Id::from(ty), &registry.borrow())
+}
+
+/// A value that can not be encoded, reported by `scl.validate`.
+#[derive(Debug, Clone, PartialEq, Eq, ToJsValue)]
+pub struct Issue {
+    /// Where the value is, as `votes[1].amount`, empty for the value itself.
+    pub path: String,
+    /// The type the value was expected to be.
+    pub expected: String,
+    /// A short description of the value.
+    pub got: String,
+    /// The error the encoder would have raised.
+    pub message: String,
+}
+
+#[derive(ToJsValue)]
+struct ValidationReport {
+    ok: bool,
+    errors: Vec<Issue>,
+}
+
+/// `scl.validate(value, ty, registry)`, returning `{ok, errors: [{path, expected, got}]}` instead
+/// of throwing on the first problem.
+#[js::host_call]
+fn validate(
+    value: js::Value,
+    tid: Id,
+    type_registry: TypeRegistry,
+) -> js::Result<ValidationReport> {
+    let errors = validate_into(value, &tid, &type_registry.borrow());
+    Ok(ValidationReport {
+        ok: errors.is_empty(),
+        errors,
+    })
+}
+
+fn validate_into(value: js::Value, tid: &Id, registry: &Registry) -> Vec<Issue> {
+    let mut validation = Validation::default();
+    encode_at(
+        Segment::Root,
+        value,
+        tid,
+        registry,
+        &mut Discard,
+        &Hooks::default(),
+        Some(&mut validation),
+    )
+    .expect("BUG: validation errors are collected");
+    validation.issues
+}
+
+/// The state of an `encode_into` that validates instead of encoding.
+#[derive(Default)]
+struct Validation {
+    path: String,
+    issues: Vec<Issue>,
+}
+
+/// Where a value is in its parent.
+enum Segment<'a> {
+    Root,
+    Field(&'a str),
+    Index(usize),
+}
+
+/// The output of a validation, which writes nothing.
+struct Discard;
+
+impl Output for Discard {
+    fn write(&mut self, _bytes: &[u8]) {}
+}
+
+/// Encode the part of a value at `segment`. When validating, its errors are recorded and the
+/// traversal goes on with the next part.
+fn encode_at(
+    segment: Segment,
+    value: js::Value,
+    tid: &Id,
+    registry: &Registry,
+    out: &mut impl Output,
+    hooks: &Hooks,
+    validation: Option<&mut Validation>,
+) -> js::Result<()> {
+    let Some(validation) = validation else {
+        return encode_checked(value, tid, registry, out, hooks, None);
+    };
+    let parent_len = validation.path.len();
+    match segment {
+        Segment::Root => {}
+        Segment::Field(name) if parent_len == 0 => validation.path.push_str(name),
+        Segment::Field(name) => {
+            validation.path.push('.');
+            validation.path.push_str(name);
+        }
+        Segment::Index(ind) => validation.path.push_str(&alloc::format!("[{ind}]")),
+    }
+    if let Err(err) = encode_checked(value.clone(), tid, registry, out, hooks, Some(validation)) {
+        validation.issues.push(Issue {
+            path: validation.path.clone(),
+            expected: describe_type(tid, registry),
+            got: describe_value(&value),
+            message: alloc::format!("{err}"),
+        });
+    }
+    validation.path.truncate(parent_len);
+    Ok(())
+}
+
+fn describe_type(tid: &Id, registry: &Registry) -> String {
+    if !matches!(tid.info, IdInfo::Type(_)) {
+        return type_name(tid);
+    }
+    let Ok(ty) = registry.resolve_type(tid, true) else {
+        return type_name(tid);
+    };
+    match ty.as_ref() {
+        Type::Primitive(ty) => alloc::format!("{ty:?}").to_ascii_lowercase(),
+        Type::Compact(_) => "compact".into(),
+        Type::Seq(_) => "sequence".into(),
+        Type::Tuple(_) | Type::LabeledTuple(_) => "tuple".into(),
+        Type::Array(_, len) => alloc::format!("array of length {len}"),
+        Type::Enum(_) => "enum".into(),
+        Type::Struct(..) => "struct".into(),
+        Type::Alias(_) => type_name(tid),
+        Type::Fixed(_) => "fixed-point number".into(),
+        Type::Wide(Wide::U256) => "256-bit integer".into(),
+        Type::Wide(wide) => alloc::format!("{}-byte hash", wide.encoded_len()),
+    }
+}
+
+fn describe_value(value: &js::Value) -> String {
+    if value.is_string() {
+        return alloc::format!("{:?}", value.to_string());
+    }
+    if value.is_number() || value.is_bool() || value.is_big_int() {
+        return value.to_string();
+    }
+    if value.is_array() {
+        if let Ok(len) = value.length() {
+            return alloc::format!("array of length {len}");
+        }
+    }
+    value.describe_type()
+}
+
+/// Decode `bytes` as the type `ty`, which is either a type name or a type written in the DSL.
+///
+/// Trailing bytes are ignored.
+pub fn decode_value(
+    ctx: &js::Context,
+    bytes: &[u8],
+    ty: &str,
+    registry: &TypeRegistry,
+) -> js::Result<js::Value> {
+    let mut buf = bytes;
+    decode_valude(ctx, &mut buf, &Id::from(ty), &registry.borrow())
 }
 
 fn u8a_or_hex<T>(
@@ -433,23 +1298,42 @@ fn u8a_or_hex<T>(
         };
         return Some(f(&bytes));
     }
+    // Wrappers like polkadot-js codecs, see `Context::register_wrapper_class`.
+    if value.is_wrapper() {
+        return Some(value.decode_bytes().and_then(|bytes| f(&bytes)));
+    }
     None
 }
 
-fn encode_value(
+fn encode_into(
     value: js::Value,
     tid: &Id,
     registry: &Registry,
     out: &mut impl Output,
 ) -> js::Result<()> {
+    encode_checked(value, tid, registry, out, &Hooks::default(), None)
+}
+
+fn encode_checked(
+    value: js::Value,
+    tid: &Id,
+    registry: &Registry,
+    out: &mut impl Output,
+    hooks: &Hooks,
+    mut validation: Option<&mut Validation>,
+) -> js::Result<()> {
+    let value = hooks.pre_encode(value, tid, registry)?;
     let t = registry.resolve_type(tid, true)?;
     match t.as_ref() {
         Type::Alias(_) => unreachable!("Alias should be resolved"),
         Type::Primitive(ty) => encode_primitive(value, ty, out),
+        Type::Fixed(fixed) => fixed.encode(&value, out),
+        Type::Wide(wide) => wide.encode(&value, out),
         Type::Compact(tid) => {
             let ty = registry.resolve_type(tid, false)?;
             match ty.as_ref() {
                 Type::Primitive(ty) => encode_compact_primitive(value, ty, out),
+                Type::Fixed(fixed) => fixed.encode_compact(&value, out),
                 Type::Tuple(tids) if tids.is_empty() => {
                     Compact(()).encode_to(out);
                     Ok(())
@@ -468,17 +1352,64 @@ fn encode_value(
                     return result;
                 }
             }
+            match ty.as_ref() {
+                Type::Primitive(PrimitiveType::U64) if js::JsBigUint64Array::is(&value) => {
+                    js::JsBigUint64Array::from_js_value(value)?
+                        .as_slice()
+                        .encode_to(out);
+                    return Ok(());
+                }
+                Type::Primitive(PrimitiveType::I64) if js::JsBigInt64Array::is(&value) => {
+                    js::JsBigInt64Array::from_js_value(value)?
+                        .as_slice()
+                        .encode_to(out);
+                    return Ok(());
+                }
+                _ => {}
+            }
             let length = value.get_property("length")?.decode_u32()?;
             Compact(length).encode_to(out);
             for i in 0..length {
-                encode_value(value.index(i as _)?, tid, registry, out)?;
+                let sub_value = value.index(i as _)?;
+                encode_at(
+                    Segment::Index(i as _),
+                    sub_value,
+                    tid,
+                    registry,
+                    out,
+                    hooks,
+                    validation.as_deref_mut(),
+                )?;
             }
             Ok(())
         }
         Type::Tuple(ids) => {
             for (ind, ty) in ids.iter().enumerate() {
-                let sub_value = value.index(ind)?;
-                encode_value(sub_value, ty, registry, out)?;
+                let sub_value = tuple_element(&value, ind, None)?;
+                encode_at(
+                    Segment::Index(ind),
+                    sub_value,
+                    ty,
+                    registry,
+                    out,
+                    hooks,
+                    validation.as_deref_mut(),
+                )?;
+            }
+            Ok(())
+        }
+        Type::LabeledTuple(elements) => {
+            for (ind, (label, ty)) in elements.iter().enumerate() {
+                let sub_value = tuple_element(&value, ind, Some(label))?;
+                encode_at(
+                    Segment::Field(label),
+                    sub_value,
+                    ty,
+                    registry,
+                    out,
+                    hooks,
+                    validation.as_deref_mut(),
+                )?;
             }
             Ok(())
         }
@@ -503,7 +1434,15 @@ fn encode_value(
             }
             for ind in 0..len {
                 let sub_value = value.index(ind)?;
-                encode_value(sub_value, ty, registry, out)?;
+                encode_at(
+                    Segment::Index(ind),
+                    sub_value,
+                    ty,
+                    registry,
+                    out,
+                    hooks,
+                    validation.as_deref_mut(),
+                )?;
             }
             Ok(())
         }
@@ -516,19 +1455,27 @@ fn encode_value(
                     let ind =
                         u8::try_from(ind).or(Err(anyhow!("variant index {ind} is too large")))?;
                     ind.encode_to(out);
-                    return encode_value(value, ty, registry, out);
+                    return encode_checked(value, ty, registry, out, hooks, validation);
                 }
             }
             for entry in value.entries()? {
                 let (k, v) = entry?;
                 let key = js::JsString::from_js_value(k)?;
-                if let Ok((_name, ty, ind)) = def.get_variant_by_name(key.as_str()) {
+                if let Ok((name, ty, ind)) = def.get_variant_by_name(key.as_str()) {
                     let Ok(ind) = u8::try_from(ind) else {
                         bail!("variant index {} is too large", ind);
                     };
                     ind.encode_to(out);
                     if let Some(ty) = ty {
-                        encode_value(v, &ty, registry, out)?;
+                        encode_at(
+                            Segment::Field(name),
+                            v,
+                            &ty,
+                            registry,
+                            out,
+                            hooks,
+                            validation,
+                        )?;
                     }
                     return Ok(());
                 }
@@ -542,16 +1489,66 @@ fn encode_value(
                     .join(", ")
             )
         }
-        Type::Struct(fields) => {
+        Type::Struct(fields, defaults) => {
             for (name, ty) in fields.iter() {
-                let sub_value = value.get_property(name)?;
-                encode_value(sub_value, ty, registry, out)?;
+                let mut sub_value = value.get_property(name)?;
+                if sub_value.is_undefined() {
+                    if let Some((_, default)) = defaults.iter().find(|(field, _)| field == name) {
+                        sub_value = default_value(value.context()?, default)?;
+                    }
+                }
+                encode_at(
+                    Segment::Field(name),
+                    sub_value,
+                    ty,
+                    registry,
+                    out,
+                    hooks,
+                    validation.as_deref_mut(),
+                )?;
             }
             Ok(())
         }
     }
 }
 
+/// The JS value a struct field default is encoded from.
+fn default_value(ctx: &js::Context, default: &FieldDefault) -> js::Result<js::Value> {
+    match *default {
+        FieldDefault::Num(n) => n.to_js_value(ctx),
+        FieldDefault::Bool(b) => b.to_js_value(ctx),
+        FieldDefault::Empty => Ok(ctx.new_array()),
+        FieldDefault::Zeroed(len) => {
+            let array = ctx.new_array();
+            for _ in 0..len {
+                array.array_push(&0u32.to_js_value(ctx)?)?;
+            }
+            Ok(array)
+        }
+    }
+}
+
+/// Get the element of a tuple from an array, an object with numeric keys or, for labeled tuples,
+/// an object keyed by the labels.
+fn tuple_element(value: &js::Value, ind: usize, label: Option<&str>) -> js::Result<js::Value> {
+    if value.is_array() {
+        return value.index(ind);
+    }
+    if !value.is_object() {
+        bail!(
+            "expected array or object for tuple, got {}",
+            value.describe_type()
+        );
+    }
+    if let Some(label) = label {
+        let sub_value = value.get_property(label)?;
+        if !sub_value.is_undefined() {
+            return Ok(sub_value);
+        }
+    }
+    value.index(ind)
+}
+
 fn encode_primitive(value: js::Value, t: &PrimitiveType, out: &mut impl Output) -> js::Result<()> {
     match t {
         PrimitiveType::U8 => {
@@ -594,6 +1591,36 @@ fn encode_primitive(value: js::Value, t: &PrimitiveType, out: &mut impl Output)
     Ok(())
 }
 
+/// Thrown as a `RangeError` with code `ERR_EOF`.
+pub(crate) fn unexpected_eof() -> js::Error {
+    js::Error::msg(js::JsError::range_error("unexpected end of buffer").with_code("ERR_EOF"))
+}
+
+/// Thrown as a `TypeError` with code `ERR_UNKNOWN_TYPE` and the name in `typeName`.
+fn unknown_type(name: &str, message: String) -> js::Error {
+    js::Error::msg(
+        js::JsError::type_error(message)
+            .with_code("ERR_UNKNOWN_TYPE")
+            .with_property("typeName", String::from(name)),
+    )
+}
+
+/// The Levenshtein distance between `a` and `b`, counted in bytes.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.bytes().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
 fn compactable_err<T>() -> js::Result<T> {
     Err(anyhow!("a number or () for compact"))
 }
@@ -621,8 +1648,15 @@ fn decode(
     value: js::JsUint8Array,
     tid: Id,
     type_registry: TypeRegistry,
+    hooks: Hooks,
 ) -> js::Result<js::Value> {
-    decode_valude(&ctx, &mut value.as_bytes(), &tid, &type_registry.borrow())
+    decode_with(
+        &ctx,
+        &mut value.as_bytes(),
+        &tid,
+        &type_registry.borrow(),
+        &hooks,
+    )
 }
 
 #[js::host_call(with_context)]
@@ -632,12 +1666,32 @@ fn decode_all(
     value: js::JsUint8Array,
     tids: Vec<Id>,
     type_registry: TypeRegistry,
+    hooks: Hooks,
 ) -> js::Result<Vec<js::Value>> {
-    let mut buf = value.as_bytes();
+    decode_each(&ctx, &mut value.as_bytes(), &tids, &type_registry, &hooks)
+}
+
+/// The body of `scl.decodeAll`, shared with the measured function of [`setup_with_metrics`].
+fn decode_each(
+    ctx: &js::Context,
+    buf: &mut &[u8],
+    tids: &[Id],
+    type_registry: &TypeRegistry,
+    hooks: &Hooks,
+) -> js::Result<Vec<js::Value>> {
+    let registry = type_registry.borrow();
+    let min_len = min_encoded_len_sum(tids.iter(), &registry, 0);
+    if min_len > buf.len() {
+        bail!(
+            "{} values need at least {min_len} bytes, got {}",
+            tids.len(),
+            buf.len()
+        );
+    }
+    drop(registry);
     let mut out = Vec::new();
     for tid in tids {
-        let v = decode_valude(&ctx, &mut buf, &tid, &type_registry.borrow())?;
-        out.push(v);
+        out.push(decode_with(ctx, buf, tid, &type_registry.borrow(), hooks)?);
     }
     Ok(out)
 }
@@ -648,12 +1702,15 @@ fn codec(
     _this: js::Value,
     tid: js::Value,
     registry: js::Value,
+    hooks: js::Value,
 ) -> js::Result<js::Value> {
-    let obj = ctx.new_object("ScaleCodec");
+    Hooks::from_js_value(hooks.clone())?;
     let proto = ctx.get_global_object().get_property("ScaleCodec")?;
-    obj.set_prototype(&proto)?;
+    let obj = ctx.new_object_with_proto(&proto)?;
+    obj.set_name("ScaleCodec")?;
     obj.set_property("ty", &tid)?;
     obj.set_property("registry", &registry)?;
+    obj.set_property("hooks", &hooks)?;
     obj.set_property("isArray", &js::Value::from_bool(&ctx, tid.is_array()))?;
     Ok(obj)
 }
@@ -663,15 +1720,45 @@ fn decode_valude(
     buf: &mut &[u8],
     ty: &Id,
     registry: &Registry,
+) -> js::Result<js::Value> {
+    decode_with(ctx, buf, ty, registry, &Hooks::default())
+}
+
+fn decode_with(
+    ctx: &js::Context,
+    buf: &mut &[u8],
+    ty: &Id,
+    registry: &Registry,
+    hooks: &Hooks,
+) -> js::Result<js::Value> {
+    let value = decode_structure(ctx, buf, ty, registry, hooks)?;
+    hooks.post_decode(value, ty, registry)
+}
+
+fn decode_structure(
+    ctx: &js::Context,
+    buf: &mut &[u8],
+    ty: &Id,
+    registry: &Registry,
+    hooks: &Hooks,
 ) -> js::Result<js::Value> {
     let t = registry.resolve_type(ty, true)?;
     match t.as_ref() {
         Type::Alias(_) => unreachable!("Alias should be resolved"),
         Type::Primitive(ty) => decode_primitive(ctx, buf, ty),
+        Type::Fixed(fixed) => {
+            let raw = fixed.decode(buf)?;
+            fixed.to_js(ctx, raw, registry.fixed_as_number)
+        }
+        Type::Wide(wide) => wide.decode(ctx, buf),
         Type::Compact(tid) => {
             let tid = registry.resolve_type(tid, false)?;
             match tid.as_ref() {
                 Type::Primitive(ty) => decode_compact_primitive(ctx, buf, ty),
+                Type::Fixed(fixed) => {
+                    let raw = fixed.decode_compact(buf)?;
+                    fixed.to_js(ctx, raw, registry.fixed_as_number)
+                }
                 Type::Tuple(tids) if tids.is_empty() => {
                     Compact::<()>::decode(buf).context("failed to decode compact tuple")?;
                     Ok(ctx.new_array())
@@ -681,16 +1768,19 @@ fn decode_valude(
         }
         Type::Seq(ty) => {
             let t = registry.resolve_type(ty, false)?;
-            if matches!(t.as_ref(), Type::Primitive(PrimitiveType::U8)) {
-                let value = Vec::<u8>::decode(buf).context("failed to decode sequence")?;
-                return AsBytes(value).to_js_value(ctx);
-            }
             let length = Compact::<u32>::decode(buf)
                 .context("failed to decode sequence length")?
                 .0;
+            if matches!(t.as_ref(), Type::Primitive(PrimitiveType::U8)) {
+                check_declared_len(length as usize, 1, buf)?;
+                let (value, rest) = buf.split_at(length as usize);
+                *buf = rest;
+                return AsBytes(value.to_vec()).to_js_value(ctx);
+            }
+            check_declared_len(length as usize, min_encoded_len(ty, registry, 0), buf)?;
             let out = ctx.new_array();
             for _ in 0..length {
-                let sub_value = decode_valude(ctx, buf, ty, registry)?;
+                let sub_value = decode_with(ctx, buf, ty, registry, hooks)?;
                 out.array_push(&sub_value)?;
             }
             Ok(out)
@@ -698,7 +1788,7 @@ fn decode_valude(
         Type::Tuple(types) => {
             let out = ctx.new_array();
             for ty in types {
-                let sub_value = decode_valude(ctx, buf, ty, registry)?;
+                let sub_value = decode_with(ctx, buf, ty, registry, hooks)?;
                 out.array_push(&sub_value)?;
             }
             Ok(out)
@@ -708,7 +1798,7 @@ fn decode_valude(
             let t = registry.resolve_type(ty, false)?;
             if matches!(t.as_ref(), Type::Primitive(PrimitiveType::U8)) {
                 if buf.len() < len {
-                    bail!("unexpected end of buffer")
+                    return Err(unexpected_eof());
                 }
                 let value = buf[..len].to_vec();
                 *buf = &buf[len..];
@@ -716,7 +1806,7 @@ fn decode_valude(
             }
             let out = ctx.new_array();
             for _ in 0..len {
-                let sub_value = decode_valude(ctx, buf, ty, registry)?;
+                let sub_value = decode_with(ctx, buf, ty, registry, hooks)?;
                 out.array_push(&sub_value)?;
             }
             Ok(out)
@@ -727,7 +1817,7 @@ fn decode_valude(
                 if tag == 0 {
                     return Ok(js::Value::Null);
                 } else if tag as u32 == ind {
-                    return decode_valude(ctx, buf, ty, registry);
+                    return decode_with(ctx, buf, ty, registry, hooks);
                 } else {
                     bail!("unexpected variant index {tag} for Option<T>");
                 }
@@ -735,17 +1825,17 @@ fn decode_valude(
             let (variant_name, variant_type) = def.get_variant_by_index(tag)?;
             let out = ctx.new_object(&variant_name);
             if let Some(variant_type) = variant_type {
-                let sub_value = decode_valude(ctx, buf, &variant_type, registry)?;
+                let sub_value = decode_with(ctx, buf, &variant_type, registry, hooks)?;
                 out.set_property(&variant_name, &sub_value)?;
             } else {
                 out.set_property(&variant_name, &js::Value::Null)?;
             }
             Ok(out)
         }
-        Type::Struct(fields) => {
+        Type::Struct(fields, _) | Type::LabeledTuple(fields) => {
             let out = ctx.new_object("");
             for (name, ty) in fields {
-                let sub_value = decode_valude(ctx, buf, ty, registry)?;
+                let sub_value = decode_with(ctx, buf, ty, registry, hooks)?;
                 out.set_property(name, &sub_value)?;
             }
             Ok(out)
@@ -753,6 +1843,76 @@ fn decode_valude(
     }
 }
 
+/// The most zero-sized elements, like those of `Vec<()>`, a sequence may declare. Their count is
+/// not bounded by the input.
+const MAX_ZERO_SIZED_LEN: usize = 1 << 16;
+
+/// Fail if `len` elements of at least `min_len` bytes each cannot fit in `buf`, so that a forged
+/// length prefix is rejected before decoding any element. Zero-sized elements are capped at
+/// [`MAX_ZERO_SIZED_LEN`] instead.
+fn check_declared_len(len: usize, min_len: usize, buf: &[u8]) -> js::Result<()> {
+    if min_len == 0 {
+        return check_zero_sized_len(len);
+    }
+    if len.saturating_mul(min_len) > buf.len() {
+        bail!(
+            "declared length {len} exceeds remaining {} bytes",
+            buf.len()
+        );
+    }
+    Ok(())
+}
+
+fn check_zero_sized_len(len: usize) -> js::Result<()> {
+    if len > MAX_ZERO_SIZED_LEN {
+        bail!("declared length {len} of zero-sized elements exceeds {MAX_ZERO_SIZED_LEN}");
+    }
+    Ok(())
+}
+
+/// The fewest bytes a value of type `ty` is encoded in. Types that fail to resolve count as zero
+/// bytes and are reported when decoded.
+fn min_encoded_len(ty: &Id, registry: &Registry, depth: usize) -> usize {
+    const MAX_DEPTH: usize = 32;
+    if depth > MAX_DEPTH {
+        return 0;
+    }
+    let Ok(t) = registry.resolve_type(ty, true) else {
+        return 0;
+    };
+    match t.as_ref() {
+        Type::Primitive(p) => match p {
+            PrimitiveType::U8 | PrimitiveType::I8 | PrimitiveType::Bool => 1,
+            PrimitiveType::U16 | PrimitiveType::I16 => 2,
+            PrimitiveType::U32 | PrimitiveType::I32 => 4,
+            PrimitiveType::U64 | PrimitiveType::I64 => 8,
+            PrimitiveType::U128 | PrimitiveType::I128 => 16,
+            // The compact length prefix.
+            PrimitiveType::Str => 1,
+        },
+        Type::Fixed(fixed) => fixed.encoded_len(),
+        Type::Wide(wide) => wide.encoded_len(),
+        Type::Compact(_) | Type::Seq(_) | Type::Enum(_) => 1,
+        Type::Tuple(tids) => min_encoded_len_sum(tids.iter(), registry, depth + 1),
+        Type::Array(tid, len) => {
+            (*len as usize).saturating_mul(min_encoded_len(tid, registry, depth + 1))
+        }
+        Type::Struct(fields, _) | Type::LabeledTuple(fields) => {
+            min_encoded_len_sum(fields.iter().map(|(_, tid)| tid), registry, depth + 1)
+        }
+        Type::Alias(_) => 0,
+    }
+}
+
+fn min_encoded_len_sum<'a>(
+    tids: impl Iterator<Item = &'a Id>,
+    registry: &Registry,
+    depth: usize,
+) -> usize {
+    tids.map(|tid| min_encoded_len(tid, registry, depth))
+        .fold(0, usize::saturating_add)
+}
+
 fn decode_primitive(
     ctx: &js::Context,
     buf: &mut &[u8],
@@ -760,7 +1920,7 @@ fn decode_primitive(
 ) -> js::Result<js::Value> {
     macro_rules! decode_num {
         ($t: ident) => {{
-            let value = <$t>::decode(buf).context("unexpected end of buffer")?;
+            let value = <$t>::decode(buf).map_err(|_| unexpected_eof())?;
             value.to_js_value(ctx)
         }};
     }
@@ -777,7 +1937,7 @@ fn decode_primitive(
         PrimitiveType::I128 => decode_num!(i128),
         PrimitiveType::Bool => decode_num!(bool),
         PrimitiveType::Str => String::decode(buf)
-            .context("unexpected end of buffer")?
+            .map_err(|_| unexpected_eof())?
             .to_js_value(ctx),
     }
 }
@@ -789,7 +1949,7 @@ fn decode_compact_primitive(
 ) -> js::Result<js::Value> {
     macro_rules! decode_num {
         ($t: ident) => {{
-            let value = Compact::<$t>::decode(buf).context("unexpected end of buffer")?;
+            let value = Compact::<$t>::decode(buf).map_err(|_| unexpected_eof())?;
             value.0.to_js_value(ctx)
         }};
     }
@@ -802,3 +1962,923 @@ fn decode_compact_primitive(
         _ => compactable_err(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    std::thread_local! {
+        pub(super) static STD_PARSES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    }
+
+    #[test]
+    fn encode_decode_from_rust() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        registry
+            .define_struct(
+                "Transfer",
+                [("to", "[u8;2]".into()), ("amount", "u32".into())],
+            )
+            .unwrap();
+
+        let value = ctx
+            .eval(&js::Code::Source("({ to: '0x0102', amount: 7 })"))
+            .unwrap();
+        let encoded = encode_value(&value, "Transfer", &registry).unwrap();
+        assert_eq!(encoded, [1, 2, 7, 0, 0, 0]);
+
+        let decoded = decode_value(&ctx, &encoded, "Transfer", &registry).unwrap();
+        assert_eq!(
+            decoded
+                .get_property("amount")
+                .unwrap()
+                .decode_u32()
+                .unwrap(),
+            7
+        );
+
+        let encoded = encode_value(&decoded.get_property("amount").unwrap(), "@u32", &registry);
+        assert_eq!(encoded.unwrap(), [28]);
+    }
+
+    #[test]
+    fn defines_types_from_specs() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        registry
+            .define("Account", TypeSpec::array("u8", 2))
+            .unwrap()
+            .define(
+                "Transfer",
+                TypeSpec::labeled_tuple([
+                    ("to", "Account".into()),
+                    ("amount", TypeSpec::compact("u32").into()),
+                ]),
+            )
+            .unwrap()
+            .define_enum(
+                "Message",
+                [
+                    ("Ping", None),
+                    ("Batch", Some(TypeSpec::seq("Transfer").into())),
+                ],
+            )
+            .unwrap();
+
+        let value = ctx
+            .eval(&js::Code::Source(
+                "({ Batch: [{ to: [1, 2], amount: 3 }, [[4, 5], 6]] })",
+            ))
+            .unwrap();
+        let encoded = encode_value(&value, "Message", &registry).unwrap();
+        assert_eq!(encoded, [1, 8, 1, 2, 12, 4, 5, 24]);
+
+        let option = TypeRef::generic("Option", ["Message".into()]);
+        registry.define("Reply", TypeSpec::alias(option)).unwrap();
+        let decoded = decode_value(&ctx, &[1, 0], "Reply", &registry).unwrap();
+        assert!(decoded.get_property("Ping").unwrap().is_null());
+    }
+
+    #[test]
+    fn encodes_field_defaults() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        registry
+            .append(
+                "Config={nonce: u32 = 0, tip: @u128 = 0, data: [u8] = [], key: [u8; 2] = [0; 2]}",
+            )
+            .unwrap();
+        let value = ctx.eval(&js::Code::Source("({})")).unwrap();
+        let encoded = encode_value(&value, "Config", &registry).unwrap();
+        assert_eq!(encoded, [0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let value = ctx
+            .eval(&js::Code::Source("({ nonce: 1, data: '0x0203' })"))
+            .unwrap();
+        let encoded = encode_value(&value, "Config", &registry).unwrap();
+        assert_eq!(encoded, [1, 0, 0, 0, 0, 8, 2, 3, 0, 0]);
+    }
+
+    #[test]
+    fn validates_without_stopping_at_the_first_problem() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let report = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes(`
+                    Action=<Transfer:{to: [u8; 2], amount: u8}|Remark:str>
+                    Proposal={title: str, action: Action, votes: [u8], fallback: Action}
+                `);
+                const valid = {
+                    title: "fund",
+                    action: { Remark: "hi" },
+                    votes: [1, 2],
+                    fallback: { Transfer: { to: "0x0102", amount: 3 } },
+                };
+                const invalid = {
+                    title: "fund",
+                    action: { Transfer: { to: "0x010203", amount: 300 } },
+                    votes: [1, 2],
+                    fallback: { Burn: 1 },
+                };
+                const report = scl.validate(invalid, "Proposal", registry);
+                [
+                    scl.validate(valid, "Proposal", registry).ok,
+                    report.ok,
+                    ...report.errors.map((e) => `${e.path}:${e.expected}:${e.got}`),
+                ].join(" ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            report.decode_string().unwrap(),
+            "true false action.Transfer.to:array of length 2:\"0x010203\" \
+             action.Transfer.amount:u8:300 fallback:Action:object"
+        );
+
+        let registry = TypeRegistry::new().unwrap();
+        let value = ctx.eval(&js::Code::Source("[1, 256, 3]")).unwrap();
+        let issues = validate_value(&value, "Vec<u8>", &registry);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "[1]");
+        assert!(encode_value(&value, "Vec<u8>", &registry).is_err());
+    }
+
+    #[test]
+    fn non_finite_numbers_do_not_encode() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        let types = [
+            "u8", "u32", "u64", "u128", "i32", "i64", "i128", "@u32", "@u128",
+        ];
+        for src in ["NaN", "Infinity", "-Infinity"] {
+            let value = ctx.eval(&js::Code::Source(src)).unwrap();
+            for ty in types {
+                let err = encode_value(&value, ty, &registry).unwrap_err();
+                let err = format!("{err:#}");
+                assert!(err.contains(&format!("got {src}")), "{ty}: {err}");
+            }
+        }
+        let zero = ctx.eval(&js::Code::Source("-0")).unwrap();
+        for ty in types {
+            let encoded = encode_value(&zero, ty, &registry).unwrap();
+            assert!(encoded.iter().all(|&byte| byte == 0), "{ty}: {encoded:?}");
+        }
+    }
+
+    #[test]
+    fn encodes_wrapped_numbers_and_bytes() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        registry
+            .define_struct(
+                "Transfer",
+                [("to", "[u8;2]".into()), ("amount", "u128".into())],
+            )
+            .unwrap();
+        let value = ctx
+            .eval(&js::Code::Source(
+                r#"
+                class BN { valueOf() { return 7n; } }
+                class AccountId { toU8a() { return new Uint8Array([1, 2]); } }
+                globalThis.classes = [BN, AccountId];
+                ({ to: new AccountId(), amount: new BN() })
+                "#,
+            ))
+            .unwrap();
+        assert!(encode_value(&value, "Transfer", &registry).is_err());
+        let classes = ctx.eval(&js::Code::Source("classes")).unwrap();
+        for i in 0..2 {
+            ctx.register_wrapper_class(&classes.index(i).unwrap()).unwrap();
+        }
+        let encoded = encode_value(&value, "Transfer", &registry).unwrap();
+        assert_eq!(encoded[..3], [1, 2, 7]);
+    }
+
+    #[test]
+    fn encodes_tuples_from_arrays_and_objects() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let result = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes(`
+                    Pair=(u8, u16)
+                    Transfer=(from: u16, amount: u8)
+                `);
+                const hex = (bytes) => Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+                const decoded = scl.decode(scl.encode([513, 3], "Transfer", registry), "Transfer", registry);
+                [
+                    hex(scl.encode([1, 2], "Pair", registry)),
+                    hex(scl.encode({ 0: 1, 1: 2 }, "Pair", registry)),
+                    hex(scl.encode({ from: 513, amount: 3 }, "Transfer", registry)),
+                    hex(scl.encode({ 0: 513, 1: 3 }, "Transfer", registry)),
+                    JSON.stringify(scl.decode(scl.encode([1, 2], "Pair", registry), "Pair", registry)),
+                    JSON.stringify(decoded),
+                ].join(" ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            result.decode_string().unwrap(),
+            r#"010200 010200 010203 010203 [1,2] {"from":513,"amount":3}"#
+        );
+
+        let err = ctx
+            .eval(&js::Code::Source(r#"scl.encode(1, "Pair", registry)"#))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("expected array or object for tuple"), "{err}");
+    }
+
+    #[test]
+    fn streaming_decode_matches_one_shot() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let result = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes(`
+                    Action=<Ping|Data:[u8]|Vote:(u8,bool)>
+                    Message={id: @u32, name: str, scores: Vec<u16>, action: Action, memo: Option<str>}
+                `);
+                const bytes = scl.encode({
+                    id: 70000,
+                    name: "hello world",
+                    scores: [1, 2, 3],
+                    action: { Vote: [7, true] },
+                    memo: "ok",
+                }, "Message", registry);
+                const expected = JSON.stringify(scl.decode(bytes, "Message", registry));
+                const results = [];
+                for (let cut = 0; cut <= bytes.length; cut++) {
+                    const decoder = scl.decoder("Message", registry);
+                    decoder.push(bytes.slice(0, cut));
+                    decoder.push(bytes.slice(cut));
+                    results.push(JSON.stringify(decoder.finish()) === expected);
+                }
+                const bytewise = scl.decoder("Message", registry);
+                for (const byte of bytes) {
+                    bytewise.push(new Uint8Array([byte]));
+                }
+                results.push(JSON.stringify(bytewise.finish()) === expected);
+
+                const errors = [];
+                const partial = scl.decoder("Message", registry);
+                partial.push(bytes.slice(0, 6));
+                try { partial.finish() } catch (e) { errors.push(e.message) }
+                const trailing = scl.decoder("Message", registry);
+                trailing.push(new Uint8Array([...bytes, 0]));
+                try { trailing.finish() } catch (e) { errors.push(e.message) }
+                results.every(x => x) + " " + errors.join(", ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            result.decode_string().unwrap(),
+            "true incomplete value, 2 bytes buffered, 1 trailing bytes after the value"
+        );
+    }
+
+    #[test]
+    fn streaming_decode_checks_declared_lengths() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.set_conversion_limits(js::ConversionLimits {
+            max_string_bytes: 64,
+            max_array_len: 100,
+            ..Default::default()
+        })
+        .unwrap();
+        let scl = setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let start = std::time::Instant::now();
+        let result = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes("Entry=(u8,u64)");
+                const push = (ty, bytes) => {
+                    try {
+                        scl.decoder(ty, registry).push(new Uint8Array(bytes));
+                        return "ok";
+                    } catch (e) {
+                        return e.message;
+                    }
+                };
+                // Compact 0xFFFFFFFF.
+                const huge = [0x03, 0xff, 0xff, 0xff, 0xff];
+                [
+                    push("Vec<()>", huge),
+                    push("Vec<Entry>", huge),
+                    push("str", huge),
+                    push("Vec<u8>", huge),
+                    // Compact 65, a string over the limit.
+                    push("str", [0x05, 0x01]),
+                    push("Vec<Entry>", [0x90]),
+                ].join("; ")
+                "#,
+            ))
+            .unwrap();
+        assert!(start.elapsed().as_millis() < 100);
+        assert_eq!(
+            result.decode_string().unwrap(),
+            "declared length 4294967295 of zero-sized elements exceeds 65536; \
+             conversion limit max_array_len exceeded: 4294967295 > 100; \
+             conversion limit max_string_bytes exceeded: 4294967300 > 64; \
+             conversion limit max_string_bytes exceeded: 4294967300 > 64; \
+             conversion limit max_string_bytes exceeded: 67 > 64; \
+             ok"
+        );
+    }
+
+    #[test]
+    fn codec_hooks_transform_named_types() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let result = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes(`
+                    Moment=u64
+                    Event={name: str, at: Moment, tags: [str]}
+                `);
+                const seen = [];
+                const codec = scl.codec("Event", registry, {
+                    preEncode(value, ty) {
+                        seen.push(`pre:${ty}`);
+                        return ty === "Moment" ? value.getTime() : value;
+                    },
+                    postDecode(value, ty) {
+                        seen.push(`post:${ty}`);
+                        return ty === "Moment" ? new Date(Number(value)) : value;
+                    },
+                });
+                const bytes = codec.encode({ name: "launch", at: new Date(1700000000000), tags: [] });
+                const plain = scl.decode(bytes, "Event", registry);
+                const event = codec.decode(bytes);
+
+                const failing = scl.codec("Event", registry, {
+                    preEncode(value, ty) {
+                        if (ty === "Moment") throw new Error("not a date");
+                        return value;
+                    },
+                });
+                let message;
+                try { failing.encode({ name: "x", at: 0, tags: [] }) } catch (e) { message = e.message }
+                [
+                    event.at instanceof Date,
+                    event.at.getTime(),
+                    plain.at,
+                    seen.join(","),
+                    message.includes("preEncode hook of Moment failed"),
+                    message.includes("not a date"),
+                ].join(" ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            result.decode_string().unwrap(),
+            "true 1700000000000 1700000000000 \
+             pre:Event,pre:Moment,post:Moment,post:Event true true"
+        );
+    }
+
+    #[test]
+    fn collects_metrics() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = ctx.new_object("Scale");
+        let metrics = ScaleMetrics::new();
+        setup_with_metrics(&scl, &ctx, metrics.clone()).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        ctx.eval(&js::Code::Source(
+            r#"
+            const registry = scl.parseTypes("Pair=(u8,u32)");
+            const bytes = scl.encode([1, 2], "Pair", registry);
+            scl.decode(bytes, "Pair", registry);
+            scl.encodeAll([7, 8], ["u8", "u16"], registry);
+            try { scl.encode(1, "NoSuchMetricsType", registry) } catch (e) {}
+            try { scl.decode(new Uint8Array([1]), "u32", registry) } catch (e) {}
+            try { scl.decodeAll(new Uint8Array([1]), ["u32", "u32"], registry) } catch (e) {}
+            "#,
+        ))
+        .unwrap();
+        let snapshot = metrics.snapshot().unwrap();
+        assert_eq!(snapshot.encode_calls, 2);
+        assert_eq!(snapshot.decode_calls, 1);
+        assert_eq!(snapshot.bytes_encoded, 8);
+        assert_eq!(snapshot.bytes_decoded, 5);
+        let errors: Vec<_> = snapshot
+            .errors
+            .iter()
+            .map(|(kind, n)| (kind.as_str(), *n))
+            .collect();
+        assert_eq!(
+            errors,
+            [("ERR_EOF", 1), ("ERR_UNKNOWN_TYPE", 1), ("Error", 1)]
+        );
+        let mut names: Vec<_> = snapshot
+            .slowest
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["Pair", "u8,u16"]);
+
+        let dumped = ctx
+            .eval(&js::Code::Source("scl.__metrics().encodeCalls"))
+            .unwrap();
+        assert_eq!(dumped.decode_u32().unwrap(), 2);
+    }
+
+    #[test]
+    fn flushes_each_collector_once() {
+        #[derive(Default)]
+        struct Flushes(core::cell::Cell<u32>);
+        impl MetricsCollector for Flushes {
+            fn record_encode(&self, _: &str, _: usize, _: core::time::Duration) {}
+            fn record_decode(&self, _: &str, _: usize, _: core::time::Duration) {}
+            fn record_error(&self, _: &str, _: &'static str, _: &str) {}
+            fn flush(&self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = ctx.new_object("Scale");
+        let first = Rc::new(Flushes::default());
+        let second = Rc::new(Flushes::default());
+        setup_with_metrics(&scl, &ctx, first.clone()).unwrap();
+        // Replacing a collector flushes it, without keeping a callback for it.
+        metrics::set_collector(&ctx, second.clone()).unwrap();
+        assert_eq!((first.0.get(), second.0.get()), (1, 0));
+        drop(scl);
+        drop(ctx);
+        assert_eq!((first.0.get(), second.0.get()), (1, 1));
+    }
+
+    #[test]
+    fn encodes_bigint_arrays() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        let items = [1u64 << 63, u64::MAX];
+        let array = js::JsBigUint64Array::new(&ctx, &items).unwrap();
+        let value = array.to_js_value(&ctx).unwrap();
+        let encoded = encode_value(&value, "Vec<u64>", &registry).unwrap();
+        assert_eq!(encoded, items.to_vec().encode());
+    }
+
+    #[test]
+    fn shares_builtin_types_across_contexts() {
+        let runtime = js::Runtime::new(&Default::default());
+        setup_runtime(&runtime).unwrap();
+        let parses = STD_PARSES.with(|n| n.get());
+        for i in 0..100u32 {
+            let ctx = runtime.new_context();
+            let scl = setup_context(&ctx).unwrap();
+            ctx.get_global_object().set_property("scl", &scl).unwrap();
+            let src = format!(
+                r#"
+                const registry = scl.parseTypes("Pair=(u8,u32)");
+                const codec = scl.codec("Pair", registry);
+                const pair = codec.decode(codec.encode([{i} % 256, {i}]));
+                const all = scl.codec(["u16", "Perbill"], registry);
+                pair[1] + all.decode(all.encode([1, "50%"]))[1].raw
+                "#
+            );
+            let value = ctx.eval(&js::Code::Source(&src)).unwrap();
+            assert_eq!(value.decode_u32().unwrap(), i + 500_000_000);
+        }
+        // Only `setup_runtime` parsed them.
+        assert_eq!(STD_PARSES.with(|n| n.get()), parses);
+
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        setup_context(&ctx).unwrap();
+        TypeRegistry::new().unwrap();
+        assert_eq!(STD_PARSES.with(|n| n.get()), parses + 1);
+    }
+
+    #[test]
+    fn decode_errors_carry_codes() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let caught = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes("Pair=(u8,u32)");
+                const describe = (f) => {
+                    try { f(); } catch (e) { return `${e.name}:${e.code}:${e.typeName}`; }
+                };
+                [
+                    describe(() => scl.decode(new Uint8Array([1, 2]), "Pair", registry)),
+                    describe(() => scl.decode(new Uint8Array([1]), "Nope", registry)),
+                ].join(",")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(
+            caught.decode_string().unwrap(),
+            "RangeError:ERR_EOF:undefined,TypeError:ERR_UNKNOWN_TYPE:Nope"
+        );
+    }
+
+    #[test]
+    fn encodes_fixed_point_types() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        let encode = |src: &str, ty: &str| {
+            let value = ctx.eval(&js::Code::Source(src)).unwrap();
+            encode_value(&value, ty, &registry)
+        };
+        let perbill = 125_000_000u32.encode();
+        assert_eq!(encode("'12.5%'", "Perbill").unwrap(), perbill);
+        assert_eq!(encode("0.125", "Perbill").unwrap(), perbill);
+        assert_eq!(encode("125000000n", "Perbill").unwrap(), perbill);
+        assert_eq!(encode("({raw: 125000000})", "Perbill").unwrap(), perbill);
+        assert_eq!(
+            encode("'100%'", "Perbill").unwrap(),
+            1_000_000_000u32.encode()
+        );
+        assert_eq!(encode("1", "Permill").unwrap(), 1_000_000u32.encode());
+        assert_eq!(encode("'7%'", "Percent").unwrap(), [7]);
+        assert_eq!(encode("0", "Percent").unwrap(), [0]);
+        assert_eq!(
+            encode("'12.5%'", "@Perbill").unwrap(),
+            Compact(125_000_000u32).encode()
+        );
+        assert_eq!(
+            encode("'340282366920938463463.374607431768211455'", "FixedU128").unwrap(),
+            u128::MAX.encode()
+        );
+        assert_eq!(
+            encode("'1.000000000000000001'", "FixedU128").unwrap(),
+            1_000_000_000_000_000_001u128.encode()
+        );
+        for (src, ty, err) in [
+            ("'100.0000001%'", "Perbill", "exceed 100%"),
+            ("1000000001n", "Perbill", "exceed 100%"),
+            ("'12.5%'", "Percent", "finer than one part"),
+            ("-0.5", "Perbill", "invalid Perbill"),
+            ("'1e40'", "FixedU128", "out of range"),
+        ] {
+            let message = encode(src, ty).unwrap_err().to_string();
+            assert!(message.contains(err), "{src}: {message}");
+        }
+
+        let decoded = decode_value(&ctx, &perbill, "Perbill", &registry).unwrap();
+        assert_eq!(
+            decoded.get_property("raw").unwrap().decode_u32().unwrap(),
+            125_000_000
+        );
+        assert_eq!(
+            decoded
+                .get_property("asNumber")
+                .unwrap()
+                .decode_f64()
+                .unwrap(),
+            0.125
+        );
+        let raw = decode_value(&ctx, &u128::MAX.encode(), "FixedU128", &registry)
+            .unwrap()
+            .get_property("raw")
+            .unwrap();
+        assert_eq!(raw.decode_u128().unwrap(), u128::MAX);
+        assert!(decode_value(&ctx, &1_000_000_001u32.encode(), "Perbill", &registry).is_err());
+
+        registry.set_fixed_as_number(true).unwrap();
+        let decoded = decode_value(&ctx, &[50], "Percent", &registry).unwrap();
+        assert_eq!(decoded.decode_f64().unwrap(), 0.5);
+    }
+
+    #[test]
+    fn encodes_hashes_and_u256() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src)).unwrap();
+        let max = [0xffu8; 32];
+
+        let encoded = encode_value(&eval("2n ** 256n - 1n"), "U256", &registry).unwrap();
+        assert_eq!(encoded, max);
+        let decoded = decode_value(&ctx, &max, "U256", &registry).unwrap();
+        assert!(decoded.is_big_int());
+        let is_max = eval("n => n === 2n ** 256n - 1n");
+        assert!(is_max
+            .call(&js::Value::undefined(), &[decoded])
+            .unwrap()
+            .decode_bool()
+            .unwrap());
+
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        let mut wide = [0u8; 32];
+        wide[..17].copy_from_slice(&[0x10; 17]);
+        for (src, expected) in [
+            ("1n", one),
+            ("1", one),
+            ("'0x01'", one),
+            ("'0x1'", one),
+            ("'1'", one),
+            ("'0x1010101010101010101010101010101010'", wide),
+        ] {
+            let encoded = encode_value(&eval(src), "U256", &registry).unwrap();
+            assert_eq!(encoded, expected, "{src}");
+        }
+        for src in ["-1n", "2n ** 256n", "'0xzz'", "'0x'", "({})"] {
+            assert!(
+                encode_value(&eval(src), "U256", &registry).is_err(),
+                "{src}"
+            );
+        }
+        // A script patching `BigInt.prototype.toString` does not change the encoding.
+        eval("BigInt.prototype.toString = () => 'ff'");
+        assert_eq!(encode_value(&eval("1n"), "U256", &registry).unwrap(), one);
+
+        let hash = alloc::format!("0x{}", "ab".repeat(20));
+        let encoded = encode_value(&eval(&alloc::format!("'{hash}'")), "H160", &registry).unwrap();
+        assert_eq!(encoded, [0xab; 20]);
+        let from_bytes =
+            encode_value(&eval("new Uint8Array(20).fill(0xab)"), "H160", &registry).unwrap();
+        assert_eq!(from_bytes, encoded);
+        let decoded = decode_value(&ctx, &encoded, "H160", &registry).unwrap();
+        assert_eq!(decoded.decode_string().unwrap(), hash);
+        assert!(encode_value(&eval("'0xabab'"), "H256", &registry).is_err());
+        assert_eq!(
+            encode_value(&eval("new Uint8Array(64)"), "H512", &registry)
+                .unwrap()
+                .len(),
+            64
+        );
+
+        // A user definition takes precedence over the builtin.
+        registry.append("H256=[u8;32]").unwrap();
+        let decoded = decode_value(&ctx, &max, "H256", &registry).unwrap();
+        assert!(decoded.is_uint8_array());
+    }
+
+    #[test]
+    fn rejects_absurd_length_prefixes() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        registry.append("Entry=(u8,u64)").unwrap();
+        // Compact 0xFFFFFFFF followed by a few bytes.
+        let payload = [0x03, 0xff, 0xff, 0xff, 0xff, 1, 2, 3];
+        for ty in [
+            "Vec<u8>",
+            "Vec<u32>",
+            "Vec<Entry>",
+            "Vec<Vec<u8>>",
+        ] {
+            let start = std::time::Instant::now();
+            let err = decode_value(&ctx, &payload, ty, &registry).unwrap_err();
+            // Far below the seconds it takes to iterate the declared elements.
+            assert!(start.elapsed().as_millis() < 100, "{ty}");
+            assert!(
+                err.to_string()
+                    .contains("declared length 4294967295 exceeds remaining 3 bytes"),
+                "{ty}: {err}"
+            );
+        }
+        for ty in ["Vec<()>", "Vec<[u8;0]>"] {
+            let err = decode_value(&ctx, &payload, ty, &registry).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("declared length 4294967295 of zero-sized elements exceeds 65536"),
+                "{ty}: {err}"
+            );
+            // Compact 1000, with no bytes for the elements.
+            let decoded = decode_value(&ctx, &[0xa1, 0x0f], ty, &registry).unwrap();
+            assert_eq!(decoded.length().unwrap(), 1000, "{ty}");
+        }
+        // Entries take at least 9 bytes, so 3 bytes cannot hold one.
+        let err = decode_value(&ctx, &[4, 1, 2, 3], "Vec<Entry>", &registry).unwrap_err();
+        assert!(err.to_string().contains("declared length 1"), "{err}");
+
+        let scl = ctx.new_object("Scale");
+        setup(&scl, &ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let err = ctx
+            .eval(&js::Code::Source(
+                r#"scl.decodeAll(new Uint8Array([1]), ["u64", "u32"], scl.parseTypes("Pair=(u8,u32)"))"#,
+            ))
+            .unwrap_err();
+        assert!(
+            err.contains("2 values need at least 12 bytes, got 1"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn child_registries_layer_over_their_parent() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let base = TypeRegistry::new().unwrap();
+        base.append("Balance=u32\nAccount=(u8,Balance)").unwrap();
+        let child = TypeRegistry::with_parent(&base);
+        child.append("Balance=u64").unwrap();
+        let sibling = TypeRegistry::with_parent(&base);
+        sibling.append("Extra=u8").unwrap();
+        let value = ctx.eval(&js::Code::Source("[1, 2]")).unwrap();
+        let encode = |ty, registry| encode_value(&value, ty, registry).map(|out| out.len());
+
+        // Shadowed in the child, including where the parent's types refer to the name.
+        assert_eq!(encode("Account", &base).unwrap(), 5);
+        assert_eq!(encode("Account", &child).unwrap(), 9);
+        assert_eq!(encode("Account", &sibling).unwrap(), 5);
+        // Falling through to the parent and the builtin types.
+        assert_eq!(encode("(u8,u16)", &child).unwrap(), 3);
+        // Appends stay in the registry they were made to.
+        assert!(encode("(Extra,u8)", &sibling).is_ok());
+        assert!(encode("(Extra,u8)", &child).is_err());
+        assert!(encode("(Extra,u8)", &base).is_err());
+        base.append("Late=u8").unwrap();
+        assert!(encode("(Late,u8)", &base).is_ok());
+        assert!(encode("(Late,u8)", &child).is_err());
+
+        let scl = ctx.new_object("Scale");
+        setup(&scl, &ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let len = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const base = scl.parseTypes("Balance=u32\nAccount=(u8,Balance)");
+                const child = scl.childRegistry(base, "Balance=u128");
+                [scl.encode([1, 2], "Account", child).length, scl.encode([1, 2], "Account", base).length].join()
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(len.to_string(), "17,5");
+    }
+
+    #[test]
+    fn unknown_names_suggest_aliases_and_case_folds() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let registry = TypeRegistry::new().unwrap();
+        let value = ctx.eval(&js::Code::Source("new Uint8Array(32)")).unwrap();
+        let encode =
+            |ty, registry| encode_value(&value, ty, registry).map_err(|e| alloc::format!("{e:#}"));
+
+        let suggestion = "unknown type accountid, did you mean AccountId or AccountId32?";
+        assert!(encode("accountid", &registry)
+            .unwrap_err()
+            .contains(suggestion));
+        registry.set_case_insensitive(true).unwrap();
+        assert_eq!(encode("accountid", &registry).unwrap().len(), 32);
+        registry.append("Hash=u8\nHASH=u16").unwrap();
+        assert_eq!(encode("HASH", &registry).unwrap().len(), 2);
+        let ambiguous = "ambiguous type hash, it matches HASH, Hash";
+        assert!(encode("hash", &registry).unwrap_err().contains(ambiguous));
+
+        // Aliases of aliases, and children inheriting the case folding.
+        registry.define_alias("Who", "AccountId").unwrap();
+        registry.define_alias("AccountID", "Who").unwrap();
+        assert!(registry.define_alias("Who", "AccountID").is_err());
+        assert!(registry.define_alias("Nobody", "Nope").is_err());
+        let child = TypeRegistry::with_parent(&registry);
+        assert_eq!(encode("AccountID", &child).unwrap().len(), 32);
+        assert_eq!(encode("who", &child).unwrap().len(), 32);
+
+        let scl = setup_context(&ctx).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let len = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes("Balance=u64", { case_insensitive: true });
+                scl.registerAlias(registry, "Amount", "balance");
+                scl.encode(1, "amount", registry).length
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(len.decode_u32().unwrap(), 8);
+    }
+
+    /// Append a type to `registry`, reporting the operation of the reentrancy error if any.
+    #[js::host_call]
+    fn reenter(registry: TypeRegistry) -> String {
+        match registry.append("Late=u16") {
+            Ok(()) => "appended".into(),
+            Err(err) => match err.downcast_ref::<js::Reentrancy>() {
+                Some(reentrancy) => {
+                    alloc::format!("{}:{}", reentrancy.type_name, reentrancy.operation)
+                }
+                None => alloc::format!("{err}"),
+            },
+        }
+    }
+
+    #[test]
+    fn hooks_reentering_the_registry_fail_or_defer() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = setup_context(&ctx).unwrap();
+        scl.define_property_fn("reenter", reenter).unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let run = |options: &str| {
+            let src = alloc::format!(
+                r#"(() => {{
+                const registry = scl.parseTypes("Id=u8", {options});
+                const seen = [];
+                const codec = scl.codec("Id", registry, {{
+                    preEncode(value, ty) {{
+                        if (ty !== "Id") return value;
+                        seen.push(scl.reenter(registry));
+                        try {{
+                            scl.registerAlias(registry, "Other", "Id");
+                            seen.push("aliased");
+                        }} catch (e) {{
+                            seen.push(e.message.includes("define_alias re-entered TypeRegistry"));
+                        }}
+                        return value;
+                    }},
+                }});
+                seen.push(codec.encode(1).length);
+                for (const ty of ["Late", "Other"]) {{
+                    try {{ seen.push(scl.encode(1, ty, registry).length) }} catch (e) {{ seen.push(`no ${{ty}}`) }}
+                }}
+                return seen.join();
+                }})()"#
+            );
+            ctx.eval(&js::Code::Source(&src)).unwrap().to_string()
+        };
+        assert_eq!(run("{}"), "TypeRegistry:append,true,1,no Late,no Other");
+        assert_eq!(run("{ defer_reentrant: true }"), "appended,aliased,1,2,1");
+    }
+
+    std::thread_local! {
+        static FORWARDED: core::cell::RefCell<Vec<String>> = const { core::cell::RefCell::new(Vec::new()) };
+    }
+
+    /// Any name for `RawArgs` takes the rest of the arguments.
+    type Forwarded<'a> = js::RawArgs<'a>;
+
+    /// Middleware logging the calls to `scl.encode` before forwarding them as they are.
+    #[js::host_call(with_context)]
+    fn logged_encode(
+        ctx: js::Context,
+        this: js::Value,
+        args: Forwarded<'_>,
+    ) -> js::Result<js::Value> {
+        let encode = ctx
+            .get_global_object()
+            .get_property("scl")?
+            .get_property("encode")?;
+        let types = args.get(1).map(|tid| tid.to_string()).unwrap_or_default();
+        FORWARDED.with(|log| log.borrow_mut().push(format!("{}:{types}", args.len())));
+        encode.call(&this, &args)
+    }
+
+    #[test]
+    fn raw_args_forward_calls_unchanged() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let scl = setup_context(&ctx).unwrap();
+        scl.define_property_fn("loggedEncode", logged_encode)
+            .unwrap();
+        ctx.get_global_object().set_property("scl", &scl).unwrap();
+        let same = ctx
+            .eval(&js::Code::Source(
+                r#"
+                const registry = scl.parseTypes("Pair=(u8,u32)\nNames=[str]");
+                const hex = (bytes) => Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+                const proxy = new Proxy([7, 300], {});
+                const names = ["a", "b"];
+                [
+                    [[1, 2], "Pair"],
+                    [proxy, "Pair"],
+                    [names, "Names"],
+                ].map(([value, ty]) =>
+                    hex(scl.loggedEncode(value, ty, registry)) === hex(scl.encode(value, ty, registry))
+                ).join()
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(same.to_string(), "true,true,true");
+        assert_eq!(
+            FORWARDED.with(|log| log.take()),
+            ["3:Pair", "3:Pair", "3:Names"]
+        );
+    }
+}
@@ -7,15 +7,28 @@ use core::fmt::Write;
 use core::fmt::{self, Display};
 use tinyvec_string::TinyString;
 
+use super::fixed::FixedPoint;
+use super::wide::Wide;
+
 //use crate::scale::PrimitiveType;
 
-pub type String = TinyString<[u8; 24]>;
+pub(crate) type String = TinyString<[u8; 24]>;
 
 type Span = SimpleSpan<usize>;
 
 #[derive(Copy, Clone, PartialEq, Eq)]
-pub struct Poor {
+pub(crate) struct Poor {
     span: Span,
+    message: Option<&'static str>,
+}
+
+impl Poor {
+    fn with_message(span: Span, message: &'static str) -> Self {
+        Self {
+            span,
+            message: Some(message),
+        }
+    }
 }
 
 impl<'a, I: Input<'a, Span = Span>> Error<'a, I> for Poor {
@@ -25,13 +38,19 @@ impl<'a, I: Input<'a, Span = Span>> Error<'a, I> for Poor {
         _found: Option<MaybeRef<'a, I::Token>>,
         span: I::Span,
     ) -> Self {
-        Self { span }
+        Self {
+            span,
+            message: None,
+        }
     }
 }
 
 impl fmt::Debug for Poor {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "at {:?}", self.span.start)?;
+        if let Some(message) = self.message {
+            write!(f, ": {message}")?;
+        }
         Ok(())
     }
 }
@@ -60,14 +79,19 @@ impl<'src> fmt::Display for Token<'src> {
 }
 
 fn lexer<'src>() -> impl Parser<'src, &'src str, Vec<(Token<'src>, Span)>, extra::Err<Poor>> {
-    // A parser for numbers
-    let num = text::int(10)
-        .try_map(|s: &str, span| {
-            s.parse::<u32>().or(Err(Error::<&str>::expected_found(
-                [],
-                s.chars().next().map(Into::into),
-                span,
-            )))
+    // A parser for numbers, decimal or 0x prefixed hex, with optional `_` separators
+    let num = any()
+        .filter(|c: &char| c.is_ascii_digit())
+        .then(
+            any()
+                .filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_')
+                .repeated()
+                .collect::<alloc::string::String>(),
+        )
+        .try_map(|(first, rest), span| {
+            let mut literal = alloc::string::String::from(first);
+            literal.push_str(&rest);
+            parse_num(&literal).map_err(|message| Poor::with_message(span, message))
         })
         .map(Token::Num);
     // A parser for control characters (delimiters, semicolons, etc.)
@@ -87,13 +111,38 @@ fn lexer<'src>() -> impl Parser<'src, &'src str, Vec<(Token<'src>, Span)>, extra
         .collect()
 }
 
+fn parse_num(literal: &str) -> Result<u32, &'static str> {
+    let (digits, radix) = match literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        Some(hex) => (hex, 16),
+        None => (literal, 10),
+    };
+    if digits.is_empty() {
+        return Err("missing digits in number literal");
+    }
+    if digits.starts_with('_') || digits.ends_with('_') {
+        return Err("number literal can not start or end with `_`");
+    }
+    let mut value: u32 = 0;
+    for c in digits.chars().filter(|c| *c != '_') {
+        let digit = c.to_digit(radix).ok_or("invalid digit in number literal")?;
+        value = value
+            .checked_mul(radix)
+            .and_then(|v| v.checked_add(digit))
+            .ok_or("number literal out of range of u32")?;
+    }
+    Ok(value)
+}
+
 #[derive(Debug, Clone)]
-pub struct Id {
-    pub info: IdInfo,
-    pub type_args: Vec<Id>,
+pub(crate) struct Id {
+    pub(crate) info: IdInfo,
+    pub(crate) type_args: Vec<Id>,
 }
 #[derive(Debug, Clone)]
-pub enum IdInfo {
+pub(crate) enum IdInfo {
     Name(String),
     Num(u32),
     Type(Box<Type>),
@@ -114,6 +163,15 @@ impl From<String> for Id {
     }
 }
 
+impl From<Type> for Id {
+    fn from(ty: Type) -> Self {
+        Self {
+            info: IdInfo::Type(Box::new(ty)),
+            type_args: Default::default(),
+        }
+    }
+}
+
 impl From<u32> for Id {
     fn from(n: u32) -> Self {
         Self {
@@ -124,15 +182,15 @@ impl From<u32> for Id {
 }
 
 #[derive(Debug, Clone)]
-pub struct Enum {
-    pub variants: Vec<(String, Option<Id>, Option<u32>)>,
+pub(crate) struct Enum {
+    pub(crate) variants: Vec<(String, Option<Id>, Option<u32>)>,
 }
 
 impl Enum {
-    pub fn new(variants: Vec<(String, Option<Id>, Option<u32>)>) -> Self {
+    pub(crate) fn new(variants: Vec<(String, Option<Id>, Option<u32>)>) -> Self {
         Self { variants }
     }
-    pub fn is_option_and_some_def(&self) -> Option<(&Id, u32)> {
+    pub(crate) fn is_option_and_some_def(&self) -> Option<(&Id, u32)> {
         if self.variants.len() != 2 {
             return None;
         }
@@ -152,6 +210,7 @@ impl Enum {
     }
 }
 
+/// Public only for the shapes of the fuzzing harness in `roundtrip`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PrimitiveType {
     U8,
@@ -168,6 +227,20 @@ pub enum PrimitiveType {
     Str,
 }
 
+impl PrimitiveType {
+    /// The largest numeric field default the type can hold.
+    fn max_default(&self) -> u32 {
+        match self {
+            PrimitiveType::U8 => u8::MAX.into(),
+            PrimitiveType::U16 => u16::MAX.into(),
+            PrimitiveType::I8 => i8::MAX as u32,
+            PrimitiveType::I16 => i16::MAX as u32,
+            PrimitiveType::I32 => i32::MAX as u32,
+            _ => u32::MAX,
+        }
+    }
+}
+
 impl core::str::FromStr for PrimitiveType {
     type Err = ();
 
@@ -177,15 +250,83 @@ impl core::str::FromStr for PrimitiveType {
 }
 
 #[derive(Debug, Clone)]
-pub enum Type {
+pub(crate) enum Type {
     Primitive(PrimitiveType),
     Compact(Id),
     Seq(Id),
     Tuple(Vec<Id>),
+    /// A tuple with labeled elements, `(from: AccountId, amount: Balance)`. Encoded
+    /// positionally, decoded to an object keyed by the labels.
+    LabeledTuple(Vec<(String, Id)>),
     Array(Id, u32),
     Enum(Enum),
-    Struct(Vec<(String, Id)>),
+    /// A struct with its fields, and the defaults of the fields declared with one.
+    Struct(Vec<(String, Id)>, Vec<(String, FieldDefault)>),
     Alias(Id),
+    /// A fixed-point number, built in as `Perbill`, `Permill`, `Percent` and `FixedU128`.
+    Fixed(FixedPoint),
+    /// A hash or wide integer, built in as `H160`, `H256`, `H512` and `U256`.
+    Wide(Wide),
+}
+
+/// The default of a struct field, used by the encoder when the field is missing,
+/// `{nonce: u32 = 0, flag: bool = false, data: [u8] = [], hash: [u8; 32] = [0; 32]}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FieldDefault {
+    Num(u32),
+    Bool(bool),
+    /// An empty sequence.
+    Empty,
+    /// An array of the given length filled with zeros.
+    Zeroed(u32),
+}
+
+impl FieldDefault {
+    /// Check that the default can be encoded as `ty`, as far as can be told without resolving
+    /// named types.
+    fn check(&self, ty: &Id) -> Result<(), &'static str> {
+        const MISMATCH: &str = "default value does not match the field type";
+        let primitive = |ty: &PrimitiveType| match (ty, self) {
+            (PrimitiveType::Bool, FieldDefault::Bool(_)) => Ok(()),
+            (PrimitiveType::Bool | PrimitiveType::Str, _) => Err(MISMATCH),
+            (_, FieldDefault::Num(n)) if n > &ty.max_default() => {
+                Err("default value is out of the range of the field type")
+            }
+            (_, FieldDefault::Num(_)) => Ok(()),
+            _ => Err(MISMATCH),
+        };
+        match &ty.info {
+            IdInfo::Name(name) if ty.type_args.is_empty() => match PrimitiveType::from_str(name) {
+                Some(ty) => primitive(ty),
+                None => Err(
+                    "defaults are only supported on primitive, compact, sequence and array fields",
+                ),
+            },
+            IdInfo::Type(inner) => match (inner.as_ref(), self) {
+                (Type::Primitive(ty), _) => primitive(ty),
+                (Type::Compact(inner), FieldDefault::Num(_)) => match &inner.info {
+                    IdInfo::Name(name) => PrimitiveType::from_str(name).map_or(Ok(()), primitive),
+                    IdInfo::Type(inner) => match inner.as_ref() {
+                        Type::Primitive(ty) => primitive(ty),
+                        _ => Ok(()),
+                    },
+                    IdInfo::Num(_) => Ok(()),
+                },
+                (Type::Seq(_), FieldDefault::Empty) => Ok(()),
+                (Type::Array(_, len), FieldDefault::Zeroed(n)) if len == n => Ok(()),
+                (Type::Array(..), FieldDefault::Zeroed(_)) => {
+                    Err("zero-filled default must have the length of the array")
+                }
+                (Type::Compact(_) | Type::Seq(_) | Type::Array(..), _) => Err(MISMATCH),
+                _ => Err(
+                    "defaults are only supported on primitive, compact, sequence and array fields",
+                ),
+            },
+            _ => {
+                Err("defaults are only supported on primitive, compact, sequence and array fields")
+            }
+        }
+    }
 }
 
 macro_rules! impl_primitive_types {
@@ -249,13 +390,13 @@ impl From<PrimitiveType> for Type {
 }
 
 #[derive(Clone, Debug)]
-pub struct TypeName {
-    pub name: Option<String>,
-    pub type_params: Vec<String>,
+pub(crate) struct TypeName {
+    pub(crate) name: Option<String>,
+    pub(crate) type_params: Vec<String>,
 }
 
 impl TypeName {
-    fn new(name: String, type_params: Vec<String>) -> Self {
+    pub(crate) fn new(name: String, type_params: Vec<String>) -> Self {
         Self {
             name: Some(name),
             type_params,
@@ -270,9 +411,9 @@ impl TypeName {
 }
 
 #[derive(Clone, Debug)]
-pub struct TypeDef {
-    pub name: TypeName,
-    pub ty: Type,
+pub(crate) struct TypeDef {
+    pub(crate) name: TypeName,
+    pub(crate) ty: Type,
 }
 
 impl Display for TypeName {
@@ -348,17 +489,33 @@ fn type_parser<'tokens, 'src: 'tokens>(
         });
         let tid = tid_parser(typ.clone());
         let num = select! { Num(v) => v };
-        // A list of type identifiers
-        let tids = typ
-            .clone()
-            .separated_by(just(Op(',')))
-            .allow_trailing()
-            .collect::<Vec<_>>();
         let compact_def = just(Op('@')).ignore_then(typ.clone()).map(Type::Compact);
+        // A tuple element, optionally labeled
+        let tuple_element = ident.then_ignore(just(Op(':'))).or_not().then(typ.clone());
         let tuple_def = just(Op('('))
-            .ignore_then(tids)
+            .ignore_then(
+                tuple_element
+                    .separated_by(just(Op(',')))
+                    .allow_trailing()
+                    .collect::<Vec<_>>(),
+            )
             .then_ignore(just(Op(')')))
-            .map(Type::Tuple);
+            .try_map(|elements, span| {
+                if elements.iter().all(|(label, _)| label.is_none()) {
+                    return Ok(Type::Tuple(
+                        elements.into_iter().map(|(_, ty)| ty).collect(),
+                    ));
+                }
+                elements
+                    .into_iter()
+                    .map(|(label, ty)| Some((label?, ty)))
+                    .collect::<Option<Vec<_>>>()
+                    .map(Type::LabeledTuple)
+                    .ok_or(Poor::with_message(
+                        span,
+                        "tuple elements must be either all labeled or all unlabeled",
+                    ))
+            });
         let array_def = just(Op('['))
             .ignore_then(typ.clone().then_ignore(just(Op(';'))).then(num))
             .then_ignore(just(Op(']')))
@@ -380,9 +537,29 @@ fn type_parser<'tokens, 'src: 'tokens>(
             )
             .map(|vec| Type::Enum(Enum::new(vec)))
             .then_ignore(just(Op('>')));
+        let field_default = choice((
+            num.map(FieldDefault::Num),
+            just(Ident("true")).to(FieldDefault::Bool(true)),
+            just(Ident("false")).to(FieldDefault::Bool(false)),
+            just(Op('[')).then(just(Op(']'))).to(FieldDefault::Empty),
+            just(Op('['))
+                .ignore_then(just(Num(0)))
+                .ignore_then(just(Op(';')))
+                .ignore_then(num)
+                .then_ignore(just(Op(']')))
+                .map(FieldDefault::Zeroed),
+        ));
         let struct_field = ident
             .then(just(Op(':')).ignore_then(typ.clone()))
-            .map(|(name, ty)| (name, ty));
+            .then(just(Op('=')).ignore_then(field_default).or_not())
+            .try_map(|((name, ty), default), span| {
+                if let Some(default) = &default {
+                    default
+                        .check(&ty)
+                        .map_err(|message| Poor::with_message(span, message))?;
+                }
+                Ok((name, ty, default))
+            });
         let struct_def = just(Op('{'))
             .ignore_then(
                 struct_field
@@ -391,7 +568,14 @@ fn type_parser<'tokens, 'src: 'tokens>(
                     .collect::<Vec<_>>(),
             )
             .then_ignore(just(Op('}')))
-            .map(Type::Struct);
+            .map(|fields| {
+                let defaults = fields
+                    .iter()
+                    .filter_map(|(name, _, default)| Some((name.clone(), (*default)?)))
+                    .collect();
+                let fields = fields.into_iter().map(|(name, ty, _)| (name, ty)).collect();
+                Type::Struct(fields, defaults)
+            });
         let alias_def = tid.map(Type::Alias);
         let primitive_def = just(Op('#'))
             .ignore_then(primitive_parser())
@@ -433,7 +617,7 @@ fn parser<'tokens, 'src: 'tokens>(
         .then_ignore(end())
 }
 
-pub fn parse_types(src: &str) -> js::Result<Vec<TypeDef>> {
+pub(crate) fn parse_types(src: &str) -> js::Result<Vec<TypeDef>> {
     let tokens = lexer()
         .parse(src)
         .into_result()
@@ -451,11 +635,11 @@ fn convert_errors(errors: Vec<Poor>, src: &str) -> js::Error {
         let start = span.start;
         let end = span.end;
         let src = substr(src, (start, end), 30);
-        write!(
-            &mut report,
-            "invalid syntax at {start}..{end}: here->`{src}`"
-        )
-        .unwrap();
+        write!(&mut report, "invalid syntax at {start}..{end}: ").unwrap();
+        if let Some(message) = error.message {
+            write!(&mut report, "{message}, ").unwrap();
+        }
+        write!(&mut report, "here->`{src}`").unwrap();
     }
     js::Error::msg(report.to_string())
 }
@@ -471,7 +655,7 @@ fn substr(src: &str, range: (usize, usize), range_extension: usize) -> &str {
     &src[start..end]
 }
 
-pub fn parse_type(src: &str) -> js::Result<Type> {
+pub(crate) fn parse_type(src: &str) -> js::Result<Type> {
     let tokens = lexer()
         .parse(src)
         .into_result()
@@ -494,3 +678,89 @@ fn it_works() {
     println!("{:#?}", ast);
     assert!(ast.is_ok());
 }
+
+#[test]
+fn number_literals() {
+    assert_eq!(parse_num("32"), Ok(32));
+    assert_eq!(parse_num("0x20"), Ok(32));
+    assert_eq!(parse_num("0XfF"), Ok(255));
+    assert_eq!(parse_num("1_000"), Ok(1000));
+    assert_eq!(
+        parse_num("0x_10"),
+        Err("number literal can not start or end with `_`")
+    );
+    assert_eq!(
+        parse_num("10_"),
+        Err("number literal can not start or end with `_`")
+    );
+    assert_eq!(parse_num("0x"), Err("missing digits in number literal"));
+    assert_eq!(parse_num("12a"), Err("invalid digit in number literal"));
+    assert_eq!(parse_num("4294967295"), Ok(u32::MAX));
+    assert_eq!(
+        parse_num("4294967296"),
+        Err("number literal out of range of u32")
+    );
+    assert_eq!(
+        parse_num("0x1_0000_0000"),
+        Err("number literal out of range of u32")
+    );
+
+    assert!(parse_types("a=[u8;0x20];b=[u8;65_536];c=<A:0x1,B:1_0>").is_ok());
+    let err = parse_types("a=[u8;4294967296]").unwrap_err().to_string();
+    assert!(err.contains("number literal out of range of u32"), "{err}");
+}
+
+#[test]
+fn labeled_tuples() {
+    let types = parse_types("Transfer=(from: AccountId, amount: u128);Pair=(u8, u16)").unwrap();
+    let Type::LabeledTuple(elements) = &types[0].ty else {
+        panic!("expected labeled tuple, got {:?}", types[0].ty);
+    };
+    let labels: Vec<_> = elements.iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(labels, ["from", "amount"]);
+    assert!(matches!(&types[1].ty, Type::Tuple(ids) if ids.len() == 2));
+
+    let err = parse_types("Bad=(from: AccountId, u128)")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("all labeled or all unlabeled"), "{err}");
+}
+
+#[test]
+fn struct_field_defaults() {
+    let types =
+        parse_types("Config={nonce: u32 = 0, tip: @u128 = 0, data: [u8] = [], key: [u8; 4] = [0; 4], on: bool = true, who: AccountId}")
+            .unwrap();
+    let Type::Struct(fields, defaults) = &types[0].ty else {
+        panic!("expected struct, got {:?}", types[0].ty);
+    };
+    assert_eq!(fields.len(), 6);
+    let defaults: Vec<_> = defaults
+        .iter()
+        .map(|(name, default)| (name.as_str(), *default))
+        .collect();
+    assert_eq!(
+        defaults,
+        [
+            ("nonce", FieldDefault::Num(0)),
+            ("tip", FieldDefault::Num(0)),
+            ("data", FieldDefault::Empty),
+            ("key", FieldDefault::Zeroed(4)),
+            ("on", FieldDefault::Bool(true)),
+        ]
+    );
+    assert!(parse_types("A={a: u8 = 255, b: i8 = 127, c: u128 = 4294967295}").is_ok());
+
+    for (src, message) in [
+        ("A={data: [u8] = 0}", "does not match the field type"),
+        ("A={on: bool = 1}", "does not match the field type"),
+        ("A={key: [u8; 4] = [0; 2]}", "length of the array"),
+        ("A={who: AccountId = 0}", "only supported on primitive"),
+        ("A={n: u8 = 256}", "out of the range"),
+        ("A={n: i16 = 32768}", "out of the range"),
+        ("A={n: @u8 = 300}", "out of the range"),
+    ] {
+        let err = parse_types(src).unwrap_err().to_string();
+        assert!(err.contains(message), "{src}: {err}");
+    }
+}
@@ -0,0 +1,584 @@
+//! The round-trip harness shared by the property tests and the `decode` fuzz target in
+//! `qjs-extensions/fuzz`.
+//!
+//! A [`Shape`] is a random type definition and a [`Sample`] a value of it in the form the
+//! decoder produces, so that `decode(encode(sample))` must give the sample back as is.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{
+    decode_value, encode_value, Enum, FixedPoint, Id, PrimitiveType, Type, TypeRegistry, TypeSpec,
+    Wide,
+};
+
+const PRIMITIVES: [PrimitiveType; 12] = [
+    PrimitiveType::U8,
+    PrimitiveType::U16,
+    PrimitiveType::U32,
+    PrimitiveType::U64,
+    PrimitiveType::U128,
+    PrimitiveType::I8,
+    PrimitiveType::I16,
+    PrimitiveType::I32,
+    PrimitiveType::I64,
+    PrimitiveType::I128,
+    PrimitiveType::Bool,
+    PrimitiveType::Str,
+];
+
+const COMPACTABLE: [PrimitiveType; 5] = [
+    PrimitiveType::U8,
+    PrimitiveType::U16,
+    PrimitiveType::U32,
+    PrimitiveType::U64,
+    PrimitiveType::U128,
+];
+
+/// What a compact can hold: the unsigned primitives, the fixed-point numbers and `()`.
+fn compactable() -> Vec<Shape> {
+    COMPACTABLE
+        .iter()
+        .map(|p| Shape::Primitive(*p))
+        .chain(FixedPoint::ALL.iter().map(|f| Shape::Fixed(*f)))
+        .chain([Shape::Tuple(Vec::new())])
+        .collect()
+}
+
+/// A type definition. Struct fields are named `f0, f1, ..`, tuple labels `l0, l1, ..` and enum
+/// variants `V0, V1, ..`.
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Primitive(PrimitiveType),
+    Fixed(FixedPoint),
+    Wide(Wide),
+    /// Of an unsigned primitive, a fixed-point number or the empty tuple.
+    Compact(Box<Shape>),
+    Seq(Box<Shape>),
+    Array(Box<Shape>, u32),
+    Tuple(Vec<Shape>),
+    LabeledTuple(Vec<Shape>),
+    Struct(Vec<Shape>),
+    Enum(Vec<Option<Shape>>),
+    /// Never directly holds another option, `Option<Option<T>>` decodes `Some(None)` as null.
+    Option(Box<Shape>),
+}
+
+impl Shape {
+    fn to_type(&self) -> Type {
+        match self {
+            Shape::Primitive(p) => Type::Primitive(*p),
+            Shape::Fixed(fixed) => Type::Fixed(*fixed),
+            Shape::Wide(wide) => Type::Wide(*wide),
+            Shape::Compact(inner) => Type::Compact(inner.to_id()),
+            Shape::Seq(inner) => Type::Seq(inner.to_id()),
+            Shape::Array(inner, len) => Type::Array(inner.to_id(), *len),
+            Shape::Tuple(items) => Type::Tuple(items.iter().map(Shape::to_id).collect()),
+            Shape::LabeledTuple(items) => Type::LabeledTuple(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| (format!("l{i}"), item.to_id()))
+                    .collect(),
+            ),
+            Shape::Struct(fields) => Type::Struct(
+                fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| (format!("f{i}").as_str().into(), field.to_id()))
+                    .collect(),
+                Vec::new(),
+            ),
+            Shape::Enum(variants) => Type::Enum(Enum::new(
+                variants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        (
+                            format!("V{i}").as_str().into(),
+                            v.as_ref().map(Shape::to_id),
+                            None,
+                        )
+                    })
+                    .collect(),
+            )),
+            Shape::Option(inner) => Type::Enum(Enum::new(alloc::vec![
+                ("_None".into(), None, None),
+                ("_Some".into(), Some(inner.to_id()), None),
+            ])),
+        }
+    }
+
+    fn to_id(&self) -> Id {
+        Id::from(self.to_type())
+    }
+
+    /// A registry holding this shape as the type `T`.
+    pub fn registry(&self) -> TypeRegistry {
+        let registry = TypeRegistry::no_std();
+        registry
+            .define("T", TypeSpec(self.to_type()))
+            .expect("new registry in use");
+        registry
+    }
+
+    fn is_u8(&self) -> bool {
+        matches!(self, Shape::Primitive(PrimitiveType::U8))
+    }
+}
+
+/// A decoded value, compared in Rust so that a mismatch shows both sides.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sample {
+    /// A Number holding an integer.
+    Number(i64),
+    /// A Number with a fraction, like the `asNumber` of a fixed-point number.
+    Float(f64),
+    /// A BigInt, in decimal.
+    BigInt(String),
+    Bool(bool),
+    Str(String),
+    /// A Uint8Array, what sequences and arrays of `u8` decode to.
+    Bytes(Vec<u8>),
+    Null,
+    Array(Vec<Sample>),
+    Object(Vec<(String, Sample)>),
+}
+
+impl Sample {
+    /// A 64 bit integer the way the decoder returns it: a Number within the `i32` range, a BigInt
+    /// outside.
+    pub fn int(val: i128) -> Self {
+        if (i32::MIN as i128..=i32::MAX as i128).contains(&val) {
+            Sample::Number(val as i64)
+        } else {
+            Sample::BigInt(val.to_string())
+        }
+    }
+
+    /// A Number the way it is read back, an integer if it has no fraction.
+    pub fn number(val: f64) -> Self {
+        if val.fract() == 0.0 && val.abs() < i64::MAX as f64 {
+            Sample::Number(val as i64)
+        } else {
+            Sample::Float(val)
+        }
+    }
+
+    /// A fixed-point number of `raw` parts, the way the decoder returns it.
+    pub fn fixed(fixed: FixedPoint, raw: u128) -> Self {
+        let raw_sample = match fixed {
+            FixedPoint::FixedU128 => Sample::BigInt(raw.to_string()),
+            _ => Sample::Number(raw as i64),
+        };
+        Sample::Object(alloc::vec![
+            ("raw".into(), raw_sample),
+            (
+                "asNumber".into(),
+                Sample::number(raw as f64 / fixed.accuracy() as f64),
+            ),
+        ])
+    }
+
+    /// A wide type of the little-endian `bytes`, the way the decoder returns it: a hex string
+    /// of the hashes, a BigInt of `U256`.
+    pub fn wide(wide: Wide, bytes: &[u8]) -> Self {
+        match wide {
+            Wide::U256 => Sample::BigInt(le_decimal(bytes)),
+            _ => Sample::Str(bytes.iter().fold("0x".into(), |mut hex, b| {
+                hex.push_str(&format!("{b:02x}"));
+                hex
+            })),
+        }
+    }
+
+    pub fn to_js(&self, ctx: &js::Context) -> js::Result<js::Value> {
+        Ok(match self {
+            Sample::Number(n) => js::Value::from_f64(ctx, *n as f64),
+            Sample::Float(n) => js::Value::from_f64(ctx, *n),
+            Sample::BigInt(n) => js::Value::bigint_from_str(ctx, n)?,
+            Sample::Bool(b) => js::Value::from_bool(ctx, *b),
+            Sample::Str(s) => js::Value::from_str(ctx, s),
+            Sample::Bytes(bytes) => js::Value::from_bytes(ctx, bytes),
+            Sample::Null => js::Value::Null,
+            Sample::Array(items) => {
+                let out = ctx.new_array();
+                for item in items {
+                    out.array_push(&item.to_js(ctx)?)?;
+                }
+                out
+            }
+            Sample::Object(fields) => {
+                let out = ctx.new_object("");
+                for (name, value) in fields {
+                    out.set_property(name, &value.to_js(ctx)?)?;
+                }
+                out
+            }
+        })
+    }
+
+    pub fn from_js(value: &js::Value) -> js::Result<Self> {
+        Ok(if value.is_null() {
+            Sample::Null
+        } else if value.is_bool() {
+            Sample::Bool(value.decode_bool()?)
+        } else if value.is_number() {
+            Sample::number(value.decode_f64()?)
+        } else if value.is_big_int() {
+            Sample::BigInt(value.to_string())
+        } else if value.is_string() {
+            Sample::Str(value.decode_string()?)
+        } else if value.is_uint8_array() {
+            Sample::Bytes(value.decode_bytes()?)
+        } else if value.is_array() {
+            let items = (0..value.length()?)
+                .map(|i| Sample::from_js(&value.index(i)?))
+                .collect::<js::Result<_>>()?;
+            Sample::Array(items)
+        } else if value.is_object() {
+            let fields = value
+                .entries()?
+                .map(|pair| {
+                    let (key, value) = pair?;
+                    Ok((key.decode_string()?, Sample::from_js(&value)?))
+                })
+                .collect::<js::Result<_>>()?;
+            Sample::Object(fields)
+        } else {
+            anyhow::bail!("unexpected decoded {}", value.describe_type())
+        })
+    }
+}
+
+/// The decimal digits of the little-endian unsigned integer `bytes`.
+fn le_decimal(bytes: &[u8]) -> String {
+    let mut limbs: Vec<u32> = bytes
+        .chunks(4)
+        .rev()
+        .map(|chunk| chunk.iter().rev().fold(0, |n, b| n << 8 | u32::from(*b)))
+        .collect();
+    let mut digits = Vec::new();
+    while limbs.iter().any(|&limb| limb != 0) {
+        let mut rem = 0u64;
+        for limb in limbs.iter_mut() {
+            let n = rem << 32 | u64::from(*limb);
+            *limb = (n / 10) as u32;
+            rem = n % 10;
+        }
+        digits.push(b'0' + rem as u8);
+    }
+    if digits.is_empty() {
+        digits.push(b'0');
+    }
+    digits.iter().rev().map(|&d| d as char).collect()
+}
+
+/// Encode `sample` as `shape` and decode it back.
+pub fn roundtrip(ctx: &js::Context, shape: &Shape, sample: &Sample) -> js::Result<Sample> {
+    let registry = shape.registry();
+    let bytes = encode_value(&sample.to_js(ctx)?, "T", &registry)?;
+    Sample::from_js(&decode_value(ctx, &bytes, "T", &registry)?)
+}
+
+/// Decode arbitrary `bytes` as `shape`. Failing is fine, panicking is not. What decodes must
+/// encode again and decode to the same value.
+pub fn check_decode(ctx: &js::Context, shape: &Shape, bytes: &[u8]) {
+    let registry = shape.registry();
+    let Ok(value) = decode_value(ctx, bytes, "T", &registry) else {
+        return;
+    };
+    let decoded = Sample::from_js(&value).expect("decoded value not understood");
+    let encoded = encode_value(&value, "T", &registry).expect("decoded value does not encode");
+    let again =
+        decode_value(ctx, &encoded, "T", &registry).expect("re-encoded value does not decode");
+    assert_eq!(
+        Sample::from_js(&again).expect("decoded value not understood"),
+        decoded
+    );
+}
+
+#[cfg(feature = "fuzzing")]
+impl Shape {
+    /// A shape nested at most `depth` levels deep, for the fuzz target.
+    pub fn arbitrary(u: &mut arbitrary::Unstructured, depth: u32) -> arbitrary::Result<Self> {
+        let kind = if depth == 0 {
+            u.int_in_range(0..=3)?
+        } else {
+            u.int_in_range(0..=10)?
+        };
+        Ok(match kind {
+            0 => Shape::Primitive(*u.choose(&PRIMITIVES)?),
+            1 => Shape::Fixed(*u.choose(&FixedPoint::ALL)?),
+            2 => Shape::Wide(*u.choose(&Wide::ALL)?),
+            3 => Shape::Compact(Box::new(u.choose(&compactable())?.clone())),
+            4 => Shape::Seq(Box::new(Shape::arbitrary(u, depth - 1)?)),
+            5 => {
+                let inner = Shape::arbitrary(u, depth - 1)?;
+                Shape::Array(Box::new(inner), u.int_in_range(0..=8)?)
+            }
+            6..=9 => {
+                let len = u.int_in_range(1..=4)?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(if kind == 9 && u.arbitrary()? {
+                        None
+                    } else {
+                        Some(Shape::arbitrary(u, depth - 1)?)
+                    });
+                }
+                match kind {
+                    6 => Shape::Tuple(items.into_iter().flatten().collect()),
+                    7 => Shape::LabeledTuple(items.into_iter().flatten().collect()),
+                    8 => Shape::Struct(items.into_iter().flatten().collect()),
+                    _ => Shape::Enum(items),
+                }
+            }
+            _ => match Shape::arbitrary(u, depth - 1)? {
+                Shape::Option(inner) => Shape::Option(inner),
+                inner => Shape::Option(Box::new(inner)),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod strategy {
+    //! Proptest strategies for shapes and their samples.
+
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    fn primitive_sample(p: PrimitiveType) -> BoxedStrategy<Sample> {
+        match p {
+            PrimitiveType::U8 => any::<u8>().prop_map(|n| Sample::Number(n.into())).boxed(),
+            PrimitiveType::U16 => any::<u16>().prop_map(|n| Sample::Number(n.into())).boxed(),
+            PrimitiveType::U32 => any::<u32>().prop_map(|n| Sample::Number(n.into())).boxed(),
+            PrimitiveType::I8 => any::<i8>().prop_map(|n| Sample::Number(n.into())).boxed(),
+            PrimitiveType::I16 => any::<i16>().prop_map(|n| Sample::Number(n.into())).boxed(),
+            PrimitiveType::I32 => any::<i32>().prop_map(|n| Sample::Number(n.into())).boxed(),
+            PrimitiveType::U64 => any::<u64>().prop_map(|n| Sample::int(n.into())).boxed(),
+            PrimitiveType::I64 => any::<i64>().prop_map(|n| Sample::int(n.into())).boxed(),
+            PrimitiveType::U128 => any::<u128>()
+                .prop_map(|n| Sample::BigInt(n.to_string()))
+                .boxed(),
+            PrimitiveType::I128 => any::<i128>()
+                .prop_map(|n| Sample::BigInt(n.to_string()))
+                .boxed(),
+            PrimitiveType::Bool => any::<bool>().prop_map(Sample::Bool).boxed(),
+            PrimitiveType::Str => "\\PC{0,8}".prop_map(Sample::Str).boxed(),
+        }
+    }
+
+    pub fn shape() -> impl Strategy<Value = Shape> {
+        let leaf = prop_oneof![
+            proptest::sample::select(&PRIMITIVES[..]).prop_map(Shape::Primitive),
+            proptest::sample::select(&FixedPoint::ALL[..]).prop_map(Shape::Fixed),
+            proptest::sample::select(&Wide::ALL[..]).prop_map(Shape::Wide),
+            proptest::sample::select(compactable()).prop_map(|s| Shape::Compact(Box::new(s))),
+        ];
+        leaf.prop_recursive(3, 32, 4, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|s| Shape::Seq(Box::new(s))),
+                (inner.clone(), 0..5u32).prop_map(|(s, len)| Shape::Array(Box::new(s), len)),
+                vec(inner.clone(), 1..4).prop_map(Shape::Tuple),
+                vec(inner.clone(), 1..4).prop_map(Shape::LabeledTuple),
+                vec(inner.clone(), 1..4).prop_map(Shape::Struct),
+                vec(proptest::option::of(inner.clone()), 1..4).prop_map(Shape::Enum),
+                inner.prop_map(|s| match s {
+                    Shape::Option(s) => Shape::Option(s),
+                    s => Shape::Option(Box::new(s)),
+                }),
+            ]
+        })
+    }
+
+    pub fn sample(shape: &Shape) -> BoxedStrategy<Sample> {
+        match shape {
+            Shape::Primitive(p) => primitive_sample(*p),
+            Shape::Fixed(fixed) => {
+                let fixed = *fixed;
+                let max = match fixed {
+                    FixedPoint::FixedU128 => u128::MAX,
+                    _ => fixed.accuracy(),
+                };
+                (0..=max)
+                    .prop_map(move |raw| Sample::fixed(fixed, raw))
+                    .boxed()
+            }
+            Shape::Wide(wide) => {
+                let wide = *wide;
+                vec(any::<u8>(), wide.encoded_len())
+                    .prop_map(move |bytes| Sample::wide(wide, &bytes))
+                    .boxed()
+            }
+            Shape::Compact(inner) => sample(inner),
+            Shape::Seq(inner) if inner.is_u8() => {
+                vec(any::<u8>(), 0..8).prop_map(Sample::Bytes).boxed()
+            }
+            Shape::Seq(inner) => vec(sample(inner), 0..4).prop_map(Sample::Array).boxed(),
+            Shape::Array(inner, len) if inner.is_u8() => vec(any::<u8>(), *len as usize)
+                .prop_map(Sample::Bytes)
+                .boxed(),
+            Shape::Array(inner, len) => vec(sample(inner), *len as usize)
+                .prop_map(Sample::Array)
+                .boxed(),
+            Shape::Tuple(items) => items
+                .iter()
+                .map(sample)
+                .collect::<Vec<_>>()
+                .prop_map(Sample::Array)
+                .boxed(),
+            Shape::LabeledTuple(items) => items
+                .iter()
+                .map(sample)
+                .collect::<Vec<_>>()
+                .prop_map(|values| {
+                    let items = values
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, value)| (format!("l{i}"), value))
+                        .collect();
+                    Sample::Object(items)
+                })
+                .boxed(),
+            Shape::Struct(fields) => fields
+                .iter()
+                .map(sample)
+                .collect::<Vec<_>>()
+                .prop_map(|values| {
+                    let fields = values
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, value)| (format!("f{i}"), value))
+                        .collect();
+                    Sample::Object(fields)
+                })
+                .boxed(),
+            Shape::Enum(variants) => {
+                let variants = variants
+                    .iter()
+                    .enumerate()
+                    .map(|(i, variant)| {
+                        let name = format!("V{i}");
+                        let value = match variant {
+                            Some(shape) => sample(shape),
+                            None => Just(Sample::Null).boxed(),
+                        };
+                        value.prop_map(move |value| {
+                            Sample::Object(alloc::vec![(name.clone(), value)])
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                proptest::strategy::Union::new(variants).boxed()
+            }
+            Shape::Option(inner) => prop_oneof![Just(Sample::Null), sample(inner)].boxed(),
+        }
+    }
+
+    /// A shape and a sample of it.
+    pub fn typed_sample() -> impl Strategy<Value = (Shape, Sample)> {
+        shape().prop_flat_map(|shape| (Just(shape.clone()), sample(&shape)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::test_runner::{Config, TestRunner};
+
+    #[test]
+    fn decode_inverts_encode() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let mut runner = TestRunner::new(Config::default());
+        runner
+            .run(&strategy::typed_sample(), |(shape, sample)| {
+                let decoded = roundtrip(&ctx, &shape, &sample)
+                    .map_err(|err| TestCaseError::fail(format!("{err:?}")))?;
+                prop_assert_eq!(decoded, sample);
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn every_kind_of_type_roundtrips() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let u32_shape = || Box::new(Shape::Primitive(PrimitiveType::U32));
+        let cases = [
+            (
+                Shape::Fixed(FixedPoint::Perbill),
+                Sample::fixed(FixedPoint::Perbill, 125_000_000),
+            ),
+            (
+                Shape::Fixed(FixedPoint::FixedU128),
+                Sample::fixed(FixedPoint::FixedU128, u128::MAX),
+            ),
+            (
+                Shape::Compact(Box::new(Shape::Fixed(FixedPoint::Percent))),
+                Sample::fixed(FixedPoint::Percent, 100),
+            ),
+            (
+                Shape::Compact(Box::new(Shape::Tuple(Vec::new()))),
+                Sample::Array(Vec::new()),
+            ),
+            (
+                Shape::Wide(Wide::H160),
+                Sample::wide(Wide::H160, &[0xab; 20]),
+            ),
+            (
+                Shape::Wide(Wide::U256),
+                Sample::wide(Wide::U256, &[0xff; 32]),
+            ),
+            (
+                Shape::LabeledTuple(alloc::vec![
+                    *u32_shape(),
+                    Shape::Primitive(PrimitiveType::Bool)
+                ]),
+                Sample::Object(alloc::vec![
+                    ("l0".into(), Sample::Number(7)),
+                    ("l1".into(), Sample::Bool(true)),
+                ]),
+            ),
+            // Zero-sized elements, any count of them fits in the length prefix alone.
+            (
+                Shape::Seq(Box::new(Shape::Array(u32_shape(), 0))),
+                Sample::Array(alloc::vec![Sample::Array(Vec::new()); 1000]),
+            ),
+        ];
+        for (shape, sample) in cases {
+            let decoded = roundtrip(&ctx, &shape, &sample).unwrap();
+            assert_eq!(decoded, sample, "{shape:?}");
+        }
+        assert_eq!(
+            Sample::wide(Wide::U256, &[0xff; 32]),
+            Sample::BigInt(
+                "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn decoding_arbitrary_bytes_does_not_panic() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let mut runner = TestRunner::new(Config::default());
+        let input = (
+            strategy::shape(),
+            proptest::collection::vec(any::<u8>(), 0..64),
+        );
+        runner
+            .run(&input, |(shape, bytes)| {
+                check_decode(&ctx, &shape, &bytes);
+                Ok(())
+            })
+            .unwrap();
+    }
+}
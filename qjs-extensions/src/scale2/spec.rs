@@ -0,0 +1,106 @@
+//! The public description of types for [`TypeRegistry`](super::TypeRegistry), kept apart from the
+//! parser's AST so that it can change without breaking embedders.
+
+use alloc::vec::Vec;
+
+use super::parser::{Enum, Id, String as TinyString, Type};
+
+/// A reference to a type, by name like `AccountId` or `Vec<u8>`, or to an anonymous
+/// [`TypeSpec`].
+#[derive(Debug, Clone)]
+pub struct TypeRef(pub(super) Id);
+
+impl TypeRef {
+    /// The type named `name`, resolved when the registry encodes or decodes.
+    pub fn named(name: &str) -> Self {
+        Self(Id::from(name))
+    }
+
+    /// The generic type `name` applied to `args`, `Option<T>` for example.
+    pub fn generic(name: &str, args: impl IntoIterator<Item = TypeRef>) -> Self {
+        let mut id = Id::from(name);
+        id.type_args = args.into_iter().map(|arg| arg.0).collect();
+        Self(id)
+    }
+}
+
+impl From<&str> for TypeRef {
+    fn from(name: &str) -> Self {
+        Self::named(name)
+    }
+}
+
+impl From<TypeSpec> for TypeRef {
+    fn from(spec: TypeSpec) -> Self {
+        Self(Id::from(spec.0))
+    }
+}
+
+/// The definition of a type, given a name with [`TypeRegistry::define`](super::TypeRegistry::define)
+/// or used in place with [`TypeRef::from`].
+#[derive(Debug, Clone)]
+pub struct TypeSpec(pub(super) Type);
+
+impl TypeSpec {
+    /// Another name of `target`.
+    pub fn alias(target: impl Into<TypeRef>) -> Self {
+        Self(Type::Alias(target.into().0))
+    }
+
+    /// `Compact<inner>`.
+    pub fn compact(inner: impl Into<TypeRef>) -> Self {
+        Self(Type::Compact(inner.into().0))
+    }
+
+    /// `Vec<element>`, prefixed with its length.
+    pub fn seq(element: impl Into<TypeRef>) -> Self {
+        Self(Type::Seq(element.into().0))
+    }
+
+    /// `[element; len]`.
+    pub fn array(element: impl Into<TypeRef>, len: u32) -> Self {
+        Self(Type::Array(element.into().0, len))
+    }
+
+    /// A tuple, encoded in order.
+    pub fn tuple(elements: impl IntoIterator<Item = TypeRef>) -> Self {
+        Self(Type::Tuple(elements.into_iter().map(|ty| ty.0).collect()))
+    }
+
+    /// A tuple encoded in order and decoded to an object keyed by the labels.
+    pub fn labeled_tuple<'a>(elements: impl IntoIterator<Item = (&'a str, TypeRef)>) -> Self {
+        Self(Type::LabeledTuple(labeled(elements)))
+    }
+
+    /// A struct, its fields encoded in order.
+    pub fn structure<'a>(fields: impl IntoIterator<Item = (&'a str, TypeRef)>) -> Self {
+        Self(Type::Struct(labeled(fields), Vec::new()))
+    }
+
+    /// An enum, its variants indexed in order.
+    pub fn enumeration<'a>(variants: impl IntoIterator<Item = (&'a str, Option<TypeRef>)>) -> Self {
+        let variants = variants
+            .into_iter()
+            .map(|(name, ty)| (name.into(), ty.map(|ty| ty.0), None))
+            .collect();
+        Self(Type::Enum(Enum::new(variants)))
+    }
+
+    /// An enum with explicit variant indices, like `#[codec(index = ..)]`.
+    pub fn indexed_enumeration<'a>(
+        variants: impl IntoIterator<Item = (&'a str, Option<TypeRef>, u32)>,
+    ) -> Self {
+        let variants = variants
+            .into_iter()
+            .map(|(name, ty, index)| (name.into(), ty.map(|ty| ty.0), Some(index)))
+            .collect();
+        Self(Type::Enum(Enum::new(variants)))
+    }
+}
+
+fn labeled<'a>(elements: impl IntoIterator<Item = (&'a str, TypeRef)>) -> Vec<(TinyString, Id)> {
+    elements
+        .into_iter()
+        .map(|(name, ty)| (name.into(), ty.0))
+        .collect()
+}
@@ -0,0 +1,415 @@
+//! Incremental decoding of a value split across several buffers, `scl.decoder(ty, registry)`.
+//!
+//! Composite values are built with an explicit stack of frames instead of recursion, so the
+//! decoder can stop wherever a chunk ends and pick up on the next one. A leaf, a number or a
+//! string or a byte sequence, is only decoded once all of its bytes are buffered, by the one-shot
+//! decoder.
+//!
+//! The lengths are checked as they are read, since the rest of the input is not known yet:
+//! leaves against `max_string_bytes` and sequences against `max_array_len` of the context's
+//! conversion limits, and sequences of zero-sized elements as `scl.decode` does.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use anyhow::bail;
+use parity_scale_codec::{Compact, Decode};
+
+use super::{
+    check_zero_sized_len, decode_valude, min_encoded_len, Id, PrimitiveType, Registry, TinyString,
+    Type, TypeRegistry,
+};
+use js::{ErrorContext, NoGc};
+
+pub use self::native_classes::ScaleDecoder;
+
+#[js::host_call(with_context)]
+pub(super) fn decoder(
+    ctx: js::Context,
+    _this: js::Value,
+    tid: Id,
+    type_registry: TypeRegistry,
+) -> js::Result<js::Native<ScaleDecoder>> {
+    use js::IntoNativeObject;
+    ScaleDecoder {
+        registry: NoGc(type_registry),
+        stream: Stream::new(tid),
+    }
+    .into_native_object(&ctx)
+}
+
+#[js::qjsbind]
+mod native_classes {
+    use super::{NoGc, Stream, TypeRegistry};
+
+    #[qjs(class(rename_all = "camelCase"))]
+    pub struct ScaleDecoder {
+        pub(super) registry: NoGc<TypeRegistry>,
+        pub(super) stream: Stream,
+    }
+
+    impl ScaleDecoder {
+        /// Append `chunk` and decode as far as the buffered bytes go.
+        #[qjs(method)]
+        fn push(
+            &mut self,
+            #[qjs(from_context)] ctx: js::Context,
+            chunk: js::JsUint8Array,
+        ) -> js::Result<()> {
+            let registry = self.registry.borrow();
+            self.stream.push(&ctx, chunk.as_bytes(), &registry)
+        }
+
+        /// The decoded value. Fails if the value is incomplete or followed by more bytes.
+        #[qjs(method)]
+        fn finish(&mut self) -> js::Result<js::Value> {
+            self.stream.finish()
+        }
+    }
+}
+
+/// The decoding state of `scl.decoder`.
+#[derive(js::GcMark)]
+pub(super) struct Stream {
+    #[gc(skip)]
+    ty: Id,
+    /// The bytes not consumed yet.
+    buf: Vec<u8>,
+    stack: Vec<Frame>,
+    result: Option<js::Value>,
+    /// The error that stopped the decoder, reported again by later calls.
+    error: Option<String>,
+}
+
+/// A composite value being built.
+#[derive(js::GcMark)]
+struct Frame {
+    /// The array or object the children are stored into, `None` for the payload of an `Option`,
+    /// which is passed to the parent as it is.
+    out: Option<js::Value>,
+    #[gc(skip)]
+    children: Children,
+}
+
+enum Children {
+    /// The elements of a sequence or an array.
+    Repeat { ty: Id, remaining: u32 },
+    /// The fields of a struct, the elements of a tuple or the payload of an enum. Named children
+    /// are set as properties and the others pushed to the array.
+    List {
+        items: Vec<(Option<TinyString>, Id)>,
+        next: usize,
+    },
+}
+
+enum Step {
+    Value(js::Value),
+    Frame(Frame),
+}
+
+impl Frame {
+    fn list(out: Option<js::Value>, items: Vec<(Option<TinyString>, Id)>) -> Self {
+        Self {
+            out,
+            children: Children::List { items, next: 0 },
+        }
+    }
+
+    /// The type of the next child, `None` once the value is complete.
+    fn peek(&self) -> Option<&Id> {
+        match &self.children {
+            Children::Repeat { ty, remaining } => (*remaining > 0).then_some(ty),
+            Children::List { items, next } => items.get(*next).map(|(_, ty)| ty),
+        }
+    }
+
+    /// Store the next child. Returns it back if this frame only passes it through.
+    fn accept(&mut self, value: js::Value) -> js::Result<Option<js::Value>> {
+        let name = match &mut self.children {
+            Children::Repeat { remaining, .. } => {
+                *remaining -= 1;
+                None
+            }
+            Children::List { items, next } => {
+                *next += 1;
+                items[*next - 1].0.clone()
+            }
+        };
+        match (&self.out, name) {
+            (None, _) => return Ok(Some(value)),
+            (Some(out), Some(name)) => out.set_property(&name, &value)?,
+            (Some(out), None) => out.array_push(&value)?,
+        }
+        Ok(None)
+    }
+}
+
+impl Stream {
+    fn new(ty: Id) -> Self {
+        Self {
+            ty,
+            buf: Vec::new(),
+            stack: Vec::new(),
+            result: None,
+            error: None,
+        }
+    }
+
+    pub(super) fn push(
+        &mut self,
+        ctx: &js::Context,
+        chunk: &[u8],
+        registry: &Registry,
+    ) -> js::Result<()> {
+        if let Some(error) = &self.error {
+            bail!("decoder failed earlier: {error}");
+        }
+        self.buf.extend_from_slice(chunk);
+        let mut pos = 0;
+        let result = self.advance(ctx, registry, &mut pos);
+        self.buf.drain(..pos);
+        if let Err(err) = &result {
+            self.error = Some(err.to_string());
+        }
+        result
+    }
+
+    pub(super) fn finish(&mut self) -> js::Result<js::Value> {
+        if let Some(error) = &self.error {
+            bail!("decoder failed earlier: {error}");
+        }
+        let Some(value) = &self.result else {
+            bail!("incomplete value, {} bytes buffered", self.buf.len());
+        };
+        if !self.buf.is_empty() {
+            bail!("{} trailing bytes after the value", self.buf.len());
+        }
+        Ok(value.clone())
+    }
+
+    /// Decode from `self.buf[*pos..]` until the value is complete or more bytes are needed,
+    /// moving `pos` past the bytes consumed.
+    fn advance(
+        &mut self,
+        ctx: &js::Context,
+        registry: &Registry,
+        pos: &mut usize,
+    ) -> js::Result<()> {
+        while self.result.is_none() {
+            let ty = match self.stack.last() {
+                None => self.ty.clone(),
+                Some(frame) => match frame.peek() {
+                    Some(ty) => ty.clone(),
+                    None => {
+                        let frame = self.stack.pop().expect("frame just peeked");
+                        self.deliver(frame.out.expect("pass-through frames are never complete"))?;
+                        continue;
+                    }
+                },
+            };
+            let Some((consumed, step)) = start(ctx, &self.buf[*pos..], &ty, registry)? else {
+                return Ok(());
+            };
+            *pos += consumed;
+            match step {
+                Step::Value(value) => self.deliver(value)?,
+                Step::Frame(frame) => self.stack.push(frame),
+            }
+        }
+        Ok(())
+    }
+
+    fn deliver(&mut self, mut value: js::Value) -> js::Result<()> {
+        while let Some(frame) = self.stack.last_mut() {
+            match frame.accept(value)? {
+                None => return Ok(()),
+                Some(passed) => {
+                    self.stack.pop();
+                    value = passed;
+                }
+            }
+        }
+        self.result = Some(value);
+        Ok(())
+    }
+}
+
+/// Start decoding a value of type `ty` from `buf`: a complete leaf, or the frame of a composite
+/// value with the bytes of its header consumed. `None` if more bytes are needed first.
+fn start(
+    ctx: &js::Context,
+    buf: &[u8],
+    ty: &Id,
+    registry: &Registry,
+) -> js::Result<Option<(usize, Step)>> {
+    let t = registry.resolve_type(ty, true)?;
+    let step = match t.as_ref() {
+        Type::Alias(_) => unreachable!("Alias should be resolved"),
+        Type::Primitive(PrimitiveType::Str) => {
+            let Some((prefix, len)) = seq_len(buf)? else {
+                return Ok(None);
+            };
+            return leaf(ctx, buf, prefix + len as usize, ty, registry);
+        }
+        Type::Primitive(_) | Type::Fixed(_) | Type::Wide(_) => {
+            return leaf(ctx, buf, min_encoded_len(ty, registry, 0), ty, registry);
+        }
+        Type::Compact(inner) => {
+            // Encoded in no bytes at all.
+            if is_empty_tuple(inner, registry)? {
+                return leaf(ctx, buf, 0, ty, registry);
+            }
+            let Some(len) = compact_len(buf) else {
+                return Ok(None);
+            };
+            return leaf(ctx, buf, len, ty, registry);
+        }
+        Type::Seq(elem) => {
+            let Some((prefix, len)) = seq_len(buf)? else {
+                return Ok(None);
+            };
+            if is_u8(elem, registry)? {
+                return leaf(ctx, buf, prefix + len as usize, ty, registry);
+            }
+            if min_encoded_len(elem, registry, 0) == 0 {
+                check_zero_sized_len(len as usize)?;
+            }
+            check_array_len(ctx, len as usize)?;
+            let frame = Frame {
+                out: Some(ctx.new_array()),
+                children: Children::Repeat {
+                    ty: elem.clone(),
+                    remaining: len,
+                },
+            };
+            return Ok(Some((prefix, Step::Frame(frame))));
+        }
+        Type::Array(elem, len) => {
+            if is_u8(elem, registry)? {
+                return leaf(ctx, buf, *len as usize, ty, registry);
+            }
+            Step::Frame(Frame {
+                out: Some(ctx.new_array()),
+                children: Children::Repeat {
+                    ty: elem.clone(),
+                    remaining: *len,
+                },
+            })
+        }
+        Type::Tuple(tids) => {
+            let items = tids.iter().map(|tid| (None, tid.clone())).collect();
+            Step::Frame(Frame::list(Some(ctx.new_array()), items))
+        }
+        Type::Struct(fields, _) | Type::LabeledTuple(fields) => {
+            let items = fields
+                .iter()
+                .map(|(name, tid)| (Some(name.clone()), tid.clone()))
+                .collect();
+            Step::Frame(Frame::list(Some(ctx.new_object("")), items))
+        }
+        Type::Enum(def) => {
+            let Some(&tag) = buf.first() else {
+                return Ok(None);
+            };
+            let step = if let Some((some_ty, ind)) = def.is_option_and_some_def() {
+                if tag == 0 {
+                    Step::Value(js::Value::Null)
+                } else if tag as u32 == ind {
+                    Step::Frame(Frame::list(None, alloc::vec![(None, some_ty.clone())]))
+                } else {
+                    bail!("unexpected variant index {tag} for Option<T>");
+                }
+            } else {
+                let (variant_name, variant_type) = def.get_variant_by_index(tag)?;
+                let out = ctx.new_object(&variant_name);
+                match variant_type {
+                    Some(variant_type) => Step::Frame(Frame::list(
+                        Some(out),
+                        alloc::vec![(Some(variant_name), variant_type)],
+                    )),
+                    None => {
+                        out.set_property(&variant_name, &js::Value::Null)?;
+                        Step::Value(out)
+                    }
+                }
+            };
+            return Ok(Some((1, step)));
+        }
+    };
+    Ok(Some((0, step)))
+}
+
+/// Decode a leaf of `len` bytes once they are all buffered, so that a failure is never caused by
+/// a chunk boundary.
+fn leaf(
+    ctx: &js::Context,
+    buf: &[u8],
+    len: usize,
+    ty: &Id,
+    registry: &Registry,
+) -> js::Result<Option<(usize, Step)>> {
+    let max = ctx.conversion_limits().max_string_bytes;
+    if len > max {
+        return Err(js::Error::msg(js::LimitExceeded {
+            limit: "max_string_bytes",
+            max,
+            actual: len,
+        }));
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let mut rest = &buf[..len];
+    let value = decode_valude(ctx, &mut rest, ty, registry)?;
+    // The length is worked out from the header, the decoder must agree.
+    if !rest.is_empty() {
+        bail!("{} bytes of the value were not decoded", rest.len());
+    }
+    Ok(Some((len, Step::Value(value))))
+}
+
+fn check_array_len(ctx: &js::Context, len: usize) -> js::Result<()> {
+    let max = ctx.conversion_limits().max_array_len;
+    if len > max {
+        return Err(js::Error::msg(js::LimitExceeded {
+            limit: "max_array_len",
+            max,
+            actual: len,
+        }));
+    }
+    Ok(())
+}
+
+/// The length of the compact integer at the start of `buf`, known from its first byte.
+fn compact_len(buf: &[u8]) -> Option<usize> {
+    let first = *buf.first()?;
+    Some(match first & 0b11 {
+        0b00 => 1,
+        0b01 => 2,
+        0b10 => 4,
+        _ => 5 + (first >> 2) as usize,
+    })
+}
+
+/// The length of the compact length prefix at the start of `buf` and the length it declares.
+fn seq_len(buf: &[u8]) -> js::Result<Option<(usize, u32)>> {
+    let Some(prefix) = compact_len(buf) else {
+        return Ok(None);
+    };
+    if buf.len() < prefix {
+        return Ok(None);
+    }
+    let len = Compact::<u32>::decode(&mut &buf[..prefix])
+        .context("failed to decode sequence length")?
+        .0;
+    Ok(Some((prefix, len)))
+}
+
+fn is_u8(ty: &Id, registry: &Registry) -> js::Result<bool> {
+    let t = registry.resolve_type(ty, false)?;
+    Ok(matches!(t.as_ref(), Type::Primitive(PrimitiveType::U8)))
+}
+
+fn is_empty_tuple(ty: &Id, registry: &Registry) -> js::Result<bool> {
+    let t = registry.resolve_type(ty, false)?;
+    Ok(matches!(t.as_ref(), Type::Tuple(tids) if tids.is_empty()))
+}
@@ -0,0 +1,153 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail};
+use parity_scale_codec::Output;
+
+/// The hashes and the wide integer of Ethereum-compatible chains.
+///
+/// The hashes are byte arrays decoded to 0x-prefixed hex strings, `U256` is a little-endian
+/// unsigned integer decoded to a BigInt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wide {
+    H160,
+    H256,
+    H512,
+    U256,
+}
+
+impl Wide {
+    pub const ALL: [Wide; 4] = [Wide::H160, Wide::H256, Wide::H512, Wide::U256];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Wide::H160 => "H160",
+            Wide::H256 => "H256",
+            Wide::H512 => "H512",
+            Wide::U256 => "U256",
+        }
+    }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        match self {
+            Wide::H160 => 20,
+            Wide::H256 | Wide::U256 => 32,
+            Wide::H512 => 64,
+        }
+    }
+
+    /// Get the little-endian bytes of a `U256` from a BigInt, a non-negative integer, or a
+    /// string of hex digits prefixed with `0x` or decimal digits.
+    pub fn u256_from_js(value: &js::Value) -> js::Result<[u8; 32]> {
+        let digits = if value.is_big_int() {
+            // The engine's own conversion, not the patchable `BigInt.prototype.toString`.
+            return Self::u256_from_decimal(&value.to_string());
+        } else if value.is_number() {
+            alloc::format!("{:x}", value.decode_u64()?)
+        } else if value.is_string() {
+            let text = value.decode_string()?;
+            match text.strip_prefix("0x") {
+                Some(hex) => hex.into(),
+                None => {
+                    let n = js::Value::bigint_from_str(&value.context()?, &text)
+                        .map_err(|_| anyhow!("invalid U256: {text:?}"))?;
+                    return Self::u256_from_js(&n);
+                }
+            }
+        } else {
+            bail!(
+                "expected a BigInt, a number or a hex string for U256, got {}",
+                value.describe_type()
+            );
+        };
+        let invalid = || anyhow!("invalid U256: {digits:?}");
+        let nibbles = digits
+            .bytes()
+            .map(|b| (b as char).to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let significant = match nibbles.iter().position(|&n| n != 0) {
+            Some(first) => &nibbles[first..],
+            None if nibbles.is_empty() => return Err(invalid()),
+            None => &[],
+        };
+        if significant.len() > 64 {
+            bail!("{digits:?} is out of range for U256");
+        }
+        let mut out = [0u8; 32];
+        for (i, nibble) in significant.iter().rev().enumerate() {
+            out[i / 2] |= nibble << (4 * (i % 2));
+        }
+        Ok(out)
+    }
+
+    /// Get the little-endian bytes of a `U256` from its decimal digits.
+    fn u256_from_decimal(digits: &str) -> js::Result<[u8; 32]> {
+        if digits.is_empty() {
+            bail!("invalid U256: {digits:?}");
+        }
+        let mut out = [0u8; 32];
+        for b in digits.bytes() {
+            let Some(digit) = (b as char).to_digit(10) else {
+                bail!("invalid U256: {digits:?}");
+            };
+            let mut carry = digit;
+            for byte in out.iter_mut() {
+                let n = *byte as u32 * 10 + carry;
+                *byte = n as u8;
+                carry = n >> 8;
+            }
+            if carry != 0 {
+                bail!("{digits:?} is out of range for U256");
+            }
+        }
+        Ok(out)
+    }
+
+    pub(crate) fn encode(&self, value: &js::Value, out: &mut impl Output) -> js::Result<()> {
+        if let Wide::U256 = self {
+            out.write(&Self::u256_from_js(value)?);
+            return Ok(());
+        }
+        let len = self.encoded_len();
+        let bytes = super::u8a_or_hex(value, |bytes| {
+            if bytes.len() != len {
+                bail!(
+                    "expected {len} bytes for {}, got {}",
+                    self.name(),
+                    bytes.len()
+                );
+            }
+            out.write(bytes);
+            Ok(())
+        });
+        bytes.unwrap_or_else(|| {
+            Err(anyhow!(
+                "expected a Uint8Array or a hex string for {}, got {}",
+                self.name(),
+                value.describe_type()
+            ))
+        })
+    }
+
+    pub(crate) fn decode(&self, ctx: &js::Context, buf: &mut &[u8]) -> js::Result<js::Value> {
+        let len = self.encoded_len();
+        if buf.len() < len {
+            return Err(super::unexpected_eof());
+        }
+        let (bytes, rest) = buf.split_at(len);
+        *buf = rest;
+        let mut hex = String::from("0x");
+        let push = |hex: &mut String, b: &u8| hex.push_str(&alloc::format!("{b:02x}"));
+        match self {
+            // The number is written big-endian.
+            Wide::U256 => {
+                bytes.iter().rev().for_each(|b| push(&mut hex, b));
+                js::Value::bigint_from_str(ctx, &hex)
+            }
+            _ => {
+                bytes.iter().for_each(|b| push(&mut hex, b));
+                Ok(js::Value::from_str(ctx, &hex))
+            }
+        }
+    }
+}
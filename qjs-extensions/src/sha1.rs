@@ -1,9 +1,39 @@
-use js::{AsBytes, BytesOrString};
+use js::{BytesOrString, JsUint8Array, Result};
 use sha1::{Digest, Sha1};
 
+use crate::output::Output;
+
 #[js::host_call]
-pub fn sha1(data: BytesOrString) -> AsBytes<[u8; 20]> {
+pub fn sha1(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 20]>> {
     let mut hasher = Sha1::new();
     hasher.update(data.as_bytes());
-    AsBytes(hasher.finalize().into())
+    Output::new(hasher.finalize().into(), out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Extensions;
+
+    #[test]
+    fn sha1_into_output_buffer() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        Extensions::new().with_hash().install(&ctx).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let same = eval(
+            r#"
+            const out = new Uint8Array(20);
+            const ret = Hash.sha1("abc", out);
+            ret === out && Hash.sha1("abc").join() === out.join()
+            "#,
+        )
+        .unwrap();
+        assert!(same.decode_bool().unwrap());
+
+        let err = eval(r#"Hash.sha1("abc", new Uint8Array(32))"#).unwrap_err();
+        assert!(
+            err.contains("output buffer must be 20 bytes long, got 32"),
+            "{err}"
+        );
+    }
 }
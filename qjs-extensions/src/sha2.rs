@@ -1,9 +1,77 @@
-use js::{AsBytes, BytesOrString};
+use js::{BytesOrString, JsUint8Array, Result};
 use sha2::{Digest, Sha256};
 
+use crate::output::Output;
+
 #[js::host_call]
-pub fn sha256(data: BytesOrString) -> AsBytes<[u8; 32]> {
+pub fn sha256(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 32]>> {
     let mut hasher = Sha256::new();
     hasher.update(data.as_bytes());
-    AsBytes(hasher.finalize().into())
+    Output::new(hasher.finalize().into(), out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Extensions;
+
+    #[test]
+    fn sha256_can_be_overridden() {
+        let runtime = js::Runtime::new(&Default::default());
+        runtime.enable_host_function_registry();
+        let ctx = runtime.new_context();
+        Extensions::new().with_hash().install(&ctx).unwrap();
+        let digest = || {
+            ctx.eval(&js::Code::Source(
+                r#"
+                ((out) => typeof out == "string"
+                    ? out
+                    : Array.from(out, (b) => b.toString(16).padStart(2, "0")).join(""))(Hash.sha256("abc"))
+                "#,
+            ))
+            .unwrap()
+            .decode_string()
+            .unwrap()
+        };
+        let real = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(digest(), real);
+
+        ctx.override_host_function("Hash.sha256", |ctx, _this, _args| {
+            Ok(ctx.new_string("canned"))
+        })
+        .unwrap();
+        assert_eq!(digest(), "canned");
+        let functions = ctx.host_functions().unwrap();
+        let (_, info) = functions
+            .iter()
+            .find(|(path, _)| path == "Hash.sha256")
+            .unwrap();
+        assert!(info.overridden);
+
+        ctx.restore_host_function("Hash.sha256").unwrap();
+        assert_eq!(digest(), real);
+    }
+
+    #[test]
+    fn sha256_into_output_buffer() {
+        let runtime = js::Runtime::new(&Default::default());
+        runtime.enable_host_function_registry();
+        let ctx = runtime.new_context();
+        Extensions::new().with_hash().install(&ctx).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let same = eval(
+            r#"
+            const out = new Uint8Array(32);
+            const ret = Hash.sha256("abc", out);
+            ret === out && Hash.sha256("abc").every((b, i) => b === out[i])
+            "#,
+        )
+        .unwrap();
+        assert!(same.decode_bool().unwrap());
+
+        let err = eval(r#"Hash.sha256("abc", new Uint8Array(31))"#).unwrap_err();
+        assert!(
+            err.contains("output buffer must be 32 bytes long, got 31"),
+            "{err}"
+        );
+    }
 }
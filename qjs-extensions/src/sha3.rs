@@ -1,16 +1,49 @@
-use js::{AsBytes, BytesOrString};
+use js::{BytesOrString, JsUint8Array, Result};
 pub use sha3::{Digest, Sha3_256, Sha3_512};
 
+use crate::output::Output;
+
 #[js::host_call]
-pub fn sha3_256(data: BytesOrString) -> AsBytes<[u8; 32]> {
+pub fn sha3_256(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 32]>> {
     let mut hasher = Sha3_256::new();
     hasher.update(data.as_bytes());
-    AsBytes(hasher.finalize().into())
+    Output::new(hasher.finalize().into(), out)
 }
 
 #[js::host_call]
-pub fn sha3_512(data: BytesOrString) -> AsBytes<[u8; 64]> {
+pub fn sha3_512(data: BytesOrString, out: Option<JsUint8Array>) -> Result<Output<[u8; 64]>> {
     let mut hasher = Sha3_512::new();
     hasher.update(data.as_bytes());
-    AsBytes(hasher.finalize().into())
+    Output::new(hasher.finalize().into(), out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Extensions;
+
+    #[test]
+    fn sha3_into_output_buffer() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        Extensions::new().with_hash().install(&ctx).unwrap();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        let same = eval(
+            r#"
+            const out256 = new Uint8Array(32);
+            const out512 = new Uint8Array(64);
+            Hash.sha3_256("abc", out256) === out256
+                && Hash.sha3_256("abc").join() === out256.join()
+                && Hash.sha3_512("abc", out512) === out512
+                && Hash.sha3_512("abc").join() === out512.join()
+            "#,
+        )
+        .unwrap();
+        assert!(same.decode_bool().unwrap());
+
+        let err = eval(r#"Hash.sha3_512("abc", out256)"#).unwrap_err();
+        assert!(
+            err.contains("output buffer must be 64 bytes long, got 32"),
+            "{err}"
+        );
+    }
 }
@@ -0,0 +1,92 @@
+use alloc::string::{String, ToString};
+use js::HashKind;
+
+pub fn setup(ns: &js::Value, ctx: &js::Context) -> js::Result<()> {
+    ns.define_property_fn("stableHash", stable_hash)?;
+    let memoize = ctx
+        .eval(&js::Code::Source(
+            r#"(function (stableHash) {
+                "use strict";
+                // Captured now, so that scripts patching the globals later can not reach the
+                // caches or change what they hold.
+                const apply = Reflect.apply;
+                const NewMap = Map;
+                const NewWeakMap = WeakMap;
+                const { has, get, set } = Map.prototype;
+                const weakGet = WeakMap.prototype.get;
+                const weakSet = WeakMap.prototype.set;
+                return function memoize(fn) {
+                    // One cache per object receiver, so that a memoized method called on two
+                    // objects with the same arguments runs for each of them.
+                    const byReceiver = new NewWeakMap();
+                    const unbound = new NewMap();
+                    return function (...args) {
+                        const isObject = (typeof this === "object" && this !== null)
+                            || typeof this === "function";
+                        let cache = unbound;
+                        let key;
+                        if (isObject) {
+                            cache = apply(weakGet, byReceiver, [this]);
+                            if (cache === undefined) {
+                                cache = new NewMap();
+                                apply(weakSet, byReceiver, [this, cache]);
+                            }
+                            key = stableHash(args);
+                        } else {
+                            key = stableHash([this, args]);
+                        }
+                        if (apply(has, cache, [key])) {
+                            return apply(get, cache, [key]);
+                        }
+                        const result = apply(fn, this, args);
+                        apply(set, cache, [key, result]);
+                        return result;
+                    };
+                };
+            })"#,
+        ))
+        .map_err(js::Error::msg)?
+        .call(&js::Value::undefined(), &[ns.get_property("stableHash")?])?;
+    ns.define_property_value("memoize", memoize)?;
+    Ok(())
+}
+
+/// Hash the structure of a value into a hex string, see `js::Value::stable_hash`.
+#[js::host_call]
+fn stable_hash(value: js::Value) -> js::Result<String> {
+    Ok(hex_fmt::HexFmt(value.stable_hash(HashKind::Sha256)?).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Extensions;
+
+    #[test]
+    fn memoize_keeps_a_cache_per_receiver() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        Extensions::new().with_stable_hash().install(&ctx).unwrap();
+        let seen = ctx
+            .eval(&js::Code::Source(
+                r#"
+                let calls = 0;
+                const name = memoize(function (greeting) {
+                    calls++;
+                    return `${greeting} ${this.name}`;
+                });
+                const a = { name: "a", name_: name };
+                const b = { name: "b", name_: name };
+                const plain = memoize((n) => (calls++, n * 2));
+                Map.prototype.get = Map.prototype.has = () => true;
+                Map = WeakMap = function () { return {}; };
+                Reflect.apply = () => "patched";
+                [
+                    a.name_("hi"), b.name_("hi"), a.name_("hi"),
+                    plain(2), plain(2), calls,
+                ].join()
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(seen.to_string(), "hi a,hi b,hi a,4,4,3");
+    }
+}
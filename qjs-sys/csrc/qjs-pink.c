@@ -156,14 +156,21 @@ static JSValue eval_bytecode(JSContext *ctx, const uint8_t *buf,
 }
 
 static int eval_buf(JSContext *ctx, const void *buf, int buf_len,
-                    int is_bytecode, callbacks_t *callbacks) {
+                    int is_bytecode, const char *filename, int line,
+                    int eval_flags, callbacks_t *callbacks) {
     JSValue val;
     int ret;
 
     if (is_bytecode) {
         val = eval_bytecode(ctx, buf, buf_len);
     } else {
-        val = JS_Eval(ctx, buf, buf_len, "<eval>", JS_EVAL_TYPE_GLOBAL);
+        JSEvalOptions options = {
+            .version = JS_EVAL_OPTIONS_VERSION,
+            .eval_flags = JS_EVAL_TYPE_GLOBAL | eval_flags,
+            .filename = filename ? filename : "<eval>",
+            .line_num = line > 0 ? line : 1,
+        };
+        val = JS_Eval2(ctx, buf, buf_len, &options);
     }
     if (JS_IsException(val)) {
         JSValue exception_val = JS_GetException(ctx);
@@ -189,5 +196,5 @@ static int eval_buf(JSContext *ctx, const void *buf, int buf_len,
 
 int js_eval_code(JSContext *ctx, const code_t *code, callbacks_t *callbacks) {
     return eval_buf(ctx, code->code, code->code_len, code->is_bytecode,
-                    callbacks);
+                    code->filename, code->line, code->eval_flags, callbacks);
 }
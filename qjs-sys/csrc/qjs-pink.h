@@ -18,6 +18,12 @@ typedef struct {
     const void *code;
     size_t code_len;
     int is_bytecode;
+    /* The filename reported in errors and stack traces, "<eval>" if NULL. */
+    const char *filename;
+    /* The line number of the first line of source code, starting at 1. */
+    int line;
+    /* Extra JS_EVAL_FLAG_* for source code, like JS_EVAL_FLAG_STRICT. */
+    int eval_flags;
 } code_t;
 
 void js_pink_env_init(JSContext *ctx);
@@ -0,0 +1,22 @@
+//! With the `header` feature, generates `qjsbind.h` into `OUT_DIR`. The header shipped in
+//! `include` is refreshed from that output when the API changes.
+
+fn main() {
+    #[cfg(feature = "header")]
+    generate_header();
+}
+
+#[cfg(feature = "header")]
+fn generate_header() {
+    use std::path::PathBuf;
+
+    let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    cbindgen::generate(&crate_dir)
+        .expect("failed to generate the C header")
+        .write_to_file(out_dir.join("qjsbind.h"));
+}
@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+
+    println!("cargo:rerun-if-changed=c/sha256.c");
+    println!("cargo:rerun-if-changed=../include/qjsbind.h");
+
+    // The C test program, linked into the test binaries and called by `tests/c_api.rs`.
+    cc::Build::new()
+        .file(crate_dir.join("c").join("sha256.c"))
+        .include(crate_dir.join("..").join("include"))
+        .warnings_into_errors(true)
+        .compile("qjsbind_capi_test");
+}
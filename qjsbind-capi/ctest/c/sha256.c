@@ -0,0 +1,83 @@
+/* Drives the C API the way an embedder would. Returns 0 on success, or the number of the
+ * first failed check. */
+
+#include <string.h>
+
+#include "qjsbind.h"
+
+#define EVAL(ctx, src, out) qjsbind_context_eval(ctx, src, strlen(src), out)
+
+static int run(QjsbindContext *ctx)
+{
+    static const uint8_t expected[32] = {
+        0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22, 0x23,
+        0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad,
+    };
+    uint8_t *digest = NULL;
+    size_t digest_len = 0;
+    char *text = NULL;
+    const char *err;
+    int ok;
+
+    if (qjsbind_context_install_extension(ctx, "hash") != QJSBIND_STATUS_OK)
+        return 1;
+    if (qjsbind_context_set_bytes(ctx, "input", (const uint8_t *)"abc", 3) != QJSBIND_STATUS_OK)
+        return 2;
+    if (EVAL(ctx, "digest = Hash.sha256(input)", NULL) != QJSBIND_STATUS_OK)
+        return 3;
+    if (qjsbind_context_get_bytes(ctx, "digest", &digest, &digest_len) != QJSBIND_STATUS_OK)
+        return 4;
+    ok = digest_len == sizeof(expected) && memcmp(digest, expected, sizeof(expected)) == 0;
+    qjsbind_bytes_free(digest, digest_len);
+    if (!ok)
+        return 5;
+
+    if (qjsbind_context_set_string(ctx, "name", "wörld", strlen("wörld")) != QJSBIND_STATUS_OK)
+        return 6;
+    if (EVAL(ctx, "greeting = `hello ${name}`", &text) != QJSBIND_STATUS_OK)
+        return 7;
+    ok = strcmp(text, "hello wörld") == 0;
+    qjsbind_string_free(text);
+    if (!ok)
+        return 8;
+    if (qjsbind_context_get_string(ctx, "greeting", &text) != QJSBIND_STATUS_OK)
+        return 9;
+    ok = strcmp(text, "hello wörld") == 0;
+    qjsbind_string_free(text);
+    if (!ok)
+        return 10;
+
+    if (EVAL(ctx, "throw new Error('boom')", NULL) != QJSBIND_STATUS_ERROR)
+        return 11;
+    err = qjsbind_context_last_error(ctx);
+    if (err == NULL || strstr(err, "boom") == NULL)
+        return 12;
+    if (qjsbind_context_get_bytes(ctx, "greeting", &digest, &digest_len) != QJSBIND_STATUS_ERROR)
+        return 13;
+    if (qjsbind_context_install_extension(ctx, "nope") != QJSBIND_STATUS_INVALID_ARGUMENT)
+        return 14;
+    if (qjsbind_context_install_extension(ctx, NULL) != QJSBIND_STATUS_INVALID_ARGUMENT)
+        return 15;
+    if (EVAL(ctx, "1 + 1", NULL) != QJSBIND_STATUS_OK || qjsbind_context_last_error(ctx) != NULL)
+        return 16;
+    return 0;
+}
+
+int qjsbind_capi_sha256_test(void)
+{
+    QjsbindRuntime *rt = qjsbind_runtime_new();
+    QjsbindContext *ctx;
+    int status;
+
+    if (rt == NULL)
+        return 100;
+    ctx = qjsbind_context_new(rt);
+    if (ctx == NULL) {
+        qjsbind_runtime_free(rt);
+        return 101;
+    }
+    status = run(ctx);
+    qjsbind_context_free(ctx);
+    qjsbind_runtime_free(rt);
+    return status;
+}
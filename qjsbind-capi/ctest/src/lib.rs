@@ -0,0 +1 @@
+//! C programs exercising the qjsbind-capi library. Test-only, never published.
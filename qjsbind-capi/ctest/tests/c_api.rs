@@ -0,0 +1,15 @@
+//! Runs the C program in `c`, compiled against the shipped header by the build script.
+
+use std::ffi::c_int;
+
+// Link the library the C program calls into.
+use qjsbind_capi as _;
+
+extern "C" {
+    fn qjsbind_capi_sha256_test() -> c_int;
+}
+
+#[test]
+fn c_program_hashes_through_the_api() {
+    assert_eq!(unsafe { qjsbind_capi_sha256_test() }, 0);
+}
@@ -0,0 +1,183 @@
+#ifndef QJSBIND_H
+#define QJSBIND_H
+
+/* Generated with cbindgen from qjsbind-capi/src/lib.rs, do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ The result of a call. On anything but `QJSBIND_STATUS_OK`, the message is available from
+ `qjsbind_context_last_error`.
+ */
+typedef enum QjsbindStatus {
+  QJSBIND_STATUS_OK = 0,
+  /*
+   The script threw or the value did not have the requested type.
+   */
+  QJSBIND_STATUS_ERROR = 1,
+  /*
+   A null pointer, a string that is not UTF-8 or an unknown extension.
+   */
+  QJSBIND_STATUS_INVALID_ARGUMENT = 2,
+  /*
+   A bug in qjsbind, caught before it crossed the boundary.
+   */
+  QJSBIND_STATUS_PANIC = 3,
+} QjsbindStatus;
+
+/*
+ A JS context with its own globals.
+ */
+typedef struct QjsbindContext QjsbindContext;
+
+/*
+ A JS runtime, the heap shared by its contexts.
+ */
+typedef struct QjsbindRuntime QjsbindRuntime;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ Create a runtime. Returns null if it could not be created.
+ */
+struct QjsbindRuntime *qjsbind_runtime_new(void);
+
+/*
+ Free a runtime. Its contexts must have been freed before.
+
+ # Safety
+
+ `rt` is null or a runtime from `qjsbind_runtime_new` not freed yet.
+ */
+void qjsbind_runtime_free(struct QjsbindRuntime *rt);
+
+/*
+ Create a context in `rt`, without any extension installed. Returns null if `rt` is null or
+ the context could not be created.
+
+ # Safety
+
+ `rt` is null or a live runtime from `qjsbind_runtime_new`.
+ */
+struct QjsbindContext *qjsbind_context_new(struct QjsbindRuntime *rt);
+
+/*
+ Free a context.
+
+ # Safety
+
+ `ctx` is null or a context from `qjsbind_context_new` not freed yet.
+ */
+void qjsbind_context_free(struct QjsbindContext *ctx);
+
+/*
+ Install a bundled extension by name: `crypto`, `scale`, `hash`, `encoding`, `repr`,
+ `stable-hash`, `multiformats`, `compression` or `cbor`. Installing one twice does nothing.
+
+ # Safety
+
+ `ctx` is a live context and `name` a NUL-terminated string.
+ */
+enum QjsbindStatus qjsbind_context_install_extension(struct QjsbindContext *ctx, const char *name);
+
+/*
+ Evaluate `len` bytes of UTF-8 source. Unless `result` is null, the completion value is
+ converted to a string and stored in `*result`, to be freed with `qjsbind_string_free`.
+
+ # Safety
+
+ `ctx` is a live context, `source` points to `len` readable bytes and `result` is null or
+ writable.
+ */
+enum QjsbindStatus qjsbind_context_eval(struct QjsbindContext *ctx,
+                                        const char *source,
+                                        size_t len,
+                                        char **result);
+
+/*
+ Set the global `name` to a string of `len` bytes of UTF-8.
+
+ # Safety
+
+ `ctx` is a live context, `name` a NUL-terminated string and `value` points to `len` readable
+ bytes.
+ */
+enum QjsbindStatus qjsbind_context_set_string(struct QjsbindContext *ctx,
+                                              const char *name,
+                                              const char *value,
+                                              size_t len);
+
+/*
+ Read the global `name`, which must be a string, into `*value`, to be freed with
+ `qjsbind_string_free`.
+
+ # Safety
+
+ `ctx` is a live context, `name` a NUL-terminated string and `value` writable.
+ */
+enum QjsbindStatus qjsbind_context_get_string(struct QjsbindContext *ctx,
+                                              const char *name,
+                                              char **value);
+
+/*
+ Set the global `name` to a `Uint8Array` holding a copy of `len` bytes.
+
+ # Safety
+
+ `ctx` is a live context, `name` a NUL-terminated string and `data` points to `len` readable
+ bytes.
+ */
+enum QjsbindStatus qjsbind_context_set_bytes(struct QjsbindContext *ctx,
+                                             const char *name,
+                                             const uint8_t *data,
+                                             size_t len);
+
+/*
+ Read the global `name`, a `Uint8Array` or an `ArrayBuffer`, into `*data` and `*len`, to be
+ freed with `qjsbind_bytes_free`.
+
+ # Safety
+
+ `ctx` is a live context, `name` a NUL-terminated string and `data` and `len` writable.
+ */
+enum QjsbindStatus qjsbind_context_get_bytes(struct QjsbindContext *ctx,
+                                             const char *name,
+                                             uint8_t **data,
+                                             size_t *len);
+
+/*
+ The message of the last failed call on `ctx`, or null if it succeeded. Owned by the context
+ and valid until the next call taking it.
+
+ # Safety
+
+ `ctx` is null or a live context.
+ */
+const char *qjsbind_context_last_error(const struct QjsbindContext *ctx);
+
+/*
+ Free a string returned by this library.
+
+ # Safety
+
+ `s` is null or a string returned by this library not freed yet.
+ */
+void qjsbind_string_free(char *s);
+
+/*
+ Free a byte array returned by this library, with the length it was returned with.
+
+ # Safety
+
+ `data` is null or a byte array of `len` bytes returned by this library not freed yet.
+ */
+void qjsbind_bytes_free(uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* QJSBIND_H */
@@ -0,0 +1,352 @@
+//! A C API for embedding qjsbind and the bundled extensions from hosts not written in Rust.
+//!
+//! The header ships in `include/qjsbind.h`; building with the `header` feature regenerates it
+//! with cbindgen into `OUT_DIR`.
+//!
+//! Ownership rules:
+//!
+//! - A runtime is created by `qjsbind_runtime_new` and released by `qjsbind_runtime_free`, a
+//!   context by `qjsbind_context_new` and `qjsbind_context_free`. Every context must be freed
+//!   before the runtime it was created from. Neither is thread-safe.
+//! - Pointers passed in are borrowed for the duration of the call.
+//! - Strings and byte arrays returned through out-parameters belong to the caller, who releases
+//!   them with `qjsbind_string_free` and `qjsbind_bytes_free`.
+//! - `qjsbind_context_last_error` returns a string owned by the context, valid until the next
+//!   call taking the context.
+//!
+//! No Rust panic crosses the boundary: it is caught and reported as `QJSBIND_STATUS_PANIC`.
+
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::Display;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use qjs_extensions::Extensions;
+
+/// The result of a call. On anything but `QJSBIND_STATUS_OK`, the message is available from
+/// `qjsbind_context_last_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QjsbindStatus {
+    Ok = 0,
+    /// The script threw or the value did not have the requested type.
+    Error = 1,
+    /// A null pointer, a string that is not UTF-8 or an unknown extension.
+    InvalidArgument = 2,
+    /// A bug in qjsbind, caught before it crossed the boundary.
+    Panic = 3,
+}
+
+/// A JS runtime, the heap shared by its contexts.
+pub struct QjsbindRuntime {
+    runtime: js::Runtime,
+}
+
+/// A JS context with its own globals.
+pub struct QjsbindContext {
+    ctx: js::Context,
+    last_error: Option<CString>,
+}
+
+type Failure = (QjsbindStatus, String);
+
+fn error(message: impl Display) -> Failure {
+    (QjsbindStatus::Error, message.to_string())
+}
+
+fn invalid(message: impl Display) -> Failure {
+    (QjsbindStatus::InvalidArgument, message.to_string())
+}
+
+/// Run `f` on the context behind `ctx`, recording its failure as the last error.
+fn with_context(
+    ctx: *mut QjsbindContext,
+    f: impl FnOnce(&js::Context) -> Result<(), Failure>,
+) -> QjsbindStatus {
+    // SAFETY: the caller passes a context from `qjsbind_context_new` or null.
+    let Some(ctx) = (unsafe { ctx.as_mut() }) else {
+        return QjsbindStatus::InvalidArgument;
+    };
+    ctx.last_error = None;
+    let outcome = catch_unwind(AssertUnwindSafe(|| f(&ctx.ctx)))
+        .unwrap_or_else(|_| Err((QjsbindStatus::Panic, "panicked".into())));
+    match outcome {
+        Ok(()) => QjsbindStatus::Ok,
+        Err((status, message)) => {
+            ctx.last_error = Some(to_c_string_lossy(message));
+            status
+        }
+    }
+}
+
+fn to_c_string_lossy(message: String) -> CString {
+    CString::new(message.replace('\0', "\\0")).expect("NUL bytes were replaced")
+}
+
+/// # Safety
+///
+/// `ptr` is null or a NUL-terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(invalid(format!("{what} is null")));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| invalid(format!("{what} is not UTF-8")))
+}
+
+/// # Safety
+///
+/// `ptr` points to `len` readable bytes, or `len` is zero.
+unsafe fn bytes_arg<'a>(ptr: *const u8, len: usize, what: &str) -> Result<&'a [u8], Failure> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        return Err(invalid(format!("{what} is null")));
+    }
+    Ok(std::slice::from_raw_parts(ptr, len))
+}
+
+/// Create a runtime. Returns null if it could not be created.
+#[no_mangle]
+pub extern "C" fn qjsbind_runtime_new() -> *mut QjsbindRuntime {
+    catch_unwind(|| {
+        let runtime = js::Runtime::new(&Default::default());
+        Box::into_raw(Box::new(QjsbindRuntime { runtime }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Free a runtime. Its contexts must have been freed before.
+///
+/// # Safety
+///
+/// `rt` is null or a runtime from `qjsbind_runtime_new` not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_runtime_free(rt: *mut QjsbindRuntime) {
+    if !rt.is_null() {
+        _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(rt))));
+    }
+}
+
+/// Create a context in `rt`, without any extension installed. Returns null if `rt` is null or
+/// the context could not be created.
+///
+/// # Safety
+///
+/// `rt` is null or a live runtime from `qjsbind_runtime_new`.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_new(rt: *mut QjsbindRuntime) -> *mut QjsbindContext {
+    let Some(rt) = rt.as_ref() else {
+        return ptr::null_mut();
+    };
+    catch_unwind(AssertUnwindSafe(|| {
+        let ctx = rt.runtime.new_context();
+        Box::into_raw(Box::new(QjsbindContext {
+            ctx,
+            last_error: None,
+        }))
+    }))
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Free a context.
+///
+/// # Safety
+///
+/// `ctx` is null or a context from `qjsbind_context_new` not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_free(ctx: *mut QjsbindContext) {
+    if !ctx.is_null() {
+        _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(ctx))));
+    }
+}
+
+/// Install a bundled extension by name: `crypto`, `scale`, `hash`, `encoding`, `repr`,
+/// `stable-hash`, `multiformats`, `compression` or `cbor`. Installing one twice does nothing.
+///
+/// # Safety
+///
+/// `ctx` is a live context and `name` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_install_extension(
+    ctx: *mut QjsbindContext,
+    name: *const c_char,
+) -> QjsbindStatus {
+    with_context(ctx, |ctx| {
+        let name = str_arg(name, "name")?;
+        let extensions = Extensions::new().with_named(name).map_err(invalid)?;
+        extensions
+            .install(ctx)
+            .map_err(|err| error(format!("{err:#}")))?;
+        Ok(())
+    })
+}
+
+/// Evaluate `len` bytes of UTF-8 source. Unless `result` is null, the completion value is
+/// converted to a string and stored in `*result`, to be freed with `qjsbind_string_free`.
+///
+/// # Safety
+///
+/// `ctx` is a live context, `source` points to `len` readable bytes and `result` is null or
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_eval(
+    ctx: *mut QjsbindContext,
+    source: *const c_char,
+    len: usize,
+    result: *mut *mut c_char,
+) -> QjsbindStatus {
+    with_context(ctx, |ctx| {
+        let source = bytes_arg(source as *const u8, len, "source")?;
+        let source = std::str::from_utf8(source).map_err(|_| invalid("source is not UTF-8"))?;
+        let value = ctx.eval(&js::Code::Source(source)).map_err(error)?;
+        if !result.is_null() {
+            *result = to_c_string(value.to_string())?.into_raw();
+        }
+        Ok(())
+    })
+}
+
+fn to_c_string(s: String) -> Result<CString, Failure> {
+    CString::new(s).map_err(|_| error("the string contains a NUL character"))
+}
+
+/// Set the global `name` to a string of `len` bytes of UTF-8.
+///
+/// # Safety
+///
+/// `ctx` is a live context, `name` a NUL-terminated string and `value` points to `len` readable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_set_string(
+    ctx: *mut QjsbindContext,
+    name: *const c_char,
+    value: *const c_char,
+    len: usize,
+) -> QjsbindStatus {
+    with_context(ctx, |ctx| {
+        let name = str_arg(name, "name")?;
+        let value = bytes_arg(value as *const u8, len, "value")?;
+        let value = std::str::from_utf8(value).map_err(|_| invalid("value is not UTF-8"))?;
+        ctx.get_global_object()
+            .set_property(name, &js::Value::from_str(ctx, value))
+            .map_err(error)
+    })
+}
+
+/// Read the global `name`, which must be a string, into `*value`, to be freed with
+/// `qjsbind_string_free`.
+///
+/// # Safety
+///
+/// `ctx` is a live context, `name` a NUL-terminated string and `value` writable.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_get_string(
+    ctx: *mut QjsbindContext,
+    name: *const c_char,
+    value: *mut *mut c_char,
+) -> QjsbindStatus {
+    with_context(ctx, |ctx| {
+        let name = str_arg(name, "name")?;
+        if value.is_null() {
+            return Err(invalid("value is null"));
+        }
+        let global = ctx.get_global_object().get_property(name).map_err(error)?;
+        if !global.is_string() {
+            return Err(error(format!("{name} is not a string")));
+        }
+        *value = to_c_string(global.decode_string().map_err(error)?)?.into_raw();
+        Ok(())
+    })
+}
+
+/// Set the global `name` to a `Uint8Array` holding a copy of `len` bytes.
+///
+/// # Safety
+///
+/// `ctx` is a live context, `name` a NUL-terminated string and `data` points to `len` readable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_set_bytes(
+    ctx: *mut QjsbindContext,
+    name: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> QjsbindStatus {
+    with_context(ctx, |ctx| {
+        let name = str_arg(name, "name")?;
+        let data = bytes_arg(data, len, "data")?;
+        ctx.get_global_object()
+            .set_property(name, &js::Value::from_bytes(ctx, data))
+            .map_err(error)
+    })
+}
+
+/// Read the global `name`, a `Uint8Array` or an `ArrayBuffer`, into `*data` and `*len`, to be
+/// freed with `qjsbind_bytes_free`.
+///
+/// # Safety
+///
+/// `ctx` is a live context, `name` a NUL-terminated string and `data` and `len` writable.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_get_bytes(
+    ctx: *mut QjsbindContext,
+    name: *const c_char,
+    data: *mut *mut u8,
+    len: *mut usize,
+) -> QjsbindStatus {
+    with_context(ctx, |ctx| {
+        let name = str_arg(name, "name")?;
+        if data.is_null() || len.is_null() {
+            return Err(invalid("data or len is null"));
+        }
+        let global = ctx.get_global_object().get_property(name).map_err(error)?;
+        if !global.is_uint8_array() && !global.is_array_buffer() {
+            return Err(error(format!("{name} is not a Uint8Array")));
+        }
+        let bytes = global.decode_bytes().map_err(error)?.into_boxed_slice();
+        *len = bytes.len();
+        *data = Box::into_raw(bytes) as *mut u8;
+        Ok(())
+    })
+}
+
+/// The message of the last failed call on `ctx`, or null if it succeeded. Owned by the context
+/// and valid until the next call taking it.
+///
+/// # Safety
+///
+/// `ctx` is null or a live context.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_context_last_error(ctx: *const QjsbindContext) -> *const c_char {
+    match ctx.as_ref().and_then(|ctx| ctx.last_error.as_ref()) {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Free a string returned by this library.
+///
+/// # Safety
+///
+/// `s` is null or a string returned by this library not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Free a byte array returned by this library, with the length it was returned with.
+///
+/// # Safety
+///
+/// `data` is null or a byte array of `len` bytes returned by this library not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn qjsbind_bytes_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
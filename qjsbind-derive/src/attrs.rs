@@ -27,7 +27,7 @@ impl RenameAll {
             "SCREAMING_SNAKE_CASE" => Ok(RenameAll::ScreamingSnakeCase),
             "kebab-case" => Ok(RenameAll::KebabCase),
             "SCREAMING-KEBAB-CASE" => Ok(RenameAll::ScreamingKebabCase),
-            "keep" => Ok(RenameAll::Keep),
+            "keep" | "none" => Ok(RenameAll::Keep),
             _ => Err(Error::new_spanned(lit, "invalid value")),
         }
     }
@@ -84,6 +84,12 @@ pub struct ContainerAttrs<'a> {
     ident: &'a Ident,
     rename_all: Option<RenameAll>,
     allow_default: bool,
+    accumulate_errors: bool,
+    max_depth: Option<usize>,
+    detect_cycles: bool,
+    template: bool,
+    partial: bool,
+    omit_none: bool,
 }
 
 pub(crate) fn respan(
@@ -113,6 +119,12 @@ impl<'a> ContainerAttrs<'a> {
             ident: &input.ident,
             rename_all: None,
             allow_default: false,
+            accumulate_errors: false,
+            max_depth: None,
+            detect_cycles: false,
+            template: false,
+            partial: false,
+            omit_none: false,
         };
 
         for attr in input.attrs.iter() {
@@ -126,6 +138,20 @@ impl<'a> ContainerAttrs<'a> {
                     rv.rename_all = Some(RenameAll::parse(&lit)?);
                 } else if meta.path.is_ident("default") {
                     rv.allow_default = true;
+                } else if meta.path.is_ident("accumulate_errors") {
+                    rv.accumulate_errors = true;
+                } else if meta.path.is_ident("max_depth") {
+                    ensure_none!(rv.max_depth, meta.path, "duplicate max_depth attribute");
+                    let lit: syn::LitInt = meta.value()?.parse()?;
+                    rv.max_depth = Some(lit.base10_parse()?);
+                } else if meta.path.is_ident("detect_cycles") {
+                    rv.detect_cycles = true;
+                } else if meta.path.is_ident("template") {
+                    rv.template = true;
+                } else if meta.path.is_ident("partial") {
+                    rv.partial = true;
+                } else if meta.path.is_ident("omit_none") {
+                    rv.omit_none = true;
                 } else {
                     syn_bail!(meta.path, "unsupported attribute");
                 }
@@ -149,9 +175,56 @@ impl<'a> ContainerAttrs<'a> {
         self.ident
     }
 
+    pub fn has_rename_all(&self) -> bool {
+        self.rename_all.is_some()
+    }
+
     pub fn allow_default(&self) -> bool {
         self.allow_default
     }
+
+    pub fn accumulate_errors(&self) -> bool {
+        self.accumulate_errors
+    }
+
+    /// The `Option<usize>` expression of the depth cap of the type.
+    pub fn max_depth(&self) -> proc_macro2::TokenStream {
+        match self.max_depth {
+            Some(max) => quote::quote!(Some(#max)),
+            None => quote::quote!(None),
+        }
+    }
+
+    pub fn detect_cycles(&self) -> bool {
+        self.detect_cycles
+    }
+
+    /// Whether the derived `ToJsValue` sets the fields through a per-context `ObjectTemplate`.
+    pub fn template(&self) -> bool {
+        self.template
+    }
+
+    /// Whether the derived `FromJsValue` comes with a `<Name>Partial` struct of optional fields.
+    pub fn partial(&self) -> bool {
+        self.partial
+    }
+
+    /// Whether the derived `ToJsValue` leaves out the `Option` fields that are `None`.
+    pub fn omit_none(&self) -> bool {
+        self.omit_none
+    }
+}
+
+/// Whether `ty` is spelled as an `Option`, the way `omit_none` recognizes optional fields.
+fn is_option(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(path) if path.qself.is_none() => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
 }
 
 pub fn trim_rust_raw(name: Ident) -> Ident {
@@ -169,6 +242,9 @@ pub struct FieldAttrs<'a> {
     default: Option<TypeDefault>,
     as_bytes: bool,
     bytes_or_hex: bool,
+    bytes_as_array: bool,
+    skip_serializing_if: Option<ExprPath>,
+    finite: bool,
 }
 
 impl<'a> FieldAttrs<'a> {
@@ -179,6 +255,9 @@ impl<'a> FieldAttrs<'a> {
             default: None,
             as_bytes: false,
             bytes_or_hex: false,
+            bytes_as_array: false,
+            skip_serializing_if: None,
+            finite: false,
         };
 
         for attr in field.attrs.iter() {
@@ -209,6 +288,28 @@ impl<'a> FieldAttrs<'a> {
                         syn_bail!(meta.path, "duplicate bytes_or_hex attribute");
                     }
                     rv.bytes_or_hex = true;
+                } else if meta.path.is_ident("bytes") {
+                    let lit: LitStr = meta.value()?.parse()?;
+                    match lit.value().as_str() {
+                        "array" => rv.bytes_as_array = true,
+                        "uint8array" => rv.bytes_as_array = false,
+                        _ => {
+                            syn_bail!(lit, "expected \"array\" or \"uint8array\"");
+                        }
+                    }
+                } else if meta.path.is_ident("skip_serializing_if") {
+                    ensure_none!(
+                        rv.skip_serializing_if,
+                        meta.path,
+                        "duplicate skip_serializing_if attribute"
+                    );
+                    let lit: LitStr = meta.value()?.parse()?;
+                    rv.skip_serializing_if = Some(parse_lit_into_expr_path(&lit)?);
+                } else if meta.path.is_ident("finite") {
+                    if rv.finite || rv.as_bytes || rv.bytes_or_hex {
+                        syn_bail!(meta.path, "finite conflicts with another attribute");
+                    }
+                    rv.finite = true;
                 } else {
                     syn_bail!(meta.path, "unsupported attribute");
                 }
@@ -234,6 +335,17 @@ impl<'a> FieldAttrs<'a> {
             })
     }
 
+    /// The camelCase name of the field if the field has no explicit name and would be renamed by
+    /// a context wide `Convention::CamelCase`.
+    pub fn camel_name(&self, container_attrs: &ContainerAttrs) -> Option<String> {
+        if self.rename.is_some() || container_attrs.has_rename_all() {
+            return None;
+        }
+        let ident = trim_rust_raw(self.field.ident.clone()?);
+        let camel = RenameAll::CamelCase.rename(&ident);
+        (camel != ident).then(|| camel.to_string())
+    }
+
     pub fn as_bytes(&self) -> bool {
         self.as_bytes
     }
@@ -242,13 +354,32 @@ impl<'a> FieldAttrs<'a> {
         self.bytes_or_hex
     }
 
-    pub fn decoder_fn(&self, crate_qjsbind: &Ident) -> Path {
+    /// Whether the bytes are written as an array of numbers rather than a Uint8Array.
+    pub fn bytes_as_array(&self) -> bool {
+        self.bytes_as_array
+    }
+
+    /// The boolean expression telling whether the field is left out of the output object, from
+    /// `skip_serializing_if` or, for `Option` fields, the container's `omit_none`.
+    pub fn skip_expr(&self, container_attrs: &ContainerAttrs) -> Option<proc_macro2::TokenStream> {
+        let ident = &self.field.ident;
+        if let Some(skip_if) = &self.skip_serializing_if {
+            return Some(quote::quote!(#skip_if(&self.#ident)));
+        }
+        (container_attrs.omit_none() && is_option(&self.field.ty))
+            .then(|| quote::quote!(Option::is_none(&self.#ident)))
+    }
+
+    /// The function decoding the field, `None` for `FromJsValue::from_js_value_depth`.
+    pub fn decoder_fn(&self, crate_qjsbind: &Ident) -> Option<Path> {
         if self.as_bytes {
-            syn::parse_quote!(#crate_qjsbind::decode_as_bytes)
+            Some(syn::parse_quote!(#crate_qjsbind::decode_as_bytes))
         } else if self.bytes_or_hex {
-            syn::parse_quote!(#crate_qjsbind::decode_as_bytes_maybe_hex)
+            Some(syn::parse_quote!(#crate_qjsbind::decode_as_bytes_maybe_hex))
+        } else if self.finite {
+            Some(syn::parse_quote!(#crate_qjsbind::decode_finite))
         } else {
-            syn::parse_quote!(FromJsValue::from_js_value)
+            None
         }
     }
 
@@ -40,6 +40,13 @@ fn derive_newtype_struct(
                     fn from_js_value(js_value: Value) -> Result<Self> {
                         Ok(Self(FromJsValue::from_js_value(js_value)?))
                     }
+
+                    fn from_js_value_depth(
+                        js_value: Value,
+                        depth: #crate_qjsbind::ConversionDepth,
+                    ) -> Result<Self> {
+                        Ok(Self(FromJsValue::from_js_value_depth(js_value, depth)?))
+                    }
                 }
             };
         })
@@ -93,44 +100,59 @@ fn derive_struct(
         let bounded_where_clause = where_clause_with_bound(&input.generics, bound);
 
         Ok(quote! {
+            #(if container_attrs.partial()) {
+                #{derive_partial(input, &attrs, &container_attrs, &crate_qjsbind)}
+            }
             const _: () = {
                 use #crate_qjsbind::{c, Value, FromJsValue, Result, Error, alloc};
                 impl #impl_generics FromJsValue for #ident #ty_generics #bounded_where_clause {
                     fn from_js_value(val: Value) -> Result<Self> {
+                        let depth = #crate_qjsbind::ConversionDepth::of(&val);
+                        Self::from_js_value_depth(val, depth)
+                    }
+
+                    fn from_js_value_depth(
+                        val: Value,
+                        depth: #crate_qjsbind::ConversionDepth,
+                    ) -> Result<Self> {
                         #(if container_attrs.allow_default()) {
                             if val.is_null_or_undefined() {
                                 return Ok(<Self as Default>::default());
                             }
                         }
-                        Ok(Self {
-                            #(for field in &attrs) {
-                                #{&field.field().ident}: {
-                                    let field_value = val.get_property(#{field.js_name(&container_attrs)})?;
-                                    #{
-                                        let field_name = &field.field().ident.as_ref().map(|f| f.to_string()).unwrap_or_default();
-                                        let err_msg = format!("failed to decode field {}", field_name);
-                                        let decoding_expr = quote! {
-                                            #crate_qjsbind::ErrorContext::context(
-                                                #{field.decoder_fn(&crate_qjsbind)}(field_value),
-                                                #err_msg,
-                                            )?
-                                        };
-                                        match field.default_fn() {
-                                            Some(f) => {
-                                                quote! {
-                                                    if field_value.is_null_or_undefined() {
-                                                        #f()
-                                                    } else {
-                                                        #decoding_expr
-                                                    }
-                                                }
-                                            }
-                                            None => decoding_expr,
-                                        }
-                                    }
-                                },
+                        #[allow(unused_variables)]
+                        let depth = depth.enter_derived(
+                            #{ident.to_string()},
+                            #{container_attrs.max_depth()},
+                        )?;
+                        #(if container_attrs.accumulate_errors()) {
+                            let mut errors = #crate_qjsbind::ErrorList::new();
+                            #(for (i, field) in attrs.iter().enumerate()) {
+                                let #{field_var(i)} = errors.collect(
+                                    #{field.js_name(&container_attrs)},
+                                    #{get_field(field, &container_attrs)}
+                                        .and_then(|field_value| -> Result<_> {
+                                            Ok(#{field_decoder(field, &crate_qjsbind, false)})
+                                        }),
+                                );
                             }
-                        })
+                            errors.into_result()?;
+                            Ok(Self {
+                                #(for (i, field) in attrs.iter().enumerate()) {
+                                    #{&field.field().ident}: #{field_var(i)}.expect("BUG: missing field error"),
+                                }
+                            })
+                        }
+                        #(else) {
+                            Ok(Self {
+                                #(for field in &attrs) {
+                                    #{&field.field().ident}: {
+                                        let field_value = #{get_field(field, &container_attrs)}?;
+                                        #{field_decoder(field, &crate_qjsbind, true)}
+                                    },
+                                }
+                            })
+                        }
                     }
                 }
             };
@@ -153,15 +175,29 @@ fn derive_struct(
                 use #crate_qjsbind::{c, Value, #trait_name, Result};
                 impl #impl_generics #trait_name for #ident #ty_generics #bounded_where_clause {
                     fn #fn_name(#self_arg, ctx: &#crate_qjsbind::Context) -> Result<Value> {
+                        #(if container_attrs.detect_cycles() && !into) {
+                            let address = Some(self as *const Self as *const ());
+                        }
+                        #(else) {
+                            let address = None;
+                        }
+                        let _guard = #crate_qjsbind::ConversionGuard::enter(
+                            ctx,
+                            #{ident.to_string()},
+                            #{container_attrs.max_depth()},
+                            address,
+                        )?;
+                        #(if container_attrs.template()) {
+                            static TEMPLATE: #crate_qjsbind::TemplateKey =
+                                #crate_qjsbind::TemplateKey::new();
+                            let template = ctx.cached_object_template(
+                                &TEMPLATE,
+                                &[#(for field in &attrs) { #{field_name(field, &container_attrs)}, }],
+                            );
+                        }
                         let obj = ctx.new_object(#{ident.to_string()});
-                        #(for field in &attrs) {
-                            #(if field.as_bytes() || field.bytes_or_hex()) {
-                                let field_value = #crate_qjsbind::encode_as_bytes(ctx, &self.#{&field.field().ident});
-                            }
-                            #(else) {
-                                let field_value = self.#{&field.field().ident}.#fn_name(ctx)?;
-                            }
-                            obj.set_property(#{field.js_name(&container_attrs)}, &field_value)?;
+                        #(for (i, field) in attrs.iter().enumerate()) {
+                            #{encode_field(field, i, &container_attrs, &crate_qjsbind, &fn_name)}
                         }
                         Ok(obj)
                     }
@@ -170,3 +206,155 @@ fn derive_struct(
         })
     }
 }
+
+/// The `<Name>Partial` struct of `#[qjs(partial)]`, with every field optional, and its
+/// `FromJsValue` converting only the properties that are not `undefined`.
+fn derive_partial(
+    input: &syn::DeriveInput,
+    attrs: &[FieldAttrs],
+    container_attrs: &ContainerAttrs,
+    crate_qjsbind: &syn::Ident,
+) -> TokenStream {
+    let ident = container_attrs.ident();
+    let partial = syn::Ident::new(&format!("{ident}Partial"), ident.span());
+    let vis = &input.vis;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let bound = syn::parse_quote!(#crate_qjsbind::FromJsValue);
+    let bounded_where_clause = where_clause_with_bound(&input.generics, bound);
+    let doc = format!("[`{ident}`] with only the fields present in the JS object.");
+    quote! {
+        #[doc = #doc]
+        #[allow(dead_code)]
+        #vis struct #partial #{&input.generics} #where_clause {
+            #(for field in attrs) {
+                #{&field.field().vis} #{&field.field().ident}: Option<#{&field.field().ty}>,
+            }
+        }
+        const _: () = {
+            use #crate_qjsbind::{c, Value, FromJsValue, Result, Error, alloc};
+            impl #impl_generics FromJsValue for #partial #ty_generics #bounded_where_clause {
+                fn from_js_value(val: Value) -> Result<Self> {
+                    #[allow(unused_variables)]
+                    let depth = #crate_qjsbind::ConversionDepth::of(&val);
+                    Ok(Self {
+                        #(for field in attrs) {
+                            #{&field.field().ident}: {
+                                let field_value = #{get_field(field, container_attrs)}?;
+                                if field_value.is_undefined() {
+                                    None
+                                } else {
+                                    Some(#{decode_field(field, crate_qjsbind, true)})
+                                }
+                            },
+                        }
+                    })
+                }
+            }
+        };
+    }
+}
+
+/// The statements setting the field, the `index`th of the struct, on `obj`.
+fn encode_field(
+    field: &FieldAttrs,
+    index: usize,
+    container_attrs: &ContainerAttrs,
+    crate_qjsbind: &syn::Ident,
+    fn_name: &TokenStream,
+) -> TokenStream {
+    let ident = &field.field().ident;
+    let encode = if field.bytes_as_array() {
+        quote! { #crate_qjsbind::encode_as_array(ctx, &self.#ident)? }
+    } else if field.as_bytes() || field.bytes_or_hex() {
+        quote! { #crate_qjsbind::encode_as_bytes(ctx, &self.#ident)? }
+    } else {
+        quote! { self.#ident.#fn_name(ctx)? }
+    };
+    let set = if container_attrs.template() {
+        quote! { template.set(&obj, #index, &field_value)?; }
+    } else {
+        quote! { obj.set_property(#{field_name(field, container_attrs)}, &field_value)?; }
+    };
+    let Some(skip) = field.skip_expr(container_attrs) else {
+        return quote! {
+            let field_value = #encode;
+            #set
+        };
+    };
+    // The object is new, so a skipped field is absent when it is not set.
+    quote! {
+        if !#skip {
+            let field_value = #encode;
+            #set
+        }
+    }
+}
+
+/// The expression reading the field from `val`, accepting the camelCase name when the context
+/// default asks for it.
+fn get_field(field: &FieldAttrs, container_attrs: &ContainerAttrs) -> TokenStream {
+    let name = field.js_name(container_attrs);
+    match field.camel_name(container_attrs) {
+        Some(camel_name) => quote! { val.get_field(#name, #camel_name) },
+        None => quote! { val.get_property(#name) },
+    }
+}
+
+/// The expression of the key a field is written to.
+fn field_name(field: &FieldAttrs, container_attrs: &ContainerAttrs) -> TokenStream {
+    let name = field.js_name(container_attrs);
+    match field.camel_name(container_attrs) {
+        Some(camel_name) => quote! { ctx.field_name(#name, #camel_name) },
+        None => quote! { #name },
+    }
+}
+
+fn field_var(index: usize) -> syn::Ident {
+    syn::Ident::new(&format!("field_{index}"), proc_macro2::Span::call_site())
+}
+
+/// The expression decoding `field_value` into the field type, applying the field default.
+fn field_decoder(
+    field: &FieldAttrs,
+    crate_qjsbind: &syn::Ident,
+    with_context: bool,
+) -> TokenStream {
+    let decoding_expr = decode_field(field, crate_qjsbind, with_context);
+    match field.default_fn() {
+        Some(f) => {
+            quote! {
+                if field_value.is_null_or_undefined() {
+                    #f()
+                } else {
+                    #decoding_expr
+                }
+            }
+        }
+        None => decoding_expr,
+    }
+}
+
+/// The expression decoding `field_value`, at `depth`, into the field type.
+fn decode_field(field: &FieldAttrs, crate_qjsbind: &syn::Ident, with_context: bool) -> TokenStream {
+    let decode = match field.decoder_fn(crate_qjsbind) {
+        Some(decoder_fn) => quote!(#decoder_fn(field_value)),
+        None => quote!(FromJsValue::from_js_value_depth(field_value, depth)),
+    };
+    if with_context {
+        let field_name = field
+            .field()
+            .ident
+            .as_ref()
+            .map(|f| f.to_string())
+            .unwrap_or_default();
+        let err_msg = format!("failed to decode field {}", field_name);
+        quote! {
+            #crate_qjsbind::ErrorContext::context(
+                #decode,
+                #err_msg,
+            )?
+        }
+    } else {
+        quote! { #decode? }
+    }
+}
@@ -0,0 +1,154 @@
+use proc_macro2::TokenStream;
+use template_quote::quote;
+
+use super::find_crate_name;
+
+pub fn derive(input: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    if !input.generics.params.is_empty() {
+        syn_bail!(input.generics, "generic types are not supported");
+    }
+    let crate_ext = find_crate_name("qjs-extensions")?;
+    // Full paths, since the type may well be named `TypeRef` or `TypeSpec` itself.
+    let scale2 = quote!(#crate_ext::scale2);
+    let ident = &input.ident;
+    // By path, so that types of the same name in other modules do not collide.
+    let name = quote!(concat!(module_path!(), "::", #{ident.to_string()}));
+    let ty = match &input.data {
+        syn::Data::Struct(s) => fields_type(&scale2, &s.fields)?,
+        syn::Data::Enum(e) => enum_type(&scale2, e)?,
+        syn::Data::Union(_) => {
+            syn_bail!(ident, "unions are not supported");
+        }
+    };
+    Ok(quote! {
+        impl #scale2::ScaleJsType for #ident {
+            fn scale_type(registry: &#scale2::TypeRegistry) -> #scale2::TypeRef {
+                // Fails only while the registry is in use, the type is then reported unknown.
+                const NAME: &str = #name;
+                let _ = registry.define(NAME, #ty);
+                #scale2::TypeRef::named(NAME)
+            }
+        }
+    })
+}
+
+/// The `#[codec(..)]` attributes of `parity-scale-codec` that change the encoding.
+#[derive(Default)]
+struct CodecAttrs {
+    compact: bool,
+    skip: bool,
+    index: Option<u32>,
+}
+
+impl CodecAttrs {
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<CodecAttrs> {
+        let mut rv = CodecAttrs::default();
+        for attr in attrs {
+            if !attr.path().is_ident("codec") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("compact") {
+                    rv.compact = true;
+                } else if meta.path.is_ident("skip") {
+                    rv.skip = true;
+                } else if meta.path.is_ident("index") {
+                    let lit: syn::LitInt = meta.value()?.parse()?;
+                    rv.index = Some(lit.base10_parse()?);
+                } else {
+                    syn_bail!(meta.path, "unsupported by ScaleJsType");
+                }
+                Ok(())
+            })?;
+        }
+        Ok(rv)
+    }
+}
+
+/// An expression of type `TypeRef` for the field.
+fn field_id(scale2: &TokenStream, field: &syn::Field, attrs: &CodecAttrs) -> TokenStream {
+    let ty = &field.ty;
+    let id = quote!(<#ty as #scale2::ScaleJsType>::scale_type(registry));
+    if attrs.compact {
+        quote!(#scale2::TypeRef::from(#scale2::TypeSpec::compact(#id)))
+    } else {
+        id
+    }
+}
+
+/// An expression of type `TypeSpec` for the fields, encoded in order.
+fn fields_type(scale2: &TokenStream, fields: &syn::Fields) -> syn::Result<TokenStream> {
+    let mut encoded = vec![];
+    for field in fields.iter() {
+        let attrs = CodecAttrs::parse(&field.attrs)?;
+        if attrs.index.is_some() {
+            syn_bail!(field, "index is only supported on enum variants");
+        }
+        if !attrs.skip {
+            encoded.push((field, field_id(scale2, field, &attrs)));
+        }
+    }
+    Ok(match fields {
+        syn::Fields::Named(_) => quote! {
+            #scale2::TypeSpec::structure(
+                [#(for (field, id) in &encoded) { (#{field.ident.as_ref().unwrap().to_string()}, #id), }]
+            )
+        },
+        // A newtype is encoded as its field.
+        syn::Fields::Unnamed(_) if encoded.len() == 1 => {
+            let id = &encoded[0].1;
+            quote!(#scale2::TypeSpec::alias(#id))
+        }
+        syn::Fields::Unnamed(_) | syn::Fields::Unit => quote! {
+            #scale2::TypeSpec::tuple([#(for (_, id) in &encoded) { #id, }])
+        },
+    })
+}
+
+fn enum_type(scale2: &TokenStream, item_enum: &syn::DataEnum) -> syn::Result<TokenStream> {
+    let mut variants = vec![];
+    for variant in item_enum.variants.iter() {
+        let attrs = CodecAttrs::parse(&variant.attrs)?;
+        if attrs.compact {
+            syn_bail!(variant, "compact is only supported on fields");
+        }
+        if attrs.skip {
+            continue;
+        }
+        // Skipped variants do not take an index, as in `parity-scale-codec`.
+        let index = match attrs.index {
+            Some(index) => index,
+            None => variants.len() as u32,
+        };
+        let id = match &variant.fields {
+            syn::Fields::Unit => quote!(None),
+            fields => {
+                let ty = fields_type(scale2, fields)?;
+                quote!(Some(#scale2::TypeRef::from(#ty)))
+            }
+        };
+        variants.push((variant.ident.to_string(), id, index));
+    }
+    Ok(quote! {
+        #scale2::TypeSpec::indexed_enumeration(
+            [#(for (name, id, index) in &variants) { (#name, #id, #index), }]
+        )
+    })
+}
+
+#[test]
+fn show_tokens() {
+    let input: syn::DeriveInput = syn::parse_quote! {
+        enum Call {
+            Remark(Vec<u8>),
+            #[codec(skip)]
+            Unused,
+            #[codec(index = 5)]
+            Transfer { to: [u8; 32], #[codec(compact)] amount: u128 },
+            Batch(u32, Option<bool>),
+            Noop,
+        }
+    };
+    let generated = derive(&input).unwrap();
+    insta::assert_snapshot!(rustfmt_snippet::rustfmt(&generated.to_string()).unwrap());
+}
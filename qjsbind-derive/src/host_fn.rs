@@ -62,7 +62,7 @@ fn patch_or_err(attrs: TokenStream, input: TokenStream) -> syn::Result<TokenStre
     }
     for arg in args_iter {
         arg_exprs.push(respan(arg.span(), quote! {
-            #crate_qjsbind::FromJsValue::from_js_value(args.next().unwrap_or(#crate_qjsbind::Value::undefined()))?
+            #crate_qjsbind::FromHostArgs::from_host_args(host_ctx, &mut rest)?
         }));
     }
     let fn_name = fn_ident.to_string();
@@ -80,12 +80,16 @@ fn patch_or_err(attrs: TokenStream, input: TokenStream) -> syn::Result<TokenStre
             #[allow(unused_variables)]
             let #ctx_var = #crate_qjsbind::Context::clone_from_ptr(c_ctx).expect("calling host function with null context");
             let _pause_gc = #ctx_var.pause_gc();
-            let args = if argc > 0 {
+            let argv: &[#crate_qjsbind::c::JSValue] = if argc > 0 {
                 unsafe { core::slice::from_raw_parts(argv, argc as usize) }
             } else {
                 &[]
             };
-            let mut args = args.into_iter().map(|v| #crate_qjsbind::Value::new_cloned(&ctx, *v));
+            #crate_qjsbind::record_host_call(#fn_name, &ctx, argv);
+            #[allow(unused_variables)]
+            let host_ctx = &ctx;
+            #[allow(unused_mut, unused_variables)]
+            let mut rest = argv;
             #(if with_context) {
                 let #this_var = #crate_qjsbind::Value::new_cloned(&ctx, c_this);
             }
@@ -7,6 +7,7 @@ mod attrs;
 mod bound;
 mod derive;
 mod derive_gc_mark;
+mod derive_scale_type;
 mod host_fn;
 mod qjsbind;
 
@@ -42,6 +43,16 @@ pub fn derive_gc_mark(input: TokenStream) -> TokenStream {
         .into()
 }
 
+/// Describes the SCALE encoding of a type to the `qjs-extensions` type registry, see
+/// `qjs_extensions::scale2::ScaleJsType`.
+#[proc_macro_derive(ScaleJsType, attributes(codec))]
+pub fn derive_scale_js_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    derive_scale_type::derive(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
 #[proc_macro_attribute]
 pub fn host_call(attrs: TokenStream, input: TokenStream) -> TokenStream {
     host_fn::patch(
@@ -62,7 +73,10 @@ pub fn qjsbind(attrs: TokenStream, input: TokenStream) -> TokenStream {
 
 #[cfg(test)]
 fn find_crate_name(origin: &str) -> syn::Result<syn::Ident> {
-    Ok(syn::Ident::new(origin, proc_macro2::Span::call_site()))
+    Ok(syn::Ident::new(
+        &origin.replace('-', "_"),
+        proc_macro2::Span::call_site(),
+    ))
 }
 
 #[cfg(not(test))]
@@ -55,6 +55,7 @@ struct FieldAttrs {
     js_name: Option<LitStr>,
     getter: Option<Ident>,
     setter: Option<Ident>,
+    notify: Option<Ident>,
 }
 
 struct ArgSelf {
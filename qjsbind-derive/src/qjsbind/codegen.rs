@@ -296,18 +296,32 @@ impl DerivedProperty {
             let getter_fn = self.getter_fn_name(class);
             tokens.extend(quote_spanned! { getter.span() =>
                 #[crate_js::host_call(with_context)]
-                fn #getter_fn(_ctx: crate_js::Context, this_value: crate_js::Native<#{&class.name}>) -> #{&self.ty} {
-                    this_value.borrow().#{&self.name}.clone()
+                fn #getter_fn(_ctx: crate_js::Context, this_value: crate_js::Native<#{&class.name}>) -> crate_js::Result<#{&self.ty}> {
+                    Ok(this_value.try_borrow()?.#{&self.name}.clone())
                 }
             });
         }
 
-        if let Some(setter) = &self.attrs.setter {
+        if let (Some(setter), Some(_)) = (&self.attrs.setter, &self.attrs.notify) {
             let setter_fn = self.setter_fn_name(class);
+            let js_name = self.js_name_str(class);
             tokens.extend(quote_spanned! { setter.span() =>
                 #[crate_js::host_call(with_context)]
-                fn #setter_fn(_ctx: crate_js::Context, this_value: crate_js::Native<#{&class.name}>, value: #{&self.ty}) {
-                    this_value.borrow_mut().#{&self.name} = value;
+                fn #setter_fn(ctx: crate_js::Context, this_value: crate_js::Native<#{&class.name}>, value: #{&self.ty}) -> crate_js::Result<()> {
+                    let new = crate_js::ToJsValue::to_js_value(&value, &ctx)?;
+                    let old = core::mem::replace(&mut this_value.try_borrow_mut()?.#{&self.name}, value);
+                    let old = crate_js::ToJsValue::to_js_value(&old, &ctx)?;
+                    this_value.notify_change(#js_name, old, new);
+                    Ok(())
+                }
+            });
+        } else if let Some(setter) = &self.attrs.setter {
+            let setter_fn = self.setter_fn_name(class);
+            tokens.extend(quote_spanned! { setter.span() =>
+                #[crate_js::host_call(with_context)]
+                fn #setter_fn(_ctx: crate_js::Context, this_value: crate_js::Native<#{&class.name}>, value: #{&self.ty}) -> crate_js::Result<()> {
+                    this_value.try_borrow_mut()?.#{&self.name} = value;
+                    Ok(())
                 }
             });
         }
@@ -375,32 +389,46 @@ impl Method {
         let fn_name = self.impl_fn_name(class);
         let class_name = &class.name;
         let args = self.args.args_defs();
-        let args_idents = self.args.args_idents();
+        let args_idents: Vec<_> = self.args.args_idents().collect();
+        // Instance methods fail with `Reentrancy` rather than panic when the instance is borrowed.
+        let output = match &self.return_ty {
+            syn::ReturnType::Default => quote!(()),
+            syn::ReturnType::Type(_, ty) => quote!(#ty),
+        };
+        let borrow = if self.is_mut {
+            quote!(try_borrow_mut)
+        } else {
+            quote!(try_borrow)
+        };
+        let call = quote!(this_value.#borrow()?.#name(#(#args_idents),*));
+        let body = match &self.return_ty {
+            syn::ReturnType::Default => quote!(#call; Ok(())),
+            syn::ReturnType::Type(..) => quote!(Ok(#call)),
+        };
 
         tokens.extend(quote_spanned! { self.attrs.marker_token.span() =>
             #[crate_js::host_call(with_context)]
+            #(if self.is_static) {
             fn #fn_name(
                 ctx: crate_js::Context,
-                #(if self.is_static) {
                 _this_value: crate_js::Value,
-                }
-                #(else) {
-                this_value: crate_js::Native<#class_name>,
-                }
                 #(#args),*
             ) #{&self.return_ty} {
                 #[allow(unused_variables)]
                 let ctx = ctx;
-                #(if self.is_static) {
-                    #class_name::
-                }
-                #(else if self.is_mut) {
-                    this_value.borrow_mut().
-                }
-                #(else) {
-                    this_value.borrow().
-                }
-                    #name(#(#args_idents),*)
+                #class_name::#name(#(#args_idents),*)
+            }
+            }
+            #(else) {
+            fn #fn_name(
+                ctx: crate_js::Context,
+                this_value: crate_js::Native<#class_name>,
+                #(#args),*
+            ) -> crate_js::Result<#output> {
+                #[allow(unused_variables)]
+                let ctx = ctx;
+                #body
+            }
             }
         });
     }
@@ -116,6 +116,7 @@ impl FieldAttrs {
         let mut js_name = None;
         let mut getter = None;
         let mut setter = None;
+        let mut notify = None;
 
         for attr in attrs {
             if attr.path().is_ident("qjs") {
@@ -130,6 +131,9 @@ impl FieldAttrs {
                         "setter" => {
                             setter = Some(ident.clone());
                         }
+                        "notify" => {
+                            notify = Some(ident.clone());
+                        }
                         "js_name" => {
                             ensure_none!(js_name, meta.path, "duplicate `js_name` attribute");
                             js_name = Some(meta.value()?.parse::<LitStr>()?);
@@ -143,10 +147,15 @@ impl FieldAttrs {
             }
         }
 
+        if let (Some(notify), None) = (&notify, &setter) {
+            syn_bail!(notify, "`notify` requires `setter`");
+        }
+
         Ok(Self {
             js_name,
             getter,
             setter,
+            notify,
         })
     }
 }
@@ -0,0 +1,107 @@
+//! Creating request objects property by property against creating them from an
+//! `ObjectTemplate`.
+//!
+//! `cargo bench -p qjsbind --bench object_template`
+//!
+//! Prints how long a template takes against the way it replaces. Timings are only printed, not
+//! checked, as `cargo test --all-targets` runs this too, on machines of any load.
+
+use std::time::{Duration, Instant};
+
+use qjsbind::{self as js, ToJsValue};
+
+const N: usize = 100_000;
+
+#[derive(ToJsValue)]
+struct Request {
+    method: String,
+    url: String,
+    headers: Vec<String>,
+    body: String,
+}
+
+#[derive(ToJsValue)]
+#[qjs(template)]
+struct TemplatedRequest {
+    method: String,
+    url: String,
+    headers: Vec<String>,
+    body: String,
+}
+
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..N {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{name:<24} {:>8.2?} total, {:>6} ns/object",
+        elapsed,
+        elapsed.as_nanos() / N as u128
+    );
+    elapsed
+}
+
+/// Print how long `name` took against `baseline`.
+fn compare(name: &str, took: Duration, baseline: Duration) {
+    println!(
+        "{name:<24} {:>8.2}x the time of the way it replaces",
+        took.as_secs_f64() / baseline.as_secs_f64().max(f64::MIN_POSITIVE)
+    );
+}
+
+fn main() {
+    let runtime = js::Runtime::new(&Default::default());
+    let ctx = runtime.new_context();
+    let headers = vec!["accept: */*".to_string()];
+
+    let by_property = bench("set_property", || {
+        let obj = ctx.new_object("");
+        for (key, value) in [("method", "GET"), ("url", "/"), ("body", "")] {
+            obj.set_property(key, &value.to_js_value(&ctx).unwrap())
+                .unwrap();
+        }
+        obj.set_property("headers", &headers.to_js_value(&ctx).unwrap())
+            .unwrap();
+    });
+
+    let template = ctx.object_template(&["method", "url", "headers", "body"]);
+    let by_template = bench("ObjectTemplate", || {
+        template
+            .instantiate(&[&"GET", &"/", &headers, &""])
+            .unwrap();
+    });
+
+    let request = Request {
+        method: "GET".into(),
+        url: "/".into(),
+        headers: headers.clone(),
+        body: String::new(),
+    };
+    let derived = bench("derive", || {
+        request.to_js_value(&ctx).unwrap();
+    });
+
+    let request = TemplatedRequest {
+        method: "GET".into(),
+        url: "/".into(),
+        headers: headers.clone(),
+        body: String::new(),
+    };
+    let derived_templated = bench("derive with template", || {
+        request.to_js_value(&ctx).unwrap();
+    });
+
+    let obj = request.to_js_value(&ctx).unwrap();
+    ctx.get_global_object().set_property("r", &obj).unwrap();
+    let keys = ctx
+        .eval(&js::Code::Source("Object.keys(r).join()"))
+        .unwrap()
+        .decode_string()
+        .unwrap();
+    assert_eq!(keys, "method,url,headers,body");
+
+    compare("ObjectTemplate", by_template, by_property);
+    compare("derive with template", derived_templated, derived);
+}
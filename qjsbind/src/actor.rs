@@ -0,0 +1,247 @@
+//! A runtime owned by a thread of its own, for services whose other threads submit work to it.
+
+use alloc::{boxed::Box, string::String};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use anyhow::{anyhow, bail};
+
+use crate::{Context, EngineConfig, Result, Runtime};
+
+type Job = Box<dyn FnOnce(&Context) + Send>;
+
+/// A context on a dedicated thread, running the closures posted to it one at a time, in the
+/// order they were posted.
+///
+/// `Runtime` and `Context` are not `Send`, so this is the way to share one between threads:
+///
+/// ```ignore
+/// let actor = JsActor::spawn(EngineConfig::default(), |ctx| {
+///     Extensions::new().with_scale().install(ctx).map(drop)
+/// })?;
+/// let sum = actor.post_blocking(|ctx| ctx.eval(&Code::Source("1 + 1")).map(|v| v.to_string()))?;
+/// ```
+///
+/// A closure that panics fails with an error and the actor keeps serving the next ones.
+/// Dropping the actor, or calling [`shutdown`](Self::shutdown), runs the closures already
+/// posted before the thread exits.
+pub struct JsActor {
+    tx: Option<mpsc::Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl JsActor {
+    /// Start the thread, creating a runtime with `config` and a context that `setup` prepares.
+    pub fn spawn(
+        config: EngineConfig,
+        setup: impl FnOnce(&Context) -> Result<()> + Send + 'static,
+    ) -> Result<Self> {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+        let thread = std::thread::Builder::new()
+            .name("js-actor".into())
+            .spawn(move || {
+                let runtime = Runtime::new(&config);
+                let ctx = runtime.new_context();
+                let ready = catch_unwind(AssertUnwindSafe(|| setup(&ctx)))
+                    .unwrap_or_else(|panic| Err(panic_error(panic)));
+                let failed = ready.is_err();
+                _ = ready_tx.send(ready);
+                if failed {
+                    return;
+                }
+                // Ends once every sender is dropped and the queue is drained.
+                for job in rx {
+                    job(&ctx);
+                }
+            })?;
+        let actor = Self {
+            tx: Some(tx),
+            thread: Some(thread),
+        };
+        ready_rx
+            .recv()
+            .map_err(|_| anyhow!("the actor thread exited during setup"))??;
+        Ok(actor)
+    }
+
+    /// Queue `f` and return the channel its result is sent to.
+    pub fn post_async<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Context) -> R + Send + 'static,
+    ) -> Result<mpsc::Receiver<Result<R>>> {
+        let (result_tx, result_rx) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |ctx| {
+            let result = catch_unwind(AssertUnwindSafe(|| f(ctx))).map_err(panic_error);
+            // The caller may have stopped waiting.
+            _ = result_tx.send(result);
+        });
+        let Some(tx) = &self.tx else {
+            bail!("the actor has shut down");
+        };
+        if tx.send(job).is_err() {
+            bail!("the actor has shut down");
+        }
+        Ok(result_rx)
+    }
+
+    /// Queue `f` and wait for its result.
+    ///
+    /// Fails when called from a closure run by the actor itself, which would wait for its own
+    /// thread forever. Such a closure can use [`post_async`](Self::post_async) instead.
+    pub fn post_blocking<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Context) -> R + Send + 'static,
+    ) -> Result<R> {
+        if self.on_actor_thread() {
+            bail!("post_blocking called from the actor thread");
+        }
+        self.post_async(f)?
+            .recv()
+            .map_err(|_| anyhow!("the actor stopped before running the closure"))?
+    }
+
+    /// Run the closures already posted, then stop the thread.
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn on_actor_thread(&self) -> bool {
+        let current = std::thread::current().id();
+        self.thread.as_ref().map(|thread| thread.thread().id()) == Some(current)
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        drop(self.tx.take());
+        // Dropped by one of its closures, the thread exits once the queue is drained.
+        if self.on_actor_thread() {
+            return Ok(());
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                bail!("the actor thread panicked");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for JsActor {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            log::warn!("failed to stop the JS actor: {err}");
+        }
+    }
+}
+
+fn panic_error(panic: Box<dyn core::any::Any + Send>) -> crate::Error {
+    let message = if let Some(message) = panic.downcast_ref::<&str>() {
+        String::from(*message)
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    };
+    anyhow!("the closure panicked: {message}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Code, FromJsValue};
+    use alloc::vec::Vec;
+    use std::time::Duration;
+
+    #[test]
+    fn runs_posted_closures_in_order() {
+        let actor = JsActor::spawn(EngineConfig::default(), |ctx| {
+            ctx.eval(&Code::Source(
+                "globalThis.busy = false; globalThis.log = []",
+            ))
+            .map_err(|err| anyhow!(err))?;
+            Ok(())
+        })
+        .unwrap();
+        std::thread::scope(|scope| {
+            for thread in 0..3 {
+                let actor = &actor;
+                scope.spawn(move || {
+                    for i in 0..10 {
+                        actor
+                            .post_blocking(move |ctx| {
+                                let enter = "if (busy) throw new Error('overlap'); busy = true";
+                                ctx.eval(&Code::Source(enter)).unwrap();
+                                std::thread::sleep(Duration::from_millis(1));
+                                let leave = format!("busy = false; log.push('{thread}:{i}')");
+                                ctx.eval(&Code::Source(&leave)).unwrap();
+                            })
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        let log = actor
+            .post_blocking(|ctx| {
+                let log = ctx.eval(&Code::Source("log")).unwrap();
+                Vec::<String>::from_js_value(log).unwrap()
+            })
+            .unwrap();
+        assert_eq!(log.len(), 30);
+        for thread in 0..3 {
+            let prefix = format!("{thread}:");
+            let order: Vec<&str> = log
+                .iter()
+                .filter_map(|entry| entry.strip_prefix(&prefix))
+                .collect();
+            let expected: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+            assert_eq!(order, expected);
+        }
+
+        let err = actor
+            .post_blocking(|_ctx| -> u32 { panic!("boom") })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "the closure panicked: boom");
+        assert_eq!(actor.post_blocking(|_ctx| 7).unwrap(), 7);
+    }
+
+    #[test]
+    fn shutdown_drains_the_queue() {
+        let actor = JsActor::spawn(EngineConfig::default(), |_ctx| Ok(())).unwrap();
+        let pending: Vec<_> = (0..5)
+            .map(|i| {
+                actor
+                    .post_async(move |ctx| {
+                        std::thread::sleep(Duration::from_millis(2));
+                        let code = format!("{i} * 2");
+                        ctx.eval(&Code::Source(&code))
+                            .unwrap()
+                            .decode_u32()
+                            .unwrap()
+                    })
+                    .unwrap()
+            })
+            .collect();
+        actor.shutdown().unwrap();
+        let results: Vec<u32> = pending
+            .into_iter()
+            .map(|rx| rx.recv().unwrap().unwrap())
+            .collect();
+        assert_eq!(results, [0, 2, 4, 6, 8]);
+
+        let failed = JsActor::spawn(EngineConfig::default(), |_ctx| bail!("no extensions"));
+        assert_eq!(failed.err().unwrap().to_string(), "no extensions");
+    }
+
+    #[test]
+    fn refuses_to_block_its_own_thread() {
+        let actor =
+            std::sync::Arc::new(JsActor::spawn(EngineConfig::default(), |_ctx| Ok(())).unwrap());
+        let inner = actor.clone();
+        let err = actor
+            .post_blocking(move |_ctx| inner.post_blocking(|_ctx| 1).unwrap_err().to_string())
+            .unwrap();
+        assert_eq!(err, "post_blocking called from the actor thread");
+        assert_eq!(actor.post_blocking(|_ctx| 7).unwrap(), 7);
+    }
+}
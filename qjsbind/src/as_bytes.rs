@@ -1,4 +1,4 @@
-use core::ops::Deref;
+use core::{any::Any, ops::Deref};
 
 use alloc::vec::Vec;
 
@@ -12,6 +12,17 @@ pub fn encode_as_bytes<T: AsRef<[u8]>>(ctx: &js::Context, data: &T) -> Result<Va
     Ok(Value::from_bytes(ctx, data.as_ref()))
 }
 
+/// Encode the bytes as a JS array of numbers instead of a Uint8Array.
+///
+/// Used by derived impls for fields with `#[qjs(bytes = "array")]`.
+pub fn encode_as_array<T: AsRef<[u8]>>(ctx: &js::Context, data: &T) -> Result<Value> {
+    let array = Value::new_array(ctx);
+    for byte in data.as_ref() {
+        array.array_push(&Value::from_u8(ctx, *byte))?;
+    }
+    Ok(array)
+}
+
 pub fn decode_as_bytes<T>(js_value: Value) -> Result<T>
 where
     Vec<u8>: TryInto<T>,
@@ -20,7 +31,7 @@ where
     bytes
         .try_into()
         .ok()
-        .expect_js_value(&js_value, "bytes-like object")
+        .expect_type(&js_value, "bytes-like object")
 }
 
 pub fn decode_as_bytes_maybe_hex<T>(js_value: Value) -> Result<T>
@@ -31,7 +42,7 @@ where
     bytes
         .try_into()
         .ok()
-        .expect_js_value(&js_value, "bytes-like object")
+        .expect_type(&js_value, "bytes-like object")
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
@@ -48,10 +59,19 @@ impl<T> From<T> for AsBytes<T> {
     }
 }
 
-impl<T: AsRef<[u8]>> ToJsValue for AsBytes<T> {
+impl<T: AsRef<[u8]> + 'static> ToJsValue for AsBytes<T> {
     fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
         encode_as_bytes(ctx, &self.0)
     }
+
+    /// Hands a `Vec<u8>` over to the engine, see [`Value::from_bytes_owned`].
+    fn into_js_value(self, ctx: &js::Context) -> Result<Value> {
+        let mut slot = Some(self.0);
+        if let Some(bytes) = (&mut slot as &mut dyn Any).downcast_mut::<Option<Vec<u8>>>() {
+            return Value::from_bytes_owned(ctx, bytes.take().expect("checked above"));
+        }
+        encode_as_bytes(ctx, &slot.expect("only taken when returning"))
+    }
 }
 
 impl<T> FromJsValue for AsBytes<T>
@@ -234,4 +254,56 @@ impl ToJsValue for Bytes {
             Self::Bytes(bytes) => encode_as_bytes(ctx, bytes),
         }
     }
+
+    fn into_js_value(self, ctx: &js::Context) -> Result<Value> {
+        match self {
+            Self::Bytes(bytes) => Value::from_bytes_owned(ctx, bytes),
+            _ => self.to_js_value(ctx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    #[derive(Debug, PartialEq, crate::ToJsValue, crate::FromJsValue)]
+    struct HttpRequest {
+        url: String,
+        body: Vec<u8>,
+    }
+
+    #[derive(Debug, PartialEq, crate::ToJsValue, crate::FromJsValue)]
+    struct LegacyRequest {
+        url: String,
+        #[qjs(bytes = "array")]
+        body: Vec<u8>,
+    }
+
+    #[test]
+    fn byte_vecs_round_trip() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let request = HttpRequest {
+            url: "https://example.com".into(),
+            body: b"hello".to_vec(),
+        };
+        let value = request.to_js_value(&ctx).unwrap();
+        assert!(value.get_property("body").unwrap().is_uint8_array());
+        assert_eq!(HttpRequest::from_js_value(value).unwrap(), request);
+
+        let legacy = LegacyRequest {
+            url: request.url.clone(),
+            body: request.body.clone(),
+        };
+        let value = legacy.to_js_value(&ctx).unwrap();
+        assert!(value.get_property("body").unwrap().is_array());
+        assert_eq!(LegacyRequest::from_js_value(value.clone()).unwrap(), legacy);
+        // Either form is accepted whatever the field is written as.
+        assert_eq!(HttpRequest::from_js_value(value).unwrap(), request);
+
+        let empty = Vec::<u8>::new().to_js_value(&ctx).unwrap();
+        assert!(Vec::<u8>::from_js_value(empty).unwrap().is_empty());
+    }
 }
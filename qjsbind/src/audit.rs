@@ -0,0 +1,289 @@
+//! Opt-in logging of the host functions and globals a script touches.
+
+use alloc::{rc::Rc, string::String, vec::Vec};
+use anyhow::bail;
+use core::cell::{Cell, RefCell};
+
+use crate::{self as js, ErrorContext, FromJsValue, Result, ToJsValue, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// A host function was invoked.
+    HostCall,
+    /// A global registered with `watch_globals` was read.
+    GlobalRead,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessEvent {
+    pub kind: AccessKind,
+    pub name: String,
+    /// The JS stack at the time of access. Only captured for global reads.
+    pub stack: Option<String>,
+}
+
+/// The auditor of a context, kept on the host side so that the audited script can not remove it.
+#[derive(Default)]
+struct AuditState {
+    auditor: RefCell<Option<Rc<dyn Fn(AccessEvent)>>>,
+    /// Whether the `on_destroy` callback dropping the auditor is registered.
+    dropped_on_destroy: Cell<bool>,
+}
+
+impl js::Context {
+    /// Install a hook receiving an `AccessEvent` for every host function call and every read of
+    /// the globals watched via `watch_globals`.
+    ///
+    /// The hook is kept with the context and must not hold JS values of it. It is dropped when
+    /// the context is destroyed, see [`on_destroy`](Self::on_destroy), so that what it holds,
+    /// like the sender of a channel, tells the end of the context.
+    pub fn set_access_auditor(&self, auditor: impl Fn(AccessEvent) + 'static) -> Result<()> {
+        self.replace_access_auditor(Rc::new(auditor))
+    }
+
+    /// The auditor installed, to put back later with `replace_access_auditor`.
+    pub(crate) fn access_auditor(&self) -> Option<Rc<dyn Fn(AccessEvent)>> {
+        self.user_data::<AuditState>()?.auditor.borrow().clone()
+    }
+
+    pub(crate) fn replace_access_auditor(&self, auditor: Rc<dyn Fn(AccessEvent)>) -> Result<()> {
+        let state = self
+            .state::<AuditState>()
+            .context("no access auditing for a context without teardown support")?;
+        if !state.dropped_on_destroy.replace(true) {
+            self.on_destroy(|ctx| {
+                if let Some(state) = ctx.user_data::<AuditState>() {
+                    state.auditor.take();
+                }
+            });
+        }
+        *state.auditor.borrow_mut() = Some(auditor);
+        self.set_audit_enabled(true);
+        Ok(())
+    }
+
+    pub fn clear_access_auditor(&self) -> Result<()> {
+        if let Some(state) = self.user_data::<AuditState>() {
+            state.auditor.take();
+        }
+        self.set_audit_enabled(false);
+        Ok(())
+    }
+}
+
+pub(crate) fn emit(ctx: &js::Context, kind: AccessKind, name: &str) {
+    if !ctx.audit_enabled() {
+        return;
+    }
+    // A clone, so that the auditor can replace or clear itself.
+    let Some(auditor) = ctx
+        .user_data::<AuditState>()
+        .and_then(|state| state.auditor.borrow().clone())
+    else {
+        return;
+    };
+    let stack = match kind {
+        AccessKind::HostCall => None,
+        AccessKind::GlobalRead => current_stack(ctx),
+    };
+    auditor(AccessEvent {
+        kind,
+        name: name.into(),
+        stack,
+    });
+}
+
+fn current_stack(ctx: &js::Context) -> Option<String> {
+    let error = ctx.intrinsic("Error").ok()?.construct(&[]).ok()?;
+    error.get_property("stack").ok()?.decode_string().ok()
+}
+
+pub(crate) const GLOBAL_READ_FN: &str = "audit_global_read";
+
+#[crate::host_call(with_context)]
+fn audit_global_read(ctx: js::Context, _this: Value, name: js::JsString) {
+    emit(&ctx, AccessKind::GlobalRead, name.as_str());
+}
+
+/// Replace each of the named globals with a getter reporting `AccessKind::GlobalRead` to the
+/// context's access auditor, and return the names left unwatched.
+///
+/// The properties are redefined as non-configurable, so scripts can not remove the getters.
+/// Accessors keep their getter and setter, and read-only values stay read-only. Globals that
+/// are already non-configurable, like the ones declared with `var`, can not be redefined and
+/// are returned instead.
+pub fn watch_globals(ctx: &js::Context, names: &[&str]) -> Result<Vec<String>> {
+    let install = ctx
+        .eval(&js::Code::Source(
+            r#"(function (names, report) {
+                const apply = Reflect.apply;
+                const skipped = [];
+                for (const name of names) {
+                    const desc = Object.getOwnPropertyDescriptor(globalThis, name);
+                    if (desc && !desc.configurable) {
+                        skipped.push(name);
+                        continue;
+                    }
+                    const enumerable = desc ? desc.enumerable : false;
+                    const watched = { enumerable, configurable: false };
+                    if (desc && !("value" in desc)) {
+                        const { get, set } = desc;
+                        watched.get = function () {
+                            report(name);
+                            return get ? apply(get, this, []) : undefined;
+                        };
+                        if (set) watched.set = function (v) { apply(set, this, [v]); };
+                    } else {
+                        let value = desc ? desc.value : undefined;
+                        watched.get = () => { report(name); return value; };
+                        if (!desc || desc.writable) watched.set = (v) => { value = v; };
+                    }
+                    Object.defineProperty(globalThis, name, watched);
+                }
+                return skipped;
+            })"#,
+        ))
+        .map_err(js::Error::msg)?;
+    if !install.is_function() {
+        bail!("failed to create the global watcher");
+    }
+    let report = ctx.new_function("report", audit_global_read, 1, js::c::JS_CFUNC_generic);
+    let names = names.to_js_value(ctx)?;
+    let skipped = install.call(&Value::undefined(), &[names, report])?;
+    Vec::from_js_value(skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[crate::host_call]
+    fn touch() -> u32 {
+        1
+    }
+
+    #[crate::host_call]
+    fn seen_calls() -> usize {
+        SEEN.with(|seen| {
+            let seen = seen.borrow();
+            seen.iter().filter(|e| e.name == "seen_calls").count()
+        })
+    }
+
+    std::thread_local! {
+        static SEEN: RefCell<Vec<AccessEvent>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn events() -> Vec<(AccessKind, String)> {
+        SEEN.with(|seen| {
+            seen.borrow()
+                .iter()
+                .map(|event| (event.kind, event.name.clone()))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn events_follow_the_script() {
+        SEEN.with(|seen| seen.borrow_mut().clear());
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let global = ctx.get_global_object();
+        global.define_property_fn("touch", touch).unwrap();
+        global.define_property_fn("seenCalls", seen_calls).unwrap();
+        ctx.eval(&js::Code::Source(
+            "globalThis.secret = 42; globalThis.other = 1;",
+        ))
+        .unwrap();
+        assert!(watch_globals(&ctx, &["secret"]).unwrap().is_empty());
+        ctx.set_access_auditor(|event| SEEN.with(|seen| seen.borrow_mut().push(event)))
+            .unwrap();
+
+        let result = ctx
+            .eval(&js::Code::Source(
+                r#"
+                delete globalThis._QjsBind;
+                other + secret + touch() + touch();
+                // The call is reported before it runs.
+                if (seenCalls() !== 1) throw new Error("reported late");
+                "#,
+            ))
+            .unwrap();
+        assert!(result.is_undefined());
+        use AccessKind::*;
+        assert_eq!(
+            events(),
+            [
+                (GlobalRead, "secret".into()),
+                (HostCall, "touch".into()),
+                (HostCall, "touch".into()),
+                (HostCall, "seen_calls".into()),
+            ]
+        );
+        let stack = SEEN.with(|seen| seen.borrow()[0].stack.clone()).unwrap();
+        assert!(stack.contains("<eval>"), "{stack}");
+
+        ctx.clear_access_auditor().unwrap();
+        assert!(!ctx.audit_enabled());
+        ctx.eval(&js::Code::Source("secret; touch()")).unwrap();
+        assert_eq!(events().len(), 4);
+    }
+
+    #[test]
+    fn accessors_and_fixed_globals() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src)).unwrap();
+        eval(
+            r#"
+            var fixed = 1;
+            let ticks = 0;
+            Object.defineProperty(globalThis, "clock", {
+                get() { return ++ticks; },
+                set(v) { ticks = v; },
+                configurable: true,
+            });
+            Object.defineProperty(globalThis, "frozen", { value: 5, configurable: true });
+            "#,
+        );
+        let reads = Rc::new(Cell::new(0));
+        let counter = reads.clone();
+        ctx.set_access_auditor(move |_| counter.set(counter.get() + 1))
+            .unwrap();
+        let skipped = watch_globals(&ctx, &["clock", "frozen", "fixed"]).unwrap();
+        assert_eq!(skipped, ["fixed"]);
+
+        let result = eval("clock = 10; [clock, clock, frozen, (frozen = 6, frozen), fixed].join()");
+        assert_eq!(result.to_string(), "11,12,5,5,1");
+        assert_eq!(reads.get(), 4);
+    }
+
+    #[test]
+    fn auditors_are_per_context() {
+        let runtime = js::Runtime::new(&Default::default());
+        let audited = runtime.new_context();
+        let other = runtime.new_context();
+        let count = Rc::new(core::cell::Cell::new(0));
+        let counter = count.clone();
+        audited
+            .set_access_auditor(move |_| counter.set(counter.get() + 1))
+            .unwrap();
+        for ctx in [&audited, &other] {
+            ctx.get_global_object()
+                .define_property_fn("touch", touch)
+                .unwrap();
+            ctx.eval(&js::Code::Source("touch()")).unwrap();
+        }
+        assert_eq!(count.get(), 1);
+        assert!(!other.audit_enabled());
+
+        // Replaced, then dropped with the context.
+        let counter = count.clone();
+        audited
+            .set_access_auditor(move |_| counter.set(counter.get() + 1))
+            .unwrap();
+        assert_eq!(Rc::strong_count(&count), 2);
+        drop(audited);
+        assert_eq!(Rc::strong_count(&count), 1);
+    }
+}
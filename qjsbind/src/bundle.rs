@@ -0,0 +1,282 @@
+//! Sets of named scripts evaluated in the order of their dependencies.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use anyhow::{anyhow, bail};
+
+use crate::{Context, EvalOptions, JsCode, Result, Value};
+
+/// Named scripts with the names of the scripts they depend on, evaluated in one context so that
+/// a script sees the globals defined by its dependencies.
+///
+/// ```ignore
+/// let mut bundle = Bundle::new();
+/// bundle.add("app.js", JsCode::Source(app), ["lib.js"]);
+/// bundle.add("lib.js", JsCode::Source(lib), [] as [&str; 0]);
+/// bundle.eval(&ctx)?.check()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Bundle {
+    scripts: Vec<Script>,
+    isolated: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Script {
+    name: String,
+    code: JsCode,
+    deps: Vec<String>,
+}
+
+/// The outcome of [`Bundle::eval`]: the scripts evaluated, in order, up to the first failure.
+#[derive(Debug)]
+pub struct BundleReport {
+    pub scripts: Vec<ScriptOutcome>,
+}
+
+/// The result of evaluating one script of a bundle.
+#[derive(Debug)]
+pub struct ScriptOutcome {
+    pub name: String,
+    /// The completion value of the script, or the module namespace when isolated.
+    pub result: Result<Value, String>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the script `name`, to be evaluated after the scripts named in `deps`.
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        code: JsCode,
+        deps: impl IntoIterator<Item = impl Into<String>>,
+    ) -> &mut Self {
+        self.scripts.push(Script {
+            name: name.into(),
+            code,
+            deps: deps.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+
+    /// Evaluate each script as an ES module, so that its top-level `let`, `const` and functions
+    /// stay its own. Scripts then share only what they put on `globalThis`.
+    pub fn isolated(&mut self, isolated: bool) -> &mut Self {
+        self.isolated = isolated;
+        self
+    }
+
+    /// The names of the scripts in evaluation order: dependencies first, and otherwise in the
+    /// order they were added.
+    ///
+    /// Fails for duplicate names, dependencies on scripts not in the bundle, and dependency
+    /// cycles, naming the scripts of the cycle.
+    pub fn order(&self) -> Result<Vec<&str>> {
+        let mut index = BTreeMap::new();
+        for (i, script) in self.scripts.iter().enumerate() {
+            if index.insert(script.name.as_str(), i).is_some() {
+                bail!("duplicate script '{}'", script.name);
+            }
+        }
+        for script in &self.scripts {
+            if let Some(dep) = script
+                .deps
+                .iter()
+                .find(|dep| !index.contains_key(dep.as_str()))
+            {
+                bail!("script '{}' depends on unknown script '{dep}'", script.name);
+            }
+        }
+
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            New,
+            Visiting,
+            Done,
+        }
+        let mut states = alloc::vec![State::New; self.scripts.len()];
+        let mut order = Vec::with_capacity(self.scripts.len());
+        // Depth-first, with the path to the current script to report cycles.
+        let mut path: Vec<(usize, usize)> = Vec::new();
+        for root in 0..self.scripts.len() {
+            if states[root] != State::New {
+                continue;
+            }
+            states[root] = State::Visiting;
+            path.push((root, 0));
+            while let Some((current, next_dep)) = path.last_mut() {
+                let script = &self.scripts[*current];
+                let Some(dep) = script.deps.get(*next_dep) else {
+                    states[*current] = State::Done;
+                    order.push(script.name.as_str());
+                    path.pop();
+                    continue;
+                };
+                *next_dep += 1;
+                let dep = index[dep.as_str()];
+                match states[dep] {
+                    State::Done => {}
+                    State::New => {
+                        states[dep] = State::Visiting;
+                        path.push((dep, 0));
+                    }
+                    State::Visiting => {
+                        let start = path.iter().position(|(i, _)| *i == dep).unwrap_or(0);
+                        let mut cycle: Vec<&str> = path[start..]
+                            .iter()
+                            .map(|(i, _)| self.scripts[*i].name.as_str())
+                            .collect();
+                        cycle.push(&self.scripts[dep].name);
+                        bail!("dependency cycle: {}", cycle.join(" -> "));
+                    }
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Evaluate the scripts in [`order`](Self::order), each with its name as the filename of
+    /// its errors and stack traces, stopping at the first that fails.
+    ///
+    /// Errors are for bundles that cannot be ordered, the failure of a script is in the report.
+    pub fn eval(&self, ctx: &Context) -> Result<BundleReport> {
+        let order = self.order()?;
+        let mut report = BundleReport {
+            scripts: Vec::with_capacity(order.len()),
+        };
+        for name in order {
+            let Some(script) = self.scripts.iter().find(|script| script.name == name) else {
+                continue;
+            };
+            let result = if self.isolated {
+                ctx.eval_named_module(name, &script.code)
+                    .map_err(|err| alloc::format!("{err:#}"))
+            } else {
+                ctx.eval_with_options(&script.code.as_code(), &EvalOptions::at(name, 1))
+            };
+            let failed = result.is_err();
+            report.scripts.push(ScriptOutcome {
+                name: name.into(),
+                result,
+            });
+            if failed {
+                break;
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl BundleReport {
+    /// The script that failed, the last one evaluated.
+    pub fn failed(&self) -> Option<&ScriptOutcome> {
+        self.scripts.last().filter(|script| script.result.is_err())
+    }
+
+    /// Fail with the error of the script that failed, naming it.
+    pub fn check(&self) -> Result<()> {
+        match self.failed() {
+            Some(ScriptOutcome {
+                name,
+                result: Err(err),
+            }) => Err(anyhow!("{err}").context(alloc::format!("script '{name}' failed"))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{self as js, Code};
+
+    fn source(src: &str) -> JsCode {
+        JsCode::Source(src.into())
+    }
+
+    fn chain(b: &str) -> Bundle {
+        let mut bundle = Bundle::new();
+        bundle
+            .add("c.js", source("globalThis.c = b + 1; c"), ["b.js"])
+            .add("b.js", source(b), ["a.js"])
+            .add("a.js", source("globalThis.a = 1"), [] as [&str; 0]);
+        bundle
+    }
+
+    #[test]
+    fn scripts_run_after_their_dependencies() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let bundle = chain("globalThis.b = a + 1");
+        assert_eq!(bundle.order().unwrap(), ["a.js", "b.js", "c.js"]);
+        let report = bundle.eval(&ctx).unwrap();
+        report.check().unwrap();
+        let last = report.scripts.last().unwrap();
+        assert_eq!(last.result.as_ref().unwrap().decode_u32().unwrap(), 3);
+
+        let mut duplicate = chain("globalThis.b = a + 1");
+        duplicate.add("a.js", source(""), ["c.js"]);
+        let err = duplicate.order().unwrap_err().to_string();
+        assert_eq!(err, "duplicate script 'a.js'");
+        let mut cyclic = Bundle::new();
+        cyclic
+            .add("a.js", source(""), ["c.js"])
+            .add("b.js", source(""), ["a.js"])
+            .add("c.js", source(""), ["b.js"]);
+        let err = cyclic.eval(&ctx).unwrap_err().to_string();
+        assert_eq!(err, "dependency cycle: a.js -> c.js -> b.js -> a.js");
+    }
+
+    #[test]
+    fn a_failing_script_is_named() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let bundle = chain("function load() { throw new Error('no b'); }\nload();");
+        let report = bundle.eval(&ctx).unwrap();
+        let names: Vec<_> = report.scripts.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["a.js", "b.js"]);
+        let failed = report.failed().unwrap();
+        assert_eq!(failed.name, "b.js");
+        let err = failed.result.as_ref().unwrap_err();
+        assert!(err.contains("no b") && err.contains("b.js:1"), "{err}");
+        let err = alloc::format!("{:#}", report.check().unwrap_err());
+        assert!(
+            err.starts_with("script 'b.js' failed: Error: no b"),
+            "{err}"
+        );
+        let c = ctx.eval(&Code::Source("typeof c")).unwrap();
+        assert_eq!(c.decode_string().unwrap(), "undefined");
+    }
+
+    #[test]
+    fn isolated_scripts_keep_their_top_level() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let mut bundle = Bundle::new();
+        bundle
+            .add(
+                "a.js",
+                source("const x = 1; globalThis.shared = x;"),
+                [] as [&str; 0],
+            )
+            .add(
+                "b.js",
+                source("const x = 2; globalThis.shared += x;"),
+                ["a.js"],
+            )
+            .isolated(true);
+        bundle.eval(&ctx).unwrap().check().unwrap();
+        let shared = ctx.eval(&Code::Source("shared")).unwrap();
+        assert_eq!(shared.decode_u32().unwrap(), 3);
+
+        // The same scripts clash without isolation.
+        let ctx = runtime.new_context();
+        let report = bundle.isolated(false).eval(&ctx).unwrap();
+        assert_eq!(report.failed().unwrap().name, "b.js");
+    }
+}
@@ -0,0 +1,165 @@
+//! Structured clone of values between contexts.
+
+use alloc::collections::BTreeMap;
+use anyhow::bail;
+
+use crate::{self as js, c, Result, Value};
+
+const MAX_DEPTH: usize = 128;
+
+impl Value {
+    /// Deep copy the value into `target`, which may belong to another runtime.
+    ///
+    /// Primitives, BigInts, strings, arrays, plain objects and byte arrays are copied, nested in
+    /// any way. An object reached twice, through a cycle or not, is copied once and the copy is
+    /// shared the same way. Functions, class instances and other objects fail the clone.
+    ///
+    /// Values can not be used in another context as they are: setting them as properties or
+    /// passing them as arguments there fails, they have to be copied with this first.
+    pub fn clone_into(&self, target: &js::Context) -> Result<Value> {
+        Cloner {
+            target,
+            seen: BTreeMap::new(),
+        }
+        .clone_value(self, 0)
+    }
+
+    /// Fail if the value belongs to a context other than `ctx`.
+    pub(crate) fn check_context(&self, ctx: &js::Context) -> Result<()> {
+        match self {
+            Value::Other { ctx: own, .. } if own.as_ptr() != ctx.as_ptr() => {
+                bail!("the value belongs to another context, copy it with `Value::clone_into`")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Cloner<'a> {
+    target: &'a js::Context,
+    /// The copies of the objects cloned so far, by the address of the original.
+    seen: BTreeMap<usize, Value>,
+}
+
+impl Cloner<'_> {
+    fn clone_value(&mut self, value: &Value, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("value too deep to clone");
+        }
+        let ctx = self.target;
+        if value.is_undefined() {
+            return Ok(Value::undefined());
+        }
+        if value.is_null() {
+            return Ok(Value::null());
+        }
+        if value.is_bool() {
+            return Ok(Value::from_bool(ctx, value.decode_bool()?));
+        }
+        if value.is_number() {
+            return Ok(Value::from_f64(ctx, value.decode_f64()?));
+        }
+        if value.is_big_int() {
+            return Value::bigint_from_str(ctx, &value.to_string());
+        }
+        if value.is_string() {
+            return Ok(Value::from_str(ctx, &value.decode_string()?));
+        }
+        if !value.is_object() {
+            bail!("can not clone {} into another context", value.get_name());
+        }
+        let key = unsafe { c::JS_GetPtr(*value.raw_value()) } as usize;
+        if let Some(copy) = self.seen.get(&key) {
+            return Ok(copy.clone());
+        }
+        if value.is_uint8_array() || value.is_array_buffer() {
+            let copy = Value::from_bytes(ctx, &value.decode_bytes()?);
+            self.seen.insert(key, copy.clone());
+            return Ok(copy);
+        }
+        if value.is_array() {
+            let array = ctx.new_array();
+            self.seen.insert(key, array.clone());
+            for i in 0..value.length()? {
+                array.array_push(&self.clone_value(&value.index(i)?, depth + 1)?)?;
+            }
+            return Ok(array);
+        }
+        if value.is_plain_object() {
+            let object = ctx.new_object("");
+            self.seen.insert(key, object.clone());
+            for entry in value.entries()? {
+                let (name, field) = entry?;
+                let field = self.clone_value(&field, depth + 1)?;
+                // Defined, so that an own `__proto__` key stays a key.
+                object.define_property_value(&name.decode_string()?, field)?;
+            }
+            return Ok(object);
+        }
+        bail!("can not clone {} into another context", value.get_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Code, Runtime, ToArgs};
+
+    use super::*;
+
+    #[test]
+    fn nested_values_round_trip_between_contexts() {
+        let runtime = Runtime::new(&Default::default());
+        let a = runtime.new_context();
+        let b = runtime.new_context();
+        let value = a
+            .eval(&Code::Source(
+                "const v = { name: 'x', n: 1.5, big: 12345678901234567890n,
+                    list: [1, null, { bytes: new Uint8Array([1, 2, 3]) }] };
+                v.self = v;
+                v",
+            ))
+            .unwrap();
+
+        let copy = value.clone_into(&b).unwrap();
+        assert!(copy.context().unwrap().as_ptr() == b.as_ptr());
+        b.get_global_object().set_property("copy", &copy).unwrap();
+        let check = b
+            .eval(&Code::Source(
+                "copy.self === copy && copy.list[2].bytes instanceof Uint8Array
+                    && copy.big === 12345678901234567890n",
+            ))
+            .unwrap();
+        assert!(check.decode_bool().unwrap());
+
+        let back = copy.clone_into(&a).unwrap();
+        let bytes = back.get_property("list").unwrap().index(2).unwrap();
+        let bytes = bytes.get_property("bytes").unwrap().decode_bytes().unwrap();
+        assert_eq!(bytes, [1, 2, 3]);
+        assert_eq!(
+            back.get_property("name").unwrap().decode_string().unwrap(),
+            "x"
+        );
+
+        let err = b.get_global_object().set_property("v", &value).unwrap_err();
+        assert!(err.to_string().contains("another context"), "{err}");
+        assert!((value.clone(),).to_args(&b).is_err());
+        let f = a.eval(&Code::Source("() => 1")).unwrap();
+        assert!(f.clone_into(&b).is_err());
+
+        let keyed = a
+            .eval(&Code::Source(
+                r#"JSON.parse('{"__proto__": {"polluted": 1}}')"#,
+            ))
+            .unwrap();
+        b.get_global_object()
+            .set_property("keyed", &keyed.clone_into(&b).unwrap())
+            .unwrap();
+        let check = b
+            .eval(&Code::Source(
+                "Object.getPrototypeOf(keyed) === Object.prototype && keyed.polluted === undefined
+                    && Object.keys(keyed).join() === '__proto__'",
+            ))
+            .unwrap();
+        assert!(check.decode_bool().unwrap());
+    }
+}
@@ -0,0 +1,335 @@
+//! Compiling scripts ahead of time, and finding out what they depend on before running them.
+
+use alloc::ffi::CString;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+
+use anyhow::{anyhow, bail};
+
+use crate::{self as js, c, Result, Value};
+
+/// A compiled script or module, see [`compile`].
+#[derive(Debug)]
+pub struct Compiled {
+    /// The compiled function, or the module when the source is one. It has not been run.
+    pub value: Value,
+    /// The serialized bytecode, to be run later with [`js::Code::Bytecode`].
+    pub bytecode: Vec<u8>,
+    /// The globals the code reads, writes or calls, nested functions included, each listed
+    /// once in bytecode order.
+    pub globals: Vec<String>,
+    /// The specifiers of the modules a module imports or re-exports from, each listed once.
+    pub imports: Vec<String>,
+    /// Whether the source was compiled as a module.
+    pub is_module: bool,
+}
+
+/// Compile `src` without running it, as a module if it has imports or exports.
+///
+/// The referenced globals are read from the compiled bytecode: every name a function does not
+/// resolve to a local, an argument or a closure variable. The top-level declarations of a script
+/// are globals too, so they are listed when used. Globals reached by a computed name, like
+/// `globalThis[name]` or `eval`, can not be seen. Use the list to review or reject scripts up
+/// front, not as a sandbox.
+pub fn compile(ctx: &js::Context, src: &str, name: &str) -> Result<Compiled> {
+    let is_module = is_module(src);
+    let value = compile_source(ctx, src, name, is_module)?;
+    let bytecode = ctx.write_object(&value, WriteFlags::bytecode())?;
+    let globals = visit_names(ctx, &value, c::JS_VisitGlobalRefs)?;
+    let imports = if is_module {
+        visit_names(ctx, &value, c::JS_VisitModuleRequests)?
+    } else {
+        Vec::new()
+    };
+    Ok(Compiled {
+        value,
+        bytecode,
+        globals,
+        imports,
+        is_module,
+    })
+}
+
+/// Whether `src` has imports or exports.
+fn is_module(src: &str) -> bool {
+    unsafe { c::JS_DetectModule(src.as_ptr() as _, src.len() as _) != 0 }
+}
+
+/// Compile `src` without running it.
+fn compile_source(ctx: &js::Context, src: &str, name: &str, is_module: bool) -> Result<Value> {
+    let kind = if is_module {
+        c::JS_EVAL_TYPE_MODULE
+    } else {
+        c::JS_EVAL_TYPE_GLOBAL
+    };
+    let code = CString::new(src).or(Err(anyhow!("NUL in the source")))?;
+    let filename = CString::new(name).or(Err(anyhow!("NUL in the name")))?;
+    let value = unsafe {
+        c::JS_Eval(
+            ctx.as_ptr(),
+            code.as_ptr() as _,
+            src.len() as _,
+            filename.as_ptr() as _,
+            (kind | c::JS_EVAL_FLAG_COMPILE_ONLY) as _,
+        )
+    };
+    let value = Value::new_moved(ctx, value);
+    if value.is_exception() {
+        return Err(ctx.get_exception_error());
+    }
+    Ok(value)
+}
+
+/// What `Context::write_object` may serialize besides plain data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteFlags {
+    /// Allow functions and modules, serialized as bytecode.
+    pub bytecode: bool,
+    /// Keep objects reached more than once shared, cycles included, instead of failing.
+    pub references: bool,
+}
+
+impl WriteFlags {
+    pub fn bytecode() -> Self {
+        Self {
+            bytecode: true,
+            ..Default::default()
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        let mut bits = 0;
+        if self.bytecode {
+            bits |= c::JS_WRITE_OBJ_BYTECODE;
+        }
+        if self.references {
+            bits |= c::JS_WRITE_OBJ_REFERENCE;
+        }
+        bits
+    }
+}
+
+impl js::Context {
+    /// Compile `src` without running it and serialize its bytecode, to be run later with
+    /// [`js::Code::Bytecode`] or [`js::JsCode::Bytecode`], in this runtime or another one using
+    /// the same engine build.
+    pub fn compile_to_bytecode(&self, src: &str, name: &str) -> Result<Vec<u8>> {
+        let value = compile_source(self, src, name, is_module(src))?;
+        self.write_object(&value, WriteFlags::bytecode())
+    }
+
+    /// Like [`compile_to_bytecode`](Self::compile_to_bytecode), but always as a global script,
+    /// even if `src` looks like a module.
+    pub fn compile_script_to_bytecode(&self, src: &str, name: &str) -> Result<Vec<u8>> {
+        let value = compile_source(self, src, name, false)?;
+        self.write_object(&value, WriteFlags::bytecode())
+    }
+
+    /// Serialize `value` in the binary format of the engine, to be read back with
+    /// [`read_object`](Self::read_object).
+    pub fn write_object(&self, value: &Value, flags: WriteFlags) -> Result<Vec<u8>> {
+        value.check_context(self)?;
+        unsafe {
+            let mut len = 0;
+            let buf = c::JS_WriteObject(
+                self.as_ptr(),
+                &mut len,
+                *value.raw_value(),
+                flags.bits() as _,
+            );
+            if buf.is_null() {
+                return Err(self.get_exception_error());
+            }
+            let bytes = core::slice::from_raw_parts(buf as *const u8, len as _).to_vec();
+            c::js_free(self.as_ptr(), buf as _);
+            Ok(bytes)
+        }
+    }
+
+    /// Deserialize a value written by [`write_object`](Self::write_object), bytecode and shared
+    /// references included. Functions are returned without being run.
+    ///
+    /// Bytes written by another version of the engine are rejected. Bytecode is not verified
+    /// otherwise, so it must come from a trusted source: loading crafted bytecode is unsound.
+    pub fn read_object(&self, bytes: &[u8]) -> Result<Value> {
+        check_bytecode_version(self, bytes)?;
+        let value = unsafe {
+            c::JS_ReadObject(
+                self.as_ptr(),
+                bytes.as_ptr() as _,
+                bytes.len() as _,
+                (c::JS_READ_OBJ_BYTECODE | c::JS_READ_OBJ_REFERENCE) as _,
+            )
+        };
+        let value = Value::new_moved(self, value);
+        if value.is_exception() {
+            return Err(self.get_exception_error());
+        }
+        Ok(value)
+    }
+}
+
+/// Fail unless `bytes` start with the format version of this engine build, before the engine
+/// interprets the rest.
+pub(crate) fn check_bytecode_version(ctx: &js::Context, bytes: &[u8]) -> Result<()> {
+    let Some(&version) = bytes.first() else {
+        bail!("empty bytecode");
+    };
+    // Everything written starts with the version, even `undefined`.
+    let expected = ctx.write_object(&Value::undefined(), WriteFlags::default())?[0];
+    if version != expected {
+        bail!("bytecode version {version} does not match the engine's {expected}");
+    }
+    Ok(())
+}
+
+type Visit = unsafe extern "C" fn(
+    *mut c::JSContext,
+    c::JSValueConst,
+    c::atom_visitor_fn,
+    *mut c_void,
+) -> core::ffi::c_int;
+
+/// The distinct names `visit` reports for the compiled `value`, in the order first reported.
+fn visit_names(ctx: &js::Context, value: &Value, visit: Visit) -> Result<Vec<String>> {
+    unsafe extern "C" fn push(opaque: *mut c_void, atom: c::JSAtom) {
+        let atoms = &mut *(opaque as *mut Vec<c::JSAtom>);
+        if !atoms.contains(&atom) {
+            atoms.push(atom);
+        }
+    }
+    let mut atoms: Vec<c::JSAtom> = Vec::new();
+    let r = unsafe {
+        visit(
+            ctx.as_ptr(),
+            *value.raw_value(),
+            Some(push),
+            &mut atoms as *mut _ as *mut c_void,
+        )
+    };
+    if r < 0 {
+        bail!("not compiled code");
+    }
+    atoms
+        .into_iter()
+        .map(|atom| {
+            let name = unsafe { c::JS_AtomToString(ctx.as_ptr(), atom) };
+            Value::new_moved(ctx, name).decode_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn bytecode_runs_in_another_runtime() {
+        let bytecode = {
+            let runtime = js::Runtime::new(&Default::default());
+            let ctx = runtime.new_context();
+            ctx.compile_to_bytecode("[1, 2, 3].map(n => n * 2).join()", "doubled.js")
+                .unwrap()
+        };
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let result = ctx.eval(&js::Code::Bytecode(&bytecode)).unwrap();
+        assert_eq!(result.decode_string().unwrap(), "2,4,6");
+
+        let data = ctx
+            .eval(&js::Code::Source(
+                "const o = { list: [1, 'two'] }; o.again = o.list; o",
+            ))
+            .unwrap();
+        let flags = WriteFlags {
+            references: true,
+            ..Default::default()
+        };
+        let bytes = ctx.write_object(&data, flags).unwrap();
+        let copy = ctx.read_object(&bytes).unwrap();
+        ctx.get_global_object().set_property("copy", &copy).unwrap();
+        let shared = ctx
+            .eval(&js::Code::Source(
+                "copy.again === copy.list && copy.list[1]",
+            ))
+            .unwrap();
+        assert_eq!(shared.decode_string().unwrap(), "two");
+        let function = ctx.eval(&js::Code::Source("() => 1")).unwrap();
+        assert!(ctx.write_object(&function, WriteFlags::default()).is_err());
+
+        let mut stale = bytecode.clone();
+        stale[0] = stale[0].wrapping_add(1);
+        let err = ctx.read_object(&stale).unwrap_err();
+        assert!(err.to_string().contains("bytecode version"), "{err}");
+        let err = ctx.eval(&js::Code::Bytecode(&stale)).unwrap_err();
+        assert!(err.contains("bytecode version"), "{err}");
+    }
+
+    #[test]
+    fn lists_referenced_globals_and_imports() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let compiled = compile(
+            &ctx,
+            r#"
+            const url = `https://example.com/${path.join("/")}`;
+            async function load({ key, retries: n = defaultRetries }, ...rest) {
+                const res = await fetch(url, { method: "GET", headers });
+                const digest = await crypto.subtle.digest("SHA-256", key);
+                const parse = (text) => JSON.parse(text);
+                return { res, digest, parse, n, rest, size: /[/]/.test(url) };
+            }
+            class Cache { entries = new Map(); get(key) { return this.entries.get(key); } }
+            load
+            "#,
+            "script.js",
+        )
+        .unwrap();
+        assert!(!compiled.is_module);
+        assert!(compiled.imports.is_empty());
+        let mut globals = compiled.globals.clone();
+        globals.sort();
+        assert_eq!(
+            globals,
+            [
+                "JSON",
+                "Map",
+                "crypto",
+                "defaultRetries",
+                "fetch",
+                "headers",
+                "load",
+                "path",
+                "url"
+            ]
+        );
+
+        let function = ctx.eval(&js::Code::Bytecode(&compiled.bytecode)).unwrap();
+        assert_eq!(function.get_property("name").unwrap().to_string(), "load");
+
+        let module = compile(
+            &ctx,
+            r#"
+            import { encode as hex } from "hex";
+            import * as scale from 'scale';
+            export { digest } from "./digest.js";
+            export const id = hex(scale.encode(fetch));
+            "#,
+            "module.js",
+        )
+        .unwrap();
+        assert!(module.is_module);
+        assert_eq!(module.imports, ["hex", "scale", "./digest.js"]);
+        assert_eq!(module.globals, ["fetch"]);
+
+        let shadowed = compile(&ctx, "(function(){ var fetch })(); fetch(x)", "shadow.js").unwrap();
+        assert_eq!(shadowed.globals, ["fetch", "x"]);
+        let redeclared = compile(&ctx, "var fetch; fetch(1)", "redeclare.js").unwrap();
+        assert_eq!(redeclared.globals, ["fetch"]);
+
+        let err = compile(&ctx, "let = ;", "broken.js").unwrap_err();
+        assert!(alloc::format!("{err:?}").contains("SyntaxError"), "{err:?}");
+    }
+}
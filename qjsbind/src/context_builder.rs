@@ -0,0 +1,133 @@
+//! Creating contexts with some of the standard intrinsics left out.
+
+use crate::{c, Context, Runtime};
+
+/// Create a context without some of the standard objects, for sandboxes that should not offer
+/// them at all rather than delete them afterwards:
+///
+/// ```ignore
+/// let ctx = ContextBuilder::new(&runtime).without_eval().without_proxy().build();
+/// ```
+///
+/// With nothing left out, the context is the one of [`Runtime::new_context`].
+pub struct ContextBuilder<'a> {
+    runtime: &'a Runtime,
+    eval: bool,
+    proxy: bool,
+    date: bool,
+}
+
+impl<'a> ContextBuilder<'a> {
+    pub fn new(runtime: &'a Runtime) -> Self {
+        Self {
+            runtime,
+            eval: true,
+            proxy: true,
+            date: true,
+        }
+    }
+
+    /// Leave out the compiler of the context: `eval` and `new Function` throw, and so does
+    /// evaluating source from the host. The context runs bytecode only, compiled in another
+    /// context, see [`Context::compile_to_bytecode`].
+    pub fn without_eval(mut self) -> Self {
+        self.eval = false;
+        self
+    }
+
+    /// Leave out `Proxy`.
+    pub fn without_proxy(mut self) -> Self {
+        self.proxy = false;
+        self
+    }
+
+    /// Leave out `Date`.
+    pub fn without_date(mut self) -> Self {
+        self.date = false;
+        self
+    }
+
+    pub fn build(self) -> Context {
+        if self.eval && self.proxy && self.date {
+            return self.runtime.new_context();
+        }
+        // The intrinsics of `JS_NewContext`, in the same order.
+        let ptr = unsafe {
+            let ctx = c::JS_NewContextRaw(self.runtime.as_ptr());
+            if !ctx.is_null() {
+                c::JS_AddIntrinsicBaseObjects(ctx);
+                if self.date {
+                    c::JS_AddIntrinsicDate(ctx);
+                }
+                if self.eval {
+                    c::JS_AddIntrinsicEval(ctx);
+                }
+                c::JS_AddIntrinsicStringNormalize(ctx);
+                c::JS_AddIntrinsicRegExp(ctx);
+                c::JS_AddIntrinsicJSON(ctx);
+                if self.proxy {
+                    c::JS_AddIntrinsicProxy(ctx);
+                }
+                c::JS_AddIntrinsicMapSet(ctx);
+                c::JS_AddIntrinsicTypedArrays(ctx);
+                c::JS_AddIntrinsicPromise(ctx);
+                c::JS_AddIntrinsicBigInt(ctx);
+                c::JS_AddIntrinsicWeakRef(ctx);
+                c::JS_AddPerformance(ctx);
+            }
+            ctx
+        };
+        self.runtime.init_context(ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use crate::Code;
+
+    use super::*;
+
+    #[test]
+    fn left_out_intrinsics_are_missing() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let nested = ctx.eval(&Code::Source("eval('1')")).unwrap();
+        assert_eq!(nested.decode_u32().unwrap(), 1);
+        let probe = ctx
+            .compile_to_bytecode(
+                "let evaluated;
+                try { evaluated = eval('1'); } catch (e) { evaluated = 'threw'; }
+                [evaluated, typeof Proxy, typeof Date, typeof Map].join()",
+                "probe.js",
+            )
+            .unwrap();
+        let probe = |ctx: &Context| ctx.eval(&Code::Bytecode(&probe)).unwrap().to_string();
+        assert_eq!(probe(&ctx), "1,function,function,function");
+
+        let sandbox = ContextBuilder::new(&runtime)
+            .without_eval()
+            .without_proxy()
+            .without_date()
+            .build();
+        assert!(sandbox.eval(&Code::Source("eval('1')")).is_err());
+        assert_eq!(probe(&sandbox), "threw,undefined,undefined,function");
+        let no_proxy = ContextBuilder::new(&runtime).without_proxy().build();
+        assert_eq!(probe(&no_proxy), "1,undefined,function,function");
+    }
+
+    #[test]
+    fn the_intrinsics_follow_new_context() {
+        let runtime = Runtime::new(&Default::default());
+        let globals = |ctx: &Context| {
+            ctx.eval(&Code::Source(
+                "Object.getOwnPropertyNames(globalThis).filter(name => name !== 'Date').sort().join()",
+            ))
+            .unwrap()
+            .to_string()
+        };
+        let without_date = ContextBuilder::new(&runtime).without_date().build();
+        assert_eq!(globals(&without_date), globals(&runtime.new_context()));
+    }
+}
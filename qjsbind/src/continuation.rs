@@ -0,0 +1,272 @@
+//! Host functions that complete later, for scripts written in the callback style.
+//!
+//! A host function called under [`Context::call_with_continuation`](js::Context) may return
+//! [`Suspend`] instead of a value. The script is unwound and the call returns
+//! [`CallOutcome::Suspended`] with the token, which the host settles once the operation is done,
+//! from its own scheduler:
+//!
+//! ```ignore
+//! #[js::host_call(with_context)]
+//! fn read(ctx: js::Context, _this: js::Value, key: String, callback: js::Value) -> Result<Suspend> {
+//!     Ok(Suspend(Continuation::new(&ctx, callback)?))
+//! }
+//!
+//! match ctx.call_with_continuation(&main, &[])? {
+//!     CallOutcome::Returned(value) => done(value),
+//!     CallOutcome::Suspended(token) => scheduler.push(token),
+//! }
+//! // later
+//! token.resume(value)?;
+//! ```
+
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use log::warn;
+
+use crate::{
+    self as js, error::expect_err, Error, ErrorContext, ErrorValueExt, JsError, Result,
+    ToJsValue, Value,
+};
+
+/// The result of `Context::call_with_continuation`.
+pub enum CallOutcome {
+    /// The function returned the value.
+    Returned(Value),
+    /// A host function called by the script suspended it.
+    Suspended(Continuation),
+}
+
+/// A suspended host operation and the script callback that receives its result.
+///
+/// The callback is called Node.js style: with `(null, value)` by [`resume`](Self::resume), with
+/// `(error)` by [`fail`](Self::fail), and with an error of code `ERR_CANCELLED` when the
+/// continuation is dropped without being settled, unless dropped by a panic. It runs under
+/// `Context::call_with_continuation`, so it may suspend again.
+pub struct Continuation {
+    ctx: js::Context,
+    callback: Option<Value>,
+}
+
+impl Continuation {
+    pub fn new(ctx: &js::Context, callback: Value) -> Result<Self> {
+        if !callback.is_function() {
+            return Err(expect_err("function", &callback));
+        }
+        Ok(Self {
+            ctx: ctx.clone(),
+            callback: Some(callback),
+        })
+    }
+
+    pub fn resume(mut self, value: impl ToJsValue) -> Result<CallOutcome> {
+        let value = value.to_js_value(&self.ctx)?;
+        self.settle(&[Value::null(), value])
+    }
+
+    pub fn fail(mut self, err: Error) -> Result<CallOutcome> {
+        let err = err.to_js_error_value(&self.ctx);
+        self.settle(&[err])
+    }
+
+    fn settle(&mut self, args: &[Value]) -> Result<CallOutcome> {
+        let callback = self.callback.take().expect("continuation already settled");
+        self.ctx.call_with_continuation(&callback, args)
+    }
+}
+
+impl Drop for Continuation {
+    fn drop(&mut self) {
+        if self.callback.is_none() {
+            return;
+        }
+        // Calling into the script while unwinding could panic again and abort.
+        #[cfg(feature = "std")]
+        if std::thread::panicking() {
+            return;
+        }
+        let err = Error::msg(
+            JsError::new("Error", "the host dropped the continuation").with_code("ERR_CANCELLED"),
+        );
+        let err = err.to_js_error_value(&self.ctx);
+        // A continuation the callback suspends on is dropped here as well, and cancelled in turn.
+        if let Err(err) = self.settle(&[err]) {
+            warn!("continuation callback failed on cancellation: {err:?}");
+        }
+    }
+}
+
+/// Returned by a host function to suspend the script that called it.
+///
+/// The script sees an error of code `ERR_SUSPENDED` thrown, which it should let propagate. Outside
+/// of `Context::call_with_continuation` the continuation is cancelled right away.
+pub struct Suspend(pub Continuation);
+
+#[derive(Default)]
+struct SuspendState {
+    /// The depth of nested `call_with_continuation` calls.
+    active: usize,
+    pending: Option<Continuation>,
+}
+
+fn suspend_state(ctx: &js::Context) -> Result<Rc<RefCell<SuspendState>>> {
+    ctx.state()
+        .context("can not suspend in a context without teardown support")
+}
+
+/// Park the continuation for the innermost `call_with_continuation` and unwind the script.
+pub(crate) fn suspend(ctx: &js::Context, continuation: Continuation) -> Result<Value> {
+    let state = suspend_state(ctx)?;
+    let mut state = state.borrow_mut();
+    // The state is released before the continuation is cancelled, which calls into the script.
+    if state.active == 0 {
+        drop(state);
+        drop(continuation);
+        return Err(Error::msg(JsError::new(
+            "Error",
+            "can not suspend outside of call_with_continuation",
+        )));
+    }
+    if state.pending.is_some() {
+        drop(state);
+        drop(continuation);
+        return Err(Error::msg(JsError::new(
+            "Error",
+            "the script is already suspended",
+        )));
+    }
+    state.pending = Some(continuation);
+    Err(Error::msg(
+        JsError::new("Error", "suspended by the host").with_code("ERR_SUSPENDED"),
+    ))
+}
+
+impl js::Context {
+    /// Call `f` with `args`, allowing the host functions it calls to return [`Suspend`].
+    ///
+    /// Once a host function suspended, the outcome is `Suspended` however the script went on,
+    /// even if it caught the `ERR_SUSPENDED` error.
+    pub fn call_with_continuation(&self, f: &Value, args: &[Value]) -> Result<CallOutcome> {
+        let state = suspend_state(self)?;
+        state.borrow_mut().active += 1;
+        let result = f.call(&Value::undefined(), args);
+        let pending = {
+            let mut state = state.borrow_mut();
+            state.active -= 1;
+            state.pending.take()
+        };
+        match (result, pending) {
+            (_, Some(continuation)) => Ok(CallOutcome::Suspended(continuation)),
+            (Ok(value), None) => Ok(CallOutcome::Returned(value)),
+            (Err(err), None) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+    use alloc::string::String;
+
+    #[crate::host_call(with_context)]
+    fn read_later(ctx: js::Context, _this: Value, callback: Value) -> Result<Suspend> {
+        Ok(Suspend(Continuation::new(&ctx, callback)?))
+    }
+
+    fn setup(ctx: &js::Context) -> Value {
+        let global = ctx.get_global_object();
+        global.define_property_fn("readLater", read_later).unwrap();
+        ctx.eval(&Code::Source(
+            r#"
+            globalThis.log = [];
+            (() => {
+                readLater((err, value) => {
+                    if (err) {
+                        log.push(`${err.code}: ${err.message}`);
+                        return;
+                    }
+                    log.push(`got ${value}`);
+                    readLater((err, value) => log.push(err ? err.code : `then ${value}`));
+                });
+                log.push("not reached");
+            })
+            "#,
+        ))
+        .unwrap()
+    }
+
+    fn log(ctx: &js::Context) -> String {
+        ctx.eval(&Code::Source("log.join('; ')"))
+            .unwrap()
+            .decode_string()
+            .unwrap()
+    }
+
+    fn suspended(outcome: Result<CallOutcome>) -> Continuation {
+        match outcome.unwrap() {
+            CallOutcome::Suspended(continuation) => continuation,
+            CallOutcome::Returned(_) => panic!("expected the call to suspend"),
+        }
+    }
+
+    #[test]
+    fn resumes_suspended_calls() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let main = setup(&ctx);
+        let token = suspended(ctx.call_with_continuation(&main, &[]));
+        assert_eq!(log(&ctx), "");
+        let token = suspended(token.resume(1));
+        assert_eq!(log(&ctx), "got 1");
+        let outcome = token.resume(2).unwrap();
+        assert!(matches!(outcome, CallOutcome::Returned(_)));
+        assert_eq!(log(&ctx), "got 1; then 2");
+    }
+
+    #[test]
+    fn fails_and_cancels_continuations() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let main = setup(&ctx);
+        let token = suspended(ctx.call_with_continuation(&main, &[]));
+        let err = Error::msg(JsError::new("Error", "no such key").with_code("ERR_NOT_FOUND"));
+        token.fail(err).unwrap();
+        assert_eq!(log(&ctx), "ERR_NOT_FOUND: no such key");
+
+        ctx.eval(&Code::Source("log = []")).unwrap();
+        let token = suspended(ctx.call_with_continuation(&main, &[]));
+        drop(token);
+        assert_eq!(
+            log(&ctx),
+            "ERR_CANCELLED: the host dropped the continuation"
+        );
+
+        // Outside of `call_with_continuation` the continuation is cancelled at once.
+        ctx.eval(&Code::Source("log = []")).unwrap();
+        assert!(main.call(&Value::undefined(), &[]).is_err());
+        assert_eq!(
+            log(&ctx),
+            "ERR_CANCELLED: the host dropped the continuation"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn keeps_state_host_side_and_skips_the_callback_on_panic() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let main = setup(&ctx);
+        ctx.eval(&Code::Source("delete globalThis._QjsBind"))
+            .unwrap();
+        let token = suspended(ctx.call_with_continuation(&main, &[]));
+        assert_eq!(log(&ctx), "");
+
+        let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _token = token;
+            panic!("host failure");
+        }));
+        assert!(unwound.is_err());
+        assert_eq!(log(&ctx), "");
+    }
+}
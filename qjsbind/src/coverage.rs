@@ -0,0 +1,678 @@
+//! Line coverage of evaluated scripts.
+//!
+//! Scripts evaluated with `EvalOptions::coverage` are instrumented at the source level: a probe
+//! call is inserted at the start of each line that begins a statement. Other evaluations are left
+//! untouched, so coverage costs nothing unless it is used.
+//!
+//! The lines are recorded on the host. The probe is a host function the global `__qjsbind_cov`
+//! is redefined to before each instrumented evaluation, read-only so that scripts can not replace
+//! it, and it only records the instrumented lines of the file it is called from.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use anyhow::Context as _;
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use crate::{self as js, c, Code, EvalOptions, Result, Value};
+
+const PROBE_FN: &str = "__qjsbind_cov";
+
+#[derive(Default)]
+struct CoverageState {
+    files: RefCell<Vec<FileCoverage>>,
+}
+
+struct FileCoverage {
+    name: String,
+    /// The instrumented lines, the only ones the probe records.
+    probed: BTreeSet<u32>,
+    executed: BTreeSet<u32>,
+}
+
+#[crate::host_call(with_context)]
+fn record_line(ctx: js::Context, _this: Value, file: u32, line: u32) {
+    let Some(state) = ctx.user_data::<CoverageState>() else {
+        return;
+    };
+    let mut files = state.files.borrow_mut();
+    let Some(file) = files.get_mut(file as usize) else {
+        return;
+    };
+    if file.probed.contains(&line) && caller_filename(&ctx).as_deref() == Some(&file.name) {
+        file.executed.insert(line);
+    }
+}
+
+/// The filename of the script calling the running host function.
+fn caller_filename(ctx: &js::Context) -> Option<String> {
+    // Level 0 is the host function itself.
+    let atom = unsafe { c::JS_GetScriptOrModuleName(ctx.as_ptr(), 1) };
+    if atom == c::JS_ATOM_NULL {
+        return None;
+    }
+    let name = Value::new_moved(ctx, unsafe { c::JS_AtomToValue(ctx.as_ptr(), atom) });
+    unsafe { c::JS_FreeAtom(ctx.as_ptr(), atom) };
+    Some(name.to_string())
+}
+
+impl js::Context {
+    /// Evaluate `source` with line coverage recorded under `filename`, see
+    /// [`EvalOptions::coverage`].
+    ///
+    /// Functions defined by the script keep recording when called later.
+    pub fn eval_with_coverage(&self, filename: &str, source: &str) -> Result<Value, String> {
+        let options = EvalOptions {
+            filename: Some(filename.into()),
+            coverage: true,
+            ..Default::default()
+        };
+        self.eval_with_options(&Code::Source(source), &options)
+    }
+
+    /// Take the lines executed so far, by filename, and reset the counters.
+    pub fn take_coverage(&self) -> BTreeMap<String, Vec<u32>> {
+        let Some(state) = self.user_data::<CoverageState>() else {
+            return BTreeMap::new();
+        };
+        let mut files = state.files.borrow_mut();
+        files
+            .iter_mut()
+            .map(|file| {
+                let lines = core::mem::take(&mut file.executed).into_iter().collect();
+                (file.name.clone(), lines)
+            })
+            .collect()
+    }
+
+    /// Forget the files recorded so far, with their lines. Probes left in functions of those
+    /// files record nothing, as their index is gone or names another file.
+    pub(crate) fn clear_coverage(&self) {
+        if let Some(state) = self.user_data::<CoverageState>() {
+            state.files.borrow_mut().clear();
+        }
+    }
+}
+
+/// Instrument `source`, whose first line is line `first_line` of `filename`, and install the
+/// probe.
+pub(crate) fn instrument_source(
+    ctx: &js::Context,
+    filename: &str,
+    first_line: u32,
+    source: &str,
+) -> Result<String> {
+    let state = ctx
+        .state::<CoverageState>()
+        .context("no coverage for a context without teardown support")?;
+    install_probe(ctx)?;
+    let (index, probes) = {
+        let mut files = state.files.borrow_mut();
+        let index = match files.iter().position(|file| file.name == filename) {
+            Some(index) => index,
+            None => {
+                files.push(FileCoverage {
+                    name: filename.into(),
+                    probed: BTreeSet::new(),
+                    executed: BTreeSet::new(),
+                });
+                files.len() - 1
+            }
+        };
+        let probes = find_probes(source, first_line);
+        files[index]
+            .probed
+            .extend(probes.iter().map(|(_, line)| *line));
+        (index as u32, probes)
+    };
+    let mut output = String::with_capacity(source.len() + probes.len() * 24);
+    let mut last = 0;
+    for (pos, line) in probes {
+        output.push_str(&source[last..pos]);
+        _ = write!(output, "{PROBE_FN}({index},{line});");
+        last = pos;
+    }
+    output.push_str(&source[last..]);
+    Ok(output)
+}
+
+/// Define the global probe as the host's, whatever the scripts did to it.
+fn install_probe(ctx: &js::Context) -> Result<()> {
+    let probe = ctx.host_object("coverageProbe", || {
+        Ok(ctx.new_function(PROBE_FN, record_line, 2, c::JS_CFUNC_generic))
+    })?;
+    let global = ctx.get_global_object();
+    let name =
+        unsafe { c::JS_NewAtomLen(ctx.as_ptr(), PROBE_FN.as_ptr() as _, PROBE_FN.len() as _) };
+    let r = unsafe {
+        c::JS_DefinePropertyValue(
+            ctx.as_ptr(),
+            *global.raw_value(),
+            name,
+            probe.leak(),
+            (c::JS_PROP_CONFIGURABLE | c::JS_PROP_THROW) as _,
+        )
+    };
+    unsafe { c::JS_FreeAtom(ctx.as_ptr(), name) };
+    if r < 0 {
+        return Err(ctx.get_exception_error());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    Paren { control: bool, switch: bool },
+    Square,
+    Block,
+    Switch,
+    Object,
+    Template,
+    TemplateExpr,
+}
+
+/// The kind of the last significant token, which decides whether a new line may start a
+/// statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prev {
+    Start,
+    Semicolon,
+    BlockOpen,
+    BlockClose,
+    CaseColon,
+    CloseParen,
+    ControlParen { switch: bool },
+    Value,
+    Keyword(&'static str),
+    Arrow,
+    Other,
+}
+
+const CONTROL_KEYWORDS: &[&str] = &["if", "for", "while", "with", "switch", "catch"];
+const KEYWORDS: &[&str] = &[
+    "if",
+    "for",
+    "while",
+    "with",
+    "switch",
+    "catch",
+    "return",
+    "throw",
+    "break",
+    "continue",
+    "typeof",
+    "new",
+    "delete",
+    "void",
+    "else",
+    "do",
+    "case",
+    "default",
+    "in",
+    "instanceof",
+    "extends",
+    "try",
+    "finally",
+    "let",
+    "const",
+    "var",
+    "function",
+    "class",
+    "export",
+    "import",
+    "yield",
+    "await",
+];
+/// Words that continue the previous statement when they start a line.
+const CONTINUATIONS: &[&str] = &[
+    "else",
+    "catch",
+    "finally",
+    "case",
+    "default",
+    "in",
+    "instanceof",
+    "of",
+    "extends",
+];
+
+struct Instrumenter<'a> {
+    src: &'a [u8],
+    pos: usize,
+    stack: Vec<Frame>,
+    prev: Prev,
+    pending_case: bool,
+    ternaries: usize,
+    /// The line of `counted`, the position up to which line breaks were counted.
+    line: u32,
+    counted: usize,
+    probes: Vec<(usize, u32)>,
+}
+
+/// The positions and lines of the probes to insert, at the start of every line that begins a
+/// statement, numbering the lines of `source` from `first_line`.
+///
+/// This is a best-effort tokenizer rather than a parser: lines whose first token could continue
+/// the previous expression are not probed, so the inserted calls never change the meaning of the
+/// script. Line numbers are preserved since probes are inserted without line breaks.
+fn find_probes(source: &str, first_line: u32) -> Vec<(usize, u32)> {
+    let mut instrumenter = Instrumenter {
+        src: source.as_bytes(),
+        pos: 0,
+        stack: Vec::new(),
+        prev: Prev::Start,
+        pending_case: false,
+        ternaries: 0,
+        line: first_line,
+        counted: 0,
+        probes: Vec::new(),
+    };
+    instrumenter.run();
+    instrumenter.probes
+}
+
+fn is_ident_start(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_' || c == b'$' || c >= 0x80
+}
+
+fn is_ident_char(c: u8) -> bool {
+    is_ident_start(c) || c.is_ascii_digit()
+}
+
+impl Instrumenter<'_> {
+    fn peek(&self, offset: usize) -> u8 {
+        self.src.get(self.pos + offset).copied().unwrap_or(0)
+    }
+
+    /// The line of `pos`, which must not be before the positions asked for earlier.
+    fn line_at(&mut self, pos: usize) -> u32 {
+        let breaks = self.src[self.counted..pos]
+            .iter()
+            .filter(|c| **c == b'\n')
+            .count();
+        self.line += breaks as u32;
+        self.counted = pos;
+        self.line
+    }
+
+    fn run(&mut self) {
+        let mut line_start = true;
+        while self.pos < self.src.len() {
+            if self.stack.last() == Some(&Frame::Template) {
+                self.template_text();
+                continue;
+            }
+            let c = self.peek(0);
+            match c {
+                b'\n' => {
+                    line_start = true;
+                    self.pos += 1;
+                    continue;
+                }
+                b' ' | b'\t' | b'\r' => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let comment = c == b'/' && matches!(self.peek(1), b'/' | b'*');
+            if line_start && !comment && self.can_probe() {
+                let line = self.line_at(self.pos);
+                self.probes.push((self.pos, line));
+            }
+            line_start = false;
+            self.token();
+        }
+    }
+
+    fn can_probe(&self) -> bool {
+        match self.stack.last() {
+            None | Some(Frame::Block) | Some(Frame::Switch) => {}
+            _ => return false,
+        }
+        let c = self.peek(0);
+        let word = self.word_at(self.pos);
+        if let Some(word) = word {
+            if CONTINUATIONS.contains(&word) {
+                return false;
+            }
+            if word == "while" && self.prev == Prev::BlockClose {
+                // Possibly the tail of a do-while loop
+                return false;
+            }
+        }
+        match self.prev {
+            Prev::Start | Prev::Semicolon | Prev::BlockOpen | Prev::CaseColon => {
+                // Quotes are skipped so that directives like "use strict" stay first
+                !b"})],.?:=*%&|^<>'\"".contains(&c)
+            }
+            Prev::Value | Prev::BlockClose | Prev::CloseParen => word.is_some(),
+            _ => false,
+        }
+    }
+
+    fn word_at(&self, pos: usize) -> Option<&str> {
+        let first = *self.src.get(pos)?;
+        if !is_ident_start(first) {
+            return None;
+        }
+        let len = self.src[pos..]
+            .iter()
+            .take_while(|c| is_ident_char(**c))
+            .count();
+        core::str::from_utf8(&self.src[pos..pos + len]).ok()
+    }
+
+    fn token(&mut self) {
+        let c = self.peek(0);
+        if let Some(word) = self.word_at(self.pos) {
+            let keyword = KEYWORDS.iter().find(|k| **k == word).copied();
+            let is_case = matches!(word, "case" | "default");
+            self.pos += word.len();
+            if is_case && self.stack.last() == Some(&Frame::Switch) {
+                self.pending_case = true;
+            }
+            self.prev = match keyword {
+                Some(keyword) => Prev::Keyword(keyword),
+                None => Prev::Value,
+            };
+            return;
+        }
+        if c.is_ascii_digit() || (c == b'.' && self.peek(1).is_ascii_digit()) {
+            self.pos += 1;
+            while is_ident_char(self.peek(0)) || self.peek(0) == b'.' {
+                self.pos += 1;
+            }
+            self.prev = Prev::Value;
+            return;
+        }
+        match c {
+            b'\'' | b'"' => self.string(c),
+            b'`' => {
+                self.pos += 1;
+                self.stack.push(Frame::Template);
+            }
+            b'/' if self.peek(1) == b'/' => {
+                while self.pos < self.src.len() && self.peek(0) != b'\n' {
+                    self.pos += 1;
+                }
+            }
+            b'/' if self.peek(1) == b'*' => {
+                self.pos += 2;
+                while self.pos < self.src.len() && !(self.peek(0) == b'*' && self.peek(1) == b'/') {
+                    self.pos += 1;
+                }
+                self.pos = (self.pos + 2).min(self.src.len());
+            }
+            b'/' if !matches!(self.prev, Prev::Value | Prev::CloseParen) => self.regex(),
+            b'(' => {
+                self.pos += 1;
+                let (control, switch) = match self.prev {
+                    Prev::Keyword(kw) => (CONTROL_KEYWORDS.contains(&kw), kw == "switch"),
+                    _ => (false, false),
+                };
+                self.stack.push(Frame::Paren { control, switch });
+                self.prev = Prev::Other;
+            }
+            b')' => {
+                self.pos += 1;
+                self.prev = match self.stack.pop() {
+                    Some(Frame::Paren {
+                        control: true,
+                        switch,
+                    }) => Prev::ControlParen { switch },
+                    _ => Prev::CloseParen,
+                };
+            }
+            b'[' => {
+                self.pos += 1;
+                self.stack.push(Frame::Square);
+                self.prev = Prev::Other;
+            }
+            b']' => {
+                self.pos += 1;
+                self.stack.pop();
+                self.prev = Prev::Value;
+            }
+            b'{' => {
+                self.pos += 1;
+                let frame = match self.prev {
+                    Prev::ControlParen { switch: true } => Frame::Switch,
+                    Prev::Start
+                    | Prev::Semicolon
+                    | Prev::BlockOpen
+                    | Prev::BlockClose
+                    | Prev::CaseColon
+                    | Prev::CloseParen
+                    | Prev::ControlParen { .. }
+                    | Prev::Arrow
+                    | Prev::Keyword("else" | "do" | "try" | "finally" | "catch") => Frame::Block,
+                    _ => Frame::Object,
+                };
+                self.stack.push(frame);
+                self.prev = match frame {
+                    Frame::Object => Prev::Other,
+                    _ => Prev::BlockOpen,
+                };
+            }
+            b'}' => {
+                self.pos += 1;
+                self.prev = match self.stack.pop() {
+                    Some(Frame::Block | Frame::Switch) => Prev::BlockClose,
+                    _ => Prev::Value,
+                };
+            }
+            b';' => {
+                self.pos += 1;
+                self.prev = Prev::Semicolon;
+            }
+            b'?' => {
+                if (self.peek(1) == b'.' && !self.peek(2).is_ascii_digit()) || self.peek(1) == b'?'
+                {
+                    self.pos += 2;
+                } else {
+                    self.pos += 1;
+                    self.ternaries += 1;
+                }
+                self.prev = Prev::Other;
+            }
+            b':' => {
+                self.pos += 1;
+                if self.ternaries > 0 {
+                    self.ternaries -= 1;
+                    self.prev = Prev::Other;
+                } else if self.pending_case && self.stack.last() == Some(&Frame::Switch) {
+                    self.pending_case = false;
+                    self.prev = Prev::CaseColon;
+                } else {
+                    self.prev = Prev::Other;
+                }
+            }
+            b'=' if self.peek(1) == b'>' => {
+                self.pos += 2;
+                self.prev = Prev::Arrow;
+            }
+            b'+' | b'-' if self.peek(1) == c => {
+                self.pos += 2;
+                self.prev = Prev::Value;
+            }
+            _ => {
+                self.pos += 1;
+                self.prev = Prev::Other;
+            }
+        }
+    }
+
+    fn string(&mut self, quote: u8) {
+        self.pos += 1;
+        while self.pos < self.src.len() {
+            match self.peek(0) {
+                b'\\' => self.pos += 2,
+                b'\n' => break,
+                c => {
+                    self.pos += 1;
+                    if c == quote {
+                        break;
+                    }
+                }
+            }
+        }
+        self.pos = self.pos.min(self.src.len());
+        self.prev = Prev::Value;
+    }
+
+    fn regex(&mut self) {
+        self.pos += 1;
+        let mut in_class = false;
+        while self.pos < self.src.len() {
+            match self.peek(0) {
+                b'\\' => self.pos += 2,
+                b'\n' => break,
+                b'[' => {
+                    in_class = true;
+                    self.pos += 1;
+                }
+                b']' => {
+                    in_class = false;
+                    self.pos += 1;
+                }
+                b'/' if !in_class => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        while is_ident_char(self.peek(0)) {
+            self.pos += 1;
+        }
+        self.pos = self.pos.min(self.src.len());
+        self.prev = Prev::Value;
+    }
+
+    fn template_text(&mut self) {
+        while self.pos < self.src.len() {
+            match self.peek(0) {
+                b'\\' => self.pos += 2,
+                b'`' => {
+                    self.pos += 1;
+                    self.stack.pop();
+                    self.prev = Prev::Value;
+                    return;
+                }
+                b'$' if self.peek(1) == b'{' => {
+                    self.pos += 2;
+                    self.stack.push(Frame::TemplateExpr);
+                    self.prev = Prev::Other;
+                    return;
+                }
+                _ => self.pos += 1,
+            }
+        }
+        self.pos = self.pos.min(self.src.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probed_lines(source: &str) -> Vec<u32> {
+        find_probes(source, 1)
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect()
+    }
+
+    #[test]
+    fn probes_statement_lines() {
+        let source = r#""use strict";
+let a = {
+    b: 1,
+    c: [1,
+        2],
+};
+if (a.b > 0) {
+    a.c.push(`x ${a.b}
+    y`);
+} else {
+    a.c = a.b
+        ? 1
+        : 2;
+}
+for (let i = 0;
+     i < 3; i++)
+    a.b++
+switch (a.b) {
+    case 1:
+        a.b = 2;
+        break;
+    default:
+        a.b = 3
+}
+const f = x =>
+    x * 2
+// comment
+foo()
+"#;
+        assert_eq!(
+            probed_lines(source),
+            [2, 7, 8, 11, 15, 18, 20, 21, 23, 25, 28]
+        );
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let instrumented = instrument_source(&ctx, "test.js", 1, source).unwrap();
+        assert!(instrumented.starts_with("\"use strict\";"));
+        assert_eq!(instrumented.lines().count(), source.lines().count());
+    }
+
+    #[test]
+    fn records_taken_branch() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let source = "let x = 1;\nif (x > 0) {\n  x = 2;\n} else {\n  x = 3;\n}\n";
+        ctx.eval_with_coverage("policy.js", source).unwrap();
+        let coverage = ctx.take_coverage();
+        assert_eq!(coverage["policy.js"], [1, 2, 3]);
+        assert!(ctx.take_coverage()["policy.js"].is_empty());
+    }
+
+    #[test]
+    fn records_with_eval_options() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let options = EvalOptions {
+            coverage: true,
+            ..EvalOptions::at("policy.js", 10)
+        };
+        let source = "let x = 1;\nif (x > 0) {\n  x = 2;\n}\n";
+        ctx.eval_with_options(&Code::Source(source), &options)
+            .unwrap();
+        assert_eq!(ctx.take_coverage()["policy.js"], [10, 11, 12]);
+
+        ctx.eval(&Code::Source("let y = 1;\ny = 2;\n")).unwrap();
+        assert!(!ctx.take_coverage().contains_key("<eval>"));
+    }
+
+    #[test]
+    fn scripts_can_not_forge_coverage() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src));
+        eval("globalThis.__qjsbind_cov = () => {};").unwrap();
+        let source = "var x = 1;\nif (x > 1) {\n  x = 2;\n}\nfunction later() {\n  x = 3;\n}\n";
+        ctx.eval_with_coverage("policy.js", source).unwrap();
+        assert_eq!(ctx.take_coverage()["policy.js"], [1, 2, 5]);
+
+        eval(
+            r#"
+            __qjsbind_cov = () => {};
+            __qjsbind_cov(0, 3);
+            later();
+            "#,
+        )
+        .unwrap();
+        assert_eq!(ctx.take_coverage()["policy.js"], [6]);
+    }
+}
@@ -0,0 +1,308 @@
+//! One call for a host event loop to run what is due in a runtime: host futures, pending jobs
+//! and timers.
+//!
+//! ```ignore
+//! let driver = Driver::new(Runtime::new(&Default::default()));
+//! let ctx = driver.runtime().new_context();
+//! driver.install(&ctx)?;
+//! ctx.eval(&Code::Source(script))?;
+//! loop {
+//!     let outcome = driver.poll(now())?;
+//!     if outcome.idle {
+//!         break;
+//!     }
+//!     wait_until(outcome.next_deadline);
+//! }
+//! ```
+
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use core::cell::{Cell, RefCell};
+use core::task::{Context as TaskContext, Waker};
+
+use anyhow::{bail, Context as _};
+
+use crate::{Context, Millis, RawArgs, Result, Runtime, Value};
+
+/// The timers of a runtime driven by a `Driver`, kept in its user data.
+#[derive(Default)]
+struct Timers {
+    /// The time of the current poll, or of the last one.
+    now: Cell<u64>,
+    /// The id of the last timer set, ids growing in the order the timers are set.
+    next_id: Cell<u32>,
+    queue: RefCell<BTreeMap<(u64, u32), Timer>>,
+}
+
+struct Timer {
+    callback: Value,
+    args: RawArgs,
+}
+
+impl Timers {
+    /// Take the first timer due at `now` among the ones set up to the timer `last_id`.
+    fn take_due(&self, now: u64, last_id: u32) -> Option<(u32, Timer)> {
+        let mut queue = self.queue.borrow_mut();
+        let key = *queue
+            .keys()
+            .take_while(|(deadline, _)| *deadline <= now)
+            .find(|(_, id)| *id <= last_id)?;
+        let timer = queue.remove(&key)?;
+        Some((key.1, timer))
+    }
+
+    fn next_deadline(&self) -> Option<u64> {
+        self.queue
+            .borrow()
+            .keys()
+            .next()
+            .map(|(deadline, _)| *deadline)
+    }
+}
+
+/// What [`Driver::poll`] did, and when it wants to be polled again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PollOutcome {
+    /// The pending jobs run, like promise reactions.
+    pub ran_jobs: usize,
+    /// The timers that fired.
+    pub fired_timers: usize,
+    /// The host futures that finished and settled their promises.
+    pub completed_futures: usize,
+    /// When the earliest timer is due. It may be the time of the poll, for timers set by the
+    /// timers that fired.
+    pub next_deadline: Option<Millis>,
+    /// Whether nothing is left to do: no pending jobs, no timers and no running host futures.
+    pub idle: bool,
+}
+
+/// The sources of work of a runtime polled in a fixed order, so that a host event loop, be it
+/// tokio, epoll or the tick of a bare-metal host, only calls [`poll`](Self::poll) with the
+/// current time.
+///
+/// Each poll runs, in order:
+///
+/// 1. the host futures of [`Context::spawn_host_future`], settling the promises of the finished
+///    ones, so that a future completing at the same instant as a timer is seen first;
+/// 2. the pending jobs, so that microtasks always run before timers;
+/// 3. the timers due, in the order of their deadlines then of `setTimeout` calls, with the jobs
+///    they queue run after each of them.
+///
+/// The timers are the `setTimeout` and `clearTimeout` of the contexts given to
+/// [`install`](Self::install), on the clock of the times given to `poll`. A timer set by a
+/// timer fires at the next poll, even with no delay. A pending timer keeps its context alive
+/// until it fires, is cleared, or the driver is dropped.
+pub struct Driver {
+    timers: Rc<Timers>,
+    waker: Waker,
+    runtime: Runtime,
+}
+
+impl Driver {
+    pub fn new(runtime: Runtime) -> Self {
+        runtime.set_user_data(Timers::default());
+        let timers = runtime
+            .user_data::<Timers>()
+            .expect("timers were just attached");
+        Self {
+            timers,
+            waker: Waker::noop().clone(),
+            runtime,
+        }
+    }
+
+    /// Poll the host futures with `waker`, for the host to learn when they can make progress.
+    pub fn with_waker(mut self, waker: Waker) -> Self {
+        self.waker = waker;
+        self
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    /// Define `setTimeout` and `clearTimeout` on the global object of `ctx`, a context of the
+    /// runtime of the driver.
+    pub fn install(&self, ctx: &Context) -> Result<()> {
+        if ctx.runtime_user_data::<Timers>().is_none() {
+            bail!("the context is not of the runtime of the driver");
+        }
+        let global = ctx.get_global_object();
+        global.define_property_fn("setTimeout", set_timeout)?;
+        global.define_property_fn("clearTimeout", clear_timeout)?;
+        Ok(())
+    }
+
+    /// Run what is due at `now`, in milliseconds on the clock of the host, in the order given
+    /// in the [`Driver`] docs. Times before the one of the previous poll count as that time.
+    ///
+    /// A job or a timer that throws stops the poll with its error, the rest waits for the next.
+    pub fn poll(&self, now: Millis) -> Result<PollOutcome> {
+        let now = now.0.max(self.timers.now.get());
+        self.timers.now.set(now);
+        let mut outcome = PollOutcome::default();
+
+        let mut cx = TaskContext::from_waker(&self.waker);
+        outcome.completed_futures = self.runtime.poll_host_futures(&mut cx);
+        outcome.ran_jobs += self.runtime.execute_pending_jobs()?;
+
+        let last_id = self.timers.next_id.get();
+        while let Some((id, timer)) = self.timers.take_due(now, last_id) {
+            timer
+                .callback
+                .call(&Value::undefined(), &*timer.args)
+                .with_context(|| format!("timer {id} failed"))?;
+            outcome.fired_timers += 1;
+            outcome.ran_jobs += self.runtime.execute_pending_jobs()?;
+        }
+
+        let next_deadline = self.timers.next_deadline();
+        outcome.next_deadline = next_deadline.map(Millis);
+        outcome.idle = next_deadline.is_none()
+            && !self.runtime.has_pending_jobs()
+            && self.runtime.pending_host_futures() == 0;
+        Ok(outcome)
+    }
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        // The callbacks are values of their contexts, released before the runtime is freed.
+        let queue = core::mem::take(&mut *self.timers.queue.borrow_mut());
+        drop(queue);
+    }
+}
+
+fn timers(ctx: &Context) -> Result<Rc<Timers>> {
+    ctx.runtime_user_data::<Timers>()
+        .context("no driver for the runtime of the context")
+}
+
+#[crate::host_call(with_context)]
+fn set_timeout(
+    ctx: Context,
+    _this: Value,
+    callback: Value,
+    delay: Option<f64>,
+    args: RawArgs,
+) -> Result<u32> {
+    if !callback.is_function() {
+        bail!("setTimeout expects a function");
+    }
+    let timers = timers(&ctx)?;
+    // Like browsers, a missing, negative or `NaN` delay is no delay.
+    let delay = delay.filter(|delay| *delay > 0.0).unwrap_or(0.0) as u64;
+    let deadline = timers.now.get().saturating_add(delay);
+    let Some(id) = timers.next_id.get().checked_add(1) else {
+        bail!("out of timer ids");
+    };
+    timers.next_id.set(id);
+    timers
+        .queue
+        .borrow_mut()
+        .insert((deadline, id), Timer { callback, args });
+    Ok(id)
+}
+
+#[crate::host_call(with_context)]
+fn clear_timeout(ctx: Context, _this: Value, id: Option<u32>) -> Result<()> {
+    let Some(id) = id else {
+        return Ok(());
+    };
+    let timers = timers(&ctx)?;
+    let key = timers
+        .queue
+        .borrow()
+        .keys()
+        .find(|key| key.1 == id)
+        .copied();
+    if let Some(key) = key {
+        // Dropped once the queue is no longer borrowed, as releasing values may run finalizers.
+        let removed = timers.queue.borrow_mut().remove(&key);
+        drop(removed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::poll_fn;
+    use core::task::Poll;
+
+    use super::*;
+    use crate::Code;
+
+    std::thread_local! {
+        static FETCHED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    #[crate::host_call(with_context)]
+    fn fetch(ctx: Context, _this: Value) -> Result<Value> {
+        ctx.spawn_host_future(poll_fn(|_| {
+            if FETCHED.with(Cell::get) {
+                Poll::Ready(Ok(42))
+            } else {
+                Poll::Pending
+            }
+        }))
+    }
+
+    #[test]
+    fn sources_run_in_order_on_a_fake_clock() {
+        let driver = Driver::new(Runtime::new(&Default::default()));
+        let ctx = driver.runtime().new_context();
+        driver.install(&ctx).unwrap();
+        ctx.get_global_object()
+            .define_property_fn("fetch", fetch)
+            .unwrap();
+        ctx.eval(&Code::Source(
+            r#"
+            globalThis.log = [];
+            setTimeout(() => log.push("timeout 10"), 10);
+            Promise.resolve()
+                .then(() => log.push("micro 1"))
+                .then(() => log.push("micro 2"));
+            fetch().then((n) => {
+                log.push(`fetched ${n}`);
+                setTimeout(() => log.push("timeout after fetch"));
+            });
+            setTimeout(() => {
+                log.push("timeout 0");
+                Promise.resolve().then(() => log.push("micro in timeout"));
+            }, 0);
+            clearTimeout(setTimeout(() => log.push("cleared"), 5));
+            "#,
+        ))
+        .unwrap();
+        let log = || {
+            let log = ctx.eval(&Code::Source("log.join(', ')")).unwrap();
+            log.decode_string().unwrap()
+        };
+
+        let outcome = driver.poll(Millis(0)).unwrap();
+        assert_eq!(log(), "micro 1, micro 2, timeout 0, micro in timeout");
+        assert_eq!(outcome.fired_timers, 1);
+        assert_eq!(outcome.next_deadline, Some(Millis(10)));
+        assert!(!outcome.idle);
+
+        // Nothing is due before the timer, the fetch is still running.
+        let outcome = driver.poll(Millis(7)).unwrap();
+        assert_eq!((outcome.ran_jobs, outcome.fired_timers), (0, 0));
+
+        // The fetch completes at the same instant the timer is due, and is seen first.
+        FETCHED.with(|fetched| fetched.set(true));
+        let outcome = driver.poll(Millis(10)).unwrap();
+        assert_eq!(outcome.completed_futures, 1);
+        assert_eq!(outcome.fired_timers, 1);
+        assert_eq!(outcome.next_deadline, Some(Millis(10)));
+        assert_eq!(
+            log(),
+            "micro 1, micro 2, timeout 0, micro in timeout, fetched 42, timeout 10"
+        );
+
+        let outcome = driver.poll(Millis(10)).unwrap();
+        assert!(outcome.idle);
+        assert!(log().ends_with("timeout 10, timeout after fetch"));
+        assert!(!log().contains("cleared"));
+    }
+}
@@ -1,8 +1,21 @@
+use core::any::{Any, TypeId};
+use core::cell::{Cell, RefCell, RefMut};
 use core::ptr::NonNull;
+use std::sync::Once;
 use std::time::Instant;
 
-use crate::{c, Code, Result, ToJsValue, Value};
-use alloc::string::{String, ToString};
+use crate::host_future::HostFutures;
+use crate::memory::AllocState;
+use crate::module_loader::ModuleHooks;
+use crate::small_str::SmallStr;
+use crate::{c, Code, EvalOptions, GcEvent, JsArrayBuffer, Millis, Result, ToJsValue, Value};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
 use anyhow::{anyhow, bail, Context as _};
 use qjs_sys::inline_fns::JSCFunction;
 use tokio::sync::broadcast;
@@ -34,11 +47,93 @@ pub struct Context {
     pub(crate) ptr: NonNull<c::JSContext>,
 }
 
+/// The Rust side of a context created by `Runtime::new_context`, held by its opaque pointer.
+struct ContextData {
+    /// The live `Context` handles, including the ones held by values.
+    handles: Cell<usize>,
+    on_destroy: RefCell<Vec<Box<dyn FnOnce(&Context)>>>,
+    user_data: RefCell<BTreeMap<TypeId, Rc<dyn Any>>>,
+    /// The object holding the JS values of [`Context::host_object`], `JS_UNDEFINED` until it is
+    /// first used.
+    host_values: Cell<c::JSValue>,
+    /// Whether an access auditor is installed, checked on every host call.
+    audit_enabled: Cell<bool>,
+    /// Whether a receipt is being recorded, checked on every host call.
+    receipts_enabled: Cell<bool>,
+    /// The convention of `Context::set_default_rename`, checked on every derived conversion.
+    default_rename: Cell<crate::Convention>,
+    /// The conversion limits and the conversion in progress, checked on every derived
+    /// conversion.
+    limits: Rc<crate::limits::LimitsState>,
+    /// The address of the `Object.prototype` of the context, listed in [`ObjectPrototypes`].
+    object_prototype: Cell<usize>,
+}
+
+/// The addresses of the `Object.prototype` of the contexts of a runtime, for recognizing it by
+/// identity whichever context an object comes from. An address is listed until the data of its
+/// context is dropped, before the prototype can be freed.
+#[derive(Default)]
+pub(crate) struct ObjectPrototypes(pub(crate) RefCell<Vec<usize>>);
+
 impl Context {
     pub fn clone_from_ptr(ptr: *mut c::JSContext) -> Option<Self> {
         let ptr = NonNull::new(ptr)?;
         unsafe { c::JS_DupContext(ptr.as_ptr()) };
-        Some(Self { ptr })
+        let ctx = Self { ptr };
+        ctx.add_handle();
+        Some(ctx)
+    }
+
+    fn data(&self) -> Option<&ContextData> {
+        unsafe { (c::JS_GetContextOpaque(self.as_ptr()) as *const ContextData).as_ref() }
+    }
+
+    fn add_handle(&self) {
+        if let Some(data) = self.data() {
+            data.handles.set(data.handles.get() + 1);
+        }
+    }
+
+    /// Call `callback` when the host lets go of the context, to release the host resources an
+    /// extension keeps for it, like timer queues or open streams, or to flush buffered state.
+    ///
+    /// That is when the last `Context` handle is dropped, counting the handles held by values,
+    /// and not when QuickJS frees the context: objects of other contexts of the runtime, like
+    /// functions passed between them, can keep it alive past that point. Callbacks run once, in
+    /// the reverse order of their registration, before the last handle releases the context. A
+    /// panicking callback is logged and does not keep the others from running.
+    ///
+    /// The context may already be on its way out: the handle can be the last one held by a value
+    /// that the GC is finalizing. Callbacks should only release Rust state. They must not
+    /// evaluate code or call functions, and should not touch values beyond dropping the ones
+    /// they hold.
+    ///
+    /// Only contexts created by `Runtime::new_context` have callbacks. For other contexts the
+    /// callback is dropped without being called.
+    pub fn on_destroy(&self, callback: impl FnOnce(&Context) + 'static) {
+        match self.data() {
+            Some(data) => data.on_destroy.borrow_mut().push(Box::new(callback)),
+            None => log::warn!("on_destroy ignored for a context without teardown support"),
+        }
+    }
+
+    fn run_destroy_callbacks(&self, data: &ContextData) {
+        // Keep the handle of the callbacks counted, so that clones made by them do not start
+        // another teardown when dropped.
+        data.handles.set(1);
+        loop {
+            let Some(callback) = data.on_destroy.borrow_mut().pop() else {
+                break;
+            };
+            let result = std::panic::catch_unwind(core::panic::AssertUnwindSafe(|| callback(self)));
+            if result.is_err() {
+                log::error!("an on_destroy callback panicked");
+            }
+        }
+        // Freed while the handle is counted, as it may run finalizers that clone the context.
+        let host_values = data.host_values.replace(c::JS_UNDEFINED);
+        unsafe { c::JS_FreeValue(self.as_ptr(), host_values) };
+        data.handles.set(data.handles.get() - 1);
     }
 
     pub fn as_ptr(&self) -> *mut c::JSContext {
@@ -53,18 +148,125 @@ impl Context {
         Value::new_object(self, name)
     }
 
+    pub fn new_object_with_proto(&self, proto: &Value) -> Result<Value> {
+        Value::new_object_with_proto(self, proto)
+    }
+
     pub fn new_array(&self) -> Value {
         Value::new_array(self)
     }
 
+    /// The intrinsic `Object.prototype`, whatever the script did to the global `Object`.
+    pub fn object_prototype(&self) -> Result<Value> {
+        self.new_object("").get_prototype()
+    }
+
+    /// The intrinsic `Array.prototype`.
+    pub fn array_prototype(&self) -> Result<Value> {
+        self.new_array().get_prototype()
+    }
+
     pub fn new_string(&self, s: &str) -> Value {
         Value::from_str(self, s)
     }
 
+    pub fn new_array_buffer_owned(&self, bytes: Vec<u8>) -> Result<JsArrayBuffer> {
+        JsArrayBuffer::from_vec(self, bytes)
+    }
+
     pub fn eval(&self, code: &Code) -> Result<Value, String> {
         crate::eval(self, code)
     }
 
+    pub fn eval_with_options(&self, code: &Code, options: &EvalOptions) -> Result<Value, String> {
+        crate::eval::eval_with_options(self, code, options)
+    }
+
+    /// Evaluate `code` and run the pending jobs it queued, see `EvalOptions::auto_drain_jobs`.
+    pub fn eval_and_drain(&self, code: &Code) -> Result<Value, String> {
+        self.eval_with_options(code, &EvalOptions::drain())
+    }
+
+    /// Evaluate `code`, interrupting it once it ran for `timeout`. The error then tells how long
+    /// it ran and has an [`Interrupted`](crate::Interrupted) context.
+    ///
+    /// The deadline is checked by an interrupt handler installed for the call, which also calls
+    /// the handler of [`Runtime::set_interrupt_handler`] and puts it back afterwards.
+    #[cfg(feature = "std")]
+    pub fn eval_with_timeout(&self, code: &Code, timeout: core::time::Duration) -> Result<Value> {
+        let start = Instant::now();
+        let timeout_ms = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+        self.eval_until(code, timeout_ms, move || start.elapsed().as_millis() as u64)
+    }
+
+    /// [`eval_with_timeout`](Self::eval_with_timeout) on a clock given by the host rather than
+    /// `std::time::Instant`, like one it advances itself to keep runs reproducible. `now_ms`
+    /// returns milliseconds since any fixed point.
+    pub fn eval_with_deadline(
+        &self,
+        code: &Code,
+        timeout_ms: u64,
+        now_ms: fn() -> u64,
+    ) -> Result<Value> {
+        self.eval_until(code, timeout_ms, now_ms)
+    }
+
+    fn eval_until(
+        &self,
+        code: &Code,
+        timeout_ms: u64,
+        now_ms: impl Fn() -> u64 + Copy + 'static,
+    ) -> Result<Value> {
+        let Some(previous) = self
+            .runtime_data()
+            .map(|data| data.interrupt.borrow().clone())
+        else {
+            bail!("the context has no runtime to interrupt it");
+        };
+        let start = now_ms();
+        let timed_out = Rc::new(Cell::new(false));
+        let handler: InterruptHandler = Rc::new(RefCell::new({
+            let previous = previous.clone();
+            let timed_out = timed_out.clone();
+            move || {
+                if now_ms().saturating_sub(start) >= timeout_ms {
+                    timed_out.set(true);
+                    return true;
+                }
+                match previous.as_ref().map(|handler| handler.try_borrow_mut()) {
+                    Some(Ok(mut handler)) => handler(),
+                    _ => false,
+                }
+            }
+        }));
+        // The runtime data is reached again for each borrow, none is held while the script runs.
+        if let Some(data) = self.runtime_data() {
+            let _replaced = data.interrupt.replace(Some(handler));
+        }
+        // Put the previous handler back even if the eval unwinds.
+        let _restore = scopeguard::guard(previous, |previous| {
+            if let Some(data) = self.runtime_data() {
+                let _replaced = data.interrupt.replace(previous);
+            }
+        });
+        unsafe {
+            c::JS_SetInterruptHandler(
+                c::JS_GetRuntime(self.as_ptr()),
+                Some(interrupt_handler),
+                core::ptr::null_mut(),
+            );
+        }
+        crate::eval::eval_checked(self, code).map_err(|err| {
+            if !(timed_out.get() && err.is::<crate::Interrupted>()) {
+                return err;
+            }
+            let elapsed = now_ms().saturating_sub(start);
+            err.context(format!(
+                "script timed out after running for {elapsed} ms, the limit is {timeout_ms} ms"
+            ))
+        })
+    }
+
     pub fn throw(&self, err: impl core::fmt::Display) {
         self.throw_str(&format!("{err:#}"));
     }
@@ -74,35 +276,100 @@ impl Context {
     }
 
     pub fn throw_str(&self, err: &str) {
-        let cmsg = alloc::ffi::CString::new(err).unwrap_or_default();
+        let cmsg = SmallStr::c_str(err);
         unsafe {
             c::JS_ThrowGenericError(self.as_ptr(), cmsg.as_ptr());
         }
     }
 
+    /// Throw `value` as is.
+    pub fn throw_value(&self, value: Value) {
+        unsafe { c::JS_Throw(self.as_ptr(), value.leak()) };
+    }
+
     pub fn throw_type_err(&self, err: &str) {
-        let cmsg = alloc::ffi::CString::new(err).unwrap_or_default();
+        let cmsg = SmallStr::c_str(err);
         unsafe { c::JS_ThrowTypeError(self.as_ptr(), cmsg.as_ptr()) };
     }
 
+    /// Take the pending exception as an error, with an [`OutOfMemory`](crate::OutOfMemory)
+    /// context if the engine ran out of memory, and an [`Interrupted`](crate::Interrupted) one,
+    /// plus [`OutOfFuel`](crate::OutOfFuel) when the fuel ran out, if the script was interrupted.
     pub fn get_exception_error(&self) -> crate::Error {
-        anyhow!("{}", self.get_exception_str())
+        let message = self.get_exception_str();
+        self.classify_error(anyhow!("{message}"))
+    }
+
+    /// Add the [`OutOfMemory`](crate::OutOfMemory), [`Interrupted`](crate::Interrupted) and
+    /// [`OutOfFuel`](crate::OutOfFuel) contexts to the error of an exception that was one of them.
+    pub(crate) fn classify_error(&self, err: crate::Error) -> crate::Error {
+        let message = err.to_string();
+        if self.is_out_of_memory(&message) {
+            return err.context(crate::OutOfMemory);
+        }
+        if self.is_interrupted(&message) {
+            let out_of_fuel = self
+                .runtime_data()
+                .is_some_and(|data| data.fuel.get() == Some(0));
+            let err = err.context(crate::Interrupted);
+            if out_of_fuel {
+                return err.context(crate::OutOfFuel);
+            }
+            return err;
+        }
+        err
+    }
+
+    /// Whether `error`, returned by [`eval`](Self::eval), is the engine running out of memory
+    /// rather than an exception of the script. Call it once, right after the failed eval: it
+    /// consumes what the engine recorded when it threw the error.
+    pub fn is_out_of_memory(&self, error: &str) -> bool {
+        let refused = self
+            .runtime_data()
+            .is_some_and(|data| data.alloc_state.take_out_of_memory());
+        refused && error.lines().next() == Some("InternalError: out of memory")
+    }
+
+    /// Whether `error` is the interruption the interrupt handler of the runtime asked for, rather
+    /// than the same error thrown by a script. Like `is_out_of_memory`, it consumes what the
+    /// runtime recorded.
+    fn is_interrupted(&self, error: &str) -> bool {
+        let interrupted = self
+            .runtime_data()
+            .is_some_and(|data| data.interrupted.take());
+        interrupted && crate::is_interrupted(error)
     }
 
     pub fn get_exception_str(&self) -> String {
+        unsafe {
+            let e = c::JS_GetException(self.as_ptr());
+            let exc_str = self.error_string(e);
+            c::JS_FreeValue(self.as_ptr(), e);
+            exc_str
+        }
+    }
+
+    /// Format a thrown value or rejection reason like `get_exception_str` does, with the stack
+    /// of an `Error` appended.
+    pub fn error_to_string(&self, error: &Value) -> String {
+        self.error_string(*error.raw_value())
+    }
+
+    fn error_string(&self, e: c::JSValueConst) -> String {
         let ctx_ptr = self.as_ptr();
+        let mut exc_str = crate::ctx_to_string(self, e);
+        if unsafe { c::JS_IsObject(e) } == 0 {
+            return exc_str;
+        }
         unsafe {
-            let e = c::JS_GetException(ctx_ptr);
-            let mut exc_str = crate::ctx_to_string(self, e);
             let stack = c::JS_GetPropertyStr(ctx_ptr, e, cstr::cstr!("stack").as_ptr() as _);
             if !c::is_undefined(stack) {
                 exc_str.push_str("\n[stack]\n");
                 exc_str.push_str(&crate::ctx_to_string(self, stack));
             }
-            c::JS_FreeValue(ctx_ptr, e);
             c::JS_FreeValue(ctx_ptr, stack);
-            exc_str
         }
+        exc_str
     }
 
     pub fn get_qjsbind_object<F, V>(&self, name: &str, or_default: F) -> Result<Value>
@@ -110,6 +377,17 @@ impl Context {
         F: Fn() -> Result<V>,
         V: ToJsValue,
     {
+        let bindings = self.qjsbind_bindings()?;
+        let mut obj = bindings.get_property(name)?;
+        if obj.is_undefined() {
+            obj = or_default()?.to_js_value(self)?;
+            bindings.set_property(name, &obj)?;
+        }
+        Ok(obj)
+    }
+
+    /// The global `_QjsBind` object holding the per-context state of qjsbind.
+    pub(crate) fn qjsbind_bindings(&self) -> Result<Value> {
         let global = self.get_global_object();
         let bindings_obj_name = "_QjsBind";
         let mut bindings = global
@@ -119,12 +397,159 @@ impl Context {
             bindings = self.new_object(bindings_obj_name);
             global.set_property(bindings_obj_name, &bindings)?;
         }
-        let mut obj = bindings.get_property(name)?;
-        if obj.is_undefined() {
-            obj = or_default()?.to_js_value(self)?;
-            bindings.set_property(name, &obj)?;
+        Ok(bindings)
+    }
+
+    fn runtime_data(&self) -> Option<&RuntimeData> {
+        unsafe {
+            let rt = c::JS_GetRuntime(self.as_ptr());
+            let data = c::JS_GetRuntimeOpaque(rt) as *const RuntimeData;
+            data.as_ref()
+        }
+    }
+
+    /// The host futures of the runtime, for a context with teardown support.
+    pub(crate) fn host_futures(&self) -> Option<RefMut<'_, HostFutures>> {
+        self.data()?;
+        Some(self.runtime_data()?.host_futures.borrow_mut())
+    }
+
+    pub(crate) fn module_hooks(&self) -> Option<RefMut<'_, ModuleHooks>> {
+        Some(self.runtime_data()?.modules.borrow_mut())
+    }
+
+    /// The data of type `T` attached to the runtime of this context, see
+    /// [`Runtime::set_user_data`].
+    pub fn runtime_user_data<T: 'static>(&self) -> Option<Rc<T>> {
+        self.runtime_data()?.user_data()
+    }
+
+    /// Attach `data` to the context, replacing any earlier data of the same type, for the host
+    /// functions called with it to reach, like the handle of the request a context serves.
+    ///
+    /// The data is dropped with the context, after its `on_destroy` callbacks ran. It must not
+    /// hold JS values of the context, which would keep it alive. Only contexts created by
+    /// `Runtime::new_context` have data, for others it is dropped right away.
+    pub fn set_user_data<T: 'static>(&self, data: T) {
+        let Some(context_data) = self.data() else {
+            log::warn!("set_user_data ignored for a context without teardown support");
+            return;
+        };
+        // Dropped after the borrow ends, in case its drop reads the data of the context.
+        let _replaced = context_data
+            .user_data
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Rc::new(data));
+    }
+
+    /// The data of type `T` attached with [`set_user_data`](Self::set_user_data).
+    pub fn user_data<T: 'static>(&self) -> Option<Rc<T>> {
+        let data = self
+            .data()?
+            .user_data
+            .borrow()
+            .get(&TypeId::of::<T>())?
+            .clone();
+        data.downcast().ok()
+    }
+
+    /// The state of type `T` qjsbind keeps for the context, created on first use. It is kept
+    /// with the user data of the context, out of reach of scripts. `None` for contexts without
+    /// teardown support.
+    pub(crate) fn state<T: Default + 'static>(&self) -> Option<Rc<T>> {
+        if let Some(state) = self.user_data::<T>() {
+            return Some(state);
+        }
+        self.data()?;
+        self.set_user_data(T::default());
+        self.user_data()
+    }
+
+    /// Like [`state`](Self::state), for the state shared by the contexts of the runtime.
+    pub(crate) fn runtime_state<T: Default + 'static>(&self) -> Option<Rc<T>> {
+        let data = self.runtime_data()?;
+        if let Some(state) = data.user_data() {
+            return Some(state);
+        }
+        data.user_data
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Rc::new(T::default()));
+        data.user_data()
+    }
+
+    /// The object `name` qjsbind keeps for the context, created with `or_default` the first
+    /// time, like [`get_qjsbind_object`](Self::get_qjsbind_object) but held by the host rather
+    /// than by a global, so that scripts can neither reach nor replace it. It is released when
+    /// the context is torn down, after its `on_destroy` callbacks ran.
+    pub(crate) fn host_object(
+        &self,
+        name: &str,
+        or_default: impl FnOnce() -> Result<Value>,
+    ) -> Result<Value> {
+        let Some(data) = self.data() else {
+            bail!("no host state for a context without teardown support");
+        };
+        let mut values = data.host_values.get();
+        if c::is_undefined(values) {
+            values = unsafe { c::JS_NewObjectProto(self.as_ptr(), c::JS_NULL) };
+            if c::is_exception(values) {
+                return Err(self.get_exception_error());
+            }
+            data.host_values.set(values);
+        }
+        let values = Value::new_cloned(self, values);
+        let mut object = values.get_property(name)?;
+        if object.is_undefined() {
+            object = or_default()?;
+            values.define_property_value(name, object.clone())?;
+        }
+        Ok(object)
+    }
+
+    pub(crate) fn audit_enabled(&self) -> bool {
+        self.data().is_some_and(|data| data.audit_enabled.get())
+    }
+
+    pub(crate) fn set_audit_enabled(&self, enabled: bool) {
+        if let Some(data) = self.data() {
+            data.audit_enabled.set(enabled);
+        }
+    }
+
+    pub(crate) fn default_rename_cell(&self) -> Option<&Cell<crate::Convention>> {
+        self.data().map(|data| &data.default_rename)
+    }
+
+    pub(crate) fn limits_state(&self) -> Option<&Rc<crate::limits::LimitsState>> {
+        self.data().map(|data| &data.limits)
+    }
+
+    pub(crate) fn receipts_enabled(&self) -> bool {
+        self.data().is_some_and(|data| data.receipts_enabled.get())
+    }
+
+    pub(crate) fn set_receipts_enabled(&self, enabled: bool) {
+        if let Some(data) = self.data() {
+            data.receipts_enabled.set(enabled);
+        }
+    }
+
+    /// Run a garbage collection pass once the host function being called returns, to release
+    /// what the script dropped during a long evaluation without waiting for the GC threshold.
+    pub fn request_gc(&self) {
+        if let Some(data) = self.runtime_data() {
+            data.gc_requested.set(true);
+        }
+    }
+
+    /// Run the pass asked for by [`Context::request_gc`], if any.
+    pub(crate) fn run_requested_gc(&self) {
+        let Some(data) = self.runtime_data() else {
+            return;
+        };
+        if data.gc_requested.take() {
+            collect_garbage(unsafe { c::JS_GetRuntime(self.as_ptr()) });
         }
-        Ok(obj)
     }
 
     pub fn resolve_object(&self, full_path: &str) -> Result<Value> {
@@ -173,12 +598,35 @@ impl AsRef<c::JSContext> for Context {
 impl Clone for Context {
     fn clone(&self) -> Self {
         unsafe { c::JS_DupContext(self.ptr.as_ptr()) };
+        self.add_handle();
         Context { ptr: self.ptr }
     }
 }
 
 impl Drop for Context {
     fn drop(&mut self) {
+        if let Some(data) = self.data() {
+            let handles = data.handles.get() - 1;
+            data.handles.set(handles);
+            if handles == 0 {
+                self.run_destroy_callbacks(data);
+                self.cancel_host_futures();
+            }
+            // A callback may have kept a clone, the data then waits for the last of them.
+            if data.handles.get() == 0 {
+                let rt = unsafe { c::JS_GetRuntime(self.as_ptr()) };
+                if let Some(prototypes) = runtime_state_of::<ObjectPrototypes>(rt) {
+                    let address = data.object_prototype.get();
+                    prototypes.0.borrow_mut().retain(|&p| p != address);
+                }
+                unsafe {
+                    c::JS_SetContextOpaque(self.as_ptr(), core::ptr::null_mut());
+                    drop(Box::from_raw(
+                        data as *const ContextData as *mut ContextData,
+                    ));
+                }
+            }
+        }
         unsafe {
             c::JS_FreeContext(self.ptr.as_ptr());
         }
@@ -189,58 +637,243 @@ pub struct Runtime {
     ptr: NonNull<c::JSRuntime>,
 }
 
+/// The stack size runtimes are limited to on wasm32, see `Runtime::set_max_stack_size`. It
+/// leaves room for the frames of the host below the engine.
+pub const WASM_MAX_STACK_SIZE: usize = 256 * 1024;
+
 #[derive(Debug, Clone, Default)]
 pub struct EngineConfig {
     pub memory_limit: Option<u32>,
     pub gas_limit: Option<u32>,
+    /// Wall-clock limit for script execution, in milliseconds. See
+    /// [`with_time_limit`](Self::with_time_limit) to set it with a unit.
     pub time_limit: Option<u64>,
 }
 
 impl EngineConfig {
+    /// Limit the wall-clock time of script execution to `limit`.
+    pub fn with_time_limit(mut self, limit: Millis) -> Self {
+        self.time_limit = Some(limit.0);
+        self
+    }
+
     pub fn need_interrupt(&self) -> bool {
         self.gas_limit.is_some() || self.time_limit.is_some()
     }
 }
 
+/// The closure of `Runtime::set_interrupt_handler`.
+type InterruptHandler = Rc<RefCell<dyn FnMut() -> bool>>;
+type GcObserver = Rc<dyn Fn(GcEvent)>;
+type RejectionHandler = Rc<RefCell<dyn FnMut(&Context, Value, Value, bool)>>;
+
+/// What the runtime does with the promises rejected without a handler.
+enum RejectionTracking {
+    Handler(RejectionHandler),
+    /// The reasons of the rejections still unhandled, with the address of their promise to
+    /// tell which one gets handled later.
+    Collect(Vec<(usize, String)>),
+}
+
+/// The data of a runtime, reached from the engine callbacks, which may run while the host holds
+/// it too, so it is only ever shared and what changes is in cells.
 struct RuntimeData {
-    gas_remain: u32,
-    abort_tx: Option<broadcast::Sender<()>>,
+    /// `None` without a gas limit.
+    gas_remain: Cell<Option<u32>>,
+    /// `None` unless `Runtime::set_fuel` was called.
+    fuel: Cell<Option<u64>>,
+    abort_tx: RefCell<Option<broadcast::Sender<()>>>,
     start_time: Instant,
     time_limit: Option<u64>,
+    gc_requested: Cell<bool>,
+    gc_observer: RefCell<Option<GcObserver>>,
+    /// When the collection being observed started, and the bytes allocated then.
+    gc_started: Cell<Option<(Instant, usize)>>,
+    interrupt: RefCell<Option<InterruptHandler>>,
+    /// Whether the interrupt handler stopped a script since the last error was classified.
+    interrupted: Cell<bool>,
+    rejections: RefCell<Option<RejectionTracking>>,
+    modules: RefCell<ModuleHooks>,
+    host_futures: RefCell<HostFutures>,
+    user_data: RefCell<BTreeMap<TypeId, Rc<dyn Any>>>,
+    alloc_state: AllocState,
+}
+
+impl RuntimeData {
+    fn user_data<T: 'static>(&self) -> Option<Rc<T>> {
+        let data = self.user_data.borrow().get(&TypeId::of::<T>())?.clone();
+        data.downcast().ok()
+    }
 }
 
 extern "C" fn interrupt_handler(rt: *mut c::JSRuntime, _opaque: *mut core::ffi::c_void) -> i32 {
-    let data = unsafe { &mut *(c::JS_GetRuntimeOpaque(rt) as *mut RuntimeData) };
-    if data.gas_remain == 0 {
-        if let Some(tx) = &data.abort_tx {
+    let Some(data) = (unsafe { (c::JS_GetRuntimeOpaque(rt) as *const RuntimeData).as_ref() })
+    else {
+        return 0;
+    };
+    if should_interrupt(data) {
+        data.interrupted.set(true);
+        if let Some(tx) = &*data.abort_tx.borrow() {
             let _ = tx.send(());
         }
         return 1;
     }
+    0
+}
+
+fn should_interrupt(data: &RuntimeData) -> bool {
+    let gas = data.gas_remain.get();
+    let fuel = data.fuel.get();
+    if gas == Some(0) || fuel == Some(0) {
+        return true;
+    }
     if let Some(time_limit) = data.time_limit {
         let elapsed = data.start_time.elapsed();
         if elapsed.as_millis() >= time_limit as _ {
-            if let Some(tx) = &data.abort_tx {
-                let _ = tx.send(());
+            return true;
+        }
+    }
+    data.gas_remain.set(gas.map(|gas| gas - 1));
+    data.fuel.set(fuel.map(|fuel| fuel - 1));
+    // A clone, so that the handler can replace itself while it runs.
+    let Some(handler) = data.interrupt.borrow().clone() else {
+        return false;
+    };
+    // Already running if the handler itself ran a script, which is left to go on.
+    let Ok(mut handler) = handler.try_borrow_mut() else {
+        return false;
+    };
+    handler()
+}
+
+extern "C" fn promise_rejection_tracker(
+    ctx: *mut c::JSContext,
+    promise: c::JSValueConst,
+    reason: c::JSValueConst,
+    is_handled: i32,
+    _opaque: *mut core::ffi::c_void,
+) {
+    let Some(ctx) = Context::clone_from_ptr(ctx) else {
+        return;
+    };
+    let is_handled = is_handled != 0;
+    let Some(data) = ctx.runtime_data() else {
+        return;
+    };
+    let tracking = match &*data.rejections.borrow() {
+        Some(RejectionTracking::Handler(handler)) => Some(handler.clone()),
+        Some(RejectionTracking::Collect(_)) => None,
+        None => return,
+    };
+    let handler = match tracking {
+        Some(handler) => handler,
+        None => {
+            // Formatted before borrowing the list, as converting the reason runs scripts.
+            let reason = (!is_handled).then(|| ctx.error_string(reason));
+            let mut rejections = data.rejections.borrow_mut();
+            let Some(RejectionTracking::Collect(unhandled)) = &mut *rejections else {
+                return;
+            };
+            let promise = unsafe { c::JS_GetPtr(promise) } as usize;
+            match reason {
+                Some(reason) => unhandled.push((promise, reason)),
+                // An older entry for the same address is from a promise already freed.
+                None => {
+                    if let Some(i) = unhandled.iter().rposition(|(p, _)| *p == promise) {
+                        unhandled.remove(i);
+                    }
+                }
             }
-            return 1;
+            return;
         }
+    };
+    // Already running if the handler itself rejected a promise, which is not reported.
+    let Ok(mut handler) = handler.try_borrow_mut() else {
+        return;
+    };
+    let promise = Value::new_cloned(&ctx, promise);
+    let reason = Value::new_cloned(&ctx, reason);
+    handler(&ctx, promise, reason, is_handled);
+}
+
+/// The state of type `T` of the runtime `rt`, see `Context::runtime_state`, for the finalizers
+/// that only get the runtime. `None` once the runtime is being freed.
+pub(crate) fn runtime_state_of<T: 'static>(rt: *mut c::JSRuntime) -> Option<Rc<T>> {
+    let data = unsafe { (c::JS_GetRuntimeOpaque(rt) as *const RuntimeData).as_ref() }?;
+    data.user_data()
+}
+
+/// Collect the garbage of `rt`, reported to the observer of `Runtime::set_gc_observer` like the
+/// collections the engine runs on its own.
+pub(crate) fn collect_garbage(rt: *mut c::JSRuntime) {
+    unsafe { c::JS_RunGC(rt) };
+}
+
+/// Called by the engine before and after each collection, see `Runtime::set_gc_observer`.
+unsafe extern "C" fn gc_hook(rt: *mut c::JSRuntime, end: core::ffi::c_int) {
+    let Some(data) = (c::JS_GetRuntimeOpaque(rt) as *const RuntimeData).as_ref() else {
+        return;
+    };
+    // A clone, so that the observer can replace itself.
+    let Some(observer) = data.gc_observer.borrow().clone() else {
+        return;
+    };
+    let malloc_size = crate::memory::malloc_size(rt);
+    if end == 0 {
+        observer(GcEvent::Begin { malloc_size });
+        data.gc_started.set(Some((Instant::now(), malloc_size)));
+        return;
     }
-    data.gas_remain -= 1;
-    0
+    // Unset when the observer was set during the collection.
+    let Some((start, before)) = data.gc_started.take() else {
+        return;
+    };
+    observer(GcEvent::End {
+        duration: start.elapsed(),
+        reclaimed: before.saturating_sub(malloc_size),
+        malloc_size,
+    });
 }
 
+/// Called by the engine when it throws its out of memory error, which only it can tell from the
+/// same error thrown by a script, see `Context::is_out_of_memory`.
+unsafe extern "C" fn oom_hook(rt: *mut c::JSRuntime) {
+    if let Some(data) = (c::JS_GetRuntimeOpaque(rt) as *const RuntimeData).as_ref() {
+        data.alloc_state.set_out_of_memory();
+    }
+}
+
+static HOOKS: Once = Once::new();
+
 impl Runtime {
     pub fn new(config: &EngineConfig) -> Self {
         let ptr = unsafe { c::JS_NewRuntime() };
         let ptr = NonNull::new(ptr).expect("Failed to create JSRuntime");
+        // The same hooks for every runtime, finding the observer, the time providers and where to
+        // record running out of memory in the runtime and context data. They are process-wide statics of the engine, set once so
+        // that runtimes created on several threads do not race on them.
+        HOOKS.call_once(|| unsafe {
+            c::JS_SetGCHook(Some(gc_hook));
+            c::JS_SetDateNowHook(Some(crate::time::date_now_hook));
+            c::JS_SetOutOfMemoryHook(Some(oom_hook));
+        });
 
-        let gas_remain = config.gas_limit.unwrap_or_default();
         let data = Box::new(RuntimeData {
-            gas_remain,
+            gas_remain: Cell::new(config.gas_limit),
+            fuel: Cell::new(None),
             start_time: Instant::now(),
             time_limit: config.time_limit,
-            abort_tx: None,
+            abort_tx: RefCell::new(None),
+            gc_requested: Cell::new(false),
+            gc_observer: RefCell::new(None),
+            gc_started: Cell::new(None),
+            interrupt: RefCell::new(None),
+            interrupted: Cell::new(false),
+            rejections: RefCell::new(None),
+            modules: RefCell::new(ModuleHooks::default()),
+            host_futures: RefCell::new(HostFutures::default()),
+            user_data: RefCell::new(BTreeMap::new()),
+            alloc_state: AllocState::default(),
         });
         unsafe {
             c::JS_SetRuntimeOpaque(ptr.as_ptr(), Box::into_raw(data) as *mut _);
@@ -252,32 +885,251 @@ impl Runtime {
                     core::ptr::null_mut(),
                 );
             }
-            if let Some(memory_limit) = config.memory_limit {
-                c::JS_SetMemoryLimit(ptr.as_ptr(), memory_limit as usize);
-            }
         }
-        Runtime { ptr }
+        let runtime = Runtime { ptr };
+        #[cfg(target_arch = "wasm32")]
+        runtime.set_max_stack_size(WASM_MAX_STACK_SIZE);
+        if let Some(memory_limit) = config.memory_limit {
+            runtime.set_memory_limit(memory_limit as usize);
+        }
+        runtime
+    }
+
+    /// Cap the bytes the runtime allocates, `0` for no cap. Applies to the contexts already
+    /// created as well as later ones, and can be changed at any time.
+    ///
+    /// An allocation past the cap fails, and the script gets an `InternalError: out of memory`.
+    /// The engine records that it threw it, so that it can be told apart from the same error
+    /// thrown by a script, with [`Context::is_out_of_memory`] or the
+    /// [`OutOfMemory`](crate::OutOfMemory) context of the errors of calls. With the
+    /// `pink-allocator` feature of qjs-sys the bytes counted are the capacity of the underlying
+    /// allocations, which may be a little more than what was asked for.
+    pub fn set_memory_limit(&self, bytes: usize) {
+        unsafe { c::JS_SetMemoryLimit(self.ptr.as_ptr(), bytes) };
+    }
+
+    /// Collect the garbage of the runtime now, including the cycles reference counting can not
+    /// free. The finalizers of the collected objects, like the drop of the Rust values held by
+    /// opaque objects, run before it returns.
+    pub fn run_gc(&self) {
+        collect_garbage(self.ptr.as_ptr());
+    }
+
+    /// Call `observer` before and after each collection: the ones QuickJS runs on its own when
+    /// allocations cross the [GC threshold](Self::set_gc_threshold), and the ones run by the
+    /// host with [`run_gc`](Self::run_gc), [`Context::request_gc`] or when a pool resets a
+    /// context.
+    ///
+    /// The observer runs while the runtime is collecting, and must not create JS values, run
+    /// scripts or panic. It replaces the previous one, and is dropped with the runtime.
+    pub fn set_gc_observer(&self, observer: impl Fn(GcEvent) + 'static) {
+        let _replaced = self.data().gc_observer.replace(Some(Rc::new(observer)));
+    }
+
+    /// Remove the observer of `set_gc_observer`.
+    pub fn clear_gc_observer(&self) {
+        let _removed = self.data().gc_observer.take();
+    }
+
+    /// Run a collection whenever the bytes allocated grow by `bytes` since the last one. QuickJS
+    /// starts at 256 KiB.
+    pub fn set_gc_threshold(&self, bytes: usize) {
+        unsafe { c::JS_SetGCThreshold(self.ptr.as_ptr(), bytes) };
+    }
+
+    /// Limit the native stack the engine may use below the point where the runtime was created,
+    /// `0` for no limit. A script recursing past it gets an `InternalError: stack overflow`
+    /// instead of overflowing the stack of the host.
+    ///
+    /// QuickJS picks the limit on native targets. On wasm32 the runtime starts with
+    /// [`WASM_MAX_STACK_SIZE`], which fits the 1 MiB stack Rust gives wasm modules by default.
+    pub fn set_max_stack_size(&self, bytes: usize) {
+        unsafe { c::JS_SetMaxStackSize(self.ptr.as_ptr(), bytes as _) };
+    }
+
+    /// Call `handler` periodically while scripts run, interrupting them when it returns `true`,
+    /// to cancel a script stuck in a loop. It is also called while a script runs inside a host
+    /// function called by another one.
+    ///
+    /// An interrupted script gets an uncatchable `InternalError: interrupted`, which can be told
+    /// apart from other errors with [`is_interrupted`](crate::is_interrupted) or
+    /// [`Interrupted`](crate::Interrupted). The handler replaces the previous one, and is dropped
+    /// with the runtime. It runs in addition to the gas and time limits of the `EngineConfig`.
+    pub fn set_interrupt_handler(&self, handler: impl FnMut() -> bool + 'static) {
+        let handler: InterruptHandler = Rc::new(RefCell::new(handler));
+        let _replaced = self.data().interrupt.replace(Some(handler));
+        self.enable_interrupts();
+    }
+
+    /// Remove the handler of `set_interrupt_handler`.
+    pub fn clear_interrupt_handler(&self) {
+        let _removed = self.data().interrupt.take();
+    }
+
+    /// Give scripts `fuel` units to run on, replacing what is left. Once it runs out, scripts
+    /// are interrupted like by [`set_interrupt_handler`](Self::set_interrupt_handler), with an
+    /// [`OutOfFuel`](crate::OutOfFuel) error, until more fuel is set.
+    ///
+    /// A unit is burnt each time the engine polls for interrupts, which it does after a fixed
+    /// count of jumps and calls. Unlike the time limit, the same script with the same inputs on
+    /// the same engine build burns the same fuel, so it can be charged for deterministically.
+    /// The count carries over between calls into a context, so compare runs on fresh contexts:
+    /// a call after others may burn one unit more or less.
+    pub fn set_fuel(&self, fuel: u64) {
+        self.data().fuel.set(Some(fuel));
+        self.enable_interrupts();
+    }
+
+    /// The fuel left since [`set_fuel`](Self::set_fuel), `None` if it was never called.
+    pub fn fuel_remaining(&self) -> Option<u64> {
+        self.data().fuel.get()
+    }
+
+    /// Call `handler` with the promise and its reason when a promise is rejected without a
+    /// rejection handler, and again with `is_handled` set when one is attached later. The reason
+    /// can be formatted with its stack by [`Context::error_to_string`].
+    ///
+    /// The handler replaces the previous one or the collection of
+    /// [`collect_unhandled_rejections`](Self::collect_unhandled_rejections), and is dropped
+    /// with the runtime.
+    pub fn set_promise_rejection_tracker(
+        &self,
+        handler: impl FnMut(&Context, Value, Value, bool) + 'static,
+    ) {
+        let handler: RejectionHandler = Rc::new(RefCell::new(handler));
+        let _replaced = self
+            .data()
+            .rejections
+            .replace(Some(RejectionTracking::Handler(handler)));
+        self.enable_rejection_tracking();
+    }
+
+    /// Keep the reasons of the promises rejected without a handler, formatted with their stack,
+    /// for [`take_unhandled_rejections`](Self::take_unhandled_rejections). A rejection is
+    /// forgotten once a handler is attached to its promise. Replaces the handler of
+    /// [`set_promise_rejection_tracker`](Self::set_promise_rejection_tracker).
+    pub fn collect_unhandled_rejections(&self) {
+        let rejections = &self.data().rejections;
+        if !matches!(*rejections.borrow(), Some(RejectionTracking::Collect(_))) {
+            let _replaced = rejections.replace(Some(RejectionTracking::Collect(Vec::new())));
+        }
+        self.enable_rejection_tracking();
+    }
+
+    /// Take the reasons of the rejections still unhandled, oldest first, see
+    /// [`collect_unhandled_rejections`](Self::collect_unhandled_rejections). Empty when the
+    /// runtime does not collect them.
+    pub fn take_unhandled_rejections(&self) -> Vec<String> {
+        match &mut *self.data().rejections.borrow_mut() {
+            Some(RejectionTracking::Collect(unhandled)) => core::mem::take(unhandled)
+                .into_iter()
+                .map(|(_, reason)| reason)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Stop tracking rejections, dropping the handler or the collected rejections.
+    pub fn clear_promise_rejection_tracker(&self) {
+        unsafe {
+            c::JS_SetHostPromiseRejectionTracker(self.ptr.as_ptr(), None, core::ptr::null_mut());
+        }
+        let _removed = self.data().rejections.take();
+    }
+
+    fn enable_rejection_tracking(&self) {
+        unsafe {
+            c::JS_SetHostPromiseRejectionTracker(
+                self.ptr.as_ptr(),
+                Some(promise_rejection_tracker),
+                core::ptr::null_mut(),
+            );
+        }
+    }
+
+    fn enable_interrupts(&self) {
+        unsafe {
+            c::JS_SetInterruptHandler(
+                self.ptr.as_ptr(),
+                Some(interrupt_handler),
+                core::ptr::null_mut(),
+            );
+        }
+    }
+
+    fn data(&self) -> &RuntimeData {
+        unsafe { &*(c::JS_GetRuntimeOpaque(self.ptr.as_ptr()) as *const RuntimeData) }
+    }
+
+    pub(crate) fn host_futures(&self) -> RefMut<'_, HostFutures> {
+        self.data().host_futures.borrow_mut()
+    }
+
+    pub(crate) fn module_hooks(&self) -> RefMut<'_, ModuleHooks> {
+        self.data().modules.borrow_mut()
     }
 
     pub fn new_context(&self) -> Context {
         let ptr = unsafe { c::JS_NewContext(self.ptr.as_ptr()) };
+        self.init_context(ptr)
+    }
+
+    /// Set up a context just created in the runtime.
+    pub(crate) fn init_context(&self, ptr: *mut c::JSContext) -> Context {
         let ptr = NonNull::new(ptr).expect("Failed to create JSContext");
+        let data = Box::new(ContextData {
+            handles: Cell::new(1),
+            on_destroy: RefCell::new(Vec::new()),
+            user_data: RefCell::new(BTreeMap::new()),
+            host_values: Cell::new(c::JS_UNDEFINED),
+            audit_enabled: Cell::new(false),
+            receipts_enabled: Cell::new(false),
+            default_rename: Cell::new(crate::Convention::Keep),
+            limits: Rc::default(),
+            object_prototype: Cell::new(0),
+        });
         unsafe {
             c::js_opaque_class_init(ptr.as_ptr());
+            c::JS_SetContextOpaque(ptr.as_ptr(), Box::into_raw(data) as *mut _);
+        }
+        let ctx = Context { ptr };
+        if let (Some(data), Ok(proto), Some(prototypes)) = (
+            ctx.data(),
+            ctx.object_prototype(),
+            ctx.runtime_state::<ObjectPrototypes>(),
+        ) {
+            let address = unsafe { c::JS_GetPtr(*proto.raw_value()) } as usize;
+            data.object_prototype.set(address);
+            prototypes.0.borrow_mut().push(address);
+        }
+        if let Err(err) = crate::intrinsics::capture(&ctx) {
+            log::warn!("failed to capture the intrinsics: {err:?}");
         }
-        Context { ptr }
+        ctx
     }
 
+    #[deprecated(note = "use `execute_pending_jobs`, which runs them all")]
     pub fn exec_pending_jobs(&self) -> Result<i32, String> {
-        let mut ctx_ptr = core::ptr::null_mut();
-        let ret = unsafe { c::JS_ExecutePendingJob(self.ptr.as_ptr(), &mut ctx_ptr) };
-        if ret < 0 {
-            return match Context::clone_from_ptr(ctx_ptr) {
-                Some(ctx) => Err(ctx.get_exception_str()),
-                None => Err("no context".to_string()),
-            };
+        match run_pending_job(self.ptr.as_ptr()) {
+            Ok(ran) => Ok(ran as i32),
+            Err(err) => Err(err.root_cause().to_string()),
         }
-        Ok(ret)
+    }
+
+    /// Run the pending jobs, like promise reactions, until none are left, including the ones
+    /// queued by the jobs themselves, and return how many ran.
+    ///
+    /// The first job that throws stops the run with its exception, leaving the rest pending. The
+    /// error has a [`JobFailed`](crate::JobFailed) context telling which context the job ran
+    /// in. A script that keeps queueing jobs keeps this running until the gas, fuel or time
+    /// limits interrupt it; `EvalOptions::job_limit` caps the jobs run after an eval instead.
+    pub fn execute_pending_jobs(&self) -> Result<usize> {
+        run_pending_jobs(self.ptr.as_ptr(), usize::MAX)
+    }
+
+    /// Whether jobs are waiting for [`execute_pending_jobs`](Self::execute_pending_jobs).
+    pub fn has_pending_jobs(&self) -> bool {
+        unsafe { c::JS_IsJobPending(self.ptr.as_ptr()) != 0 }
     }
 
     pub fn enable_dump_exceptions(&self) {
@@ -300,13 +1152,29 @@ impl Runtime {
         }
     }
 
+    /// Attach `data` to the runtime, replacing any earlier data of the same type. It is shared by
+    /// the contexts of the runtime, and dropped before the runtime is freed, so it must not hold
+    /// JS values.
+    pub fn set_user_data<T: 'static>(&self, data: T) {
+        let _replaced = self
+            .data()
+            .user_data
+            .borrow_mut()
+            .insert(TypeId::of::<T>(), Rc::new(data));
+    }
+
+    /// The data of type `T` attached with `set_user_data`.
+    pub fn user_data<T: 'static>(&self) -> Option<Rc<T>> {
+        self.data().user_data()
+    }
+
     pub fn subscribe_abort(&self) -> broadcast::Receiver<()> {
-        let data = unsafe { &mut *(c::JS_GetRuntimeOpaque(self.ptr.as_ptr()) as *mut RuntimeData) };
-        if let Some(tx) = &data.abort_tx {
+        let mut abort_tx = self.data().abort_tx.borrow_mut();
+        if let Some(tx) = &*abort_tx {
             return tx.subscribe();
         }
         let (tx, rx) = broadcast::channel(1);
-        data.abort_tx = Some(tx);
+        *abort_tx = Some(tx);
         rx
     }
 
@@ -315,13 +1183,410 @@ impl Runtime {
     }
 }
 
+/// Run the next pending job of `rt`, `false` if there was none.
+fn run_pending_job(rt: *mut c::JSRuntime) -> Result<bool> {
+    let mut job_ctx = core::ptr::null_mut();
+    let ret = unsafe { c::JS_ExecutePendingJob(rt, &mut job_ctx) };
+    if ret < 0 {
+        let failed = crate::JobFailed::new(job_ctx);
+        return Err(match Context::clone_from_ptr(job_ctx) {
+            Some(job_ctx) => job_ctx.get_exception_error().context(failed),
+            None => anyhow!("pending job failed").context(failed),
+        });
+    }
+    Ok(ret > 0)
+}
+
+/// Run the pending jobs of `rt` until none are left, failing if more than `limit` would run.
+pub(crate) fn run_pending_jobs(rt: *mut c::JSRuntime, limit: usize) -> Result<usize> {
+    for executed in 0..limit {
+        if !run_pending_job(rt)? {
+            return Ok(executed);
+        }
+    }
+    if unsafe { c::JS_IsJobPending(rt) } != 0 {
+        bail!("pending job limit of {limit} reached");
+    }
+    Ok(limit)
+}
+
 impl Drop for Runtime {
     fn drop(&mut self) {
+        self.cancel_all_host_futures();
         unsafe {
+            c::JS_SetInterruptHandler(self.ptr.as_ptr(), None, core::ptr::null_mut());
+            c::JS_SetHostPromiseRejectionTracker(self.ptr.as_ptr(), None, core::ptr::null_mut());
             let data = c::JS_GetRuntimeOpaque(self.ptr.as_ptr());
+            // Unset first, for what the data drops, or the runtime frees, not to find it half
+            // dropped.
+            c::JS_SetRuntimeOpaque(self.ptr.as_ptr(), core::ptr::null_mut());
             let data = Box::from_raw(data as *mut RuntimeData);
             drop(data);
             c::JS_FreeRuntime(self.ptr.as_ptr());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destroy_callbacks_run_in_reverse_order() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for name in ["first", "panics", "second"] {
+            let log = log.clone();
+            ctx.on_destroy(move |ctx| {
+                assert!(!ctx.as_ptr().is_null());
+                if name == "panics" {
+                    panic!("teardown failed");
+                }
+                log.borrow_mut().push(name);
+            });
+        }
+        let value = ctx.new_object("");
+        drop(ctx);
+        // The value still holds the context.
+        assert!(log.borrow().is_empty());
+        drop(value);
+        assert_eq!(*log.borrow(), ["second", "first"]);
+    }
+
+    #[test]
+    fn runaway_recursion_is_an_error() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let err = ctx
+            .eval(&Code::Source("function f() { f(); } f()"))
+            .unwrap_err();
+        assert!(err.contains("stack overflow"), "{err}");
+
+        let depth = |max_stack_size| {
+            runtime.set_max_stack_size(max_stack_size);
+            ctx.eval(&Code::Source(
+                "var depth = 0; function g() { depth++; g(); } try { g() } catch {} depth",
+            ))
+            .unwrap()
+            .decode_u32()
+            .unwrap()
+        };
+        let shallow = depth(64 * 1024);
+        let deep = depth(512 * 1024);
+        assert!(shallow > 0 && deep > shallow, "{shallow} {deep}");
+    }
+
+    #[crate::host_call]
+    fn run(f: Value) -> Result<Value> {
+        f.call(&Value::undefined(), &[])
+    }
+
+    #[test]
+    fn interrupt_handler_stops_runaway_scripts() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.get_global_object()
+            .define_property_fn("run", run)
+            .unwrap();
+        // The number of polls to let through before interrupting.
+        let budget = Rc::new(Cell::new(u32::MAX));
+        let polls = budget.clone();
+        runtime.set_interrupt_handler(move || {
+            let left = polls.get();
+            polls.set(left.saturating_sub(1));
+            left == 0
+        });
+        let spin = ctx
+            .eval(&Code::Source("() => { while (true) {} }"))
+            .unwrap();
+
+        budget.set(3);
+        let err = ctx
+            .eval(&Code::Source("try { while (true) {} } catch { 'caught' }"))
+            .unwrap_err();
+        assert!(crate::is_interrupted(&err), "{err}");
+
+        budget.set(3);
+        let err = spin.call(&Value::undefined(), &[]).unwrap_err();
+        assert!(
+            err.downcast_ref::<crate::Interrupted>().is_some(),
+            "{err:?}"
+        );
+
+        // The same error thrown by a script is not taken for one.
+        let fake = ctx
+            .eval(&Code::Source(
+                "() => { throw Object.assign(new Error('interrupted'), { name: 'InternalError' }) }",
+            ))
+            .unwrap();
+        let err = fake.call(&Value::undefined(), &[]).unwrap_err();
+        assert!(
+            err.to_string().starts_with("InternalError: interrupted"),
+            "{err}"
+        );
+        assert!(!err.is::<crate::Interrupted>(), "{err:?}");
+
+        // Inside a host function called by the script.
+        budget.set(3);
+        let err = ctx
+            .eval(&Code::Source("run(() => { while (true) {} })"))
+            .unwrap_err();
+        assert!(err.contains("interrupted"), "{err}");
+
+        runtime.clear_interrupt_handler();
+        budget.set(0);
+        assert_eq!(
+            ctx.eval(&Code::Source(
+                "let n = 0; for (let i = 0; i < 100000; i++) n++; n"
+            ))
+            .unwrap()
+            .decode_u32()
+            .unwrap(),
+            100000
+        );
+
+        runtime.set_interrupt_handler({
+            let budget = budget.clone();
+            move || budget.get() == 0
+        });
+        drop(ctx);
+        drop(spin);
+        drop(runtime);
+        assert_eq!(Rc::strong_count(&budget), 1);
+    }
+
+    struct Tenant {
+        name: &'static str,
+        log: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl Drop for Tenant {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(self.name);
+        }
+    }
+
+    #[crate::host_call(with_context)]
+    fn tenant(ctx: Context, _this: Value) -> Option<&'static str> {
+        ctx.user_data::<Tenant>().map(|tenant| tenant.name)
+    }
+
+    #[test]
+    fn user_data_is_dropped_with_the_context() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        ctx.get_global_object()
+            .define_property_fn("tenant", tenant)
+            .unwrap();
+        let eval = |ctx: &Context| ctx.eval(&Code::Source("tenant()")).unwrap().to_string();
+        assert_eq!(eval(&ctx), "undefined");
+
+        let tenant = |name| Tenant {
+            name,
+            log: log.clone(),
+        };
+        ctx.set_user_data(tenant("old"));
+        ctx.set_user_data(tenant("alice"));
+        ctx.set_user_data(7u32);
+        assert_eq!(*log.borrow(), ["old"]);
+        assert_eq!(eval(&ctx), "alice");
+        assert_eq!(ctx.user_data::<u32>().as_deref(), Some(&7));
+        assert!(runtime.user_data::<u32>().is_none());
+
+        // Contexts do not share their data.
+        let other = runtime.new_context();
+        assert!(other.user_data::<Tenant>().is_none());
+
+        let kept = ctx.eval(&Code::Source("({})")).unwrap();
+        ctx.on_destroy({
+            let log = log.clone();
+            move |ctx| {
+                assert!(ctx.user_data::<Tenant>().is_some());
+                log.borrow_mut().push("on_destroy");
+            }
+        });
+        drop(ctx);
+        assert_eq!(*log.borrow(), ["old"]);
+        drop(kept);
+        assert_eq!(*log.borrow(), ["old", "on_destroy", "alice"]);
+    }
+
+    #[test]
+    fn eval_with_timeout_keeps_the_previous_handler() {
+        use core::time::Duration;
+
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let stop = Rc::new(Cell::new(false));
+        runtime.set_interrupt_handler({
+            let stop = stop.clone();
+            move || stop.get()
+        });
+
+        let start = Instant::now();
+        let err = ctx
+            .eval_with_timeout(&Code::Source("while (true) {}"), Duration::from_millis(50))
+            .unwrap_err();
+        let ran = start.elapsed();
+        assert!(
+            err.downcast_ref::<crate::Interrupted>().is_some(),
+            "{err:?}"
+        );
+        assert!(
+            err.to_string().contains("timed out after running for"),
+            "{err}"
+        );
+        assert!(ran >= Duration::from_millis(50), "{ran:?}");
+        assert!(ran < Duration::from_secs(5), "{ran:?}");
+
+        let fast = ctx
+            .eval_with_timeout(&Code::Source("1 + 1"), Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(fast.decode_u32().unwrap(), 2);
+        let err = ctx
+            .eval_with_timeout(
+                &Code::Source("throw new Error('boom')"),
+                Duration::from_secs(60),
+            )
+            .unwrap_err();
+        assert!(
+            err.downcast_ref::<crate::Interrupted>().is_none(),
+            "{err:?}"
+        );
+        let thrown = err.downcast_ref::<crate::JsError>().expect("a JsError");
+        assert_eq!(
+            (thrown.name.as_str(), thrown.message.as_str()),
+            ("Error", "boom")
+        );
+        assert!(err.to_string().starts_with("Error: boom\n"), "{err}");
+
+        // The handler set before is back in place, and still runs during timed evals.
+        stop.set(true);
+        let err = ctx.eval(&Code::Source("while (true) {}")).unwrap_err();
+        assert!(crate::is_interrupted(&err), "{err}");
+        let err = ctx
+            .eval_with_timeout(&Code::Source("while (true) {}"), Duration::from_secs(60))
+            .unwrap_err();
+        assert!(!err.to_string().contains("timed out"), "{err}");
+        assert!(err.is::<crate::Interrupted>(), "{err:?}");
+    }
+
+    #[test]
+    fn fuel_is_burnt_deterministically() {
+        const WORK: &str =
+            "() => { let sum = 0; for (let i = 0; i < 1000000; i++) sum += i % 7; return sum; }";
+        let burn = |fuel| {
+            let runtime = Runtime::new(&Default::default());
+            let ctx = runtime.new_context();
+            assert_eq!(runtime.fuel_remaining(), None);
+            let work = ctx.eval(&Code::Source(WORK)).unwrap();
+            runtime.set_fuel(fuel);
+            let sum = work.call(&Value::undefined(), &[]);
+            let burnt = fuel - runtime.fuel_remaining().unwrap();
+            // Still out of fuel until it is set again.
+            if runtime.fuel_remaining() == Some(0) {
+                assert!(work.call(&Value::undefined(), &[]).is_err());
+            }
+            (sum, burnt)
+        };
+
+        let (sum, burnt) = burn(1_000_000);
+        assert_eq!(sum.unwrap().decode_u32().unwrap(), 2999997);
+        assert!(burnt > 0);
+        let (sum, burnt_again) = burn(1_000_000);
+        assert_eq!(sum.unwrap().decode_u32().unwrap(), 2999997);
+        assert_eq!(burnt_again, burnt);
+
+        let (sum, burnt_short) = burn(burnt / 2);
+        let err = sum.unwrap_err();
+        assert!(err.downcast_ref::<crate::OutOfFuel>().is_some(), "{err:?}");
+        assert!(
+            err.downcast_ref::<crate::Interrupted>().is_some(),
+            "{err:?}"
+        );
+        assert_eq!(burnt_short, burnt / 2);
+    }
+
+    #[test]
+    fn pending_jobs_are_pumped_by_the_host() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let other = runtime.new_context();
+        assert!(!runtime.has_pending_jobs());
+        ctx.eval(&Code::Source(
+            "Promise.resolve().then(() => globalThis.done = true)",
+        ))
+        .unwrap();
+        let done = || {
+            ctx.get_global_object()
+                .get_property("done")
+                .unwrap()
+                .decode_bool()
+                .unwrap_or(false)
+        };
+        assert!(!done());
+        assert!(runtime.has_pending_jobs());
+        let mut executed = 0;
+        while !done() {
+            executed += runtime.execute_pending_jobs().unwrap();
+        }
+        assert!(executed >= 1);
+        assert!(!runtime.has_pending_jobs());
+        assert_eq!(runtime.execute_pending_jobs().unwrap(), 0);
+
+        other
+            .eval(&Code::Source(
+                "Promise.resolve().then(() => { throw new Error('in job'); })",
+            ))
+            .unwrap();
+        let err = runtime.execute_pending_jobs().unwrap_err();
+        assert!(format!("{err:#}").contains("in job"), "{err:#}");
+        let failed = err.downcast_ref::<crate::JobFailed>().unwrap();
+        assert!(failed.is_from(&other));
+        assert!(!failed.is_from(&ctx));
+    }
+
+    #[test]
+    fn unhandled_rejections_are_tracked() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        runtime.collect_unhandled_rejections();
+        ctx.eval(&Code::Source(
+            r#"
+            Promise.reject(new Error("boom"));
+            const late = Promise.reject(new Error("handled later"));
+            late.catch(() => {});
+            undefined
+            "#,
+        ))
+        .unwrap();
+        runtime.execute_pending_jobs().unwrap();
+        let unhandled = runtime.take_unhandled_rejections();
+        assert_eq!(unhandled.len(), 1, "{unhandled:?}");
+        assert!(unhandled[0].starts_with("Error: boom"), "{unhandled:?}");
+        assert!(unhandled[0].contains("[stack]"), "{unhandled:?}");
+        assert!(runtime.take_unhandled_rejections().is_empty());
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        runtime.set_promise_rejection_tracker({
+            let log = log.clone();
+            move |ctx, _promise, reason, is_handled| {
+                log.borrow_mut()
+                    .push((ctx.error_to_string(&reason), is_handled));
+            }
+        });
+        ctx.eval(&Code::Source(
+            "globalThis.p = Promise.reject('no'); p.then(null, () => {}); undefined",
+        ))
+        .unwrap();
+        assert_eq!(
+            *log.borrow(),
+            [("no".to_string(), false), ("no".to_string(), true)]
+        );
+        drop(ctx);
+        drop(runtime);
+        assert_eq!(Rc::strong_count(&log), 1);
+    }
+}
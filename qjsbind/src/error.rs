@@ -1,3 +1,8 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use core::fmt::{Debug, Display};
 
 pub use anyhow::{Error, Result};
@@ -63,6 +68,10 @@ impl<T> AnyError for T where T: Debug + Display + Send + Sync + 'static {}
 
 pub trait JsResultExt {
     type T;
+    /// Fails with an [`ExpectError`] naming what `value` is.
+    fn expect_type(self, value: &crate::Value, expected: &'static str) -> Result<Self::T>;
+
+    #[deprecated(note = "use `expect_type`, whose error can be downcast to `ExpectError`")]
     fn expect_js_value(self, value: &crate::Value, tobe: &str) -> Result<Self::T>;
 }
 
@@ -71,19 +80,702 @@ where
     E: AnyError,
 {
     type T = T;
+    fn expect_type(self, value: &crate::Value, expected: &'static str) -> Result<T> {
+        self.map_err(Error::msg)
+            .context(ExpectError::new(expected, value))
+    }
+
+    #[allow(deprecated)]
     fn expect_js_value(self, value: &crate::Value, tobe: &str) -> Result<T> {
         self.map_err(Error::msg)
-            .context(format!("expect {tobe}, got {}", value.get_name()))
+            .context(expect_js_value(value, tobe))
     }
 }
 
 impl<T> JsResultExt for Option<T> {
     type T = T;
+    fn expect_type(self, value: &crate::Value, expected: &'static str) -> Result<Self::T> {
+        self.context(ExpectError::new(expected, value))
+    }
+
+    #[allow(deprecated)]
     fn expect_js_value(self, value: &crate::Value, tobe: &str) -> Result<Self::T> {
-        self.context(format!("expect {tobe}, got {}", value.get_name()))
+        self.ok_or_else(|| expect_js_value(value, tobe))
     }
 }
 
+#[deprecated(note = "use `expect_err`, whose error can be downcast to `ExpectError`")]
 pub fn expect_js_value(value: &crate::Value, tobe: &str) -> Error {
-    Error::msg(format!("expect {tobe}, got {}", value.get_name()))
+    Error::msg(format!("expected {tobe}, got {}", value.describe_type()))
+}
+
+/// A value of the wrong type, displayed as `expected TypeRegistry, got object (Map)`.
+///
+/// Find it with `err.downcast_ref::<ExpectError>()` to tell type mismatches apart from other
+/// conversion errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectError {
+    /// What was wanted, a type name or a short description like `bytes-like object`.
+    pub expected: &'static str,
+    /// What was passed, see [`Value::describe_type`](crate::Value::describe_type). `None` if
+    /// there was no value at all.
+    pub got: Option<String>,
+}
+
+impl ExpectError {
+    pub fn new(expected: &'static str, value: &crate::Value) -> Self {
+        Self {
+            expected,
+            got: Some(value.describe_type()),
+        }
+    }
+
+    /// No value where one of `expected` was wanted, like a missing tuple element.
+    pub fn missing(expected: &'static str) -> Self {
+        Self {
+            expected,
+            got: None,
+        }
+    }
+}
+
+impl Display for ExpectError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "expected {}", self.expected)?;
+        if let Some(got) = &self.got {
+            write!(f, ", got {got}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A `NaN` or infinite number where an integer, or a finite number, was expected, displayed as
+/// `expected u32, got NaN`. Find it with `err.downcast_ref::<NonFiniteNumber>()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NonFiniteNumber {
+    pub expected: &'static str,
+    pub value: f64,
+}
+
+impl Display for NonFiniteNumber {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let got = if self.value.is_nan() {
+            "NaN"
+        } else if self.value > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        };
+        write!(f, "expected {}, got {got}", self.expected)
+    }
+}
+
+/// The error of a value that is not of the `expected` type.
+pub fn expect_err(expected: &'static str, value: &crate::Value) -> Error {
+    Error::msg(ExpectError::new(expected, value))
+}
+
+/// The context of an error caused by an allocation past the memory limit of the runtime, see
+/// `Runtime::set_memory_limit`. Find it with `err.downcast_ref::<OutOfMemory>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfMemory;
+
+impl Display for OutOfMemory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("out of memory")
+    }
+}
+
+/// Whether `error` is the engine running out of memory rather than an exception of the script,
+/// that is whether it has an [`OutOfMemory`] context. For the errors of `Context::eval`, see
+/// `Context::is_out_of_memory`.
+pub fn is_out_of_memory(error: &Error) -> bool {
+    error.downcast_ref::<OutOfMemory>().is_some()
+}
+
+/// The context of an error caused by the interrupt handler of the runtime stopping the script,
+/// see `Runtime::set_interrupt_handler`. Find it with `err.downcast_ref::<Interrupted>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+/// The context of an error caused by the script running out of the fuel given by
+/// `Runtime::set_fuel`. The error has an [`Interrupted`] context too. Find it with
+/// `err.downcast_ref::<OutOfFuel>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfFuel;
+
+impl Display for OutOfFuel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("out of fuel")
+    }
+}
+
+/// The error of an operation on a native object that is already borrowed, typically by a method
+/// that called back into the script which called the object again. Find it with
+/// `err.downcast_ref::<Reentrancy>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reentrancy {
+    /// The Rust type of the borrowed object.
+    pub type_name: &'static str,
+    /// What was attempted on it.
+    pub operation: &'static str,
+}
+
+impl Display for Reentrancy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} re-entered {} while it was in use",
+            self.operation, self.type_name
+        )
+    }
+}
+
+/// The context of an error thrown by a job run by `Runtime::execute_pending_jobs`, telling
+/// which context the job belongs to. Find it with `err.downcast_ref::<JobFailed>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobFailed {
+    /// The address of the context, only compared, never dereferenced.
+    context: usize,
+}
+
+impl JobFailed {
+    pub(crate) fn new(ctx: *mut crate::c::JSContext) -> Self {
+        Self {
+            context: ctx as usize,
+        }
+    }
+
+    /// Whether the job ran in `ctx`.
+    pub fn is_from(&self, ctx: &crate::Context) -> bool {
+        self.context == ctx.as_ptr() as usize
+    }
+}
+
+impl Display for JobFailed {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("pending job failed")
+    }
+}
+
+/// Whether the error of `Context::eval` is the script being interrupted, by the interrupt handler
+/// or the gas and time limits of the runtime, rather than an exception of the script.
+///
+/// Scripts can not catch the interruption, but can throw an `InternalError: interrupted` of their
+/// own, so this is meant for reporting, not to make security decisions.
+pub fn is_interrupted(error: &str) -> bool {
+    error.lines().next() == Some("InternalError: interrupted")
+}
+
+/// An error carrying the name of a JS `Error` subclass.
+///
+/// When converted with `ErrorValueExt::to_js_error_value`, the error is constructed with the
+/// global constructor of the same name (e.g. `TypeError`), and `properties` are set on it. It is
+/// also what `ErrorValueExt::from_js_error` produces from a caught JS error.
+///
+/// ```ignore
+/// return Err(JsError::new("TypeError", "unknown type Foo")
+///     .with_code("ERR_UNKNOWN_TYPE")
+///     .with_property("typeName", "Foo")
+///     .into());
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsError {
+    pub name: String,
+    pub message: String,
+    pub stack: Option<String>,
+    pub properties: Vec<(String, ErrorProperty)>,
+}
+
+/// The value of an extra property of a [`JsError`].
+#[derive(Clone)]
+pub struct ErrorProperty(Arc<dyn crate::ToJsValue + Send + Sync>);
+
+impl ErrorProperty {
+    pub fn new(value: impl crate::ToJsValue + Send + Sync + 'static) -> Self {
+        Self(Arc::new(value))
+    }
+
+    pub fn to_js_value(&self, ctx: &crate::Context) -> Result<crate::Value> {
+        self.0.to_js_value(ctx)
+    }
+}
+
+impl Debug for ErrorProperty {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("ErrorProperty(..)")
+    }
+}
+
+impl JsError {
+    pub fn new(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            message: message.into(),
+            stack: None,
+            properties: Vec::new(),
+        }
+    }
+
+    /// A plain `Error` with extra properties.
+    pub fn with_properties<V>(message: impl Into<String>, props: Vec<(String, V)>) -> Self
+    where
+        V: crate::ToJsValue + Send + Sync + 'static,
+    {
+        let mut err = Self::new("Error", message);
+        for (key, value) in props {
+            err = err.with_property(key, value);
+        }
+        err
+    }
+
+    /// Set `key` on the thrown error, replacing a property set before under the same key.
+    pub fn with_property(
+        mut self,
+        key: impl Into<String>,
+        value: impl crate::ToJsValue + Send + Sync + 'static,
+    ) -> Self {
+        let key = key.into();
+        self.properties.retain(|(k, _)| *k != key);
+        self.properties.push((key, ErrorProperty::new(value)));
+        self
+    }
+
+    /// Set the `code` property, e.g. `ERR_INVALID_KEY_LENGTH`.
+    pub fn with_code(self, code: &'static str) -> Self {
+        self.with_property("code", code)
+    }
+
+    pub fn type_error(message: impl Into<String>) -> Self {
+        Self::new("TypeError", message)
+    }
+
+    pub fn range_error(message: impl Into<String>) -> Self {
+        Self::new("RangeError", message)
+    }
+}
+
+impl Display for JsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for JsError {}
+
+pub trait ErrorValueExt: Sized {
+    /// Convert the error to a JS `Error` instance without throwing it.
+    ///
+    /// The source chain is attached as a `rustSource` array of messages.
+    fn to_js_error_value(&self, ctx: &crate::Context) -> crate::Value;
+
+    /// Extract name, message and stack from a caught JS value.
+    fn from_js_error(value: &crate::Value) -> Self;
+}
+
+impl ErrorValueExt for Error {
+    fn to_js_error_value(&self, ctx: &crate::Context) -> crate::Value {
+        let js_error = self.downcast_ref::<JsError>();
+        let (name, message) = match js_error {
+            Some(err) => (err.name.as_str(), err.message.clone()),
+            None => ("Error", self.to_string()),
+        };
+        let js_message = crate::Value::from_str(ctx, &message);
+        let error = ctx
+            .intrinsic(name)
+            .and_then(|ctor| ctor.construct(&[js_message.clone()]))
+            .unwrap_or_else(|_| {
+                let error =
+                    unsafe { crate::Value::new_moved(ctx, crate::c::JS_NewError(ctx.as_ptr())) };
+                _ = error.set_property("name", &crate::Value::from_str(ctx, name));
+                _ = error.set_property("message", &js_message);
+                error
+            });
+        let chain = crate::Value::new_array(ctx);
+        for cause in self.chain() {
+            _ = chain.array_push(&crate::Value::from_str(ctx, &cause.to_string()));
+        }
+        _ = error.set_property("rustSource", &chain);
+        for (key, value) in js_error.iter().flat_map(|err| &err.properties) {
+            match value.to_js_value(ctx) {
+                Ok(value) => _ = error.set_property(key, &value),
+                Err(err) => log::warn!("failed to convert error property {key}: {err:?}"),
+            }
+        }
+        error
+    }
+
+    fn from_js_error(value: &crate::Value) -> Self {
+        if !value.is_error() {
+            return Error::msg(JsError::new("Error", value.to_string()));
+        }
+        let get_str = |key: &str| {
+            value
+                .get_property(key)
+                .ok()
+                .filter(|v| !v.is_undefined())
+                .map(|v| v.to_string())
+        };
+        Error::msg(JsError {
+            name: get_str("name").unwrap_or_else(|| "Error".into()),
+            message: get_str("message").unwrap_or_default(),
+            stack: get_str("stack"),
+            properties: Vec::new(),
+        })
+    }
+}
+
+/// The index of the element of a sequence that failed to convert, attached as context to its
+/// error by the `Vec` conversion. The indices of directly nested sequences are merged, as in
+/// `[2][0]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementPath(String);
+
+impl ElementPath {
+    pub fn path(&self) -> &str {
+        &self.0
+    }
+
+    /// Attach the index of the failing element to `err`.
+    pub(crate) fn wrap(err: Error, index: usize) -> Error {
+        let path = match Self::outermost(&err) {
+            Some(inner) => format!("[{index}]{}", inner.0),
+            None => format!("[{index}]"),
+        };
+        err.context(Self(path))
+    }
+
+    /// The path of `err` if it is the outermost context of the error.
+    fn outermost(err: &Error) -> Option<&Self> {
+        let path = err.downcast_ref::<Self>()?;
+        (err.to_string() == path.to_string()).then_some(path)
+    }
+}
+
+impl Display for ElementPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "failed to decode element {}", self.0)
+    }
+}
+
+/// A list of errors keyed by the path of the value that failed to convert.
+///
+/// Produced by `#[derive(FromJsValue)]` with `#[qjs(accumulate_errors)]`. Nested lists are
+/// flattened with dotted paths, and elements of sequences get their index, as in
+/// `headers[2].name`.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorList {
+    errors: Vec<(String, String)>,
+}
+
+impl ErrorList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, path: &str, err: Error) {
+        let element = ElementPath::outermost(&err);
+        let path = match element {
+            Some(element) => format!("{path}{}", element.0),
+            None => path.into(),
+        };
+        if let Some(nested) = err.downcast_ref::<ErrorList>() {
+            for (sub_path, message) in &nested.errors {
+                let sep = if sub_path.starts_with('[') { "" } else { "." };
+                self.errors
+                    .push((format!("{path}{sep}{sub_path}"), message.clone()));
+            }
+            return;
+        }
+        let message = match element {
+            // The element path is part of the key, the message is the rest of the chain, after
+            // the context of each merged index.
+            Some(element) => {
+                let mut message = String::new();
+                for cause in err.chain().skip(element.0.matches('[').count()) {
+                    if !message.is_empty() {
+                        message.push_str(": ");
+                    }
+                    message.push_str(&cause.to_string());
+                }
+                message
+            }
+            None => format!("{err:#}"),
+        };
+        self.errors.push((path, message));
+    }
+
+    /// Record the error of `result` under `path`, if any.
+    pub fn collect<T>(&mut self, path: &str, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.push(path, err);
+                None
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.errors.iter().map(|(p, m)| (p.as_str(), m.as_str()))
+    }
+
+    pub fn into_result(self) -> Result<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::msg(self))
+        }
+    }
+}
+
+impl Display for ErrorList {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, (path, message)) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{path}: {message}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{self as js, FromJsValue};
+
+    #[test]
+    fn out_of_memory_is_told_apart() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let grow = "const chunks = []; for (;;) chunks.push(new Array(100000).fill(1));";
+        let grow_fn = ctx
+            .eval(&js::Code::Source(&alloc::format!("() => {{ {grow} }}")))
+            .unwrap();
+        // Set after the context is created.
+        runtime.set_memory_limit(16 << 20);
+
+        let err = ctx.eval(&js::Code::Source(grow)).unwrap_err();
+        assert!(ctx.is_out_of_memory(&err), "{err}");
+        let err = grow_fn.call(&js::Value::undefined(), &[]).unwrap_err();
+        assert!(is_out_of_memory(&err), "{err:?}");
+
+        // The same error thrown by a script is not taken for one.
+        let fake = "throw Object.assign(new Error('out of memory'), { name: 'InternalError' })";
+        let err = ctx.eval(&js::Code::Source(fake)).unwrap_err();
+        assert!(err.starts_with("InternalError: out of memory"), "{err}");
+        assert!(!ctx.is_out_of_memory(&err));
+        let fake_fn = ctx
+            .eval(&js::Code::Source(&alloc::format!("() => {{ {fake} }}")))
+            .unwrap();
+        let err = fake_fn.call(&js::Value::undefined(), &[]).unwrap_err();
+        assert!(!is_out_of_memory(&err), "{err:?}");
+
+        runtime.set_memory_limit(0);
+        let len = ctx
+            .eval(&js::Code::Source("new Array(1000000).fill(1).length"))
+            .unwrap();
+        assert_eq!(len.decode_u32().unwrap(), 1000000);
+    }
+
+    #[test]
+    fn expect_errors_describe_the_value() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src)).unwrap();
+        let err = |src: &str| js::JsUint8Array::from_js_value(eval(src)).unwrap_err();
+
+        assert_eq!(
+            err("new Map()").to_string(),
+            "expected Uint8Array, got object (Map)"
+        );
+        assert_eq!(
+            err("({})").to_string(),
+            "expected Uint8Array, got object (Object)"
+        );
+        assert_eq!(
+            err("[]").to_string(),
+            "expected Uint8Array, got object (Array)"
+        );
+        assert_eq!(err("null").to_string(), "expected Uint8Array, got null");
+        assert_eq!(err("1").to_string(), "expected Uint8Array, got number");
+
+        // Describing the value runs no getters or proxy traps.
+        eval("globalThis.calls = 0");
+        assert_eq!(
+            err("new Proxy({}, { get() { calls++ } })").to_string(),
+            "expected Uint8Array, got object (Proxy)"
+        );
+        assert_eq!(
+            err("Object.create({ get constructor() { calls++; return Map } })").to_string(),
+            "expected Uint8Array, got object (Object)"
+        );
+        assert_eq!(eval("calls").decode_u32().unwrap(), 0);
+
+        let err = u32::from_js_value(eval("-1")).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ExpectError>(),
+            Some(&ExpectError {
+                expected: "u32",
+                got: Some("number".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn errors_round_trip_through_js_values() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src)).unwrap();
+        let describe = eval(
+            "(e) => [e instanceof TypeError, e.name, e.message, e.rustSource.join(' | ')].join()",
+        );
+
+        let err = Error::msg(JsError::type_error("bad key")).context("import failed");
+        let value = err.to_js_error_value(&ctx);
+        let described = describe
+            .call(&js::Value::undefined(), &[value.clone()])
+            .unwrap();
+        assert_eq!(
+            described.to_string(),
+            "true,TypeError,bad key,import failed | TypeError: bad key"
+        );
+        let back = Error::from_js_error(&value);
+        let back = back.downcast_ref::<JsError>().unwrap();
+        assert_eq!(
+            (back.name.as_str(), back.message.as_str()),
+            ("TypeError", "bad key")
+        );
+
+        // A plain Rust error becomes an `Error` with its message.
+        let value = Error::msg("plain failure").to_js_error_value(&ctx);
+        assert!(value.is_error());
+        let back = Error::from_js_error(&value);
+        let back = back.downcast_ref::<JsError>().unwrap();
+        assert_eq!(
+            (back.name.as_str(), back.message.as_str()),
+            ("Error", "plain failure")
+        );
+
+        // The constructors the context was created with, not the globals of the script.
+        eval("globalThis.Real = TypeError; TypeError = function () { globalThis.hijacked = true }");
+        let value = Error::msg(JsError::type_error("bad key")).to_js_error_value(&ctx);
+        let check = eval("(e) => e instanceof Real && !globalThis.hijacked");
+        let check = check.call(&js::Value::undefined(), &[value]).unwrap();
+        assert!(check.decode_bool().unwrap());
+
+        let caught = Error::from_js_error(&eval(
+            "(() => { try { null.x } catch (e) { return e; } })()",
+        ));
+        let caught = caught.downcast_ref::<JsError>().unwrap();
+        assert_eq!(caught.name, "TypeError");
+        assert!(caught.stack.is_some());
+        let thrown = Error::from_js_error(&eval("'not an error'"));
+        assert_eq!(thrown.to_string(), "Error: not an error");
+    }
+
+    #[crate::host_call(with_context)]
+    fn refuse(ctx: js::Context, _this: js::Value) -> Result<js::Value> {
+        ctx.spawn_host_future(async { Err::<(), _>(Error::msg(JsError::range_error("too far"))) })
+    }
+
+    #[test]
+    fn rejected_host_futures_reject_with_js_errors() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.get_global_object()
+            .define_property_fn("refuse", refuse)
+            .unwrap();
+        ctx.eval(&js::Code::Source(
+            "refuse().catch((e) => globalThis.caught = `${e instanceof RangeError} ${e.message}`)",
+        ))
+        .unwrap();
+        let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+        runtime.poll_host_futures(&mut cx);
+        runtime.execute_pending_jobs().unwrap();
+        let caught = ctx.eval(&js::Code::Source("caught")).unwrap();
+        assert_eq!(caught.to_string(), "true too far");
+    }
+    #[derive(Debug, crate::FromJsValue)]
+    struct Header {
+        name: String,
+    }
+
+    #[derive(Debug, crate::FromJsValue)]
+    #[qjs(accumulate_errors)]
+    struct Request {
+        method: String,
+        url: String,
+        headers: Vec<Vec<String>>,
+        trailers: Vec<Header>,
+        body: crate::Bytes,
+    }
+
+    #[test]
+    fn field_errors_are_accumulated() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let request = ctx
+            .eval(&js::Code::Source(
+                r#"({
+                    method: 1,
+                    url: "https://example.com",
+                    headers: [["a", "b"], ["c", "d"], ["e", 5]],
+                    trailers: [{ name: "x" }],
+                    body: 7,
+                })"#,
+            ))
+            .unwrap();
+        let err = Request::from_js_value(request).unwrap_err();
+        let list = err.downcast_ref::<ErrorList>().expect("an error list");
+        let paths: Vec<&str> = list.iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, ["method", "headers[2][1]", "body"]);
+        assert_eq!(
+            err.to_string(),
+            "method: expected string, got number; \
+             headers[2][1]: expected string, got number; \
+             body: expected bytes-like object, got number"
+        );
+
+        let request = ctx
+            .eval(&js::Code::Source(
+                r#"({ method: "GET", url: "/", headers: [], trailers: [{}, { name: [] }], body: "" })"#,
+            ))
+            .unwrap();
+        let err = Request::from_js_value(request).unwrap_err();
+        let list = err.downcast_ref::<ErrorList>().expect("an error list");
+        let paths: Vec<&str> = list.iter().map(|(path, _)| path).collect();
+        assert_eq!(paths, ["trailers[0]"]);
+    }
+
+    #[test]
+    fn element_errors_keep_their_cause() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let value = ctx.eval(&js::Code::Source("[[1], [2, 'x']]")).unwrap();
+        let err = Vec::<Vec<u32>>::from_js_value(value).unwrap_err();
+        assert_eq!(err.to_string(), "failed to decode element [1][1]");
+        assert_eq!(
+            err.downcast_ref::<ElementPath>().map(ElementPath::path),
+            Some("[1][1]")
+        );
+        assert!(err.root_cause().to_string().contains("u32"), "{err:#}");
+    }
 }
@@ -0,0 +1,277 @@
+//! Human readable reports of script errors.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use anyhow::Context as _;
+use core::cell::RefCell;
+use core::fmt::{self, Display};
+
+use crate::{self as js, Error, JsError, Result};
+
+/// The sources kept by `Context::retain_sources`, with their first line, by filename. Only
+/// present once enabled.
+#[derive(Default)]
+struct RetainedSources {
+    sources: RefCell<BTreeMap<String, (u32, String)>>,
+}
+
+/// A frame of a JS stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function: Option<String>,
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+/// A multi-line report of a script error: the message, each stack frame, and a source excerpt
+/// around the location of the top frame.
+///
+/// ```text
+/// Error: boom
+///     at f (<eval>:2:9)
+///     at <eval>:4:1
+///
+///   1 | function f() {
+/// > 2 |   throw new Error("boom");
+///     |         ^
+///   3 | }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub message: String,
+    pub frames: Vec<StackFrame>,
+    excerpt: Option<String>,
+}
+
+impl ErrorReport {
+    /// Build a report from an error, looking up the source of the top frame's file with
+    /// `source_lookup`. Sources retained by the context are used if the lookup returns `None`.
+    pub fn from_error(
+        ctx: &js::Context,
+        err: &Error,
+        source_lookup: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let (message, stack) = match err.downcast_ref::<JsError>() {
+            Some(err) => (
+                alloc::format!("{}: {}", err.name, err.message),
+                err.stack.clone().unwrap_or_default(),
+            ),
+            None => split_exception(&err.root_cause().to_string()),
+        };
+        Self::with_sources(message, &stack, |file| {
+            source_lookup(file)
+                .map(|source| (1, source))
+                .or_else(|| ctx.retained_source(file))
+        })
+    }
+
+    /// Build a report from an error message and a JS `stack` string.
+    pub fn new(
+        message: String,
+        stack: &str,
+        source_lookup: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        Self::with_sources(message, stack, |file| {
+            source_lookup(file).map(|source| (1, source))
+        })
+    }
+
+    /// `new` with sources that start at a given line of their file.
+    fn with_sources(
+        message: String,
+        stack: &str,
+        source_lookup: impl Fn(&str) -> Option<(u32, String)>,
+    ) -> Self {
+        let frames: Vec<_> = stack.lines().filter_map(parse_frame).collect();
+        let excerpt = frames.first().and_then(|frame| {
+            let (first_line, source) = source_lookup(&frame.file)?;
+            excerpt(&source, first_line, frame.line, frame.column)
+        });
+        Self {
+            message,
+            frames,
+            excerpt,
+        }
+    }
+}
+
+impl Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.message)?;
+        for frame in &self.frames {
+            write!(f, "    at ")?;
+            let location = match frame.column {
+                Some(column) => alloc::format!("{}:{}:{column}", frame.file, frame.line),
+                None => alloc::format!("{}:{}", frame.file, frame.line),
+            };
+            match &frame.function {
+                Some(function) => writeln!(f, "{function} ({location})")?,
+                None => writeln!(f, "{location}")?,
+            }
+        }
+        if let Some(excerpt) = &self.excerpt {
+            write!(f, "\n{excerpt}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Split the text of a caught exception, as produced by `Context::get_exception_str` or
+/// `Context::eval`, into its message and stack.
+fn split_exception(text: &str) -> (String, String) {
+    let text = text
+        .strip_prefix("Error::JsException(")
+        .and_then(|text| text.strip_suffix(')'))
+        .unwrap_or(text);
+    let mut message = Vec::new();
+    let mut stack = Vec::new();
+    for line in text.lines() {
+        if line.trim_start().starts_with("at ") {
+            stack.push(line);
+        } else if stack.is_empty() && line != "[stack]" {
+            message.push(line);
+        }
+    }
+    (message.join("\n"), stack.join("\n"))
+}
+
+fn parse_frame(line: &str) -> Option<StackFrame> {
+    let frame = line.trim().strip_prefix("at ")?;
+    let (function, location) = match frame.strip_suffix(')').and_then(|f| f.rsplit_once(" (")) {
+        Some((function, location)) => (Some(function.to_string()), location),
+        None => (None, frame),
+    };
+    let mut parts = location.rsplitn(3, ':');
+    let last: u32 = parts.next()?.parse().ok()?;
+    let (file, line, column) = match (parts.next(), parts.next()) {
+        (Some(line), Some(file)) => match line.parse() {
+            Ok(line) => (file, line, Some(last)),
+            Err(_) => (location.rsplit_once(':')?.0, last, None),
+        },
+        (Some(file), None) => (file, last, None),
+        _ => return None,
+    };
+    Some(StackFrame {
+        function,
+        file: file.to_string(),
+        line,
+        column,
+    })
+}
+
+fn excerpt(source: &str, first_line: u32, line: u32, column: Option<u32>) -> Option<String> {
+    let first_line = first_line.max(1) as usize;
+    let index = (line as usize).checked_sub(first_line)?;
+    let lines: Vec<&str> = source.lines().collect();
+    lines.get(index)?;
+    let first = index.saturating_sub(1);
+    let last = (index + 1).min(lines.len() - 1);
+    let width = (last + first_line).to_string().len();
+    let mut out = String::new();
+    for (i, text) in lines.iter().enumerate().take(last + 1).skip(first) {
+        let marker = if i == index { '>' } else { ' ' };
+        out.push_str(&alloc::format!(
+            "{marker} {:>width$} | {text}\n",
+            i + first_line
+        ));
+        if let (true, Some(column)) = (i == index, column) {
+            let pad = " ".repeat(column.saturating_sub(1) as usize);
+            out.push_str(&alloc::format!("  {:width$} | {pad}^\n", ""));
+        }
+    }
+    Some(out)
+}
+
+impl js::Context {
+    /// Keep the source of every script evaluated with `Code::Source` from now on, so that
+    /// `ErrorReport::from_error` can show excerpts without a lookup function.
+    ///
+    /// Sources are keyed by the filename reported in stack traces, which is `<eval>` unless set
+    /// with `EvalOptions::filename`, so only the most recent script of each name is kept.
+    pub fn retain_sources(&self) -> Result<()> {
+        self.state::<RetainedSources>()
+            .context("no retained sources for a context without teardown support")?;
+        Ok(())
+    }
+
+    /// Keep `source`, which starts at `first_line` of `filename`, if `retain_sources` was called.
+    pub(crate) fn retain_source(&self, filename: &str, first_line: u32, source: &str) {
+        if let Some(retained) = self.user_data::<RetainedSources>() {
+            retained
+                .sources
+                .borrow_mut()
+                .insert(filename.into(), (first_line, source.into()));
+        }
+    }
+
+    /// Drop the sources kept so far. Sources evaluated from now on are still kept.
+    pub(crate) fn clear_retained_sources(&self) {
+        if let Some(retained) = self.user_data::<RetainedSources>() {
+            retained.sources.borrow_mut().clear();
+        }
+    }
+
+    fn retained_source(&self, filename: &str) -> Option<(u32, String)> {
+        let retained = self.user_data::<RetainedSources>()?;
+        let sources = retained.sources.borrow();
+        sources.get(filename).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_report() {
+        let source = "function f() {\n  throw new Error(\"boom\");\n}\nf();\n";
+        let stack = "    at f (<eval>:2:9)\n    at <eval>:4:1\n    at native_fn (native)";
+        let report = ErrorReport::new("Error: boom".into(), stack, |file| {
+            (file == "<eval>").then(|| source.to_string())
+        });
+        assert_eq!(
+            report.to_string(),
+            r#"Error: boom
+    at f (<eval>:2:9)
+    at <eval>:4:1
+
+  1 | function f() {
+> 2 |   throw new Error("boom");
+    |         ^
+  3 | }
+"#
+        );
+    }
+
+    #[test]
+    fn splits_exception_text() {
+        let (message, stack) =
+            split_exception("Error::JsException(Error: boom\n[stack]\n    at <eval>:4:1\n)");
+        assert_eq!(message, "Error: boom");
+        assert_eq!(stack, "    at <eval>:4:1");
+    }
+
+    #[test]
+    fn retains_sources_once_enabled() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src)).unwrap();
+        eval("1 + 1");
+        assert_eq!(ctx.retained_source("<eval>"), None);
+
+        ctx.retain_sources().unwrap();
+        eval("2 + 2");
+        assert_eq!(ctx.retained_source("<eval>"), Some((1, "2 + 2".into())));
+
+        let script = "function f() {\n  throw new Error('boom');\n}\nf();";
+        let options = js::EvalOptions::at("widget.js", 40);
+        let err = ctx
+            .eval_with_options(&js::Code::Source(script), &options)
+            .unwrap_err();
+        assert_eq!(ctx.retained_source("widget.js"), Some((40, script.into())));
+        let report = ErrorReport::from_error(&ctx, &anyhow::anyhow!("{err}"), |_| None);
+        assert!(report.to_string().contains("> 41 |   throw"), "{report}");
+    }
+}
@@ -1,15 +1,159 @@
 use core::ffi::CStr;
 
+use alloc::ffi::CString;
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
-use crate::{self as js, c, Value};
+use crate::{self as js, c, ErrorValueExt as _, SourceMap, Value};
 
 pub enum Code<'a> {
     Source(&'a str),
     Bytecode(&'a [u8]),
 }
 
+/// Owned [`Code`], returned by hooks like the module loader of `Runtime::set_module_loader`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsCode {
+    Source(String),
+    Bytecode(Vec<u8>),
+}
+
+impl JsCode {
+    pub fn as_code(&self) -> Code<'_> {
+        match self {
+            JsCode::Source(src) => Code::Source(src),
+            JsCode::Bytecode(bytes) => Code::Bytecode(bytes),
+        }
+    }
+}
+
+/// The default for `EvalOptions::job_limit`.
+pub const DEFAULT_JOB_LIMIT: usize = 10_000;
+
+/// Options for `Context::eval_with_options`.
+#[derive(Debug, Clone)]
+pub struct EvalOptions {
+    /// After the script returns, run pending jobs (promise reactions) until there are none left,
+    /// so that `.then` chains started by the script have settled when eval returns.
+    ///
+    /// Jobs run under the same interrupt handler as the script: they consume the gas of the
+    /// runtime and are subject to its time limit, and an interrupted job fails the eval.
+    pub auto_drain_jobs: bool,
+    /// The most jobs to run when draining. A script that keeps queueing jobs fails with an error
+    /// once the limit is reached, leaving the remaining jobs pending.
+    pub job_limit: usize,
+    /// The filename reported in errors and stack traces instead of `<eval>`.
+    pub filename: Option<String>,
+    /// The line number, starting at 1, of the first line of the script, for scripts cut out of
+    /// a larger file.
+    pub line: u32,
+    /// Evaluate the script in strict mode, as if it started with `"use strict"`.
+    pub strict: bool,
+    /// Record the lines the script executes under its filename, see `Context::take_coverage`.
+    /// Only applies to `Code::Source`, which is instrumented for it.
+    pub coverage: bool,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            auto_drain_jobs: false,
+            job_limit: DEFAULT_JOB_LIMIT,
+            filename: None,
+            line: 1,
+            strict: false,
+            coverage: false,
+        }
+    }
+}
+
+impl EvalOptions {
+    pub fn drain() -> Self {
+        Self {
+            auto_drain_jobs: true,
+            ..Default::default()
+        }
+    }
+
+    /// Report errors of the script at `filename`, starting at `line`.
+    ///
+    /// The location only applies to `Code::Source`, bytecode keeps the one it was compiled with.
+    pub fn at(filename: impl Into<String>, line: u32) -> Self {
+        Self {
+            filename: Some(filename.into()),
+            line,
+            ..Default::default()
+        }
+    }
+}
+
+/// Evaluate `script`, then drain pending jobs if `options.auto_drain_jobs` is set. The first job
+/// that throws fails the eval with its exception.
+pub fn eval_with_options(
+    ctx: &js::Context,
+    script: &Code,
+    options: &EvalOptions,
+) -> Result<Value, String> {
+    let value = eval_at(ctx, script, options)?;
+    if options.auto_drain_jobs {
+        drain_jobs(ctx, options.job_limit)?;
+    }
+    Ok(value)
+}
+
+/// Run the pending jobs of the runtime of `ctx`, at most `limit` of them, failing with the
+/// exception of the first job that throws.
+pub(crate) fn drain_jobs(ctx: &js::Context, limit: usize) -> Result<usize, String> {
+    let rt = unsafe { c::JS_GetRuntime(ctx.as_ptr()) };
+    crate::engine::run_pending_jobs(rt, limit).map_err(|err| err.root_cause().to_string())
+}
+
 pub fn eval(ctx: &js::Context, script: &Code) -> Result<Value, String> {
+    eval_at(ctx, script, &EvalOptions::default())
+}
+
+/// `eval` at the location and in the mode of `options`.
+fn eval_at(ctx: &js::Context, script: &Code, options: &EvalOptions) -> Result<Value, String> {
+    eval_failing(ctx, script, options).map_err(|failure| failure.message)
+}
+
+/// `eval`, failing with the same error as the other calls into the engine: it has the
+/// [`JsError`](js::JsError) of a thrown error and the contexts of `Context::get_exception_error`.
+pub(crate) fn eval_checked(ctx: &js::Context, script: &Code) -> js::Result<Value> {
+    eval_failing(ctx, script, &EvalOptions::default()).map_err(|failure| {
+        let error = match &failure.thrown {
+            Some(thrown) => js::Error::from_js_error(thrown).context(failure.message),
+            None => anyhow::anyhow!("{}", failure.message),
+        };
+        ctx.classify_error(error)
+    })
+}
+
+/// A failed eval, with the value it threw if it threw one.
+struct Failure {
+    message: String,
+    thrown: Option<Value>,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure {
+            message,
+            thrown: None,
+        }
+    }
+}
+
+fn eval_failing(ctx: &js::Context, script: &Code, options: &EvalOptions) -> Result<Value, Failure> {
+    #[cfg(feature = "stable-hash")]
+    crate::receipt::record_eval(ctx, script);
+    let result = eval_code(ctx, script, options);
+    #[cfg(feature = "stable-hash")]
+    crate::receipt::record_result(ctx, result.as_ref().ok());
+    result
+}
+
+fn eval_code(ctx: &js::Context, script: &Code, options: &EvalOptions) -> Result<Value, Failure> {
     struct IO {
         output: Result<Value, String>,
     }
@@ -45,33 +189,149 @@ pub fn eval(ctx: &js::Context, script: &Code) -> Result<Value, String> {
         read_args: None,
     };
 
-    let code = match script {
-        Code::Source(src) => c::code_t {
-            code: src.as_ptr() as _,
-            code_len: src.as_bytes().len() as _,
-            is_bytecode: 0,
-        },
-        Code::Bytecode(bytes) => c::code_t {
-            code: bytes.as_ptr() as _,
-            code_len: bytes.len() as _,
-            is_bytecode: 1,
+    let filename = options.filename.as_deref().unwrap_or("<eval>");
+    let c_filename =
+        CString::new(filename).map_err(|_| "filename contains a NUL byte".to_string())?;
+    let instrumented: String;
+    let bytes = match script {
+        Code::Source(src) => {
+            ctx.retain_source(filename, options.line, src);
+            if options.coverage {
+                instrumented = crate::coverage::instrument_source(ctx, filename, options.line, src)
+                    .map_err(|err| format!("{err:#}"))?;
+                instrumented.as_bytes()
+            } else {
+                src.as_bytes()
+            }
+        }
+        Code::Bytecode(bytes) => bytes,
+    };
+    let code = c::code_t {
+        code: bytes.as_ptr() as _,
+        code_len: bytes.len() as _,
+        is_bytecode: matches!(script, Code::Bytecode(_)) as _,
+        filename: c_filename.as_ptr(),
+        line: options.line.try_into().unwrap_or(i32::MAX),
+        eval_flags: if options.strict {
+            c::JS_EVAL_FLAG_STRICT as _
+        } else {
+            0
         },
     };
     if code.code_len == 0 {
         // Empty String or Vec in Rust would get a invalid ptr of address 0x1
         return Ok(Value::undefined());
     }
+    if let Code::Bytecode(bytes) = script {
+        crate::compile::check_bytecode_version(ctx, bytes).map_err(|err| err.to_string())?;
+    }
     let ret = unsafe { c::js_eval_code(ctx.as_ptr(), &code, &mut callbacks) };
     if ret == 0 {
-        userdata.output
+        Ok(userdata.output?)
     } else {
         let output = userdata.output?;
         if output.is_error() {
             let message = output.to_string();
-            let backtrace = output.get_property("stack").unwrap_or_default();
-            Err(format!("{}\n{}", message, backtrace))
+            let mut backtrace = output.get_property("stack").unwrap_or_default().to_string();
+            // The map describes the script as given, whose first line is `options.line`.
+            if let Code::Source(src) = script {
+                if let Ok(Some(map)) = SourceMap::from_inline_comment(src) {
+                    backtrace = map.rewrite_stack_from(filename, options.line, &backtrace);
+                }
+            }
+            Err(Failure {
+                message: format!("{}\n{}", message, backtrace),
+                thrown: Some(output),
+            })
         } else {
-            Err(output.to_string())
+            Err(Failure {
+                message: output.to_string(),
+                thrown: Some(output),
+            })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drained_then_chains_are_visible_after_eval() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.eval_and_drain(&Code::Source(
+            "globalThis.steps = [];
+            Promise.resolve(1)
+                .then(n => { steps.push(n); return n + 1; })
+                .then(n => steps.push(n));",
+        ))
+        .unwrap();
+        let steps = ctx.eval(&Code::Source("steps.join()")).unwrap();
+        assert_eq!(steps.decode_string().unwrap(), "1,2");
+
+        let err = ctx
+            .eval_and_drain(&Code::Source(
+                "Promise.resolve().then(() => { throw new Error('in job'); })",
+            ))
+            .unwrap_err();
+        assert!(err.contains("in job"), "{err}");
+    }
+
+    #[test]
+    fn job_limit_stops_self_reposting_jobs() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let options = EvalOptions {
+            auto_drain_jobs: true,
+            job_limit: 100,
+            ..Default::default()
+        };
+        let err = ctx
+            .eval_with_options(
+                &Code::Source(
+                    "globalThis.ticks = 0;
+                    (function tick() { ticks++; Promise.resolve().then(tick); })();",
+                ),
+                &options,
+            )
+            .unwrap_err();
+        assert!(err.contains("limit of 100"), "{err}");
+        let ticks = ctx.eval(&Code::Source("ticks")).unwrap();
+        assert_eq!(ticks.decode_u32().unwrap(), 101);
+    }
+
+    #[test]
+    fn errors_report_the_given_location() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let script = "function f() {\n  throw new Error('boom');\n}\nf();";
+
+        let err = ctx.eval(&Code::Source(script)).unwrap_err();
+        assert!(err.contains("<eval>:2"), "{err}");
+
+        let err = ctx
+            .eval_with_options(&Code::Source(script), &EvalOptions::at("widget.js", 40))
+            .unwrap_err();
+        assert!(err.contains("widget.js:41"), "{err}");
+        assert!(!err.contains("<eval>"), "{err}");
+        let err = ctx
+            .eval_with_options(
+                &Code::Source(script),
+                &EvalOptions::at("widget.js", 5_000_000),
+            )
+            .unwrap_err();
+        assert!(err.contains("widget.js:5000001"), "{err}");
+
+        let sloppy = Code::Source("undeclared = 1");
+        ctx.eval(&sloppy).unwrap();
+        let strict = EvalOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let err = ctx
+            .eval_with_options(&Code::Source("also_undeclared = 1"), &strict)
+            .unwrap_err();
+        assert!(err.contains("also_undeclared"), "{err}");
+    }
+}
@@ -1,4 +1,6 @@
-use js::AnyError;
+use core::any::Any;
+
+use js::{AnyError, ErrorValueExt};
 
 use crate::{self as js, c, ToJsValue, Value};
 
@@ -16,7 +18,14 @@ where
     T: ToJsValue,
 {
     fn into_js_value(self, ctx: &js::Context) -> js::Result<Value> {
-        self.to_js_value(ctx)
+        ToJsValue::into_js_value(self, ctx)
+    }
+}
+
+impl private::Sealed for js::Suspend {}
+impl HostCallOutput for js::Suspend {
+    fn into_js_value(self, ctx: &js::Context) -> js::Result<Value> {
+        crate::continuation::suspend(ctx, self.0)
     }
 }
 
@@ -32,21 +41,54 @@ where
     E: AnyError,
 {
     fn into_js_value(self, ctx: &js::Context) -> js::Result<Value> {
-        self.map_err(|err| js::Error::msg(format!("{err:?}")))?
-            .into_js_value(ctx)
+        self.map_err(into_js_error)?.into_js_value(ctx)
     }
 }
 
+/// Flatten the error to its debug message, unless it carries a `JsError` which is kept so that
+/// the thrown value gets the error's name.
+fn into_js_error<E: AnyError>(err: E) -> js::Error {
+    let mut slot = Some(err);
+    if let Some(inner) = (&mut slot as &mut dyn Any).downcast_mut::<Option<js::Error>>() {
+        if inner
+            .as_ref()
+            .is_some_and(|err| err.downcast_ref::<js::JsError>().is_some())
+        {
+            return inner.take().expect("checked above");
+        }
+    }
+    let err = slot.expect("only taken when returning");
+    js::Error::msg(format!("{err:?}"))
+}
+
+/// Report a call to the host function `fname` to the access auditor of `ctx` and record it in
+/// its receipt, for those enabled. Called before the arguments are converted.
+#[doc(hidden)]
+#[allow(unused_variables)]
+pub fn record_host_call(fname: &str, ctx: &js::Context, args: &[c::JSValue]) {
+    if fname != crate::audit::GLOBAL_READ_FN {
+        crate::audit::emit(ctx, crate::audit::AccessKind::HostCall, fname);
+    }
+    #[cfg(feature = "stable-hash")]
+    crate::receipt::record_host_call(ctx, fname, args);
+}
+
 pub fn convert_host_call_result(
     _fname: &str,
     ctx: &js::Context,
     result: impl HostCallOutput,
 ) -> c::JSValue {
-    match result.into_js_value(ctx) {
+    let rv = match result.into_js_value(ctx) {
         Ok(v) => v.leak(),
         Err(err) => {
-            ctx.throw_dbg(&err);
+            if err.downcast_ref::<js::JsError>().is_some() {
+                ctx.throw_value(err.to_js_error_value(ctx));
+            } else {
+                ctx.throw_dbg(&err);
+            }
             c::JS_EXCEPTION
         }
-    }
+    };
+    ctx.run_requested_gc();
+    rv
 }
@@ -0,0 +1,269 @@
+//! Futures run on behalf of a context, settling the promises given to scripts by async host
+//! calls.
+//!
+//! The host drives them with [`Runtime::poll_host_futures`], then runs the promise reactions
+//! with `Runtime::execute_pending_jobs`. A future lives no longer than its context: when the
+//! last handle of the context is dropped, or the runtime is, the futures still running are
+//! dropped without being polled again and their promises are never settled.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::ptr::NonNull;
+use core::task::{Context as TaskContext, Poll};
+
+use anyhow::bail;
+
+use crate::{c, Context, ErrorValueExt, Result, Runtime, ToJsValue, Value};
+
+/// Turns the output of a future into the value its promise is resolved with.
+type Settle = Box<dyn FnOnce(&Context) -> Result<Value>>;
+
+struct HostTask {
+    ctx: NonNull<c::JSContext>,
+    rt: NonNull<c::JSRuntime>,
+    /// The resolving functions of the promise, owned by the task. They are raw values rather
+    /// than `Value`s, which would hold a handle to the context and keep it from being torn down.
+    resolve: c::JSValue,
+    reject: c::JSValue,
+    future: Pin<Box<dyn Future<Output = Settle>>>,
+}
+
+impl HostTask {
+    fn settle(self, settle: Settle) {
+        let Some(ctx) = Context::clone_from_ptr(self.ctx.as_ptr()) else {
+            return;
+        };
+        let (func, arg) = match settle(&ctx) {
+            Ok(value) => (self.resolve, value),
+            Err(err) => (self.reject, err.to_js_error_value(&ctx)),
+        };
+        let func = Value::new_cloned(&ctx, func);
+        if let Err(err) = func.call(&Value::undefined(), &[arg]) {
+            log::error!("failed to settle the promise of a host future: {err:?}");
+        }
+    }
+}
+
+impl Drop for HostTask {
+    fn drop(&mut self) {
+        unsafe {
+            c::JS_FreeValueRT(self.rt.as_ptr(), self.resolve);
+            c::JS_FreeValueRT(self.rt.as_ptr(), self.reject);
+        }
+    }
+}
+
+/// The host futures of a runtime, kept in its `RuntimeData`.
+#[derive(Default)]
+pub(crate) struct HostFutures {
+    tasks: Vec<HostTask>,
+    /// Whether the tasks are taken out by `Runtime::poll_host_futures`.
+    polling: bool,
+    /// The contexts torn down while polling, whose tasks are dropped once it is over.
+    cancelled: Vec<NonNull<c::JSContext>>,
+}
+
+impl Context {
+    /// Run `future` on behalf of the context and return a promise settled with its output: a
+    /// value converted with `ToJsValue`, or an error turned into a JS `Error`. This is what an
+    /// async host call returns to the script.
+    ///
+    /// The future is polled by [`Runtime::poll_host_futures`]. It is dropped unfinished if the
+    /// context is torn down first, and the promise then stays pending. A future holding values
+    /// of the context holds a handle to it, and keeps it alive until the future finishes.
+    ///
+    /// Only contexts created by `Runtime::new_context` can run host futures.
+    pub fn spawn_host_future<T, F>(&self, future: F) -> Result<Value>
+    where
+        T: ToJsValue + 'static,
+        F: Future<Output = Result<T>> + 'static,
+    {
+        if self.host_futures().is_none() {
+            bail!("host futures need a context created by Runtime::new_context");
+        }
+        let mut resolving = [c::JS_UNDEFINED; 2];
+        let promise = unsafe { c::JS_NewPromiseCapability(self.as_ptr(), resolving.as_mut_ptr()) };
+        let promise = Value::new_moved(self, promise);
+        if promise.is_exception() {
+            return Err(self.get_exception_error());
+        }
+        let future = async move {
+            let output = future.await;
+            Box::new(move |ctx: &Context| output?.to_js_value(ctx)) as Settle
+        };
+        let rt = unsafe { c::JS_GetRuntime(self.as_ptr()) };
+        let task = HostTask {
+            ctx: self.ptr,
+            rt: NonNull::new(rt).expect("context without runtime"),
+            resolve: resolving[0],
+            reject: resolving[1],
+            future: Box::pin(future),
+        };
+        if let Some(mut futures) = self.host_futures() {
+            futures.tasks.push(task);
+        }
+        Ok(promise)
+    }
+
+    /// Drop the host futures of the context, on teardown.
+    pub(crate) fn cancel_host_futures(&self) {
+        let Some(mut futures) = self.host_futures() else {
+            return;
+        };
+        if futures.polling {
+            futures.cancelled.push(self.ptr);
+        }
+        let (cancelled, tasks): (Vec<_>, _) = core::mem::take(&mut futures.tasks)
+            .into_iter()
+            .partition(|task| task.ctx == self.ptr);
+        futures.tasks = tasks;
+        // Dropped once `futures` is no longer borrowed, as freeing the resolving functions may
+        // run finalizers that come back here.
+        drop(futures);
+        drop(cancelled);
+    }
+
+    /// How many host futures spawned by [`spawn_host_future`](Self::spawn_host_future) are still
+    /// running.
+    pub fn pending_host_futures(&self) -> usize {
+        let Some(futures) = self.host_futures() else {
+            return 0;
+        };
+        futures
+            .tasks
+            .iter()
+            .filter(|task| task.ctx == self.ptr)
+            .count()
+    }
+}
+
+impl Runtime {
+    /// Drop the host futures of all contexts, while the runtime is dropped. A future may hold
+    /// the last handle of its context, whose teardown reads the data of the runtime, so they go
+    /// while it is still in place.
+    pub(crate) fn cancel_all_host_futures(&self) {
+        loop {
+            let tasks = core::mem::take(&mut self.host_futures().tasks);
+            if tasks.is_empty() {
+                break;
+            }
+            drop(tasks);
+        }
+    }
+
+    /// How many host futures of all contexts of the runtime are still running.
+    pub fn pending_host_futures(&self) -> usize {
+        self.host_futures().tasks.len()
+    }
+
+    /// Poll the host futures of all contexts of the runtime once with `cx`, settling the
+    /// promises of the finished ones, and return how many finished.
+    ///
+    /// The promise reactions run later, with `execute_pending_jobs`. Calling this from a future
+    /// being polled does nothing.
+    pub fn poll_host_futures(&self, cx: &mut TaskContext<'_>) -> usize {
+        let mut futures = self.host_futures();
+        if futures.polling {
+            return 0;
+        }
+        futures.polling = true;
+        let mut tasks = core::mem::take(&mut futures.tasks);
+        // The futures and settling run the host and scripts, which may spawn or cancel others.
+        drop(futures);
+        let mut finished = 0;
+        let mut i = 0;
+        while i < tasks.len() {
+            if self.host_futures().cancelled.contains(&tasks[i].ctx) {
+                drop(tasks.remove(i));
+                continue;
+            }
+            match tasks[i].future.as_mut().poll(cx) {
+                Poll::Pending => i += 1,
+                Poll::Ready(settle) => {
+                    tasks.remove(i).settle(settle);
+                    finished += 1;
+                }
+            }
+        }
+        let mut futures = self.host_futures();
+        futures.polling = false;
+        let cancelled = core::mem::take(&mut futures.cancelled);
+        // The tasks spawned while polling go after the older ones.
+        tasks.append(&mut futures.tasks);
+        let (cancelled, tasks): (Vec<_>, _) = tasks
+            .into_iter()
+            .partition(|task| cancelled.contains(&task.ctx));
+        futures.tasks = tasks;
+        drop(futures);
+        drop(cancelled);
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+    use core::task::Waker;
+
+    use super::*;
+    use crate::{self as js, Code};
+
+    std::thread_local! {
+        static DROPPED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct DropCounter;
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            DROPPED.with(|dropped| dropped.set(dropped.get() + 1));
+        }
+    }
+
+    #[crate::host_call(with_context)]
+    fn forever(ctx: js::Context, _this: Value) -> Result<Value> {
+        let counter = DropCounter;
+        ctx.spawn_host_future(async move {
+            let _counter = counter;
+            core::future::pending::<Result<()>>().await
+        })
+    }
+
+    #[crate::host_call(with_context)]
+    fn double(ctx: js::Context, _this: Value, n: u32) -> Result<Value> {
+        ctx.spawn_host_future(async move { Ok(n * 2) })
+    }
+
+    #[test]
+    fn futures_are_cancelled_with_their_context() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let global = ctx.get_global_object();
+        global.define_property_fn("forever", forever).unwrap();
+        global.define_property_fn("double", double).unwrap();
+        drop(global);
+        ctx.eval(&Code::Source(
+            "globalThis.result = 0; double(21).then(n => result = n); forever(); forever();",
+        ))
+        .unwrap();
+        assert_eq!(ctx.pending_host_futures(), 3);
+
+        let mut cx = TaskContext::from_waker(Waker::noop());
+        assert_eq!(runtime.poll_host_futures(&mut cx), 1);
+        assert_eq!(runtime.poll_host_futures(&mut cx), 0);
+        runtime.execute_pending_jobs().unwrap();
+        let result = ctx.eval(&Code::Source("result")).unwrap();
+        assert_eq!(result.decode_u32().unwrap(), 42);
+        drop(result);
+        assert_eq!(ctx.pending_host_futures(), 2);
+        assert_eq!(DROPPED.with(Cell::get), 0);
+
+        drop(ctx);
+        assert_eq!(DROPPED.with(Cell::get), 2);
+        assert_eq!(runtime.poll_host_futures(&mut cx), 0);
+        assert!(runtime.host_futures().tasks.is_empty());
+        drop(runtime);
+    }
+}
@@ -0,0 +1,390 @@
+//! The host functions defined on a context, and replacing them from the host.
+//!
+//! Once enabled with [`Runtime::enable_host_function_registry`](js::Runtime), functions defined
+//! with [`Value::define_property_fn`] are recorded in a registry kept with the runtime. Their
+//! implementation can be swapped at any time, typically by a test harness stubbing out a source
+//! of randomness:
+//!
+//! ```ignore
+//! runtime.enable_host_function_registry();
+//! // ... install the extensions ...
+//! ctx.override_host_function("crypto.getRandomValues", |_ctx, _this, args| Ok(args[0].clone()))?;
+//! ctx.eval(&code)?;
+//! ctx.restore_host_function("crypto.getRandomValues")?;
+//! ```
+//!
+//! The function object is kept, so scripts that stored a reference to it see the replacement
+//! as well.
+
+use alloc::rc::{Rc, Weak};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use anyhow::bail;
+
+use crate::{self as js, c, engine::runtime_state_of, small_str::SmallStr, Result, Value};
+
+/// A host function found by [`Context::host_functions`](js::Context::host_functions).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionInfo {
+    /// The key the function was defined with.
+    pub name: String,
+    /// Whether it runs a replacement set by `override_host_function`.
+    pub overridden: bool,
+}
+
+type Replacement = Rc<dyn Fn(&js::Context, Value, &[Value]) -> Result<Value>>;
+
+struct HostFunction {
+    name: String,
+    func: c::JsCFunction,
+    replacement: Option<Replacement>,
+    /// The function object, to find the slot of a function. Not a reference: the slot is freed
+    /// along with the function.
+    object: *mut core::ffi::c_void,
+}
+
+/// The host functions of a runtime, by slot. The slot of a function is its `magic`.
+#[derive(Default)]
+struct Registry {
+    slots: Vec<Option<HostFunction>>,
+    free: Vec<usize>,
+}
+
+type SharedRegistry = RefCell<Registry>;
+
+/// Attached to a function object, frees its slot when the function is collected.
+struct SlotGuard {
+    registry: Weak<SharedRegistry>,
+    slot: usize,
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        let Some(registry) = self.registry.upgrade() else {
+            return;
+        };
+        let mut registry = registry.borrow_mut();
+        let entry = registry.slots[self.slot].take();
+        registry.free.push(self.slot);
+        // Dropped unborrowed, as the values a replacement holds may free other functions.
+        drop(registry);
+        drop(entry);
+    }
+}
+
+fn registry_of(ctx: *mut c::JSContext) -> Option<Rc<SharedRegistry>> {
+    runtime_state_of(unsafe { c::JS_GetRuntime(ctx) })
+}
+
+/// Create the function object for `func`, recorded in the registry if the runtime has one.
+pub(crate) fn new_host_function(
+    ctx: &js::Context,
+    name: &str,
+    func: c::JsCFunction,
+) -> Result<Value> {
+    let Some(registry) = registry_of(ctx.as_ptr()) else {
+        return Ok(ctx.new_function(name, func, 0, c::JS_CFUNC_generic));
+    };
+    let slot = {
+        let mut registry = registry.borrow_mut();
+        let entry = HostFunction {
+            name: name.into(),
+            func,
+            replacement: None,
+            object: core::ptr::null_mut(),
+        };
+        match registry.free.pop() {
+            Some(slot) => {
+                registry.slots[slot] = Some(entry);
+                slot
+            }
+            None => {
+                registry.slots.push(Some(entry));
+                registry.slots.len() - 1
+            }
+        }
+    };
+    // Created first, so that the slot is freed if anything below fails.
+    let guard = Value::new_opaque_object(
+        ctx,
+        Some("HostFunction"),
+        SlotGuard {
+            registry: Rc::downgrade(&registry),
+            slot,
+        },
+    );
+    let mut data = [*guard.raw_value()];
+    let function = unsafe {
+        Value::new_moved(
+            ctx,
+            c::JS_NewCFunctionData(
+                ctx.as_ptr(),
+                Some(dispatch),
+                0,
+                slot as _,
+                1,
+                data.as_mut_ptr(),
+            ),
+        )
+    };
+    if function.is_exception() {
+        return Err(ctx.get_exception_error());
+    }
+    if let Some(entry) = &mut registry.borrow_mut().slots[slot] {
+        entry.object = unsafe { c::JS_GetPtr(*function.raw_value()) };
+    }
+    let r = unsafe {
+        c::JS_DefinePropertyValue(
+            ctx.as_ptr(),
+            *function.raw_value(),
+            c::JS_ATOM_name,
+            Value::from_str(ctx, name).leak(),
+            c::JS_PROP_CONFIGURABLE as _,
+        )
+    };
+    if r < 0 {
+        return Err(ctx.get_exception_error());
+    }
+    Ok(function)
+}
+
+unsafe extern "C" fn dispatch(
+    ctx: *mut c::JSContext,
+    this: c::JSValue,
+    argc: core::ffi::c_int,
+    argv: *mut c::JSValue,
+    magic: core::ffi::c_int,
+    _data: *mut c::JSValue,
+) -> c::JSValue {
+    let (func, replacement) = {
+        let Some(registry) = registry_of(ctx) else {
+            return throw_internal(ctx, "host function registry not enabled");
+        };
+        let registry = registry.borrow();
+        let Some(Some(entry)) = registry.slots.get(magic as usize) else {
+            let message = alloc::format!("host function slot {magic} not registered");
+            return throw_internal(ctx, &message);
+        };
+        (entry.func, entry.replacement.clone())
+    };
+    let Some(replacement) = replacement else {
+        return func(ctx, this, argc, argv);
+    };
+    let Some(ctx) = js::Context::clone_from_ptr(ctx) else {
+        return c::JS_EXCEPTION;
+    };
+    let name = slot_info(&ctx, magic as usize).map(|info| info.name);
+    let this = Value::new_cloned(&ctx, this);
+    let args: Vec<Value> = (0..argc.max(0) as usize)
+        .map(|i| Value::new_cloned(&ctx, *argv.add(i)))
+        .collect();
+    let result = replacement(&ctx, this, &args);
+    js::convert_host_call_result(name.as_deref().unwrap_or(""), &ctx, result)
+}
+
+/// Throw an InternalError with `message` on the raw `ctx` and return the exception.
+unsafe fn throw_internal(ctx: *mut c::JSContext, message: &str) -> c::JSValue {
+    let message = SmallStr::c_str(message);
+    c::JS_ThrowInternalError(ctx, b"%s\0".as_ptr() as _, message.as_ptr())
+}
+
+fn slot_info(ctx: &js::Context, slot: usize) -> Option<FunctionInfo> {
+    let registry = registry_of(ctx.as_ptr())?;
+    let registry = registry.borrow();
+    let entry = registry.slots.get(slot)?.as_ref()?;
+    Some(FunctionInfo {
+        name: entry.name.clone(),
+        overridden: entry.replacement.is_some(),
+    })
+}
+
+/// The slot of `function`, if it is a recorded host function.
+fn find_slot(ctx: &js::Context, function: &Value) -> Option<usize> {
+    let registry = registry_of(ctx.as_ptr())?;
+    let ptr = unsafe { c::JS_GetPtr(*function.raw_value()) };
+    let registry = registry.borrow();
+    registry
+        .slots
+        .iter()
+        .position(|entry| matches!(entry, Some(entry) if entry.object == ptr))
+}
+
+impl js::Runtime {
+    /// Record the host functions defined from now on, so that they can be listed and overridden
+    /// with `Context::host_functions` and `Context::override_host_function`. Off by default, as
+    /// it adds a lookup to every call of the functions.
+    pub fn enable_host_function_registry(&self) {
+        if self.user_data::<SharedRegistry>().is_none() {
+            self.set_user_data(SharedRegistry::default());
+        }
+    }
+}
+
+impl js::Context {
+    /// The host functions reachable from `globalThis` through plain objects, by path.
+    ///
+    /// The walk reads the properties it passes, running any getter defined on them.
+    pub fn host_functions(&self) -> Result<Vec<(String, FunctionInfo)>> {
+        let mut found = Vec::new();
+        let mut visited = Vec::new();
+        let mut pending = alloc::vec![(String::new(), self.get_global_object())];
+        while let Some((prefix, object)) = pending.pop() {
+            let ptr = unsafe { c::JS_GetPtr(*object.raw_value()) };
+            if visited.contains(&ptr) {
+                continue;
+            }
+            visited.push(ptr);
+            for key in object.keys()? {
+                let key = key?.decode_string()?;
+                if prefix.is_empty() && key == "_QjsBind" {
+                    continue;
+                }
+                let value = object.get_property(&key)?;
+                let path = if prefix.is_empty() {
+                    key
+                } else {
+                    alloc::format!("{prefix}.{key}")
+                };
+                if value.is_function() {
+                    if let Some(info) = find_slot(self, &value).and_then(|i| slot_info(self, i)) {
+                        found.push((path, info));
+                    }
+                } else if value.is_generic_object() {
+                    pending.push((path, value));
+                }
+            }
+        }
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(found)
+    }
+
+    /// Make the host function at `path` run `f` instead, until restored.
+    ///
+    /// `f` must not hold on to the context it is given, which would keep it alive forever.
+    pub fn override_host_function(
+        &self,
+        path: &str,
+        f: impl Fn(&js::Context, Value, &[Value]) -> Result<Value> + 'static,
+    ) -> Result<()> {
+        self.set_replacement(path, Some(Rc::new(f)))
+    }
+
+    /// Run the original implementation of the host function at `path` again.
+    pub fn restore_host_function(&self, path: &str) -> Result<()> {
+        self.set_replacement(path, None)
+    }
+
+    fn set_replacement(&self, path: &str, replacement: Option<Replacement>) -> Result<()> {
+        let Some(registry) = registry_of(self.as_ptr()) else {
+            bail!("the host function registry is not enabled");
+        };
+        let function = self.resolve_object(path)?;
+        let Some(slot) = find_slot(self, &function) else {
+            bail!("{path} is not a host function");
+        };
+        let previous = match &mut registry.borrow_mut().slots[slot] {
+            Some(entry) => core::mem::replace(&mut entry.replacement, replacement),
+            None => None,
+        };
+        drop(previous);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+
+    #[crate::host_call]
+    fn answer() -> u32 {
+        42
+    }
+
+    #[test]
+    fn overrides_keep_the_function_identity() {
+        let runtime = js::Runtime::new(&Default::default());
+        runtime.enable_host_function_registry();
+        let ctx = runtime.new_context();
+        let ns = ctx.new_object("Ns");
+        ns.define_property_fn("answer", answer).unwrap();
+        ctx.get_global_object().set_property("ns", &ns).unwrap();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap().to_string();
+        eval("globalThis.saved = ns.answer");
+
+        let info = |overridden| FunctionInfo {
+            name: "answer".into(),
+            overridden,
+        };
+        assert_eq!(
+            ctx.host_functions().unwrap(),
+            [("ns.answer".into(), info(false))]
+        );
+
+        ctx.override_host_function("ns.answer", |ctx, _this, args| {
+            Ok(Value::from_usize(ctx, args.len()))
+        })
+        .unwrap();
+        assert_eq!(eval("saved(1, 2, 3) + ' ' + saved.name"), "3 answer");
+        assert_eq!(ctx.host_functions().unwrap()[0].1, info(true));
+
+        ctx.restore_host_function("ns.answer").unwrap();
+        assert_eq!(eval("saved(1, 2, 3)"), "42");
+        assert!(ctx
+            .override_host_function("Math.max", |_, this, _| Ok(this))
+            .is_err());
+    }
+
+    #[test]
+    fn records_nothing_unless_enabled() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let global = ctx.get_global_object();
+        global.define_property_fn("answer", answer).unwrap();
+        assert_eq!(ctx.host_functions().unwrap(), []);
+        assert!(ctx
+            .override_host_function("answer", |_, this, _| Ok(this))
+            .is_err());
+    }
+
+    #[test]
+    fn frees_the_slots_of_collected_functions() {
+        let runtime = js::Runtime::new(&Default::default());
+        runtime.enable_host_function_registry();
+        let ctx = runtime.new_context();
+        let global = ctx.get_global_object();
+        global.define_property_fn("answer", answer).unwrap();
+        global.define_property_fn("answer", answer).unwrap();
+        runtime.run_gc();
+        let registry = registry_of(ctx.as_ptr()).unwrap();
+        let live = registry.borrow().slots.iter().flatten().count();
+        assert_eq!(live, 1);
+        assert_eq!(ctx.host_functions().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn calls_to_a_removed_slot_throw() {
+        let runtime = js::Runtime::new(&Default::default());
+        runtime.enable_host_function_registry();
+        let ctx = runtime.new_context();
+        let global = ctx.get_global_object();
+        global.define_property_fn("answer", answer).unwrap();
+        let function = global.get_property("answer").unwrap();
+        let slot = find_slot(&ctx, &function).unwrap();
+        let registry = registry_of(ctx.as_ptr()).unwrap();
+        let entry = registry.borrow_mut().slots[slot].take();
+        drop(entry);
+
+        let caught = ctx
+            .eval(&Code::Source(
+                "try { answer(); 'no error' } catch (e) { `${e.name}: ${e.message}` }",
+            ))
+            .unwrap();
+        assert_eq!(
+            caught.to_string(),
+            alloc::format!("InternalError: host function slot {slot} not registered")
+        );
+    }
+}
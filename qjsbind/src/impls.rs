@@ -5,7 +5,8 @@ use tinyvec::TinyVec;
 use super::{FromArgs, FromJsValue, Result, ToArgs, ToJsValue, Value};
 use crate::{
     self as js,
-    error::{expect_js_value, JsResultExt},
+    error::{expect_err, ExpectError, JsResultExt},
+    ConversionDepth, Error, JsBigInt64Array, JsBigUint64Array, JsUint8Array,
 };
 
 impl FromJsValue for Value {
@@ -15,13 +16,19 @@ impl FromJsValue for Value {
 }
 
 macro_rules! impl_from_for {
-    ($t: ident, $decode_fn: ident) => {
+    ($t: ident, $decode_fn: ident $(, $array: ident)?) => {
         impl FromJsValue for $t {
             fn from_js_value(js_value: Value) -> Result<Self> {
                 js_value
                     .$decode_fn()
-                    .expect_js_value(&js_value, stringify!($t))
+                    .expect_type(&js_value, stringify!($t))
             }
+            $(
+            fn vec_from_js_value(js_value: &Value) -> Option<Result<Vec<Self>>> {
+                $array::is(js_value)
+                    .then(|| Ok($array::from_js_value(js_value.clone())?.to_vec()))
+            }
+            )?
         }
     };
 }
@@ -29,11 +36,11 @@ macro_rules! impl_from_for {
 impl_from_for!(i8, decode_i8);
 impl_from_for!(i16, decode_i16);
 impl_from_for!(i32, decode_i32);
-impl_from_for!(i64, decode_i64);
-impl_from_for!(u8, decode_u8);
+impl_from_for!(i64, decode_i64, JsBigInt64Array);
+impl_from_for!(u8, decode_u8, JsUint8Array);
 impl_from_for!(u16, decode_u16);
 impl_from_for!(u32, decode_u32);
-impl_from_for!(u64, decode_u64);
+impl_from_for!(u64, decode_u64, JsBigUint64Array);
 impl_from_for!(f32, decode_f32);
 impl_from_for!(f64, decode_f64);
 impl_from_for!(i128, decode_i128);
@@ -47,7 +54,7 @@ impl FromJsValue for () {
         if js_value.is_null_or_undefined() {
             Ok(())
         } else {
-            Err(expect_js_value(&js_value, "()"))
+            Err(expect_err("()", &js_value))
         }
     }
 }
@@ -59,8 +66,14 @@ macro_rules! impl_from_for_tuple {
             $($t: FromJsValue),*
         {
             fn from_js_value(js_value: Value) -> Result<Self> {
-                let mut iter = iter_values(js_value)?;
-                Ok(($($t::from_js_value(iter.next().ok_or_else(|| anyhow!("expect tuple"))??)?,)*))
+                let depth = ConversionDepth::of(&js_value);
+                Self::from_js_value_depth(js_value, depth)
+            }
+
+            fn from_js_value_depth(js_value: Value, depth: ConversionDepth) -> Result<Self> {
+                let depth = depth.enter()?;
+                let mut iter = iter_values(js_value, depth)?;
+                Ok(($($t::from_js_value_depth(iter.next().ok_or_else(|| Error::msg(ExpectError::missing("tuple element")))??, depth)?,)*))
             }
         }
     };
@@ -77,51 +90,116 @@ impl_from_for_tuple!(A, B, C, D, E, F, G, H, I);
 
 impl<T: FromJsValue> FromJsValue for Option<T> {
     fn from_js_value(js_value: Value) -> Result<Self> {
+        let depth = ConversionDepth::of(&js_value);
+        Self::from_js_value_depth(js_value, depth)
+    }
+
+    fn from_js_value_depth(js_value: Value, depth: ConversionDepth) -> Result<Self> {
         if js_value.is_null_or_undefined() {
             Ok(None)
         } else {
-            Ok(Some(T::from_js_value(js_value)?))
+            Ok(Some(T::from_js_value_depth(js_value, depth)?))
         }
     }
 }
 
 impl<T: FromJsValue> FromJsValue for Box<T> {
     fn from_js_value(js_value: Value) -> Result<Self> {
-        Ok(Box::new(T::from_js_value(js_value)?))
+        let depth = ConversionDepth::of(&js_value);
+        Self::from_js_value_depth(js_value, depth)
+    }
+
+    fn from_js_value_depth(js_value: Value, depth: ConversionDepth) -> Result<Self> {
+        Ok(Box::new(T::from_js_value_depth(js_value, depth)?))
     }
 }
 
 impl<T: FromJsValue> FromJsValue for Vec<T> {
     fn from_js_value(js_value: Value) -> Result<Self> {
-        iter_values(js_value)?.collect()
+        let depth = ConversionDepth::of(&js_value);
+        Self::from_js_value_depth(js_value, depth)
+    }
+
+    fn from_js_value_depth(js_value: Value, depth: ConversionDepth) -> Result<Self> {
+        if let Some(vec) = T::vec_from_js_value(&js_value) {
+            return vec;
+        }
+        if js_value.is_array() {
+            crate::limits::check_array_len(js_value.context()?, js_value.length()?)?;
+        } else if !js_value.is_object() {
+            return Err(not_an_array(&js_value));
+        }
+        iter_values(js_value, depth.enter()?)?
+            .enumerate()
+            .map(|(i, item)| item.map_err(|err| crate::ElementPath::wrap(err, i)))
+            .collect()
+    }
+}
+
+/// Decode a float that must be finite, failing with a `NonFiniteNumber` for `NaN` and infinities.
+///
+/// Used by derived impls for fields with `#[qjs(finite)]`.
+pub fn decode_finite<T: FromJsValue + Copy + Into<f64>>(js_value: Value) -> Result<T> {
+    let value = T::from_js_value(js_value)?;
+    let float: f64 = value.into();
+    if !float.is_finite() {
+        return Err(crate::Error::msg(crate::NonFiniteNumber {
+            expected: "finite number",
+            value: float,
+        }));
+    }
+    Ok(value)
+}
+
+/// The error for a single value passed where a list is expected, a common mistake.
+pub(crate) fn not_an_array(value: &Value) -> crate::Error {
+    let got = value.describe_type();
+    let suggestion = if value.is_string() {
+        value.decode_string().ok().map(|s| format!("{s:?}"))
+    } else if value.is_number() || value.is_bool() {
+        Some(value.to_string())
+    } else {
+        None
+    };
+    match suggestion {
+        Some(item) => anyhow!("expected an array, got {got} — did you mean [{item}]?"),
+        None => anyhow!("expected an array, got {got}"),
     }
 }
 
-fn iter_values<V: FromJsValue>(js_value: Value) -> Result<impl Iterator<Item = Result<V>>> {
+/// The items of `js_value`, converted at `depth`.
+fn iter_values<V: FromJsValue>(
+    js_value: Value,
+    depth: ConversionDepth,
+) -> Result<impl Iterator<Item = Result<V>>> {
     let mut iter = js_value
         .values()
-        .expect_js_value(&js_value, "array-like object")?;
+        .expect_type(&js_value, "array-like object")?;
     Ok(core::iter::from_fn(move || -> Option<Result<V>> {
         let value = opt_try!(iter.next()?);
-        Some(V::from_js_value(value))
+        Some(V::from_js_value_depth(value, depth))
     }))
 }
 
-fn iter_fields<K, V>(js_value: Value) -> Result<impl Iterator<Item = Result<(K, V)>>>
+/// The entries of `js_value`, their values converted at `depth`.
+fn iter_fields<K, V>(
+    js_value: Value,
+    depth: ConversionDepth,
+) -> Result<impl Iterator<Item = Result<(K, V)>>>
 where
     K: FromJsValue,
     V: FromJsValue,
 {
     let mut iter = js_value
         .entries()
-        .expect_js_value(&js_value, "map-like object")?;
+        .expect_type(&js_value, "map-like object")?;
     Ok(core::iter::from_fn(move || -> Option<Result<(K, V)>> {
         let (key, value) = opt_try!(iter.next()?);
         let key = match K::from_js_value(key) {
             Ok(k) => k,
             Err(err) => return Some(Err(err)),
         };
-        let value = match V::from_js_value(value) {
+        let value = match V::from_js_value_depth(value, depth) {
             Ok(v) => v,
             Err(err) => return Some(Err(err)),
         };
@@ -135,13 +213,23 @@ where
     V: FromJsValue,
 {
     fn from_js_value(js_value: Value) -> Result<Self> {
-        iter_fields(js_value)?.collect()
+        let depth = ConversionDepth::of(&js_value);
+        Self::from_js_value_depth(js_value, depth)
+    }
+
+    fn from_js_value_depth(js_value: Value, depth: ConversionDepth) -> Result<Self> {
+        iter_fields(js_value, depth)?.collect()
     }
 }
 
 impl<const N: usize, T: FromJsValue + Default> FromJsValue for [T; N] {
     fn from_js_value(js_value: Value) -> Result<Self> {
-        let mut iter = iter_values(js_value)?;
+        let depth = ConversionDepth::of(&js_value);
+        Self::from_js_value_depth(js_value, depth)
+    }
+
+    fn from_js_value_depth(js_value: Value, depth: ConversionDepth) -> Result<Self> {
+        let mut iter = iter_values(js_value, depth.enter()?)?;
         let mut array: Vec<T> = vec![];
         for _ in 0..N {
             array.push(
@@ -154,17 +242,23 @@ impl<const N: usize, T: FromJsValue + Default> FromJsValue for [T; N] {
 }
 
 impl ToJsValue for Value {
-    fn to_js_value(&self, _ctx: &js::Context) -> Result<Value> {
+    fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
+        self.check_context(ctx)?;
         Ok(self.clone())
     }
 }
 
 macro_rules! impl_to_js_for {
-    ($t: ident, $encode_fn: ident) => {
+    ($t: ident, $encode_fn: ident $(, $array: ident)?) => {
         impl ToJsValue for $t {
             fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
                 Ok(Value::$encode_fn(ctx, *self))
             }
+            $(
+            fn slice_to_js_value(items: &[Self], ctx: &js::Context) -> Option<Result<Value>> {
+                Some($array::new(ctx, items).and_then(|array| array.to_js_value(ctx)))
+            }
+            )?
         }
     };
 }
@@ -172,11 +266,14 @@ macro_rules! impl_to_js_for {
 impl_to_js_for!(i8, from_i8);
 impl_to_js_for!(i16, from_i16);
 impl_to_js_for!(i32, from_i32);
-impl_to_js_for!(i64, from_i64);
-impl_to_js_for!(u8, from_u8);
+impl_to_js_for!(i64, from_i64, JsBigInt64Array);
+// `Vec<u8>`, `[u8]` and `[u8; N]` are converted to a Uint8Array, and back from either a
+// Uint8Array or an array of numbers. Use `encode_as_array` for the JS array of numbers they
+// were converted to before.
+impl_to_js_for!(u8, from_u8, JsUint8Array);
 impl_to_js_for!(u16, from_u16);
 impl_to_js_for!(u32, from_u32);
-impl_to_js_for!(u64, from_u64);
+impl_to_js_for!(u64, from_u64, JsBigUint64Array);
 impl_to_js_for!(f32, from_f32);
 impl_to_js_for!(f64, from_f64);
 impl_to_js_for!(i128, from_i128);
@@ -242,8 +339,25 @@ impl<T: ToJsValue> ToJsValue for Box<T> {
     }
 }
 
+impl<T: ToJsValue> ToJsValue for alloc::rc::Rc<T> {
+    fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
+        self.as_ref().to_js_value(ctx)
+    }
+}
+
+impl<T: ToJsValue> ToJsValue for core::cell::RefCell<T> {
+    fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
+        self.try_borrow()
+            .map_err(|_| anyhow!("value is mutably borrowed"))?
+            .to_js_value(ctx)
+    }
+}
+
 impl<T: ToJsValue> ToJsValue for [T] {
     fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
+        if let Some(value) = T::slice_to_js_value(self, ctx) {
+            return value;
+        }
         let js_array = Value::new_array(ctx);
         for value in self.iter() {
             js_array.array_push(&value.to_js_value(ctx)?)?;
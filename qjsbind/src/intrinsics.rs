@@ -0,0 +1,53 @@
+//! The standard constructors of a context as they were when it was created, before scripts could
+//! replace the globals.
+
+use anyhow::bail;
+
+use crate::{self as js, c, Result, Value};
+
+const INTRINSICS: &str = "intrinsics";
+
+/// The constructors captured, when the context has them.
+const NAMES: &[&str] = &[
+    "Error",
+    "EvalError",
+    "RangeError",
+    "ReferenceError",
+    "SyntaxError",
+    "TypeError",
+    "URIError",
+    "AggregateError",
+    "InternalError",
+];
+
+/// Keep the constructors of `ctx` on the host side. Called when the context is created, before
+/// anything runs in it.
+pub(crate) fn capture(ctx: &js::Context) -> Result<()> {
+    let global = ctx.get_global_object();
+    let intrinsics = unsafe { c::JS_NewObjectProto(ctx.as_ptr(), c::JS_NULL) };
+    let intrinsics = Value::new_moved(ctx, intrinsics);
+    if intrinsics.is_exception() {
+        return Err(ctx.get_exception_error());
+    }
+    for name in NAMES {
+        let ctor = global.get_property(name)?;
+        if ctor.is_function() {
+            intrinsics.define_property_value(name, ctor)?;
+        }
+    }
+    ctx.host_object(INTRINSICS, || Ok(intrinsics))?;
+    Ok(())
+}
+
+impl js::Context {
+    /// The standard constructor `name` as the context was created with, whatever scripts did to
+    /// the global since.
+    pub(crate) fn intrinsic(&self, name: &str) -> Result<Value> {
+        let intrinsics = self.host_object(INTRINSICS, || bail!("no intrinsics captured"))?;
+        let ctor = intrinsics.get_property(name)?;
+        if ctor.is_undefined() {
+            bail!("{name} is not an intrinsic of this context");
+        }
+        Ok(ctor)
+    }
+}
@@ -3,7 +3,7 @@ use core::{ops::Deref, ptr};
 use alloc::vec::Vec;
 use anyhow::bail;
 
-use crate::{self as js, c, error::expect_js_value, FromJsValue, GcMark, Result, ToJsValue, Value};
+use crate::{self as js, c, error::expect_err, FromJsValue, GcMark, Result, ToJsValue, Value};
 
 /// A wrapper of JS Uint8Array. When passing a string from JS to Rust, using this type
 /// is more efficient than `Vec<u8>` because it avoids extra memory allocation and copy.
@@ -30,6 +30,14 @@ impl JsArrayBuffer {
         Self::from_value(ctx, value)
     }
 
+    /// Create an ArrayBuffer taking ownership of `bytes` without copying them.
+    ///
+    /// The Vec is dropped by the GC finalizer of the ArrayBuffer.
+    pub fn from_vec(ctx: &js::Context, bytes: Vec<u8>) -> Result<Self> {
+        let value = new_external_array_buffer(ctx, bytes)?;
+        Self::from_value(ctx, value)
+    }
+
     pub fn from_value(ctx: &js::Context, value: Value) -> Result<Self> {
         unsafe {
             if value.is_exception() {
@@ -92,16 +100,49 @@ impl JsArrayBuffer {
     }
 }
 
+/// An ArrayBuffer over `bytes`, which are dropped by its finalizer.
+pub(crate) fn new_external_array_buffer(ctx: &js::Context, bytes: Vec<u8>) -> Result<Value> {
+    // Boxed, so that the free function drops the Vec with its original capacity.
+    let mut boxed = alloc::boxed::Box::new(bytes);
+    let ptr = boxed.as_mut_ptr();
+    let len = boxed.len();
+    let opaque = alloc::boxed::Box::into_raw(boxed);
+    let value = unsafe {
+        c::JS_NewArrayBuffer(
+            ctx.as_ptr(),
+            ptr,
+            len as _,
+            Some(free_external_buffer),
+            opaque as _,
+            false,
+        )
+    };
+    if c::is_exception(value) {
+        // The engine only takes the buffer over once the ArrayBuffer is created.
+        drop(unsafe { alloc::boxed::Box::from_raw(opaque) });
+        return Err(ctx.get_exception_error());
+    }
+    Ok(Value::new_moved(ctx, value))
+}
+
+unsafe extern "C" fn free_external_buffer(
+    _rt: *mut c::JSRuntime,
+    opaque: *mut core::ffi::c_void,
+    _ptr: *mut core::ffi::c_void,
+) {
+    drop(alloc::boxed::Box::from_raw(opaque as *mut Vec<u8>));
+}
+
 impl FromJsValue for JsArrayBuffer {
     fn from_js_value(value: Value) -> Result<Self> {
         if !value.is_array_buffer() {
-            return Err(expect_js_value(&value, "ArrayBuffer"));
+            return Err(expect_err("ArrayBuffer", &value));
         }
         let ctx = value.context()?;
         let mut len = 0;
         let ptr = unsafe { c::JS_GetArrayBuffer(ctx.as_ptr(), &mut len, *value.raw_value()) };
         if ptr.is_null() {
-            return Err(expect_js_value(&value, "Uint8Array"));
+            return Err(expect_err("Uint8Array", &value));
         }
         Ok(JsArrayBuffer {
             value,
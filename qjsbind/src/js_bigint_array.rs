@@ -0,0 +1,177 @@
+use core::ops::Deref;
+
+use alloc::vec::Vec;
+use anyhow::bail;
+
+use crate::{self as js, c, error::expect_err, FromJsValue, GcMark, Result, ToJsValue, Value};
+
+macro_rules! bigint_array {
+    ($name: ident, $elem: ty, $class: ident, $js_name: literal) => {
+        #[doc = concat!("A wrapper of JS ", $js_name, ". The elements are in native byte order and")]
+        /// can be accessed as a slice without converting each of them through a BigInt.
+        #[derive(Clone)]
+        pub struct $name {
+            value: Value,
+            ptr: *const $elem,
+            len: usize,
+        }
+
+        impl core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_struct(stringify!($name))
+                    .field("len", &self.len)
+                    .finish()
+            }
+        }
+
+        impl $name {
+            #[doc = concat!("Create a ", $js_name, " holding a copy of `items`.")]
+            pub fn new(ctx: &js::Context, items: &[$elem]) -> Result<Self> {
+                let buffer = Value::new_moved(ctx, unsafe {
+                    c::JS_NewArrayBufferCopy(
+                        ctx.as_ptr(),
+                        items.as_ptr() as _,
+                        core::mem::size_of_val(items) as _,
+                    )
+                });
+                if buffer.is_exception() {
+                    bail!(
+                        "failed to create ArrayBuffer: {:?}",
+                        ctx.get_exception_error()
+                    );
+                }
+                let array = js::get_global(ctx)
+                    .get_property($js_name)?
+                    .construct(&[buffer])?;
+                Self::from_js_value(array)
+            }
+            pub fn as_slice(&self) -> &[$elem] {
+                if self.len == 0 {
+                    return &[];
+                }
+                unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+            }
+            pub fn to_vec(&self) -> Vec<$elem> {
+                self.as_slice().to_vec()
+            }
+            pub fn is(value: &Value) -> bool {
+                unsafe { c::JS_IsTypeOf(*value.raw_value(), c::$class as _) != 0 }
+            }
+        }
+
+        impl FromJsValue for $name {
+            fn from_js_value(value: Value) -> Result<Self> {
+                if !Self::is(&value) {
+                    return Err(expect_err($js_name, &value));
+                }
+                let (ptr, len) = typed_array_slice::<$elem>(&value)
+                    .ok_or_else(|| expect_err($js_name, &value))?;
+                Ok($name { value, ptr, len })
+            }
+        }
+
+        impl ToJsValue for $name {
+            fn to_js_value(&self, _ctx: &js::Context) -> Result<Value> {
+                Ok(self.value.clone())
+            }
+        }
+
+        impl Deref for $name {
+            type Target = [$elem];
+            fn deref(&self) -> &Self::Target {
+                self.as_slice()
+            }
+        }
+
+        impl GcMark for $name {
+            fn gc_mark(&self, rt: *mut c::JSRuntime, mark_fn: c::JS_MarkFunc) {
+                self.value.gc_mark(rt, mark_fn);
+            }
+        }
+    };
+}
+
+bigint_array!(
+    JsBigInt64Array,
+    i64,
+    JS_CLASS_BIG_INT64_ARRAY,
+    "BigInt64Array"
+);
+bigint_array!(
+    JsBigUint64Array,
+    u64,
+    JS_CLASS_BIG_UINT64_ARRAY,
+    "BigUint64Array"
+);
+
+/// Locate the elements of a typed array in its underlying ArrayBuffer.
+///
+/// Returns `None` if the buffer is detached or the elements are not aligned for `T`.
+fn typed_array_slice<T>(value: &Value) -> Option<(*const T, usize)> {
+    let ctx = value.context().ok()?;
+    let mut offset = 0;
+    let mut byte_len = 0;
+    let mut bytes_per_element = 0;
+    let buffer = Value::new_moved(ctx, unsafe {
+        c::JS_GetTypedArrayBuffer(
+            ctx.as_ptr(),
+            *value.raw_value(),
+            &mut offset,
+            &mut byte_len,
+            &mut bytes_per_element,
+        )
+    });
+    if buffer.is_exception() || bytes_per_element != core::mem::size_of::<T>() as _ {
+        return None;
+    }
+    let len = byte_len / core::mem::size_of::<T>();
+    if len == 0 {
+        return Some((core::ptr::null(), 0));
+    }
+    let mut buffer_len = 0;
+    let base = unsafe { c::JS_GetArrayBuffer(ctx.as_ptr(), &mut buffer_len, *buffer.raw_value()) };
+    if base.is_null() || offset + byte_len > buffer_len {
+        return None;
+    }
+    let ptr = unsafe { base.add(offset) } as *const T;
+    if ptr.align_offset(core::mem::align_of::<T>()) != 0 {
+        return None;
+    }
+    // The typed array holds a reference to the buffer, so the pointer stays valid as long as the
+    // wrapper keeps the typed array alive.
+    Some((ptr, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+
+    #[test]
+    fn round_trips_values_around_i64_max() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let unsigned = vec![0, (1u64 << 63) - 1, 1u64 << 63, (1u64 << 63) + 1, u64::MAX];
+        let value = unsigned.to_js_value(&ctx).unwrap();
+        assert!(JsBigUint64Array::is(&value));
+        assert_eq!(Vec::<u64>::from_js_value(value).unwrap(), unsigned);
+
+        let signed = vec![i64::MIN, -1, 0, i64::MAX - 1, i64::MAX];
+        let value = signed.to_js_value(&ctx).unwrap();
+        assert!(JsBigInt64Array::is(&value));
+        assert_eq!(Vec::<i64>::from_js_value(value).unwrap(), signed);
+    }
+
+    #[test]
+    fn reads_arrays_created_by_scripts() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let value = ctx
+            .eval(&Code::Source(
+                "new BigUint64Array([2n ** 63n - 1n, 2n ** 63n, 2n ** 64n - 1n]).subarray(1)",
+            ))
+            .unwrap();
+        let array = JsBigUint64Array::from_js_value(value).unwrap();
+        assert_eq!(array.as_slice(), &[1u64 << 63, u64::MAX]);
+    }
+}
@@ -2,7 +2,7 @@ use core::ops::Deref;
 
 use alloc::vec::Vec;
 
-use crate::{self as js, c, error::expect_js_value, FromJsValue, GcMark, Result, ToJsValue, Value};
+use crate::{self as js, c, error::expect_err, FromJsValue, GcMark, Result, ToJsValue, Value};
 
 /// A wrapper of JS Uint8Array. When passing a string from JS to Rust, using this type
 /// is more efficient than `Vec<u8>` because it avoids extra memory allocation and copy.
@@ -22,6 +22,12 @@ impl core::fmt::Debug for JsUint8Array {
 }
 
 impl JsUint8Array {
+    pub fn new(ctx: &js::Context, bytes: &[u8]) -> Result<Self> {
+        Self::from_js_value(Value::from_bytes(ctx, bytes))
+    }
+    pub fn is(value: &Value) -> bool {
+        value.is_uint8_array()
+    }
     pub fn as_bytes(&self) -> &[u8] {
         unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
     }
@@ -42,12 +48,12 @@ impl JsUint8Array {
 impl FromJsValue for JsUint8Array {
     fn from_js_value(value: Value) -> Result<Self> {
         if !value.is_uint8_array() {
-            return Err(expect_js_value(&value, "Uint8Array"));
+            return Err(expect_err("Uint8Array", &value));
         }
         let mut len = 0;
         let ptr = unsafe { c::JS_Uint8ArrayGetBuffer(*value.raw_value(), &mut len) };
         if ptr.is_null() {
-            return Err(expect_js_value(&value, "Uint8Array"));
+            return Err(expect_err("Uint8Array", &value));
         }
         Ok(JsUint8Array {
             value,
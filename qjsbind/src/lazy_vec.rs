@@ -0,0 +1,156 @@
+//! Converting the elements of a JS array one at a time.
+
+use core::marker::PhantomData;
+
+use anyhow::Context as _;
+
+use crate::{self as js, FromJsValue, GcMark, Result, ToJsValue, Value};
+
+/// A JS array whose elements are converted as they are read, for host functions that stream over
+/// arrays too large to convert up front like `Vec<T>` does.
+///
+/// The length is taken when the `LazyVec` is created. Elements appended later are not read, and
+/// the ones removed read as `undefined`. An element that fails to convert ends the iteration
+/// with an error naming its index.
+pub struct LazyVec<T> {
+    array: Value,
+    len: usize,
+    next: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: FromJsValue> LazyVec<T> {
+    /// The length of the array when the `LazyVec` was created.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Convert the element at `index`, whatever was read before.
+    pub fn get(&self, index: usize) -> Result<T> {
+        if index >= self.len {
+            anyhow::bail!("index {index} out of bounds, the length is {}", self.len);
+        }
+        T::from_js_value(self.array.index(index)?)
+            .with_context(|| format!("invalid element at index {index}"))
+    }
+
+    /// The array itself.
+    pub fn as_value(&self) -> &Value {
+        &self.array
+    }
+}
+
+impl<T: FromJsValue> Iterator for LazyVec<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+        let item = self.get(self.next);
+        self.next = if item.is_ok() {
+            self.next + 1
+        } else {
+            self.len
+        };
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.len - self.next;
+        (0, Some(left))
+    }
+}
+
+impl<T> core::fmt::Debug for LazyVec<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LazyVec")
+            .field("len", &self.len)
+            .field("next", &self.next)
+            .finish()
+    }
+}
+
+impl<T> FromJsValue for LazyVec<T> {
+    fn from_js_value(value: Value) -> Result<Self> {
+        if !value.is_array() {
+            return Err(crate::impls::not_an_array(&value));
+        }
+        Ok(Self {
+            len: value.length()?,
+            array: value,
+            next: 0,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T> ToJsValue for LazyVec<T> {
+    fn to_js_value(&self, _ctx: &js::Context) -> Result<Value> {
+        Ok(self.array.clone())
+    }
+}
+
+impl<T> GcMark for LazyVec<T> {
+    fn gc_mark(&self, rt: *mut js::c::JSRuntime, mark_fn: js::c::JS_MarkFunc) {
+        self.array.gc_mark(rt, mark_fn);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec::Vec};
+
+    use super::*;
+    use crate::Code;
+
+    #[crate::host_call]
+    fn sum_until(items: LazyVec<u32>, limit: u32) -> Result<u32> {
+        let mut sum = 0;
+        for item in items {
+            sum += item?;
+            if sum >= limit {
+                break;
+            }
+        }
+        Ok(sum)
+    }
+
+    #[test]
+    fn elements_convert_as_they_are_read() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.get_global_object()
+            .define_property_fn("sumUntil", sum_until)
+            .unwrap();
+        let eval = |src: &str| ctx.eval(&Code::Source(src));
+        // Stops before reaching the element that fails to convert.
+        assert_eq!(
+            eval("sumUntil([1, 2, 3, 'x'], 6)").unwrap().to_string(),
+            "6"
+        );
+        let err = eval("sumUntil([1, 2, 3, 'x'], 100)").unwrap_err();
+        assert!(err.contains("invalid element at index 3"), "{err}");
+        assert!(eval("sumUntil(1, 100)").is_err());
+
+        let array = eval("globalThis.list = [1, 2, 3]; list").unwrap();
+        let mut lazy = LazyVec::<Option<u32>>::from_js_value(array).unwrap();
+        assert_eq!(lazy.len(), 3);
+        assert_eq!(lazy.next().unwrap().unwrap(), Some(1));
+        // The length is a snapshot, the elements are read when reached.
+        eval("list.push(4); list[1] = 20; list.length = 2").unwrap();
+        let rest: Vec<_> = lazy.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(rest, [Some(20), None]);
+        assert!(lazy.get(3).is_err());
+
+        let mut failing = LazyVec::<u32>::from_js_value(eval("[1, 'x', 3]").unwrap()).unwrap();
+        assert!(failing.next().unwrap().is_ok());
+        assert!(failing.next().unwrap().is_err());
+        assert!(failing.next().is_none());
+        assert_eq!(failing.get(2).unwrap(), 3);
+    }
+}
@@ -4,49 +4,122 @@
 pub extern crate alloc;
 
 pub use as_bytes::{
-    decode_as_bytes, decode_as_bytes_maybe_hex, encode_as_bytes, AsBytes, Bytes, BytesOrHex,
-    BytesOrString,
+    decode_as_bytes, decode_as_bytes_maybe_hex, encode_as_array, encode_as_bytes, AsBytes, Bytes,
+    BytesOrHex, BytesOrString,
 };
-pub use engine::{Context, Runtime, EngineConfig};
+pub use continuation::{CallOutcome, Continuation, Suspend};
+pub use engine::{Context, Runtime, EngineConfig, WASM_MAX_STACK_SIZE};
 pub use error::{
-    no_std_context::NoStdContext, AnyError, Context as ErrorContext, Error, JsResultExt, Result,
+    expect_err, is_interrupted, is_out_of_memory, no_std_context::NoStdContext, AnyError,
+    Context as ErrorContext, ElementPath, Error, ErrorList, ErrorProperty, ErrorValueExt,
+    ExpectError, Interrupted, JobFailed, JsError, JsResultExt, NonFiniteNumber, OutOfFuel,
+    OutOfMemory, Reentrancy, Result,
 };
-pub use eval::{eval, Code};
-pub use host_function::convert_host_call_result;
+pub use error_report::{ErrorReport, StackFrame};
+pub use eval::{eval, Code, EvalOptions, JsCode};
+pub use host_function::{convert_host_call_result, record_host_call};
+pub use host_registry::FunctionInfo;
+pub use impls::decode_finite;
+pub use js_bigint_array::{JsBigInt64Array, JsBigUint64Array};
 pub use js_string::{JsString, String};
 pub use js_u8array::JsUint8Array;
+pub use lazy_vec::LazyVec;
+pub use lockdown::lockdown;
+pub use memory::{GcEvent, GcHistogram, GcStats, MemoryUsage};
+pub use limits::{ConversionDepth, ConversionGuard, ConversionLimits, LimitExceeded};
 pub use js_arraybuffer::JsArrayBuffer;
 pub use native_object::{
     GcMark, IntoNativeObject, Named, Native, NativeClass, NativeValueRef, NativeValueRefMut, NoGc,
 };
+pub use object_template::{ObjectTemplate, TemplateKey};
+pub use one_or_many::OneOrMany;
+pub use overload::Overloaded;
+#[cfg(feature = "std")]
+pub use actor::JsActor;
+#[cfg(feature = "std")]
+pub use pool::{ContextPool, PoolConfig, PooledContext, ResetGlobals};
 pub use qjs_sys as sys;
+pub use random::SeededRng;
+pub use rename::Convention;
+pub use sandbox::Sandbox;
+pub use source_map::SourceMap;
+pub use time::{Millis, Seconds};
 pub use qjs_sys::c;
-pub use qjsbind_derive::{host_call, qjsbind, FromJsValue, GcMark, ToJsValue};
-pub use traits::{FromArgs, FromJsContext, FromJsValue, OwnedRawArgs, ToArgs, ToJsValue};
-pub use utils::{compile, ctx_to_str, ctx_to_string, recursive_to_string};
-pub use value::{get_global, Value};
+pub use qjsbind_derive::{host_call, qjsbind, FromJsValue, GcMark, ScaleJsType, ToJsValue};
+pub use traits::{
+    FromArgs, FromHostArgs, FromJsContext, FromJsValue, OwnedRawArgs, RawArgs, ToArgs, ToJsValue,
+};
+pub use compile::{compile, Compiled, WriteFlags};
+pub use context_builder::ContextBuilder;
+pub use utils::{ctx_to_str, ctx_to_string, recursive_to_string};
+pub use value::{get_global, TypedArrayKind, Value, OWNED_BYTES_THRESHOLD};
 pub use log;
 
 #[macro_use]
 mod macros;
+#[cfg(feature = "std")]
+mod actor;
 mod as_bytes;
+pub mod audit;
+pub mod bundle;
+mod clone;
+mod continuation;
+mod compile;
+mod context_builder;
+mod coverage;
+pub mod driver;
 mod engine;
 mod error;
+mod error_report;
 mod eval;
 mod host_function;
+mod host_future;
+mod host_registry;
 mod impls;
+mod intrinsics;
+mod js_bigint_array;
 mod js_string;
 mod js_u8array;
 mod js_arraybuffer;
+mod lazy_vec;
+mod limits;
+mod lockdown;
+mod memory;
+mod module_loader;
 mod native_object;
+mod object_template;
+mod one_or_many;
 mod opaque_value;
+mod overload;
+mod own_keys;
+#[cfg(feature = "std")]
+mod pool;
+mod random;
+mod rename;
+pub mod repl;
+mod reset;
+mod sandbox;
+mod small_str;
+mod source_map;
+mod time;
+mod transfer;
 mod traits;
 mod utils;
 mod value;
+mod wrapper;
 
 #[cfg(feature = "json")]
 mod json_value;
 
+#[cfg(feature = "stable-hash")]
+mod stable_hash;
+#[cfg(feature = "stable-hash")]
+pub use stable_hash::HashKind;
+#[cfg(feature = "stable-hash")]
+mod receipt;
+#[cfg(feature = "stable-hash")]
+pub use receipt::{Receipt, ReceiptEntry, ReceiptOptions};
+
 #[cfg(feature = "tynm")]
 use tynm::type_name;
 
@@ -0,0 +1,429 @@
+use alloc::{rc::Rc, vec::Vec};
+use core::cell::{Cell, RefCell};
+
+use anyhow::bail;
+
+use crate::{self as js, Error, ErrorContext, Result, Value};
+
+/// Upper bounds applied when converting JS values into Rust values.
+///
+/// The limits are stored per context, see `Context::set_conversion_limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionLimits {
+    /// Maximum length in bytes of a string or a byte buffer.
+    pub max_string_bytes: usize,
+    /// Maximum length of an array converted to a `Vec`.
+    pub max_array_len: usize,
+    /// Maximum nesting depth of arrays and derived structs, in both directions.
+    pub max_depth: usize,
+}
+
+impl Default for ConversionLimits {
+    fn default() -> Self {
+        Self {
+            max_string_bytes: 256 * 1024 * 1024,
+            max_array_len: 16 * 1024 * 1024,
+            max_depth: 128,
+        }
+    }
+}
+
+/// The error returned when a conversion trips one of the `ConversionLimits`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub limit: &'static str,
+    pub max: usize,
+    pub actual: usize,
+}
+
+impl core::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "conversion limit {} exceeded: {} > {}",
+            self.limit, self.actual, self.max
+        )
+    }
+}
+
+/// The conversion limits of a context and the derived conversion in progress, held by the
+/// context data so that entering a conversion needs no lookup.
+#[derive(Default)]
+pub(crate) struct LimitsState {
+    limits: Cell<ConversionLimits>,
+    depth: Cell<usize>,
+    /// The values being converted by types that detect cycles, by type name and address, as
+    /// a value and its first field can share an address.
+    visiting: RefCell<Vec<(&'static str, usize)>>,
+}
+
+fn check(limit: &'static str, max: usize, actual: usize) -> Result<()> {
+    if actual > max {
+        return Err(Error::msg(LimitExceeded { limit, max, actual }));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_string_bytes(ctx: &js::Context, len: usize) -> Result<()> {
+    check(
+        "max_string_bytes",
+        ctx.conversion_limits().max_string_bytes,
+        len,
+    )
+}
+
+/// Check the string `value` against `max_string_bytes` before converting it, from its length
+/// in UTF-16 code units, which its UTF-8 encoding is never shorter than.
+pub(crate) fn check_string_len(value: &Value) -> Result<()> {
+    check_string_bytes(value.context()?, value.length()?)
+}
+
+pub(crate) fn check_array_len(ctx: &js::Context, len: usize) -> Result<()> {
+    check("max_array_len", ctx.conversion_limits().max_array_len, len)
+}
+
+impl js::Context {
+    pub fn set_conversion_limits(&self, limits: ConversionLimits) -> Result<()> {
+        let state = self
+            .limits_state()
+            .context("no conversion limits for a context without teardown support")?;
+        state.limits.set(limits);
+        Ok(())
+    }
+
+    pub fn conversion_limits(&self) -> ConversionLimits {
+        self.limits_state()
+            .map(|state| state.limits.get())
+            .unwrap_or_default()
+    }
+
+    /// Put back `limits`, as kept by a snapshot of the context.
+    pub(crate) fn restore_conversion_limits(&self, limits: ConversionLimits) {
+        if let Some(state) = self.limits_state() {
+            state.limits.set(limits);
+        }
+    }
+}
+
+/// How deep a value is nested in the value being converted from JS, passed down by
+/// [`FromJsValue::from_js_value_depth`](crate::FromJsValue::from_js_value_depth).
+///
+/// Arrays and derived structs convert their items one level deeper, failing past
+/// `ConversionLimits::max_depth`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionDepth {
+    level: usize,
+    max: usize,
+}
+
+impl ConversionDepth {
+    /// The depth of `value` converted on its own, limited by its context.
+    pub fn of(value: &Value) -> Self {
+        let limits = match value.context() {
+            Ok(ctx) => ctx.conversion_limits(),
+            Err(_) => ConversionLimits::default(),
+        };
+        Self {
+            level: 0,
+            max: limits.max_depth,
+        }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// The depth of the items of an array at this depth.
+    pub fn enter(self) -> Result<Self> {
+        let level = self.level + 1;
+        check("max_depth", self.max, level)?;
+        Ok(Self { level, ..self })
+    }
+
+    /// The depth of the fields of `type_name` at this depth. Used by the generated code.
+    ///
+    /// `max_depth` comes from `#[qjs(max_depth = N)]`, refusing to convert the type nested
+    /// deeper than `N` even if the context allows more.
+    #[doc(hidden)]
+    pub fn enter_derived(self, type_name: &'static str, max_depth: Option<usize>) -> Result<Self> {
+        let level = self.level + 1;
+        let max = self.max.min(max_depth.unwrap_or(usize::MAX));
+        check("max_depth", max, level).map_err(|err| {
+            err.context(alloc::format!(
+                "{type_name} is nested deeper than {max} levels"
+            ))
+        })?;
+        Ok(Self { level, ..self })
+    }
+}
+
+/// Tracks the nesting depth of a derived `ToJsValue` conversion in progress, released on drop.
+#[doc(hidden)]
+pub struct ConversionGuard {
+    state: Option<Rc<LimitsState>>,
+    visiting: Option<(&'static str, usize)>,
+}
+
+impl ConversionGuard {
+    /// Enter the conversion of `type_name`. Used by the generated code.
+    ///
+    /// `max_depth` is as in [`ConversionDepth::enter_derived`]. `address` is the value being
+    /// converted by a `#[qjs(detect_cycles)]` type, which fails if the same value is already
+    /// being converted further up.
+    pub fn enter(
+        ctx: &js::Context,
+        type_name: &'static str,
+        max_depth: Option<usize>,
+        address: Option<*const ()>,
+    ) -> Result<Self> {
+        let Some(state) = ctx.limits_state().cloned() else {
+            return Ok(Self {
+                state: None,
+                visiting: None,
+            });
+        };
+        let depth = state.depth.get() + 1;
+        let max = state
+            .limits
+            .get()
+            .max_depth
+            .min(max_depth.unwrap_or(usize::MAX));
+        check("max_depth", max, depth).map_err(|err| {
+            err.context(alloc::format!(
+                "{type_name} is nested deeper than {max} levels"
+            ))
+        })?;
+        let key = address.map(|ptr| (type_name, ptr as usize));
+        if let Some(key) = key {
+            let mut visiting = state.visiting.borrow_mut();
+            if visiting.contains(&key) {
+                bail!("cycle detected while converting {type_name}");
+            }
+            visiting.push(key);
+        }
+        state.depth.set(depth);
+        Ok(Self {
+            state: Some(state),
+            visiting: key,
+        })
+    }
+}
+
+impl Drop for ConversionGuard {
+    fn drop(&mut self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        state.depth.set(state.depth.get().saturating_sub(1));
+        if let Some(key) = self.visiting {
+            state.visiting.borrow_mut().retain(|k| *k != key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Code, FromJsValue, ToJsValue};
+    use alloc::{rc::Rc, string::String, string::ToString};
+
+    #[derive(Debug, Default, crate::ToJsValue, crate::FromJsValue)]
+    struct Tree {
+        children: Vec<Tree>,
+    }
+
+    #[derive(Debug, crate::FromJsValue)]
+    #[qjs(max_depth = 16)]
+    struct Shallow {
+        children: Vec<Shallow>,
+    }
+
+    #[derive(Debug, crate::FromJsValue)]
+    struct Dir {
+        entries: alloc::collections::BTreeMap<String, Dir>,
+    }
+
+    #[derive(crate::ToJsValue)]
+    #[qjs(detect_cycles)]
+    struct Node {
+        name: String,
+        next: Option<Rc<RefCell<Node>>>,
+    }
+
+    #[derive(crate::ToJsValue)]
+    #[qjs(detect_cycles)]
+    struct Head {
+        node: Node,
+    }
+
+    fn has_cause(err: &Error, message: &str) -> bool {
+        err.chain().any(|cause| cause.to_string() == message)
+    }
+
+    #[test]
+    fn deep_nesting_fails_with_the_type_name() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let nested = |depth: usize| {
+            let src = alloc::format!(
+                "let v = {{ children: [] }}; for (let i = 1; i < {depth}; i++) v = {{ children: [v] }}; v"
+            );
+            ctx.eval(&Code::Source(&src)).unwrap()
+        };
+        let err = Tree::from_js_value(nested(10_000)).unwrap_err();
+        assert!(
+            has_cause(&err, "Tree is nested deeper than 128 levels"),
+            "{err:#}"
+        );
+        assert!(err.downcast_ref::<LimitExceeded>().is_some());
+        assert!(Tree::from_js_value(nested(8)).is_ok());
+
+        let err = Shallow::from_js_value(nested(9)).unwrap_err();
+        assert!(
+            has_cause(&err, "Shallow is nested deeper than 16 levels"),
+            "{err:#}"
+        );
+        assert!(Shallow::from_js_value(nested(8)).is_ok());
+
+        let mut tree = Tree::default();
+        for _ in 0..10_000 {
+            tree = Tree {
+                children: alloc::vec![tree],
+            };
+        }
+        let err = tree.to_js_value(&ctx).unwrap_err();
+        assert!(
+            has_cause(&err, "Tree is nested deeper than 128 levels"),
+            "{err:#}"
+        );
+        // Dropping the tree recursively would overflow the stack as well.
+        while let Some(child) = tree.children.pop() {
+            tree = child;
+        }
+        // The depth is released after a failure.
+        assert!(Tree::from_js_value(nested(8)).is_ok());
+    }
+
+    #[test]
+    fn depth_is_passed_down_through_maps() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let src =
+            "let v = { entries: {} }; for (let i = 1; i < 10000; i++) v = { entries: { a: v } }; v";
+        let err = Dir::from_js_value(ctx.eval(&Code::Source(src)).unwrap()).unwrap_err();
+        assert!(
+            has_cause(&err, "Dir is nested deeper than 128 levels"),
+            "{err:#}"
+        );
+
+        let value = ctx.eval(&Code::Source("({ entries: {} })")).unwrap();
+        let depth = ConversionDepth::of(&value);
+        assert_eq!(depth.level(), 0);
+        let mut deep = depth;
+        for _ in 0..128 {
+            deep = deep.enter().unwrap();
+        }
+        assert!(Dir::from_js_value_depth(value.clone(), depth).is_ok());
+        let err = Dir::from_js_value_depth(value, deep).unwrap_err();
+        assert!(err.downcast_ref::<LimitExceeded>().is_some(), "{err:#}");
+    }
+
+    fn limit_of(err: &Error) -> &'static str {
+        err.downcast_ref::<LimitExceeded>()
+            .map(|err| err.limit)
+            .unwrap_or_else(|| panic!("not a limit error: {err:#}"))
+    }
+
+    #[test]
+    fn oversized_values_are_refused() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.set_conversion_limits(ConversionLimits {
+            max_string_bytes: 8,
+            max_array_len: 4,
+            ..Default::default()
+        })
+        .unwrap();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+
+        assert_eq!(
+            String::from_js_value(eval("'12345678'")).unwrap(),
+            "12345678"
+        );
+        let err = String::from_js_value(eval("'123456789'")).unwrap_err();
+        assert_eq!(limit_of(&err), "max_string_bytes");
+        let err = js::JsString::from_js_value(eval("'123456789'")).unwrap_err();
+        assert_eq!(limit_of(&err), "max_string_bytes");
+        // Four code units, but twelve bytes once encoded.
+        let err = String::from_js_value(eval("'\\u4e2d\\u6587\\u4e2d\\u6587'")).unwrap_err();
+        assert_eq!(limit_of(&err), "max_string_bytes");
+
+        let err = eval("new Uint8Array(9)").decode_bytes().unwrap_err();
+        assert_eq!(limit_of(&err), "max_string_bytes");
+        let err = eval("new Array(9).fill(0)").decode_bytes().unwrap_err();
+        assert_eq!(limit_of(&err), "max_string_bytes");
+
+        assert!(Vec::<u32>::from_js_value(eval("[1, 2, 3, 4]")).is_ok());
+        let err = Vec::<u32>::from_js_value(eval("[1, 2, 3, 4, 5]")).unwrap_err();
+        assert_eq!(limit_of(&err), "max_array_len");
+        // The length is checked before any element is read.
+        let err =
+            Vec::<u32>::from_js_value(eval("const a = []; a.length = 2 ** 32 - 1; a")).unwrap_err();
+        assert_eq!(limit_of(&err), "max_array_len");
+    }
+
+    #[test]
+    fn scripts_can_not_reset_the_limits() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let limits = ConversionLimits {
+            max_string_bytes: 8,
+            ..Default::default()
+        };
+        ctx.set_conversion_limits(limits).unwrap();
+        ctx.eval(&Code::Source(
+            "for (const key of Object.getOwnPropertyNames(globalThis)) delete globalThis[key];",
+        ))
+        .unwrap();
+        assert_eq!(ctx.conversion_limits(), limits);
+    }
+
+    #[test]
+    fn cycles_are_detected_on_opt_in() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let node = |name: &str, next| {
+            Rc::new(RefCell::new(Node {
+                name: name.into(),
+                next,
+            }))
+        };
+        let shared = node("shared", None);
+        let a = node("a", Some(shared.clone()));
+        let b = node("b", Some(a.clone()));
+        assert!(b.to_js_value(&ctx).is_ok());
+
+        shared.borrow_mut().next = Some(b.clone());
+        let err = b.to_js_value(&ctx).unwrap_err();
+        assert_eq!(err.to_string(), "cycle detected while converting Node");
+        shared.borrow_mut().next = None;
+        assert!(a.to_js_value(&ctx).is_ok());
+    }
+
+    #[test]
+    fn a_value_and_its_field_at_the_same_address_are_no_cycle() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let head = Head {
+            node: Node {
+                name: "node".into(),
+                next: None,
+            },
+        };
+        assert_eq!(
+            &head as *const Head as usize,
+            &head.node as *const Node as usize
+        );
+        assert!(head.to_js_value(&ctx).is_ok());
+    }
+}
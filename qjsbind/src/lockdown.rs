@@ -0,0 +1,192 @@
+use alloc::vec::Vec;
+
+use crate::own_keys::{own_keys, Atom};
+use crate::{self as js, c, Result, Value};
+
+/// Deep-freeze the globals `names`, so that later scripts in the context can not replace what
+/// they hold, like `crypto.subtle.encrypt`, to intercept the data passed by other calls.
+///
+/// Every object reachable from them is made non-extensible and its own properties,
+/// string-keyed and symbol-keyed, non-configurable and, for data properties, non-writable.
+/// This covers functions and their properties, and prototypes. The global bindings themselves
+/// are frozen the same way. Assignments then throw a TypeError in strict mode and are ignored
+/// otherwise.
+///
+/// The objects shared with the rest of the realm, the other globals and their `prototype`s
+/// like `Object.prototype` or `Uint8Array.prototype`, are left alone, as are the objects passed
+/// to [`Context::exempt_from_lockdown`]. Missing globals are skipped.
+pub fn lockdown(ctx: &js::Context, names: &[&str]) -> Result<()> {
+    let global = ctx.get_global_object();
+    let mut walk = Walk {
+        ctx,
+        seen: exempt_list(ctx)?,
+    };
+    for key in own_keys(ctx, &global)? {
+        let is_locked = key
+            .name
+            .as_deref()
+            .is_some_and(|name| names.contains(&name));
+        if is_locked {
+            continue;
+        }
+        let value = global.get_property_atom(key.atom.raw())?;
+        if !value.is_object() {
+            continue;
+        }
+        let prototype = value.get_property("prototype")?;
+        if prototype.is_object() {
+            walk.seen.push(prototype);
+        }
+        walk.seen.push(value);
+    }
+    for name in names {
+        let value = global.get_property(name)?;
+        if value.is_undefined() {
+            continue;
+        }
+        walk.freeze(&value)?;
+        let key = Atom::new(ctx, name);
+        freeze_property(ctx, &global, key.raw())?;
+    }
+    Ok(())
+}
+
+impl js::Context {
+    /// Leave `value` mutable when it is reached by [`lockdown`], for a native object whose
+    /// properties are meant to be replaced. The objects it holds are left alone too.
+    pub fn exempt_from_lockdown(&self, value: &Value) -> Result<()> {
+        self.host_object("lockdownExempt", || Ok(Value::new_array(self)))?
+            .array_push(value)
+    }
+}
+
+fn exempt_list(ctx: &js::Context) -> Result<Vec<Value>> {
+    let list = ctx.host_object("lockdownExempt", || Ok(Value::new_array(ctx)))?;
+    (0..list.length()?).map(|i| list.index(i)).collect()
+}
+
+struct Walk<'a> {
+    ctx: &'a js::Context,
+    /// The objects not to freeze: the exempt ones and the intrinsics, then the frozen ones.
+    seen: Vec<Value>,
+}
+
+impl Walk<'_> {
+    fn freeze(&mut self, value: &Value) -> Result<()> {
+        if !value.is_object() || self.seen.iter().any(|seen| seen.ptr_eq(value)) {
+            return Ok(());
+        }
+        self.seen.push(value.clone());
+        let ctx = self.ctx;
+        if unsafe { c::JS_PreventExtensions(ctx.as_ptr(), *value.raw_value()) } < 0 {
+            return Err(ctx.get_exception_error());
+        }
+        for key in own_keys(ctx, value)? {
+            for held in freeze_property(ctx, value, key.atom.raw())? {
+                self.freeze(&held)?;
+            }
+        }
+        self.freeze(&value.get_prototype()?)
+    }
+}
+
+/// Make the property `atom` of `obj` non-configurable and, if it is a data property,
+/// non-writable. Returns the values it holds: its value, or its getter and setter.
+fn freeze_property(ctx: &js::Context, obj: &Value, atom: c::JSAtom) -> Result<Vec<Value>> {
+    let mut desc: c::JSPropertyDescriptor = unsafe { core::mem::zeroed() };
+    let found = unsafe { c::JS_GetOwnProperty(ctx.as_ptr(), &mut desc, *obj.raw_value(), atom) };
+    if found < 0 {
+        return Err(ctx.get_exception_error());
+    }
+    if found == 0 {
+        return Ok(Vec::new());
+    }
+    let value = Value::new_moved(ctx, desc.value);
+    let getter = Value::new_moved(ctx, desc.getter);
+    let setter = Value::new_moved(ctx, desc.setter);
+    let is_accessor = desc.flags as u32 & c::JS_PROP_GETSET != 0;
+    let mut flags = c::JS_PROP_HAS_CONFIGURABLE | c::JS_PROP_THROW;
+    if !is_accessor {
+        flags |= c::JS_PROP_HAS_WRITABLE;
+    }
+    let undefined = Value::undefined();
+    let r = unsafe {
+        c::JS_DefineProperty(
+            ctx.as_ptr(),
+            *obj.raw_value(),
+            atom,
+            *undefined.raw_value(),
+            *undefined.raw_value(),
+            *undefined.raw_value(),
+            flags as _,
+        )
+    };
+    if r < 0 {
+        return Err(ctx.get_exception_error());
+    }
+    Ok(if is_accessor {
+        alloc::vec![getter, setter]
+    } else {
+        alloc::vec![value]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locked_globals_can_not_be_patched() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&js::Code::Source(src));
+        eval(
+            r#"
+            class Codec { encode() { return "real"; } }
+            globalThis.ns = {
+                subtle: { encrypt: () => "real" },
+                codec: new Codec(),
+                bytes: new Uint8Array(1),
+                cache: {},
+            };
+            "#,
+        )
+        .unwrap();
+        let cache = eval("ns.cache").unwrap();
+        ctx.exempt_from_lockdown(&cache).unwrap();
+        // Scripts can not add themselves to the exempt list, it is kept by the host.
+        let reachable = eval("typeof _QjsBind === 'object' && 'lockdownExempt' in _QjsBind");
+        assert!(!reachable.unwrap().decode_bool().unwrap());
+        lockdown(&ctx, &["ns", "missing"]).unwrap();
+
+        let sloppy = eval(
+            r#"
+            ns.subtle.encrypt = () => "stolen";
+            ns.codec.encode = () => "stolen";
+            Object.getPrototypeOf(ns.codec).encode = () => "stolen";
+            ns.subtle.extra = 1;
+            ns = null;
+            ns.cache.entry = 1;
+            [ns.subtle.encrypt(), ns.codec.encode(), ns.subtle.extra, ns.cache.entry].join()
+            "#,
+        )
+        .unwrap();
+        assert_eq!(sloppy.decode_string().unwrap(), "real,real,,1");
+
+        let strict = eval(r#""use strict"; ns.subtle.encrypt = () => "stolen";"#).unwrap_err();
+        assert!(strict.contains("TypeError"), "{strict}");
+        let redefine = eval(r#"Object.defineProperty(ns.subtle, "encrypt", { value: 1 })"#);
+        assert!(redefine.is_err());
+
+        // The intrinsics shared with the rest of the realm stay mutable.
+        let shared = eval(
+            r#"
+            Uint8Array.prototype.tag = 1;
+            Object.prototype.tag2 = 2;
+            [ns.bytes.tag, Object.isFrozen(Object.prototype)].join()
+            "#,
+        )
+        .unwrap();
+        assert_eq!(shared.decode_string().unwrap(), "1,false");
+    }
+}
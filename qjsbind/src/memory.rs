@@ -0,0 +1,274 @@
+use alloc::rc::Rc;
+use core::cell::{Cell, RefCell};
+use core::time::Duration;
+
+use crate::{c, Runtime};
+
+/// A snapshot of the memory of a runtime, see [`Runtime::memory_usage`].
+///
+/// Sizes are in bytes. The counts and sizes of each kind of item are estimates made by walking
+/// the heap, `malloc_size` and `malloc_count` are what the allocator is holding.
+#[derive(Debug, Clone, Default, PartialEq, Eq, crate::ToJsValue)]
+#[qjs(rename_all = "camelCase")]
+pub struct MemoryUsage {
+    pub malloc_size: i64,
+    /// The cap set with [`Runtime::set_memory_limit`], `-1` without one.
+    pub malloc_limit: i64,
+    pub memory_used_size: i64,
+    pub malloc_count: i64,
+    pub memory_used_count: i64,
+    pub atom_count: i64,
+    pub atom_size: i64,
+    pub str_count: i64,
+    pub str_size: i64,
+    pub obj_count: i64,
+    pub obj_size: i64,
+    pub prop_count: i64,
+    pub prop_size: i64,
+    pub shape_count: i64,
+    pub shape_size: i64,
+    /// Bytecode functions.
+    pub js_func_count: i64,
+    pub js_func_size: i64,
+    pub js_func_code_size: i64,
+    pub js_func_pc2line_count: i64,
+    pub js_func_pc2line_size: i64,
+    /// Host functions.
+    pub c_func_count: i64,
+    pub array_count: i64,
+    pub fast_array_count: i64,
+    pub fast_array_elements: i64,
+    /// Array buffers and typed arrays.
+    pub binary_object_count: i64,
+    pub binary_object_size: i64,
+}
+
+impl Runtime {
+    /// The memory used by the runtime and all its contexts.
+    ///
+    /// This walks the whole heap, it is meant for monitoring rather than for every call.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage: c::JSMemoryUsage = unsafe { core::mem::zeroed() };
+        unsafe { c::JS_ComputeMemoryUsage(self.as_ptr(), &mut usage) };
+        MemoryUsage {
+            malloc_size: usage.malloc_size as _,
+            malloc_limit: usage.malloc_limit as _,
+            memory_used_size: usage.memory_used_size as _,
+            malloc_count: usage.malloc_count as _,
+            memory_used_count: usage.memory_used_count as _,
+            atom_count: usage.atom_count as _,
+            atom_size: usage.atom_size as _,
+            str_count: usage.str_count as _,
+            str_size: usage.str_size as _,
+            obj_count: usage.obj_count as _,
+            obj_size: usage.obj_size as _,
+            prop_count: usage.prop_count as _,
+            prop_size: usage.prop_size as _,
+            shape_count: usage.shape_count as _,
+            shape_size: usage.shape_size as _,
+            js_func_count: usage.js_func_count as _,
+            js_func_size: usage.js_func_size as _,
+            js_func_code_size: usage.js_func_code_size as _,
+            js_func_pc2line_count: usage.js_func_pc2line_count as _,
+            js_func_pc2line_size: usage.js_func_pc2line_size as _,
+            c_func_count: usage.c_func_count as _,
+            array_count: usage.array_count as _,
+            fast_array_count: usage.fast_array_count as _,
+            fast_array_elements: usage.fast_array_elements as _,
+            binary_object_count: usage.binary_object_count as _,
+            binary_object_size: usage.binary_object_size as _,
+        }
+    }
+}
+
+/// The bytes the allocator of `rt` is holding.
+pub(crate) fn malloc_size(rt: *mut c::JSRuntime) -> usize {
+    unsafe { c::JS_GetMallocSize(rt) }
+}
+
+/// What the engine records of a runtime running out of memory, see `oom_hook`.
+#[derive(Default)]
+pub(crate) struct AllocState {
+    out_of_memory: Cell<bool>,
+}
+
+impl AllocState {
+    /// Called by the engine when it throws its out of memory error, because an allocation
+    /// failed for the memory limit or in the allocator.
+    pub(crate) fn set_out_of_memory(&self) {
+        self.out_of_memory.set(true);
+    }
+
+    /// Whether the engine ran out of memory since the last call.
+    pub(crate) fn take_out_of_memory(&self) -> bool {
+        self.out_of_memory.replace(false)
+    }
+}
+
+/// A garbage collection, reported to [`Runtime::set_gc_observer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcEvent {
+    /// A collection is about to run, with the bytes allocated before it.
+    Begin { malloc_size: usize },
+    /// The collection is over, finalizers included.
+    End {
+        duration: Duration,
+        /// The bytes freed, the allocated size before the collection minus the one after.
+        reclaimed: usize,
+        malloc_size: usize,
+    },
+}
+
+/// What a [`GcHistogram`] recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: u64,
+    pub reclaimed: u64,
+    pub total_duration: Duration,
+    pub max_duration: Duration,
+    /// The collections by duration: the first bucket counts the ones under a microsecond, and
+    /// bucket `i` the ones from 2^(i-1) up to 2^i microseconds. The last one counts all those
+    /// from about 16 ms up.
+    pub buckets: [u64; GcHistogram::BUCKETS],
+}
+
+/// A GC observer counting collections by duration.
+///
+/// ```ignore
+/// let histogram = GcHistogram::new();
+/// runtime.set_gc_observer(histogram.observer());
+/// // ...
+/// log::info!("{:?}", histogram.stats());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GcHistogram {
+    stats: Rc<RefCell<GcStats>>,
+}
+
+impl GcHistogram {
+    pub const BUCKETS: usize = 16;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The observer to give to [`Runtime::set_gc_observer`], recording into this histogram.
+    pub fn observer(&self) -> impl Fn(GcEvent) + 'static {
+        let stats = self.stats.clone();
+        move |event| {
+            let GcEvent::End {
+                duration,
+                reclaimed,
+                ..
+            } = event
+            else {
+                return;
+            };
+            let mut stats = stats.borrow_mut();
+            let micros = duration.as_micros();
+            let bucket = (u128::BITS - micros.leading_zeros()) as usize;
+            stats.buckets[bucket.min(Self::BUCKETS - 1)] += 1;
+            stats.collections += 1;
+            stats.reclaimed += reclaimed as u64;
+            stats.total_duration += duration;
+            stats.max_duration = stats.max_duration.max(duration);
+        }
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats.borrow().clone()
+    }
+
+    pub fn reset(&self) {
+        *self.stats.borrow_mut() = GcStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Code, Runtime, ToJsValue};
+
+    #[test]
+    fn allocations_show_in_malloc_size() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let before = runtime.memory_usage();
+        ctx.eval(&Code::Source(
+            "globalThis.kept = Array.from({ length: 10000 }, (_, i) => ({ i }));",
+        ))
+        .unwrap();
+        let after = runtime.memory_usage();
+        assert!(
+            after.malloc_size > before.malloc_size,
+            "{before:?} {after:?}"
+        );
+        assert!(after.obj_count >= before.obj_count + 10000);
+
+        let usage = after.to_js_value(&ctx).unwrap();
+        let obj_count: i64 = usage.get_property_t("objCount").unwrap();
+        assert_eq!(obj_count, after.obj_count);
+    }
+
+    #[test]
+    fn gc_observer_sees_reclaimed_cycles() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        // Only the collection run below.
+        runtime.set_gc_threshold(usize::MAX);
+        let histogram = GcHistogram::new();
+        let events = Rc::new(RefCell::new(alloc::vec::Vec::new()));
+        runtime.set_gc_observer({
+            let events = events.clone();
+            let record = histogram.observer();
+            move |event| {
+                events.borrow_mut().push(event);
+                record(event);
+            }
+        });
+        // Cycles are only freed by a collection.
+        ctx.eval(&Code::Source(
+            "for (let i = 0; i < 1000; i++) {
+                const a = { data: 'x'.repeat(1000) + i };
+                a.self = a;
+            }",
+        ))
+        .unwrap();
+        runtime.run_gc();
+
+        let events = events.borrow();
+        let [GcEvent::Begin { malloc_size }, GcEvent::End {
+            reclaimed,
+            malloc_size: after,
+            ..
+        }] = events[..]
+        else {
+            panic!("{events:?}");
+        };
+        assert!(reclaimed > 0, "{events:?}");
+        assert_eq!(after, malloc_size - reclaimed);
+        let stats = histogram.stats();
+        assert_eq!(stats.collections, 1);
+        assert_eq!(stats.reclaimed, reclaimed as u64);
+        assert_eq!(stats.buckets.iter().sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn gc_observer_sees_automatic_collections() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        runtime.set_gc_threshold(64 * 1024);
+        let histogram = GcHistogram::new();
+        runtime.set_gc_observer(histogram.observer());
+        ctx.eval(&Code::Source(
+            "for (let i = 0; i < 1000; i++) {
+                const a = { data: 'x'.repeat(1000) + i };
+                a.self = a;
+            }",
+        ))
+        .unwrap();
+        let stats = histogram.stats();
+        assert!(stats.collections > 0, "{stats:?}");
+        assert!(stats.reclaimed > 0, "{stats:?}");
+    }
+}
@@ -0,0 +1,317 @@
+//! ES modules: evaluating them, and loading the modules they import from the host.
+
+use alloc::ffi::CString;
+use alloc::rc::Rc;
+use alloc::string::String;
+use core::ffi::{c_char, c_void, CStr};
+
+use anyhow::{anyhow, bail, Context as _};
+
+use crate::{c, Context, JsCode, Result, Runtime, Value};
+
+type LoadFn = dyn Fn(&Context, &str) -> Result<JsCode>;
+type NormalizeFn = dyn Fn(&Context, &str, &str) -> Result<String>;
+
+/// The module hooks of a runtime, kept in its `RuntimeData`.
+#[derive(Default)]
+pub(crate) struct ModuleHooks {
+    load: Option<Rc<LoadFn>>,
+    normalize: Option<Rc<NormalizeFn>>,
+}
+
+impl Runtime {
+    /// Load the modules imported by scripts, with `import` declarations or `import()`, by calling
+    /// `loader` with the normalized module name. It returns the source of the module, or its
+    /// bytecode as compiled by [`compile`](crate::compile). An error fails the import with it.
+    ///
+    /// A context loads each module once, and later imports of the same name get the same
+    /// module. The loader replaces the previous one, and is dropped with the runtime.
+    pub fn set_module_loader(&self, loader: impl Fn(&Context, &str) -> Result<JsCode> + 'static) {
+        self.module_hooks().load = Some(Rc::new(loader));
+        self.install_module_hooks();
+    }
+
+    /// Turn the specifier `name` imported by the module `base` into the name given to the module
+    /// loader, called as `normalizer(ctx, base, name)`.
+    ///
+    /// Without a normalizer, specifiers starting with `./` or `../` are resolved against the
+    /// directory of `base`, and the others are taken as they are.
+    pub fn set_module_normalizer(
+        &self,
+        normalizer: impl Fn(&Context, &str, &str) -> Result<String> + 'static,
+    ) {
+        self.module_hooks().normalize = Some(Rc::new(normalizer));
+        self.install_module_hooks();
+    }
+
+    fn install_module_hooks(&self) {
+        let hooks = self.module_hooks();
+        let normalize: c::JSModuleNormalizeFunc = match hooks.normalize {
+            Some(_) => Some(normalize_module_name),
+            None => None,
+        };
+        let load: c::JSModuleLoaderFunc = match hooks.load {
+            Some(_) => Some(load_module),
+            None => None,
+        };
+        unsafe {
+            c::JS_SetModuleLoaderFunc(self.as_ptr(), normalize, load, core::ptr::null_mut());
+        }
+    }
+}
+
+unsafe extern "C" fn load_module(
+    ctx: *mut c::JSContext,
+    name: *const c_char,
+    _opaque: *mut c_void,
+) -> *mut c::JSModuleDef {
+    let Some(ctx) = Context::clone_from_ptr(ctx) else {
+        return core::ptr::null_mut();
+    };
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+    let Some(load) = ctx.module_hooks().and_then(|hooks| hooks.load.clone()) else {
+        ctx.throw_str(&format!("no loader for module '{name}'"));
+        return core::ptr::null_mut();
+    };
+    let module = load(&ctx, &name).and_then(|code| compile_module(&ctx, &name, &code));
+    match module {
+        // The context keeps the module in its list of loaded modules, the value was only another
+        // reference to it.
+        Ok(module) => unsafe { c::JS_GetPtr(*module.raw_value()) as *mut c::JSModuleDef },
+        Err(err) => {
+            ctx.throw(err.context(format!("failed to load module '{name}'")));
+            core::ptr::null_mut()
+        }
+    }
+}
+
+/// Compile a module without linking or evaluating it.
+fn compile_module(ctx: &Context, name: &str, code: &JsCode) -> Result<Value> {
+    #[cfg(feature = "stable-hash")]
+    crate::receipt::record_eval(ctx, &code.as_code());
+    let module = match code {
+        JsCode::Source(src) => {
+            let code = CString::new(src.as_str()).or(Err(anyhow!("NUL in the source")))?;
+            let filename = CString::new(name).or(Err(anyhow!("NUL in the module name")))?;
+            ctx.retain_source(name, 1, src);
+            unsafe {
+                c::JS_Eval(
+                    ctx.as_ptr(),
+                    code.as_ptr() as _,
+                    src.len() as _,
+                    filename.as_ptr() as _,
+                    (c::JS_EVAL_TYPE_MODULE | c::JS_EVAL_FLAG_COMPILE_ONLY) as _,
+                )
+            }
+        }
+        JsCode::Bytecode(bytes) => {
+            crate::compile::check_bytecode_version(ctx, bytes)?;
+            unsafe {
+                c::JS_ReadObject(
+                    ctx.as_ptr(),
+                    bytes.as_ptr() as _,
+                    bytes.len() as _,
+                    c::JS_READ_OBJ_BYTECODE as _,
+                )
+            }
+        }
+    };
+    let module = Value::new_moved(ctx, module);
+    if module.is_exception() {
+        return Err(ctx.get_exception_error());
+    }
+    if unsafe { c::JS_GetTag(*module.raw_value()) } != c::JS_TAG_MODULE as i64 {
+        bail!("the bytecode is not a module");
+    }
+    Ok(module)
+}
+
+impl Context {
+    /// Evaluate `code` as an ES module named `<module>` and return its namespace object, see
+    /// [`eval_named_module`](Self::eval_named_module).
+    pub fn eval_module(&self, code: &JsCode) -> Result<Value> {
+        self.eval_named_module("<module>", code)
+    }
+
+    /// Evaluate `code` as an ES module named `name` and return its namespace object, whose
+    /// properties are the exports, `default` included.
+    ///
+    /// The pending jobs are run until none are left, so that a module using top-level `await`
+    /// has finished. The modules it imports are loaded by the loader of
+    /// [`Runtime::set_module_loader`], with `name` as the base of relative specifiers. Errors,
+    /// from a syntax error to a failed import or a throw during evaluation, name the module.
+    pub fn eval_named_module(&self, name: &str, code: &JsCode) -> Result<Value> {
+        let namespace = self.eval_module_namespace(name, code);
+        #[cfg(feature = "stable-hash")]
+        crate::receipt::record_result(self, namespace.as_ref().ok());
+        namespace
+    }
+
+    fn eval_module_namespace(&self, name: &str, code: &JsCode) -> Result<Value> {
+        let failed = || format!("failed to evaluate module '{name}'");
+        let module = compile_module(self, name, code).with_context(failed)?;
+        let ptr = unsafe { c::JS_GetPtr(*module.raw_value()) } as *mut c::JSModuleDef;
+        if unsafe { c::JS_ResolveModule(self.as_ptr(), *module.raw_value()) } < 0 {
+            return Err(self.get_exception_error()).with_context(failed);
+        }
+        let promise = unsafe { c::JS_EvalFunction(self.as_ptr(), module.leak()) };
+        let promise = Value::new_moved(self, promise);
+        if promise.is_exception() {
+            return Err(self.get_exception_error()).with_context(failed);
+        }
+        crate::eval::drain_jobs(self, crate::eval::DEFAULT_JOB_LIMIT)
+            .map_err(|err| anyhow!("{err}"))
+            .with_context(failed)?;
+        if promise.is_object() {
+            let state = unsafe { c::JS_PromiseState(self.as_ptr(), *promise.raw_value()) };
+            if state == c::JSPromiseStateEnum_JS_PROMISE_REJECTED {
+                let reason = unsafe { c::JS_PromiseResult(self.as_ptr(), *promise.raw_value()) };
+                let reason = Value::new_moved(self, reason);
+                return Err(anyhow!("{}", self.error_to_string(&reason))).with_context(failed);
+            }
+            if state == c::JSPromiseStateEnum_JS_PROMISE_PENDING {
+                return Err(anyhow!("top-level await never settled")).with_context(failed);
+            }
+        }
+        let namespace = unsafe { c::JS_GetModuleNamespace(self.as_ptr(), ptr) };
+        let namespace = Value::new_moved(self, namespace);
+        if namespace.is_exception() {
+            return Err(self.get_exception_error()).with_context(failed);
+        }
+        Ok(namespace)
+    }
+}
+
+unsafe extern "C" fn normalize_module_name(
+    ctx: *mut c::JSContext,
+    base: *const c_char,
+    name: *const c_char,
+    _opaque: *mut c_void,
+) -> *mut c_char {
+    let Some(ctx) = Context::clone_from_ptr(ctx) else {
+        return core::ptr::null_mut();
+    };
+    let base = unsafe { CStr::from_ptr(base) }.to_string_lossy();
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+    let normalized = ctx
+        .module_hooks()
+        .and_then(|hooks| hooks.normalize.clone())
+        .context("no module normalizer")
+        .and_then(|normalize| normalize(&ctx, &base, &name))
+        .and_then(|normalized| CString::new(normalized).or(Err(anyhow!("NUL in the name"))));
+    let normalized = match normalized {
+        Ok(normalized) => normalized,
+        Err(err) => {
+            ctx.throw(err.context(format!("failed to resolve module '{name}' from '{base}'")));
+            return core::ptr::null_mut();
+        }
+    };
+    // QuickJS takes the name and frees it with `js_free`.
+    let bytes = normalized.as_bytes_with_nul();
+    let buf = unsafe { c::js_malloc(ctx.as_ptr(), bytes.len() as _) } as *mut u8;
+    if !buf.is_null() {
+        unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), buf, bytes.len()) };
+    }
+    buf as _
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::string::ToString;
+
+    use crate::Code;
+
+    use super::*;
+
+    fn serve(modules: &[(&str, &str)]) -> impl Fn(&Context, &str) -> Result<JsCode> {
+        let modules: BTreeMap<String, String> = modules
+            .iter()
+            .map(|(name, src)| (name.to_string(), src.to_string()))
+            .collect();
+        move |_ctx, name| match modules.get(name) {
+            Some(src) => Ok(JsCode::Source(src.clone())),
+            None => bail!("no such module"),
+        }
+    }
+
+    fn import(ctx: &Context, name: &str) -> core::result::Result<Value, String> {
+        ctx.eval_and_drain(&Code::Source(&format!(
+            "import('{name}').then(ns => globalThis.imported = ns, e => globalThis.imported = e)"
+        )))?;
+        Ok(ctx.get_global_object().get_property("imported").unwrap())
+    }
+
+    #[test]
+    fn module_exports_are_read_from_rust() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let ns = ctx
+            .eval_module(&JsCode::Source(
+                "export default 'hello';
+                export function add(a, b) { return a + b; }
+                export const later = await Promise.resolve(7);"
+                    .into(),
+            ))
+            .unwrap();
+        let default = ns.get_property("default").unwrap();
+        assert_eq!(default.decode_string().unwrap(), "hello");
+        let add = ns.get_property("add").unwrap();
+        let three = add
+            .call(
+                &Value::undefined(),
+                &[Value::from_u32(&ctx, 1), Value::from_u32(&ctx, 2)],
+            )
+            .unwrap();
+        assert_eq!(three.decode_u32().unwrap(), 3);
+        assert_eq!(ns.get_property("later").unwrap().decode_u32().unwrap(), 7);
+
+        let err = ctx
+            .eval_named_module("broken", &JsCode::Source("export const = 1;".into()))
+            .unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("module 'broken'"), "{message}");
+        assert!(message.contains("SyntaxError"), "{message}");
+
+        let err = ctx
+            .eval_named_module("importer", &JsCode::Source("import 'missing';".into()))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("module 'importer'"), "{err:#}");
+        let err = ctx
+            .eval_named_module("thrower", &JsCode::Source("throw new Error('bad');".into()))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("Error: bad"), "{err:#}");
+    }
+
+    #[test]
+    fn imports_are_loaded_by_the_host() {
+        let runtime = Runtime::new(&Default::default());
+        runtime.set_module_loader(serve(&[
+            (
+                "lib/a",
+                "import { b } from './b'; export const value = b * 2;",
+            ),
+            ("lib/b", "export const b = 21;"),
+        ]));
+        let ctx = runtime.new_context();
+        let a = import(&ctx, "lib/a").unwrap();
+        assert_eq!(a.get_property("value").unwrap().decode_u32().unwrap(), 42);
+
+        let err = import(&ctx, "lib/c").unwrap();
+        assert!(err.is_error());
+        let message = err.to_string();
+        assert!(
+            message.contains("failed to load module 'lib/c'"),
+            "{message}"
+        );
+        assert!(message.contains("no such module"), "{message}");
+
+        runtime.set_module_normalizer(|_ctx, _base, name| {
+            let name = name.trim_start_matches("@lib/").trim_start_matches("./");
+            Ok(format!("lib/{name}"))
+        });
+        let ctx = runtime.new_context();
+        let a = import(&ctx, "@lib/a").unwrap();
+        assert_eq!(a.get_property("value").unwrap().decode_u32().unwrap(), 42);
+    }
+}
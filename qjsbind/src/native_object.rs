@@ -1,10 +1,12 @@
 use crate::{
     self as js,
-    error::expect_js_value,
+    error::expect_err,
     opaque_value::{new_opaque_object, opaque_object_get_data_mut},
 };
 
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
 use core::{
+    cell::RefCell,
     marker::PhantomData,
     ops::{Deref, DerefMut},
 };
@@ -37,7 +39,39 @@ pub trait NativeClass: GcMark + Named + 'static {
     }
 }
 
-struct Guard<T>(T);
+/// Receives the JS name, old value and new value of a changed `#[qjs(setter, notify)]` field.
+pub type ChangeCallback = Rc<dyn Fn(&str, Value, Value)>;
+
+#[derive(Default)]
+struct ChangeObserver {
+    callback: Option<ChangeCallback>,
+    suspended: usize,
+    pending: Vec<(&'static str, Value, Value)>,
+}
+
+/// The values of the held back changes are marked with the instance, the callback is not.
+impl GcMark for ChangeObserver {
+    fn gc_mark(&self, rt: *mut c::JSRuntime, mark_fn: c::JS_MarkFunc) {
+        for (_, old, new) in &self.pending {
+            old.gc_mark(rt, mark_fn);
+            new.gc_mark(rt, mark_fn);
+        }
+    }
+}
+
+struct Guard<T> {
+    value: T,
+    observer: RefCell<Option<Box<ChangeObserver>>>,
+}
+
+impl<T> Guard<T> {
+    fn new(value: T) -> Self {
+        Self {
+            value,
+            observer: RefCell::new(None),
+        }
+    }
+}
 
 pub struct NativeValueRef<'a, T> {
     r: super::opaque_value::Ref<'a, Guard<T>>,
@@ -56,8 +90,8 @@ impl<T> Deref for NativeValueRef<'_, T> {
         &self
             .r
             .get()
-            .expect("Native object ref should never be None")
-            .0
+            .expect("Native object is already borrowed, see Native::try_borrow")
+            .value
     }
 }
 
@@ -78,8 +112,8 @@ impl<T> Deref for NativeValueRefMut<'_, T> {
         &self
             .r
             .get()
-            .expect("Native object ref should never be None")
-            .0
+            .expect("Native object is already borrowed, see Native::try_borrow")
+            .value
     }
 }
 
@@ -88,8 +122,8 @@ impl<T> DerefMut for NativeValueRefMut<'_, T> {
         &mut self
             .r
             .get_mut()
-            .expect("Native object ref should never be None")
-            .0
+            .expect("Native object is already borrowed, see Native::try_borrow")
+            .value
     }
 }
 
@@ -110,7 +144,7 @@ impl<T> Clone for Native<T> {
 impl<T: GcMark + Named + 'static> FromJsValue for Native<T> {
     fn from_js_value(value: Value) -> Result<Self> {
         if !value.is_opaque_object_of::<Guard<T>>() {
-            return Err(expect_js_value(&value, T::CLASS_NAME));
+            return Err(expect_err(T::CLASS_NAME, &value));
         }
         Ok(Self {
             inner: value,
@@ -144,9 +178,98 @@ impl<T: 'static> Native<T> {
         }
     }
 
+    /// Borrow the value, failing with [`Reentrancy`](crate::Reentrancy) rather than panicking on
+    /// deref if it is mutably borrowed, e.g. by a `&mut self` method calling back into the script.
+    pub fn try_borrow(&self) -> Result<NativeValueRef<'_, T>> {
+        let r = self.borrow();
+        if r.is_none() {
+            return Err(reentrancy::<T>("borrow"));
+        }
+        Ok(r)
+    }
+
+    /// Borrow the value mutably, failing with [`Reentrancy`](crate::Reentrancy) if it is
+    /// borrowed.
+    pub fn try_borrow_mut(&self) -> Result<NativeValueRefMut<'_, T>> {
+        let r = self.borrow_mut();
+        if r.is_none() {
+            return Err(reentrancy::<T>("borrow_mut"));
+        }
+        Ok(r)
+    }
+
     pub fn js_value(&self) -> Value {
         self.inner.clone()
     }
+
+    fn with_observer<R>(&self, f: impl FnOnce(&mut ChangeObserver) -> R) -> Option<R> {
+        let guard = self.inner.opaque_object_data::<Guard<T>>();
+        let mut observer = guard.get()?.observer.borrow_mut();
+        Some(f(observer.get_or_insert_with(Default::default)))
+    }
+
+    /// Register the callback invoked when a script assigns a `#[qjs(setter, notify)]` field of
+    /// this instance, replacing any previous one.
+    ///
+    /// The GC does not see into the callback, so it should not capture values referring back to
+    /// the instance, which would keep both alive.
+    pub fn on_change(&self, callback: impl Fn(&str, Value, Value) + 'static) {
+        self.with_observer(|observer| observer.callback = Some(Rc::new(callback)));
+    }
+
+    /// Hold back change notifications until the matching `end_update`. Calls may be nested.
+    pub fn begin_update(&self) {
+        self.with_observer(|observer| observer.suspended += 1);
+    }
+
+    /// Deliver the changes made since the outermost `begin_update`, one per field, in the order
+    /// the fields first changed, with the value before the first change and the latest value.
+    pub fn end_update(&self) {
+        let flushed = self.with_observer(|observer| {
+            observer.suspended = observer.suspended.saturating_sub(1);
+            if observer.suspended > 0 {
+                return None;
+            }
+            let pending = core::mem::take(&mut observer.pending);
+            Some((observer.callback.clone()?, pending))
+        });
+        if let Some(Some((callback, pending))) = flushed {
+            for (field, old, new) in pending {
+                callback(field, old, new);
+            }
+        }
+    }
+
+    /// Report a field change. Used by the code generated for `#[qjs(setter, notify)]`.
+    #[doc(hidden)]
+    pub fn notify_change(&self, field: &'static str, old: Value, new: Value) {
+        let callback = self.with_observer(|observer| {
+            let callback = observer.callback.clone()?;
+            if observer.suspended == 0 {
+                return Some(callback);
+            }
+            match observer
+                .pending
+                .iter_mut()
+                .find(|(name, ..)| *name == field)
+            {
+                Some(change) => change.2 = new.clone(),
+                None => observer.pending.push((field, old.clone(), new.clone())),
+            }
+            None
+        });
+        // The borrow is released, so the callback may read or update the instance.
+        if let Some(Some(callback)) = callback {
+            callback(field, old, new);
+        }
+    }
+}
+
+fn reentrancy<T>(operation: &'static str) -> js::Error {
+    js::Error::msg(js::Reentrancy {
+        type_name: core::any::type_name::<T>(),
+        operation,
+    })
 }
 
 impl<T: GcMark + Named + 'static> Native<T> {
@@ -160,12 +283,17 @@ impl<T: GcMark + Named + 'static> Native<T> {
             let data = data
                 .get_mut()
                 .expect("Native object ref should never be None");
-            data.0.gc_mark(rt, mark_fn);
+            data.value.gc_mark(rt, mark_fn);
+            if let Ok(observer) = data.observer.try_borrow() {
+                if let Some(observer) = observer.as_deref() {
+                    observer.gc_mark(rt, mark_fn);
+                }
+            }
         }
         let object = new_opaque_object(
             ctx,
             Some(T::CLASS_NAME),
-            Guard(opaque_value),
+            Guard::new(opaque_value),
             Some(gc_mark::<T>),
         );
         Ok(Self {
@@ -217,3 +345,78 @@ where
         Ok(self?.into_native_object(ctx)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+    use alloc::{format, string::String};
+
+    #[crate::qjsbind]
+    mod classes {
+        #[qjs(class(rename_all = "camelCase"))]
+        pub struct Config {
+            #[qjs(getter, setter, notify)]
+            pub gas_limit: u32,
+            #[qjs(getter, setter, notify)]
+            pub name: alloc::string::String,
+            #[qjs(getter, setter, notify)]
+            pub meta: crate::Value,
+        }
+    }
+
+    use classes::Config;
+
+    #[test]
+    fn notifies_field_changes() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let config = ctx
+            .wrap_native(Config {
+                gas_limit: 1,
+                name: "a".into(),
+                meta: crate::Value::undefined(),
+            })
+            .unwrap();
+        let log = Rc::new(RefCell::new(Vec::<String>::new()));
+        let sink = log.clone();
+        config.on_change(move |field, old, new| {
+            sink.borrow_mut().push(format!("{field}: {old} -> {new}"));
+        });
+        ctx.get_global_object()
+            .set_property("config", &config.js_value())
+            .unwrap();
+
+        ctx.eval(&Code::Source("config.gasLimit = 2; config.name = 'b';"))
+            .unwrap();
+        assert_eq!(*log.borrow(), ["gasLimit: 1 -> 2", "name: a -> b"]);
+        assert_eq!(config.borrow().gas_limit, 2);
+
+        log.borrow_mut().clear();
+        config.begin_update();
+        ctx.eval(&Code::Source(
+            "config.name = 'c'; config.gasLimit = 3; config.name = 'd'; config.gasLimit = 4;",
+        ))
+        .unwrap();
+        assert!(log.borrow().is_empty());
+        config.end_update();
+        assert_eq!(*log.borrow(), ["name: b -> d", "gasLimit: 2 -> 4"]);
+
+        // The held back values are marked through a GC.
+        log.borrow_mut().clear();
+        config.begin_update();
+        ctx.eval(&Code::Source(
+            "config.meta = { tag: 'held', self: config }; globalThis.config = undefined;",
+        ))
+        .unwrap();
+        runtime.run_gc();
+        let tags = Rc::new(RefCell::new(Vec::<String>::new()));
+        let sink = tags.clone();
+        config.on_change(move |_, _, new| {
+            let tag = new.get_property("tag").map(|tag| tag.to_string());
+            sink.borrow_mut().push(tag.unwrap_or_default());
+        });
+        config.end_update();
+        assert_eq!(*tags.borrow(), ["held"]);
+    }
+}
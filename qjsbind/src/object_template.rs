@@ -0,0 +1,257 @@
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::bail;
+
+use crate::{self as js, c, Convention, Result, ToJsValue, Value};
+
+/// Property names interned once, to create many objects with the same properties in the same
+/// order without looking the names up again.
+///
+/// ```ignore
+/// let template = ctx.object_template(&["method", "url", "headers", "body"]);
+/// for req in requests {
+///     let obj = template.instantiate(&[&req.method, &req.url, &req.headers, &req.body])?;
+///     handle(obj)?;
+/// }
+/// ```
+///
+/// Objects created from the same template also share their shape in the engine, since their
+/// properties are added in the same order.
+#[derive(Clone)]
+pub struct ObjectTemplate {
+    ctx: js::Context,
+    names: Rc<Names>,
+}
+
+struct Names {
+    rt: *mut c::JSRuntime,
+    keys: Vec<String>,
+    atoms: Vec<c::JSAtom>,
+}
+
+impl Names {
+    fn new(ctx: &js::Context, keys: &[&str]) -> Self {
+        let atoms = keys
+            .iter()
+            .map(|key| unsafe { c::JS_NewAtomLen(ctx.as_ptr(), key.as_ptr() as _, key.len() as _) })
+            .collect();
+        Self {
+            rt: unsafe { c::JS_GetRuntime(ctx.as_ptr()) },
+            keys: keys.iter().map(|&key| key.into()).collect(),
+            atoms,
+        }
+    }
+}
+
+impl Drop for Names {
+    fn drop(&mut self) {
+        for atom in self.atoms.drain(..) {
+            unsafe { c::JS_FreeAtomRT(self.rt, atom) };
+        }
+    }
+}
+
+impl ObjectTemplate {
+    /// The number of properties of the template.
+    pub fn len(&self) -> usize {
+        self.names.atoms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.atoms.is_empty()
+    }
+
+    /// The property names, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.keys.iter().map(String::as_str)
+    }
+
+    /// Create an object with one value per property, in the order of the names.
+    pub fn instantiate(&self, values: &[&dyn ToJsValue]) -> Result<Value> {
+        if values.len() != self.len() {
+            bail!(
+                "template of {} properties instantiated with {} values",
+                self.len(),
+                values.len()
+            );
+        }
+        let obj = self.ctx.new_object("");
+        for (index, value) in values.iter().enumerate() {
+            self.set(&obj, index, &value.to_js_value(&self.ctx)?)?;
+        }
+        Ok(obj)
+    }
+
+    /// Set the property `index` of the template on `obj`.
+    pub fn set(&self, obj: &Value, index: usize, value: &Value) -> Result<()> {
+        let Some(atom) = self.names.atoms.get(index) else {
+            bail!("template has no property {index}");
+        };
+        obj.set_property_atom(*atom, value.clone())
+    }
+
+    /// Set the property `index` of the template on `obj`, or remove it for `None`.
+    pub fn set_opt(&self, obj: &Value, index: usize, value: Option<&Value>) -> Result<()> {
+        let Some(atom) = self.names.atoms.get(index) else {
+            bail!("template has no property {index}");
+        };
+        obj.set_property_atom_opt(*atom, value.cloned())
+    }
+}
+
+/// Identifies the template of a derived `ToJsValue` impl, a static of the generated code.
+#[doc(hidden)]
+pub struct TemplateKey(AtomicUsize);
+
+impl TemplateKey {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    /// A number unique to the key, assigned on first use.
+    fn index(&self) -> usize {
+        static NEXT: AtomicUsize = AtomicUsize::new(1);
+        let id = self.0.load(Ordering::Relaxed);
+        if id != 0 {
+            return id - 1;
+        }
+        let fresh = NEXT.fetch_add(1, Ordering::Relaxed);
+        match self
+            .0
+            .compare_exchange(0, fresh, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            Ok(_) => fresh - 1,
+            Err(assigned) => assigned - 1,
+        }
+    }
+}
+
+/// The templates of the derived `ToJsValue` impls of a runtime, by key and naming convention.
+#[derive(Default)]
+struct Cache {
+    slots: Vec<Option<Rc<Names>>>,
+}
+
+impl js::Context {
+    /// Intern `names` for creating objects with these properties, see [`ObjectTemplate`].
+    pub fn object_template(&self, names: &[&str]) -> ObjectTemplate {
+        ObjectTemplate {
+            ctx: self.clone(),
+            names: Rc::new(Names::new(self, names)),
+        }
+    }
+
+    /// The template of `key` with the field `names`, kept with the runtime once created. The
+    /// names depend on the key and the `default_rename` of the context only. Used by the
+    /// generated code.
+    #[doc(hidden)]
+    pub fn cached_object_template(&self, key: &TemplateKey, names: &[&str]) -> ObjectTemplate {
+        let Some(cache) = self.runtime_state::<RefCell<Cache>>() else {
+            return self.object_template(names);
+        };
+        let camel = self.default_rename() == Convention::CamelCase;
+        let slot = key.index() * 2 + camel as usize;
+        let mut cache = cache.borrow_mut();
+        if cache.slots.len() <= slot {
+            cache.slots.resize(slot + 1, None);
+        }
+        let names = cache.slots[slot]
+            .get_or_insert_with(|| Rc::new(Names::new(self, names)))
+            .clone();
+        ObjectTemplate {
+            ctx: self.clone(),
+            names,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Convention;
+
+    #[derive(crate::ToJsValue)]
+    #[qjs(template)]
+    struct Request {
+        method: String,
+        request_url: String,
+        #[qjs(skip_serializing_if = "Option::is_none")]
+        body: Option<String>,
+    }
+
+    fn keys(value: &Value) -> Vec<String> {
+        value
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().0.decode_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn instantiates_in_order() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let template = ctx.object_template(&["method", "url", "headers"]);
+        let obj = template
+            .instantiate(&[&"GET", &"/", &alloc::vec![1u32, 2]])
+            .unwrap();
+        assert_eq!(keys(&obj), ["method", "url", "headers"]);
+        ctx.get_global_object().set_property("r", &obj).unwrap();
+        let summary = ctx
+            .eval(&crate::Code::Source("`${r.method} ${r.url} ${r.headers}`"))
+            .unwrap();
+        assert_eq!(summary.decode_string().unwrap(), "GET / 1,2");
+        assert!(template.instantiate(&[&"GET"]).is_err());
+    }
+
+    #[test]
+    fn derived_impls_reuse_the_template() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let request = Request {
+            method: "POST".into(),
+            request_url: "/submit".into(),
+            body: Some("{}".into()),
+        };
+        let value = request.to_js_value(&ctx).unwrap();
+        assert_eq!(keys(&value), ["method", "request_url", "body"]);
+        assert_eq!(
+            value.get_property("body").unwrap().decode_string().unwrap(),
+            "{}"
+        );
+
+        let request = Request {
+            body: None,
+            ..request
+        };
+        assert_eq!(
+            keys(&request.to_js_value(&ctx).unwrap()),
+            ["method", "request_url"]
+        );
+
+        ctx.set_default_rename(Convention::CamelCase).unwrap();
+        assert_eq!(
+            keys(&request.to_js_value(&ctx).unwrap()),
+            ["method", "requestUrl"]
+        );
+        ctx.set_default_rename(Convention::Keep).unwrap();
+        assert_eq!(
+            keys(&request.to_js_value(&ctx).unwrap()),
+            ["method", "request_url"]
+        );
+
+        // The contexts of the runtime share the templates, one per naming convention.
+        let other = runtime.new_context();
+        assert_eq!(
+            keys(&request.to_js_value(&other).unwrap()),
+            ["method", "request_url"]
+        );
+        let cache = ctx.runtime_state::<RefCell<Cache>>().unwrap();
+        assert_eq!(cache.borrow().slots.iter().flatten().count(), 2);
+    }
+}
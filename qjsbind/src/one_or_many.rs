@@ -0,0 +1,95 @@
+use core::ops::Deref;
+
+use alloc::vec::Vec;
+
+use crate::{self as js, FromJsValue, GcMark, Result, ToJsValue, Value};
+
+/// A list that scripts may also pass as a single value, `"sign"` for `["sign"]`.
+///
+/// Only arrays are taken as lists, anything else converts as one element.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OneOrMany<T>(pub Vec<T>);
+
+impl<T> OneOrMany<T> {
+    pub fn into_vec(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for OneOrMany<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> From<Vec<T>> for OneOrMany<T> {
+    fn from(items: Vec<T>) -> Self {
+        Self(items)
+    }
+}
+
+impl<T: FromJsValue> FromJsValue for OneOrMany<T> {
+    fn from_js_value(value: Value) -> Result<Self> {
+        let depth = js::ConversionDepth::of(&value);
+        Self::from_js_value_depth(value, depth)
+    }
+
+    fn from_js_value_depth(value: Value, depth: js::ConversionDepth) -> Result<Self> {
+        if value.is_array() {
+            return Ok(Self(Vec::from_js_value_depth(value, depth)?));
+        }
+        Ok(Self(alloc::vec![T::from_js_value_depth(value, depth)?]))
+    }
+}
+
+impl<T: ToJsValue> ToJsValue for OneOrMany<T> {
+    fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
+        self.0.to_js_value(ctx)
+    }
+}
+
+impl<T: GcMark> GcMark for OneOrMany<T> {
+    fn gc_mark(&self, rt: *mut js::c::JSRuntime, mark_fn: js::c::JS_MarkFunc) {
+        self.0.gc_mark(rt, mark_fn);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Code, JsString};
+    use alloc::string::{String, ToString};
+
+    #[test]
+    fn scalars_and_lists() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+        let usages = |src: &str| -> Vec<String> {
+            OneOrMany::<JsString>::from_js_value(eval(src))
+                .unwrap()
+                .iter()
+                .map(|s| s.as_str().into())
+                .collect()
+        };
+        assert_eq!(usages("'sign'"), ["sign"]);
+        assert_eq!(usages("['sign', 'verify']"), ["sign", "verify"]);
+        assert!(usages("[]").is_empty());
+        assert!(OneOrMany::<u32>::from_js_value(eval("'x'")).is_err());
+
+        let err = Vec::<JsString>::from_js_value(eval("'sign'")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"expected an array, got string — did you mean ["sign"]?"#
+        );
+        let err = Vec::<u32>::from_js_value(eval("5")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected an array, got number — did you mean [5]?"
+        );
+        let err = Vec::<u32>::from_js_value(eval("undefined")).unwrap_err();
+        assert_eq!(err.to_string(), "expected an array, got undefined");
+    }
+}
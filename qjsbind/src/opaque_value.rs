@@ -1,12 +1,12 @@
 use core::any::TypeId;
 use core::cell::RefCell;
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, collections::BTreeMap, rc::Rc};
 use log::debug;
 use qjs_sys::c;
 
 use crate as js;
-use crate::Value;
+use crate::{Result, Value};
 
 fn type_id<T: 'static>() -> u64 {
     let type_id = TypeId::of::<T>();
@@ -79,19 +79,30 @@ impl<T> RefMut<'_, T> {
     }
 }
 
+extern "C" fn free_opaque<T>(
+    _rt: *mut c::JSRuntime,
+    data: *mut ::core::ffi::c_void,
+    _tag: ::core::ffi::c_int,
+) {
+    let _drop_it = unsafe { Box::from_raw(data as *mut Cell<T>) };
+}
+
 pub fn new_opaque_object<T: 'static>(
     ctx: &js::Context,
     name: Option<&str>,
     value: T,
     gc_mark: c::JSClassGCMark,
 ) -> Value {
-    extern "C" fn free_opaque<T>(
-        _rt: *mut c::JSRuntime,
-        data: *mut ::core::ffi::c_void,
-        _tag: ::core::ffi::c_int,
-    ) {
-        let _drop_it = unsafe { Box::from_raw(data as *mut Cell<T>) };
-    }
+    new_opaque_object_with(ctx, name, value, gc_mark, free_opaque::<T>)
+}
+
+fn new_opaque_object_with<T: 'static>(
+    ctx: &js::Context,
+    name: Option<&str>,
+    value: T,
+    gc_mark: c::JSClassGCMark,
+    free: extern "C" fn(*mut c::JSRuntime, *mut ::core::ffi::c_void, ::core::ffi::c_int),
+) -> Value {
     debug!(
         "new_opaque_object TID={}, T={:?}",
         type_id::<T>(),
@@ -101,13 +112,7 @@ pub fn new_opaque_object<T: 'static>(
     let data = Box::into_raw(boxed);
     let tag: u64 = type_id::<T>();
     let js_value = unsafe {
-        c::JS_OpaqueObjectNew(
-            ctx.as_ptr(),
-            data as *mut _,
-            Some(free_opaque::<T>),
-            gc_mark,
-            tag as _,
-        )
+        c::JS_OpaqueObjectNew(ctx.as_ptr(), data as *mut _, Some(free), gc_mark, tag as _)
     };
     let object = Value::new_moved(ctx, js_value);
     if let Some(name) = name {
@@ -116,6 +121,80 @@ pub fn new_opaque_object<T: 'static>(
     object
 }
 
+/// The objects of `new_opaque_object_cached` still alive, kept in the runtime data. They are
+/// held without a reference, so that the cache does not keep them alive, and removed by their
+/// finalizer.
+#[derive(Default)]
+struct OpaqueIdentities {
+    entries: RefCell<IdentityEntries>,
+}
+
+#[derive(Default)]
+struct IdentityEntries {
+    /// The objects by context, type and `Rc` allocation.
+    objects: BTreeMap<IdentityKey, c::JSValue>,
+    /// The keys by the address of the data of the objects, for their finalizer.
+    keys: BTreeMap<usize, IdentityKey>,
+}
+
+type IdentityKey = (usize, u64, usize);
+
+extern "C" fn free_cached_opaque<T>(
+    rt: *mut c::JSRuntime,
+    data: *mut ::core::ffi::c_void,
+    tag: ::core::ffi::c_int,
+) {
+    if let Some(identities) = js::engine::runtime_state_of::<OpaqueIdentities>(rt) {
+        // Not borrowed by `new_opaque_object_cached`, which frees no value while it holds it.
+        if let Ok(mut entries) = identities.entries.try_borrow_mut() {
+            if let Some(key) = entries.keys.remove(&(data as usize)) {
+                entries.objects.remove(&key);
+            }
+        }
+    }
+    free_opaque::<Rc<T>>(rt, data, tag);
+}
+
+/// Like `new_opaque_object`, but returns the same JS object for the same `Rc` allocation as long
+/// as the previously created object is still alive in the context.
+///
+/// The cache is kept by the host, out of reach of scripts, and does not keep the objects from
+/// being collected: an entry goes away with its object. Only contexts created by
+/// `Runtime::new_context` cache, others get a new object each time.
+pub fn new_opaque_object_cached<T: 'static>(
+    ctx: &js::Context,
+    name: Option<&str>,
+    value: Rc<T>,
+) -> Result<Value> {
+    let Some(identities) = ctx.runtime_state::<OpaqueIdentities>() else {
+        return Ok(new_opaque_object(ctx, name, value, None));
+    };
+    let key = (
+        ctx.as_ptr() as usize,
+        type_id::<Rc<T>>(),
+        Rc::as_ptr(&value) as *const () as usize,
+    );
+    let cached = identities.entries.borrow().objects.get(&key).copied();
+    if let Some(object) = cached {
+        // The data may have been taken out, and the allocation reused by another `Rc`.
+        let same = opaque_object_get_data_raw::<Rc<T>>(&object)
+            .get()
+            .is_some_and(|held| Rc::ptr_eq(held, &value));
+        if same {
+            return Ok(Value::new_cloned(ctx, object));
+        }
+    }
+    let object = new_opaque_object_with(ctx, name, value, None, free_cached_opaque::<T>);
+    let data = unsafe { c::JS_OpaqueObjectDataGet(*object.raw_value(), type_id::<Rc<T>>() as _) };
+    let mut entries = identities.entries.borrow_mut();
+    if let Some(stale) = entries.objects.insert(key, *object.raw_value()) {
+        let stale = unsafe { c::JS_OpaqueObjectDataGet(stale, type_id::<Rc<T>>() as _) };
+        entries.keys.remove(&(stale as usize));
+    }
+    entries.keys.insert(data as usize, key);
+    Ok(object)
+}
+
 pub fn is_opaque_object_of<T: 'static>(value: &Value) -> bool {
     let Value::Other { value, ctx: _ } = value else {
         return false;
@@ -181,3 +260,102 @@ pub fn opaque_object_take_data<T: 'static>(value: &Value) -> Option<T> {
         cell.take()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+
+    std::thread_local! {
+        static DROPPED: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    }
+
+    struct Resource;
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            DROPPED.with(|dropped| dropped.set(dropped.get() + 1));
+        }
+    }
+
+    #[crate::host_call(with_context)]
+    fn open(ctx: js::Context, _this: Value) -> Value {
+        Value::new_opaque_object(&ctx, None, Resource)
+    }
+
+    #[crate::host_call(with_context)]
+    fn collect(ctx: js::Context, _this: Value) {
+        ctx.request_gc();
+    }
+
+    #[test]
+    fn gc_releases_opaque_objects_in_cycles() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let global = ctx.get_global_object();
+        global.define_property_fn("open", open).unwrap();
+        global.define_property_fn("collect", collect).unwrap();
+        let dropped = || DROPPED.with(|dropped| dropped.get());
+        // Reference counting alone can not free an object referring to itself.
+        let leak_cycles = "for (let i = 0; i < 100; i++) { const r = open(); r.self = r; }";
+
+        ctx.eval(&Code::Source(leak_cycles)).unwrap();
+        assert_eq!(dropped(), 0);
+        runtime.run_gc();
+        assert_eq!(dropped(), 100);
+
+        ctx.eval(&Code::Source(leak_cycles)).unwrap();
+        ctx.eval(&Code::Source("collect(); 'collected'")).unwrap();
+        assert_eq!(dropped(), 200);
+    }
+
+    #[test]
+    fn cached_objects_keep_their_identity_until_collected() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let resource = Rc::new(7u32);
+        let cached = |ctx: &js::Context| {
+            Value::new_opaque_object_cached(ctx, None, resource.clone()).unwrap()
+        };
+        let entries = || {
+            let identities = ctx.runtime_state::<OpaqueIdentities>().unwrap();
+            let entries = identities.entries.borrow();
+            assert_eq!(entries.objects.len(), entries.keys.len());
+            entries.objects.len()
+        };
+        let same = ctx
+            .eval(&Code::Source(
+                "(a) => { const seen = new WeakMap([[a, 1]]); return (b) => seen.get(b) === 1; }",
+            ))
+            .unwrap();
+        let first = cached(&ctx);
+        let is_first = same.call(&Value::undefined(), &[first.clone()]).unwrap();
+        let result = is_first.call(&Value::undefined(), &[cached(&ctx)]).unwrap();
+        assert!(result.decode_bool().unwrap());
+        // The cache is out of reach of scripts.
+        ctx.eval(&Code::Source("delete globalThis._QjsBind"))
+            .unwrap();
+        let result = is_first.call(&Value::undefined(), &[cached(&ctx)]).unwrap();
+        assert!(result.decode_bool().unwrap());
+        assert_eq!(entries(), 1);
+
+        // Each context has its own objects.
+        let other = runtime.new_context();
+        let elsewhere = cached(&other);
+        assert!(!elsewhere.ptr_eq(&first));
+        assert_eq!(entries(), 2);
+        drop(elsewhere);
+        drop(other);
+
+        first
+            .set_property("tag", &Value::from_bool(&ctx, true))
+            .unwrap();
+        drop((first, is_first, same));
+        runtime.run_gc();
+        assert_eq!(entries(), 0);
+        let fresh = cached(&ctx);
+        assert!(fresh.get_property("tag").unwrap().is_undefined());
+        let data = fresh.opaque_object_data::<Rc<u32>>();
+        assert!(Rc::ptr_eq(data.get().unwrap(), &resource));
+    }
+}
@@ -0,0 +1,110 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+use crate::{type_name, Error, FromArgs, Result, Value};
+
+/// Dispatches a call to the first arm whose arguments convert successfully.
+///
+/// ```ignore
+/// #[js::host_call]
+/// fn encode(value: js::Value, ty: js::Value) -> js::Result<Bytes> {
+///     js::Overloaded::new(&[value, ty])
+///         .arm(|(value, name): (js::Value, js::JsString)| encode_by_name(value, &name))
+///         .arm(|(value, id): (js::Value, u32)| encode_by_id(value, id))
+///         .finish()
+/// }
+/// ```
+///
+/// Arms are tried in the order they are added. Once an arm's arguments convert, its result is
+/// returned as is, even if it is an error. If no arm matches, the error lists the conversion
+/// failure of each arm. Wrapper objects are not unwrapped while matching, see
+/// [`Context::register_wrapper_class`](crate::Context::register_wrapper_class).
+pub struct Overloaded<'a, R> {
+    args: &'a [Value],
+    result: Option<Result<R>>,
+    failures: Vec<(String, Error)>,
+}
+
+impl<'a, R> Overloaded<'a, R> {
+    pub fn new(args: &'a [Value]) -> Self {
+        Self {
+            args,
+            result: None,
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn arm<A: FromArgs>(mut self, f: impl FnOnce(A) -> Result<R>) -> Self {
+        if self.result.is_some() {
+            return self;
+        }
+        let convert = || A::from_args(self.args);
+        let converted = match self.args.iter().find_map(|arg| arg.context().ok()) {
+            Some(ctx) => ctx.speculate(convert),
+            None => convert(),
+        };
+        match converted {
+            Ok(args) => self.result = Some(f(args)),
+            Err(err) => self.failures.push((type_name::<A>(), err)),
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<R> {
+        if let Some(result) = self.result {
+            return result;
+        }
+        let mut message = String::from("no matching overload");
+        for (signature, err) in self.failures {
+            _ = write!(message, "\n  {signature}: {err:#}");
+        }
+        Err(Error::msg(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{self as js, Code, JsString};
+
+    fn describe(args: &[Value]) -> Result<String> {
+        Overloaded::new(args)
+            .arm(|(name,): (JsString,)| Ok(alloc::format!("name {name}")))
+            .arm(|(id,): (u32,)| Ok(alloc::format!("id {id}")))
+            .arm(|(id, name): (u32, JsString)| Ok(alloc::format!("both {id} {name}")))
+            .finish()
+    }
+
+    #[test]
+    fn the_first_matching_arm_is_called() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+        assert_eq!(describe(&[eval("'x'")]).unwrap(), "name x");
+        assert_eq!(describe(&[eval("7")]).unwrap(), "id 7");
+        // The second arm matches first, ignoring the extra argument.
+        assert_eq!(describe(&[eval("7"), eval("'x'")]).unwrap(), "id 7");
+
+        // The error of a matched arm is returned as is.
+        let err = Overloaded::new(&[eval("7")])
+            .arm(|(_,): (u32,)| -> Result<()> { Err(Error::msg("refused")) })
+            .arm(|(_,): (Value,)| Ok(()))
+            .finish()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "refused");
+    }
+
+    #[test]
+    fn no_match_lists_each_arm() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let args = [ctx.eval(&Code::Source("({})")).unwrap()];
+        let err = describe(&args).unwrap_err().to_string();
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines.len(), 4, "{err}");
+        assert_eq!(lines[0], "no matching overload");
+        assert!(lines[1].contains("JsString"), "{err}");
+        assert!(lines[2].starts_with("  (u32,): "), "{err}");
+        assert!(lines[3].starts_with("  (u32, "), "{err}");
+    }
+}
@@ -0,0 +1,99 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{self as js, c, Result, Value};
+
+/// An atom, freed when dropped.
+pub(crate) struct Atom {
+    raw: c::JSAtom,
+    ctx: js::Context,
+}
+
+impl Atom {
+    pub(crate) fn new(ctx: &js::Context, name: &str) -> Self {
+        let raw = unsafe { c::JS_NewAtomLen(ctx.as_ptr(), name.as_ptr() as _, name.len() as _) };
+        Self {
+            raw,
+            ctx: ctx.clone(),
+        }
+    }
+
+    pub(crate) fn raw(&self) -> c::JSAtom {
+        self.raw
+    }
+
+    /// The key as a string or symbol value.
+    pub(crate) fn to_value(&self) -> Value {
+        Value::new_moved(&self.ctx, unsafe {
+            c::JS_AtomToValue(self.ctx.as_ptr(), self.raw)
+        })
+    }
+}
+
+impl Drop for Atom {
+    fn drop(&mut self) {
+        unsafe { c::JS_FreeAtom(self.ctx.as_ptr(), self.raw) };
+    }
+}
+
+pub(crate) struct Key {
+    pub(crate) atom: Atom,
+    /// The name of a string key.
+    pub(crate) name: Option<String>,
+}
+
+/// The own string and symbol keys of `obj`, enumerable or not.
+pub(crate) fn own_keys(ctx: &js::Context, obj: &Value) -> Result<Vec<Key>> {
+    own_property_names(ctx, obj, c::JS_GPN_STRING_MASK | c::JS_GPN_SYMBOL_MASK)
+}
+
+/// The own enumerable string keys of `obj`, in the order of `Object.keys`, without calling
+/// anything scripts can patch.
+pub(crate) fn own_enumerable_keys(ctx: &js::Context, obj: &Value) -> Result<Vec<Key>> {
+    own_property_names(ctx, obj, c::JS_GPN_STRING_MASK | c::JS_GPN_ENUM_ONLY)
+}
+
+/// Delete the property `key` of `obj`, without calling anything scripts can patch. `false` if it
+/// is not configurable.
+pub(crate) fn delete_property(ctx: &js::Context, obj: &Value, key: &Atom) -> Result<bool> {
+    let r = unsafe { c::JS_DeleteProperty(ctx.as_ptr(), *obj.raw_value(), key.raw, 0) };
+    if r < 0 {
+        return Err(ctx.get_exception_error());
+    }
+    Ok(r != 0)
+}
+
+fn own_property_names(ctx: &js::Context, obj: &Value, flags: u32) -> Result<Vec<Key>> {
+    let mut tab: *mut c::JSPropertyEnum = core::ptr::null_mut();
+    let mut len = 0;
+    let r = unsafe {
+        c::JS_GetOwnPropertyNames(
+            ctx.as_ptr(),
+            &mut tab,
+            &mut len,
+            *obj.raw_value(),
+            flags as _,
+        )
+    };
+    if r < 0 {
+        return Err(ctx.get_exception_error());
+    }
+    let entries = unsafe { core::slice::from_raw_parts(tab, len as usize) };
+    // The atoms of the table are handed over to the keys, which free them.
+    let keys = entries
+        .iter()
+        .map(|entry| {
+            let atom = Atom {
+                raw: entry.atom,
+                ctx: ctx.clone(),
+            };
+            let key = atom.to_value();
+            Key {
+                name: key.is_string().then(|| key.to_string()),
+                atom,
+            }
+        })
+        .collect();
+    unsafe { c::js_free(ctx.as_ptr(), tab as _) };
+    Ok(keys)
+}
@@ -0,0 +1,340 @@
+//! Reuse set-up contexts across requests instead of creating one per request.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::cell::RefCell;
+use core::ops::Deref;
+
+use anyhow::bail;
+
+use crate::own_keys::{delete_property, Atom};
+use crate::{c, Context, Result, Runtime};
+
+/// The globals deleted when a context is returned to the pool.
+#[derive(Debug, Clone, Default)]
+pub enum ResetGlobals {
+    /// Every global that was not there right after setup.
+    #[default]
+    Added,
+    /// Only the listed globals.
+    Only(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Discard a context once it has served this many requests.
+    pub max_uses: Option<usize>,
+    /// The most contexts kept idle, returned contexts beyond it are dropped.
+    pub max_idle: usize,
+    pub reset: ResetGlobals,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_uses: None,
+            max_idle: 8,
+            reset: ResetGlobals::Added,
+        }
+    }
+}
+
+struct Entry {
+    // Declared before the runtime so that it is dropped first.
+    ctx: Context,
+    runtime: Runtime,
+    uses: usize,
+}
+
+/// A pool of contexts, each in its own runtime, that have already been through setup.
+///
+/// A context is returned to the pool when its [`PooledContext`] is dropped, and reset there:
+///
+/// - it is discarded if it was marked failed, reached `max_uses`, or has pending jobs left;
+/// - the globals selected by [`PoolConfig::reset`] are deleted, see [`Context::reset_globals`],
+///   and the context is discarded if one of them can not be deleted, as is the case for
+///   top-level `var` declarations;
+/// - the state qjsbind keeps for it on the host side, like the receipt being recorded or the
+///   access auditor, goes back to the one it had right after setup;
+/// - the garbage collector is run, dropping the native objects only the deleted globals held.
+///
+/// Only global properties are reset. Globals kept from setup, extension namespaces included,
+/// keep whatever changes a request made to them, and so do the built-in prototypes; freeze them
+/// in setup if requests must not share them. Top-level `let`, `const` and `class` declarations
+/// of scripts are not properties of the global object and are not cleared either, so requests
+/// should declare their state inside a function or a module.
+///
+/// ```ignore
+/// let pool = ContextPool::new(
+///     PoolConfig::default(),
+///     || Runtime::new(&Default::default()),
+///     |ctx| Extensions::new().with_scale().install(ctx),
+/// );
+/// let ctx = pool.acquire()?;
+/// ctx.eval(&Code::Source(request))?;
+/// ```
+pub struct ContextPool {
+    config: PoolConfig,
+    new_runtime: Box<dyn Fn() -> Runtime>,
+    setup: Box<dyn Fn(&Context) -> Result<()>>,
+    idle: RefCell<Vec<Entry>>,
+}
+
+impl ContextPool {
+    pub fn new(
+        config: PoolConfig,
+        new_runtime: impl Fn() -> Runtime + 'static,
+        setup: impl Fn(&Context) -> Result<()> + 'static,
+    ) -> Self {
+        Self {
+            config,
+            new_runtime: Box::new(new_runtime),
+            setup: Box::new(setup),
+            idle: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Take an idle context, or create and set up a new one if there is none.
+    pub fn acquire(&self) -> Result<PooledContext<'_>> {
+        let entry = match self.idle.borrow_mut().pop() {
+            Some(entry) => entry,
+            None => self.create()?,
+        };
+        Ok(PooledContext {
+            pool: self,
+            entry: Some(entry),
+            failed: false,
+        })
+    }
+
+    /// The number of contexts ready for reuse.
+    pub fn idle(&self) -> usize {
+        self.idle.borrow().len()
+    }
+
+    fn create(&self) -> Result<Entry> {
+        let runtime = (self.new_runtime)();
+        let ctx = runtime.new_context();
+        (self.setup)(&ctx)?;
+        ctx.snapshot_globals()?;
+        Ok(Entry {
+            ctx,
+            runtime,
+            uses: 0,
+        })
+    }
+
+    fn release(&self, mut entry: Entry, failed: bool) {
+        entry.uses += 1;
+        if failed || self.config.max_uses.is_some_and(|max| entry.uses >= max) {
+            return;
+        }
+        if self.idle() >= self.config.max_idle {
+            return;
+        }
+        if let Err(err) = self.reset(&entry) {
+            log::debug!("discarding pooled context: {err:#}");
+            return;
+        }
+        self.idle.borrow_mut().push(entry);
+    }
+
+    fn reset(&self, entry: &Entry) -> Result<()> {
+        if unsafe { c::JS_IsJobPending(entry.runtime.as_ptr()) } != 0 {
+            bail!("the context has pending jobs");
+        }
+        let names = match &self.config.reset {
+            ResetGlobals::Added => return entry.ctx.reset_globals(),
+            ResetGlobals::Only(names) => names,
+        };
+        entry.ctx.reset_host_state()?;
+        let global = entry.ctx.get_global_object();
+        for name in names {
+            let key = Atom::new(&entry.ctx, name);
+            if !delete_property(&entry.ctx, &global, &key)? {
+                bail!("global {name} can not be deleted");
+            }
+        }
+        crate::engine::collect_garbage(entry.runtime.as_ptr());
+        Ok(())
+    }
+}
+
+/// A context taken from a [`ContextPool`], returned to it on drop.
+pub struct PooledContext<'a> {
+    pool: &'a ContextPool,
+    entry: Option<Entry>,
+    failed: bool,
+}
+
+impl PooledContext<'_> {
+    /// Discard the context instead of returning it to the pool, for example after a request
+    /// failed and may have left it in an unknown state.
+    pub fn discard(&mut self) {
+        self.failed = true;
+    }
+
+    /// The number of requests the context served before this one.
+    pub fn uses(&self) -> usize {
+        self.entry.as_ref().map_or(0, |entry| entry.uses)
+    }
+}
+
+impl Deref for PooledContext<'_> {
+    type Target = Context;
+
+    fn deref(&self) -> &Context {
+        &self.entry.as_ref().expect("pooled context taken").ctx
+    }
+}
+
+impl Drop for PooledContext<'_> {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.pool.release(entry, self.failed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    fn pool(config: PoolConfig, created: Rc<Cell<usize>>) -> ContextPool {
+        ContextPool::new(
+            config,
+            || Runtime::new(&Default::default()),
+            move |ctx| {
+                created.set(created.get() + 1);
+                ctx.eval(&Code::Source("globalThis.Ext = { greet: () => 'hi' }"))
+                    .map_err(crate::Error::msg)?;
+                Ok(())
+            },
+        )
+    }
+
+    #[test]
+    fn requests_do_not_share_globals() {
+        let created = Rc::new(Cell::new(0));
+        let pool = pool(PoolConfig::default(), created.clone());
+        for i in 0..50 {
+            let ctx = pool.acquire().unwrap();
+            let seen = ctx
+                .eval(&Code::Source(
+                    "typeof leaked + ',' + typeof leakedFn + ',' + Ext.greet()",
+                ))
+                .unwrap()
+                .decode_string()
+                .unwrap();
+            assert_eq!(seen, "undefined,undefined,hi", "request {i}");
+            ctx.eval(&Code::Source(
+                "globalThis.leaked = 1; globalThis.leakedFn = () => leaked;",
+            ))
+            .unwrap();
+            assert_eq!(ctx.uses(), i);
+        }
+        assert_eq!(created.get(), 1);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[test]
+    fn discards_contexts_that_can_not_be_reset() {
+        let created = Rc::new(Cell::new(0));
+        let config = PoolConfig {
+            max_uses: Some(3),
+            ..Default::default()
+        };
+        let pool = pool(config, created.clone());
+        for _ in 0..6 {
+            drop(pool.acquire().unwrap());
+        }
+        assert_eq!(created.get(), 2);
+
+        let ctx = pool.acquire().unwrap();
+        ctx.eval(&Code::Source("var sticky = 1")).unwrap();
+        drop(ctx);
+        assert_eq!(pool.idle(), 0);
+
+        let mut ctx = pool.acquire().unwrap();
+        ctx.discard();
+        drop(ctx);
+        assert_eq!(pool.idle(), 0);
+
+        let ctx = pool.acquire().unwrap();
+        ctx.eval(&Code::Source("Promise.resolve().then(() => {})"))
+            .unwrap();
+        drop(ctx);
+        assert_eq!(pool.idle(), 0);
+        assert_eq!(created.get(), 5);
+    }
+
+    #[crate::host_call]
+    fn touch() -> u32 {
+        1
+    }
+
+    #[test]
+    fn requests_do_not_share_host_state() {
+        for reset in [
+            ResetGlobals::Added,
+            ResetGlobals::Only(alloc::vec!["session".into()]),
+        ] {
+            let config = PoolConfig {
+                reset,
+                ..Default::default()
+            };
+            let pool = pool(config, Rc::new(Cell::new(0)));
+            let request = |ctx: &Context, tenant: &str| {
+                ctx.get_global_object()
+                    .define_property_fn("touch", touch)
+                    .unwrap();
+                let src = alloc::format!("globalThis.session = '{tenant}'; touch()");
+                ctx.eval(&Code::Source(&src)).unwrap();
+            };
+
+            let audited = Rc::new(Cell::new(0));
+            let ctx = pool.acquire().unwrap();
+            let counter = audited.clone();
+            ctx.set_access_auditor(move |_| counter.set(counter.get() + 1))
+                .unwrap();
+            #[cfg(feature = "stable-hash")]
+            ctx.enable_receipt(Default::default()).unwrap();
+            request(&ctx, "tenant a");
+            drop(ctx);
+            assert_eq!(audited.get(), 1);
+
+            let ctx = pool.acquire().unwrap();
+            assert_eq!(ctx.uses(), 1);
+            #[cfg(feature = "stable-hash")]
+            assert!(ctx.take_receipt().unwrap().entries.is_empty());
+            request(&ctx, "tenant b");
+            #[cfg(feature = "stable-hash")]
+            assert!(ctx.take_receipt().unwrap().entries.is_empty());
+            assert_eq!(audited.get(), 1);
+            assert!(!ctx.audit_enabled());
+            drop(ctx);
+            // The auditor of tenant a is dropped, not kept with the context.
+            assert_eq!(Rc::strong_count(&audited), 1);
+        }
+    }
+
+    #[test]
+    fn resets_listed_globals_past_a_patched_reflect() {
+        let config = PoolConfig {
+            reset: ResetGlobals::Only(alloc::vec!["session".into()]),
+            ..Default::default()
+        };
+        let pool = pool(config, Rc::new(Cell::new(0)));
+        let ctx = pool.acquire().unwrap();
+        ctx.eval(&Code::Source(
+            "globalThis.session = 'tenant a'; Reflect.deleteProperty = () => true;",
+        ))
+        .unwrap();
+        drop(ctx);
+        let ctx = pool.acquire().unwrap();
+        let seen = ctx.eval(&Code::Source("typeof session")).unwrap();
+        assert_eq!(seen.decode_string().unwrap(), "undefined");
+    }
+}
@@ -0,0 +1,226 @@
+//! Reproducible `Math.random`.
+
+use alloc::rc::Rc;
+use core::cell::{Cell, RefCell};
+
+use anyhow::{bail, Context as _};
+
+use crate::{self as js, c, Result, Value};
+
+pub(crate) type RandomFn = Rc<RefCell<dyn FnMut() -> f64>>;
+
+/// The source of `Math.random` of a context, kept with its state.
+#[derive(Default)]
+struct RandomProvider {
+    random: RefCell<Option<RandomFn>>,
+    /// Whether `Math.random` is the host function reading the provider.
+    installed: Cell<bool>,
+}
+
+const ENGINE_RANDOM: &str = "engineRandom";
+
+impl js::Context {
+    /// Make `Math.random()` return the sequence of a [`SeededRng`] seeded with `seed`, the same
+    /// on every run and platform. It replaces the previous provider.
+    pub fn seed_random(&self, seed: u64) -> Result<()> {
+        let mut rng = SeededRng::new(seed);
+        self.set_random_provider(move || rng.next_f64())
+    }
+
+    /// Take the numbers of `Math.random()` from `random` instead of the engine. They must be in
+    /// `[0, 1)`, `Math.random()` throws otherwise. It replaces the previous provider.
+    ///
+    /// The first call makes `Math.random` a non-writable, non-configurable host function, which
+    /// finds the provider on the host side. Scripts that ran before may still hold the engine's
+    /// function, so set the provider before running them.
+    pub fn set_random_provider(&self, random: impl FnMut() -> f64 + 'static) -> Result<()> {
+        self.replace_random_provider(Some(Rc::new(RefCell::new(random))))
+    }
+
+    /// Go back to the random numbers of the engine.
+    pub fn clear_random_provider(&self) -> Result<()> {
+        if let Some(state) = self.user_data::<RandomProvider>() {
+            state.random.take();
+        }
+        Ok(())
+    }
+
+    /// The current provider.
+    pub(crate) fn random_provider(&self) -> Option<RandomFn> {
+        self.user_data::<RandomProvider>()?.random.borrow().clone()
+    }
+
+    /// Set the provider, `None` for the numbers of the engine.
+    pub(crate) fn replace_random_provider(&self, random: Option<RandomFn>) -> Result<()> {
+        let state = self
+            .state::<RandomProvider>()
+            .context("random providers need a context created by Runtime::new_context")?;
+        if !state.installed.get() {
+            install_random(self)?;
+            state.installed.set(true);
+        }
+        state.random.replace(random);
+        Ok(())
+    }
+}
+
+/// Define `Math.random` as the host's, keeping the engine's for the draws without a provider.
+fn install_random(ctx: &js::Context) -> Result<()> {
+    let math = ctx.get_global_object().get_property("Math")?;
+    let engine_random = math.get_property("random")?;
+    ctx.host_object(ENGINE_RANDOM, || Ok(engine_random))?;
+    let random = ctx.new_function("random", math_random, 0, c::JS_CFUNC_generic);
+    let name = unsafe { c::JS_NewAtomLen(ctx.as_ptr(), "random".as_ptr() as _, 6) };
+    let r = unsafe {
+        c::JS_DefinePropertyValue(
+            ctx.as_ptr(),
+            *math.raw_value(),
+            name,
+            random.leak(),
+            c::JS_PROP_THROW as _,
+        )
+    };
+    unsafe { c::JS_FreeAtom(ctx.as_ptr(), name) };
+    if r < 0 {
+        return Err(ctx.get_exception_error());
+    }
+    Ok(())
+}
+
+/// `Math.random`: the next number of the provider, or of the engine without one.
+#[crate::host_call(with_context)]
+fn math_random(ctx: js::Context, _this: Value) -> Result<Value> {
+    if let Some(random) = ctx.random_provider() {
+        let Ok(mut random) = random.try_borrow_mut() else {
+            bail!("the random provider called Math.random");
+        };
+        let value = random();
+        if !(0.0..1.0).contains(&value) {
+            bail!("the random provider returned {value}, outside of [0, 1)");
+        }
+        return Ok(Value::from_f64(&ctx, value));
+    }
+    let engine_random = ctx.host_object(ENGINE_RANDOM, || bail!("Math.random is not installed"))?;
+    engine_random.call(&Value::undefined(), &[])
+}
+
+/// A seeded PRNG, xoshiro256** seeded with splitmix64 as its authors recommend, spelled out so
+/// that its sequence is the same on every platform and in every version.
+pub struct SeededRng([u64; 4]);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        let mut splitmix = SplitMix64(seed);
+        Self([
+            splitmix.next(),
+            splitmix.next(),
+            splitmix.next(),
+            splitmix.next(),
+        ])
+    }
+
+    /// A generator seeded with 32 bytes, each of which changes the sequence.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let mut splitmix = SplitMix64(0);
+        let mut state = [0; 4];
+        for (word, chunk) in state.iter_mut().zip(seed.chunks_exact(8)) {
+            splitmix.0 ^= u64::from_le_bytes(chunk.try_into().expect("chunks of 8 bytes"));
+            *word = splitmix.next();
+        }
+        Self(state)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.0;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// A number in `[0, 1)` from the top 53 bits.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Fill `buf` with the bytes of the next numbers, little endian.
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+
+    use super::*;
+    use crate::Code;
+
+    const DRAW: &str = "Array.from({ length: 100 }, () => Math.random()).join()";
+
+    fn draw(runtime: &js::Runtime, seed: u64) -> String {
+        let ctx = runtime.new_context();
+        ctx.seed_random(seed).unwrap();
+        runtime.run_gc();
+        ctx.eval(&Code::Source(DRAW)).unwrap().to_string()
+    }
+
+    #[test]
+    fn seeded_contexts_draw_the_same_numbers() {
+        let runtime = js::Runtime::new(&Default::default());
+        let first = draw(&runtime, 42);
+        assert_eq!(first, draw(&js::Runtime::new(&Default::default()), 42));
+        assert_ne!(first, draw(&runtime, 43));
+        assert!(first
+            .split(',')
+            .map(|n| n.parse::<f64>().unwrap())
+            .all(|n| (0.0..1.0).contains(&n)));
+
+        let ctx = runtime.new_context();
+        ctx.set_random_provider(|| 0.25).unwrap();
+        let eval = |src: &str| ctx.eval(&Code::Source(src));
+        assert_eq!(
+            eval("delete globalThis._QjsBind; Math.random = () => 0; Math.random()")
+                .unwrap()
+                .to_string(),
+            "0.25"
+        );
+        ctx.set_random_provider(|| 1.0).unwrap();
+        assert!(eval("Math.random()").is_err());
+        ctx.clear_random_provider().unwrap();
+        let n = eval("Math.random()").unwrap().decode_f64().unwrap();
+        assert!((0.0..1.0).contains(&n));
+    }
+
+    #[test]
+    fn seeded_bytes_follow_the_numbers() {
+        let mut whole = [0; 20];
+        SeededRng::from_seed([7; 32]).fill_bytes(&mut whole);
+        let mut rng = SeededRng::from_seed([7; 32]);
+        assert_eq!(whole[..8], rng.next_u64().to_le_bytes());
+        assert_eq!(whole[8..16], rng.next_u64().to_le_bytes());
+        let mut other = [0; 20];
+        SeededRng::from_seed([8; 32]).fill_bytes(&mut other);
+        assert_ne!(whole, other);
+    }
+}
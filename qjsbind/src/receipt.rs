@@ -0,0 +1,307 @@
+//! Receipts of what an execution evaluated and called, comparable across machines.
+
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+
+use anyhow::Context as _;
+use sha2::{Digest, Sha256};
+
+use crate::{self as js, c, Code, HashKind, Result, Value};
+
+/// A SHA-256 hash.
+type Hash = [u8; 32];
+
+/// What `Context::enable_receipt` records. All of it by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiptOptions {
+    /// The hash of every source and bytecode evaluated.
+    pub code: bool,
+    /// The host functions called, with the hashes of their arguments.
+    pub host_calls: bool,
+    /// The hash of the result of every evaluation.
+    pub results: bool,
+}
+
+impl Default for ReceiptOptions {
+    fn default() -> Self {
+        Self {
+            code: true,
+            host_calls: true,
+            results: true,
+        }
+    }
+}
+
+/// A step of an execution, see [`Receipt`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReceiptEntry {
+    /// Source or bytecode was evaluated, as a script or a module, imported ones included.
+    Eval { bytecode: bool, code: Hash },
+    /// A host function was called. The arguments are hashed with `Value::stable_hash`, `None`
+    /// for the ones it rejects, like functions.
+    HostCall {
+        name: String,
+        args: Vec<Option<Hash>>,
+    },
+    /// An evaluation returned, with the stable hash of its result, the namespace of a module,
+    /// `None` if it threw or the result can not be hashed.
+    Result { value: Option<Hash> },
+}
+
+/// The steps of an execution in the order they happened.
+///
+/// Everything is hashed with SHA-256 over a deterministic encoding, so the same script with the
+/// same inputs produces the same receipt on any machine.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Receipt {
+    pub entries: Vec<ReceiptEntry>,
+}
+
+impl Receipt {
+    /// A hash of the whole receipt, to compare receipts without their entries.
+    pub fn digest(&self) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update((self.entries.len() as u64).to_le_bytes());
+        for entry in &self.entries {
+            match entry {
+                ReceiptEntry::Eval { bytecode, code } => {
+                    hasher.update([0, *bytecode as u8]);
+                    hasher.update(code);
+                }
+                ReceiptEntry::HostCall { name, args } => {
+                    hasher.update([1]);
+                    hasher.update((name.len() as u64).to_le_bytes());
+                    hasher.update(name.as_bytes());
+                    hasher.update((args.len() as u64).to_le_bytes());
+                    for arg in args {
+                        write_optional(&mut hasher, arg);
+                    }
+                }
+                ReceiptEntry::Result { value } => {
+                    hasher.update([2]);
+                    write_optional(&mut hasher, value);
+                }
+            }
+        }
+        hasher.finalize().into()
+    }
+}
+
+fn write_optional(hasher: &mut Sha256, hash: &Option<Hash>) {
+    match hash {
+        Some(hash) => {
+            hasher.update([1]);
+            hasher.update(hash);
+        }
+        None => hasher.update([0]),
+    }
+}
+
+struct Recorder {
+    options: ReceiptOptions,
+    receipt: Receipt,
+}
+
+/// The receipt being recorded for a context, kept on the host side so that the recorded script
+/// can not remove it.
+#[derive(Default)]
+struct ReceiptState {
+    recorder: RefCell<Option<Recorder>>,
+}
+
+impl js::Context {
+    /// Start recording a receipt of the code evaluated in the context, modules included, and
+    /// the host functions it calls, replacing the one being recorded.
+    pub fn enable_receipt(&self, options: ReceiptOptions) -> Result<()> {
+        let state = self
+            .state::<ReceiptState>()
+            .context("no receipts for a context without teardown support")?;
+        *state.recorder.borrow_mut() = Some(Recorder {
+            options,
+            receipt: Receipt::default(),
+        });
+        self.set_receipts_enabled(true);
+        Ok(())
+    }
+
+    /// Take what was recorded since the receipt was enabled or last taken. Recording goes on.
+    pub fn take_receipt(&self) -> Result<Receipt> {
+        let Some(state) = self.user_data::<ReceiptState>() else {
+            return Ok(Receipt::default());
+        };
+        let mut recorder = state.recorder.borrow_mut();
+        Ok(recorder
+            .as_mut()
+            .map(|recorder| core::mem::take(&mut recorder.receipt))
+            .unwrap_or_default())
+    }
+
+    /// The options of the receipt being recorded, `None` if none is.
+    pub(crate) fn receipt_options(&self) -> Option<ReceiptOptions> {
+        let state = self.user_data::<ReceiptState>()?;
+        let recorder = state.recorder.borrow();
+        recorder.as_ref().map(|recorder| recorder.options)
+    }
+
+    /// Stop recording, dropping what was recorded.
+    pub(crate) fn disable_receipt(&self) {
+        if let Some(state) = self.user_data::<ReceiptState>() {
+            state.recorder.take();
+        }
+        self.set_receipts_enabled(false);
+    }
+}
+
+/// Add the entry made by `entry` to the receipt of `ctx`, if one is being recorded.
+fn record(ctx: &js::Context, entry: impl FnOnce(&ReceiptOptions) -> Option<ReceiptEntry>) {
+    if !ctx.receipts_enabled() {
+        return;
+    }
+    let Some(state) = ctx.user_data::<ReceiptState>() else {
+        return;
+    };
+    let Some(options) = state
+        .recorder
+        .borrow()
+        .as_ref()
+        .map(|recorder| recorder.options)
+    else {
+        return;
+    };
+    // Made without the borrow: hashing arguments may call their `stableHash` methods.
+    if let Some(entry) = entry(&options) {
+        if let Some(recorder) = state.recorder.borrow_mut().as_mut() {
+            recorder.receipt.entries.push(entry);
+        }
+    }
+}
+
+pub(crate) fn record_eval(ctx: &js::Context, code: &Code) {
+    record(ctx, |options| {
+        if !options.code {
+            return None;
+        }
+        let (bytecode, bytes) = match code {
+            Code::Source(src) => (false, src.as_bytes()),
+            Code::Bytecode(bytes) => (true, *bytes),
+        };
+        Some(ReceiptEntry::Eval {
+            bytecode,
+            code: Sha256::digest(bytes).into(),
+        })
+    });
+}
+
+pub(crate) fn record_result(ctx: &js::Context, result: Option<&Value>) {
+    record(ctx, |options| {
+        options.results.then(|| ReceiptEntry::Result {
+            value: result.and_then(|value| value.stable_hash(HashKind::Sha256).ok()),
+        })
+    });
+}
+
+pub(crate) fn record_host_call(ctx: &js::Context, name: &str, args: &[c::JSValue]) {
+    record(ctx, |options| {
+        options.host_calls.then(|| ReceiptEntry::HostCall {
+            name: name.into(),
+            args: args
+                .iter()
+                .map(|arg| {
+                    Value::new_cloned(ctx, *arg)
+                        .stable_hash(HashKind::Sha256)
+                        .ok()
+                })
+                .collect(),
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[crate::host_call]
+    fn transfer(to: String, amount: u32) -> u32 {
+        let _ = to;
+        amount
+    }
+
+    fn run(amount: u32) -> Receipt {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let transfer = ctx.new_function("transfer", transfer, 2, c::JS_CFUNC_generic);
+        ctx.get_global_object()
+            .set_property("transfer", &transfer)
+            .unwrap();
+        ctx.enable_receipt(Default::default()).unwrap();
+        let src = alloc::format!("({{ sent: [transfer('alice', {amount}), transfer('bob', 2)] }})");
+        ctx.eval(&Code::Source(&src)).unwrap();
+        ctx.take_receipt().unwrap()
+    }
+
+    #[test]
+    fn same_execution_same_receipt() {
+        let first = run(1);
+        assert_eq!(first, run(1));
+        assert_eq!(first.digest(), run(1).digest());
+        assert!(matches!(
+            &first.entries[..],
+            [
+                ReceiptEntry::Eval {
+                    bytecode: false,
+                    ..
+                },
+                ReceiptEntry::HostCall { .. },
+                ReceiptEntry::HostCall { .. },
+                ReceiptEntry::Result { value: Some(_) },
+            ]
+        ));
+
+        // The source changes with the argument, so compare the calls too.
+        let other = run(3);
+        assert_ne!(first.digest(), other.digest());
+        assert_ne!(first.entries[1], other.entries[1]);
+        assert_eq!(first.entries[2], other.entries[2]);
+    }
+
+    #[test]
+    fn scripts_can_not_drop_the_receipt() {
+        let runtime = js::Runtime::new(&Default::default());
+        runtime.set_module_loader(|_, _| Ok(js::JsCode::Source("export const lib = 1;".into())));
+        let recorded = runtime.new_context();
+        let other = runtime.new_context();
+        for ctx in [&recorded, &other] {
+            let transfer = ctx.new_function("transfer", transfer, 2, c::JS_CFUNC_generic);
+            ctx.get_global_object()
+                .set_property("transfer", &transfer)
+                .unwrap();
+        }
+        recorded.enable_receipt(Default::default()).unwrap();
+        recorded
+            .eval(&Code::Source(
+                "delete globalThis._QjsBind; transfer('eve', 1)",
+            ))
+            .unwrap();
+        other.eval(&Code::Source("transfer('bob', 2)")).unwrap();
+        let src = "import { lib } from 'lib'; export const sent = transfer('eve', lib);";
+        recorded
+            .eval_module(&js::JsCode::Source(src.into()))
+            .unwrap();
+
+        let receipt = recorded.take_receipt().unwrap();
+        let calls = receipt
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry, ReceiptEntry::HostCall { .. }))
+            .count();
+        let evals = receipt
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry, ReceiptEntry::Eval { .. }))
+            .count();
+        assert_eq!((calls, evals), (2, 3));
+        assert_eq!(other.take_receipt().unwrap(), Receipt::default());
+    }
+}
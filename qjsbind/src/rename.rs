@@ -0,0 +1,173 @@
+use crate::{self as js, ErrorContext, Result, Value};
+
+/// The naming convention applied to the fields of derived structs that have neither a
+/// `rename_all` container attribute nor a `rename` field attribute.
+///
+/// The convention is stored per context, see `Context::set_default_rename`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Convention {
+    /// Use the Rust field names as they are.
+    #[default]
+    Keep,
+    /// Convert snake_case field names to camelCase.
+    CamelCase,
+}
+
+impl js::Context {
+    /// Set the convention used by derived `ToJsValue` and `FromJsValue` impls for fields without
+    /// an explicit name. With `Convention::CamelCase`, values are written with camelCase keys and
+    /// read from either the camelCase or the original key.
+    pub fn set_default_rename(&self, convention: Convention) -> Result<()> {
+        self.default_rename_cell()
+            .context("no default rename for a context without teardown support")?
+            .set(convention);
+        Ok(())
+    }
+
+    pub fn default_rename(&self) -> Convention {
+        self.default_rename_cell()
+            .map(|cell| cell.get())
+            .unwrap_or_default()
+    }
+
+    /// The key a derived `ToJsValue` impl writes a field to. Used by the generated code.
+    #[doc(hidden)]
+    pub fn field_name<'a>(&self, name: &'a str, camel_name: &'a str) -> &'a str {
+        match self.default_rename() {
+            Convention::Keep => name,
+            Convention::CamelCase => camel_name,
+        }
+    }
+}
+
+impl Value {
+    /// Read a field for a derived `FromJsValue` impl. Used by the generated code.
+    #[doc(hidden)]
+    pub fn get_field(&self, name: &str, camel_name: &str) -> Result<Value> {
+        let Ok(ctx) = self.context() else {
+            return self.get_property(name);
+        };
+        if ctx.default_rename() == Convention::CamelCase {
+            let value = self.get_property(camel_name)?;
+            if !value.is_undefined() {
+                return Ok(value);
+            }
+        }
+        self.get_property(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FromJsValue, ToJsValue};
+    use alloc::{string::String, vec::Vec};
+
+    #[derive(Debug, PartialEq, crate::ToJsValue, crate::FromJsValue)]
+    struct Plain {
+        gas_limit: u32,
+        #[qjs(rename = "the_nonce")]
+        nonce: u32,
+    }
+
+    #[derive(Debug, PartialEq, crate::ToJsValue, crate::FromJsValue)]
+    #[qjs(rename_all = "none")]
+    struct Snake {
+        gas_limit: u32,
+    }
+
+    #[derive(Debug, PartialEq, crate::ToJsValue)]
+    struct Sparse {
+        #[qjs(skip_serializing_if = "Option::is_none")]
+        gas_limit: Option<u32>,
+        #[qjs(rename = "the_nonce", skip_serializing_if = "Option::is_none")]
+        nonce: Option<u32>,
+        memo: Option<String>,
+        #[qjs(skip_serializing_if = "Vec::is_empty")]
+        inner: Vec<Sparse>,
+    }
+
+    fn keys(value: &Value) -> Vec<String> {
+        value
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().0.decode_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn context_default_applies_to_unnamed_fields() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let plain = Plain {
+            gas_limit: 1,
+            nonce: 2,
+        };
+        assert_eq!(
+            keys(&plain.to_js_value(&ctx).unwrap()),
+            ["gas_limit", "the_nonce"]
+        );
+
+        ctx.set_default_rename(Convention::CamelCase).unwrap();
+        let value = plain.to_js_value(&ctx).unwrap();
+        assert_eq!(keys(&value), ["gasLimit", "the_nonce"]);
+        assert_eq!(Plain::from_js_value(value).unwrap(), plain);
+
+        let snake = Snake { gas_limit: 3 };
+        let value = snake.to_js_value(&ctx).unwrap();
+        assert_eq!(keys(&value), ["gas_limit"]);
+        assert_eq!(Snake::from_js_value(value).unwrap(), snake);
+    }
+
+    #[test]
+    fn reads_either_convention_when_active() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.set_default_rename(Convention::CamelCase).unwrap();
+        let value = ctx
+            .eval(&crate::Code::Source("({ gas_limit: 7, the_nonce: 1 })"))
+            .unwrap();
+        assert_eq!(
+            Plain::from_js_value(value).unwrap(),
+            Plain {
+                gas_limit: 7,
+                nonce: 1
+            }
+        );
+    }
+
+    #[test]
+    fn skipped_fields_are_not_set() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.set_default_rename(Convention::CamelCase).unwrap();
+        let sparse = Sparse {
+            gas_limit: None,
+            nonce: Some(1),
+            memo: None,
+            inner: alloc::vec![Sparse {
+                gas_limit: Some(2),
+                nonce: None,
+                memo: None,
+                inner: Vec::new(),
+            }],
+        };
+        let value = sparse.to_js_value(&ctx).unwrap();
+        assert_eq!(keys(&value), ["the_nonce", "memo", "inner"]);
+        ctx.get_global_object().set_property("s", &value).unwrap();
+        let checks = ctx
+            .eval(&crate::Code::Source(
+                r#"
+                [
+                    "gasLimit" in s,
+                    s.memo === null,
+                    "the_nonce" in s.inner[0],
+                    "inner" in s.inner[0],
+                    s.inner[0].gasLimit,
+                ].join(" ")
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(checks.decode_string().unwrap(), "false true false false 2");
+    }
+}
@@ -0,0 +1,203 @@
+//! The state of a read-eval-print loop over a context, for debugging embedded scripts.
+//!
+//! There is no terminal handling here: the host reads lines from wherever it likes, stdin, a
+//! websocket or a test, and passes them to [`Session::submit`].
+//!
+//! ```ignore
+//! let mut session = js::repl::Session::new(ctx);
+//! for line in stdin.lines() {
+//!     match session.submit(&line?) {
+//!         Reply::Incomplete => print!("... "),
+//!         Reply::Value { shown, .. } => println!("{shown}"),
+//!         Reply::Error(report) => println!("{report}"),
+//!     }
+//! }
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{self as js, Code, Error, ErrorReport, Value};
+
+/// How deep nested objects and arrays are shown.
+const SHOW_DEPTH: u8 = 3;
+
+/// The outcome of a submitted line.
+#[derive(Debug)]
+pub enum Reply {
+    /// The input so far is not a complete script, the next line continues it.
+    Incomplete,
+    /// The input was evaluated to `value`, which is shown as `shown`.
+    Value { value: Value, shown: String },
+    /// The input threw.
+    Error(ErrorReport),
+}
+
+/// A REPL session over one context. The result of the last input is kept as the global `_`.
+pub struct Session {
+    ctx: js::Context,
+    pending: String,
+}
+
+impl Session {
+    pub fn new(ctx: js::Context) -> Self {
+        Self {
+            ctx,
+            pending: String::new(),
+        }
+    }
+
+    pub fn context(&self) -> &js::Context {
+        &self.ctx
+    }
+
+    /// Whether earlier lines are waiting for the rest of their input, to show a continuation
+    /// prompt.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Drop the incomplete input, like Ctrl-C in a terminal.
+    pub fn cancel(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Add `line` to the input and evaluate it if it is complete.
+    ///
+    /// Input is incomplete while brackets, strings, template literals or comments are left open,
+    /// or if the engine fails with a SyntaxError at the end of the input.
+    pub fn submit(&mut self, line: &str) -> Reply {
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if is_open(&self.pending) {
+            return Reply::Incomplete;
+        }
+        match self.ctx.eval(&Code::Source(&self.pending)) {
+            Ok(value) => {
+                self.pending.clear();
+                let mut shown = String::new();
+                js::recursive_to_string(&value, SHOW_DEPTH, true, &mut shown, "", 0);
+                // Failing to keep `_` is not worth failing the input that worked.
+                _ = self.ctx.get_global_object().set_property("_", &value);
+                Reply::Value { value, shown }
+            }
+            Err(err) if is_unexpected_end(&err) => Reply::Incomplete,
+            Err(err) => {
+                self.pending.clear();
+                Reply::Error(ErrorReport::from_error(&self.ctx, &Error::msg(err), |_| {
+                    None
+                }))
+            }
+        }
+    }
+}
+
+fn is_unexpected_end(err: &str) -> bool {
+    let message = err.lines().next().unwrap_or_default();
+    let Some(message) = message.strip_prefix("SyntaxError: ") else {
+        return false;
+    };
+    // The end of the input is an empty token, like in `1 +`.
+    message.starts_with("unexpected end of") || message.ends_with("expression: ''")
+}
+
+/// Whether `src` ends inside a bracket, string, template literal or block comment.
+///
+/// Regular expression literals are not recognized, a quote or bracket in one may make the input
+/// look open or closed when it is not. The engine then tells what is wrong with it.
+fn is_open(src: &str) -> bool {
+    #[derive(PartialEq)]
+    enum State {
+        Code,
+        Quoted(char),
+        Template,
+        LineComment,
+        BlockComment,
+    }
+    // The open brackets, `$` for the `${` of a template literal.
+    let mut brackets = Vec::new();
+    let mut state = State::Code;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match state {
+            State::Code => match c {
+                '(' | '[' | '{' => brackets.push(c),
+                ')' | ']' => _ = brackets.pop(),
+                '}' => {
+                    if brackets.pop() == Some('$') {
+                        state = State::Template;
+                    }
+                }
+                '\'' | '"' => state = State::Quoted(c),
+                '`' => state = State::Template,
+                '/' if chars.peek() == Some(&'/') => state = State::LineComment,
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    state = State::BlockComment;
+                }
+                _ => {}
+            },
+            State::Quoted(quote) => match c {
+                '\\' => _ = chars.next(),
+                // An unterminated string is a SyntaxError of its own, not a continued line.
+                '\n' => state = State::Code,
+                _ if c == quote => state = State::Code,
+                _ => {}
+            },
+            State::Template => match c {
+                '\\' => _ = chars.next(),
+                '`' => state = State::Code,
+                '$' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    brackets.push('$');
+                    state = State::Code;
+                }
+                _ => {}
+            },
+            State::LineComment => {
+                if c == '\n' {
+                    state = State::Code;
+                }
+            }
+            State::BlockComment => {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    state = State::Code;
+                }
+            }
+        }
+    }
+    !brackets.is_empty() || matches!(state, State::Template | State::BlockComment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripted_conversation() {
+        let runtime = js::Runtime::new(&Default::default());
+        let mut session = Session::new(runtime.new_context());
+        let mut say = |line: &str| match session.submit(line) {
+            Reply::Incomplete => "...".into(),
+            Reply::Value { shown, .. } => shown,
+            Reply::Error(report) => alloc::format!("! {}", report.message),
+        };
+
+        assert_eq!(say("1 + 2"), "3");
+        assert_eq!(say("_ * 2"), "6");
+        assert_eq!(say("function add(a, b) {"), "...");
+        assert_eq!(say("  // not done yet }"), "...");
+        assert_eq!(say("  return a + b;"), "...");
+        assert_eq!(say("}"), "undefined");
+        assert_eq!(say("add(2, 3)"), "5");
+        assert_eq!(say("const s = `multi"), "...");
+        assert_eq!(say("line ${ add(1, {"), "...");
+        assert_eq!(say("}.x ?? 1) }`; s"), r#""multi\nline 2""#);
+        assert_eq!(say("({ a: [1, 'x'] })"), r#"{a:[1,"x"]}"#);
+        assert_eq!(say("throw new Error('boom')"), "! Error: boom");
+        assert_eq!(say("_"), r#"{a:[1,"x"]}"#);
+        assert_eq!(say("1 +"), "...");
+        assert_eq!(say("4"), "5");
+    }
+}
@@ -0,0 +1,215 @@
+//! Resetting the globals of a context to reuse it for another request.
+
+use alloc::{collections::BTreeSet, rc::Rc, string::String};
+use core::cell::RefCell;
+
+use anyhow::{bail, Context as _};
+
+use crate::{
+    self as js,
+    audit::AccessEvent,
+    c,
+    own_keys::{delete_property, own_enumerable_keys, own_keys},
+    random::RandomFn,
+    time::TimeFn,
+    ConversionLimits, Result, Value,
+};
+
+const BINDINGS_KEY: &str = "_QjsBind";
+
+/// What `Context::reset_globals` keeps.
+struct Snapshot {
+    globals: BTreeSet<String>,
+    bindings: BTreeSet<String>,
+    host: HostState,
+}
+
+/// The state qjsbind keeps for a context on the host side that is set up rather than recorded.
+#[derive(Clone, Default)]
+struct HostState {
+    time_provider: Option<TimeFn>,
+    random_provider: Option<RandomFn>,
+    auditor: Option<Rc<dyn Fn(AccessEvent)>>,
+    limits: ConversionLimits,
+    #[cfg(feature = "stable-hash")]
+    receipt: Option<crate::ReceiptOptions>,
+}
+
+/// The snapshot of a context, kept with its state so that scripts can not remove it.
+#[derive(Default)]
+struct GlobalsSnapshot(RefCell<Option<Snapshot>>);
+
+impl js::Context {
+    /// Remember the current globals as the ones [`reset_globals`](Self::reset_globals) keeps.
+    /// Call it once the context is set up.
+    pub fn snapshot_globals(&self) -> Result<()> {
+        let state = self
+            .state::<GlobalsSnapshot>()
+            .context("no snapshot for a context without teardown support")?;
+        let snapshot = Snapshot {
+            globals: own_names(self, &self.get_global_object())?,
+            bindings: own_names(self, &self.qjsbind_bindings()?)?,
+            host: HostState {
+                time_provider: self.time_provider(),
+                random_provider: self.random_provider(),
+                auditor: self.access_auditor(),
+                limits: self.conversion_limits(),
+                #[cfg(feature = "stable-hash")]
+                receipt: self.receipt_options(),
+            },
+        };
+        state.0.replace(Some(snapshot));
+        Ok(())
+    }
+
+    /// Delete the globals added since [`snapshot_globals`](Self::snapshot_globals), or all but
+    /// the standard ones if it was not called, then run the GC so that the native objects they
+    /// held are dropped with their Rust state. The state qjsbind keeps for the context on the
+    /// host side is reset too: the time and random providers, the access auditor, the
+    /// conversion limits and the receipt being recorded go back to the ones of the snapshot,
+    /// or to none without one, and the receipt entries, retained sources and coverage recorded
+    /// since are dropped.
+    ///
+    /// Globals kept by the snapshot keep whatever changes were made to them. Fails if a global
+    /// can not be deleted, as is the case for top-level `var` declarations, and the context
+    /// should then be discarded.
+    pub fn reset_globals(&self) -> Result<()> {
+        let global = self.get_global_object();
+        let bindings = global.get_property(BINDINGS_KEY)?;
+        let snapshot = self.user_data::<GlobalsSnapshot>();
+        let snapshot = snapshot.as_ref().map(|state| state.0.borrow());
+        let (globals, kept_bindings) =
+            match snapshot.as_ref().and_then(|snapshot| snapshot.as_ref()) {
+                Some(snapshot) => (snapshot.globals.clone(), snapshot.bindings.clone()),
+                // The standard globals are the non-enumerable ones.
+                None => {
+                    let mut globals = own_names(self, &global)?;
+                    for key in own_enumerable_keys(self, &global)? {
+                        globals.remove(key.name.as_deref().unwrap_or_default());
+                    }
+                    globals.insert(BINDINGS_KEY.into());
+                    (globals, BTreeSet::new())
+                }
+            };
+        drop(snapshot);
+        self.reset_host_state()?;
+        if bindings.is_object() {
+            delete_added(self, &bindings, &kept_bindings)?;
+        }
+        delete_added(self, &global, &globals)?;
+        crate::engine::collect_garbage(unsafe { c::JS_GetRuntime(self.as_ptr()) });
+        Ok(())
+    }
+
+    /// Reset the state qjsbind keeps for the context on the host side, as `reset_globals` does.
+    pub(crate) fn reset_host_state(&self) -> Result<()> {
+        let snapshot = self.user_data::<GlobalsSnapshot>();
+        let host = snapshot
+            .as_ref()
+            .and_then(|state| state.0.borrow().as_ref().map(|snapshot| snapshot.host.clone()))
+            .unwrap_or_default();
+        match host.time_provider {
+            Some(now) => self.replace_time_provider(Some(now))?,
+            None => self.clear_time_provider()?,
+        }
+        match host.random_provider {
+            Some(random) => self.replace_random_provider(Some(random))?,
+            None => self.clear_random_provider()?,
+        }
+        match host.auditor {
+            Some(auditor) => self.replace_access_auditor(auditor)?,
+            None => self.clear_access_auditor()?,
+        }
+        self.restore_conversion_limits(host.limits);
+        // A fresh receipt, so that no entry of the previous request is taken with the next one.
+        #[cfg(feature = "stable-hash")]
+        match host.receipt {
+            Some(options) => self.enable_receipt(options)?,
+            None => self.disable_receipt(),
+        }
+        self.clear_retained_sources();
+        self.clear_coverage();
+        Ok(())
+    }
+}
+
+/// The own string keys of `object`, read without calling anything scripts can patch.
+fn own_names(ctx: &js::Context, object: &Value) -> Result<BTreeSet<String>> {
+    Ok(own_keys(ctx, object)?
+        .into_iter()
+        .filter_map(|key| key.name)
+        .collect())
+}
+
+/// Delete the own string keys of `object` missing from `keep`.
+fn delete_added(ctx: &js::Context, object: &Value, keep: &BTreeSet<String>) -> Result<()> {
+    for key in own_keys(ctx, object)? {
+        let Some(name) = &key.name else {
+            continue;
+        };
+        if keep.contains(name) {
+            continue;
+        }
+        if !delete_property(ctx, object, &key.atom)? {
+            bail!("global {name} can not be deleted");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::Code;
+
+    struct Tenant(Rc<Cell<bool>>);
+
+    impl Drop for Tenant {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[test]
+    fn reset_drops_what_requests_added() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap().to_string();
+        eval("globalThis.Ext = { greet: () => 'hi' }");
+        ctx.snapshot_globals().unwrap();
+
+        let dropped = Rc::new(Cell::new(false));
+        let state = Value::new_opaque_object(&ctx, Some("Tenant"), Tenant(dropped.clone()));
+        ctx.get_global_object()
+            .set_property("state", &state)
+            .unwrap();
+        drop(state);
+        eval("globalThis.leaked = 1");
+        ctx.reset_globals().unwrap();
+        assert!(dropped.get());
+        assert_eq!(
+            eval("[typeof leaked, typeof state, Ext.greet(), JSON.stringify([1])].join()"),
+            "undefined,undefined,hi,[1]"
+        );
+
+        // Neither patched builtins nor a deleted `_QjsBind` keep globals from being reset.
+        eval(
+            "globalThis.stays = 1; delete globalThis._QjsBind;
+            Reflect.deleteProperty = () => true; Reflect.ownKeys = () => [];",
+        );
+        ctx.reset_globals().unwrap();
+        assert_eq!(eval("typeof stays"), "undefined");
+
+        // Without a snapshot only the standard globals are kept.
+        let fresh = runtime.new_context();
+        fresh.eval(&Code::Source("globalThis.leaked = 1")).unwrap();
+        fresh.reset_globals().unwrap();
+        let seen = fresh
+            .eval(&Code::Source("typeof leaked + typeof JSON + typeof Math"))
+            .unwrap();
+        assert_eq!(seen.to_string(), "undefinedobjectobject");
+    }
+}
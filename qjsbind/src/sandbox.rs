@@ -0,0 +1,162 @@
+//! Run a script in its own runtime, talking to it only through cloned messages.
+
+use alloc::vec::Vec;
+use anyhow::{anyhow, bail};
+
+use crate::{self as js, Code, EngineConfig, Result, Runtime, Value};
+
+const OUTBOX_KEY: &str = "sandboxOutbox";
+
+/// A script isolated in a dedicated runtime and context, driven synchronously by the host.
+///
+/// The script receives messages through its global `onmessage(event)` handler, with the message
+/// in `event.data`, and sends messages back with the global `postMessage(value)`. Messages are
+/// copied between the runtimes, so only primitives, arrays, plain objects and byte arrays can
+/// cross the boundary.
+///
+/// ```ignore
+/// let sandbox = Sandbox::spawn(&Default::default(), "onmessage = e => postMessage(e.data)")?;
+/// sandbox.post(&js::Value::from_str(&ctx, "ping"))?;
+/// let replies = sandbox.drain(&ctx)?;
+/// ```
+pub struct Sandbox {
+    // Declared before the runtime so that it is dropped first.
+    ctx: js::Context,
+    runtime: Runtime,
+}
+
+impl Sandbox {
+    /// Create a runtime with the given config and evaluate `source` in a fresh context.
+    pub fn spawn(config: &EngineConfig, source: &str) -> Result<Self> {
+        let runtime = Runtime::new(config);
+        let ctx = runtime.new_context();
+        let post_message =
+            ctx.new_function("postMessage", post_message, 1, js::c::JS_CFUNC_generic);
+        ctx.get_global_object()
+            .set_property("postMessage", &post_message)?;
+        ctx.eval(&Code::Source(source))
+            .map_err(|err| anyhow!("failed to evaluate sandbox script: {err}"))?;
+        let sandbox = Self { ctx, runtime };
+        sandbox.run_jobs()?;
+        Ok(sandbox)
+    }
+
+    /// The context the script runs in.
+    pub fn context(&self) -> &js::Context {
+        &self.ctx
+    }
+
+    /// Copy `value` into the sandbox and pass it to the script's `onmessage` handler.
+    ///
+    /// Errors thrown by the handler, or by jobs it queued, are returned.
+    pub fn post(&self, value: &Value) -> Result<()> {
+        let handler = self.ctx.get_global_object().get_property("onmessage")?;
+        if !handler.is_function() {
+            bail!("sandbox script has no onmessage handler");
+        }
+        let event = self.ctx.new_object("MessageEvent");
+        event.set_property("data", &value.clone_into(&self.ctx)?)?;
+        handler.call(&Value::undefined(), &[event])?;
+        self.run_jobs()
+    }
+
+    /// Take the messages posted by the script so far, copied into `ctx`.
+    pub fn drain(&self, ctx: &js::Context) -> Result<Vec<Value>> {
+        let outbox = outbox(&self.ctx)?;
+        let len = outbox.length()?;
+        let messages = (0..len).map(|i| outbox.index(i)?.clone_into(ctx)).collect();
+        outbox.set_property("length", &Value::from_u32(&self.ctx, 0))?;
+        messages
+    }
+
+    fn run_jobs(&self) -> Result<()> {
+        self.runtime.execute_pending_jobs()?;
+        Ok(())
+    }
+}
+
+/// The messages posted by the script, held by the host where the script can not reach them.
+fn outbox(ctx: &js::Context) -> Result<Value> {
+    ctx.host_object(OUTBOX_KEY, || Ok(ctx.new_array()))
+}
+
+#[crate::host_call(with_context)]
+fn post_message(ctx: js::Context, _this: Value, message: Value) -> Result<()> {
+    outbox(&ctx)?.array_push(&message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echoes_messages() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let sandbox = Sandbox::spawn(
+            &Default::default(),
+            "onmessage = e => postMessage({ echo: e.data })",
+        )
+        .unwrap();
+        sandbox.post(&Value::from_str(&ctx, "ping")).unwrap();
+        let replies = sandbox.drain(&ctx).unwrap();
+        assert_eq!(replies.len(), 1);
+        let echo = replies[0].get_property("echo").unwrap();
+        assert_eq!(echo.decode_string().unwrap(), "ping");
+        assert!(sandbox.drain(&ctx).unwrap().is_empty());
+    }
+
+    #[test]
+    fn scripts_can_not_reach_the_outbox() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let sandbox = Sandbox::spawn(
+            &Default::default(),
+            r#"
+            onmessage = () => {
+                postMessage("kept");
+                const bindings = globalThis._QjsBind;
+                if (bindings && bindings.sandboxOutbox) {
+                    bindings.sandboxOutbox.length = 0;
+                    bindings.sandboxOutbox.push("forged");
+                }
+            }
+            "#,
+        )
+        .unwrap();
+        sandbox.post(&Value::null()).unwrap();
+        let replies = sandbox.drain(&ctx).unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].decode_string().unwrap(), "kept");
+    }
+
+    #[test]
+    fn surfaces_errors() {
+        let runtime = Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let sandbox = Sandbox::spawn(
+            &Default::default(),
+            "onmessage = () => { throw new Error('boom') }",
+        )
+        .unwrap();
+        let err = sandbox.post(&Value::null()).unwrap_err();
+        assert!(format!("{err:#}").contains("boom"), "{err:#}");
+        let fun = ctx.eval(&Code::Source("(() => 1)")).unwrap();
+        assert!(sandbox.post(&fun).is_err());
+    }
+
+    #[test]
+    fn isolates_globals() {
+        let a = Sandbox::spawn(&Default::default(), "globalThis.secret = 42").unwrap();
+        let b = Sandbox::spawn(&Default::default(), "").unwrap();
+        let secret = |sandbox: &Sandbox| {
+            sandbox
+                .context()
+                .get_global_object()
+                .get_property("secret")
+                .unwrap()
+        };
+        assert!(!secret(&a).is_undefined());
+        assert!(secret(&b).is_undefined());
+    }
+}
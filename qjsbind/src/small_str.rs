@@ -0,0 +1,101 @@
+//! A string buffer that stays on the stack when short, for the names and messages handed to the
+//! engine, which are mostly a few bytes long.
+//!
+//! `ctx_to_str` and `JsString::as_str` already borrow the engine's own buffer and do not need it,
+//! and property names are interned straight from the `&str`. `tests/allocations.rs` checks that
+//! none of them allocate.
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+const INLINE_LEN: usize = 48;
+
+pub(crate) struct SmallStr {
+    inline: [u8; INLINE_LEN],
+    len: usize,
+    heap: Option<String>,
+}
+
+impl SmallStr {
+    fn new() -> Self {
+        Self {
+            inline: [0; INLINE_LEN],
+            len: 0,
+            heap: None,
+        }
+    }
+
+    /// `SmallStr::format(format_args!(..))`, the stack counterpart of `format!`.
+    pub fn format(args: fmt::Arguments<'_>) -> Self {
+        let mut s = Self::new();
+        _ = s.write_fmt(args);
+        s
+    }
+
+    /// `s` with a nul terminator, or just the terminator if `s` has a nul byte itself, as
+    /// `CString::new(s).unwrap_or_default()` would give.
+    pub fn c_str(s: &str) -> Self {
+        let mut out = Self::new();
+        if !s.contains('\0') {
+            _ = out.write_str(s);
+        }
+        _ = out.write_str("\0");
+        out
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.heap {
+            Some(heap) => heap,
+            // Only whole `str`s are copied in.
+            None => unsafe { core::str::from_utf8_unchecked(&self.inline[..self.len]) },
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const core::ffi::c_char {
+        self.as_str().as_ptr() as _
+    }
+
+    #[cfg(test)]
+    fn is_inline(&self) -> bool {
+        self.heap.is_none()
+    }
+}
+
+impl Write for SmallStr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if let Some(heap) = &mut self.heap {
+            heap.push_str(s);
+            return Ok(());
+        }
+        let end = self.len + s.len();
+        if end <= INLINE_LEN {
+            self.inline[self.len..end].copy_from_slice(s.as_bytes());
+            self.len = end;
+        } else {
+            let mut heap = String::with_capacity(end);
+            heap.push_str(self.as_str());
+            heap.push_str(s);
+            self.heap = Some(heap);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spills_to_the_heap_when_long() {
+        let short = SmallStr::format(format_args!("{}", usize::MAX));
+        assert_eq!(short.as_str(), usize::MAX.to_string());
+        assert!(short.is_inline());
+        let long = "x".repeat(INLINE_LEN + 1);
+        let mut s = SmallStr::format(format_args!("{}", &long[..10]));
+        s.write_str(&long[10..]).unwrap();
+        assert_eq!(s.as_str(), long);
+        assert!(!s.is_inline());
+        assert_eq!(SmallStr::c_str("a\0b").as_str(), "\0");
+        assert_eq!(SmallStr::c_str("ab").as_str(), "ab\0");
+    }
+}
@@ -0,0 +1,462 @@
+//! Minimal source map support used to rewrite stack traces of bundled scripts.
+
+use alloc::{string::String, vec::Vec};
+use anyhow::{anyhow, bail};
+use core::fmt::Write;
+
+use crate::Result;
+
+const INLINE_MAP_PREFIX: &str = "//# sourceMappingURL=data:application/json;base64,";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mapping {
+    generated_column: u32,
+    source: u32,
+    line: u32,
+    column: u32,
+}
+
+/// A decoded source map (version 3), holding only what is needed to map locations.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    lines: Vec<Vec<Mapping>>,
+}
+
+impl SourceMap {
+    /// Build a source map from its `sources` list and the VLQ encoded `mappings` string.
+    pub fn new(sources: Vec<String>, mappings: &str) -> Result<Self> {
+        let mut lines = Vec::new();
+        let (mut source, mut line, mut column) = (0i64, 0i64, 0i64);
+        for encoded_line in mappings.split(';') {
+            let mut generated_column = 0i64;
+            let mut segments = Vec::new();
+            for segment in encoded_line.split(',').filter(|s| !s.is_empty()) {
+                let fields = decode_vlq(segment)?;
+                generated_column += fields[0];
+                if fields.len() < 4 {
+                    continue;
+                }
+                source += fields[1];
+                line += fields[2];
+                column += fields[3];
+                if generated_column < 0 || source < 0 || line < 0 || column < 0 {
+                    bail!("invalid source map segment: {segment}");
+                }
+                segments.push(Mapping {
+                    generated_column: generated_column as u32,
+                    source: source as u32,
+                    line: line as u32,
+                    column: column as u32,
+                });
+            }
+            segments.sort_by_key(|m| m.generated_column);
+            lines.push(segments);
+        }
+        Ok(Self { sources, lines })
+    }
+
+    /// Parse a source map from its JSON representation.
+    ///
+    /// The JSON is parsed on the host side, so that scripts can not intercept it by replacing
+    /// `JSON.parse`. Only `sources` and `mappings` are read, the other fields are skipped.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut reader = JsonReader::new(json);
+        let mut sources = Vec::new();
+        let mut mappings = String::new();
+        reader.expect(b'{')?;
+        if !reader.eat(b'}') {
+            loop {
+                let key = reader.string()?;
+                reader.expect(b':')?;
+                match key.as_str() {
+                    "sources" => sources = reader.sources()?,
+                    "mappings" => mappings = reader.string()?,
+                    _ => reader.skip_value()?,
+                }
+                if reader.eat(b'}') {
+                    break;
+                }
+                reader.expect(b',')?;
+            }
+        }
+        Self::new(sources, &mappings)
+    }
+
+    /// Extract the source map embedded as a trailing base64 data URL comment of `source`.
+    pub fn from_inline_comment(source: &str) -> Result<Option<Self>> {
+        let Some(pos) = source.rfind(INLINE_MAP_PREFIX) else {
+            return Ok(None);
+        };
+        let encoded = source[pos + INLINE_MAP_PREFIX.len()..].trim();
+        let json = decode_base64(encoded).ok_or_else(|| anyhow!("invalid inline source map"))?;
+        let json = core::str::from_utf8(&json).map_err(|_| anyhow!("invalid inline source map"))?;
+        Self::from_json(json).map(Some)
+    }
+
+    /// Look up the original location of a zero-based generated line and column.
+    pub fn lookup(&self, line: u32, column: u32) -> Option<(&str, u32, u32)> {
+        let segments = self.lines.get(line as usize)?;
+        let index = segments.partition_point(|m| m.generated_column <= column);
+        let mapping = segments.get(index.checked_sub(1)?)?;
+        let source = self.sources.get(mapping.source as usize)?;
+        Some((source.as_str(), mapping.line, mapping.column))
+    }
+
+    /// Rewrite every `filename:line:column` location found in `stack` to its original location.
+    ///
+    /// Locations without a mapping are left unchanged.
+    pub fn rewrite_stack(&self, filename: &str, stack: &str) -> String {
+        self.rewrite_stack_from(filename, 1, stack)
+    }
+
+    /// [`rewrite_stack`](Self::rewrite_stack) for a script whose first line is `first_line` of
+    /// `filename`, as evaluated with `EvalOptions::line`.
+    pub fn rewrite_stack_from(&self, filename: &str, first_line: u32, stack: &str) -> String {
+        let pattern = format!("{filename}:");
+        let mut output = String::new();
+        let mut rest = stack;
+        while let Some(pos) = rest.find(&pattern) {
+            output.push_str(&rest[..pos]);
+            let location = &rest[pos + pattern.len()..];
+            let mapped = parse_location(location).and_then(|(line, column, len)| {
+                let line = line.checked_sub(first_line.max(1))?;
+                let (source, line, column) = self.lookup(line, column.saturating_sub(1))?;
+                Some((source, line, column, len))
+            });
+            match mapped {
+                Some((source, line, column, len)) => {
+                    _ = write!(output, "{source}:{}:{}", line + 1, column + 1);
+                    rest = &location[len..];
+                }
+                None => {
+                    output.push_str(&pattern);
+                    rest = location;
+                }
+            }
+        }
+        output.push_str(rest);
+        output
+    }
+}
+
+/// Parse `line[:column]`, returning the one-based location and the number of bytes consumed.
+fn parse_location(s: &str) -> Option<(u32, u32, usize)> {
+    let line_len = s.bytes().take_while(u8::is_ascii_digit).count();
+    let line = s[..line_len].parse().ok()?;
+    let rest = &s[line_len..];
+    if let Some(rest) = rest.strip_prefix(':') {
+        let column_len = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if let Ok(column) = rest[..column_len].parse() {
+            return Some((line, column, line_len + 1 + column_len));
+        }
+    }
+    Some((line, 1, line_len))
+}
+
+/// Just enough of a JSON parser to read the fields of a source map.
+struct JsonReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input: input.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.input.get(self.pos) {
+            self.pos += 1;
+        }
+        self.input.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: u8) -> Result<()> {
+        if !self.eat(c) {
+            bail!(
+                "invalid source map JSON: expected `{}` at {}",
+                c as char,
+                self.pos
+            );
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let mut output = String::new();
+        loop {
+            let Some(&c) = self.input.get(self.pos) else {
+                bail!("invalid source map JSON: unterminated string");
+            };
+            self.pos += 1;
+            match c {
+                b'"' => return Ok(output),
+                b'\\' => {
+                    let escape = self.input.get(self.pos).copied();
+                    self.pos += 1;
+                    let c = match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => bail!("invalid source map JSON: bad escape at {}", self.pos),
+                    };
+                    output.push(c);
+                }
+                _ => {
+                    // Copy the run of plain bytes at once, keeping multi-byte characters whole.
+                    let start = self.pos - 1;
+                    while let Some(c) = self.input.get(self.pos) {
+                        if matches!(c, b'"' | b'\\') {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                    let run = core::str::from_utf8(&self.input[start..self.pos])
+                        .map_err(|_| anyhow!("invalid source map JSON: bad UTF-8"))?;
+                    output.push_str(run);
+                }
+            }
+        }
+    }
+
+    /// The character of a `\uXXXX` escape, the `\u` already consumed, combining surrogate
+    /// pairs. Lone surrogates become U+FFFD.
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        if (0xd800..0xdc00).contains(&high) && self.input[self.pos..].starts_with(b"\\u") {
+            let pos = self.pos;
+            self.pos += 2;
+            let low = self.hex4()?;
+            if (0xdc00..0xe000).contains(&low) {
+                let c = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                return Ok(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            self.pos = pos;
+        }
+        Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| core::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| anyhow!("invalid source map JSON: bad \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    /// The `sources` array. A `null` source, allowed by the format, is an empty name.
+    fn sources(&mut self) -> Result<Vec<String>> {
+        let mut sources = Vec::new();
+        self.expect(b'[')?;
+        if self.eat(b']') {
+            return Ok(sources);
+        }
+        loop {
+            if self.peek() == Some(b'n') {
+                self.literal()?;
+                sources.push(String::new());
+            } else {
+                sources.push(self.string()?);
+            }
+            if self.eat(b']') {
+                return Ok(sources);
+            }
+            self.expect(b',')?;
+        }
+    }
+
+    fn skip_value(&mut self) -> Result<()> {
+        match self.peek() {
+            Some(b'"') => {
+                self.string()?;
+            }
+            Some(open @ (b'[' | b'{')) => {
+                let close = if open == b'[' { b']' } else { b'}' };
+                self.pos += 1;
+                if self.eat(close) {
+                    return Ok(());
+                }
+                loop {
+                    if open == b'{' {
+                        self.string()?;
+                        self.expect(b':')?;
+                    }
+                    self.skip_value()?;
+                    if self.eat(close) {
+                        break;
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            Some(_) => self.literal()?,
+            None => bail!("invalid source map JSON: unexpected end"),
+        }
+        Ok(())
+    }
+
+    /// A number, `true`, `false` or `null`.
+    fn literal(&mut self) -> Result<()> {
+        let start = self.pos;
+        while let Some(c) = self.input.get(self.pos) {
+            if !matches!(c, b'a'..=b'z' | b'0'..=b'9' | b'-' | b'+' | b'.' | b'E') {
+                break;
+            }
+            self.pos += 1;
+        }
+        if self.pos == start {
+            bail!("invalid source map JSON: unexpected character at {start}");
+        }
+        Ok(())
+    }
+}
+
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+fn decode_vlq(segment: &str) -> Result<Vec<i64>> {
+    let mut values = Vec::new();
+    let mut value = 0i64;
+    let mut shift = 0;
+    for c in segment.bytes() {
+        let digit = base64_value(c).ok_or_else(|| anyhow!("invalid VLQ digit: {}", c as char))?;
+        if shift > 60 {
+            bail!("VLQ value overflow: {segment}");
+        }
+        value += ((digit & 0x1f) as i64) << shift;
+        if digit & 0x20 != 0 {
+            shift += 5;
+            continue;
+        }
+        let negative = value & 1 != 0;
+        value >>= 1;
+        values.push(if negative { -value } else { value });
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 || values.is_empty() {
+        bail!("truncated VLQ segment: {segment}");
+    }
+    Ok(values)
+}
+
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let input = input.trim_end_matches('=');
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in input.bytes() {
+        buffer = (buffer << 6) | base64_value(c)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as js;
+
+    #[test]
+    fn maps_stack_locations() {
+        // Generated from `a.ts`: line 2 of the bundle maps to line 5 column 3 of a.ts
+        let map = SourceMap::new(vec!["a.ts".to_string()], "AAAA;AAIE").unwrap();
+        assert_eq!(map.lookup(1, 0), Some(("a.ts", 4, 2)));
+        let stack = "    at foo (<eval>:2:1)\n    at <eval>:9:1\n";
+        assert_eq!(
+            map.rewrite_stack("<eval>", stack),
+            "    at foo (a.ts:5:3)\n    at <eval>:9:1\n"
+        );
+        let stack = "    at foo (<eval>:11:1)\n    at <eval>:9:1\n";
+        assert_eq!(
+            map.rewrite_stack_from("<eval>", 10, stack),
+            "    at foo (a.ts:5:3)\n    at <eval>:9:1\n"
+        );
+    }
+
+    #[test]
+    fn parses_json_on_the_host() {
+        let json = r#"{
+            "version": 3,
+            "file": "bundle.js",
+            "names": ["a", {"nested": [1, -2.5e3, null, true]}],
+            "sources": ["src/\u00e9t\u00e9.ts", null, "\ud83d\ude00.ts"],
+            "sourcesContent": ["let a = \"}\";"],
+            "mappings": "AAAA;AAIE"
+        }"#;
+        let map = SourceMap::from_json(json).unwrap();
+        assert_eq!(map.sources, ["src/été.ts", "", "😀.ts"]);
+        assert_eq!(map.lookup(1, 0), Some(("src/été.ts", 4, 2)));
+
+        assert!(SourceMap::from_json(r#"{"sources": ["a.ts"}"#).is_err());
+        assert!(SourceMap::from_json(r#"{"mappings": "AAAA"#).is_err());
+    }
+
+    #[test]
+    fn scripts_can_not_intercept_the_map() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        ctx.eval(&js::Code::Source("JSON.parse = () => { throw 1; }"))
+            .unwrap();
+        let map = "{\"sources\":[\"a.ts\"],\"mappings\":\"AAAA;AAIE\"}";
+        let src = alloc::format!(
+            "0;\nthrow new Error('boom');\n{INLINE_MAP_PREFIX}{}",
+            encode_base64(map.as_bytes())
+        );
+        let err = ctx.eval(&js::Code::Source(&src)).unwrap_err();
+        assert!(err.contains("a.ts:5:3"), "{err}");
+    }
+
+    fn encode_base64(input: &[u8]) -> String {
+        const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut output = String::new();
+        for chunk in input.chunks(3) {
+            let n = chunk.iter().fold(0u32, |n, b| (n << 8) | *b as u32);
+            let n = n << (8 * (3 - chunk.len()));
+            for i in 0..=chunk.len() {
+                output.push(DIGITS[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn decodes_vlq() {
+        assert_eq!(decode_vlq("AAgBC").unwrap(), vec![0, 0, 16, 1]);
+        assert!(decode_vlq("g").is_err());
+        assert_eq!(decode_base64("e30=").unwrap(), b"{}");
+    }
+}
@@ -0,0 +1,316 @@
+//! Deterministic structural hashing of JS values.
+
+use alloc::{string::String, vec::Vec};
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result, TypedArrayKind, Value};
+
+/// The hash function used by `Value::stable_hash`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashKind {
+    #[default]
+    Sha256,
+}
+
+const MAX_DEPTH: usize = 128;
+
+mod tag {
+    pub const UNDEFINED: u8 = 0;
+    pub const NULL: u8 = 1;
+    pub const FALSE: u8 = 2;
+    pub const TRUE: u8 = 3;
+    pub const NUMBER: u8 = 4;
+    pub const STRING: u8 = 5;
+    pub const BIGINT: u8 = 6;
+    pub const ARRAY: u8 = 7;
+    pub const OBJECT: u8 = 8;
+    pub const BYTES: u8 = 9;
+    pub const CUSTOM: u8 = 10;
+    pub const TYPED_ARRAY: u8 = 11;
+}
+
+impl Value {
+    /// Hash the structure of the value.
+    ///
+    /// Supported are primitives, arrays, plain objects (hashed with sorted keys), typed arrays,
+    /// ArrayBuffer and BigInt. Other objects are hashed by the result of their `stableHash`
+    /// method if they have one, and rejected otherwise. Functions are always rejected.
+    ///
+    /// A Uint8Array hashes like an ArrayBuffer of the same bytes. The other typed arrays are
+    /// hashed by their kind and the little endian bytes of their elements.
+    ///
+    /// Numbers are hashed by value, so `-0` and `0` hash the same, as do all NaNs. So do the
+    /// elements of float typed arrays.
+    pub fn stable_hash(&self, kind: HashKind) -> Result<[u8; 32]> {
+        match kind {
+            HashKind::Sha256 => {
+                let mut hasher = Sha256::new();
+                write_value(self, &mut hasher, 0).map_err(Failure::into_error)?;
+                Ok(hasher.finalize().into())
+            }
+        }
+    }
+}
+
+/// Why hashing failed.
+enum Failure {
+    /// An error of the engine, returned as is.
+    Error(Error),
+    /// A value that can not be hashed, with the path to it, its innermost segment first. The
+    /// path is only built while unwinding, so that hashing allocates nothing for it.
+    Unhashable { reason: String, path: Vec<String> },
+}
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Self {
+        Self::Error(err)
+    }
+}
+
+impl Failure {
+    fn unhashable(reason: String) -> Self {
+        Self::Unhashable {
+            reason,
+            path: Vec::new(),
+        }
+    }
+
+    /// The failure of a value reached through `segment`.
+    fn under(mut self, segment: impl FnOnce() -> String) -> Self {
+        if let Self::Unhashable { path, .. } = &mut self {
+            path.push(segment());
+        }
+        self
+    }
+
+    fn into_error(self) -> Error {
+        match self {
+            Self::Error(err) => err,
+            Self::Unhashable { reason, path } => {
+                let path: String = path.into_iter().rev().collect();
+                anyhow!("stable_hash: {reason} at `{path}`")
+            }
+        }
+    }
+}
+
+fn write_len(hasher: &mut Sha256, len: usize) {
+    hasher.update((len as u64).to_le_bytes());
+}
+
+fn write_str(hasher: &mut Sha256, s: &str) {
+    write_len(hasher, s.len());
+    hasher.update(s.as_bytes());
+}
+
+/// Make every NaN and `-0` element of a float array the same as `NaN` and `0`.
+fn normalize_floats(bytes: &mut [u8], kind: TypedArrayKind) {
+    match kind {
+        TypedArrayKind::Float32 => {
+            for element in bytes.chunks_exact_mut(4) {
+                let n = f32::from_le_bytes(element.try_into().unwrap());
+                if n.is_nan() || n == 0.0 {
+                    let n = if n.is_nan() { f32::NAN } else { 0.0 };
+                    element.copy_from_slice(&n.to_le_bytes());
+                }
+            }
+        }
+        TypedArrayKind::Float64 => {
+            for element in bytes.chunks_exact_mut(8) {
+                let n = f64::from_le_bytes(element.try_into().unwrap());
+                if n.is_nan() || n == 0.0 {
+                    let n = if n.is_nan() { f64::NAN } else { 0.0 };
+                    element.copy_from_slice(&n.to_le_bytes());
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn write_value(value: &Value, hasher: &mut Sha256, depth: usize) -> Result<(), Failure> {
+    if depth > MAX_DEPTH {
+        return Err(Failure::unhashable("value too deep or cyclic".into()));
+    }
+    if value.is_undefined() {
+        hasher.update([tag::UNDEFINED]);
+    } else if value.is_null() {
+        hasher.update([tag::NULL]);
+    } else if value.is_bool() {
+        let byte = if value.decode_bool()? {
+            tag::TRUE
+        } else {
+            tag::FALSE
+        };
+        hasher.update([byte]);
+    } else if value.is_number() {
+        let n = value.decode_f64()?;
+        let n = if n.is_nan() {
+            f64::NAN
+        } else if n == 0.0 {
+            0.0
+        } else {
+            n
+        };
+        hasher.update([tag::NUMBER]);
+        hasher.update(n.to_bits().to_le_bytes());
+    } else if value.is_string() {
+        hasher.update([tag::STRING]);
+        write_str(hasher, &value.decode_string()?);
+    } else if value.is_big_int() {
+        hasher.update([tag::BIGINT]);
+        write_str(hasher, &value.to_string());
+    } else if value.is_function() {
+        return Err(Failure::unhashable("can not hash function".into()));
+    } else if value.is_uint8_array() || value.is_array_buffer() {
+        hasher.update([tag::BYTES]);
+        let bytes = value.decode_bytes()?;
+        write_len(hasher, bytes.len());
+        hasher.update(&bytes);
+    } else if let Some(kind) = value.is_typed_array() {
+        hasher.update([tag::TYPED_ARRAY]);
+        write_str(hasher, kind.name());
+        let mut bytes = crate::transfer::typed_array_bytes(value)?;
+        crate::transfer::to_little_endian(&mut bytes, kind);
+        normalize_floats(&mut bytes, kind);
+        write_len(hasher, bytes.len());
+        hasher.update(&bytes);
+    } else if value.is_array() {
+        hasher.update([tag::ARRAY]);
+        let len = value.length()?;
+        write_len(hasher, len);
+        for i in 0..len {
+            write_value(&value.index(i)?, hasher, depth + 1)
+                .map_err(|failure| failure.under(|| format!("[{i}]")))?;
+        }
+    } else if value.is_plain_object() {
+        hasher.update([tag::OBJECT]);
+        let mut entries = value
+            .entries()?
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((key.decode_string()?, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        write_len(hasher, entries.len());
+        for (key, value) in entries {
+            write_str(hasher, &key);
+            write_value(&value, hasher, depth + 1)
+                .map_err(|failure| failure.under(|| format!(".{key}")))?;
+        }
+    } else {
+        let method = value.get_property("stableHash")?;
+        if !method.is_function() {
+            let reason = format!("can not hash {}", value.get_name());
+            return Err(Failure::unhashable(reason));
+        }
+        hasher.update([tag::CUSTOM]);
+        write_str(hasher, &value.get_name());
+        write_value(&method.call(value, &[])?, hasher, depth + 1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as js, Code, HashKind};
+
+    fn hash_of(ctx: &js::Context, src: &str) -> js::Result<[u8; 32]> {
+        let value = ctx.eval(&Code::Source(src)).map_err(js::Error::msg)?;
+        value.stable_hash(HashKind::Sha256)
+    }
+
+    #[test]
+    fn equal_structures_hash_the_same() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let hash = |src: &str| hash_of(&ctx, src).unwrap();
+        assert_eq!(
+            hash("({ a: 1, b: [true, null, 'x'], c: { d: 2n } })"),
+            hash("({ c: { d: 2n }, b: [true, null, 'x'], a: 1 })"),
+        );
+        assert_eq!(hash("[0]"), hash("[-0]"));
+        assert_eq!(hash("NaN"), hash("0 / 0"));
+        assert_ne!(hash("[1, 2]"), hash("[2, 1]"));
+        assert_ne!(hash("'1'"), hash("1"));
+        assert_ne!(hash("1"), hash("1n"));
+        assert_ne!(hash("({ a: undefined })"), hash("({})"));
+        // Pinned, so that a change of the encoding is noticed.
+        assert_eq!(
+            hex::encode(hash("({ a: [1, 'b'] })")),
+            "42ab05901095ce95912bd28619fcaa3073cdca6ebeea6f6eb5f2532a8ae7531d"
+        );
+    }
+
+    #[test]
+    fn typed_arrays_hash_their_kind_and_content() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let hash = |src: &str| hash_of(&ctx, src).unwrap();
+        assert_eq!(
+            hash("new Uint8Array([1, 2])"),
+            hash("new Uint8Array([1, 2]).buffer")
+        );
+        assert_ne!(
+            hash("new Uint8Array([1, 2])"),
+            hash("new Uint8Array([1, 3])")
+        );
+        for kind in [
+            "Int8Array",
+            "Uint8ClampedArray",
+            "Int16Array",
+            "Uint16Array",
+            "Int32Array",
+            "Uint32Array",
+            "Float32Array",
+            "Float64Array",
+        ] {
+            let a = hash(&alloc::format!("new {kind}([1, 2])"));
+            assert_eq!(a, hash(&alloc::format!("new {kind}([1, 2])")), "{kind}");
+            assert_ne!(a, hash(&alloc::format!("new {kind}([1, 3])")), "{kind}");
+            assert_ne!(a, hash("new Uint8Array([1, 2])"), "{kind}");
+        }
+        assert_ne!(hash("new Int16Array([1])"), hash("new Uint16Array([1])"));
+        assert_ne!(
+            hash("new BigInt64Array([1n])"),
+            hash("new BigInt64Array([2n])")
+        );
+        // Like numbers, floats hash by value.
+        assert_eq!(
+            hash("new Float64Array([-0, 0 / 0])"),
+            hash("new Float64Array([0, NaN])")
+        );
+        let other_nan =
+            "const b = new Float32Array(1); new DataView(b.buffer).setUint32(0, 0xffc00001); b";
+        assert_eq!(hash(other_nan), hash("new Float32Array([NaN])"));
+        assert_ne!(
+            hash("new Float64Array([-1])"),
+            hash("new Float64Array([1])")
+        );
+        // Only the viewed elements are hashed.
+        assert_eq!(
+            hash("new Int32Array([7, 1, 2]).subarray(1)"),
+            hash("new Int32Array([1, 2])")
+        );
+    }
+
+    #[test]
+    fn functions_and_natives_are_rejected() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let err = hash_of(&ctx, "({ a: [1, () => 1] })").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "stable_hash: can not hash function at `.a[1]`"
+        );
+        assert!(hash_of(&ctx, "({ m: new Map() })").is_err());
+        let custom = "class Point { stableHash() { return [1, 2]; } }; ({ v: new Point() })";
+        assert!(hash_of(&ctx, custom).is_ok());
+        let err = hash_of(&ctx, "class Opaque {}; [new Opaque()]").unwrap_err();
+        assert!(err.to_string().contains("at `[0]`"), "{err}");
+        let cyclic = "const a = []; a.push(a); a";
+        assert!(hash_of(&ctx, cyclic).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::ffi::c_int;
+use core::time::Duration;
+
+use anyhow::{bail, Context as _};
+
+use crate::{self as js, error::expect_err, FromJsValue, Result, ToJsValue, Value};
+
+/// A number of milliseconds.
+///
+/// Converted from a number, a BigInt, `{ms: n}` or `{secs: n}`, and to a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Millis(pub u64);
+
+/// A number of seconds.
+///
+/// Converted from a number, a BigInt, `{secs: n}` or `{ms: n}` with a whole number of seconds,
+/// and to a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Seconds(pub u64);
+
+impl From<Millis> for Duration {
+    fn from(ms: Millis) -> Self {
+        Duration::from_millis(ms.0)
+    }
+}
+
+impl From<Seconds> for Duration {
+    fn from(secs: Seconds) -> Self {
+        Duration::from_secs(secs.0)
+    }
+}
+
+/// Decode a non-negative integer from a number or a BigInt.
+fn decode_count(value: &Value, unit: &'static str) -> Result<u64> {
+    if value.is_big_int() {
+        let n = value.decode_i128()?;
+        if n < 0 {
+            bail!("negative {unit}: {n}");
+        }
+        return u64::try_from(n).or_else(|_| bail!("{unit} out of range: {n}"));
+    }
+    if !value.is_number() {
+        return Err(expect_err(unit, value));
+    }
+    let n = value.decode_f64()?;
+    if n < 0.0 {
+        bail!("negative {unit}: {n}");
+    }
+    if !n.is_finite() || n as u64 as f64 != n {
+        bail!("{unit} must be a whole number, got {n}");
+    }
+    Ok(n as u64)
+}
+
+/// Decode `{<key>: n}` if `value` is an object with that key.
+fn decode_unit_field(value: &Value, key: &str, unit: &'static str) -> Result<Option<u64>> {
+    if !value.is_object() {
+        return Ok(None);
+    }
+    let field = value.get_property(key)?;
+    if field.is_undefined() {
+        return Ok(None);
+    }
+    decode_count(&field, unit).map(Some)
+}
+
+impl FromJsValue for Millis {
+    fn from_js_value(value: Value) -> Result<Self> {
+        if let Some(ms) = decode_unit_field(&value, "ms", "milliseconds")? {
+            return Ok(Millis(ms));
+        }
+        if let Some(secs) = decode_unit_field(&value, "secs", "seconds")? {
+            let Some(ms) = secs.checked_mul(1000) else {
+                bail!("seconds out of range: {secs}");
+            };
+            return Ok(Millis(ms));
+        }
+        if value.is_object() {
+            return Err(expect_err("{ms} or {secs}", &value));
+        }
+        decode_count(&value, "milliseconds").map(Millis)
+    }
+}
+
+impl FromJsValue for Seconds {
+    fn from_js_value(value: Value) -> Result<Self> {
+        if let Some(secs) = decode_unit_field(&value, "secs", "seconds")? {
+            return Ok(Seconds(secs));
+        }
+        if let Some(ms) = decode_unit_field(&value, "ms", "milliseconds")? {
+            if ms % 1000 != 0 {
+                bail!("{ms} milliseconds is not a whole number of seconds");
+            }
+            return Ok(Seconds(ms / 1000));
+        }
+        if value.is_object() {
+            return Err(expect_err("{secs} or {ms}", &value));
+        }
+        decode_count(&value, "seconds").map(Seconds)
+    }
+}
+
+impl ToJsValue for Millis {
+    fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
+        Ok(Value::from_f64(ctx, self.0 as f64))
+    }
+}
+
+impl ToJsValue for Seconds {
+    fn to_js_value(&self, ctx: &js::Context) -> Result<Value> {
+        Ok(Value::from_f64(ctx, self.0 as f64))
+    }
+}
+
+/// A `Duration` is converted from `{secs, nanos}`, where both fields are optional, or from a
+/// number of milliseconds.
+impl FromJsValue for Duration {
+    fn from_js_value(value: Value) -> Result<Self> {
+        if !value.is_object() {
+            return Ok(Millis::from_js_value(value)?.into());
+        }
+        let secs = decode_unit_field(&value, "secs", "seconds")?;
+        let nanos = decode_unit_field(&value, "nanos", "nanoseconds")?;
+        if secs.is_none() && nanos.is_none() {
+            return Err(expect_err("{secs, nanos}", &value));
+        }
+        let nanos = nanos.unwrap_or_default();
+        let Ok(nanos) = u32::try_from(nanos) else {
+            bail!("nanoseconds out of range: {nanos}");
+        };
+        Duration::from_secs(secs.unwrap_or_default())
+            .checked_add(Duration::from_nanos(nanos.into()))
+            .ok_or_else(|| anyhow::anyhow!("duration out of range"))
+    }
+}
+
+pub(crate) type TimeFn = Rc<dyn Fn() -> i64>;
+
+/// The clock of the dates of a context, kept with its state.
+#[derive(Default)]
+struct TimeProvider {
+    now: RefCell<Option<TimeFn>>,
+}
+
+impl js::Context {
+    /// Read the current time, for `Date.now()`, `new Date()` and `Date()`, from `now`, in
+    /// milliseconds since the epoch, instead of the host clock. It replaces the previous provider.
+    ///
+    /// The engine asks the host for the time, so scripts can neither see nor undo the provider.
+    pub fn set_time_provider(&self, now: impl Fn() -> i64 + 'static) -> Result<()> {
+        self.replace_time_provider(Some(Rc::new(now)))
+    }
+
+    /// Go back to the host clock.
+    pub fn clear_time_provider(&self) -> Result<()> {
+        if let Some(state) = self.user_data::<TimeProvider>() {
+            state.now.take();
+        }
+        Ok(())
+    }
+
+    /// The current provider.
+    pub(crate) fn time_provider(&self) -> Option<TimeFn> {
+        self.user_data::<TimeProvider>()?.now.borrow().clone()
+    }
+
+    /// Set the provider, `None` for the host clock.
+    pub(crate) fn replace_time_provider(&self, now: Option<TimeFn>) -> Result<()> {
+        let state = self
+            .state::<TimeProvider>()
+            .context("time providers need a context created by Runtime::new_context")?;
+        state.now.replace(now);
+        Ok(())
+    }
+}
+
+/// Called by the engine for the current time of `Date`, see `Context::set_time_provider`.
+pub(crate) unsafe extern "C" fn date_now_hook(ctx: *mut js::c::JSContext, now: *mut i64) -> c_int {
+    let Some(ctx) = js::Context::clone_from_ptr(ctx) else {
+        return 0;
+    };
+    let Some(state) = ctx.user_data::<TimeProvider>() else {
+        return 0;
+    };
+    // A clone, so that the provider can replace itself.
+    let Some(provider) = state.now.borrow().clone() else {
+        return 0;
+    };
+    *now = provider();
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+
+    #[test]
+    fn dates_read_the_time_provider() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap().to_string();
+        ctx.set_time_provider(|| 1_700_000_000_000).unwrap();
+        assert_eq!(eval("Date.now()"), "1700000000000");
+        assert_eq!(eval("new Date().toISOString()"), "2023-11-14T22:13:20.000Z");
+        assert_eq!(eval("new Date(0).getTime()"), "0");
+        assert_eq!(
+            eval(
+                "class Later extends Date { later() { return this.getTime() + 1; } }
+                [new Later().later(), new Date() instanceof Date, Date() === new Date().toString()]"
+            ),
+            "1700000000001,true,true"
+        );
+        eval("delete globalThis._QjsBind; Date.prototype.constructor = null;");
+        assert_eq!(eval("Date.now()"), "1700000000000");
+        ctx.set_time_provider(|| 1).unwrap();
+        assert_eq!(eval("new Date().getTime()"), "1");
+
+        ctx.clear_time_provider().unwrap();
+        assert_ne!(eval("Date.now()"), "1");
+        ctx.set_time_provider(|| 0).unwrap();
+        assert_eq!(eval("new Date().toISOString()"), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn time_limit_in_millis() {
+        let config = js::EngineConfig::default().with_time_limit(Millis(250));
+        assert_eq!(config.time_limit, Some(250));
+    }
+
+    #[test]
+    fn accepts_each_shape() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+
+        assert_eq!(Millis::from_js_value(eval("1500")).unwrap(), Millis(1500));
+        assert_eq!(Millis::from_js_value(eval("1500n")).unwrap(), Millis(1500));
+        assert_eq!(Millis::from_js_value(eval("({ms: 7})")).unwrap(), Millis(7));
+        assert_eq!(
+            Millis::from_js_value(eval("({secs: 2})")).unwrap(),
+            Millis(2000)
+        );
+        assert_eq!(Seconds::from_js_value(eval("3")).unwrap(), Seconds(3));
+        assert_eq!(
+            Seconds::from_js_value(eval("({ms: 4000})")).unwrap(),
+            Seconds(4)
+        );
+        assert!(Seconds::from_js_value(eval("({ms: 4500})")).is_err());
+        assert_eq!(
+            Duration::from_js_value(eval("({secs: 1, nanos: 5})")).unwrap(),
+            Duration::new(1, 5)
+        );
+        assert_eq!(
+            Duration::from_js_value(eval("250")).unwrap(),
+            Duration::from_millis(250)
+        );
+        let value = Millis(42).to_js_value(&ctx).unwrap();
+        assert!(value.is_number());
+        assert_eq!(value.decode_u32().unwrap(), 42);
+    }
+
+    #[test]
+    fn rejects_negative_and_fractional_values() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+
+        for src in [
+            "-1",
+            "-1n",
+            "({ms: -1})",
+            "({secs: -1})",
+            "1.5",
+            "'10'",
+            "({})",
+        ] {
+            assert!(Millis::from_js_value(eval(src)).is_err(), "{src}");
+        }
+        let err = Seconds::from_js_value(eval("-3")).unwrap_err().to_string();
+        assert!(err.contains("negative seconds"), "{err}");
+        assert!(Duration::from_js_value(eval("({secs: -1})")).is_err());
+    }
+}
@@ -2,6 +2,7 @@ use crate as js;
 
 use super::{c, Result, Value};
 use crate::value::RawValue;
+use alloc::vec::Vec;
 use tinyvec::TinyVec;
 
 pub struct OwnedRawArgs {
@@ -24,6 +25,92 @@ impl OwnedRawArgs {
     }
 }
 
+/// The arguments of a call as they were passed, borrowed from the caller without conversion, to
+/// forward them with [`Value::call`] or inspect them one by one.
+///
+/// As a parameter of a `#[host_call]` function it takes the rest of the arguments, by its type
+/// whatever it is named, leaving `undefined` to the parameters after it. Forwarding passes the
+/// caller's values on as they are, so functions, proxies and other objects stay what they were
+/// and nothing is copied.
+#[derive(Clone, Copy)]
+pub struct RawArgs<'a> {
+    ctx: &'a js::Context,
+    argv: &'a [c::JSValue],
+}
+
+impl<'a> RawArgs<'a> {
+    /// Borrow `argv`, the arguments of a call in `ctx`.
+    pub fn new(ctx: &'a js::Context, argv: &'a [c::JSValue]) -> Self {
+        Self { ctx, argv }
+    }
+
+    pub fn len(&self) -> usize {
+        self.argv.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.argv.is_empty()
+    }
+
+    /// The argument at `index`, or `None` past the last one.
+    pub fn get(&self, index: usize) -> Option<Value> {
+        let raw = self.argv.get(index)?;
+        Some(Value::new_cloned(self.ctx, *raw))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Value> + 'a {
+        let ctx = self.ctx;
+        self.argv
+            .iter()
+            .map(move |raw| Value::new_cloned(ctx, *raw))
+    }
+
+    /// The raw values, borrowed.
+    pub fn as_raw(&self) -> &'a [c::JSValue] {
+        self.argv
+    }
+
+    fn check_context(&self, ctx: &js::Context) -> Result<()> {
+        if self.ctx.as_ptr() != ctx.as_ptr() {
+            anyhow::bail!(
+                "the arguments belong to another context, copy them with `Value::clone_into`"
+            );
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for RawArgs<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A parameter of a `#[host_call]` function, taken from the arguments left.
+#[doc(hidden)]
+pub trait FromHostArgs<'a>: Sized {
+    fn from_host_args(ctx: &'a js::Context, rest: &mut &'a [c::JSValue]) -> Result<Self>;
+}
+
+impl<'a, T: FromJsValue> FromHostArgs<'a> for T {
+    fn from_host_args(ctx: &'a js::Context, rest: &mut &'a [c::JSValue]) -> Result<Self> {
+        let value = match rest.split_first() {
+            Some((first, tail)) => {
+                *rest = tail;
+                Value::new_cloned(ctx, *first)
+            }
+            None => Value::undefined(),
+        };
+        T::from_js_value(value)
+    }
+}
+
+impl<'a> FromHostArgs<'a> for RawArgs<'a> {
+    fn from_host_args(ctx: &'a js::Context, rest: &mut &'a [c::JSValue]) -> Result<Self> {
+        Ok(RawArgs::new(ctx, core::mem::take(rest)))
+    }
+}
+
 pub trait FromJsContext {
     fn from_js_context(ctx: &js::Context) -> Result<Self>
     where
@@ -40,10 +127,51 @@ pub trait FromJsValue {
     fn from_js_value(js_value: Value) -> Result<Self>
     where
         Self: Sized;
+
+    /// Convert `js_value` found `depth` levels deep in the value being converted.
+    ///
+    /// Implemented by the containers and derived types, which convert their items one level
+    /// deeper. Other types ignore the depth.
+    fn from_js_value_depth(js_value: Value, depth: js::ConversionDepth) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let _ = depth;
+        Self::from_js_value(js_value)
+    }
+
+    /// Convert `js_value` into a `Vec<Self>` without going through each element, or return `None`
+    /// to fall back to element-wise conversion.
+    #[doc(hidden)]
+    fn vec_from_js_value(_js_value: &Value) -> Option<Result<Vec<Self>>>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 pub trait ToJsValue {
     fn to_js_value(&self, ctx: &js::Context) -> Result<Value>;
+
+    /// Convert the value, consuming it, so that owned buffers can be handed over to the engine
+    /// without a copy. Used for the values returned by host functions.
+    fn into_js_value(self, ctx: &js::Context) -> Result<Value>
+    where
+        Self: Sized,
+    {
+        self.to_js_value(ctx)
+    }
+
+    /// Convert `items` into a single JS value without going through each element, or return
+    /// `None` to fall back to a JS array.
+    #[doc(hidden)]
+    fn slice_to_js_value(_items: &[Self], _ctx: &js::Context) -> Option<Result<Value>>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 
 impl ToJsValue for &dyn ToJsValue {
@@ -69,4 +197,53 @@ pub trait ToArgs {
             raw_args,
         })
     }
+
+    /// The arguments as raw values borrowed from the caller, to pass them on without a copy, or
+    /// `None` to go through [`to_raw_args`](Self::to_raw_args).
+    #[doc(hidden)]
+    fn borrowed_raw_args(&self, _ctx: &js::Context) -> Option<Result<&[c::JSValue]>> {
+        None
+    }
+}
+
+impl ToArgs for RawArgs<'_> {
+    fn to_args(&self, ctx: &js::Context) -> Result<TinyVec<[Value; 8]>> {
+        self.check_context(ctx)?;
+        Ok(self.iter().collect())
+    }
+
+    fn borrowed_raw_args(&self, ctx: &js::Context) -> Option<Result<&[c::JSValue]>> {
+        Some(self.check_context(ctx).map(|()| self.argv))
+    }
+}
+
+impl ToArgs for [Value] {
+    fn to_args(&self, ctx: &js::Context) -> Result<TinyVec<[Value; 8]>> {
+        for arg in self {
+            arg.check_context(ctx)?;
+        }
+        Ok(self.iter().cloned().collect())
+    }
+}
+
+impl<const N: usize> ToArgs for [Value; N] {
+    fn to_args(&self, ctx: &js::Context) -> Result<TinyVec<[Value; 8]>> {
+        self[..].to_args(ctx)
+    }
+}
+
+impl ToArgs for Vec<Value> {
+    fn to_args(&self, ctx: &js::Context) -> Result<TinyVec<[Value; 8]>> {
+        self[..].to_args(ctx)
+    }
+}
+
+impl<T: ToArgs + ?Sized> ToArgs for &T {
+    fn to_args(&self, ctx: &js::Context) -> Result<TinyVec<[Value; 8]>> {
+        (**self).to_args(ctx)
+    }
+
+    fn borrowed_raw_args(&self, ctx: &js::Context) -> Option<Result<&[c::JSValue]>> {
+        (**self).borrowed_raw_args(ctx)
+    }
 }
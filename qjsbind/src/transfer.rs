@@ -0,0 +1,639 @@
+//! A compact binary encoding of values, to move them to another process.
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use anyhow::{anyhow, bail};
+
+use crate::{self as js, c, Result, TypedArrayKind, Value};
+
+const MAGIC: &[u8; 3] = b"QJT";
+/// Bumped on any change of the encoding. Older versions are rejected.
+const VERSION: u8 = 1;
+const MAX_DEPTH: usize = 128;
+
+mod tag {
+    pub const UNDEFINED: u8 = 0;
+    pub const NULL: u8 = 1;
+    pub const FALSE: u8 = 2;
+    pub const TRUE: u8 = 3;
+    pub const NUMBER: u8 = 4;
+    pub const STRING: u8 = 5;
+    pub const BIGINT: u8 = 6;
+    pub const ARRAY: u8 = 7;
+    pub const OBJECT: u8 = 8;
+    pub const TYPED_ARRAY: u8 = 9;
+    pub const ARRAY_BUFFER: u8 = 10;
+    pub const DATE: u8 = 11;
+    pub const MAP: u8 = 12;
+    pub const SET: u8 = 13;
+    /// An object encoded before, by its index in the order objects were first encoded.
+    pub const BACKREF: u8 = 14;
+}
+
+impl Value {
+    /// Encode the value to bytes that [`Context::deserialize_transferable`] turns back into an
+    /// equal value, in any runtime of any process.
+    ///
+    /// Supported are primitives, strings, BigInts, arrays, plain objects, typed arrays,
+    /// ArrayBuffers, Dates, Maps and Sets, nested in any way. An object reached twice, through a
+    /// cycle or not, is encoded once and shared the same way after decoding. Functions, native
+    /// objects and other objects fail the encoding with the path to them.
+    ///
+    /// Numbers keep `-0`, all NaNs decode to the same NaN. Each lone surrogate of a string is
+    /// replaced with U+FFFD. Array holes decode as `undefined`, typed arrays get a buffer of their
+    /// own and objects with a `null` prototype decode as ordinary objects.
+    ///
+    /// [`Context::deserialize_transferable`]: js::Context::deserialize_transferable
+    pub fn serialize_transferable(&self) -> Result<Vec<u8>> {
+        let mut writer = Writer {
+            out: MAGIC.to_vec(),
+            seen: BTreeMap::new(),
+        };
+        writer.out.push(VERSION);
+        writer.write_value(self, 0, &mut String::new())?;
+        Ok(writer.out)
+    }
+}
+
+impl js::Context {
+    /// Decode bytes made by [`Value::serialize_transferable`]. Fails on malformed input, however
+    /// it is malformed.
+    pub fn deserialize_transferable(&self, bytes: &[u8]) -> Result<Value> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            bail!("deserialize_transferable: not a transferable value");
+        };
+        let mut reader = Reader {
+            ctx: self,
+            input: rest,
+            pos: MAGIC.len(),
+            objects: Vec::new(),
+        };
+        let version = reader.byte()?;
+        if version != VERSION {
+            bail!("deserialize_transferable: unsupported version {version}");
+        }
+        let value = reader.read_value(0)?;
+        if !reader.input.is_empty() {
+            return Err(reader.error("trailing bytes"));
+        }
+        Ok(value)
+    }
+}
+
+struct Writer {
+    out: Vec<u8>,
+    /// The index of the objects encoded so far, by their address, with the objects themselves so
+    /// that no other object takes their address while encoding, like the fresh ones of getters.
+    seen: BTreeMap<usize, (usize, Value)>,
+}
+
+impl Writer {
+    fn write_len(&mut self, len: usize) {
+        let mut n = len as u64;
+        while n >= 0x80 {
+            self.out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.out.push(n as u8);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_len(bytes.len());
+        self.out.extend_from_slice(bytes);
+    }
+
+    fn write_f64(&mut self, n: f64) {
+        let n = if n.is_nan() { f64::NAN } else { n };
+        self.out.extend_from_slice(&n.to_bits().to_le_bytes());
+    }
+
+    fn write_value(&mut self, value: &Value, depth: usize, path: &mut String) -> Result<()> {
+        if depth > MAX_DEPTH {
+            bail!("serialize_transferable: value too deep at `{path}`");
+        }
+        if value.is_undefined() {
+            self.out.push(tag::UNDEFINED);
+        } else if value.is_null() {
+            self.out.push(tag::NULL);
+        } else if value.is_bool() {
+            let byte = if value.decode_bool()? {
+                tag::TRUE
+            } else {
+                tag::FALSE
+            };
+            self.out.push(byte);
+        } else if value.is_number() {
+            self.out.push(tag::NUMBER);
+            self.write_f64(value.decode_f64()?);
+        } else if value.is_string() {
+            self.out.push(tag::STRING);
+            self.write_bytes(well_formed(value.decode_string_bytes()?).as_bytes());
+        } else if value.is_big_int() {
+            self.out.push(tag::BIGINT);
+            self.write_bytes(value.to_string().as_bytes());
+        } else if !value.is_object() || value.is_function() {
+            bail!(
+                "serialize_transferable: can not transfer {} at `{path}`",
+                value.get_name()
+            );
+        } else {
+            let key = unsafe { c::JS_GetPtr(*value.raw_value()) } as usize;
+            if let Some(&(index, _)) = self.seen.get(&key) {
+                self.out.push(tag::BACKREF);
+                self.write_len(index);
+                return Ok(());
+            }
+            let index = self.seen.len();
+            self.seen.insert(key, (index, value.clone()));
+            self.write_object(value, depth, path)?;
+        }
+        Ok(())
+    }
+
+    fn write_object(&mut self, value: &Value, depth: usize, path: &mut String) -> Result<()> {
+        let mut nested = |writer: &mut Self, value: &Value, segment: &str| {
+            let base = path.len();
+            path.push_str(segment);
+            writer.write_value(value, depth + 1, path)?;
+            path.truncate(base);
+            Ok::<_, js::Error>(())
+        };
+        if let Some(kind) = value.is_typed_array() {
+            self.out.push(tag::TYPED_ARRAY);
+            self.out.push(kind_tag(kind));
+            let mut bytes = typed_array_bytes(value)?;
+            to_little_endian(&mut bytes, kind);
+            self.write_bytes(&bytes);
+        } else if value.is_array_buffer() {
+            self.out.push(tag::ARRAY_BUFFER);
+            self.write_bytes(&value.decode_bytes()?);
+        } else if value.is_date() {
+            // Read from the Date itself, not through `getTime` scripts can patch.
+            let ctx = value.context()?;
+            let mut time = 0.0;
+            let r = unsafe {
+                c::JS_GetIntrinsicDateTime(ctx.as_ptr(), *value.raw_value(), &mut time)
+            };
+            if r < 0 {
+                return Err(ctx.get_exception_error());
+            }
+            self.out.push(tag::DATE);
+            self.write_f64(time);
+        } else if value.is_map() || value.is_set() {
+            let map = value.is_map();
+            // Read from the collection itself, not through its iterators scripts can patch.
+            let ctx = value.context()?;
+            let entries = Value::new_moved(ctx, unsafe {
+                c::JS_MapOrSetEntries(ctx.as_ptr(), *value.raw_value(), !map as _)
+            });
+            if entries.is_exception() {
+                return Err(ctx.get_exception_error());
+            }
+            let width = if map { 2 } else { 1 };
+            let len = entries.length()? / width;
+            self.out.push(if map { tag::MAP } else { tag::SET });
+            self.write_len(len);
+            for i in 0..len {
+                let segment = format!("[{i}]");
+                for j in 0..width {
+                    nested(self, &entries.index(i * width + j)?, &segment)?;
+                }
+            }
+        } else if value.is_array() {
+            self.out.push(tag::ARRAY);
+            let len = value.length()?;
+            self.write_len(len);
+            for i in 0..len {
+                nested(self, &value.index(i)?, &format!("[{i}]"))?;
+            }
+        } else if value.is_plain_object() {
+            self.out.push(tag::OBJECT);
+            let entries = value.entries()?.collect::<Result<Vec<_>>>()?;
+            self.write_len(entries.len());
+            for (key, field) in entries {
+                let key = well_formed(key.decode_string_bytes()?);
+                self.write_bytes(key.as_bytes());
+                nested(self, &field, &format!(".{key}"))?;
+            }
+        } else {
+            bail!(
+                "serialize_transferable: can not transfer {} at `{path}`",
+                value.get_name()
+            );
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    ctx: &'a js::Context,
+    input: &'a [u8],
+    /// The offset of `input` in the whole encoding, for error messages.
+    pos: usize,
+    /// The objects decoded so far, in the order they were encoded.
+    objects: Vec<Value>,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, what: &str) -> js::Error {
+        anyhow!("deserialize_transferable: {what} at byte {}", self.pos)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.input.len() {
+            return Err(self.error("unexpected end of input"));
+        }
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// A length, checked to be at most the number of bytes left as every item takes one or more.
+    fn len(&mut self) -> Result<usize> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return match usize::try_from(n) {
+                    Ok(len) if len <= self.input.len() => Ok(len),
+                    _ => Err(self.error("length out of range")),
+                };
+            }
+        }
+        Err(self.error("length too long"))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<&'a str> {
+        let bytes = self.bytes()?;
+        core::str::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let bytes = self.take(8)?;
+        let mut le = [0; 8];
+        le.copy_from_slice(bytes);
+        Ok(f64::from_le_bytes(le))
+    }
+
+    /// A new object of the intrinsics, failing with the pending exception.
+    fn intrinsic(&self, raw: c::JSValue) -> Result<Value> {
+        let value = Value::new_moved(self.ctx, raw);
+        if value.is_exception() {
+            return Err(self.ctx.get_exception_error());
+        }
+        Ok(value)
+    }
+
+    /// Add an entry to a Map, or an item to a Set, with the intrinsic `set` or `add`.
+    fn add_entry(&self, collection: &Value, is_set: bool, key: &Value, item: &Value) -> Result<()> {
+        let r = unsafe {
+            c::JS_MapOrSetAdd(
+                self.ctx.as_ptr(),
+                *collection.raw_value(),
+                is_set as _,
+                *key.raw_value(),
+                *item.raw_value(),
+            )
+        };
+        if r < 0 {
+            return Err(self.ctx.get_exception_error());
+        }
+        Ok(())
+    }
+
+    /// Number the object before its content is decoded, for the back references it holds.
+    fn register(&mut self, object: &Value) {
+        self.objects.push(object.clone());
+    }
+
+    fn read_value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("value too deep"));
+        }
+        let ctx = self.ctx;
+        let value = match self.byte()? {
+            tag::UNDEFINED => Value::undefined(),
+            tag::NULL => Value::null(),
+            tag::FALSE => Value::from_bool(ctx, false),
+            tag::TRUE => Value::from_bool(ctx, true),
+            tag::NUMBER => Value::from_f64(ctx, self.f64()?),
+            tag::STRING => Value::from_str(ctx, self.string()?),
+            tag::BIGINT => {
+                let digits = self.string()?;
+                let valid = digits.strip_prefix('-').unwrap_or(digits);
+                if valid.is_empty() || !valid.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(self.error("invalid BigInt"));
+                }
+                Value::bigint_from_str(ctx, digits)?
+            }
+            tag::BACKREF => {
+                let index = self.len()?;
+                match self.objects.get(index) {
+                    Some(object) => object.clone(),
+                    None => return Err(self.error("dangling back reference")),
+                }
+            }
+            tag::TYPED_ARRAY => {
+                let kind = TypedArrayKind::ALL.get(usize::from(self.byte()?)).copied();
+                let Some(kind) = kind else {
+                    return Err(self.error("unknown typed array kind"));
+                };
+                let mut bytes = self.bytes()?.to_vec();
+                if bytes.len() % kind.element_size() != 0 {
+                    return Err(self.error("typed array of a partial element"));
+                }
+                to_little_endian(&mut bytes, kind);
+                let buffer = new_array_buffer(ctx, &bytes)?;
+                let mut argv = [*buffer.raw_value()];
+                let array = self.intrinsic(unsafe {
+                    c::JS_NewTypedArray(ctx.as_ptr(), 1, argv.as_mut_ptr(), kind.typed_array_type())
+                })?;
+                self.register(&array);
+                array
+            }
+            tag::ARRAY_BUFFER => {
+                let buffer = new_array_buffer(ctx, self.bytes()?)?;
+                self.register(&buffer);
+                buffer
+            }
+            tag::DATE => {
+                let time = self.f64()?;
+                let date = self.intrinsic(unsafe { c::JS_NewIntrinsicDate(ctx.as_ptr(), time) })?;
+                self.register(&date);
+                date
+            }
+            tag::MAP => {
+                let map = self.intrinsic(unsafe { c::JS_NewIntrinsicMapOrSet(ctx.as_ptr(), 0) })?;
+                self.register(&map);
+                for _ in 0..self.len()? {
+                    let key = self.read_value(depth + 1)?;
+                    let item = self.read_value(depth + 1)?;
+                    self.add_entry(&map, false, &key, &item)?;
+                }
+                map
+            }
+            tag::SET => {
+                let set = self.intrinsic(unsafe { c::JS_NewIntrinsicMapOrSet(ctx.as_ptr(), 1) })?;
+                self.register(&set);
+                for _ in 0..self.len()? {
+                    let item = self.read_value(depth + 1)?;
+                    self.add_entry(&set, true, &item, &Value::undefined())?;
+                }
+                set
+            }
+            tag::ARRAY => {
+                let array = ctx.new_array();
+                self.register(&array);
+                for _ in 0..self.len()? {
+                    array.array_push(&self.read_value(depth + 1)?)?;
+                }
+                array
+            }
+            tag::OBJECT => {
+                let object = ctx.new_object("");
+                self.register(&object);
+                for _ in 0..self.len()? {
+                    let key = self.string()?;
+                    let field = self.read_value(depth + 1)?;
+                    object.define_property_value(key, field)?;
+                }
+                object
+            }
+            tag => return Err(self.error(&format!("unknown tag {tag}"))),
+        };
+        Ok(value)
+    }
+}
+
+/// The byte of `kind` in the encoding, its position in `TypedArrayKind::ALL`.
+fn kind_tag(kind: TypedArrayKind) -> u8 {
+    TypedArrayKind::ALL
+        .iter()
+        .position(|k| *k == kind)
+        .expect("every kind is in ALL") as u8
+}
+
+/// The bytes of the elements of a typed array, in native byte order.
+pub(crate) fn typed_array_bytes(value: &Value) -> Result<Vec<u8>> {
+    let ctx = value.context()?;
+    let (mut offset, mut len, mut element_size) = (0, 0, 0);
+    let buffer = Value::new_moved(ctx, unsafe {
+        c::JS_GetTypedArrayBuffer(
+            ctx.as_ptr(),
+            *value.raw_value(),
+            &mut offset,
+            &mut len,
+            &mut element_size,
+        )
+    });
+    if buffer.is_exception() {
+        return Err(ctx.get_exception_error());
+    }
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let mut buffer_len = 0;
+    let base = unsafe { c::JS_GetArrayBuffer(ctx.as_ptr(), &mut buffer_len, *buffer.raw_value()) };
+    if base.is_null() || offset + len > buffer_len {
+        bail!("detached typed array");
+    }
+    Ok(unsafe { core::slice::from_raw_parts(base.add(offset) as *const u8, len) }.to_vec())
+}
+
+fn new_array_buffer(ctx: &js::Context, bytes: &[u8]) -> Result<Value> {
+    let buffer = Value::new_moved(ctx, unsafe {
+        c::JS_NewArrayBufferCopy(ctx.as_ptr(), bytes.as_ptr() as _, bytes.len() as _)
+    });
+    if buffer.is_exception() {
+        return Err(ctx.get_exception_error());
+    }
+    Ok(buffer)
+}
+
+/// Swap the elements between native and little endian byte order, a no-op on most targets.
+pub(crate) fn to_little_endian(bytes: &mut [u8], kind: TypedArrayKind) {
+    if cfg!(target_endian = "big") {
+        for element in bytes.chunks_mut(kind.element_size()) {
+            element.reverse();
+        }
+    }
+}
+
+/// The string with each lone surrogate, which QuickJS encodes as the three bytes of its code
+/// point, replaced with U+FFFD.
+fn well_formed(bytes: Vec<u8>) -> String {
+    let bytes = match String::from_utf8(bytes) {
+        Ok(s) => return s,
+        Err(err) => err.into_bytes(),
+    };
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0xed && bytes.get(i + 1).is_some_and(|b| *b >= 0xa0) {
+            out.extend_from_slice("\u{fffd}".as_bytes());
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+
+    #[test]
+    fn tricky_values_round_trip_between_runtimes() {
+        let source = js::Runtime::new(&Default::default());
+        let target = js::Runtime::new(&Default::default());
+        let (a, b) = (source.new_context(), target.new_context());
+        let value = a
+            .eval(&Code::Source(
+                "const v = {
+                    numbers: [NaN, -0, 0, Infinity, -Infinity, 1.5, 2 ** 53],
+                    strings: ['', 'héllo 😀', 'lone \\uD800!'],
+                    big: [-12345678901234567890n, 0n],
+                    bytes: new Uint8Array([1, 2, 3]),
+                    floats: new Float64Array([-0, 0.1]),
+                    wide: new BigInt64Array([-1n]),
+                    buffer: new ArrayBuffer(2),
+                    when: new Date(86400000),
+                    map: new Map([[1, 'one'], [{ k: 1 }, null]]),
+                    set: new Set(['x', undefined]),
+                };
+                v.self = v;
+                v.map.set('v', v);
+                v",
+            ))
+            .unwrap();
+        let bytes = value.serialize_transferable().unwrap();
+        let copy = b.deserialize_transferable(&bytes).unwrap();
+        b.get_global_object().set_property("v", &copy).unwrap();
+        let check = b
+            .eval(&Code::Source(
+                "const n = v.numbers;
+                [
+                    Number.isNaN(n[0]), Object.is(n[1], -0), Object.is(n[2], 0), n[5], n[6],
+                    v.strings[1], v.strings[2] === 'lone \\uFFFD!',
+                    v.big[0], v.bytes instanceof Uint8Array && v.bytes.join('-'),
+                    Object.is(v.floats[0], -0), v.floats[1], v.wide[0],
+                    v.buffer.byteLength, v.when.getTime(), v.map.get(1), [...v.map.keys()][1].k,
+                    v.map.get('v') === v, v.self === v, v.set.has(undefined),
+                ].join()",
+            ))
+            .unwrap();
+        assert_eq!(
+            check.to_string(),
+            "true,true,true,1.5,9007199254740992,héllo 😀,true,-12345678901234567890,1-2-3,\
+             true,0.1,-1,2,86400000,one,1,true,true,true"
+        );
+        assert_eq!(copy.serialize_transferable().unwrap(), bytes);
+
+        let err = a
+            .eval(&Code::Source("({ list: [1, { f() {} }] })"))
+            .unwrap()
+            .serialize_transferable()
+            .unwrap_err();
+        assert!(err.to_string().contains("`.list[1].f`"), "{err}");
+
+        for malformed in [
+            &b""[..],
+            b"QJT",
+            b"QJT\x02\x00",
+            b"QJT\x01\x07\x05",
+            b"QJT\x01\x0e\x00",
+        ] {
+            assert!(b.deserialize_transferable(malformed).is_err());
+        }
+        for end in 0..bytes.len() {
+            assert!(b.deserialize_transferable(&bytes[..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn fresh_objects_and_patched_globals_are_not_mistaken() {
+        let runtime = js::Runtime::new(&Default::default());
+        let (a, b) = (runtime.new_context(), runtime.new_context());
+        let value = a
+            .eval(&Code::Source(
+                "const fresh = [0, 1];
+                for (const i of fresh) {
+                    Object.defineProperty(fresh, i, { get: () => ({ n: i }) });
+                }
+                ({
+                    fresh,
+                    map: new Map([[1, 2]]),
+                    set: new Set([3]),
+                    when: new Date(5),
+                    bytes: new Uint8Array([6]),
+                })",
+            ))
+            .unwrap();
+        let bytes = value.serialize_transferable().unwrap();
+        b.eval(&Code::Source(
+            "Map.prototype.set = Set.prototype.add = null;
+            Map = Set = Date = Uint8Array = function () { return {}; };",
+        ))
+        .unwrap();
+        let copy = b.deserialize_transferable(&bytes).unwrap();
+        b.get_global_object().set_property("v", &copy).unwrap();
+        let check = b
+            .eval(&Code::Source(
+                "const tag = value => Object.prototype.toString.call(value);
+                [
+                    v.fresh[0] !== v.fresh[1], v.fresh[1].n,
+                    tag(v.map), v.map.size, tag(v.set), v.set.size,
+                    tag(v.when), tag(v.bytes), v.bytes.length,
+                ].join()",
+            ))
+            .unwrap();
+        assert_eq!(
+            check.to_string(),
+            "true,1,[object Map],1,[object Set],1,[object Date],[object Uint8Array],1"
+        );
+    }
+
+    #[test]
+    fn patched_prototypes_do_not_change_what_is_written() {
+        let runtime = js::Runtime::new(&Default::default());
+        let (a, b) = (runtime.new_context(), runtime.new_context());
+        let value = a
+            .eval(&Code::Source(
+                "const v = { when: new Date(5), map: new Map([[1, 2]]), set: new Set([3]) };
+                Date.prototype.getTime = () => 6;
+                const forged = function* () { yield [7, 8]; };
+                Map.prototype.entries = Map.prototype[Symbol.iterator] = forged;
+                Set.prototype.values = Set.prototype.entries = Set.prototype[Symbol.iterator] =
+                    forged;
+                Object.getPrototypeOf(new Map().entries()).next = () => ({ done: true });
+                v",
+            ))
+            .unwrap();
+        let bytes = value.serialize_transferable().unwrap();
+        let copy = b.deserialize_transferable(&bytes).unwrap();
+        b.get_global_object().set_property("v", &copy).unwrap();
+        let check = b
+            .eval(&Code::Source(
+                "[v.when.getTime(), [...v.map].join(';'), [...v.set].join(';')].join()",
+            ))
+            .unwrap();
+        assert_eq!(check.to_string(), "5,1,2,3");
+    }
+}
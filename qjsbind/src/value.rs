@@ -7,16 +7,24 @@ use scopeguard::defer;
 
 use crate::{
     self as js,
-    error::{expect_js_value, JsResultExt},
+    error::{expect_err, JsResultExt, NonFiniteNumber},
     opaque_value::{is_opaque_object_of, opaque_object_get_data_mut, Ref, RefMut},
+    own_keys::{own_enumerable_keys, Atom},
+    small_str::SmallStr,
 };
 use crate::{
-    opaque_value::{new_opaque_object, opaque_object_get_data, opaque_object_take_data},
-    FromJsValue,
+    opaque_value::{
+        new_opaque_object, new_opaque_object_cached, opaque_object_get_data,
+        opaque_object_take_data,
+    },
+    FromJsValue, ToArgs,
 };
 
 use super::{c, Error, Result};
 
+/// Byte buffers smaller than this are copied rather than transferred to the engine.
+pub const OWNED_BYTES_THRESHOLD: usize = 4096;
+
 #[repr(transparent)]
 pub struct RawValue(pub c::JSValue);
 impl Default for RawValue {
@@ -45,19 +53,32 @@ impl Default for Value {
     }
 }
 
-pub struct Iter(Value);
+pub struct Iter(IterSource);
+
+enum IterSource {
+    Js(Value),
+    Keys(OwnProperties),
+    Values(OwnProperties),
+}
 
 impl Iterator for Iter {
     type Item = Result<Value>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().transpose()
+        match &mut self.0 {
+            IterSource::Js(iter) => iter.next().transpose(),
+            IterSource::Keys(props) => props.next_key().map(|(key, _)| Ok(key)),
+            IterSource::Values(props) => {
+                let (_, atom) = props.next_key()?;
+                Some(props.obj.get_property_atom(atom.raw()))
+            }
+        }
     }
 }
 
 impl From<Value> for Iter {
     fn from(value: Value) -> Self {
-        Self(value)
+        Self(IterSource::Js(value))
     }
 }
 
@@ -75,13 +96,61 @@ impl core::fmt::Display for Value {
     }
 }
 
+enum CollectionKind {
+    MapOrSet,
+    Array,
+    Object,
+}
+
+/// The own enumerable string-keyed properties of an object, or the elements of an array,
+/// listed with `JS_GetOwnPropertyNames` up front and read as the iteration reaches them.
+struct OwnProperties {
+    obj: Value,
+    /// The keys to yield, numbers for the indices of an array, with their atoms.
+    keys: alloc::vec::IntoIter<(Value, Atom)>,
+}
+
+impl OwnProperties {
+    fn new(obj: &Value, kind: CollectionKind) -> Result<Self> {
+        let ctx = obj.context()?;
+        let keys = own_enumerable_keys(ctx, obj)?.into_iter();
+        let keys: Vec<_> = match kind {
+            // Integer keys come first, in ascending order, followed by the other properties.
+            CollectionKind::Array => keys
+                .map_while(|key| {
+                    let index = key.name.as_deref()?.parse().ok()?;
+                    Some((Value::from_usize(ctx, index), key.atom))
+                })
+                .collect(),
+            _ => keys.map(|key| (key.atom.to_value(), key.atom)).collect(),
+        };
+        Ok(Self {
+            obj: obj.clone(),
+            keys: keys.into_iter(),
+        })
+    }
+
+    fn next_key(&mut self) -> Option<(Value, Atom)> {
+        self.keys.next()
+    }
+}
+
 pub struct PairIter {
-    inner: Value,
+    inner: PairSource,
     len: Option<usize>,
 }
+
+enum PairSource {
+    Js(Value),
+    Own(OwnProperties),
+}
+
 impl PairIter {
     pub fn new(inner: Value, len: Option<usize>) -> Self {
-        Self { inner, len }
+        Self {
+            inner: PairSource::Js(inner),
+            len,
+        }
     }
     pub fn length(&self) -> Option<usize> {
         self.len
@@ -91,18 +160,24 @@ impl Iterator for PairIter {
     type Item = Result<(Value, Value)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next = opt_try!(self.inner.next())?;
-        let key = opt_try!(next.get_property("0"));
-        let value = opt_try!(next.get_property("1"));
-        Some(Ok((key, value)))
+        match &mut self.inner {
+            PairSource::Js(iter) => {
+                let next = opt_try!(iter.next())?;
+                let key = opt_try!(next.get_property("0"));
+                let value = opt_try!(next.get_property("1"));
+                Some(Ok((key, value)))
+            }
+            PairSource::Own(props) => {
+                let (key, atom) = props.next_key()?;
+                let value = opt_try!(props.obj.get_property_atom(atom.raw()));
+                Some(Ok((key, value)))
+            }
+        }
     }
 }
 impl From<Value> for PairIter {
     fn from(value: Value) -> Self {
-        Self {
-            inner: value,
-            len: None,
-        }
+        Self::new(value, None)
     }
 }
 
@@ -144,6 +219,14 @@ impl Value {
         new_opaque_object(ctx, name, value, None)
     }
 
+    pub fn new_opaque_object_cached<T: 'static>(
+        ctx: &js::Context,
+        name: Option<&str>,
+        value: alloc::rc::Rc<T>,
+    ) -> Result<Self> {
+        new_opaque_object_cached(ctx, name, value)
+    }
+
     pub fn opaque_object_data<T: 'static>(&self) -> Ref<'_, T> {
         opaque_object_get_data(self)
     }
@@ -188,7 +271,7 @@ impl Value {
     }
 
     pub fn index(&self, ind: usize) -> Result<Self> {
-        self.get_property(&ind.to_string())
+        self.get_property(SmallStr::format(format_args!("{ind}")).as_str())
     }
 
     pub fn get_property_atom(&self, prop: c::JSAtom) -> Result<Self> {
@@ -222,7 +305,7 @@ impl Value {
     pub fn next(&self) -> Result<Option<Self>> {
         let next_fn = self.get_property("next")?;
         if next_fn.is_null() {
-            Err(expect_js_value(self, "iterator"))
+            Err(expect_err("iterator", self))
         } else {
             let next_val = next_fn.call(self, &[])?;
             let done = next_val.get_property("done")?;
@@ -235,28 +318,77 @@ impl Value {
         }
     }
 
-    pub fn call_method(&self, name: &str, args: &[Value]) -> Result<Self> {
+    /// Call the method `name` of the value with the value as `this`, see [`call`](Self::call).
+    pub fn call_method(&self, name: &str, args: impl ToArgs) -> Result<Self> {
         let method = self.get_property(name)?;
         method.call(self, args)
     }
 
-    pub fn call_method_if_exists(&self, name: &str, args: &[Value]) -> Result<Self> {
+    pub fn call_method_if_exists(&self, name: &str, args: impl ToArgs) -> Result<Self> {
         let method = self.get_property(name)?;
         if !method.is_function() {
-            return Err(expect_js_value(&method, "function"));
+            return Err(expect_err("function", &method));
         }
         method.call(self, args)
     }
 
-    pub fn call(&self, this: &Value, args: &[Value]) -> Result<Self> {
+    /// Call the function with `this` and `args`, either values as in `&[Value]` or Rust values
+    /// converted with `ToJsValue` as in `(2, "two")`. A throw fails the call with its message.
+    pub fn call(&self, this: &Value, args: impl ToArgs) -> Result<Self> {
+        let ctx = self.context()?;
+        if log::log_enabled!(target: "js::callback", log::Level::Trace) {
+            match self.source_position() {
+                Some((filename, line)) => log::trace!(
+                    target: "js::callback",
+                    "call {} defined at {filename}:{line}",
+                    self.function_label()
+                ),
+                None => log::trace!(target: "js::callback", "call {}", self.function_label()),
+            }
+        }
+        let value = match args.borrowed_raw_args(ctx) {
+            Some(argv) => {
+                let argv = argv?;
+                // JS_Call only reads the arguments.
+                unsafe {
+                    c::JS_Call(
+                        ctx.as_ptr(),
+                        *self.raw_value(),
+                        *this.raw_value(),
+                        argv.len() as _,
+                        argv.as_ptr() as *mut _,
+                    )
+                }
+            }
+            None => {
+                let mut args = args.to_raw_args(ctx)?;
+                unsafe {
+                    c::JS_Call(
+                        ctx.as_ptr(),
+                        *self.raw_value(),
+                        *this.raw_value(),
+                        args.len() as _,
+                        args.as_mut_ptr() as _,
+                    )
+                }
+            }
+        };
+        let ret = Self::new_moved(ctx, value);
+        if ret.is_exception() {
+            Err(ctx.get_exception_error())
+        } else {
+            Ok(ret)
+        }
+    }
+
+    pub fn construct(&self, args: &[Value]) -> Result<Self> {
         let ctx = self.context()?;
         let mut args: tinyvec::TinyVec<[_; 16]> =
             args.iter().map(|v| RawValue(*v.raw_value())).collect();
         let value = unsafe {
-            c::JS_Call(
+            c::JS_CallConstructor(
                 ctx.as_ptr(),
                 *self.raw_value(),
-                *this.raw_value(),
                 args.len() as _,
                 args.as_mut_ptr() as _,
             )
@@ -269,26 +401,60 @@ impl Value {
         }
     }
 
+    /// Iterate the values of a collection, see [`Value::entries`].
     pub fn values(&self) -> Result<Iter> {
-        self.call_method_if_exists("values", &[]).map(Into::into)
+        Ok(Iter(match self.collection_kind()? {
+            CollectionKind::MapOrSet => IterSource::Js(self.call_method("values", &[])?),
+            kind => IterSource::Values(OwnProperties::new(self, kind)?),
+        }))
     }
 
+    /// Iterate the keys of a collection, see [`Value::entries`].
     pub fn keys(&self) -> Result<Iter> {
-        self.call_method_if_exists("keys", &[]).map(Into::into)
+        Ok(Iter(match self.collection_kind()? {
+            CollectionKind::MapOrSet => IterSource::Js(self.call_method("keys", &[])?),
+            kind => IterSource::Keys(OwnProperties::new(self, kind)?),
+        }))
     }
 
+    /// Iterate the key/value pairs of a collection:
+    ///
+    /// | value        | keys                                | values               |
+    /// |--------------|-------------------------------------|----------------------|
+    /// | Map          | map keys                            | map values           |
+    /// | Set          | set values                          | set values           |
+    /// | array        | indices as numbers, skipping holes  | elements             |
+    /// | other object | own enumerable string property keys | property values      |
+    ///
+    /// The keys of arrays and other objects are listed when the iteration starts and their
+    /// values read as it reaches them. Any other value is an error.
     pub fn entries(&self) -> Result<PairIter> {
-        if self.is_null_or_undefined() {
-            return Err(expect_js_value(self, "Object"));
+        match self.collection_kind()? {
+            CollectionKind::MapOrSet => {
+                let len = self.get_property_t("size").ok();
+                let iter = self.call_method("entries", &[])?;
+                Ok(PairIter::new(iter, len))
+            }
+            kind => {
+                let props = OwnProperties::new(self, kind)?;
+                Ok(PairIter {
+                    len: Some(props.keys.len()),
+                    inner: PairSource::Own(props),
+                })
+            }
+        }
+    }
+
+    fn collection_kind(&self) -> Result<CollectionKind> {
+        if self.is_map() || self.is_set() {
+            Ok(CollectionKind::MapOrSet)
+        } else if self.is_array() {
+            Ok(CollectionKind::Array)
+        } else if self.is_object() {
+            Ok(CollectionKind::Object)
+        } else {
+            Err(expect_err("object, array, Map or Set", self))
         }
-        #[allow(non_snake_case)]
-        let Object = get_global(self.context()?).get_property("Object")?;
-        let entries_fn = Object.get_property("entries")?;
-        let null = Value::null();
-        let arr = entries_fn.call(&null, &[self.clone()])?;
-        let len = arr.length().ok();
-        let iter = arr.call_method_if_exists("values", &[])?;
-        Ok(PairIter::new(iter, len))
     }
 
     fn to_string_utf8(&self) -> Option<Utf8Repr> {
@@ -396,6 +562,189 @@ impl Value {
     pub fn is_array_buffer(&self) -> bool {
         unsafe { c::JS_IsTypeOf(*self.raw_value(), c::JS_CLASS_ARRAY_BUFFER as _) != 0 }
     }
+    /// The element type if the value is a typed array of any kind.
+    pub fn is_typed_array(&self) -> Option<TypedArrayKind> {
+        TypedArrayKind::ALL
+            .into_iter()
+            .find(|kind| unsafe { c::JS_IsTypeOf(*self.raw_value(), kind.class_id()) != 0 })
+    }
+    pub fn is_date(&self) -> bool {
+        unsafe { c::JS_IsTypeOf(*self.raw_value(), c::JS_CLASS_DATE as _) != 0 }
+    }
+    pub fn is_reg_exp(&self) -> bool {
+        unsafe { c::JS_IsTypeOf(*self.raw_value(), c::JS_CLASS_REGEXP as _) != 0 }
+    }
+    pub fn is_map(&self) -> bool {
+        unsafe { c::JS_IsTypeOf(*self.raw_value(), c::JS_CLASS_MAP as _) != 0 }
+    }
+    pub fn is_set(&self) -> bool {
+        unsafe { c::JS_IsTypeOf(*self.raw_value(), c::JS_CLASS_SET as _) != 0 }
+    }
+    /// Whether the value is an ordinary object whose prototype is `Object.prototype` or `null`.
+    ///
+    /// Arrays, functions, typed arrays, proxies and the other exotic objects are told apart by
+    /// their class rather than by `instanceof`, so objects created in another context count as
+    /// plain as well.
+    pub fn is_plain_object(&self) -> bool {
+        if unsafe { c::JS_IsTypeOf(*self.raw_value(), c::JS_CLASS_OBJECT as _) } == 0 {
+            return false;
+        }
+        let Ok(proto) = self.get_prototype() else {
+            return false;
+        };
+        if proto.is_null() {
+            return true;
+        }
+        if let Ok(object_proto) = self.context().and_then(|ctx| ctx.object_prototype()) {
+            if proto.ptr_eq(&object_proto) {
+                return true;
+            }
+        }
+        proto.is_object_prototype_of_any_realm()
+    }
+
+    /// Whether the value is the intrinsic `Object.prototype` of one of the contexts of the
+    /// runtime, by identity.
+    fn is_object_prototype_of_any_realm(&self) -> bool {
+        let Ok(ctx) = self.context() else {
+            return false;
+        };
+        let rt = unsafe { c::JS_GetRuntime(ctx.as_ptr()) };
+        let address = unsafe { c::JS_GetPtr(*self.raw_value()) } as usize;
+        crate::engine::runtime_state_of::<crate::engine::ObjectPrototypes>(rt)
+            .is_some_and(|prototypes| prototypes.0.borrow().contains(&address))
+    }
+}
+
+/// Metadata of function values, read without running script: getters defined by scripts are
+/// not called and the kind comes from the class of the function.
+impl Value {
+    /// The `name` of a function, empty for an anonymous function and `bound f` for a bound one.
+    /// Also empty when the name is not a data property.
+    pub fn function_name(&self) -> Result<String> {
+        if !self.is_function() {
+            return Err(expect_err("function", self));
+        }
+        match self.function_property(c::JS_ATOM_name) {
+            Some(name) if name.is_string() => name.decode_string(),
+            _ => Ok(String::new()),
+        }
+    }
+
+    /// The number of parameters a function declares, its `length`.
+    pub fn function_length(&self) -> Result<u32> {
+        if !self.is_function() {
+            return Err(expect_err("function", self));
+        }
+        self.function_property(c::JS_ATOM_length)
+            .context("function length is not a data property")?
+            .decode_u32()
+    }
+
+    /// Whether the value is an async function or async generator function. Bound functions are
+    /// not, whatever they are bound to.
+    pub fn is_async(&self) -> bool {
+        matches!(
+            self.function_kind(),
+            Some("AsyncFunction" | "AsyncGeneratorFunction")
+        )
+    }
+
+    pub fn is_generator(&self) -> bool {
+        matches!(
+            self.function_kind(),
+            Some("GeneratorFunction" | "AsyncGeneratorFunction")
+        )
+    }
+
+    /// The filename and the 1-based line a function is defined at.
+    ///
+    /// `None` for native and bound functions, for bytecode compiled without debug info, and when
+    /// a script replaced the native `fileName` or `lineNumber` getters.
+    pub fn source_position(&self) -> Option<(String, u32)> {
+        if !self.is_function() {
+            return None;
+        }
+        let filename = self.function_property(c::JS_ATOM_fileName)?;
+        let line = self.function_property(c::JS_ATOM_lineNumber)?;
+        Some((filename.decode_string().ok()?, line.decode_u32().ok()?))
+    }
+
+    /// `AsyncFunction` and the like, by class, or `None` for other functions.
+    fn function_kind(&self) -> Option<&'static str> {
+        let is = |class_id: u32| unsafe { c::JS_IsTypeOf(*self.raw_value(), class_id as _) != 0 };
+        if is(c::JS_CLASS_ASYNC_FUNCTION) {
+            Some("AsyncFunction")
+        } else if is(c::JS_CLASS_GENERATOR_FUNCTION) {
+            Some("GeneratorFunction")
+        } else if is(c::JS_CLASS_ASYNC_GENERATOR_FUNCTION) {
+            Some("AsyncGeneratorFunction")
+        } else {
+            None
+        }
+    }
+
+    /// The property `atom` of a function, found up its prototype chain: the value of a data
+    /// property, or what a native getter returns. `None` for getters defined by scripts, and
+    /// for proxies, whose traps are scripts too.
+    fn function_property(&self, atom: c::JSAtom) -> Option<Value> {
+        const MAX_CHAIN: usize = 8;
+        let ctx = self.context().ok()?;
+        let is = |value: &Value, class_id: u32| unsafe {
+            c::JS_IsTypeOf(*value.raw_value(), class_id as _) != 0
+        };
+        let mut holder = self.clone();
+        for _ in 0..MAX_CHAIN {
+            if !holder.is_object() || is(&holder, c::JS_CLASS_PROXY) {
+                return None;
+            }
+            let mut desc: c::JSPropertyDescriptor = unsafe { core::mem::zeroed() };
+            let found =
+                unsafe { c::JS_GetOwnProperty(ctx.as_ptr(), &mut desc, *holder.raw_value(), atom) };
+            if found < 0 {
+                let _ = ctx.get_exception_error();
+                return None;
+            }
+            if found > 0 {
+                let value = Value::new_moved(ctx, desc.value);
+                let getter = Value::new_moved(ctx, desc.getter);
+                let _setter = Value::new_moved(ctx, desc.setter);
+                if desc.flags as u32 & c::JS_PROP_GETSET == 0 {
+                    return Some(value);
+                }
+                if !is(&getter, c::JS_CLASS_C_FUNCTION) {
+                    return None;
+                }
+                // Called directly, as `call` traces the callee with this very function.
+                let value = unsafe {
+                    c::JS_Call(
+                        ctx.as_ptr(),
+                        *getter.raw_value(),
+                        *self.raw_value(),
+                        0,
+                        core::ptr::null_mut(),
+                    )
+                };
+                let value = Value::new_moved(ctx, value);
+                if value.is_exception() {
+                    let _ = ctx.get_exception_error();
+                    return None;
+                }
+                return Some(value);
+            }
+            holder = holder.get_prototype().ok()?;
+        }
+        None
+    }
+
+    /// `[Function: name]`, `[AsyncFunction (anonymous)]` and the like.
+    pub(crate) fn function_label(&self) -> String {
+        let kind = self.function_kind().unwrap_or("Function");
+        match self.function_name() {
+            Ok(name) if !name.is_empty() => format!("[{kind}: {name}]"),
+            _ => format!("[{kind} (anonymous)]"),
+        }
+    }
 }
 
 impl Value {
@@ -430,8 +779,8 @@ impl Value {
         Self::from_u128(ctx, val as _)
     }
     pub fn from_i64(ctx: &js::Context, val: i64) -> Self {
-        if val <= i32::MAX.into() {
-            return Self::from_i32(ctx, val as _);
+        if let Ok(val) = i32::try_from(val) {
+            return Self::from_i32(ctx, val);
         }
         Self::bigint(ctx, val)
     }
@@ -480,6 +829,31 @@ impl Value {
             )
         }
     }
+    /// Create a Uint8Array from `bytes`. Buffers of at least `OWNED_BYTES_THRESHOLD` bytes are
+    /// handed over to the engine without copying.
+    pub fn from_bytes_owned(ctx: &js::Context, bytes: Vec<u8>) -> Result<Self> {
+        let array = if bytes.len() < OWNED_BYTES_THRESHOLD {
+            Self::from_bytes(ctx, &bytes)
+        } else {
+            let buffer = crate::js_arraybuffer::new_external_array_buffer(ctx, bytes)?;
+            let mut argv = [*buffer.raw_value()];
+            unsafe {
+                Self::new_moved(
+                    ctx,
+                    c::JS_NewTypedArray(
+                        ctx.as_ptr(),
+                        1,
+                        argv.as_mut_ptr(),
+                        c::JSTypedArrayEnum_JS_TYPED_ARRAY_UINT8,
+                    ),
+                )
+            }
+        };
+        if array.is_exception() {
+            return Err(ctx.get_exception_error());
+        }
+        Ok(array)
+    }
     pub fn new_array(ctx: &js::Context) -> Self {
         unsafe { Self::new_moved(ctx, c::JS_NewArray(ctx.as_ptr())) }
     }
@@ -490,11 +864,21 @@ impl Value {
         }
         object
     }
+    /// A plain object inheriting from `proto`, an object or `null`.
+    pub fn new_object_with_proto(ctx: &js::Context, proto: &Value) -> Result<Self> {
+        let object =
+            unsafe { Self::new_moved(ctx, c::JS_NewObjectProto(ctx.as_ptr(), *proto.raw_value())) };
+        if object.is_exception() {
+            Err(ctx.get_exception_error())
+        } else {
+            Ok(object)
+        }
+    }
 }
 
 impl Value {
     pub fn index_set(&self, ind: usize, value: &Value) -> Result<(), Error> {
-        self.set_property(&ind.to_string(), value)
+        self.set_property(SmallStr::format(format_args!("{ind}")).as_str(), value)
     }
 
     pub fn set_name(&self, name: &str) -> Result<(), Error> {
@@ -526,6 +910,84 @@ impl Value {
         }
     }
 
+    /// The result of `typeof`, except that `null` is `"null"`.
+    pub fn type_of(&self) -> String {
+        match self {
+            Self::Undefined => "undefined".into(),
+            Self::Null => "null".into(),
+            Self::Exception => "exception".into(),
+            Self::Other { .. } if self.is_null() => "null".into(),
+            Self::Other { ctx, value } => {
+                let ty = unsafe { c::JS_TypeOf(ctx.as_ptr(), *value) };
+                Value::new_moved(ctx, ty).to_string()
+            }
+        }
+    }
+
+    /// The class of an object: the `name` of the `constructor` found up its prototype chain, or
+    /// `Proxy` for a proxy. Read through the C API without running getters or proxy traps, so
+    /// only data properties count. `None` for primitives and objects without a named constructor.
+    pub fn class_name(&self) -> Option<String> {
+        const MAX_CHAIN: usize = 8;
+        if !self.is_object() {
+            return None;
+        }
+        let is_proxy = |value: &Value| unsafe {
+            c::JS_IsTypeOf(*value.raw_value(), c::JS_CLASS_PROXY as _) != 0
+        };
+        if is_proxy(self) {
+            return Some("Proxy".into());
+        }
+        let mut holder = self.clone();
+        for _ in 0..MAX_CHAIN {
+            if !holder.is_object() || is_proxy(&holder) {
+                return None;
+            }
+            if let Some(ctor) = holder.own_data_property(c::JS_ATOM_constructor) {
+                if !ctor.is_function() || is_proxy(&ctor) {
+                    return None;
+                }
+                let name = ctor
+                    .own_data_property(c::JS_ATOM_name)?
+                    .decode_string()
+                    .ok()?;
+                return (!name.is_empty()).then_some(name);
+            }
+            holder = holder.get_prototype().ok()?;
+        }
+        None
+    }
+
+    /// The own data property `atom` of a non-proxy object, `None` if it is missing or an
+    /// accessor.
+    fn own_data_property(&self, atom: c::JSAtom) -> Option<Value> {
+        let ctx = self.context().ok()?;
+        let mut desc: c::JSPropertyDescriptor = unsafe { core::mem::zeroed() };
+        let found =
+            unsafe { c::JS_GetOwnProperty(ctx.as_ptr(), &mut desc, *self.raw_value(), atom) };
+        if found < 0 {
+            let _ = ctx.get_exception_error();
+            return None;
+        }
+        if found == 0 {
+            return None;
+        }
+        let value = Value::new_moved(ctx, desc.value);
+        let _getter = Value::new_moved(ctx, desc.getter);
+        let _setter = Value::new_moved(ctx, desc.setter);
+        (desc.flags as u32 & c::JS_PROP_GETSET == 0).then_some(value)
+    }
+
+    /// What the value is, for error messages: its `typeof` followed by its class for objects,
+    /// e.g. `object (Map)`.
+    pub fn describe_type(&self) -> String {
+        let ty = self.type_of();
+        match self.class_name() {
+            Some(class) => alloc::format!("{ty} ({class})"),
+            None => ty,
+        }
+    }
+
     pub fn set_property(&self, key: &str, value: &Value) -> Result<(), Error> {
         let ctx = self.context()?;
         unsafe {
@@ -538,6 +1000,7 @@ impl Value {
 
     pub fn set_property_atom(&self, key: c::JSAtom, value: Value) -> Result<(), Error> {
         let ctx = self.context()?;
+        value.check_context(ctx)?;
         unsafe {
             let r = c::JS_SetProperty(ctx.as_ptr(), *self.raw_value(), key, value.leak());
             if r != 0 {
@@ -547,21 +1010,101 @@ impl Value {
             }
         }
     }
-    pub fn set_prototype(&self, proto: &Value) -> Result<(), Error> {
+
+    /// Set the property to `value`, or remove it for `None`, so that `key in obj` is false
+    /// afterwards rather than the property holding `undefined`.
+    ///
+    /// Unlike `set_property(key, &Value::undefined())`, which keeps an own property visible to
+    /// `in`, `hasOwnProperty` and `Object.keys`, the property is gone for `None`. Both are left
+    /// out by `JSON.stringify`.
+    pub fn set_property_opt(&self, key: &str, value: Option<&Value>) -> Result<(), Error> {
         let ctx = self.context()?;
         unsafe {
-            let r = c::JS_SetPrototype(ctx.as_ptr(), *self.raw_value(), *proto.raw_value());
-            if r == 1 {
-                Ok(())
-            } else {
-                bail!("failed to set prototype");
+            let key = c::JS_NewAtomLen(ctx.as_ptr(), key.as_ptr() as _, key.len() as _);
+            let ret = self.set_property_atom_opt(key, value.cloned());
+            c::JS_FreeAtom(ctx.as_ptr(), key);
+            ret
+        }
+    }
+
+    /// [`set_property_opt`](Self::set_property_opt) with an interned key.
+    pub fn set_property_atom_opt(&self, key: c::JSAtom, value: Option<Value>) -> Result<(), Error> {
+        if let Some(value) = value {
+            return self.set_property_atom(key, value);
+        }
+        let ctx = self.context()?;
+        let r = unsafe { c::JS_DeleteProperty(ctx.as_ptr(), *self.raw_value(), key, 0) };
+        if r == 1 {
+            return Ok(());
+        }
+        let exception = (r < 0).then(|| ctx.get_exception_str());
+        let name = unsafe { Value::new_moved(ctx, c::JS_AtomToString(ctx.as_ptr(), key)) };
+        match exception {
+            Some(exception) => bail!("failed to delete property {name}: {exception}"),
+            None => bail!("property {name} is not configurable"),
+        }
+    }
+
+    /// Set the prototype to `proto`, an object or `null`.
+    ///
+    /// Fails if the object is not extensible or if `proto` has the object on its own prototype
+    /// chain, leaving no exception pending.
+    pub fn set_prototype(&self, proto: &Value) -> Result<(), Error> {
+        let ctx = self.context()?;
+        let r = unsafe { c::JS_SetPrototype(ctx.as_ptr(), *self.raw_value(), *proto.raw_value()) };
+        if r == 1 {
+            return Ok(());
+        }
+        let exception = if r < 0 {
+            ctx.get_exception_str()
+        } else {
+            String::new()
+        };
+        if !self.is_extensible()? {
+            bail!("can not set the prototype of a non-extensible object");
+        }
+        let mut ancestor = proto.clone();
+        while ancestor.is_object() {
+            if ancestor.ptr_eq(self) {
+                bail!("setting the prototype would form a cycle");
             }
+            ancestor = ancestor.get_prototype()?;
+        }
+        bail!("failed to set prototype: {exception}");
+    }
+
+    /// The prototype of the object, `null` at the end of the chain.
+    pub fn get_prototype(&self) -> Result<Value> {
+        let ctx = self.context()?;
+        let proto = unsafe { c::JS_GetPrototype(ctx.as_ptr(), *self.raw_value()) };
+        let proto = Self::new_moved(ctx, proto);
+        if proto.is_exception() {
+            Err(ctx.get_exception_error())
+        } else {
+            Ok(proto)
         }
     }
 
+    pub fn is_extensible(&self) -> Result<bool> {
+        let ctx = self.context()?;
+        let r = unsafe { c::JS_IsExtensible(ctx.as_ptr(), *self.raw_value()) };
+        if r < 0 {
+            Err(ctx.get_exception_error())
+        } else {
+            Ok(r != 0)
+        }
+    }
+
+    /// Whether both are the same object.
+    pub(crate) fn ptr_eq(&self, other: &Value) -> bool {
+        unsafe { c::JS_GetPtr(*self.raw_value()) == c::JS_GetPtr(*other.raw_value()) }
+    }
+
+    /// Define a host function, recorded so that it can be listed and overridden if the runtime
+    /// enabled it, see `Runtime::enable_host_function_registry`.
     pub fn define_property_fn(&self, key: &str, f: c::JsCFunction) -> Result<(), Error> {
         let ctx = self.context()?;
-        self.define_property_value(key, ctx.new_function(key, f, 0, c::JS_CFUNC_generic))
+        self.define_property_value(key, crate::host_registry::new_host_function(ctx, key, f)?)
     }
 
     pub fn define_property_value(&self, key: &str, value: Value) -> Result<(), Error> {
@@ -656,60 +1199,76 @@ impl Value {
         if self.is_bool() {
             Ok(unsafe { c::JS_ToBool(self.context()?.as_ptr(), *self.raw_value()) != 0 })
         } else {
-            Err(expect_js_value(self, "bool"))
+            Err(expect_err("bool", self))
         }
     }
     pub fn decode_string(&self) -> Result<String> {
         if self.is_string() {
-            Ok(self
-                .to_string_utf8()
-                .expect_js_value(self, "string")?
-                .as_str()
-                .into())
+            crate::limits::check_string_len(self)?;
+            let s = self.to_string_utf8().expect_type(self, "string")?;
+            crate::limits::check_string_bytes(self.context()?, s.as_str().len())?;
+            Ok(s.as_str().into())
         } else {
-            Err(expect_js_value(self, "string"))
+            Err(expect_err("string", self))
+        }
+    }
+    /// The bytes QuickJS encodes the string to. Lone surrogates are encoded as the three bytes
+    /// of their code point, so the bytes are not always UTF-8.
+    pub(crate) fn decode_string_bytes(&self) -> Result<Vec<u8>> {
+        if !self.is_string() {
+            return Err(expect_err("string", self));
         }
+        crate::limits::check_string_len(self)?;
+        let s = self.to_string_utf8().expect_type(self, "string")?;
+        crate::limits::check_string_bytes(self.context()?, s.len)?;
+        Ok(unsafe { core::slice::from_raw_parts(s.ptr as *const u8, s.len) }.to_vec())
     }
     pub fn decode_i8(&self) -> Result<i8> {
-        self.decode_i64()?
+        self.decode_integer("i8")?
             .try_into()
             .ok()
-            .expect_js_value(self, "i8")
+            .expect_type(self, "i8")
     }
     pub fn decode_u8(&self) -> Result<u8> {
-        self.decode_i64()?
+        self.decode_integer("u8")?
             .try_into()
             .ok()
-            .expect_js_value(self, "u8")
+            .expect_type(self, "u8")
     }
     pub fn decode_i16(&self) -> Result<i16> {
-        self.decode_i64()?
+        self.decode_integer("i16")?
             .try_into()
             .ok()
-            .expect_js_value(self, "i16")
+            .expect_type(self, "i16")
     }
     pub fn decode_u16(&self) -> Result<u16> {
-        self.decode_i64()?
+        self.decode_integer("u16")?
             .try_into()
             .ok()
-            .expect_js_value(self, "u16")
+            .expect_type(self, "u16")
     }
     pub fn decode_i32(&self) -> Result<i32> {
-        self.decode_i64()?
+        self.decode_integer("i32")?
             .try_into()
             .ok()
-            .expect_js_value(self, "i32")
+            .expect_type(self, "i32")
     }
     pub fn decode_u32(&self) -> Result<u32> {
-        self.decode_i64()?
+        self.decode_integer("u32")?
             .try_into()
             .ok()
-            .expect_js_value(self, "u32")
+            .expect_type(self, "u32")
     }
     pub fn decode_i64(&self) -> Result<i64> {
+        self.decode_integer("i64")
+    }
+    /// The number as an `i64` for the integer decoders, failing for `NaN` and infinities with
+    /// a `NonFiniteNumber` naming `expected`. `-0` is `0`.
+    fn decode_integer(&self, expected: &'static str) -> Result<i64> {
         if self.is_bool() {
             return Ok(self.decode_bool()? as i64);
         }
+        self.check_finite(expected)?;
         if self.is_number() || self.is_big_int() {
             let mut v = 0;
             unsafe {
@@ -719,40 +1278,84 @@ impl Value {
                 }
             }
         }
-        Err(expect_js_value(self, "i64"))
+        if let Some(value) = self.unwrap_number()? {
+            return value.decode_integer(expected);
+        }
+        Err(expect_err(expected, self))
+    }
+    /// Fail with a `NonFiniteNumber` for `NaN` and infinities, which are numbers but no integer.
+    fn check_finite(&self, expected: &'static str) -> Result<()> {
+        match self.number_f64() {
+            Some(value) if !value.is_finite() => {
+                Err(Error::msg(NonFiniteNumber { expected, value }))
+            }
+            _ => Ok(()),
+        }
+    }
+    /// The value of a number, `None` for other values.
+    fn number_f64(&self) -> Option<f64> {
+        if !self.is_number() {
+            return None;
+        }
+        let ctx = self.context().ok()?;
+        let mut value = 0.0;
+        let r = unsafe { c::JS_ToFloat64(ctx.as_ptr(), &mut value, *self.raw_value()) };
+        (r == 0).then_some(value)
     }
     pub fn decode_u64(&self) -> Result<u64> {
-        self.decode_number().expect_js_value(self, "u64")
+        if let Some(value) = self.unwrap_number()? {
+            return value.decode_u64();
+        }
+        self.check_finite("u64")?;
+        self.decode_number().expect_type(self, "u64")
     }
     pub fn decode_usize(&self) -> Result<usize> {
         self.decode_u64()
-            .expect_js_value(self, "usize")?
+            .expect_type(self, "usize")?
             .try_into()
             .ok()
-            .expect_js_value(self, "usize")
+            .expect_type(self, "usize")
     }
+    /// The number as an `f32`, `NaN`, infinities and `-0` included.
     pub fn decode_f32(&self) -> Result<f32> {
-        self.decode_number().expect_js_value(self, "f32")
+        if let Some(value) = self.number_f64() {
+            return Ok(value as f32);
+        }
+        self.decode_number().expect_type(self, "f32")
     }
+    /// The number as an `f64`, `NaN`, infinities and `-0` included.
     pub fn decode_f64(&self) -> Result<f64> {
-        self.decode_number().expect_js_value(self, "f64")
+        if let Some(value) = self.number_f64() {
+            return Ok(value);
+        }
+        self.decode_number().expect_type(self, "f64")
     }
     pub fn decode_i128(&self) -> Result<i128> {
-        self.decode_number().expect_js_value(self, "i128")
+        if let Some(value) = self.unwrap_number()? {
+            return value.decode_i128();
+        }
+        self.check_finite("i128")?;
+        self.decode_number().expect_type(self, "i128")
     }
     pub fn decode_u128(&self) -> Result<u128> {
-        self.decode_number().expect_js_value(self, "u128")
+        if let Some(value) = self.unwrap_number()? {
+            return value.decode_u128();
+        }
+        self.check_finite("u128")?;
+        self.decode_number().expect_type(self, "u128")
     }
     pub fn decode_number<N: core::str::FromStr>(&self) -> Result<N> {
         if self.is_bool() {
             let n = if self.decode_bool()? { "1" } else { "0" };
-            return n.parse().ok().expect_js_value(self, "number");
+            return n.parse().ok().expect_type(self, "number");
         }
         // TODO: optimize performance
         if self.is_number() || self.is_big_int() {
-            self.parse().expect_js_value(self, "number")
+            self.parse().expect_type(self, "number")
+        } else if let Some(value) = self.unwrap_number()? {
+            value.decode_number()
         } else {
-            Err(expect_js_value(self, "number"))
+            Err(expect_err("number", self))
         }
     }
     pub fn decode_bytes(&self) -> Result<Vec<u8>> {
@@ -767,8 +1370,9 @@ impl Value {
                 }
             };
             if ptr.is_null() {
-                return Err(expect_js_value(self, "bytes-like object"));
+                return Err(expect_err("bytes-like object", self));
             }
+            crate::limits::check_string_bytes(ctx, len)?;
             let mut v = Vec::with_capacity(len);
             unsafe {
                 core::ptr::copy_nonoverlapping(ptr as _, v.as_mut_ptr(), len);
@@ -777,6 +1381,7 @@ impl Value {
             Ok(v)
         } else if self.is_array() {
             let len = self.length()?;
+            crate::limits::check_string_bytes(self.context()?, len)?;
             let mut v = Vec::with_capacity(len);
             for i in 0..len {
                 let v2 = self.get_property(&i.to_string())?;
@@ -790,27 +1395,78 @@ impl Value {
             }
             #[cfg(not(feature = "treat-hex-as-bytes"))]
             {
+                crate::limits::check_string_len(self)?;
                 let s = self
                     .to_string_utf8()
-                    .expect_js_value(self, "bytes-like object")?;
+                    .expect_type(self, "bytes-like object")?;
+                crate::limits::check_string_bytes(self.context()?, s.as_str().len())?;
                 Ok(s.as_str().as_bytes().to_vec())
             }
+        } else if let Some(bytes) = self.unwrap_bytes()? {
+            crate::limits::check_string_bytes(self.context()?, bytes.len())?;
+            Ok(bytes)
         } else {
-            Err(expect_js_value(self, "bytes-like object"))
+            Err(expect_err("bytes-like object", self))
+        }
+    }
+
+    /// Whether the object has the property `key` itself, rather than through its prototype.
+    pub fn has_own_property(&self, key: &str) -> Result<bool> {
+        let ctx = self.context()?;
+        let r = unsafe {
+            let atom = c::JS_NewAtomLen(ctx.as_ptr(), key.as_ptr() as _, key.len());
+            let r =
+                c::JS_GetOwnProperty(ctx.as_ptr(), core::ptr::null_mut(), *self.raw_value(), atom);
+            c::JS_FreeAtom(ctx.as_ptr(), atom);
+            r
+        };
+        if r < 0 {
+            return Err(ctx.get_exception_error());
+        }
+        Ok(r != 0)
+    }
+
+    /// Whether the object has the property `key`, itself or through its prototype, like the `in`
+    /// operator. Getters are not invoked.
+    pub fn has_property(&self, key: &str) -> Result<bool> {
+        let ctx = self.context()?;
+        let r = unsafe {
+            let atom = c::JS_NewAtomLen(ctx.as_ptr(), key.as_ptr() as _, key.len());
+            let r = c::JS_HasProperty(ctx.as_ptr(), *self.raw_value(), atom);
+            c::JS_FreeAtom(ctx.as_ptr(), atom);
+            r
+        };
+        if r < 0 {
+            return Err(ctx.get_exception_error());
+        }
+        Ok(r != 0)
+    }
+
+    /// A plain object with only the properties `keys` of this object, the ones it has, to read
+    /// a few fields out of a large object. The other properties are not read, so their getters
+    /// do not run, and the values are not copied deeply.
+    pub fn pick(&self, keys: &[&str]) -> Result<Value> {
+        let ctx = self.context()?;
+        let picked = ctx.new_object("");
+        for key in keys {
+            if self.has_property(key)? {
+                picked.set_property(key, &self.get_property(key)?)?;
+            }
         }
+        Ok(picked)
     }
 
     pub fn decode_bytes_maybe_hex(&self) -> Result<Vec<u8>> {
         if self.is_string() {
+            crate::limits::check_string_len(self)?;
             let s = self
                 .to_string_utf8()
-                .expect_js_value(self, "bytes-like object")?;
+                .expect_type(self, "bytes-like object")?;
             let s = s.as_str();
+            crate::limits::check_string_bytes(self.context()?, s.len())?;
             if s.starts_with("0x") || s.starts_with("0X") {
                 let s = &s[2..];
-                Ok(hex::decode(s)
-                    .ok()
-                    .expect_js_value(self, "bytes-like object")?)
+                Ok(hex::decode(s).ok().expect_type(self, "bytes-like object")?)
             } else {
                 Ok(s.as_bytes().to_vec())
             }
@@ -830,3 +1486,605 @@ impl Value {
 pub fn get_global(context: &js::Context) -> Value {
     Value::new_moved(context, unsafe { c::JS_GetGlobalObject(context.as_ptr()) })
 }
+
+/// The element type of a typed array, see [`Value::is_typed_array`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypedArrayKind {
+    Uint8Clamped,
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    BigInt64,
+    BigUint64,
+    Float32,
+    Float64,
+}
+
+impl TypedArrayKind {
+    pub(crate) const ALL: [Self; 11] = [
+        Self::Uint8Clamped,
+        Self::Int8,
+        Self::Uint8,
+        Self::Int16,
+        Self::Uint16,
+        Self::Int32,
+        Self::Uint32,
+        Self::BigInt64,
+        Self::BigUint64,
+        Self::Float32,
+        Self::Float64,
+    ];
+
+    fn class_id(self) -> u32 {
+        (match self {
+            Self::Uint8Clamped => c::JS_CLASS_UINT8C_ARRAY,
+            Self::Int8 => c::JS_CLASS_INT8_ARRAY,
+            Self::Uint8 => c::JS_CLASS_UINT8_ARRAY,
+            Self::Int16 => c::JS_CLASS_INT16_ARRAY,
+            Self::Uint16 => c::JS_CLASS_UINT16_ARRAY,
+            Self::Int32 => c::JS_CLASS_INT32_ARRAY,
+            Self::Uint32 => c::JS_CLASS_UINT32_ARRAY,
+            Self::BigInt64 => c::JS_CLASS_BIG_INT64_ARRAY,
+            Self::BigUint64 => c::JS_CLASS_BIG_UINT64_ARRAY,
+            Self::Float32 => c::JS_CLASS_FLOAT32_ARRAY,
+            Self::Float64 => c::JS_CLASS_FLOAT64_ARRAY,
+        }) as _
+    }
+
+    /// The type of the typed arrays of `JS_NewTypedArray`.
+    pub(crate) fn typed_array_type(self) -> c::JSTypedArrayEnum {
+        match self {
+            Self::Uint8Clamped => c::JSTypedArrayEnum_JS_TYPED_ARRAY_UINT8C,
+            Self::Int8 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_INT8,
+            Self::Uint8 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_UINT8,
+            Self::Int16 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_INT16,
+            Self::Uint16 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_UINT16,
+            Self::Int32 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_INT32,
+            Self::Uint32 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_UINT32,
+            Self::BigInt64 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_BIG_INT64,
+            Self::BigUint64 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_BIG_UINT64,
+            Self::Float32 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_FLOAT32,
+            Self::Float64 => c::JSTypedArrayEnum_JS_TYPED_ARRAY_FLOAT64,
+        }
+    }
+
+    /// The name of the constructor, `Uint8Array` and the like.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uint8Clamped => "Uint8ClampedArray",
+            Self::Int8 => "Int8Array",
+            Self::Uint8 => "Uint8Array",
+            Self::Int16 => "Int16Array",
+            Self::Uint16 => "Uint16Array",
+            Self::Int32 => "Int32Array",
+            Self::Uint32 => "Uint32Array",
+            Self::BigInt64 => "BigInt64Array",
+            Self::BigUint64 => "BigUint64Array",
+            Self::Float32 => "Float32Array",
+            Self::Float64 => "Float64Array",
+        }
+    }
+
+    /// The size of an element in bytes.
+    pub fn element_size(self) -> usize {
+        match self {
+            Self::Uint8Clamped | Self::Int8 | Self::Uint8 => 1,
+            Self::Int16 | Self::Uint16 => 2,
+            Self::Int32 | Self::Uint32 | Self::Float32 => 4,
+            Self::BigInt64 | Self::BigUint64 | Self::Float64 => 8,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Code, ToJsValue};
+
+    fn collect(iter: impl Iterator<Item = Result<Value>>) -> Vec<String> {
+        iter.map(|v| v.unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn keys_values_entries_behavior() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let cases = [
+            ("({ a: 1, b: 2 })", &["a", "b"][..], &["1", "2"][..]),
+            ("[10, , 30]", &["0", "2"], &["10", "30"]),
+            ("new Map([['x', 1], [2, 'y']])", &["x", "2"], &["1", "y"]),
+            ("new Set(['p', 'q'])", &["p", "q"], &["p", "q"]),
+        ];
+        for (src, keys, values) in cases {
+            let value = ctx.eval(&Code::Source(src)).unwrap();
+            assert_eq!(collect(value.keys().unwrap()), keys, "keys of {src}");
+            assert_eq!(collect(value.values().unwrap()), values, "values of {src}");
+            let entries = value.entries().unwrap();
+            assert_eq!(entries.length(), Some(keys.len()), "length of {src}");
+            let (entry_keys, entry_values): (Vec<_>, Vec<_>) =
+                entries.map(|entry| entry.unwrap()).unzip();
+            assert_eq!(collect(entry_keys.into_iter().map(Ok)), keys, "{src}");
+            assert_eq!(collect(entry_values.into_iter().map(Ok)), values, "{src}");
+        }
+
+        let array = ctx.eval(&Code::Source("[1]")).unwrap();
+        let (index, _) = array.entries().unwrap().next().unwrap().unwrap();
+        assert!(index.is_number());
+
+        let patched = ctx
+            .eval(&Code::Source(
+                r#"
+                Object.keys = Object.values = Object.entries = () => ["patched"];
+                ({ z: 1, get lazy() { globalThis.read = true; return 2; } })
+                "#,
+            ))
+            .unwrap();
+        assert_eq!(collect(patched.keys().unwrap()), ["z", "lazy"]);
+        let read = ctx.eval(&Code::Source("globalThis.read === true")).unwrap();
+        assert!(!read.decode_bool().unwrap());
+        assert_eq!(collect(patched.values().unwrap()), ["1", "2"]);
+
+        let string = ctx.eval(&Code::Source("'abc'")).unwrap();
+        assert!(string.keys().is_err());
+        assert!(string.values().is_err());
+        assert!(string.entries().is_err());
+        assert!(Value::undefined().entries().is_err());
+    }
+
+    #[test]
+    fn function_metadata() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+
+        let named = eval("function add(a, b) { return a + b }\nadd");
+        assert_eq!(named.function_name().unwrap(), "add");
+        assert_eq!(named.function_length().unwrap(), 2);
+        assert!(!named.is_async() && !named.is_generator());
+        let (_, line) = named.source_position().unwrap();
+        assert_eq!(line, 1);
+
+        let arrow = eval("\n[x => x][0]");
+        assert_eq!(arrow.function_name().unwrap(), "");
+        assert_eq!(arrow.function_length().unwrap(), 1);
+        assert_eq!(arrow.source_position().unwrap().1, 2);
+
+        let async_fn = eval("(async function fetch(url, opts) {})");
+        assert_eq!(async_fn.function_name().unwrap(), "fetch");
+        assert!(async_fn.is_async() && !async_fn.is_generator());
+        assert!(eval("(async function* () {})").is_generator());
+
+        let bound = eval("(function scale(k, x) { return k * x }).bind(null, 2)");
+        assert_eq!(bound.function_name().unwrap(), "bound scale");
+        assert_eq!(bound.function_length().unwrap(), 1);
+        assert_eq!(bound.source_position(), None);
+
+        assert!(eval("1").function_name().is_err());
+        assert_eq!(eval("1").source_position(), None);
+
+        let mut buf = String::new();
+        js::recursive_to_string(&eval("({f: add, g: () => 1})"), 2, true, &mut buf, "", 0);
+        assert_eq!(buf, "{f:[Function: add],g:[Function: g]}");
+        buf.clear();
+        js::recursive_to_string(&async_fn, 2, true, &mut buf, "", 0);
+        assert_eq!(buf, "[AsyncFunction: fetch]");
+
+        let forged = eval(
+            r#"
+            const forged = function () {};
+            Object.defineProperty(forged, Symbol.toStringTag, { value: "AsyncFunction" });
+            Object.defineProperty(forged, "name", { get() { globalThis.ran = true; return "x"; } });
+            Object.defineProperty(Function.prototype, "fileName", {
+                get() { globalThis.ran = true; return "forged.js"; },
+            });
+            forged
+            "#,
+        );
+        assert!(!forged.is_async());
+        assert_eq!(forged.function_name().unwrap(), "");
+        assert_eq!(forged.source_position(), None);
+        assert_eq!(forged.function_label(), "[Function (anonymous)]");
+        assert!(eval("typeof ran === 'undefined'").decode_bool().unwrap());
+        assert!(!eval("(async () => {}).bind(null)").is_async());
+    }
+
+    #[test]
+    fn prototypes() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+
+        let bare = ctx.new_object_with_proto(&Value::null()).unwrap();
+        assert!(bare.get_prototype().unwrap().is_null());
+        assert!(bare.is_plain_object());
+        assert!(bare.get_property("toString").unwrap().is_undefined());
+
+        let parent = eval("({ greet() { return 'hi' } })");
+        let child = ctx.new_object_with_proto(&parent).unwrap();
+        assert!(child.get_prototype().unwrap().ptr_eq(&parent));
+        assert!(!child.is_plain_object());
+        let greeting = child.call_method("greet", &[]).unwrap();
+        assert_eq!(greeting.decode_string().unwrap(), "hi");
+
+        // A cycle is reported and no exception is left behind.
+        let err = parent.set_prototype(&child).unwrap_err();
+        assert_eq!(err.to_string(), "setting the prototype would form a cycle");
+        assert_eq!(eval("1 + 1").to_string(), "2");
+
+        let frozen = eval("Object.freeze({})");
+        let err = frozen.set_prototype(&Value::null()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can not set the prototype of a non-extensible object"
+        );
+
+        eval("Object = null; Array = null");
+        let object_proto = ctx.object_prototype().unwrap();
+        assert!(eval("({})").get_prototype().unwrap().ptr_eq(&object_proto));
+        assert!(eval("({})").is_plain_object());
+        let array_proto = ctx.array_prototype().unwrap();
+        assert!(eval("[]").get_prototype().unwrap().ptr_eq(&array_proto));
+    }
+
+    #[test]
+    fn type_predicates() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+        let cases = [
+            ("({})", "plain"),
+            ("Object.create(null)", "plain"),
+            ("new (class A {})()", ""),
+            ("[]", ""),
+            ("() => 1", ""),
+            ("new Proxy({}, {})", ""),
+            ("new ArrayBuffer(4)", "array_buffer"),
+            ("new Uint8Array(4)", "typed:Uint8Array"),
+            ("new Uint8ClampedArray(4)", "typed:Uint8ClampedArray"),
+            ("new Float64Array(4)", "typed:Float64Array"),
+            ("new BigInt64Array(4)", "typed:BigInt64Array"),
+            ("new DataView(new ArrayBuffer(4))", ""),
+            ("new Date(0)", "date"),
+            ("/a/g", "reg_exp"),
+            ("new TypeError('x')", "error"),
+            ("Object.setPrototypeOf(new Error('x'), null)", "error"),
+            ("({ __proto__: Date.prototype })", ""),
+        ];
+        for (src, expected) in cases {
+            let value = eval(src);
+            let mut kinds = Vec::new();
+            if value.is_plain_object() {
+                kinds.push("plain".to_string());
+            }
+            if value.is_array_buffer() {
+                kinds.push("array_buffer".to_string());
+            }
+            if let Some(kind) = value.is_typed_array() {
+                kinds.push(format!("typed:{}", kind.name()));
+            }
+            if value.is_date() {
+                kinds.push("date".to_string());
+            }
+            if value.is_reg_exp() {
+                kinds.push("reg_exp".to_string());
+            }
+            if value.is_error() {
+                kinds.push("error".to_string());
+            }
+            assert_eq!(kinds.join(","), expected, "{src}");
+        }
+
+        // Objects of another context are recognized by their class, not by `instanceof`.
+        let other = runtime.new_context();
+        let foreign = |src: &str| other.eval(&Code::Source(src)).unwrap();
+        assert!(foreign("({ a: 1 })").is_plain_object());
+        assert!(foreign("new Date(0)").is_date());
+        assert_eq!(
+            foreign("new Int16Array(2)").is_typed_array(),
+            Some(TypedArrayKind::Int16)
+        );
+        ctx.get_global_object()
+            .set_property("foreign", &foreign("({ a: 1 })"))
+            .unwrap();
+        assert_eq!(eval("foreign instanceof Object").to_string(), "false");
+        assert!(eval("foreign").is_plain_object());
+
+        // A look-alike of `Object.prototype` is not taken for it.
+        let forged = eval(
+            r#"
+            (() => {
+                const proto = { __proto__: null };
+                function Object() {}
+                Object.prototype = proto;
+                proto.constructor = Object;
+                return { __proto__: proto };
+            })()
+            "#,
+        );
+        assert!(!forged.is_plain_object());
+
+        let prototypes = || {
+            let rt = unsafe { c::JS_GetRuntime(ctx.as_ptr()) };
+            let prototypes = crate::engine::runtime_state_of::<crate::engine::ObjectPrototypes>(rt);
+            prototypes.unwrap().0.borrow().len()
+        };
+        assert_eq!(prototypes(), 2);
+        drop(foreign);
+        drop(other);
+        assert_eq!(prototypes(), 1);
+    }
+
+    #[derive(Debug, crate::FromJsValue)]
+    #[qjs(partial, rename_all = "camelCase")]
+    struct Request {
+        url: String,
+        method: String,
+        content_type: String,
+        body: Vec<u8>,
+    }
+
+    #[test]
+    fn picked_and_partial_conversions_skip_other_properties() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let request = ctx
+            .eval(&Code::Source(
+                "({
+                    url: '/users',
+                    method: 'GET',
+                    contentType: 'text/plain',
+                    get body() { throw new Error('body was read'); },
+                })",
+            ))
+            .unwrap();
+
+        let picked = request.pick(&["url", "method", "missing"]).unwrap();
+        assert_eq!(collect(picked.keys().unwrap()), ["url", "method"]);
+        assert_eq!(picked.get_property("url").unwrap().to_string(), "/users");
+        assert!(request.pick(&["body"]).is_err());
+
+        let route = RequestPartial::from_js_value(picked).unwrap();
+        assert_eq!(route.url.as_deref(), Some("/users"));
+        assert_eq!(route.method.as_deref(), Some("GET"));
+        assert_eq!(route.content_type, None);
+        assert!(route.body.is_none());
+        let partial = RequestPartial::from_js_value(request.pick(&["contentType"]).unwrap());
+        assert_eq!(partial.unwrap().content_type.as_deref(), Some("text/plain"));
+        let err = Request::from_js_value(request).unwrap_err();
+        assert!(format!("{err:#}").contains("body was read"), "{err:#}");
+        let full = ctx
+            .eval(&Code::Source(
+                "({ url: '/', method: 'POST', contentType: 'a', body: [1] })",
+            ))
+            .unwrap();
+        let full = Request::from_js_value(full).unwrap();
+        assert_eq!(
+            (full.url, full.method, full.content_type, full.body),
+            ("/".into(), "POST".into(), "a".into(), vec![1])
+        );
+    }
+
+    #[derive(crate::ToJsValue)]
+    #[qjs(omit_none)]
+    struct Reply {
+        status: u16,
+        body: Option<String>,
+        #[qjs(skip_serializing_if = "Vec::is_empty")]
+        headers: Vec<String>,
+    }
+
+    #[derive(crate::ToJsValue)]
+    #[qjs(omit_none, template)]
+    struct TemplatedReply {
+        status: u16,
+        body: Option<String>,
+    }
+
+    #[derive(crate::ToJsValue)]
+    struct NullableReply {
+        body: Option<String>,
+    }
+
+    #[test]
+    fn undefined_and_missing_properties() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let probe = ctx
+            .eval(&Code::Source(
+                "(o, k) => `${k in o} ${o.hasOwnProperty(k)} ${Object.keys(o)} ${JSON.stringify(o)}`",
+            ))
+            .unwrap();
+        let probe = |obj: &Value, key: &str| {
+            probe
+                .call(&Value::undefined(), &[obj.clone(), ctx.new_string(key)])
+                .unwrap()
+                .decode_string()
+                .unwrap()
+        };
+
+        let obj = ctx.new_object("");
+        obj.set_property("a", &Value::undefined()).unwrap();
+        assert_eq!(probe(&obj, "a"), "true true a {}");
+        obj.set_property_opt("a", None).unwrap();
+        assert_eq!(probe(&obj, "a"), "false false  {}");
+        obj.set_property_opt("a", Some(&ctx.new_string("x")))
+            .unwrap();
+        assert_eq!(probe(&obj, "a"), r#"true true a {"a":"x"}"#);
+        // Removing what is not there is fine, what cannot be removed is an error.
+        obj.set_property_opt("b", None).unwrap();
+        let frozen = ctx.eval(&Code::Source("Object.freeze({ a: 1 })")).unwrap();
+        let err = frozen.set_property_opt("a", None).unwrap_err().to_string();
+        assert_eq!(err, "property a is not configurable");
+        let guarded = ctx
+            .eval(&Code::Source(
+                "new Proxy({ a: 1 }, { deleteProperty() { throw new Error('kept'); } })",
+            ))
+            .unwrap();
+        let err = guarded.set_property_opt("a", None).unwrap_err().to_string();
+        assert!(
+            err.starts_with("failed to delete property a: Error: kept"),
+            "{err}"
+        );
+        assert_eq!(ctx.eval(&Code::Source("1 + 1")).unwrap().to_string(), "2");
+
+        let reply = Reply {
+            status: 200,
+            body: None,
+            headers: Vec::new(),
+        };
+        let value = reply.to_js_value(&ctx).unwrap();
+        assert_eq!(
+            probe(&value, "body"),
+            r#"false false status {"status":200}"#
+        );
+        assert_eq!(
+            probe(&value, "headers"),
+            r#"false false status {"status":200}"#
+        );
+        let reply = Reply {
+            body: Some("ok".into()),
+            ..reply
+        };
+        assert_eq!(
+            probe(&reply.to_js_value(&ctx).unwrap(), "body"),
+            r#"true true status,body {"status":200,"body":"ok"}"#
+        );
+
+        let templated = TemplatedReply {
+            status: 404,
+            body: None,
+        };
+        assert_eq!(
+            probe(&templated.to_js_value(&ctx).unwrap(), "body"),
+            r#"false false status {"status":404}"#
+        );
+
+        // Without `omit_none`, `None` stays a `null` property.
+        let nullable = NullableReply { body: None };
+        assert_eq!(
+            probe(&nullable.to_js_value(&ctx).unwrap(), "body"),
+            r#"true true body {"body":null}"#
+        );
+    }
+
+    #[test]
+    fn call_converts_rust_arguments() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+
+        let add = eval("(a, b) => a + b");
+        assert!(add.is_function());
+        let sum = add.call(&Value::undefined(), (2, 3)).unwrap();
+        assert_eq!(sum.decode_u32().unwrap(), 5);
+        let joined = add.call(&Value::undefined(), ("a", true)).unwrap();
+        assert_eq!(joined.decode_string().unwrap(), "atrue");
+        let values = [ctx.new_string("x"), ctx.new_string("y")];
+        let joined = add.call(&Value::undefined(), &values).unwrap();
+        assert_eq!(joined.decode_string().unwrap(), "xy");
+
+        let counter = eval("({ n: 10, add(k) { return this.n += k; } })");
+        assert!(!counter.is_function());
+        assert_eq!(
+            counter
+                .call_method("add", (5,))
+                .unwrap()
+                .decode_u32()
+                .unwrap(),
+            15
+        );
+        let add_to = counter.get_property("add").unwrap();
+        let other = eval("({ n: 1 })");
+        assert_eq!(add_to.call(&other, (1,)).unwrap().decode_u32().unwrap(), 2);
+        assert_eq!(counter.get_property("n").unwrap().decode_u32().unwrap(), 15);
+
+        let fail = eval("(reason) => { throw new TypeError(`bad ${reason}`); }");
+        let err = fail.call(&Value::undefined(), ("input",)).unwrap_err();
+        assert!(
+            alloc::format!("{err:#}").contains("TypeError: bad input"),
+            "{err:#}"
+        );
+    }
+
+    #[derive(Debug, crate::FromJsValue)]
+    struct Reading {
+        #[qjs(finite)]
+        celsius: f64,
+        ratio: f64,
+    }
+
+    #[test]
+    fn i64_outside_the_i32_range_is_a_bigint() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        for (val, expected) in [
+            (i32::MIN.into(), "-2147483648"),
+            (i32::MAX.into(), "2147483647"),
+            (i64::from(i32::MIN) - 1, "-2147483649n"),
+            (i64::from(i32::MAX) + 1, "2147483648n"),
+            (i64::MIN, "-9223372036854775808n"),
+        ] {
+            let value = Value::from_i64(&ctx, val);
+            let suffix = if value.is_big_int() { "n" } else { "" };
+            assert_eq!(format!("{value}{suffix}"), expected);
+            assert_eq!(value.decode_i64().unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn integers_reject_non_finite_numbers() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+        let decoders: [(&str, fn(&Value) -> Result<()>); 12] = [
+            ("i8", |v| v.decode_i8().map(drop)),
+            ("u8", |v| v.decode_u8().map(drop)),
+            ("i16", |v| v.decode_i16().map(drop)),
+            ("u16", |v| v.decode_u16().map(drop)),
+            ("i32", |v| v.decode_i32().map(drop)),
+            ("u32", |v| v.decode_u32().map(drop)),
+            ("i64", |v| v.decode_i64().map(drop)),
+            ("u64", |v| v.decode_u64().map(drop)),
+            ("usize", |v| v.decode_usize().map(drop)),
+            ("i128", |v| v.decode_i128().map(drop)),
+            ("u128", |v| v.decode_u128().map(drop)),
+            ("u32 (derived)", |v| u32::from_js_value(v.clone()).map(drop)),
+        ];
+        for src in ["NaN", "Infinity", "-Infinity", "new Number(NaN)"] {
+            let value = eval(src);
+            for (ty, decode) in decoders {
+                let err = decode(&value).unwrap_err();
+                assert!(
+                    err.downcast_ref::<NonFiniteNumber>().is_some(),
+                    "{ty} {src}: {err:#}"
+                );
+            }
+        }
+        let err = eval("NaN").decode_u32().unwrap_err();
+        assert_eq!(err.to_string(), "expected u32, got NaN");
+        let err = eval("-Infinity").decode_u64().unwrap_err();
+        assert_eq!(err.to_string(), "expected u64, got -Infinity");
+
+        // `-0` is `0` for integers, floats keep it and the special values.
+        let negative_zero = eval("-0");
+        assert_eq!(negative_zero.decode_i32().unwrap(), 0);
+        assert_eq!(negative_zero.decode_u64().unwrap(), 0);
+        assert_eq!(negative_zero.decode_i128().unwrap(), 0);
+        assert!(negative_zero.decode_f64().unwrap().is_sign_negative());
+        assert!(eval("NaN").decode_f64().unwrap().is_nan());
+        assert_eq!(eval("Infinity").decode_f32().unwrap(), f32::INFINITY);
+        assert_eq!(eval("-Infinity").decode_f64().unwrap(), f64::NEG_INFINITY);
+
+        let reading = Reading::from_js_value(eval("({ celsius: -0, ratio: NaN })")).unwrap();
+        assert!(reading.celsius == 0.0 && reading.ratio.is_nan());
+        let err = Reading::from_js_value(eval("({ celsius: NaN, ratio: 1 })")).unwrap_err();
+        assert!(
+            alloc::format!("{err:#}").contains("expected finite number, got NaN"),
+            "{err:#}"
+        );
+        assert!(Reading::from_js_value(eval("({ celsius: Infinity, ratio: 1 })")).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! Wrapper classes of user libraries, like big numbers or byte containers, that the number and
+//! byte conversions unwrap once the host opted them in.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use anyhow::bail;
+
+use crate::error::JsResultExt;
+use crate::{self as js, c, Result, Value};
+
+/// How far up the prototype chain an instance of a wrapper class is recognized.
+const MAX_CHAIN: usize = 16;
+
+impl js::Context {
+    /// Let the instances of `class`, a constructor, convert where a number or bytes are
+    /// expected: a number from `valueOf()`, bytes from `toU8a()`, a `buffer` or `toJSON()`.
+    /// Instances of other classes, `Date` included, never have their methods called by a
+    /// conversion, and neither do the conversions tried speculatively, like the arms of
+    /// [`Overloaded`](js::Overloaded).
+    pub fn register_wrapper_class(&self, class: &Value) -> Result<()> {
+        let prototype = class.get_property("prototype")?;
+        if !class.is_function() || !prototype.is_object() {
+            bail!("a wrapper class must be a constructor with a prototype object");
+        }
+        wrapper_prototypes(self)?.array_push(&prototype)
+    }
+
+    /// Run `f` trying a conversion that may fail and fall back to another, during which wrapper
+    /// objects are not unwrapped.
+    pub(crate) fn speculate<R>(&self, f: impl FnOnce() -> R) -> R {
+        let Some(state) = self.state::<Speculation>() else {
+            return f();
+        };
+        state.depth.set(state.depth.get() + 1);
+        let _restore = scopeguard::guard((), |_| state.depth.set(state.depth.get() - 1));
+        f()
+    }
+}
+
+#[derive(Default)]
+struct Speculation {
+    depth: Cell<u32>,
+}
+
+fn wrapper_prototypes(ctx: &js::Context) -> Result<Value> {
+    ctx.host_object("wrapperClasses", || Ok(Value::new_array(ctx)))
+}
+
+impl Value {
+    /// Whether the value is an instance of a class registered with
+    /// [`Context::register_wrapper_class`](js::Context::register_wrapper_class). Proxies are
+    /// not, and no script runs to find out.
+    pub fn is_wrapper(&self) -> bool {
+        let is_proxy = |value: &Value| unsafe {
+            c::JS_IsTypeOf(*value.raw_value(), c::JS_CLASS_PROXY as _) != 0
+        };
+        if !self.is_object() || is_proxy(self) {
+            return false;
+        }
+        let Ok(ctx) = self.context() else {
+            return false;
+        };
+        let Ok(prototypes) = wrapper_prototypes(ctx) else {
+            return false;
+        };
+        let prototypes: Vec<Value> = (0..prototypes.length().unwrap_or(0))
+            .filter_map(|i| prototypes.index(i).ok())
+            .collect();
+        if prototypes.is_empty() {
+            return false;
+        }
+        let mut holder = self.clone();
+        for _ in 0..MAX_CHAIN {
+            let Ok(prototype) = holder.get_prototype() else {
+                return false;
+            };
+            if !prototype.is_object() || is_proxy(&prototype) {
+                return false;
+            }
+            if prototypes.iter().any(|known| known.ptr_eq(&prototype)) {
+                return true;
+            }
+            holder = prototype;
+        }
+        false
+    }
+
+    /// Whether a conversion may unwrap the value: it is a wrapper and no speculative conversion
+    /// is being tried.
+    fn unwraps(&self) -> bool {
+        let speculating = self
+            .context()
+            .ok()
+            .and_then(|ctx| ctx.state::<Speculation>())
+            .is_some_and(|state| state.depth.get() > 0);
+        !speculating && self.is_wrapper()
+    }
+
+    fn call_wrapper_method(&self, name: &str) -> Result<Option<Value>> {
+        let method = self.get_property(name)?;
+        if !method.is_function() {
+            return Ok(None);
+        }
+        method.call(self, &[]).map(Some)
+    }
+
+    /// The number a wrapper object stands for, from its `valueOf()` returning a number or a
+    /// BigInt.
+    pub(crate) fn unwrap_number(&self) -> Result<Option<Value>> {
+        if !self.unwraps() {
+            return Ok(None);
+        }
+        let value = self.call_wrapper_method("valueOf")?;
+        Ok(value.filter(|value| value.is_number() || value.is_big_int()))
+    }
+
+    /// The bytes a wrapper object stands for, from the first of these it has:
+    ///
+    /// 1. `toU8a()` returning a `Uint8Array`, like polkadot-js codecs;
+    /// 2. a `buffer` property holding an `ArrayBuffer`, sliced by `byteOffset` and `byteLength`
+    ///    if it has them, like `Buffer` polyfills;
+    /// 3. `toJSON()` returning an array of bytes, a `0x` prefixed hex string or the
+    ///    `{ type: "Buffer", data }` of Node's `Buffer`.
+    pub(crate) fn unwrap_bytes(&self) -> Result<Option<Vec<u8>>> {
+        if !self.unwraps() {
+            return Ok(None);
+        }
+        if let Some(u8a) = self.call_wrapper_method("toU8a")? {
+            if u8a.is_uint8_array() {
+                return u8a.decode_bytes().map(Some);
+            }
+        }
+        let buffer = self.get_property("buffer")?;
+        if buffer.is_array_buffer() {
+            let bytes = buffer.decode_bytes()?;
+            let offset = self.get_property("byteOffset")?;
+            let len = self.get_property("byteLength")?;
+            if !offset.is_number() || !len.is_number() {
+                return Ok(Some(bytes));
+            }
+            let (offset, len) = (offset.decode_usize()?, len.decode_usize()?);
+            let slice = offset
+                .checked_add(len)
+                .and_then(|end| bytes.get(offset..end))
+                .expect_type(self, "bytes-like object")?;
+            return Ok(Some(slice.to_vec()));
+        }
+        let Some(json) = self.call_wrapper_method("toJSON")? else {
+            return Ok(None);
+        };
+        if json.is_array() {
+            return json.decode_bytes().map(Some);
+        }
+        if json.is_string() {
+            let s = json.decode_string()?;
+            if s.starts_with("0x") || s.starts_with("0X") {
+                return json.decode_bytes_maybe_hex().map(Some);
+            }
+            return Ok(None);
+        }
+        if json.is_object() && json.get_property("type")?.to_string() == "Buffer" {
+            let data = json.get_property("data")?;
+            if data.is_array() {
+                return data.decode_bytes().map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as js, Code, Overloaded};
+
+    #[test]
+    fn registered_wrappers_convert_to_numbers_and_bytes() {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let eval = |src: &str| ctx.eval(&Code::Source(src)).unwrap();
+        eval(
+            r#"
+            globalThis.calls = 0;
+            class BN { constructor(n) { this.n = n; } valueOf() { calls++; return this.n; } }
+            class Codec { toU8a() { return new Uint8Array([1, 2]); } }
+            class Polyfill {
+                constructor() {
+                    this.buffer = new Uint8Array([9, 3, 4, 9]).buffer;
+                    this.byteOffset = 1;
+                    this.byteLength = 2;
+                }
+            }
+            class HexJson { toJSON() { return "0x0506"; } }
+            class NodeJson { toJSON() { return { type: "Buffer", data: [7, 8] }; } }
+            class SubCodec extends Codec {}
+            "#,
+        );
+        assert!(eval("new BN(300)").decode_u16().is_err());
+        assert!(eval("new Codec()").decode_bytes().is_err());
+        assert_eq!(eval("calls").decode_u32().unwrap(), 0);
+
+        for class in ["BN", "Codec", "Polyfill", "HexJson", "NodeJson"] {
+            ctx.register_wrapper_class(&eval(class)).unwrap();
+        }
+        assert_eq!(eval("new BN(300)").decode_u16().unwrap(), 300);
+        assert_eq!(eval("new BN(-5)").decode_i64().unwrap(), -5);
+        assert_eq!(eval("new BN(2n ** 100n)").decode_u128().unwrap(), 1 << 100);
+        assert!(eval("new BN(300)").decode_u8().is_err());
+        assert!(eval("new BN('300')").decode_u32().is_err());
+        assert_eq!(eval("new Codec()").decode_bytes().unwrap(), [1, 2]);
+        assert_eq!(eval("new SubCodec()").decode_bytes().unwrap(), [1, 2]);
+        assert_eq!(eval("new Polyfill()").decode_bytes().unwrap(), [3, 4]);
+        assert_eq!(eval("new HexJson()").decode_bytes().unwrap(), [5, 6]);
+        assert_eq!(eval("new NodeJson()").decode_bytes().unwrap(), [7, 8]);
+
+        // Other objects are left alone, whatever methods they have.
+        eval("Object.prototype.toU8a = () => new Uint8Array([6]);");
+        assert!(eval("({ valueOf: () => 6 })").decode_u32().is_err());
+        assert!(eval("({})").decode_bytes().is_err());
+        assert!(eval("new Date(0)").decode_u64().is_err());
+        assert!(eval("new Proxy(new BN(1), {})").decode_u32().is_err());
+
+        // Speculative conversions do not call `valueOf`.
+        eval("calls = 0");
+        let args = [eval("new BN(7)")];
+        let matched = Overloaded::new(&args)
+            .arm(|(n,): (u32,)| Ok(alloc::format!("number {n}")))
+            .arm(|(_,): (js::Value,)| Ok("value".into()))
+            .finish()
+            .unwrap();
+        assert_eq!(matched, "value");
+        assert_eq!(eval("calls").decode_u32().unwrap(), 0);
+    }
+}
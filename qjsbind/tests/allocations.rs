@@ -0,0 +1,155 @@
+//! Checks on what the paths of the crate allocate, that they do not allocate or do not leak. They
+//! need a counting global allocator, which is why they are a test binary of their own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use qjsbind::{self as js, Code, FromJsValue, JsString};
+
+std::thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    static LARGE_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The size from which allocations are counted by `count_large_allocations`.
+const LARGE: usize = 64 * 1024 * 1024;
+
+/// Counts the allocations of the current thread, so that tests running in parallel do not
+/// disturb each other.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        _ = LIVE_BYTES.try_with(|n| n.set(n.get() + layout.size() as isize));
+        if layout.size() >= LARGE {
+            _ = LARGE_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        _ = LIVE_BYTES.try_with(|n| n.set(n.get() - layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn count_large_allocations(f: impl FnOnce()) -> usize {
+    let before = LARGE_ALLOCATIONS.with(Cell::get);
+    f();
+    LARGE_ALLOCATIONS.with(Cell::get) - before
+}
+
+/// The bytes `f` allocated and did not free.
+fn leaked_bytes(f: impl FnOnce()) -> isize {
+    let before = LIVE_BYTES.with(Cell::get);
+    f();
+    LIVE_BYTES.with(Cell::get) - before
+}
+
+// With `pink-allocator`, the engine allocates through the global allocator as well.
+#[cfg(not(feature = "pink-allocator"))]
+#[test]
+fn property_access_does_not_allocate() {
+    let runtime = js::Runtime::new(&Default::default());
+    let ctx = runtime.new_context();
+    let object = ctx
+        .eval(&Code::Source("({ someName: 1, list: [1, 2, 3] })"))
+        .unwrap();
+    let list = object.get_property("list").unwrap();
+    let one = object.get_property("someName").unwrap();
+    let allocations = count_allocations(|| {
+        for i in 0..1000 {
+            let value = object.get_property("someName").unwrap();
+            assert!(value.is_number());
+            let value = list.index(i % 3).unwrap();
+            assert!(value.is_number());
+            list.index_set(i % 3, &one).unwrap();
+            object.set_property("someName", &one).unwrap();
+            assert!(object.has_own_property("someName").unwrap());
+            assert!(object.has_property("toString").unwrap());
+        }
+    });
+    assert_eq!(allocations, 0);
+}
+
+#[cfg(not(feature = "pink-allocator"))]
+#[test]
+fn strings_are_borrowed_from_the_engine() {
+    let runtime = js::Runtime::new(&Default::default());
+    let ctx = runtime.new_context();
+    let name = ctx.eval(&Code::Source("'algorithmName'")).unwrap();
+    // The first conversion may set up the state of the context.
+    _ = JsString::from_js_value(name.clone()).unwrap();
+    let allocations = count_allocations(|| {
+        for _ in 0..1000 {
+            let s = JsString::from_js_value(name.clone()).unwrap();
+            assert_eq!(s.as_str(), "algorithmName");
+            let len = js::ctx_to_str(&ctx, *name.raw_value(), |s| s.len());
+            assert_eq!(len, "algorithmName".len());
+        }
+    });
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn dropping_the_runtime_cancels_futures_holding_their_context() {
+    let run = || {
+        let runtime = js::Runtime::new(&Default::default());
+        let ctx = runtime.new_context();
+        let held = ctx.new_object("Held");
+        let promise = ctx
+            .spawn_host_future(async move {
+                let _held = held;
+                core::future::pending::<js::Result<()>>().await
+            })
+            .unwrap();
+        drop(promise);
+        // The future now holds the last handle of the context, which it drops with the runtime.
+        drop(ctx);
+        assert_eq!(runtime.pending_host_futures(), 1);
+        drop(runtime);
+    };
+    // The first run may set up state kept for the rest of the process.
+    run();
+    assert_eq!(leaked_bytes(run), 0);
+}
+
+#[js::host_call]
+fn large_buffer() -> js::AsBytes<Vec<u8>> {
+    js::AsBytes(vec![7; LARGE])
+}
+
+#[test]
+fn large_byte_outputs_are_not_copied() {
+    let runtime = js::Runtime::new(&Default::default());
+    let ctx = runtime.new_context();
+    ctx.get_global_object()
+        .define_property_fn("largeBuffer", large_buffer)
+        .unwrap();
+    let before = runtime.memory_usage().malloc_size;
+    let mut buffer = None;
+    let allocations = count_large_allocations(|| {
+        buffer = Some(ctx.eval(&Code::Source("largeBuffer()")).unwrap());
+    });
+    // Only the Vec is allocated, with `pink-allocator` the engine would count too.
+    assert_eq!(allocations, 1);
+    let grown = runtime.memory_usage().malloc_size - before;
+    assert!(grown < 1024 * 1024, "the engine allocated {grown} bytes");
+
+    let buffer = buffer.unwrap();
+    assert!(buffer.is_uint8_array());
+    let bytes = js::JsUint8Array::from_js_value(buffer).unwrap();
+    assert_eq!(bytes.len(), LARGE);
+    assert!(bytes.as_bytes().iter().all(|b| *b == 7));
+}
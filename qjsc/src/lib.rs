@@ -12,7 +12,9 @@ pub fn compiled(input: TokenStream) -> TokenStream {
 
 fn compile_js(js: syn::LitStr) -> syn::Result<TokenStream2> {
     let js = js.value();
-    match qjsbind::compile(&js, "<eval>") {
+    let runtime = qjsbind::Runtime::new(&Default::default());
+    let ctx = runtime.new_context();
+    match ctx.compile_script_to_bytecode(&js, "<eval>") {
         Ok(bytecode) => {
             let lit_bytes = syn::LitByteStr::new(&bytecode, js.span());
             Ok(quote::quote! {
@@ -20,7 +22,7 @@ fn compile_js(js: syn::LitStr) -> syn::Result<TokenStream2> {
             })
         }
         Err(err) => {
-            let msg = format!("{}: {}", err, js);
+            let msg = format!("{err:#}: {js}");
             Err(syn::Error::new(js.span(), msg))
         }
     }